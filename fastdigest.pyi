@@ -1,15 +1,52 @@
+import os
 from typing import (
     Any,
+    AsyncIterable,
+    Callable,
+    Coroutine,
     Dict,
+    Hashable,
     Iterable,
     Iterator,
     List,
+    Mapping,
     Optional,
     Sequence,
     Tuple,
     Union,
+    overload,
 )
 
+class FastDigestError(ValueError):
+    """
+    Base class of the errors raised by fastdigest, also available from
+    `fastdigest.errors`. As it derives from ValueError, handlers for
+    ValueError keep catching all of them.
+    """
+
+class EmptyDigestError(FastDigestError):
+    """A digest or sketch without values was queried."""
+
+class InvalidQuantileError(FastDigestError):
+    """A quantile, percentile or rank is out of range."""
+
+class InvalidValueError(FastDigestError):
+    """A value is not finite, or a weight is not finite and greater than 0."""
+
+class SerializationError(FastDigestError):
+    """
+    A dict or binary representation is missing keys, corrupt or
+    inconsistent.
+    """
+
+class PrecisionWarning(UserWarning):
+    """
+    A `decimal.Decimal` value cannot be represented exactly as a float.
+    Ignored by default; enable it with a warnings filter, e.g.
+    `warnings.simplefilter("error", PrecisionWarning)` to reject inexact
+    Decimals.
+    """
+
 class TDigest:
     """
     Class containing the t-digest data structure.
@@ -18,9 +55,30 @@ class TDigest:
         Number of centroids to maintain. A lower value enables a
         smaller memory footprint and faster computation speed at the
         cost of some accuracy. 0 disables compression. Default is 1000.
+    :param optional storage:
+        Precision of the stored centroids, "f64" (default) or "f32".
+    :param optional tail_singletons:
+        Number of centroids at either end that are never merged. Default
+        is 0.
+    :param optional resolution:
+        Part of the distribution resolved most finely: "both" (default),
+        "upper" or "lower".
+    :param optional exact_tails:
+        Number of lowest and highest values to store exactly. Default is 0.
+    :param optional backend:
+        Structure that single updates are added to: "merging" (default) or
+        "tree".
     """
 
-    def __init__(self, max_centroids: int = 1000) -> None:
+    def __init__(
+        self,
+        max_centroids: int = 1000,
+        storage: str = "f64",
+        tail_singletons: int = 0,
+        resolution: str = "both",
+        exact_tails: int = 0,
+        backend: str = "merging",
+    ) -> None:
         """
         Creates an empty new TDigest instance.
 
@@ -28,6 +86,31 @@ class TDigest:
             Number of centroids to maintain. A lower value enables a
             smaller memory footprint and faster computation speed at the
             cost of some accuracy. 0 disables compression. Default is 1000.
+        :param optional storage:
+            Precision in which the centroids are kept between operations:
+            "f64" (default) or "f32". Single precision roughly halves the
+            memory of the centroids; all computations still use double
+            precision.
+        :param optional tail_singletons:
+            Number of centroids at either end that are never merged, so
+            that the most extreme observations are kept exactly. Improves
+            the accuracy of extreme quantiles (like p99.9) at the cost of
+            up to 2 * tail_singletons additional centroids. Default is 0.
+        :param optional resolution:
+            Part of the distribution that is resolved most finely: "both"
+            tails (default), or only the "upper" or "lower" one, using
+            coarser centroids for the other tail.
+        :param optional exact_tails:
+            Number of lowest and highest values to store exactly alongside
+            the centroids. Quantiles within these values are computed
+            exactly (e.g. p99.99 of a million values with exact_tails of
+            at least 100). Default is 0.
+        :param optional backend:
+            Structure that single updates (`update`) are added to: a
+            buffer that is sorted and merged when full ("merging",
+            default), or a balanced tree of centroids ("tree"), which adds
+            every value in O(log n) without periodic flushes. Batch updates
+            and queries are unaffected. Not compatible with exact_tails.
         """
         ...
 
@@ -36,11 +119,20 @@ class TDigest:
         x: Sequence[float],
         w: Optional[Union[Sequence[float], float]] = None,
         max_centroids: int = 1000,
+        n_threads: Optional[int] = 1,
+        presorted: bool = False,
+        chunked: bool = False,
     ) -> "TDigest":
         """
         Creates a new TDigest from a sequence of numeric values.
 
-        :param x: Sequence of numeric values.
+        :param x:
+            Sequence of numeric values. Contiguous float or integer
+            buffers (e.g. NumPy arrays), Arrow data (e.g. pyarrow arrays) and
+            DLPack tensors in CPU memory (e.g. PyTorch tensors) are read in
+            place without copying; GPU tensors are copied once. datetime64
+            and timedelta64 arrays are read as (epoch) seconds, skipping NaT,
+            and set the `dtype` of the TDigest.
         :param optional w:
             Weights. This can be either a sequence of the same length as `x`,
             or a scalar that will be used as the weight for the entire batch.
@@ -49,21 +141,136 @@ class TDigest:
             Number of centroids to maintain. A lower value enables a
             smaller memory footprint and faster computation speed at the
             cost of some accuracy. 0 disables compression. Default is 1000.
+        :param optional n_threads:
+            Number of threads for sorting the values, which dominates the
+            build time of large inputs. `None` uses all available cores.
+            Default is 1.
+        :param optional presorted:
+            If `True`, `x` must already be sorted in ascending order (which
+            is checked); the sort is skipped and the values are clustered
+            in a single pass. Default is `False`.
+        :param optional chunked:
+            If `True`, the values are split into `n_threads` chunks, which
+            are sorted and clustered into digests in parallel and then
+            merged. This scales better with the cores than sorting in
+            parallel, but the result is not identical to a single-threaded
+            one. Default is `False`.
+        """
+        ...
+
+    @staticmethod
+    def from_series(
+        s: Any,
+        w: Optional[Union[Sequence[float], float]] = None,
+        max_centroids: int = 1000,
+        n_threads: Optional[int] = 1,
+    ) -> "TDigest":
+        """
+        Creates a new TDigest from a pandas or polars Series, skipping
+        NaN/NA/null values.
+
+        :param s:
+            Series of numeric values. polars Series (and other objects
+            implementing the Arrow PyCapsule interface, like pyarrow arrays)
+            are read through the Arrow C data interface, pandas Series from
+            the underlying NumPy buffer, both without copying.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `s`,
+            or a scalar that will be used as the weight for the entire batch.
+            Weights of missing values are ignored.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :param optional n_threads:
+            Number of threads for sorting the values, which dominates the
+            build time of large inputs. `None` uses all available cores.
+            Default is 1.
+        """
+        ...
+
+    @staticmethod
+    def ingest_file(
+        path: Union[str, "os.PathLike[str]"],
+        dtype: str = "f8",
+        chunk: int = 1 << 20,
+        max_centroids: int = 1000,
+    ) -> "TDigest":
+        """
+        Creates a new TDigest from a raw binary file of numbers, streamed
+        chunk by chunk without loading it into Python.
+
+        :param path: Path of the file.
+        :param optional dtype:
+            NumPy type code of the items ("f8", "f4", "i8", "i4", "i2",
+            "i1", "u8", "u4", "u2" or "u1"), optionally prefixed with a byte
+            order ("<", ">" or "="). Default is "f8" in native byte order.
+        :param optional chunk:
+            Number of values read and merged at a time. Default is 2**20.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :return: TDigest instance.
+        """
+        ...
+
+    @staticmethod
+    def from_csv(
+        path: Union[str, "os.PathLike[str]"],
+        column: Union[str, int],
+        delimiter: str = ",",
+        chunk: int = 1 << 20,
+        max_centroids: int = 1000,
+    ) -> "TDigest":
+        """
+        Creates a new TDigest from a numeric column of a CSV file with a
+        header row, streamed chunk by chunk without loading it into Python.
+        Empty fields are skipped.
+
+        :param path: Path of the file.
+        :param column: Name or index of the column.
+        :param optional delimiter: Field delimiter. Default is ",".
+        :param optional chunk:
+            Number of values parsed and merged at a time. Default is 2**20.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :return: TDigest instance.
+        """
+        ...
+
+    @staticmethod
+    def from_parquet(
+        path: Union[str, "os.PathLike[str]"],
+        column: str,
+        max_centroids: int = 1000,
+    ) -> "TDigest":
+        """
+        Creates a new TDigest from a numeric column of a Parquet file, read
+        one row group at a time with pyarrow. Null values are skipped.
+
+        :param path: Path of the file.
+        :param column: Name of the column.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :return: TDigest instance.
         """
         ...
 
     @staticmethod
-    def from_bytes(data: bytes) -> "TDigest":
+    def from_bytes(data: bytes, strict: bool = False) -> "TDigest":
         """
         Deserialize a TDigest from its binary representation.
 
-        :param data: Binary representation produced by `to_bytes()`.
+        :param data: Binary representation produced by `to_bytes()`, in
+            either mode.
+        :param strict:
+            If True, raise a ValueError if the centroids or totals are
+            inconsistent (e.g. corrupted on the way).
         :return: TDigest instance.
         """
         ...
 
     @staticmethod
-    def from_dict(tdigest_dict: Dict[str, Any]) -> "TDigest":
+    def from_dict(
+        tdigest_dict: Dict[str, Any], strict: bool = False
+    ) -> "TDigest":
         """
         Construct a TDigest from a dict representation.
 
@@ -71,6 +278,50 @@ class TDigest:
         Each centroid should be a dict with keys "m" (float) and "c" (float).
 
         :param tdigest_dict: Dict with centroids.
+        :param strict:
+            If True, raise a ValueError for non-finite means, weights that
+            are not greater than 0, centroids out of order by mean, or
+            totals ("mass", "sum", "min", "max") that do not match them.
+        :return: TDigest instance.
+        """
+        ...
+
+    @staticmethod
+    def from_otel_exponential_histogram(
+        data: Dict[str, Any], max_centroids: int = 1000
+    ) -> "TDigest":
+        """
+        Construct an approximate TDigest from an OpenTelemetry exponential
+        histogram data point.
+
+        The dict uses the field names of the OTLP data model ("scale",
+        "count", "sum", "min", "max", "zero_count", "positive", "negative").
+        Only "scale" is required. Each non-empty bucket becomes a centroid
+        located at the bucket midpoint. Non-finite bucket boundaries, a
+        "count" that differs from the total of the buckets and "min" greater
+        than "max" raise a SerializationError.
+
+        :param data: Dict representation of the exponential histogram.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :return: TDigest instance.
+        """
+        ...
+
+    @staticmethod
+    def from_spark_quantile_summaries(
+        data: bytes, max_centroids: int = 1000
+    ) -> "TDigest":
+        """
+        Construct an approximate TDigest from the serialized state of
+        Spark's approx_percentile aggregate (QuantileSummaries).
+
+        Each sample of the Greenwald-Khanna summary becomes a centroid,
+        weighted by the number of values it represents.
+
+        :param data: Serialized PercentileDigest (Spark 2.x or 3.x format).
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
         :return: TDigest instance.
         """
         ...
@@ -79,7 +330,9 @@ class TDigest:
     def max_centroids(self) -> int:
         """
         Instance parameter controlling the maximum size (number of centroids)
-        of the data structure.
+        of the data structure. Assigning a smaller value merges the
+        centroids down to it right away; all later compressions use the new
+        value.
 
         :return: Maximum number of centroids parameter.
         """
@@ -90,6 +343,89 @@ class TDigest:
         ""
         ...
 
+    @property
+    def storage(self) -> str:
+        """
+        Precision of the centroid storage ("f64" or "f32"). Can be assigned
+        to in order to convert the instance.
+
+        :return: Storage precision.
+        """
+        ...
+
+    @storage.setter
+    def storage(self, value: str) -> None:
+        ""
+        ...
+
+    @property
+    def backend(self) -> str:
+        """
+        Structure that single updates are added to ("merging" or "tree").
+
+        :return: Name of the backend.
+        """
+        ...
+
+    @property
+    def tail_singletons(self) -> int:
+        """
+        Number of centroids at either end that are never merged. Can be
+        assigned to, taking effect from the next compression.
+
+        :return: Number of protected centroids per tail.
+        """
+        ...
+
+    @tail_singletons.setter
+    def tail_singletons(self, value: int) -> None:
+        ""
+        ...
+
+    @property
+    def resolution(self) -> str:
+        """
+        Part of the distribution that is resolved most finely ("both",
+        "upper" or "lower"). Can be assigned to, taking effect from the next
+        compression.
+
+        :return: Resolved tail(s).
+        """
+        ...
+
+    @resolution.setter
+    def resolution(self, value: str) -> None:
+        ""
+        ...
+
+    @property
+    def exact_tails(self) -> int:
+        """
+        Number of lowest and highest values that are stored exactly.
+
+        :return: Number of stored values per tail.
+        """
+        ...
+
+    @property
+    def dtype(self) -> Optional[str]:
+        """
+        dtype of the NumPy datetime64 or timedelta64 values fed to the
+        TDigest, e.g. "datetime64[ns]", or `None` for plain numbers. With a
+        dtype, `quantile`, `quantile_vec`, `percentile`, `median`, `min`,
+        `max` and `mean` return NumPy scalars of it (and `iqr` a
+        timedelta64) instead of floats. Can be assigned to, with a unit of
+        "s", "ms", "us" or "ns".
+
+        :return: dtype name or `None`.
+        """
+        ...
+
+    @dtype.setter
+    def dtype(self, value: Optional[str]) -> None:
+        ""
+        ...
+
     @property
     def n_values(self) -> int:
         """
@@ -157,7 +493,45 @@ class TDigest:
         """
         ...
 
-    def merge(self, other: "TDigest") -> "TDigest":
+    def stats(self) -> Dict[str, Any]:
+        """
+        Returns counters describing the compressions and merges performed
+        on the TDigest: "compressions", "merges", "last_compression_ratio"
+        (centroids and incoming values per resulting centroid, or None),
+        "values_buffered", "values_merged", "n_centroids" and
+        "max_centroids".
+
+        :return: Dictionary of counters.
+        """
+        ...
+
+    def memory_bytes(self) -> int:
+        """
+        Returns the number of bytes used by the buffers and the centroid
+        storage of the TDigest, including its heap allocations.
+
+        :return: Number of bytes.
+        """
+        ...
+
+    def scale_weights(
+        self, factor: float, min_weight: Optional[float] = None
+    ) -> None:
+        """
+        Multiplies the weights of all centroids in-place by `factor`, so that
+        values added afterwards weigh more (manual forgetting). The count of
+        ingested values is not changed.
+
+        :param factor: Float greater than 0 and at most 1.
+        :param optional min_weight:
+            Centroids whose weight falls below it are dropped. Default is
+            `None` (keep all).
+        """
+        ...
+
+    def merge(
+        self, other: "TDigest", weight: Optional[float] = None
+    ) -> "TDigest":
         """
         Merges this TDigest with another, returning a new instance.
 
@@ -167,11 +541,16 @@ class TDigest:
         will use the higher value.
 
         :param other: Other TDigest instance.
+        :param optional weight:
+            Factor for the weights of the centroids of `other`, e.g. to blend
+            a prior with live data. Default is `None` (1).
         :return: New TDigest representing the merged data.
         """
         ...
 
-    def merge_inplace(self, other: "TDigest") -> None:
+    def merge_inplace(
+        self, other: "TDigest", weight: Optional[float] = None
+    ) -> None:
         """
         Merges another TDigest into `self`, modifying the calling object
         in-place.
@@ -179,6 +558,53 @@ class TDigest:
         Equivalent to the `+=` operator.
 
         :param other: Other TDigest instance.
+        :param optional weight:
+            Factor for the weights of the centroids of `other`. Default is
+            `None` (1).
+        """
+        ...
+
+    def difference(self, other: "TDigest") -> "TDigest":
+        """
+        Approximately removes the values of another TDigest, which were
+        merged into this one before, returning a new instance.
+
+        Equivalent to the `-` operator. The weight of each centroid of
+        `other` is taken from the nearest centroids, so the result is exact
+        only where both digests resolve the same values.
+
+        :param other: Other TDigest instance.
+        :return: New TDigest without the data of `other`.
+        """
+        ...
+
+    @staticmethod
+    def merge_all(
+        digests: Iterable["TDigest"],
+        max_centroids: Optional[int] = None,
+        n_threads: Optional[int] = 1,
+        deterministic: bool = False,
+        streaming: bool = False,
+    ) -> "TDigest":
+        """
+        Merge an iterable of TDigests into a single new instance.
+
+        Equivalent to the module-level function `merge_all`.
+
+        :param digests: Iterable of TDigest instances to merge.
+        :param optional max_centroids:
+            Parameter to be used for the new instance. If `None` (default),
+            the value is determined from the input TDigests.
+        :param optional n_threads:
+            Number of threads for a parallel tree merge. `None` uses all
+            available cores. Default is 1.
+        :param optional deterministic:
+            If `True`, the result is bit-identical regardless of the order
+            of the digests (and of `n_threads`). Default is `False`.
+        :param optional streaming:
+            If `True`, the centroids are merged in a single pass without
+            copying the digests first. Default is `False`.
+        :return: New TDigest representing the merged data.
         """
         ...
 
@@ -186,15 +612,71 @@ class TDigest:
         self,
         x: Sequence[float],
         w: Optional[Union[Sequence[float], float]] = None,
+        n_threads: Optional[int] = 1,
+        presorted: bool = False,
+        chunked: bool = False,
     ) -> None:
         """
         Updates the TDigest in-place with a sequence of numeric values.
 
-        :param x: Sequence of values to add.
+        :param x:
+            Sequence of values to add. Contiguous float or integer
+            buffers (e.g. NumPy arrays), Arrow data (e.g. pyarrow arrays) and
+            DLPack tensors in CPU memory (e.g. PyTorch tensors) are read in
+            place without copying; GPU tensors are copied once. NaN/NA/null
+            values of Series and Arrow data are skipped. datetime64 and
+            timedelta64 arrays are added as (epoch) seconds, skipping NaT,
+            and set the `dtype` of the TDigest.
         :param optional w:
             Weights. This can be either a sequence of the same length as `x`,
             or a scalar that will be used as the weight for the entire batch.
             If `None` (default), each value has a weight of 1.
+        :param optional n_threads:
+            Number of threads for sorting the values, which dominates the
+            build time of large inputs. `None` uses all available cores.
+            Default is 1.
+        :param optional presorted:
+            If `True`, `x` must already be sorted in ascending order (which
+            is checked); the sort is skipped and the values are clustered
+            in a single pass. Default is `False`.
+        :param optional chunked:
+            If `True`, the values are split into `n_threads` chunks, which
+            are sorted and clustered into digests in parallel and then
+            merged. This scales better with the cores than sorting in
+            parallel, but the result is not identical to a single-threaded
+            one. Default is `False`.
+        """
+        ...
+
+    def batch_update_chunks(
+        self,
+        chunks: Iterable[Sequence[float]],
+        callback: Optional[Callable[[int, int], Optional[bool]]] = None,
+        every: int = 1,
+    ) -> None:
+        """
+        Updates the TDigest in-place with each sequence (e.g. NumPy array)
+        yielded by an iterable, one at a time.
+
+        :param chunks: Iterable of sequences of values to add.
+        :param optional callback:
+            Called as `callback(n_chunks, n_values)` with the number of
+            chunks and values ingested so far. If it returns `False`, the
+            remaining chunks are not consumed. Default is `None`.
+        :param optional every:
+            Number of chunks between two calls of `callback`. Default is 1.
+        """
+        ...
+
+    def aupdate(
+        self, iterable: AsyncIterable[Union[float, Sequence[float]]]
+    ) -> Coroutine[Any, Any, None]:
+        """
+        Returns an awaitable that updates the TDigest in-place with each
+        value or sequence of values yielded by an async iterable, yielding
+        control to the event loop after each of them.
+
+        :param iterable: Async iterable of values or sequences of values.
         """
         ...
 
@@ -210,6 +692,16 @@ class TDigest:
         """
         ...
 
+    def remove(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Approximately removes a previously added value in-place, taking its
+        weight from the nearest centroids.
+
+        :param x: Value to remove.
+        :param optional w: Weight for `x`. Default is `None` (1).
+        """
+        ...
+
     def quantile(self, q: float) -> float:
         """
         Estimates the value at the given relative rank/cumulative probability
@@ -218,7 +710,8 @@ class TDigest:
         Inverse function of `cdf(x)`.
 
         :param q: Float between 0 and 1.
-        :return: Estimated quantile value.
+        :return:
+            Estimated quantile value, a NumPy scalar if `dtype` is set.
         """
         ...
 
@@ -265,25 +758,75 @@ class TDigest:
         """
         ...
 
+    def tail_quantile(
+        self, q: float, method: str = "gpd", threshold: float = 0.95
+    ) -> float:
+        """
+        Estimates the value at the cumulative probability `q` in one of the
+        tails, extrapolating beyond the observed range (e.g. 1-in-10^7
+        events).
+
+        Fits a generalized Pareto distribution to the values beyond the
+        `threshold` quantile (or below the `1 - threshold` quantile for
+        the lower tail). For `q` between those quantiles, this is
+        equivalent to `quantile(q)`.
+
+        :param float q: Cumulative probability (0 to 1).
+        :param optional method: Tail model, currently only "gpd".
+        :param optional threshold:
+            Quantile (0.5 to 1, exclusive) beyond which the tail is fitted.
+            Default is 0.95.
+        :return: Estimated value at the given probability.
+        """
+        ...
+
     def cdf(self, x: float) -> float:
         """
         Estimates the cumulative distribution function (CDF) at the value `x`.
 
         Inverse function of `quantile(q)`.
 
-        :param x: Value for which to compute the CDF.
+        :param x:
+            Value for which to compute the CDF, or a NumPy datetime64 or
+            timedelta64 scalar.
         :return: Float between 0 and 1 representing cumulative probability.
         """
         ...
 
-    def cdf_vec(self, x: Sequence[float]) -> List[float]:
+    @overload
+    def cdf_vec(
+        self,
+        x: Sequence[float],
+        out: None = None,
+        n_threads: Optional[int] = 1,
+    ) -> List[float]: ...
+    @overload
+    def cdf_vec(
+        self, x: Sequence[float], out: Any, n_threads: Optional[int] = 1
+    ) -> Any: ...
+    def cdf_vec(
+        self,
+        x: Sequence[float],
+        out: Optional[Any] = None,
+        n_threads: Optional[int] = 1,
+    ) -> Union[List[float], Any]:
         """
         Estimates the cumulative distribution function (CDF) at the values `x`.
 
         Inverse function of `quantile_vec(q)`.
 
-        :param x: Sequence of values for which to compute the CDF.
-        :return: List of CDF(x) floats between 0 and 1.
+        :param x:
+            Sequence of values for which to compute the CDF, or a NumPy
+            datetime64 or timedelta64 array. Float64 arrays are read in
+            place.
+        :param optional out:
+            Float64 array (or other writable buffer) of the same length as
+            `x`, into which the results are written instead of a new list.
+            It may be `x` itself.
+        :param optional n_threads:
+            Number of threads among which the values are split. `None` uses
+            all available cores. Default is 1.
+        :return: List of CDF(x) floats between 0 and 1, or `out`.
         """
         ...
 
@@ -360,14 +903,63 @@ class TDigest:
         """
         ...
 
-    def to_bytes(self) -> bytes:
+    def to_bytes(self, mode: str = "exact") -> bytes:
         """
         Returns a serialized binary representation of the TDigest.
 
+        The "compact" mode rounds the means to single precision and
+        encodes them as differences, typically taking a quarter to a third
+        of the size. The totals are kept exactly, and a digest restored
+        from the compact format gives the same bytes again.
+
+        :param mode: "exact" (default) or "compact".
         :return: Binary representation of the TDigest.
         """
         ...
 
+    def write_to(self, file: Any) -> None:
+        """
+        Writes the binary representation of `to_bytes()` to a stream.
+
+        :param file: Binary file object (with `write`) or socket.
+        """
+        ...
+
+    @staticmethod
+    def read_from(file: Any, strict: bool = False) -> "TDigest":
+        """
+        Reads a TDigest written by `write_to` from a stream, taking only
+        its bytes.
+
+        :param file: Binary file object (with `read`) or socket.
+        :param strict:
+            If True, raise a ValueError if the centroids or totals are
+            inconsistent.
+        :return: TDigest instance.
+        """
+        ...
+
+    def delta_since(self, snapshot: "TDigest") -> bytes:
+        """
+        Returns a compact binary delta from the state of `snapshot` (an
+        earlier copy of this TDigest) to the current state, holding only the
+        centroids that changed since then and the new totals.
+
+        :param snapshot: Earlier copy of this TDigest.
+        :return: Binary delta, for `apply_delta`.
+        """
+        ...
+
+    def apply_delta(self, delta: bytes) -> None:
+        """
+        Updates the TDigest in-place to the state encoded by a delta from
+        `delta_since`. The TDigest must be in the state of the snapshot the
+        delta was computed from; otherwise a `SerializationError` is raised.
+
+        :param delta: Binary delta from `delta_since`.
+        """
+        ...
+
     def to_dict(self) -> Dict[str, Union[float, int, List[Dict[str, float]]]]:
         """
         Returns a dict representation of the TDigest.
@@ -380,6 +972,67 @@ class TDigest:
         """
         ...
 
+    def to_numpy(self) -> Tuple[Any, Any]:
+        """
+        Returns the centroids as two NumPy arrays of means and weights.
+
+        Requires NumPy to be installed.
+
+        :return: Tuple of float64 arrays (means, weights), sorted by mean.
+        """
+        ...
+
+    def to_otel_exponential_histogram(
+        self, scale: int, max_size: int = 160, zero_threshold: float = 0.0
+    ) -> Dict[str, Any]:
+        """
+        Returns the TDigest as an OpenTelemetry exponential histogram data
+        point.
+
+        Each centroid is counted in the bucket containing its mean. If the
+        buckets do not fit into `max_size` at the requested `scale`, the scale
+        is reduced until they do (as in the OpenTelemetry SDKs).
+
+        :param scale: Requested resolution (-10 to 20).
+        :param optional max_size:
+            Maximum number of buckets per sign. Default is 160.
+        :param optional zero_threshold:
+            Values with an absolute value up to this threshold are counted in
+            the zero bucket. Default is 0.
+        :return: Dict with the fields of the OTLP data point.
+        """
+        ...
+
+    def to_prometheus(
+        self,
+        name: str,
+        kind: str = "summary",
+        quantiles: Optional[Sequence[float]] = None,
+        buckets: Optional[Sequence[float]] = None,
+        labels: Optional[Dict[str, Any]] = None,
+        help: Optional[str] = None,
+    ) -> str:
+        """
+        Renders the TDigest as a metric family in the Prometheus text
+        exposition format.
+
+        :param name: Metric name.
+        :param optional kind:
+            Either "summary" (default) or "histogram".
+        :param optional quantiles:
+            Quantiles to report for a summary. Default is [0.5, 0.9, 0.99].
+            Not allowed for a histogram.
+        :param optional buckets:
+            Upper bounds ("le") of the histogram buckets in increasing order.
+            Required for a histogram. The "+Inf" bucket is always added.
+        :param optional labels:
+            Labels attached to every sample. "quantile" (for a summary) and
+            "le" (for a histogram) are reserved.
+        :param optional help: Text for the "# HELP" line.
+        :return: Exposition text.
+        """
+        ...
+
     def equals(self, other: "TDigest") -> bool:
         """
         Checks equality between two TDigest instances.
@@ -435,6 +1088,15 @@ class TDigest:
         """
         ...
 
+    def __sizeof__(self) -> int:
+        """
+        Returns the size of the object including its heap allocations, as
+        used by `sys.getsizeof`.
+
+        :return: Number of bytes.
+        """
+        ...
+
     def __len__(self) -> int:
         """
         Returns the number of centroids in the TDigest.
@@ -451,6 +1113,21 @@ class TDigest:
         """
         ...
 
+    @overload
+    def __getitem__(self, index: int) -> Tuple[float, float]: ...
+    @overload
+    def __getitem__(self, index: slice) -> List[Tuple[float, float]]: ...
+    def __getitem__(
+        self, index: Union[int, slice]
+    ) -> Union[Tuple[float, float], List[Tuple[float, float]]]:
+        """
+        Returns the centroid at `index`, or a list of centroids for a slice.
+
+        :param index: Integer index (negative counts from the end) or slice.
+        :return: Centroid (mean, weight) tuple, or list of tuples.
+        """
+        ...
+
     def __repr__(self) -> str:
         """
         Returns a string representation of the TDigest.
@@ -494,20 +1171,5223 @@ class TDigest:
         """
         ...
 
-    def __iadd__(self, other: "TDigest") -> "TDigest":
+    def __radd__(self, other: int) -> "TDigest":
         """
-        Merge another TDigest into this one in-place.
+        Supports `0 + digest`, which returns a copy of the TDigest.
 
-        Equivalent to `self.merge_inplace(other)`, but using the `+=` operator.
+        This makes `sum(digests)` work, whose start value is 0.
 
-        :param other: Other TDigest instance.
-        :return: The modified TDigest instance.
+        :param other: The integer 0.
+        :return: Copy of the TDigest instance.
         """
         ...
 
-def merge_all(
-    digests: Iterable[TDigest], max_centroids: Optional[int] = None
-) -> TDigest:
+    def __sub__(self, other: "TDigest") -> "TDigest":
+        """
+        Approximately removes the data of another TDigest.
+
+        Equivalent to `self.difference(other)`, but using the `-` operator.
+
+        :param other: Other TDigest instance.
+        :return: New TDigest without the data of `other`.
+        """
+        ...
+
+    def __iadd__(self, other: "TDigest") -> "TDigest":
+        """
+        Merge another TDigest into this one in-place.
+
+        Equivalent to `self.merge_inplace(other)`, but using the `+=` operator.
+
+        :param other: Other TDigest instance.
+        :return: The modified TDigest instance.
+        """
+        ...
+
+
+class ConcurrentTDigest:
+    def __init__(
+        self, max_centroids: int = 1000, n_shards: Optional[int] = None
+    ) -> None:
+        """
+        Initialize a new, empty thread-safe digest. Concurrent updates are
+        spread over several internal digests (shards), one per thread.
+
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        :param optional n_shards:
+            Number of internal digests. `None` (default) uses one per
+            available CPU core.
+        """
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the shards and snapshots."""
+        ...
+
+    @property
+    def n_shards(self) -> int:
+        """The number of internal digests."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest in-place with a single value. This can be called
+        from several threads at the same time.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values, releasing the
+        GIL while merging. This can be called from several threads at the
+        same time.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def snapshot(self) -> TDigest:
+        """
+        Returns a consistent copy of the current state, merged from all
+        shards while they are locked.
+
+        :return: TDigest instance.
+        """
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class TDigestMap:
+    def __init__(self, max_centroids: int = 1000) -> None:
+        """
+        Initialize a new, empty map of digests, with one TDigest per key.
+        Queries for a key without a digest raise KeyError.
+
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested, over all keys."""
+        ...
+
+    def keys(self) -> List[Any]:
+        """Returns the keys in the order in which they were first seen."""
+        ...
+
+    def update(
+        self, key: Hashable, x: float, w: Optional[float] = None
+    ) -> None:
+        """
+        Updates the digest for `key` in-place with a single value, creating
+        the digest if necessary.
+
+        :param key: An int, float, str, bytes, bytearray, None or a tuple
+            thereof.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        keys: Iterable[Hashable],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digests in-place with a sequence of values, each added
+        to the digest for the key at the same position. The values are
+        grouped by key without the GIL, and each digest is merged once.
+
+        :param keys: Iterable of keys, or a numeric array (e.g. a NumPy
+            array), of the same length as `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def quantile(self, key: Hashable, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile) in
+        the digest for `key`.
+
+        :param key: Key of the digest.
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, key: Hashable, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities in the
+        digest for `key`.
+
+        :param key: Key of the digest.
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, key: Hashable, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent in
+        the digest for `key`.
+
+        :param key: Key of the digest.
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self, key: Hashable) -> float:
+        """Estimates the median of the digest for `key`."""
+        ...
+
+    def cdf(self, key: Hashable, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) of the digest
+        for `key` at the value `x`.
+
+        :param key: Key of the digest.
+        :param x: Value for which to compute the CDF.
+        :return: Float between 0 and 1 representing cumulative probability.
+        """
+        ...
+
+    def merge(self, other: "TDigestMap") -> "TDigestMap":
+        """
+        Merges this map with another one, key by key, and returns a new
+        instance. Keys found in only one of the maps are carried over.
+
+        :param other: TDigestMap instance.
+        :return: New TDigestMap instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "TDigestMap") -> None:
+        """
+        Merges another map into this one in-place, key by key.
+
+        :param other: TDigestMap instance.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of all digests."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "TDigestMap":
+        """
+        Reconstructs a TDigestMap from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: TDigestMap instance.
+        """
+        ...
+
+    def write_to(self, file: Any) -> None:
+        """
+        Writes the output of `to_bytes` to a stream in chunks, without
+        holding it in memory.
+
+        :param file: Binary file object (with `write`) or socket.
+        """
+        ...
+
+    @staticmethod
+    def read_from(file: Any) -> "TDigestMap":
+        """
+        Reads a TDigestMap written by `write_to` from a stream, digest by
+        digest, taking only its bytes.
+
+        :param file: Binary file object (with `read`) or socket.
+        :return: TDigestMap instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the max_centroids parameter and the
+        `to_dict` representations of the digests by key.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(map_dict: Dict[str, Any]) -> "TDigestMap":
+        """
+        Reconstructs a TDigestMap from the output of `to_dict`.
+
+        :param map_dict: Dictionary.
+        :return: TDigestMap instance.
+        """
+        ...
+
+    def copy(self) -> "TDigestMap":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "TDigestMap":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "TDigestMap":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of keys."""
+        ...
+
+    def __contains__(self, key: Hashable) -> bool:
+        """Returns True if there is a digest for `key`."""
+        ...
+
+    def __getitem__(self, key: Hashable) -> TDigest:
+        """Returns a copy of the digest for `key`."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both maps hold identical digests by key."""
+        ...
+
+    def __add__(self, other: "TDigestMap") -> "TDigestMap":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "TDigestMap") -> "TDigestMap":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class DigestFile:
+    """
+    Read-only mapping of keys to the digests in a file written by
+    `save_many`, each loaded from the file when it is looked up.
+    """
+
+    @property
+    def path(self) -> "os.PathLike[str]":
+        """Path of the file (a pathlib.Path)."""
+        ...
+
+    @property
+    def closed(self) -> bool:
+        """True once the file has been closed."""
+        ...
+
+    def keys(self) -> List[Any]:
+        """Returns the keys in the order in which they were saved."""
+        ...
+
+    def get(self, key: Hashable, default: Any = None) -> Any:
+        """
+        Loads the digest for `key`, or returns `default` if it is not in
+        the file.
+
+        :param key: Key of the digest.
+        :param optional default: Value for a missing key. Default is None.
+        :return: TDigest instance, or `default`.
+        """
+        ...
+
+    def to_map(self) -> TDigestMap:
+        """
+        Loads all digests into a TDigestMap. They must have the same
+        max_centroids.
+
+        :return: TDigestMap instance.
+        """
+        ...
+
+    def close(self) -> None:
+        """Closes the file. Further calls have no effect."""
+        ...
+
+    def __enter__(self) -> "DigestFile":
+        """Returns the instance, to be closed at the end of the block."""
+        ...
+
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> None:
+        """Closes the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of keys."""
+        ...
+
+    def __contains__(self, key: Hashable) -> bool:
+        """Returns True if the file holds a digest for `key`."""
+        ...
+
+    def __iter__(self) -> Iterator[Any]:
+        """Iterates over the keys."""
+        ...
+
+    def __getitem__(self, key: Hashable) -> TDigest:
+        """Loads the digest for `key`, raising KeyError if it is missing."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class TDigestArray:
+    def __init__(self, n: int, max_centroids: int = 1000) -> None:
+        """
+        Initialize an array of `n` empty digests, addressed by index. The
+        queries return NumPy arrays, which requires NumPy to be installed.
+
+        :param n: Number of digests.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested, over all digests."""
+        ...
+
+    def update(self, i: int, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest at index `i` in-place with a single value.
+
+        :param i: Index between 0 and `n - 1`.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        indices: Sequence[int],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+        n_threads: Optional[int] = 1,
+    ) -> None:
+        """
+        Updates the digests in-place with a sequence of values, each added
+        to the digest at the index at the same position. The values are
+        grouped by index without the GIL, and each digest is merged once.
+
+        :param indices:
+            Sequence of indices between 0 and `n - 1`, of the same length as
+            `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        :param optional n_threads:
+            Number of threads merging the digests. `None` uses all
+            available CPU cores. Default is 1.
+        """
+        ...
+
+    def quantile(self, q: float) -> Any:
+        """
+        Estimates the value at a given cumulative probability (quantile) in
+        every digest.
+
+        :param q: Float between 0 and 1.
+        :return: NumPy array of estimates, with NaN for empty digests.
+        """
+        ...
+
+    def percentile(self, p: float) -> Any:
+        """
+        Estimates the value at a given cumulative probability in percent in
+        every digest.
+
+        :param p: Float between 0 and 100.
+        :return: NumPy array of estimates, with NaN for empty digests.
+        """
+        ...
+
+    def median(self) -> Any:
+        """
+        Estimates the median of every digest.
+
+        :return: NumPy array of estimates, with NaN for empty digests.
+        """
+        ...
+
+    def cdf(self, x: float) -> Any:
+        """
+        Estimates the cumulative distribution function (CDF) of every digest
+        at the value `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: NumPy array of estimates, with NaN for empty digests.
+        """
+        ...
+
+    def mass(self) -> Any:
+        """Returns the total weight of every digest as a NumPy array."""
+        ...
+
+    def merge(self, other: "TDigestArray") -> "TDigestArray":
+        """
+        Merges this array with another one elementwise and returns a new
+        instance.
+
+        :param other: TDigestArray of the same length.
+        :return: New TDigestArray instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "TDigestArray") -> None:
+        """
+        Merges another array into this one in-place, elementwise.
+
+        :param other: TDigestArray of the same length.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of all digests."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "TDigestArray":
+        """
+        Reconstructs a TDigestArray from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: TDigestArray instance.
+        """
+        ...
+
+    def write_to(self, file: Any) -> None:
+        """
+        Writes the output of `to_bytes` to a stream in chunks, without
+        holding it in memory.
+
+        :param file: Binary file object (with `write`) or socket.
+        """
+        ...
+
+    @staticmethod
+    def read_from(file: Any) -> "TDigestArray":
+        """
+        Reads a TDigestArray written by `write_to` from a stream, digest by
+        digest, taking only its bytes.
+
+        :param file: Binary file object (with `read`) or socket.
+        :return: TDigestArray instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the max_centroids parameter and a list of
+        the `to_dict` representations of the digests.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(array_dict: Dict[str, Any]) -> "TDigestArray":
+        """
+        Reconstructs a TDigestArray from the output of `to_dict`.
+
+        :param array_dict: Dictionary.
+        :return: TDigestArray instance.
+        """
+        ...
+
+    def copy(self) -> "TDigestArray":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "TDigestArray":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "TDigestArray":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of digests."""
+        ...
+
+    def __getitem__(self, i: int) -> TDigest:
+        """
+        Returns a copy of the digest at index `i` (negative indices count
+        from the end).
+        """
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both arrays hold identical digests."""
+        ...
+
+    def __add__(self, other: "TDigestArray") -> "TDigestArray":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "TDigestArray") -> "TDigestArray":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class DataFrameDigest:
+    def __init__(self, max_centroids: int = 1000) -> None:
+        """
+        Initialize a summary of a table with one digest per column, keyed
+        by column name (or position for 2D arrays) in the order in which
+        the columns were first seen.
+
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @staticmethod
+    def from_dataframe(df: Any, max_centroids: int = 1000) -> "DataFrameDigest":
+        """
+        Initialize a new DataFrameDigest with the columns of a DataFrame or
+        2D array.
+
+        :param df: pandas or polars DataFrame, or 2D array.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        :return: New DataFrameDigest instance.
+        """
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def columns(self) -> List[Any]:
+        """The column names (or positions), in order of first appearance."""
+        ...
+
+    def update(self, df: Any) -> None:
+        """
+        Updates the digest of every numeric column in-place. Columns that
+        cannot be read as numbers are skipped, and missing values are
+        ignored. 2D arrays (which require NumPy) have numbered columns.
+
+        :param df: pandas or polars DataFrame, or 2D array.
+        """
+        ...
+
+    def describe(self, q: Sequence[float] = (0.25, 0.5, 0.75)) -> Any:
+        """
+        Summarizes every column like `pandas.DataFrame.describe`, with the
+        count, mean, standard deviation, min, estimated quantiles and max.
+        Requires pandas.
+
+        :param optional q:
+            Cumulative probabilities between 0 and 1. Default is the
+            quartiles.
+        :return: pandas DataFrame with one column per column.
+        """
+        ...
+
+    def merge(self, other: "DataFrameDigest") -> "DataFrameDigest":
+        """
+        Merges this summary with another one column by column and returns
+        a new instance. Columns of only one summary are included as well.
+
+        :param other: DataFrameDigest instance.
+        :return: New DataFrameDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "DataFrameDigest") -> None:
+        """
+        Merges another summary into this one in-place, column by column.
+
+        :param other: DataFrameDigest instance.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of all columns."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "DataFrameDigest":
+        """
+        Reconstructs a DataFrameDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: DataFrameDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the max_centroids parameter and the
+        `to_dict` representations of the digests by column, as for
+        TDigestMap.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(digest_dict: Dict[str, Any]) -> "DataFrameDigest":
+        """
+        Reconstructs a DataFrameDigest from the output of `to_dict`.
+
+        :param digest_dict: Dictionary.
+        :return: DataFrameDigest instance.
+        """
+        ...
+
+    def copy(self) -> "DataFrameDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "DataFrameDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "DataFrameDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of columns."""
+        ...
+
+    def __contains__(self, column: Any) -> bool:
+        """Returns True if there is a digest for `column`."""
+        ...
+
+    def __getitem__(self, column: Any) -> TDigest:
+        """
+        Returns a copy of the digest of `column`. Raises KeyError if there
+        is none.
+        """
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both summaries hold identical digests."""
+        ...
+
+    def __add__(self, other: "DataFrameDigest") -> "DataFrameDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "DataFrameDigest") -> "DataFrameDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class BinnedTDigest:
+    def __init__(
+        self, edges: Sequence[float], max_centroids: int = 1000
+    ) -> None:
+        """
+        Initialize an empty digest per bin of a covariate `x`, for the
+        quantiles of a value `y` as a function of `x`. Bin `i` holds the
+        values `y` with `edges[i] <= x < edges[i + 1]` (the last bin also
+        includes its right edge). The queries return NumPy arrays, which
+        requires NumPy to be installed.
+
+        :param edges: At least 2 finite, strictly increasing bin edges.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def edges(self) -> List[float]:
+        """The bin edges."""
+        ...
+
+    @property
+    def centers(self) -> Any:
+        """The center of every bin as a NumPy array."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested, over all bins."""
+        ...
+
+    def update(self, x: float, y: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest of the bin of `x` in-place with the value `y`.
+        Does nothing if `x` is outside all bins.
+
+        :param x: Covariate.
+        :param y: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        y: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+        n_threads: Optional[int] = 1,
+    ) -> None:
+        """
+        Updates the digests in-place with pairs of values, adding each value
+        of `y` to the bin of the value of `x` at the same position. Pairs
+        with `x` outside all bins are left out. The values are grouped by
+        bin without the GIL, and each digest is merged once.
+
+        :param x: Sequence of covariates, of the same length as `y`.
+        :param y: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `y`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        :param optional n_threads:
+            Number of threads merging the digests. `None` uses all
+            available CPU cores. Default is 1.
+        """
+        ...
+
+    def quantile(self, q: float) -> Any:
+        """
+        Estimates the value at a given cumulative probability (quantile) in
+        every bin.
+
+        :param q: Float between 0 and 1.
+        :return: NumPy array of estimates, with NaN for empty bins.
+        """
+        ...
+
+    def percentile(self, p: float) -> Any:
+        """
+        Estimates the value at a given cumulative probability in percent in
+        every bin.
+
+        :param p: Float between 0 and 100.
+        :return: NumPy array of estimates, with NaN for empty bins.
+        """
+        ...
+
+    def median(self) -> Any:
+        """
+        Estimates the median in every bin.
+
+        :return: NumPy array of estimates, with NaN for empty bins.
+        """
+        ...
+
+    def cdf(self, y: float) -> Any:
+        """
+        Estimates the cumulative distribution function (CDF) in every bin at
+        the value `y`.
+
+        :param y: Value for which to compute the CDF.
+        :return: NumPy array of estimates, with NaN for empty bins.
+        """
+        ...
+
+    def quantile_curve(self, q: Sequence[float]) -> Any:
+        """
+        Estimates several quantiles in every bin, e.g. to plot them against
+        `centers`.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return:
+            NumPy array of shape `(n_bins, len(q))`, with NaN for empty bins.
+        """
+        ...
+
+    def mass(self) -> Any:
+        """Returns the total weight of every bin as a NumPy array."""
+        ...
+
+    def merge(self, other: "BinnedTDigest") -> "BinnedTDigest":
+        """
+        Merges this digest with another one bin by bin and returns a new
+        instance.
+
+        :param other: BinnedTDigest with the same edges.
+        :return: New BinnedTDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "BinnedTDigest") -> None:
+        """
+        Merges another digest into this one in-place, bin by bin.
+
+        :param other: BinnedTDigest with the same edges.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the edges and digests."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "BinnedTDigest":
+        """
+        Reconstructs a BinnedTDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: BinnedTDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the edges, the max_centroids parameter and
+        a list of the `to_dict` representations of the digests.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(binned_dict: Dict[str, Any]) -> "BinnedTDigest":
+        """
+        Reconstructs a BinnedTDigest from the output of `to_dict`.
+
+        :param binned_dict: Dictionary.
+        :return: BinnedTDigest instance.
+        """
+        ...
+
+    def copy(self) -> "BinnedTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "BinnedTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "BinnedTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of bins."""
+        ...
+
+    def __getitem__(self, i: int) -> TDigest:
+        """
+        Returns a copy of the digest of bin `i` (negative indices count from
+        the end).
+        """
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same edges and identical digests."""
+        ...
+
+    def __add__(self, other: "BinnedTDigest") -> "BinnedTDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "BinnedTDigest") -> "BinnedTDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class RollingTDigest:
+    def __init__(
+        self, window: float, n_buckets: int = 10, max_centroids: int = 1000
+    ) -> None:
+        """
+        Initialize a new, empty digest of the values over a sliding time
+        window. The window is split into `n_buckets` intervals of equal
+        length with a digest each; the oldest one is dropped whole when the
+        clock moves past the window, so queries cover the last `window`
+        time units at the granularity of one interval.
+
+        :param window: Window length, in the unit of the timestamps.
+        :param optional n_buckets:
+            Number of intervals of the window. Default is 10.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def window(self) -> float:
+        """Window length."""
+        ...
+
+    @property
+    def n_buckets(self) -> int:
+        """Number of intervals of the window."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def now(self) -> Optional[float]:
+        """Latest timestamp, or None if the clock has not started."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points in the window."""
+        ...
+
+    def update(
+        self, timestamp: float, x: float, w: Optional[float] = None
+    ) -> None:
+        """
+        Updates the digest in-place with a single value. Timestamps must
+        not decrease.
+
+        :param timestamp: Timestamp of the value.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        timestamps: Sequence[float],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values, merging each
+        interval once.
+
+        :param timestamps:
+            Non-decreasing sequence of timestamps, of the same length as `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def advance(self, now: float) -> None:
+        """
+        Moves the clock in-place to `now` without adding a value, dropping
+        the values that left the window.
+
+        :param now: Timestamp not preceding the latest one.
+        """
+        ...
+
+    def snapshot(self) -> TDigest:
+        """Returns the values in the window as a TDigest."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile) in
+        the window.
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent in
+        the window.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median of the values in the window.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) of the values in
+        the window at the value `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the window holds no values."""
+        ...
+
+    def merge(self, other: "RollingTDigest") -> "RollingTDigest":
+        """
+        Merges this digest with another one interval by interval and returns
+        a new instance, with the later clock of both.
+
+        :param other: RollingTDigest with the same window and n_buckets.
+        :return: New RollingTDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "RollingTDigest") -> None:
+        """
+        Merges another digest into this one in-place, interval by interval.
+
+        :param other: RollingTDigest with the same window and n_buckets.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the digest."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "RollingTDigest":
+        """
+        Reconstructs a RollingTDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: RollingTDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the parameters, the clock, and the epochs
+        (interval numbers) and `to_dict` representations of the digests.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(rolling_dict: Dict[str, Any]) -> "RollingTDigest":
+        """
+        Reconstructs a RollingTDigest from the output of `to_dict`.
+
+        :param rolling_dict: Dictionary.
+        :return: RollingTDigest instance.
+        """
+        ...
+
+    def copy(self) -> "RollingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "RollingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "RollingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same parameters, clock and digests."""
+        ...
+
+    def __add__(self, other: "RollingTDigest") -> "RollingTDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "RollingTDigest") -> "RollingTDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class DecayingTDigest:
+    def __init__(self, half_life: float, max_centroids: int = 1000) -> None:
+        """
+        Initialize a new, empty digest whose values lose weight exponentially
+        over time: a value `half_life` time units older than the latest one
+        counts half as much. Values may arrive out of order, and digests with
+        the same half-life can be merged exactly.
+
+        :param half_life: Half-life, in the unit of the timestamps.
+        :param optional max_centroids:
+            Number of centroids to maintain. Default is 1000.
+        """
+        ...
+
+    @property
+    def half_life(self) -> float:
+        """Half-life of the weights."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digest."""
+        ...
+
+    @property
+    def now(self) -> Optional[float]:
+        """Latest timestamp, or None if the clock has not started."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points added."""
+        ...
+
+    def update(
+        self, timestamp: float, x: float, w: Optional[float] = None
+    ) -> None:
+        """
+        Updates the digest in-place with a single value.
+
+        :param timestamp: Timestamp of the value.
+        :param x: Value to add.
+        :param optional w: Weight at `timestamp`. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        timestamps: Sequence[float],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values.
+
+        :param timestamps: Sequence of timestamps of the same length as `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile) of
+        the decayed distribution.
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent of
+        the decayed distribution.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median of the decayed distribution.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) of the decayed
+        distribution at the value `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def mean(self) -> float:
+        """
+        Calculates the weighted mean of the decayed distribution.
+
+        :return: Mean value.
+        """
+        ...
+
+    def mass(self, now: Optional[float] = None) -> float:
+        """
+        Returns the total decayed weight.
+
+        :param optional now:
+            Time to decay the weights to. Default is the latest timestamp.
+        :return: Total weight.
+        """
+        ...
+
+    def snapshot(self, now: Optional[float] = None) -> TDigest:
+        """
+        Returns the decayed values as a TDigest.
+
+        :param optional now:
+            Time to decay the weights to. Default is the latest timestamp.
+        :return: TDigest instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def merge(self, other: "DecayingTDigest") -> "DecayingTDigest":
+        """
+        Merges this digest with another one and returns a new instance, with
+        the later clock of both.
+
+        :param other: DecayingTDigest with the same half-life.
+        :return: New DecayingTDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "DecayingTDigest") -> None:
+        """
+        Merges another digest into this one in-place.
+
+        :param other: DecayingTDigest with the same half-life.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the digest."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "DecayingTDigest":
+        """
+        Reconstructs a DecayingTDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: DecayingTDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the half-life, the landmark (the timestamp
+        the stored weights are relative to), the clock and the `to_dict`
+        representation of the digest.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(decaying_dict: Dict[str, Any]) -> "DecayingTDigest":
+        """
+        Reconstructs a DecayingTDigest from the output of `to_dict`.
+
+        :param decaying_dict: Dictionary.
+        :return: DecayingTDigest instance.
+        """
+        ...
+
+    def copy(self) -> "DecayingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "DecayingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "DecayingTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same parameters, clock and digest."""
+        ...
+
+    def __add__(self, other: "DecayingTDigest") -> "DecayingTDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "DecayingTDigest") -> "DecayingTDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class EventTimeTDigest:
+    def __init__(self, interval: float, max_centroids: int = 1000) -> None:
+        """
+        Initialize a new, empty digest of values tagged with event
+        timestamps, which may arrive in any order. Each interval of
+        `interval` time units of event time has its own digest; `expire`
+        drops the intervals that end at or before a watermark, and values
+        older than the watermark are counted as late instead of being added.
+
+        :param interval: Interval length, in the unit of the timestamps.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def interval(self) -> float:
+        """Interval length."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def watermark(self) -> Optional[float]:
+        """Latest watermark, or None if nothing has expired yet."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points held."""
+        ...
+
+    @property
+    def n_late(self) -> int:
+        """Number of data points dropped for preceding the watermark."""
+        ...
+
+    def update(
+        self, timestamp: float, x: float, w: Optional[float] = None
+    ) -> None:
+        """
+        Updates the digest in-place with a single value, unless it precedes
+        the watermark.
+
+        :param timestamp: Event timestamp of the value.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        timestamps: Sequence[float],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values, merging each
+        interval once. Values preceding the watermark are left out.
+
+        :param timestamps:
+            Event timestamps in any order, of the same length as `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def expire(self, before_ts: float) -> None:
+        """
+        Moves the watermark in-place to `before_ts`, dropping the intervals
+        that end at or before it. An earlier watermark than the current one
+        has no effect.
+
+        :param before_ts: Watermark timestamp.
+        """
+        ...
+
+    def ranges(self) -> List[Tuple[float, float]]:
+        """Returns the event-time ranges (start, end) of the intervals."""
+        ...
+
+    def snapshot(
+        self, start: Optional[float] = None, end: Optional[float] = None
+    ) -> TDigest:
+        """
+        Returns the values of the intervals overlapping the event-time range
+        [start, end) as a TDigest.
+
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: TDigest instance.
+        """
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile) of
+        all values held.
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent of
+        all values held.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median of all values held.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) of all values
+        held at the value `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def merge(self, other: "EventTimeTDigest") -> "EventTimeTDigest":
+        """
+        Merges this digest with another one interval by interval and returns
+        a new instance, with the later watermark of both.
+
+        :param other: EventTimeTDigest with the same interval.
+        :return: New EventTimeTDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "EventTimeTDigest") -> None:
+        """
+        Merges another digest into this one in-place, interval by interval.
+
+        :param other: EventTimeTDigest with the same interval.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the digest."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "EventTimeTDigest":
+        """
+        Reconstructs an EventTimeTDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: EventTimeTDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the parameters, the watermark, the number
+        of late values, and the epochs (interval numbers) and `to_dict`
+        representations of the digests.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(event_time_dict: Dict[str, Any]) -> "EventTimeTDigest":
+        """
+        Reconstructs an EventTimeTDigest from the output of `to_dict`.
+
+        :param event_time_dict: Dictionary.
+        :return: EventTimeTDigest instance.
+        """
+        ...
+
+    def copy(self) -> "EventTimeTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "EventTimeTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "EventTimeTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same parameters, state and digests."""
+        ...
+
+    def __add__(self, other: "EventTimeTDigest") -> "EventTimeTDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "EventTimeTDigest") -> "EventTimeTDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class TimeSeriesDigest:
+    def __init__(self, resolution: float, max_centroids: int = 1000) -> None:
+        """
+        Initialize a new, empty store of digests for consecutive intervals
+        of `resolution` time units. Values go to the bucket of their
+        interval in any order, queries merge the buckets overlapping a time
+        range, and `rollup` merges fine buckets into coarser ones.
+
+        :param resolution: Interval length, in the unit of the timestamps.
+        :param optional max_centroids:
+            Number of centroids to maintain in each digest. Default is 1000.
+        """
+        ...
+
+    @property
+    def resolution(self) -> float:
+        """Length of the finest intervals."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digests."""
+        ...
+
+    @property
+    def n_buckets(self) -> int:
+        """Number of buckets."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points."""
+        ...
+
+    def update(
+        self, timestamp: float, x: float, w: Optional[float] = None
+    ) -> None:
+        """
+        Updates the digest in-place with a single value.
+
+        :param timestamp: Timestamp of the value.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        timestamps: Sequence[float],
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values, merging each
+        bucket once.
+
+        :param timestamps:
+            Timestamps in any order, of the same length as `x`.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def rollup(self, resolution: float, before: Optional[float] = None) -> None:
+        """
+        Merges the buckets within each interval of `resolution` time units
+        in-place into one bucket. Intervals that overlap a bucket extending
+        beyond them are left as they are.
+
+        :param resolution:
+            Length of the coarser intervals, a multiple of the resolution of
+            the digest.
+        :param optional before:
+            Only intervals ending at or before it are rolled up. Default is
+            `None` (all intervals).
+        """
+        ...
+
+    def ranges(self) -> List[Tuple[float, float]]:
+        """Returns the time ranges (start, end) of the buckets."""
+        ...
+
+    def snapshot(
+        self, start: Optional[float] = None, end: Optional[float] = None
+    ) -> TDigest:
+        """
+        Returns the values of the buckets overlapping the time range
+        [start, end) as a TDigest.
+
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: TDigest instance.
+        """
+        ...
+
+    def quantile(
+        self,
+        q: float,
+        start: Optional[float] = None,
+        end: Optional[float] = None,
+    ) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile) in
+        the time range [start, end).
+
+        :param q: Float between 0 and 1.
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(
+        self,
+        p: float,
+        start: Optional[float] = None,
+        end: Optional[float] = None,
+    ) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent in
+        the time range [start, end).
+
+        :param p: Float between 0 and 100.
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(
+        self, start: Optional[float] = None, end: Optional[float] = None
+    ) -> float:
+        """
+        Estimates the median in the time range [start, end).
+
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(
+        self,
+        x: float,
+        start: Optional[float] = None,
+        end: Optional[float] = None,
+    ) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) in the time
+        range [start, end) at the value `x`.
+
+        :param x: Value for which to compute the CDF.
+        :param optional start: Start of the range. Default is unbounded.
+        :param optional end: End of the range. Default is unbounded.
+        :return: Estimated probability.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def merge(self, other: "TimeSeriesDigest") -> "TimeSeriesDigest":
+        """
+        Merges this digest with another one bucket by bucket and returns a
+        new instance. Overlapping buckets of different rollups are merged
+        into one covering all of them.
+
+        :param other: TimeSeriesDigest with the same resolution.
+        :return: New TimeSeriesDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "TimeSeriesDigest") -> None:
+        """
+        Merges another digest into this one in-place, bucket by bucket.
+
+        :param other: TimeSeriesDigest with the same resolution.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the digest."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "TimeSeriesDigest":
+        """
+        Reconstructs a TimeSeriesDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: TimeSeriesDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with the parameters, and the first epochs
+        (interval numbers), lengths (in intervals) and `to_dict`
+        representations of the digests of the buckets.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(series_dict: Dict[str, Any]) -> "TimeSeriesDigest":
+        """
+        Reconstructs a TimeSeriesDigest from the output of `to_dict`.
+
+        :param series_dict: Dictionary.
+        :return: TimeSeriesDigest instance.
+        """
+        ...
+
+    def copy(self) -> "TimeSeriesDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "TimeSeriesDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "TimeSeriesDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same parameters and buckets."""
+        ...
+
+    def __add__(self, other: "TimeSeriesDigest") -> "TimeSeriesDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "TimeSeriesDigest") -> "TimeSeriesDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class DiscreteTDigest:
+    def __init__(
+        self, min_fraction: float = 0.01, max_centroids: int = 1000
+    ) -> None:
+        """
+        Initialize a new, empty digest that keeps the values with at least
+        `min_fraction` of the total weight exactly, and only sketches the
+        other values. Quantiles that fall on such a value (e.g. a timeout
+        recorded as a fixed number) are exact, and those next to it are not
+        biased towards it.
+
+        :param optional min_fraction:
+            Fraction of the total weight from which a value is kept
+            exactly, greater than 0 and at most 1. Default is 0.01.
+        :param optional max_centroids:
+            Number of centroids to maintain for the other values. Default
+            is 1000.
+        """
+        ...
+
+    @property
+    def min_fraction(self) -> float:
+        """The min_fraction parameter of the digest."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digest."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points added."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest in-place with a single value.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) at the value
+        `x`, counting half the weight of an exactly kept value at itself.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def mean(self) -> float:
+        """
+        Calculates the weighted mean of all values.
+
+        :return: Mean value.
+        """
+        ...
+
+    def mass(self) -> float:
+        """
+        Returns the total weight of all values.
+
+        :return: Total weight.
+        """
+        ...
+
+    def min(self) -> float:
+        """
+        Returns the minimum of all ingested values.
+
+        :return: Minimum value.
+        """
+        ...
+
+    def max(self) -> float:
+        """
+        Returns the maximum of all ingested values.
+
+        :return: Maximum value.
+        """
+        ...
+
+    def exact_values(self) -> Dict[float, float]:
+        """
+        Returns the values with at least `min_fraction` of the total weight
+        with their exact weights.
+
+        :return: Dict of the values and their weights.
+        """
+        ...
+
+    def snapshot(self) -> TDigest:
+        """
+        Returns all values as a TDigest, with the exactly kept values as
+        centroids.
+
+        :return: TDigest instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def merge(self, other: "DiscreteTDigest") -> "DiscreteTDigest":
+        """
+        Merges this digest with another one and returns a new instance.
+
+        :param other: DiscreteTDigest with the same min_fraction.
+        :return: New DiscreteTDigest instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "DiscreteTDigest") -> None:
+        """
+        Merges another digest into this one in-place.
+
+        :param other: DiscreteTDigest with the same min_fraction.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the digest."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "DiscreteTDigest":
+        """
+        Reconstructs a DiscreteTDigest from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: DiscreteTDigest instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """
+        Returns a dictionary with `min_fraction`, the number of values, the
+        exactly tracked values and their weights, and the `to_dict`
+        representation of the digest of the other values.
+        """
+        ...
+
+    @staticmethod
+    def from_dict(discrete_dict: Dict[str, Any]) -> "DiscreteTDigest":
+        """
+        Reconstructs a DiscreteTDigest from the output of `to_dict`.
+
+        :param discrete_dict: Dictionary.
+        :return: DiscreteTDigest instance.
+        """
+        ...
+
+    def copy(self) -> "DiscreteTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "DiscreteTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "DiscreteTDigest":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both have the same parameters and values."""
+        ...
+
+    def __add__(self, other: "DiscreteTDigest") -> "DiscreteTDigest":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "DiscreteTDigest") -> "DiscreteTDigest":
+        """Merges `other` into this instance in-place."""
+        ...
+
+
+class MappedTDigest:
+    def __init__(
+        self,
+        path: Union[str, "os.PathLike[str]"],
+        max_centroids: Optional[int] = None,
+        mode: str = "w",
+        sync: str = "update",
+    ) -> None:
+        """
+        Open a digest kept in a memory-mapped file, which survives
+        restarts of the writer and can be read by other processes while
+        it is updated.
+
+        :param path: Path of the file.
+        :param optional max_centroids:
+            Number of centroids to maintain (at least 1), for a new file.
+            Default is the one of an existing file, or 1000.
+        :param optional mode:
+            "w" (default) to create or update the file, or "r" to read it
+            (e.g. in another process), with every query seeing the latest
+            state written.
+        :param optional sync:
+            "update" (default) to write the state to the file after every
+            update, or "manual" to write it only on `flush` and `close`.
+        """
+        ...
+
+    @property
+    def path(self) -> "os.PathLike[str]":
+        """Path of the file (a pathlib.Path)."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digest."""
+        ...
+
+    @property
+    def mode(self) -> str:
+        """'w' or 'r'."""
+        ...
+
+    @property
+    def sync(self) -> str:
+        """The sync parameter of the digest."""
+        ...
+
+    @property
+    def closed(self) -> bool:
+        """True once the digest has been closed."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points added."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest in-place with a single value.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def flush(self) -> None:
+        """
+        Writes the state to the file (with sync="manual") and syncs the
+        file to disk.
+        """
+        ...
+
+    def close(self) -> None:
+        """
+        Writes the state to the file and releases it. Further calls have
+        no effect.
+        """
+        ...
+
+    def __enter__(self) -> "MappedTDigest":
+        """Returns the instance, to be closed at the end of the block."""
+        ...
+
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> None:
+        """Closes the instance."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) at the value
+        `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def mean(self) -> float:
+        """
+        Calculates the weighted mean of all values.
+
+        :return: Mean value.
+        """
+        ...
+
+    def mass(self) -> float:
+        """
+        Returns the total weight of all values.
+
+        :return: Total weight.
+        """
+        ...
+
+    def min(self) -> float:
+        """
+        Returns the minimum of all ingested values.
+
+        :return: Minimum value.
+        """
+        ...
+
+    def max(self) -> float:
+        """
+        Returns the maximum of all ingested values.
+
+        :return: Maximum value.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def snapshot(self) -> TDigest:
+        """
+        Returns a copy of the digest as a TDigest.
+
+        :return: TDigest instance.
+        """
+        ...
+
+
+class WalTDigest:
+    def __init__(
+        self,
+        path: Union[str, "os.PathLike[str]"],
+        max_centroids: Optional[int] = None,
+        compact_every: int = 100_000,
+        fsync: bool = False,
+    ) -> None:
+        """
+        Open a digest persisted in an append-only write-ahead log, which
+        is recovered if it exists, so that no update is lost in a crash.
+
+        :param path: Path of the log.
+        :param optional max_centroids:
+            Number of centroids to maintain, for a new log. Default is
+            the one of an existing log, or 1000.
+        :param optional compact_every:
+            Number of logged values after which the log is compacted into
+            a snapshot of the digest. Default is 100,000.
+        :param optional fsync:
+            If True, every update is synced to disk. Default is False,
+            where updates survive the process, but not the machine,
+            crashing.
+        """
+        ...
+
+    @staticmethod
+    def recover(path: Union[str, "os.PathLike[str]"]) -> TDigest:
+        """
+        Replays the log at `path`, without opening it for updates.
+
+        :param path: Path of the log.
+        :return: TDigest instance.
+        """
+        ...
+
+    @property
+    def path(self) -> "os.PathLike[str]":
+        """Path of the log (a pathlib.Path)."""
+        ...
+
+    @property
+    def max_centroids(self) -> int:
+        """The max_centroids parameter of the digest."""
+        ...
+
+    @property
+    def compact_every(self) -> int:
+        """The compact_every parameter of the digest."""
+        ...
+
+    @property
+    def fsync(self) -> bool:
+        """The fsync parameter of the digest."""
+        ...
+
+    @property
+    def closed(self) -> bool:
+        """True once the digest has been closed."""
+        ...
+
+    @property
+    def n_logged(self) -> int:
+        """Number of values logged since the last snapshot."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Number of data points added."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest in-place with a single value.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def compact(self) -> None:
+        """Compacts the log into a snapshot of the current state."""
+        ...
+
+    def flush(self) -> None:
+        """Syncs the log to disk (with fsync=False)."""
+        ...
+
+    def close(self) -> None:
+        """Closes the log. Further calls have no effect."""
+        ...
+
+    def __enter__(self) -> "WalTDigest":
+        """Returns the instance, to be closed at the end of the block."""
+        ...
+
+    def __exit__(self, exc_type: Any, exc_value: Any, traceback: Any) -> None:
+        """Closes the instance."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """
+        Estimates the median.
+
+        :return: Estimated median.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) at the value
+        `x`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Estimated probability.
+        """
+        ...
+
+    def mean(self) -> float:
+        """
+        Calculates the weighted mean of all values.
+
+        :return: Mean value.
+        """
+        ...
+
+    def mass(self) -> float:
+        """
+        Returns the total weight of all values.
+
+        :return: Total weight.
+        """
+        ...
+
+    def min(self) -> float:
+        """
+        Returns the minimum of all ingested values.
+
+        :return: Minimum value.
+        """
+        ...
+
+    def max(self) -> float:
+        """
+        Returns the maximum of all ingested values.
+
+        :return: Maximum value.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the digest holds no values."""
+        ...
+
+    def snapshot(self) -> TDigest:
+        """
+        Returns a copy of the digest as a TDigest.
+
+        :return: TDigest instance.
+        """
+        ...
+
+
+class DDSketch:
+    def __init__(
+        self, relative_accuracy: float = 0.01, max_bins: int = 2048
+    ) -> None:
+        """
+        Initialize a new, empty DDSketch, whose quantile estimates are
+        within `relative_accuracy` of the true values.
+
+        :param optional relative_accuracy:
+            Relative accuracy, between 0 and 1 (exclusive). Default is 0.01.
+        :param optional max_bins:
+            Maximum number of buckets for positive and for negative values.
+            Beyond that, the buckets closest to zero are collapsed. Default
+            is 2048.
+        """
+        ...
+
+    @property
+    def relative_accuracy(self) -> float:
+        """The relative_accuracy parameter."""
+        ...
+
+    @property
+    def max_bins(self) -> int:
+        """The max_bins parameter."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    @property
+    def n_bins(self) -> int:
+        """Number of buckets in use."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def merge(self, other: "DDSketch") -> "DDSketch":
+        """
+        Merges this sketch with another one and returns a new instance.
+
+        :param other: DDSketch with the same relative_accuracy.
+        :return: New DDSketch instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "DDSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: DDSketch with the same relative_accuracy.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """Estimates the median."""
+        ...
+
+    def sum(self) -> float:
+        """Returns the (weighted) sum of the data."""
+        ...
+
+    def mean(self) -> float:
+        """Returns the (weighted) mean of the data."""
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "DDSketch":
+        """
+        Reconstructs a DDSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: DDSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "DDSketch":
+        """
+        Reconstructs a DDSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: DDSketch instance.
+        """
+        ...
+
+    def copy(self) -> "DDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "DDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "DDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical contents."""
+        ...
+
+    def __add__(self, other: "DDSketch") -> "DDSketch":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "DDSketch") -> "DDSketch":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class UDDSketch:
+    def __init__(
+        self, relative_accuracy: float = 0.01, max_bins: int = 1024
+    ) -> None:
+        """
+        Initialize a new, empty UDDSketch, whose quantile estimates are
+        within `relative_accuracy` of the true values for as long as the
+        data fits into `max_bins` buckets.
+
+        :param optional relative_accuracy:
+            Initial relative accuracy, between 0 and 1 (exclusive). Default
+            is 0.01.
+        :param optional max_bins:
+            Maximum total number of buckets. Beyond that, all buckets are
+            collapsed pairwise, which squares the bucket growth factor and
+            leaves the guarantee uniform across all quantiles. Default is
+            1024.
+        """
+        ...
+
+    @staticmethod
+    def from_values(
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+        relative_accuracy: float = 0.01,
+        max_bins: int = 1024,
+    ) -> "UDDSketch":
+        """
+        Creates a new UDDSketch from a sequence of numeric values.
+
+        :param x: Sequence of numeric values.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        :param optional relative_accuracy: See `__init__`.
+        :param optional max_bins: See `__init__`.
+        :return: UDDSketch instance.
+        """
+        ...
+
+    @property
+    def relative_accuracy(self) -> float:
+        """The relative_accuracy parameter."""
+        ...
+
+    @property
+    def current_relative_accuracy(self) -> float:
+        """
+        The relative accuracy guaranteed after all collapses so far. Equal
+        to `relative_accuracy` until the first collapse.
+        """
+        ...
+
+    @property
+    def max_bins(self) -> int:
+        """The max_bins parameter."""
+        ...
+
+    @property
+    def n_collapses(self) -> int:
+        """Number of times the buckets have been collapsed."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    @property
+    def n_bins(self) -> int:
+        """Number of buckets in use."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def merge(self, other: "UDDSketch") -> "UDDSketch":
+        """
+        Merges this sketch with another one and returns a new instance.
+
+        If the sketches have been collapsed a different number of times,
+        the result has the coarser accuracy of the two (or coarser still,
+        if it would otherwise exceed `max_bins`).
+
+        :param other: UDDSketch with the same relative_accuracy.
+        :return: New UDDSketch instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "UDDSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: UDDSketch with the same relative_accuracy.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """Estimates the median."""
+        ...
+
+    def iqr(self) -> float:
+        """
+        Estimates the interquartile range (IQR).
+
+        Equivalent to `quantile(0.75) - quantile(0.25)`.
+
+        :return: Estimated IQR.
+        """
+        ...
+
+    def cdf(self, x: float) -> float:
+        """
+        Estimates the cumulative distribution function (CDF) at the value `x`.
+
+        Inverse function of `quantile(q)`.
+
+        :param x: Value for which to compute the CDF.
+        :return: Float between 0 and 1 representing cumulative probability.
+        """
+        ...
+
+    def cdf_vec(self, x: Sequence[float]) -> List[float]:
+        """
+        Estimates the cumulative distribution function (CDF) at the values `x`.
+
+        Inverse function of `quantile_vec(q)`.
+
+        :param x: Sequence of values for which to compute the CDF.
+        :return: List of CDF(x) floats between 0 and 1.
+        """
+        ...
+
+    def mass(self) -> float:
+        """Returns the total weight of the data."""
+        ...
+
+    def sum(self) -> float:
+        """Returns the (weighted) sum of the data."""
+        ...
+
+    def mean(self) -> float:
+        """Returns the (weighted) mean of the data."""
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "UDDSketch":
+        """
+        Reconstructs a UDDSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: UDDSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "UDDSketch":
+        """
+        Reconstructs a UDDSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: UDDSketch instance.
+        """
+        ...
+
+    def copy(self) -> "UDDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "UDDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "UDDSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical contents."""
+        ...
+
+    def __add__(self, other: "UDDSketch") -> "UDDSketch":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "UDDSketch") -> "UDDSketch":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class KLLSketch:
+    def __init__(self, k: int = 200, seed: Optional[int] = None) -> None:
+        """
+        Initialize a new, empty KLL sketch, whose quantile estimates have a
+        guaranteed rank error, independently of the data distribution.
+
+        :param optional k:
+            Accuracy parameter between 8 and 65535. Default is 200, which
+            gives a normalized rank error of about 1.3%.
+        :param optional seed:
+            Seed for the random compactions. If `None` (default), a random
+            seed is used.
+        """
+        ...
+
+    @property
+    def k(self) -> int:
+        """The k parameter."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    @property
+    def n_retained(self) -> int:
+        """Number of values kept in the sketch."""
+        ...
+
+    @property
+    def rank_error(self) -> float:
+        """
+        Approximate normalized rank error of the quantile and rank estimates
+        (99% confidence).
+        """
+        ...
+
+    def update(self, x: float) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        """
+        ...
+
+    def batch_update(self, x: Sequence[float]) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        """
+        ...
+
+    def merge(self, other: "KLLSketch") -> "KLLSketch":
+        """
+        Merges this sketch with another one and returns a new instance.
+
+        :param other: KLLSketch instance.
+        :return: New KLLSketch instance with the smaller k of the two.
+        """
+        ...
+
+    def merge_inplace(self, other: "KLLSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: KLLSketch instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """Estimates the median."""
+        ...
+
+    def rank(self, x: float) -> float:
+        """
+        Estimates the fraction of values less than or equal to `x`.
+
+        :param x: Value.
+        :return: Estimated rank between 0 and 1.
+        """
+        ...
+
+    def rank_vec(self, x: Sequence[float]) -> List[float]:
+        """
+        Estimates the ranks of several values.
+
+        :param x: Sequence of values.
+        :return: List of estimated ranks.
+        """
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "KLLSketch":
+        """
+        Reconstructs a KLLSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: KLLSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "KLLSketch":
+        """
+        Reconstructs a KLLSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: KLLSketch instance.
+        """
+        ...
+
+    def copy(self) -> "KLLSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "KLLSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "KLLSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical contents."""
+        ...
+
+    def __add__(self, other: "KLLSketch") -> "KLLSketch":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "KLLSketch") -> "KLLSketch":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class GKSketch:
+    def __init__(self, epsilon: float = 0.01) -> None:
+        """
+        Initialize a new, empty Greenwald-Khanna sketch, whose quantile
+        estimates are deterministically within `epsilon * n_values` ranks of
+        the true ones.
+
+        :param optional epsilon:
+            Rank error, between 0 and 1 (exclusive). Default is 0.01.
+        """
+        ...
+
+    @property
+    def epsilon(self) -> float:
+        """The epsilon parameter."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    @property
+    def n_samples(self) -> int:
+        """Number of samples kept in the summary."""
+        ...
+
+    def update(self, x: float) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        """
+        ...
+
+    def batch_update(self, x: Sequence[float]) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        """
+        ...
+
+    def merge(self, other: "GKSketch") -> "GKSketch":
+        """
+        Merges this sketch with another one and returns a new instance.
+
+        :param other: GKSketch instance.
+        :return: New GKSketch instance with the larger epsilon of the two.
+        """
+        ...
+
+    def merge_inplace(self, other: "GKSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: GKSketch instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """Estimates the median."""
+        ...
+
+    def rank(self, x: float) -> float:
+        """
+        Estimates the fraction of values less than or equal to `x`.
+
+        :param x: Value.
+        :return: Estimated rank between 0 and 1.
+        """
+        ...
+
+    def rank_vec(self, x: Sequence[float]) -> List[float]:
+        """
+        Estimates the ranks of several values.
+
+        :param x: Sequence of values.
+        :return: List of estimated ranks.
+        """
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "GKSketch":
+        """
+        Reconstructs a GKSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: GKSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "GKSketch":
+        """
+        Reconstructs a GKSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: GKSketch instance.
+        """
+        ...
+
+    def copy(self) -> "GKSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "GKSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "GKSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical contents."""
+        ...
+
+    def __add__(self, other: "GKSketch") -> "GKSketch":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "GKSketch") -> "GKSketch":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class REQSketch:
+    def __init__(
+        self,
+        k: int = 12,
+        high_rank_accuracy: bool = True,
+        seed: Optional[int] = None,
+    ) -> None:
+        """
+        Initialize a new, empty REQ sketch, whose rank error is relative:
+        proportional to 1 - rank, so that extreme high quantiles are very
+        accurate.
+
+        :param optional k:
+            Even accuracy parameter between 4 and 1024. Default is 12.
+        :param optional high_rank_accuracy:
+            If True (default), the high ranks are accurate. If False, the
+            low ranks are.
+        :param optional seed:
+            Seed for the random compactions. If `None` (default), a random
+            seed is used.
+        """
+        ...
+
+    @property
+    def k(self) -> int:
+        """The k parameter."""
+        ...
+
+    @property
+    def high_rank_accuracy(self) -> bool:
+        """The high_rank_accuracy parameter."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    @property
+    def n_retained(self) -> int:
+        """Number of values kept in the sketch."""
+        ...
+
+    def rank_error(self, rank: float) -> float:
+        """
+        Approximate standard error of rank estimates around `rank`. It is 0
+        for the ranks that are still kept exactly.
+
+        :param rank: Float between 0 and 1.
+        :return: Standard error of the normalized rank.
+        """
+        ...
+
+    def update(self, x: float) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        """
+        ...
+
+    def batch_update(self, x: Sequence[float]) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        """
+        ...
+
+    def merge(self, other: "REQSketch") -> "REQSketch":
+        """
+        Merges this sketch with another one and returns a new instance.
+
+        :param other: REQSketch with the same high_rank_accuracy.
+        :return: New REQSketch instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "REQSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: REQSketch with the same high_rank_accuracy.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def quantile(self, q: float) -> float:
+        """
+        Estimates the value at a given cumulative probability (quantile).
+
+        :param q: Float between 0 and 1.
+        :return: Estimated value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[float]:
+        """
+        Estimates the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of estimated values.
+        """
+        ...
+
+    def percentile(self, p: float) -> float:
+        """
+        Estimates the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Estimated value.
+        """
+        ...
+
+    def median(self) -> float:
+        """Estimates the median."""
+        ...
+
+    def rank(self, x: float) -> float:
+        """
+        Estimates the fraction of values less than or equal to `x`.
+
+        :param x: Value.
+        :return: Estimated rank between 0 and 1.
+        """
+        ...
+
+    def rank_vec(self, x: Sequence[float]) -> List[float]:
+        """
+        Estimates the ranks of several values.
+
+        :param x: Sequence of values.
+        :return: List of estimated ranks.
+        """
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "REQSketch":
+        """
+        Reconstructs a REQSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: REQSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "REQSketch":
+        """
+        Reconstructs a REQSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: REQSketch instance.
+        """
+        ...
+
+    def copy(self) -> "REQSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "REQSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "REQSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical contents."""
+        ...
+
+    def __add__(self, other: "REQSketch") -> "REQSketch":
+        """Returns `self.merge(other)`."""
+        ...
+
+    def __iadd__(self, other: "REQSketch") -> "REQSketch":
+        """Merges `other` into this instance in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class HdrHistogram:
+    def __init__(self, lowest: int, highest: int, sig_figs: int) -> None:
+        """
+        Initialize a new, empty HDR histogram of integer values from
+        `lowest` to `highest`, resolved to `sig_figs` significant decimal
+        digits. Its layout, percentiles and V2 encoding are those of the
+        reference HdrHistogram implementation.
+
+        :param lowest: Lowest discernible value, at least 1.
+        :param highest: Highest trackable value, at least 2 * lowest.
+        :param sig_figs: Number of significant decimal digits, between 0
+            and 5.
+        """
+        ...
+
+    @property
+    def lowest(self) -> int:
+        """Lowest discernible value."""
+        ...
+
+    @property
+    def highest(self) -> int:
+        """Highest trackable value."""
+        ...
+
+    @property
+    def sig_figs(self) -> int:
+        """Number of significant decimal digits."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points recorded."""
+        ...
+
+    def record(self, value: int, count: int = 1) -> None:
+        """
+        Records a value in-place.
+
+        :param value: Non-negative integer within the trackable range.
+        :param count: Number of times to record the value (default: 1).
+        """
+        ...
+
+    def batch_record(self, values: Sequence[int]) -> None:
+        """
+        Records a sequence of values in-place. Nothing is recorded if any
+        value is out of range.
+
+        :param values: Sequence of non-negative integers.
+        """
+        ...
+
+    def merge(self, other: "HdrHistogram") -> "HdrHistogram":
+        """
+        Merges this histogram with another one and returns a new instance
+        with the parameters of `self`.
+
+        :param other: HdrHistogram instance whose values fit into `self`.
+        :return: New HdrHistogram instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "HdrHistogram") -> None:
+        """
+        Merges another histogram into this one in-place.
+
+        :param other: HdrHistogram instance whose values fit into `self`.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been recorded."""
+        ...
+
+    def quantile(self, q: float) -> int:
+        """
+        Returns the value at a given cumulative probability (quantile),
+        i.e. the highest value equivalent to the recorded one at that rank.
+
+        :param q: Float between 0 and 1.
+        :return: Value.
+        """
+        ...
+
+    def quantile_vec(self, q: Sequence[float]) -> List[int]:
+        """
+        Returns the values at several cumulative probabilities.
+
+        :param q: Sequence of floats between 0 and 1.
+        :return: List of values.
+        """
+        ...
+
+    def percentile(self, p: float) -> int:
+        """
+        Returns the value at a given cumulative probability in percent.
+
+        :param p: Float between 0 and 100.
+        :return: Value.
+        """
+        ...
+
+    def median(self) -> int:
+        """Returns the median."""
+        ...
+
+    def rank(self, x: int) -> float:
+        """
+        Returns the fraction of values less than or equal to `x`, counting
+        all values equivalent to `x`.
+
+        :param x: Value.
+        :return: Rank between 0 and 1.
+        """
+        ...
+
+    def min(self) -> int:
+        """Returns the lowest recorded value, rounded down."""
+        ...
+
+    def max(self) -> int:
+        """Returns the highest recorded value, rounded up."""
+        ...
+
+    def mean(self) -> float:
+        """Returns the mean of the recorded values."""
+        ...
+
+    def std(self) -> float:
+        """Returns the standard deviation of the recorded values."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns the compressed V2 encoding of the histogram."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "HdrHistogram":
+        """
+        Reconstructs an HdrHistogram from its compressed or uncompressed V2
+        encoding, e.g. the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: HdrHistogram instance.
+        """
+        ...
+
+    def encode(self) -> str:
+        """
+        Returns the Base64 compressed V2 encoding of the histogram, as used
+        in HdrHistogram interval logs.
+        """
+        ...
+
+    @staticmethod
+    def decode(encoded: Union[str, bytes]) -> "HdrHistogram":
+        """
+        Reconstructs an HdrHistogram from its Base64 compressed V2
+        encoding, e.g. the output of `encode`.
+
+        :param encoded: Base64 string.
+        :return: HdrHistogram instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the histogram."""
+        ...
+
+    @staticmethod
+    def from_dict(hist_dict: Dict[str, Any]) -> "HdrHistogram":
+        """
+        Reconstructs an HdrHistogram from the output of `to_dict`.
+
+        :param hist_dict: Dictionary.
+        :return: HdrHistogram instance.
+        """
+        ...
+
+    def copy(self) -> "HdrHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __copy__(self) -> "HdrHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "HdrHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling via `from_bytes`."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both histograms have identical states."""
+        ...
+
+    def __add__(self, other: "HdrHistogram") -> "HdrHistogram":
+        """Merges two histograms into a new instance."""
+        ...
+
+    def __iadd__(self, other: "HdrHistogram") -> "HdrHistogram":
+        """Merges another histogram into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class P2Quantile:
+    def __init__(self, q: float) -> None:
+        """
+        Initialize a new, empty P² estimator that tracks a single quantile
+        with five markers, i.e. in constant memory. It cannot be merged.
+
+        :param q: Quantile to track, between 0 and 1 (exclusive).
+        """
+        ...
+
+    @property
+    def q(self) -> float:
+        """The tracked quantile."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    def update(self, x: float) -> None:
+        """
+        Updates the estimator in-place with a single value.
+
+        :param x: Value to add.
+        """
+        ...
+
+    def batch_update(self, x: Sequence[float]) -> None:
+        """
+        Updates the estimator in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def estimate(self) -> float:
+        """
+        Returns the current estimate of the quantile `q`. With fewer than
+        five values, this is the value of the nearest rank.
+        """
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the estimator."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "P2Quantile":
+        """
+        Reconstructs a P2Quantile from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: P2Quantile instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the estimator."""
+        ...
+
+    @staticmethod
+    def from_dict(estimator_dict: Dict[str, Any]) -> "P2Quantile":
+        """
+        Reconstructs a P2Quantile from the output of `to_dict`.
+
+        :param estimator_dict: Dictionary.
+        :return: P2Quantile instance.
+        """
+        ...
+
+    def copy(self) -> "P2Quantile":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "P2Quantile":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "P2Quantile":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both estimators have identical states."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class MomentsSketch:
+    def __init__(self) -> None:
+        """
+        Initialize a new, empty sketch of the mean, variance, skewness and
+        kurtosis. The central moments are exact up to floating-point error
+        and merge without loss.
+        """
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    def update(self, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add.
+        :param w: Optional positive weight of the value (default 1).
+        """
+        ...
+
+    def batch_update(
+        self, x: Sequence[float], w: Optional[Sequence[float]] = None
+    ) -> None:
+        """
+        Updates the sketch in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        :param w: Optional sequence of positive weights, one per value.
+        """
+        ...
+
+    def merge(self, other: "MomentsSketch") -> "MomentsSketch":
+        """
+        Merges this sketch with another and returns a new sketch.
+
+        :param other: Other MomentsSketch instance.
+        :return: New MomentsSketch instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "MomentsSketch") -> None:
+        """
+        Merges another sketch into this one in-place.
+
+        :param other: Other MomentsSketch instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def mass(self) -> float:
+        """Returns the total weight of the ingested values."""
+        ...
+
+    def sum(self) -> float:
+        """Returns the weighted sum of the ingested values."""
+        ...
+
+    def mean(self) -> float:
+        """Returns the weighted mean."""
+        ...
+
+    def var(self) -> float:
+        """Returns the (population) variance."""
+        ...
+
+    def std(self) -> float:
+        """Returns the (population) standard deviation."""
+        ...
+
+    def skew(self) -> float:
+        """
+        Returns the (population) skewness, or NaN if all values are equal.
+        """
+        ...
+
+    def kurtosis(self, fisher: bool = True) -> float:
+        """
+        Returns the (population) kurtosis, or NaN if all values are equal.
+
+        :param fisher: If True, returns the excess kurtosis (normal = 0.0),
+            otherwise Pearson's definition (normal = 3.0).
+        """
+        ...
+
+    def min(self) -> float:
+        """Returns the lowest ingested value."""
+        ...
+
+    def max(self) -> float:
+        """Returns the highest ingested value."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "MomentsSketch":
+        """
+        Reconstructs a MomentsSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: MomentsSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "MomentsSketch":
+        """
+        Reconstructs a MomentsSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: MomentsSketch instance.
+        """
+        ...
+
+    def copy(self) -> "MomentsSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "MomentsSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "MomentsSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical states."""
+        ...
+
+    def __add__(self, other: "MomentsSketch") -> "MomentsSketch":
+        """Returns the merge of both sketches."""
+        ...
+
+    def __iadd__(self, other: "MomentsSketch") -> "MomentsSketch":
+        """Merges another sketch into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class HyperLogLog:
+    def __init__(self, precision: int = 14) -> None:
+        """
+        Initialize a new, empty HyperLogLog sketch that estimates the number
+        of distinct values. Values are hashed deterministically, so sketches
+        can be merged across processes and restored from their serialized
+        form.
+
+        :param precision: Log2 of the number of registers, between 4 and 18.
+            The relative standard error is 1.04 / sqrt(2 ** precision), e.g.
+            0.81% for the default of 14 (16 KiB).
+        """
+        ...
+
+    @property
+    def precision(self) -> int:
+        """Log2 of the number of registers."""
+        ...
+
+    @property
+    def relative_error(self) -> float:
+        """Relative standard error of the estimate."""
+        ...
+
+    def update(self, x: Hashable) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: Value to add: an int, float, str, bytes, bytearray, None or
+            a tuple thereof. Equal numbers (e.g. 1, 1.0 and True) count as
+            the same value.
+        """
+        ...
+
+    def batch_update(self, x: Iterable[Hashable]) -> None:
+        """
+        Updates the sketch in-place with several values.
+
+        :param x: Iterable of values, or a numeric array (e.g. a NumPy
+            array), which is read without conversion to Python objects.
+        """
+        ...
+
+    def merge(self, other: "HyperLogLog") -> "HyperLogLog":
+        """
+        Merges this sketch with another of the same precision and returns a
+        new sketch of the union.
+
+        :param other: Other HyperLogLog instance.
+        :return: New HyperLogLog instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "HyperLogLog") -> None:
+        """
+        Merges another sketch of the same precision into this one in-place.
+
+        :param other: Other HyperLogLog instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def estimate(self) -> float:
+        """Returns the estimated number of distinct values."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "HyperLogLog":
+        """
+        Reconstructs a HyperLogLog from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: HyperLogLog instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "HyperLogLog":
+        """
+        Reconstructs a HyperLogLog from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: HyperLogLog instance.
+        """
+        ...
+
+    def copy(self) -> "HyperLogLog":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "HyperLogLog":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "HyperLogLog":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the estimate, rounded to an integer."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical states."""
+        ...
+
+    def __add__(self, other: "HyperLogLog") -> "HyperLogLog":
+        """Returns the merge of both sketches."""
+        ...
+
+    def __iadd__(self, other: "HyperLogLog") -> "HyperLogLog":
+        """Merges another sketch into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class CountMinSketch:
+    def __init__(self, epsilon: float = 0.001, delta: float = 0.01) -> None:
+        """
+        Initialize a new, empty Count-Min sketch that estimates how often
+        items occur. Estimates never fall below the true count and exceed it
+        by more than `epsilon` times the total count only with probability
+        `delta`. Items are hashed deterministically, like in `HyperLogLog`.
+
+        :param epsilon: Error bound relative to the total count, between 0
+            and 1 (exclusive). Determines the width, ceil(e / epsilon).
+        :param delta: Failure probability, between 0 and 1 (exclusive).
+            Determines the depth, ceil(ln(1 / delta)).
+        """
+        ...
+
+    @property
+    def width(self) -> int:
+        """Number of counters per row."""
+        ...
+
+    @property
+    def depth(self) -> int:
+        """Number of rows."""
+        ...
+
+    @property
+    def epsilon(self) -> float:
+        """Error bound relative to the total count, e / width."""
+        ...
+
+    @property
+    def delta(self) -> float:
+        """Failure probability of the error bound, exp(-depth)."""
+        ...
+
+    @property
+    def total(self) -> int:
+        """Total count of all items."""
+        ...
+
+    def update(self, item: Hashable, count: int = 1) -> None:
+        """
+        Adds occurrences of an item to the sketch in-place.
+
+        :param item: An int, float, str, bytes, bytearray, None or a tuple
+            thereof.
+        :param count: Positive number of occurrences.
+        """
+        ...
+
+    def batch_update(self, x: Iterable[Hashable]) -> None:
+        """
+        Adds one occurrence of each item to the sketch in-place.
+
+        :param x: Iterable of items, or a numeric array (e.g. a NumPy
+            array).
+        """
+        ...
+
+    def estimate(self, item: Hashable) -> int:
+        """
+        Returns the estimated count of an item, an upper bound of the true
+        count.
+
+        :param item: Item to look up.
+        """
+        ...
+
+    def estimate_vec(self, x: Iterable[Hashable]) -> List[int]:
+        """
+        Returns the estimated counts of several items.
+
+        :param x: Iterable of items, or a numeric array.
+        """
+        ...
+
+    def merge(self, other: "CountMinSketch") -> "CountMinSketch":
+        """
+        Merges this sketch with another of the same dimensions and returns a
+        new sketch.
+
+        :param other: Other CountMinSketch instance.
+        :return: New CountMinSketch instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "CountMinSketch") -> None:
+        """
+        Merges another sketch of the same dimensions into this one in-place.
+
+        :param other: Other CountMinSketch instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "CountMinSketch":
+        """
+        Reconstructs a CountMinSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: CountMinSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "CountMinSketch":
+        """
+        Reconstructs a CountMinSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: CountMinSketch instance.
+        """
+        ...
+
+    def copy(self) -> "CountMinSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "CountMinSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "CountMinSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical states."""
+        ...
+
+    def __add__(self, other: "CountMinSketch") -> "CountMinSketch":
+        """Returns the merge of both sketches."""
+        ...
+
+    def __iadd__(self, other: "CountMinSketch") -> "CountMinSketch":
+        """Merges another sketch into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class TopK:
+    def __init__(self, k: int) -> None:
+        """
+        Initialize a new, empty SpaceSaving sketch that tracks the most
+        frequent items (heavy hitters) with at most `k` counters. Each count
+        overestimates the true frequency by at most its error, which is at
+        most total / k, and every item occurring more than total / k times
+        is tracked. Items are returned in canonical form: equal numbers are
+        one item (e.g. True, 1 and 1.0 are returned as 1), and bytearrays
+        are returned as bytes.
+
+        :param k: Maximum number of tracked items.
+        """
+        ...
+
+    @property
+    def k(self) -> int:
+        """Maximum number of tracked items."""
+        ...
+
+    @property
+    def total(self) -> int:
+        """Total count of all items."""
+        ...
+
+    @property
+    def max_error(self) -> int:
+        """
+        Largest possible overestimation of any count, which is also the
+        upper bound of the count of any untracked item.
+        """
+        ...
+
+    def update(self, item: Hashable, count: int = 1) -> None:
+        """
+        Adds occurrences of an item to the sketch in-place.
+
+        :param item: An int, float, str, bytes, bytearray, None or a tuple
+            thereof.
+        :param count: Positive number of occurrences.
+        """
+        ...
+
+    def batch_update(self, x: Iterable[Hashable]) -> None:
+        """
+        Adds one occurrence of each item to the sketch in-place.
+
+        :param x: Iterable of items, or a numeric array (e.g. a NumPy
+            array).
+        """
+        ...
+
+    def top(self, n: Optional[int] = None) -> List[Tuple[Any, int, int]]:
+        """
+        Returns the tracked items with the highest counts.
+
+        :param n: Maximum number of items to return (default: all).
+        :return: List of (item, count, error) tuples by descending count.
+            The true frequency is between count - error and count.
+        """
+        ...
+
+    def heavy_hitters(
+        self, phi: float, guaranteed: bool = False
+    ) -> List[Tuple[Any, int, int]]:
+        """
+        Returns the items that may occur more than phi * total times. For
+        phi >= 1 / k, no such item is missed.
+
+        :param phi: Fraction of the total count, greater than 0 and at most
+            1.
+        :param guaranteed: If True, only returns the items guaranteed to
+            occur more than phi * total times (count - error > phi * total).
+        :return: List of (item, count, error) tuples by descending count.
+        """
+        ...
+
+    def estimate(self, item: Hashable) -> int:
+        """
+        Returns an upper bound of the count of an item; for an untracked
+        item, this is `max_error`.
+
+        :param item: Item to look up.
+        """
+        ...
+
+    def merge(self, other: "TopK") -> "TopK":
+        """
+        Merges this sketch with another and returns a new sketch with the
+        smaller k of the two.
+
+        :param other: Other TopK instance.
+        :return: New TopK instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "TopK") -> None:
+        """
+        Merges another sketch into this one in-place, keeping the smaller k
+        of the two.
+
+        :param other: Other TopK instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "TopK":
+        """
+        Reconstructs a TopK from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: TopK instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "TopK":
+        """
+        Reconstructs a TopK from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: TopK instance.
+        """
+        ...
+
+    def copy(self) -> "TopK":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "TopK":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "TopK":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of tracked items."""
+        ...
+
+    def __contains__(self, item: object) -> bool:
+        """Returns True if the item is tracked."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical states."""
+        ...
+
+    def __add__(self, other: "TopK") -> "TopK":
+        """Returns the merge of both sketches."""
+        ...
+
+    def __iadd__(self, other: "TopK") -> "TopK":
+        """Merges another sketch into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class BloomFilter:
+    def __init__(self, capacity: int, fp_rate: float = 0.01) -> None:
+        """
+        Initialize a new, empty Bloom filter for approximate set membership.
+        There are no false negatives; once `capacity` items have been added,
+        an item that was not added is reported as present with probability
+        `fp_rate`. Items are hashed deterministically, like in
+        `HyperLogLog`.
+
+        :param capacity: Number of items the filter is sized for.
+        :param fp_rate: False positive rate at capacity, between 0 and 1
+            (exclusive).
+        """
+        ...
+
+    @property
+    def capacity(self) -> int:
+        """Number of items the filter is sized for."""
+        ...
+
+    @property
+    def fp_rate(self) -> float:
+        """False positive rate at capacity."""
+        ...
+
+    @property
+    def n_bits(self) -> int:
+        """Size of the bit array."""
+        ...
+
+    @property
+    def n_hashes(self) -> int:
+        """Number of bits set per item."""
+        ...
+
+    def add(self, item: Hashable) -> None:
+        """
+        Adds an item to the filter in-place.
+
+        :param item: An int, float, str, bytes, bytearray, None or a tuple
+            thereof.
+        """
+        ...
+
+    def batch_add(self, x: Iterable[Hashable]) -> None:
+        """
+        Adds several items to the filter in-place.
+
+        :param x: Iterable of items, or a numeric array (e.g. a NumPy
+            array).
+        """
+        ...
+
+    def union(self, other: "BloomFilter") -> "BloomFilter":
+        """
+        Returns the union of this filter and another of the same size.
+
+        :param other: Other BloomFilter instance.
+        :return: New BloomFilter instance.
+        """
+        ...
+
+    def union_inplace(self, other: "BloomFilter") -> None:
+        """
+        Unites another filter of the same size with this one in-place.
+
+        :param other: Other BloomFilter instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no item has been added."""
+        ...
+
+    def current_fp_rate(self) -> float:
+        """Returns the false positive rate given the bits currently set."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the filter."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "BloomFilter":
+        """
+        Reconstructs a BloomFilter from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: BloomFilter instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the filter."""
+        ...
+
+    @staticmethod
+    def from_dict(filter_dict: Dict[str, Any]) -> "BloomFilter":
+        """
+        Reconstructs a BloomFilter from the output of `to_dict`.
+
+        :param filter_dict: Dictionary.
+        :return: BloomFilter instance.
+        """
+        ...
+
+    def copy(self) -> "BloomFilter":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "BloomFilter":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "BloomFilter":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __contains__(self, item: object) -> bool:
+        """Returns True if the item may have been added."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both filters have identical states."""
+        ...
+
+    def __or__(self, other: "BloomFilter") -> "BloomFilter":
+        """Returns the union of both filters."""
+        ...
+
+    def __ior__(self, other: "BloomFilter") -> "BloomFilter":
+        """Unites another filter with this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class ThetaSketch:
+    def __init__(self, lg_k: int = 12) -> None:
+        """
+        Initialize a new, empty theta sketch that estimates the number of
+        distinct values, and of the unions, intersections and differences
+        of such sets. It keeps the smallest 2 ** lg_k value hashes, which
+        gives a relative standard error of about 1 / sqrt(2 ** lg_k) for
+        a single sketch or union (1.6% for the default of 12). Values are
+        hashed deterministically, like in `HyperLogLog`.
+
+        :param lg_k: Log2 of the maximum number of retained hashes, between
+            4 and 26.
+        """
+        ...
+
+    @property
+    def lg_k(self) -> int:
+        """Log2 of the maximum number of retained hashes."""
+        ...
+
+    @property
+    def theta(self) -> float:
+        """Sampling threshold as a fraction of the hash range (1.0 if exact)."""
+        ...
+
+    @property
+    def n_retained(self) -> int:
+        """Number of retained hashes."""
+        ...
+
+    def update(self, x: Hashable) -> None:
+        """
+        Updates the sketch in-place with a single value.
+
+        :param x: An int, float, str, bytes, bytearray, None or a tuple
+            thereof.
+        """
+        ...
+
+    def batch_update(self, x: Iterable[Hashable]) -> None:
+        """
+        Updates the sketch in-place with several values.
+
+        :param x: Iterable of values, or a numeric array (e.g. a NumPy
+            array).
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def is_estimation_mode(self) -> bool:
+        """
+        Returns True if hashes have been dropped, i.e. if the estimate is no
+        longer exact.
+        """
+        ...
+
+    def estimate(self) -> float:
+        """Returns the estimated number of distinct values."""
+        ...
+
+    def lower_bound(self, num_std_devs: int = 2) -> float:
+        """
+        Returns the approximate lower bound of the distinct count.
+
+        :param num_std_devs: Number of standard deviations: 1, 2 or 3.
+        """
+        ...
+
+    def upper_bound(self, num_std_devs: int = 2) -> float:
+        """
+        Returns the approximate upper bound of the distinct count.
+
+        :param num_std_devs: Number of standard deviations: 1, 2 or 3.
+        """
+        ...
+
+    def union(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns a sketch of the union of both sets."""
+        ...
+
+    def union_inplace(self, other: "ThetaSketch") -> None:
+        """Unites another sketch with this one in-place."""
+        ...
+
+    def intersection(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns a sketch of the intersection of both sets."""
+        ...
+
+    def difference(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns a sketch of the values in this set but not in `other`."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "ThetaSketch":
+        """
+        Reconstructs a ThetaSketch from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: ThetaSketch instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sketch."""
+        ...
+
+    @staticmethod
+    def from_dict(sketch_dict: Dict[str, Any]) -> "ThetaSketch":
+        """
+        Reconstructs a ThetaSketch from the output of `to_dict`.
+
+        :param sketch_dict: Dictionary.
+        :return: ThetaSketch instance.
+        """
+        ...
+
+    def copy(self) -> "ThetaSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __copy__(self) -> "ThetaSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "ThetaSketch":
+        """Returns a copy of the instance."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling of the instance."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both sketches have identical states."""
+        ...
+
+    def __or__(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns the union of both sketches."""
+        ...
+
+    def __ior__(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Unites another sketch with this one in-place."""
+        ...
+
+    def __and__(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns the intersection of both sketches."""
+        ...
+
+    def __sub__(self, other: "ThetaSketch") -> "ThetaSketch":
+        """Returns the difference of both sketches."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class ReservoirSampler:
+    def __init__(self, k: int, seed: Optional[int] = None) -> None:
+        """
+        Initialize a new, empty reservoir sampler that keeps a uniform random
+        sample of `k` values of the stream (Algorithm R): every ingested value
+        is in the sample with the same probability.
+
+        :param k: Sample size.
+        :param optional seed: Seed of the random generator. If `None`
+            (default), it is seeded from the system's entropy.
+        """
+        ...
+
+    @property
+    def k(self) -> int:
+        """Sample size."""
+        ...
+
+    @property
+    def n_values(self) -> int:
+        """Total number of data points ingested."""
+        ...
+
+    def update(self, x: float) -> None:
+        """
+        Updates the sampler in-place with a single value.
+
+        :param x: Value to add.
+        """
+        ...
+
+    def batch_update(self, x: Sequence[float]) -> None:
+        """
+        Updates the sampler in-place with a sequence of values.
+
+        :param x: Sequence of values to add.
+        """
+        ...
+
+    def values(self) -> Any:
+        """
+        Returns the sampled values as a NumPy array, in no particular order.
+
+        Requires NumPy to be installed.
+
+        :return: float64 array of at most `k` values.
+        """
+        ...
+
+    def to_list(self) -> List[float]:
+        """Returns the sampled values as a list, in no particular order."""
+        ...
+
+    def merge(self, other: "ReservoirSampler") -> "ReservoirSampler":
+        """
+        Merges this sampler with another by weighted subsampling, returning a
+        uniform sample of both streams as a new instance. The result keeps
+        the smaller `k` of both samplers.
+
+        Equivalent to the `+` operator.
+
+        :param other: Other ReservoirSampler instance.
+        :return: New ReservoirSampler instance.
+        """
+        ...
+
+    def merge_inplace(self, other: "ReservoirSampler") -> None:
+        """
+        Merges another sampler into `self` by weighted subsampling.
+
+        Equivalent to the `+=` operator.
+
+        :param other: Other ReservoirSampler instance.
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if no data has been ingested."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """
+        Returns a compact binary representation of the sampler, including
+        the state of its random generator.
+        """
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "ReservoirSampler":
+        """
+        Reconstructs a ReservoirSampler from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: ReservoirSampler instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the sampler."""
+        ...
+
+    @staticmethod
+    def from_dict(sampler_dict: Dict[str, Any]) -> "ReservoirSampler":
+        """
+        Reconstructs a ReservoirSampler from the output of `to_dict`.
+
+        :param sampler_dict: Dictionary.
+        :return: ReservoirSampler instance.
+        """
+        ...
+
+    def copy(self) -> "ReservoirSampler":
+        """Returns a copy of the sampler."""
+        ...
+
+    def __copy__(self) -> "ReservoirSampler":
+        """Returns a copy of the sampler."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "ReservoirSampler":
+        """Returns a copy of the sampler."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling via `from_bytes`."""
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of sampled values."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both samplers have identical states."""
+        ...
+
+    def __add__(self, other: "ReservoirSampler") -> "ReservoirSampler":
+        """Merges two samplers into a new instance."""
+        ...
+
+    def __iadd__(self, other: "ReservoirSampler") -> "ReservoirSampler":
+        """Merges another sampler into this one in-place."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class ExponentialHistogram:
+    def __init__(self, window: float, epsilon: float = 0.01) -> None:
+        """
+        Initialize a new, empty exponential histogram that estimates the
+        count and sum of the values over a sliding time window. The counts
+        have a relative error of at most `epsilon`; the sums have a similar,
+        but not guaranteed, relative error for values of similar magnitude.
+
+        :param window: Maximum window length, in the unit of the timestamps.
+        :param epsilon: Relative error of the counts, between 0 and 1
+            (exclusive).
+        """
+        ...
+
+    @property
+    def window(self) -> float:
+        """Maximum window length."""
+        ...
+
+    @property
+    def epsilon(self) -> float:
+        """Relative error bound of the counts."""
+        ...
+
+    @property
+    def now(self) -> Optional[float]:
+        """Latest timestamp, or None if no value has been added."""
+        ...
+
+    @property
+    def n_buckets(self) -> int:
+        """Number of buckets currently kept."""
+        ...
+
+    def add(self, timestamp: float, value: float = 1.0) -> None:
+        """
+        Adds a value in-place. Timestamps must not decrease; values older
+        than `window` before the latest timestamp are dropped.
+
+        :param timestamp: Timestamp of the value.
+        :param value: Value to add to the sums (default: 1.0).
+        """
+        ...
+
+    def batch_add(
+        self,
+        timestamps: Sequence[float],
+        values: Optional[Sequence[float]] = None,
+    ) -> None:
+        """
+        Adds a sequence of values in-place.
+
+        :param timestamps: Non-decreasing sequence of timestamps.
+        :param optional values: Values of the same length as the timestamps
+            (default: 1.0 each).
+        """
+        ...
+
+    def query(
+        self, window: Optional[float] = None, now: Optional[float] = None
+    ) -> Tuple[float, float]:
+        """
+        Estimates the count and sum of the values after `now - window`.
+
+        :param optional window: Window length, between 0 and the histogram's
+            `window` (default).
+        :param optional now: End of the window, not before the latest
+            timestamp (default).
+        :return: Tuple (count, sum).
+        """
+        ...
+
+    def is_empty(self) -> bool:
+        """Returns True if the histogram holds no values."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Returns a compact binary representation of the histogram."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> "ExponentialHistogram":
+        """
+        Reconstructs an ExponentialHistogram from the output of `to_bytes`.
+
+        :param data: Bytes object.
+        :return: ExponentialHistogram instance.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """Returns a dictionary representation of the histogram."""
+        ...
+
+    @staticmethod
+    def from_dict(hist_dict: Dict[str, Any]) -> "ExponentialHistogram":
+        """
+        Reconstructs an ExponentialHistogram from the output of `to_dict`.
+
+        :param hist_dict: Dictionary.
+        :return: ExponentialHistogram instance.
+        """
+        ...
+
+    def copy(self) -> "ExponentialHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __copy__(self) -> "ExponentialHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __deepcopy__(self, memo: Dict[int, Any]) -> "ExponentialHistogram":
+        """Returns a copy of the histogram."""
+        ...
+
+    def __reduce__(self) -> Tuple[Any, ...]:
+        """Enables pickling via `from_bytes`."""
+        ...
+
+    def __eq__(self, other: object) -> bool:
+        """Returns True if both histograms have identical states."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+class Registry:
+    def __init__(self) -> None:
+        """
+        Initialize a new, empty registry of named digests.
+        `fastdigest.registry` is the registry of the process.
+        """
+        ...
+
+    def digest(
+        self,
+        name: str,
+        max_centroids: Optional[int] = None,
+        help: Optional[str] = None,
+    ) -> TDigest:
+        """
+        Returns the digest registered as `name`, registering a new one if
+        there is none.
+
+        :param name: Name of the digest.
+        :param optional max_centroids:
+            Number of centroids of a new digest. Default is 1000. Raises
+            ValueError if it differs from that of an existing digest.
+        :param optional help: Help text for the Prometheus export.
+        :return: The registered TDigest instance.
+        """
+        ...
+
+    def register(
+        self, name: str, digest: TDigest, help: Optional[str] = None
+    ) -> None:
+        """
+        Registers an existing digest as `name`.
+
+        :param name: Name of the digest, which must not be taken.
+        :param digest: TDigest instance.
+        :param optional help: Help text for the Prometheus export.
+        """
+        ...
+
+    def unregister(self, name: str) -> TDigest:
+        """
+        Removes the digest registered as `name`.
+
+        :param name: Name of the digest.
+        :return: The removed TDigest instance.
+        """
+        ...
+
+    def clear(self) -> None:
+        """Removes all digests."""
+        ...
+
+    def names(self) -> List[str]:
+        """Returns the names in the order in which they were registered."""
+        ...
+
+    def get(self, name: str) -> Optional[TDigest]:
+        """Returns the digest registered as `name`, or None."""
+        ...
+
+    def update(self, name: str, x: float, w: Optional[float] = None) -> None:
+        """
+        Updates the digest registered as `name` in-place with a single
+        value, registering a new digest if there is none.
+
+        :param name: Name of the digest.
+        :param x: Value to add.
+        :param optional w: Weight. Default is 1.
+        """
+        ...
+
+    def batch_update(
+        self,
+        name: str,
+        x: Sequence[float],
+        w: Optional[Union[Sequence[float], float]] = None,
+    ) -> None:
+        """
+        Updates the digest registered as `name` in-place with a sequence of
+        values, registering a new digest if there is none.
+
+        :param name: Name of the digest.
+        :param x: Sequence of values to add.
+        :param optional w:
+            Weights. This can be either a sequence of the same length as `x`,
+            or a scalar that will be used as the weight for the entire batch.
+            If `None` (default), each value has a weight of 1.
+        """
+        ...
+
+    def snapshot(self) -> Dict[str, TDigest]:
+        """
+        Returns copies of all digests, taken at one point in time.
+
+        :return: Dict of TDigest instances by name.
+        """
+        ...
+
+    def to_prometheus(
+        self,
+        kind: str = "summary",
+        quantiles: Optional[Sequence[float]] = None,
+        buckets: Optional[Sequence[float]] = None,
+        labels: Optional[Dict[str, Any]] = None,
+    ) -> str:
+        """
+        Renders all digests, as of one point in time, in the Prometheus text
+        exposition format, each as by `TDigest.to_prometheus` with its name
+        and help text.
+
+        :param optional kind: "summary" (default) or "histogram".
+        :param optional quantiles:
+            Quantiles of a summary. Default is [0.5, 0.9, 0.99].
+        :param optional buckets: Upper bounds of the histogram buckets.
+        :param optional labels: Labels added to every sample.
+        :return: Metric families as text.
+        """
+        ...
+
+    def save(self, path: Union[str, "os.PathLike[str]"]) -> None:
+        """
+        Saves all digests, as of one point in time, to one file with
+        `save_many`.
+
+        :param path: Path of the file.
+        """
+        ...
+
+    def __len__(self) -> int:
+        """Returns the number of digests."""
+        ...
+
+    def __contains__(self, name: str) -> bool:
+        """Returns True if a digest is registered as `name`."""
+        ...
+
+    def __getitem__(self, name: str) -> TDigest:
+        """Returns the digest registered as `name`, raising KeyError."""
+        ...
+
+    def __repr__(self) -> str:
+        """Return a string representation of the instance."""
+        ...
+
+
+registry: Registry
+
+
+def merge_all(
+    digests: Iterable[TDigest],
+    max_centroids: Optional[int] = None,
+    n_threads: Optional[int] = 1,
+    deterministic: bool = False,
+    streaming: bool = False,
+) -> TDigest:
     """
     Merge an iterable of TDigests into a single new instance.
 
@@ -519,8 +6399,48 @@ def merge_all(
     :param optional max_centroids:
         Parameter to be used for the new instance.
         If `None` (default), the value is determined from the input TDigests.
+    :param optional n_threads:
+        Number of threads for a parallel tree merge: each thread merges a
+        chunk of the digests before the partial results are merged. `None`
+        uses all available cores. Default is 1 (single k-way merge).
+    :param optional deterministic:
+        If `True`, the digests are merged in a canonical order determined
+        by their contents, in a single pass, so that the result is
+        bit-identical regardless of their order (and of `n_threads`).
+        Default is `False`.
+    :param optional streaming:
+        If `True`, the centroid lists of the digests are merged in a single
+        pass with a k-way (heap) merge, without copying the digests or
+        concatenating their centroids first, so that the memory needed
+        besides the inputs is bounded by the size of the result. The result
+        is the same as with `n_threads=1`; `n_threads` is not used.
+        Default is `False`.
     :return: New TDigest representing the merged data.
     """
     ...
 
+
+def save_many(
+    digests: Union[Mapping[Hashable, TDigest], TDigestMap],
+    path: Union[str, "os.PathLike[str]"],
+) -> None:
+    """
+    Save a collection of digests to one file, from which they can be
+    loaded one by one with `load_many`. The file is replaced atomically.
+
+    :param digests: Mapping of keys to TDigests, or a TDigestMap.
+    :param path: Path of the file.
+    """
+    ...
+
+
+def load_many(path: Union[str, "os.PathLike[str]"]) -> DigestFile:
+    """
+    Open a file written by `save_many`, reading only its index.
+
+    :param path: Path of the file.
+    :return: DigestFile from which the digests are loaded by key.
+    """
+    ...
+
 __version__: str
@@ -0,0 +1,121 @@
+#ifndef FASTDIGEST_H
+#define FASTDIGEST_H
+
+/* Generated with cbindgen from src/capi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The call succeeded.
+#define FASTDIGEST_OK 0
+
+// A pointer was null, or a value, weight or size was out of range.
+#define FASTDIGEST_ERR_ARGUMENT -1
+
+// Memory could not be allocated.
+#define FASTDIGEST_ERR_MEMORY -2
+
+// The bytes are not a valid digest for this platform and version.
+#define FASTDIGEST_ERR_DATA -3
+
+// Opaque handle to a digest.
+typedef struct FastDigest FastDigest;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty digest with up to `max_centroids` centroids (1000 in
+// Python by default). Returns null if it exceeds the platform limit or
+// memory could not be allocated.
+FastDigest *fastdigest_new(size_t max_centroids);
+
+// Frees a digest. Null is ignored.
+//
+// # Safety
+//
+// `digest` must be null or a handle that has not been freed yet.
+void fastdigest_free(FastDigest *digest);
+
+// Adds a value `x` with weight `w` (1 for an unweighted value).
+//
+// # Safety
+//
+// `digest` must be null or a valid handle.
+int fastdigest_update(FastDigest *digest, double x, double w);
+
+// Adds `n` values from the array `x`. If `w` is not null, it holds the
+// weights of the values. Nothing is added if any of them is invalid.
+//
+// # Safety
+//
+// `digest` must be null or a valid handle, and `x` (and `w`, if not null)
+// must point to `n` doubles.
+int fastdigest_update_batch(FastDigest *digest, const double *x, const double *w, size_t n);
+
+// Merges `other` into `digest`, keeping the `max_centroids` of `digest`.
+//
+// # Safety
+//
+// `digest` and `other` must be null or valid handles (they may be the
+// same).
+int fastdigest_merge(FastDigest *digest, const FastDigest *other);
+
+// Estimates the quantile for a cumulative probability `q` between 0 and
+// 1. Returns NaN if the digest is empty or `q` is out of range.
+//
+// # Safety
+//
+// `digest` must be null or a valid handle.
+double fastdigest_quantile(FastDigest *digest, double q);
+
+// Estimates the rank (cumulative probability) of a value `x`. Returns NaN
+// if the digest is empty.
+//
+// # Safety
+//
+// `digest` must be null or a valid handle.
+double fastdigest_cdf(FastDigest *digest, double x);
+
+// Number of values added to the digest (0 for null).
+//
+// # Safety
+//
+// `digest` must be null or a valid handle.
+uint64_t fastdigest_count(const FastDigest *digest);
+
+// Serializes the digest into a new buffer, whose address and length are
+// written to `out` and `out_len`. The buffer must be freed with
+// `fastdigest_bytes_free`.
+//
+// # Safety
+//
+// `digest` must be null or a valid handle, and `out` and `out_len` must
+// be null or valid for writes.
+int fastdigest_to_bytes(FastDigest *digest, uint8_t **out, size_t *out_len);
+
+// Frees a buffer returned by `fastdigest_to_bytes`. Null is ignored.
+//
+// # Safety
+//
+// `bytes` must be null or a buffer from `fastdigest_to_bytes` that has not
+// been freed yet, with the length returned along with it.
+void fastdigest_bytes_free(uint8_t *bytes, size_t len);
+
+// Loads a digest from `len` bytes in the format of `fastdigest_to_bytes`
+// (or `TDigest.to_bytes` in Python) and writes the new handle to `out`.
+//
+// # Safety
+//
+// `data` must be null or point to `len` bytes, and `out` must be null or
+// valid for writes.
+int fastdigest_from_bytes(const uint8_t *data, size_t len, FastDigest **out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FASTDIGEST_H */
@@ -0,0 +1,153 @@
+//! Awaitable ingestion from async iterators
+//!
+//! `TDigest.aupdate` returns an `AsyncUpdate`, which implements the
+//! coroutine protocol by hand: it awaits `__anext__()` of the async iterator
+//! (delegating to its `__await__` iterator like `yield from` would), merges
+//! the chunk and then yields once to the event loop before the next chunk.
+
+use crate::PyTDigest;
+use pyo3::exceptions::{PyRuntimeError, PyStopAsyncIteration, PyStopIteration};
+use pyo3::prelude::*;
+
+#[pyclass(name = "AsyncUpdate", module = "fastdigest")]
+pub struct AsyncUpdate {
+    digest: Py<PyTDigest>,
+    iterator: Py<PyAny>,
+    /// `__await__` iterator of the pending `__anext__()` call
+    pending: Option<Py<PyAny>>,
+    done: bool,
+}
+
+impl AsyncUpdate {
+    pub fn new(digest: Py<PyTDigest>, iterator: Py<PyAny>) -> Self {
+        Self {
+            digest,
+            iterator,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Advances the coroutine by passing `value` (or the exception `error`)
+    /// to the pending awaitable. Returns the value to yield to the event
+    /// loop, or `None` once the async iterator is exhausted.
+    fn step(
+        &mut self,
+        py: Python<'_>,
+        value: Option<Bound<'_, PyAny>>,
+        error: Option<PyErr>,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        if self.done {
+            return Err(PyRuntimeError::new_err(
+                "aupdate() awaitable was already awaited.",
+            ));
+        }
+        let pending = match self.pending.take() {
+            Some(pending) => pending.into_bound(py),
+            None => {
+                if let Some(error) = error {
+                    self.done = true;
+                    return Err(error);
+                }
+                match self.iterator.bind(py).call_method0("__anext__") {
+                    Ok(awaitable) => awaitable.call_method0("__await__")?,
+                    Err(e) => return self.finish(py, e),
+                }
+            }
+        };
+        let result = match (error, value) {
+            (Some(error), _) => {
+                pending.call_method1("throw", (error.into_value(py),))
+            }
+            (None, Some(value)) if !value.is_none() => {
+                pending.call_method1("send", (value,))
+            }
+            (None, _) => pending.call_method0("__next__"),
+        };
+        match result {
+            Ok(yielded) => {
+                self.pending = Some(pending.unbind());
+                Ok(Some(yielded.unbind()))
+            }
+            Err(e) if e.is_instance_of::<PyStopIteration>(py) => {
+                let ingested = e
+                    .value(py)
+                    .getattr("value")
+                    .and_then(|chunk| self.ingest(&chunk));
+                if let Err(e) = ingested {
+                    self.done = true;
+                    return Err(e);
+                }
+                // bare yield: lets the event loop run before the next chunk
+                Ok(Some(py.None()))
+            }
+            Err(e) => self.finish(py, e),
+        }
+    }
+
+    /// Ends the coroutine: normally on `StopAsyncIteration`, otherwise by
+    /// propagating the error.
+    fn finish(
+        &mut self,
+        py: Python<'_>,
+        error: PyErr,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        self.done = true;
+        if error.is_instance_of::<PyStopAsyncIteration>(py) {
+            Ok(None)
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Merges a single value or an array of values into the digest.
+    fn ingest(&self, chunk: &Bound<'_, PyAny>) -> PyResult<()> {
+        let digest = self.digest.borrow(chunk.py());
+        match chunk.extract::<f64>() {
+            Ok(x) => digest.update_value(x, None),
+            Err(_) => digest.batch_update(chunk, None, Some(1), false, false),
+        }
+    }
+}
+
+#[pymethods]
+impl AsyncUpdate {
+    pub fn __await__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    pub fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        self.step(py, None, None)
+    }
+
+    pub fn send(
+        &mut self,
+        py: Python<'_>,
+        value: Bound<'_, PyAny>,
+    ) -> PyResult<Py<PyAny>> {
+        self.step(py, Some(value), None)?
+            .ok_or_else(|| PyStopIteration::new_err(()))
+    }
+
+    #[pyo3(signature = (error, _value=None, _traceback=None))]
+    pub fn throw(
+        &mut self,
+        py: Python<'_>,
+        error: Bound<'_, PyAny>,
+        _value: Option<Bound<'_, PyAny>>,
+        _traceback: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let error = PyErr::from_value(error);
+        self.step(py, None, Some(error))?
+            .ok_or_else(|| PyStopIteration::new_err(()))
+    }
+
+    pub fn close(&mut self) {
+        self.done = true;
+        self.pending = None;
+    }
+}
@@ -0,0 +1,395 @@
+//! Reading of numeric Arrow data through the Arrow C data interface
+//!
+//! Objects implementing the Arrow PyCapsule interface (`__arrow_c_stream__`
+//! or `__arrow_c_array__`) hand out arrays whose buffers are read in place.
+//! See https://arrow.apache.org/docs/format/CDataInterface.html
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::{ptr, slice};
+
+#[repr(C)]
+struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+struct ArrowArrayStream {
+    get_schema: Option<
+        unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowSchema) -> c_int,
+    >,
+    get_next: Option<
+        unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowArray) -> c_int,
+    >,
+    get_last_error:
+        Option<unsafe extern "C" fn(*mut ArrowArrayStream) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut ArrowArrayStream)>,
+    private_data: *mut c_void,
+}
+
+impl ArrowSchema {
+    fn empty() -> Self {
+        Self {
+            format: ptr::null(),
+            name: ptr::null(),
+            metadata: ptr::null(),
+            flags: 0,
+            n_children: 0,
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }
+    }
+}
+
+impl Drop for ArrowSchema {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            // SAFETY: a schema with a release callback is owned by us
+            unsafe { release(self) }
+        }
+    }
+}
+
+impl ArrowArray {
+    fn empty() -> Self {
+        Self {
+            length: 0,
+            null_count: 0,
+            offset: 0,
+            n_buffers: 0,
+            n_children: 0,
+            buffers: ptr::null_mut(),
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }
+    }
+
+    /// Validity bitmap and data buffer of a primitive array.
+    fn buffers(&self) -> (*const u8, *const c_void) {
+        // SAFETY: checked to hold two buffers in `ArrowValues::push_chunk`
+        unsafe { (*self.buffers.cast::<*const u8>(), *self.buffers.add(1)) }
+    }
+}
+
+impl Drop for ArrowArray {
+    fn drop(&mut self) {
+        if let Some(release) = self.release {
+            // SAFETY: an array with a release callback is owned by us
+            unsafe { release(self) }
+        }
+    }
+}
+
+/// Primitive Arrow types that can be read as floats.
+#[derive(Clone, Copy)]
+enum ArrowType {
+    F64,
+    F32,
+    I64,
+    I32,
+    I16,
+    I8,
+    U64,
+    U32,
+    U16,
+    U8,
+}
+
+impl ArrowType {
+    fn from_format(format: &[u8]) -> Option<Self> {
+        match format {
+            b"g" => Some(Self::F64),
+            b"f" => Some(Self::F32),
+            b"l" => Some(Self::I64),
+            b"i" => Some(Self::I32),
+            b"s" => Some(Self::I16),
+            b"c" => Some(Self::I8),
+            b"L" => Some(Self::U64),
+            b"I" => Some(Self::U32),
+            b"S" => Some(Self::U16),
+            b"C" => Some(Self::U8),
+            _ => None,
+        }
+    }
+
+    /// Reads the element with index `i` as `f64`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to a buffer of this type with more than `i` items.
+    #[inline]
+    unsafe fn read(self, data: *const c_void, i: usize) -> f64 {
+        unsafe {
+            match self {
+                Self::F64 => ptr::read_unaligned(data.cast::<f64>().add(i)),
+                Self::F32 => {
+                    ptr::read_unaligned(data.cast::<f32>().add(i)) as f64
+                }
+                Self::I64 => {
+                    ptr::read_unaligned(data.cast::<i64>().add(i)) as f64
+                }
+                Self::I32 => {
+                    ptr::read_unaligned(data.cast::<i32>().add(i)) as f64
+                }
+                Self::I16 => {
+                    ptr::read_unaligned(data.cast::<i16>().add(i)) as f64
+                }
+                Self::I8 => {
+                    ptr::read_unaligned(data.cast::<i8>().add(i)) as f64
+                }
+                Self::U64 => {
+                    ptr::read_unaligned(data.cast::<u64>().add(i)) as f64
+                }
+                Self::U32 => {
+                    ptr::read_unaligned(data.cast::<u32>().add(i)) as f64
+                }
+                Self::U16 => {
+                    ptr::read_unaligned(data.cast::<u16>().add(i)) as f64
+                }
+                Self::U8 => {
+                    ptr::read_unaligned(data.cast::<u8>().add(i)) as f64
+                }
+            }
+        }
+    }
+}
+
+/// Numeric Arrow data, held as the chunks it was exported in. Null entries
+/// are read as NaN.
+pub struct ArrowValues {
+    dtype: ArrowType,
+    chunks: Vec<ArrowArray>,
+    len: usize,
+}
+
+impl ArrowValues {
+    /// Reads `obj` through `__arrow_c_stream__` or `__arrow_c_array__`, if
+    /// it implements one of them.
+    pub fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Option<Self>> {
+        if obj.hasattr("__arrow_c_stream__")? {
+            let capsule = obj.call_method0("__arrow_c_stream__")?;
+            Self::from_stream(capsule_pointer(&capsule, c"arrow_array_stream")?)
+                .map(Some)
+        } else if obj.hasattr("__arrow_c_array__")? {
+            let capsules = obj.call_method0("__arrow_c_array__")?;
+            let (schema, array): (Bound<'_, PyAny>, Bound<'_, PyAny>) =
+                capsules.extract()?;
+            let schema = capsule_pointer(&schema, c"arrow_schema")?;
+            let array = capsule_pointer(&array, c"arrow_array")?;
+            Self::from_array(schema.cast(), array.cast()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn from_stream(stream: *mut c_void) -> PyResult<Self> {
+        let stream = stream.cast::<ArrowArrayStream>();
+        // SAFETY: the capsule keeps the stream alive until it is collected
+        unsafe {
+            let (Some(get_schema), Some(get_next)) =
+                ((*stream).get_schema, (*stream).get_next)
+            else {
+                return Err(PyValueError::new_err("Arrow stream is released."));
+            };
+
+            let mut schema = ArrowSchema::empty();
+            check_status(stream, get_schema(stream, &mut schema))?;
+            let mut values = Self {
+                dtype: parse_schema(&schema)?,
+                chunks: Vec::new(),
+                len: 0,
+            };
+            loop {
+                let mut array = ArrowArray::empty();
+                check_status(stream, get_next(stream, &mut array))?;
+                if array.release.is_none() {
+                    break;
+                }
+                values.push_chunk(array)?;
+            }
+            Ok(values)
+        }
+    }
+
+    fn from_array(
+        schema: *const ArrowSchema,
+        array: *mut ArrowArray,
+    ) -> PyResult<Self> {
+        // SAFETY: the capsules hold a valid schema and array. The array is
+        // moved out and marked as released, as the C data interface allows.
+        unsafe {
+            let dtype = parse_schema(&*schema)?;
+            if (*array).release.is_none() {
+                return Err(PyValueError::new_err("Arrow array is released."));
+            }
+            let owned = ptr::read(array);
+            ptr::addr_of_mut!((*array).release).write(None);
+            let mut values = Self {
+                dtype,
+                chunks: Vec::new(),
+                len: 0,
+            };
+            values.push_chunk(owned)?;
+            Ok(values)
+        }
+    }
+
+    fn push_chunk(&mut self, array: ArrowArray) -> PyResult<()> {
+        let (Ok(length), Ok(_)) =
+            (usize::try_from(array.length), usize::try_from(array.offset))
+        else {
+            return Err(PyValueError::new_err("Arrow data is corrupt."));
+        };
+        if array.n_buffers != 2 || array.buffers.is_null() {
+            return Err(PyValueError::new_err("Arrow data is corrupt."));
+        }
+        if length > 0 && array.buffers().1.is_null() {
+            return Err(PyValueError::new_err("Arrow data is corrupt."));
+        }
+        self.len += length;
+        self.chunks.push(array);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn iter(&self) -> ArrowIter<'_> {
+        ArrowIter {
+            dtype: self.dtype,
+            chunks: self.chunks.iter(),
+            validity: ptr::null(),
+            data: ptr::null(),
+            i: 0,
+            end: 0,
+            remaining: self.len,
+        }
+    }
+}
+
+/// Pointer held by a PyCapsule with the given name.
+fn capsule_pointer(
+    capsule: &Bound<'_, PyAny>,
+    name: &CStr,
+) -> PyResult<*mut c_void> {
+    let capsule = capsule.cast::<PyCapsule>()?;
+    let pointer = capsule.pointer();
+    if capsule.name()? != Some(name) || pointer.is_null() {
+        return Err(PyTypeError::new_err(format!(
+            "Expected a valid {} capsule.",
+            name.to_string_lossy()
+        )));
+    }
+    Ok(pointer)
+}
+
+/// Checks the element type of an array; only primitive numbers are allowed.
+fn parse_schema(schema: &ArrowSchema) -> PyResult<ArrowType> {
+    if schema.format.is_null() {
+        return Err(PyValueError::new_err("Arrow schema is corrupt."));
+    }
+    // SAFETY: the format is a null-terminated string owned by the schema
+    let format = unsafe { CStr::from_ptr(schema.format) };
+    match ArrowType::from_format(format.to_bytes()) {
+        Some(dtype) if schema.dictionary.is_null() => Ok(dtype),
+        _ => Err(PyTypeError::new_err(
+            "Arrow data must be of an integer or floating point type.",
+        )),
+    }
+}
+
+/// Turns a non-zero status code of a stream callback into a ValueError.
+///
+/// # Safety
+///
+/// `stream` must point to a valid, unreleased stream.
+unsafe fn check_status(
+    stream: *mut ArrowArrayStream,
+    status: c_int,
+) -> PyResult<()> {
+    if status == 0 {
+        return Ok(());
+    }
+    let message = unsafe {
+        (*stream)
+            .get_last_error
+            .map(|get_last_error| get_last_error(stream))
+            .filter(|message| !message.is_null())
+            .map(|message| CStr::from_ptr(message).to_string_lossy())
+    };
+    Err(PyValueError::new_err(match message {
+        Some(message) => format!("Failed to read Arrow stream: {message}"),
+        None => format!("Failed to read Arrow stream (error code {status})."),
+    }))
+}
+
+pub struct ArrowIter<'a> {
+    dtype: ArrowType,
+    chunks: slice::Iter<'a, ArrowArray>,
+    validity: *const u8,
+    data: *const c_void,
+    i: usize,
+    end: usize,
+    remaining: usize,
+}
+
+impl Iterator for ArrowIter<'_> {
+    type Item = f64;
+
+    #[inline]
+    fn next(&mut self) -> Option<f64> {
+        while self.i == self.end {
+            let chunk = self.chunks.next()?;
+            (self.validity, self.data) = chunk.buffers();
+            self.i = chunk.offset as usize;
+            self.end = self.i + chunk.length as usize;
+        }
+        let i = self.i;
+        self.i += 1;
+        self.remaining -= 1;
+        // SAFETY: `i` is within the bounds of the current chunk
+        unsafe {
+            if !self.validity.is_null()
+                && *self.validity.add(i / 8) >> (i % 8) & 1 == 0
+            {
+                return Some(f64::NAN);
+            }
+            Some(self.dtype.read(self.data, i))
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
@@ -0,0 +1,467 @@
+//! Digests of a value conditioned on a covariate, one per covariate bin
+//!
+//! Pairs `(x, y)` are routed by `x` into fixed bins given by their edges,
+//! and `y` is added to the digest of that bin in a `DigestArray`, so that
+//! the quantiles of `y` can be read as a function of `x` (e.g. latency by
+//! payload size). Bins are half-open like `numpy.histogram`, except that
+//! the last one also includes its right edge; pairs outside all bins are
+//! left out.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_array::{validate_index, DigestArray, PyTDigestArray};
+use crate::digest_map::wrap_digest;
+use crate::errors::{InvalidQuantileError, SerializationError};
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, extract_values, f64_array, malloc_error,
+    validate_max_centroids, validate_n_threads, validate_value,
+    validate_values, validate_weight, validate_weights, PyTDigest,
+};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTBTD~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+pub struct BinnedDigest {
+    edges: Vec<f64>,
+    array: DigestArray,
+}
+
+/// Whether `edges` are at least two finite, strictly increasing values.
+fn valid_edges(edges: &[f64]) -> bool {
+    edges.len() >= 2
+        && edges.iter().all(|x| x.is_finite())
+        && edges.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+impl BinnedDigest {
+    pub fn new(
+        edges: Vec<f64>,
+        max_size: usize,
+    ) -> Result<Self, TryReserveError> {
+        let array = DigestArray::new(edges.len() - 1, max_size)?;
+        Ok(Self { edges, array })
+    }
+
+    /// The bin of the covariate `x`, or None if it is outside all bins.
+    pub fn bin(&self, x: f64) -> Option<usize> {
+        let last = self.edges.len() - 1;
+        if !(self.edges[0] <= x && x <= self.edges[last]) {
+            return None;
+        }
+        let i = self.edges.partition_point(|&edge| edge <= x);
+        Some((i - 1).min(last - 1))
+    }
+
+    /// Binary representation; the digests must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64s(&self.edges);
+        writer.bytes(&self.array.to_bytes()?);
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let edges = reader.f64s()?;
+        let array = DigestArray::from_bytes(reader.bytes()?)?;
+        reader.finish()?;
+        Self::from_parts(edges, array).ok_or(BytesError::CorruptData)
+    }
+
+    /// Returns None unless `edges` are valid for the bins of `array`.
+    fn from_parts(edges: Vec<f64>, array: DigestArray) -> Option<Self> {
+        if !valid_edges(&edges) || array.len() != edges.len() - 1 {
+            return None;
+        }
+        Some(Self { edges, array })
+    }
+}
+
+#[pyclass(name = "BinnedTDigest", module = "fastdigest", frozen)]
+pub struct PyBinnedTDigest {
+    state: Mutex<BinnedDigest>,
+}
+
+impl PyBinnedTDigest {
+    fn from_binned(binned: BinnedDigest) -> Self {
+        Self {
+            state: Mutex::new(binned),
+        }
+    }
+
+    /// Locks the digests after flushing all bins.
+    fn lock_flushed(&self) -> PyResult<MutexGuard<'_, BinnedDigest>> {
+        let mut binned = self.state.lock();
+        binned.array.flush().map_err(malloc_error)?;
+        Ok(binned)
+    }
+
+    /// Applies `f` to every non-empty digest, returning a NumPy array.
+    fn map_digests<'py>(
+        &self,
+        py: Python<'py>,
+        f: impl Fn(&TDigest) -> f64 + Send,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let results = py.detach(|| {
+            self.state.lock().array.map_digests(f).map_err(malloc_error)
+        })?;
+        f64_array(py, &results)
+    }
+}
+
+#[pymethods]
+impl PyBinnedTDigest {
+    /// Constructs a new BinnedTDigest instance with an empty digest for
+    /// each bin between consecutive `edges`.
+    #[new]
+    #[pyo3(signature = (edges, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(edges: Vec<f64>, max_centroids: i64) -> PyResult<Self> {
+        if !valid_edges(&edges) {
+            return Err(PyValueError::new_err(
+                "edges must be at least 2 finite, strictly increasing values.",
+            ));
+        }
+        let max_size = validate_max_centroids(max_centroids)?;
+        let binned =
+            BinnedDigest::new(edges, max_size).map_err(malloc_error)?;
+        Ok(Self::from_binned(binned))
+    }
+
+    /// Getter property: returns the bin edges.
+    #[getter(edges)]
+    pub fn get_edges(&self) -> Vec<f64> {
+        self.state.lock().edges.clone()
+    }
+
+    /// Getter property: returns the center of every bin.
+    #[getter(centers)]
+    pub fn get_centers<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let centers: Vec<f64> = self
+            .state
+            .lock()
+            .edges
+            .windows(2)
+            .map(|pair| 0.5 * (pair[0] + pair[1]))
+            .collect();
+        f64_array(py, &centers)
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().array.max_size()
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().array.count()
+    }
+
+    /// Updates the digest of the bin of `x` (in-place) with the value `y`.
+    /// Does nothing if `x` is outside all bins.
+    #[pyo3(signature = (x, y, w=None))]
+    pub fn update(&self, x: f64, y: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(y)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut binned = self.state.lock();
+        match binned.bin(x) {
+            Some(i) => binned.array.add(i, y, weight).map_err(malloc_error),
+            None => Ok(()),
+        }
+    }
+
+    /// Updates the digests (in-place) with two sequences of float values,
+    /// adding each value of `y` to the bin of the value of `x` at the same
+    /// position. Pairs with `x` outside all bins are left out.
+    #[pyo3(signature = (x, y, w=None, n_threads=Some(1)))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        y: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        n_threads: Option<i64>,
+    ) -> PyResult<()> {
+        let py = y.py();
+        let n_threads = validate_n_threads(n_threads)?;
+        let (covariates, _) = extract_values(x)?;
+        let (values, skip_nan) = extract_values(y)?;
+        if covariates.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "x must have the same length as y.",
+            ));
+        }
+        let weights = validate_weights(w, values.len())?;
+        let mut indices = Vec::new();
+        let mut kept_values = Vec::new();
+        let mut kept_weights = weights.as_ref().map(|_| Vec::new());
+        {
+            let binned = self.state.lock();
+            for (j, (x, y)) in
+                covariates.iter(py).zip(values.iter(py)).enumerate()
+            {
+                // missing values are left out together with their weights
+                if skip_nan && y.is_nan() {
+                    continue;
+                }
+                let Some(i) = binned.bin(x) else {
+                    continue;
+                };
+                indices.push(i);
+                kept_values.push(y);
+                if let (Some(kept), Some(weights)) =
+                    (&mut kept_weights, &weights)
+                {
+                    kept.push(weights[j]);
+                }
+            }
+        }
+        validate_values(kept_values.iter().copied())?;
+        py.detach(|| {
+            self.state
+                .lock()
+                .array
+                .add_grouped(
+                    &indices,
+                    &kept_values,
+                    kept_weights.as_deref(),
+                    n_threads,
+                )
+                .map_err(malloc_error)
+        })
+    }
+
+    /// Estimates the quantile of `y` in every bin for a given cumulative
+    /// probability `q`, with NaN for empty bins.
+    pub fn quantile<'py>(
+        &self,
+        py: Python<'py>,
+        q: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.map_digests(py, |digest| digest.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile of `y` in every bin for a given cumulative
+    /// probability `p` (%), with NaN for empty bins.
+    pub fn percentile<'py>(
+        &self,
+        py: Python<'py>,
+        p: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.map_digests(py, |digest| digest.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median of `y` in every bin, with NaN for empty bins.
+    pub fn median<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.map_digests(py, |digest| digest.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF of `y` in every bin at the value `y`, with NaN for
+    /// empty bins.
+    pub fn cdf<'py>(
+        &self,
+        py: Python<'py>,
+        y: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.map_digests(py, |digest| digest.estimate_rank(y))
+    }
+
+    /// Returns a NumPy array of shape (n_bins, len(q)) with the estimated
+    /// quantiles `q` of `y` in every bin (a row of NaN for empty bins).
+    pub fn quantile_curve<'py>(
+        &self,
+        py: Python<'py>,
+        q: Vec<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let (n_bins, results) = {
+            let binned = self.lock_flushed()?;
+            let mut results = Vec::new();
+            for digest in binned.array.digests() {
+                results.extend(q.iter().map(|&q_i| {
+                    if digest.is_empty() {
+                        f64::NAN
+                    } else {
+                        digest.estimate_quantile(q_i)
+                    }
+                }));
+            }
+            (binned.array.len(), results)
+        };
+        f64_array(py, &results)?.call_method1("reshape", ((n_bins, q.len()),))
+    }
+
+    /// Returns the total weight of every bin.
+    pub fn mass<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let results: Vec<f64> = {
+            let binned = self.lock_flushed()?;
+            binned.array.digests().map(TDigest::mass).collect()
+        };
+        f64_array(py, &results)
+    }
+
+    /// Merges this digest with another with the same edges, returning a
+    /// new BinnedTDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same edges into this one (in-place),
+    /// bin by bin.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut binned = self.state.lock();
+        if binned.edges != other.edges {
+            return Err(PyValueError::new_err(
+                "Cannot merge BinnedTDigests with different edges.",
+            ));
+        }
+        binned.array.merge(&other.array).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the edges and all digests.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.lock_flushed()?.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a BinnedTDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let binned = BinnedDigest::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_binned(binned))
+    }
+
+    /// Returns a dict representation of the edges and all digests, in the
+    /// format of TDigestArray with the additional key "edges".
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let binned = self.lock_flushed()?.clone();
+        let dict = PyTDigestArray::from_array(binned.array).to_dict(py)?;
+        dict.set_item("edges", binned.edges)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a BinnedTDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(binned_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let edges: Vec<f64> = binned_dict
+            .get_item("edges")?
+            .ok_or_else(|| {
+                SerializationError::new_err("Key 'edges' not found in dict.")
+            })?
+            .extract()?;
+        let array = PyTDigestArray::from_dict(binned_dict)?.into_array();
+        let binned =
+            BinnedDigest::from_parts(edges, array).ok_or_else(|| {
+                SerializationError::new_err("Inconsistent BinnedTDigest edges.")
+            })?;
+        Ok(Self::from_binned(binned))
+    }
+
+    /// BinnedTDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_binned(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via BinnedTDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(digest) returns the number of bins.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().array.len()
+    }
+
+    /// Magic method: digest[i] returns a copy of the digest of bin `i`
+    /// (negative indices count from the end) as a TDigest.
+    pub fn __getitem__(&self, i: i64) -> PyResult<PyTDigest> {
+        let mut binned = self.state.lock();
+        let len = binned.array.len();
+        let i = if i < 0 { i + len as i64 } else { i };
+        let i = validate_index(i, len)?;
+        let digest = binned.array.digest(i).map_err(malloc_error)?;
+        Ok(wrap_digest(digest.clone()))
+    }
+
+    /// Magic method: repr/str(BinnedTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let binned = self.state.lock();
+        format!(
+            "BinnedTDigest(n_bins={}, max_centroids={})",
+            binned.array.len(),
+            binned.array.max_size()
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.array.flush().map_err(malloc_error)?;
+        let binned = self.lock_flushed()?;
+        Ok(binned.edges == other.edges
+            && binned.array.same_digests(&other.array))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
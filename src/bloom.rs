@@ -0,0 +1,379 @@
+//! Bloom filter: approximate set membership
+//!
+//! An item sets `n_hashes` bits of a bit array, and is reported as present
+//! if all of them are set, so there are no false negatives. For a filter
+//! sized for `capacity` items at a false positive rate `p`, the array has
+//! `ceil(-capacity * ln(p) / ln(2)^2)` bits and `round(ln(2) * bits /
+//! capacity)` hashes. The bit positions are derived from one 64-bit item
+//! hash by enhanced double hashing (Kirsch & Mitzenmacher, 2006). Filters
+//! of the same size are merged (united) by a bitwise OR.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::hash::{hash_value, hash_values};
+use crate::tdigest::BytesError;
+use crate::{bytes_error, malloc_error};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTBLM~";
+const VERSION: u32 = 1;
+const SEED: u64 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    capacity: u64,
+    fp_rate: f64,
+    n_bits: u64,
+    n_hashes: u32,
+    words: Vec<u64>,
+}
+
+impl BloomFilter {
+    pub fn new(capacity: u64, fp_rate: f64) -> Result<Self, TryReserveError> {
+        let ln2 = std::f64::consts::LN_2;
+        let n_bits = (-(capacity as f64) * fp_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(1.0);
+        let n_hashes = (ln2 * n_bits / capacity as f64).round().max(1.0);
+        let n_bits = n_bits as u64;
+        let mut words = Vec::new();
+        // overflows are reported as a capacity overflow
+        let len = usize::try_from(n_bits.div_ceil(64)).unwrap_or(usize::MAX);
+        words.try_reserve_exact(len)?;
+        words.resize(len, 0);
+        Ok(Self {
+            capacity,
+            fp_rate,
+            n_bits,
+            n_hashes: n_hashes as u32,
+            words,
+        })
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Bit positions of an item hash.
+    #[inline]
+    fn positions(&self, hash: u64) -> impl Iterator<Item = u64> {
+        let n_bits = self.n_bits;
+        let mut a = hash;
+        // odd, so that the positions differ for all i
+        let mut b =
+            hash.rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..self.n_hashes).map(move |i| {
+            let position = ((u128::from(a) * u128::from(n_bits)) >> 64) as u64;
+            a = a.wrapping_add(b);
+            b = b.wrapping_add(u64::from(i));
+            position
+        })
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        for position in self.positions(hash) {
+            self.words[(position / 64) as usize] |= 1 << (position % 64);
+        }
+    }
+
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        self.positions(hash).all(|position| {
+            self.words[(position / 64) as usize] & (1 << (position % 64)) != 0
+        })
+    }
+
+    pub fn union(&mut self, other: &Self) {
+        for (word, &other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Probability of a false positive given the bits currently set.
+    pub fn current_fp_rate(&self) -> f64 {
+        let set: u64 =
+            self.words.iter().map(|w| u64::from(w.count_ones())).sum();
+        (set as f64 / self.n_bits as f64).powi(self.n_hashes as i32)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.capacity);
+        writer.f64(self.fp_rate);
+        writer.u64(self.n_bits);
+        writer.u32(self.n_hashes);
+        for &word in &self.words {
+            writer.u64(word);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let capacity = reader.u64()?;
+        let fp_rate = reader.f64()?;
+        let n_bits = reader.u64()?;
+        let n_hashes = reader.u32()?;
+        let words = (0..n_bits.div_ceil(64))
+            .map(|_| reader.u64())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Self::from_parts(capacity, fp_rate, n_bits, n_hashes, words)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a filter, checking that the parts are consistent.
+    fn from_parts(
+        capacity: u64,
+        fp_rate: f64,
+        n_bits: u64,
+        n_hashes: u32,
+        words: Vec<u64>,
+    ) -> Option<Self> {
+        // the bits beyond `n_bits` in the last word must not be set
+        let used = n_bits % 64;
+        let valid = capacity > 0
+            && valid_fp_rate(fp_rate)
+            && n_bits > 0
+            && n_hashes > 0
+            && words.len() as u64 == n_bits.div_ceil(64)
+            && words.last().is_some_and(|&w| used == 0 || w >> used == 0);
+        valid.then_some(Self {
+            capacity,
+            fp_rate,
+            n_bits,
+            n_hashes,
+            words,
+        })
+    }
+}
+
+#[inline]
+fn valid_fp_rate(fp_rate: f64) -> bool {
+    fp_rate > 0.0 && fp_rate < 1.0
+}
+
+#[pyclass(name = "BloomFilter", module = "fastdigest", frozen)]
+pub struct PyBloomFilter {
+    state: Mutex<BloomFilter>,
+}
+
+impl PyBloomFilter {
+    fn from_filter(filter: BloomFilter) -> Self {
+        Self {
+            state: Mutex::new(filter),
+        }
+    }
+}
+
+#[pymethods]
+impl PyBloomFilter {
+    /// Constructs a new empty BloomFilter instance sized for `capacity`
+    /// items at the false positive rate `fp_rate`.
+    #[new]
+    #[pyo3(signature = (capacity, fp_rate=0.01))]
+    pub fn py_new(capacity: i64, fp_rate: f64) -> PyResult<Self> {
+        let capacity =
+            u64::try_from(capacity).ok().filter(|&c| c > 0).ok_or_else(
+                || PyValueError::new_err("capacity must be positive."),
+            )?;
+        if !valid_fp_rate(fp_rate) {
+            return Err(PyValueError::new_err(
+                "fp_rate must be between 0 and 1 (exclusive).",
+            ));
+        }
+        let filter =
+            BloomFilter::new(capacity, fp_rate).map_err(malloc_error)?;
+        Ok(Self::from_filter(filter))
+    }
+
+    /// Getter property: returns the number of items the filter is sized
+    /// for.
+    #[getter(capacity)]
+    pub fn get_capacity(&self) -> u64 {
+        self.state.lock().capacity
+    }
+
+    /// Getter property: returns the false positive rate at capacity.
+    #[getter(fp_rate)]
+    pub fn get_fp_rate(&self) -> f64 {
+        self.state.lock().fp_rate
+    }
+
+    /// Getter property: returns the size of the bit array.
+    #[getter(n_bits)]
+    pub fn get_n_bits(&self) -> u64 {
+        self.state.lock().n_bits
+    }
+
+    /// Getter property: returns the number of bits set per item.
+    #[getter(n_hashes)]
+    pub fn get_n_hashes(&self) -> u32 {
+        self.state.lock().n_hashes
+    }
+
+    /// Adds a hashable item to the filter (in-place).
+    pub fn add(&self, item: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hash = hash_value(item, SEED)?;
+        self.state.lock().add_hash(hash);
+        Ok(())
+    }
+
+    /// Adds each item of an iterable or array to the filter (in-place).
+    pub fn batch_add(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hashes = hash_values(x, SEED)?;
+        let mut filter = self.state.lock();
+        for hash in hashes {
+            filter.add_hash(hash);
+        }
+        Ok(())
+    }
+
+    /// Returns the united filter of this filter and another, which must
+    /// have the same size.
+    pub fn union(&self, other: &Self) -> PyResult<Self> {
+        let united = self.copy();
+        united.union_inplace(other)?;
+        Ok(united)
+    }
+
+    /// Unites another filter of the same size with this one (in-place).
+    pub fn union_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut filter = self.state.lock();
+        if (filter.n_bits, filter.n_hashes) != (other.n_bits, other.n_hashes) {
+            return Err(PyValueError::new_err(
+                "Cannot unite BloomFilters of different sizes.",
+            ));
+        }
+        filter.union(&other);
+        Ok(())
+    }
+
+    /// Returns True if no item has been added.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns the false positive probability given the bits currently
+    /// set.
+    pub fn current_fp_rate(&self) -> f64 {
+        self.state.lock().current_fp_rate()
+    }
+
+    /// Returns a binary representation of the filter.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a BloomFilter from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let filter = BloomFilter::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_filter(filter))
+    }
+
+    /// Returns a dict representation of the filter.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let filter = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("capacity", filter.capacity)?;
+        dict.set_item("fp_rate", filter.fp_rate)?;
+        dict.set_item("n_bits", filter.n_bits)?;
+        dict.set_item("n_hashes", filter.n_hashes)?;
+        dict.set_item("words", &filter.words)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a BloomFilter from a dict.
+    #[staticmethod]
+    pub fn from_dict(filter_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            filter_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let filter = BloomFilter::from_parts(
+            get("capacity")?.extract()?,
+            get("fp_rate")?.extract()?,
+            get("n_bits")?.extract()?,
+            get("n_hashes")?.extract()?,
+            get("words")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent BloomFilter bits.")
+        })?;
+        Ok(Self::from_filter(filter))
+    }
+
+    /// BloomFilter.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_filter(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(filter) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(filter) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via BloomFilter.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: `item in filter` checks whether an item may have been
+    /// added.
+    pub fn __contains__(&self, item: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let hash = hash_value(item, SEED)?;
+        Ok(self.state.lock().contains_hash(hash))
+    }
+
+    /// Magic method: repr/str(BloomFilter) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let filter = self.state.lock();
+        format!(
+            "BloomFilter(capacity={}, fp_rate={})",
+            filter.capacity, filter.fp_rate
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: bf1 | bf2 returns bf1.union(bf2).
+    pub fn __or__(&self, other: &Self) -> PyResult<Self> {
+        self.union(other)
+    }
+
+    /// Magic method: bf1 |= bf2 unites bf2 with bf1.
+    pub fn __ior__(&self, other: &Self) -> PyResult<()> {
+        self.union_inplace(other)
+    }
+}
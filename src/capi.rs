@@ -0,0 +1,321 @@
+//! C API, compiled with the `capi` feature
+//!
+//! A `FastDigest` handle owns a digest plus a buffer for single updates,
+//! like the Python class. The functions report errors with the
+//! `FASTDIGEST_*` status codes; queries return NaN instead. The binary
+//! format of `fastdigest_to_bytes` is the one of `TDigest.to_bytes` in
+//! Python, so digests can be exchanged between both. The declarations for
+//! C are in `include/fastdigest.h` (generated with cbindgen).
+//!
+//! A handle must not be used from several threads at the same time.
+
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_PLATFORM_MAX};
+use ordered_float::OrderedFloat;
+use std::collections::TryReserveError;
+use std::ffi::c_int;
+use std::{iter, ptr, slice};
+
+/// The call succeeded.
+pub const FASTDIGEST_OK: c_int = 0;
+/// A pointer was null, or a value, weight or size was out of range.
+pub const FASTDIGEST_ERR_ARGUMENT: c_int = -1;
+/// Memory could not be allocated.
+pub const FASTDIGEST_ERR_MEMORY: c_int = -2;
+/// The bytes are not a valid digest for this platform and version.
+pub const FASTDIGEST_ERR_DATA: c_int = -3;
+
+const BUFFER_SIZE: usize = 256;
+
+/// Opaque handle to a digest.
+#[derive(Clone)]
+pub struct FastDigest {
+    digest: TDigest,
+    buffer: Vec<(OrderedFloat<f64>, f64)>,
+}
+
+impl FastDigest {
+    fn wrap(digest: TDigest) -> Result<Self, TryReserveError> {
+        let mut buffer = Vec::new();
+        buffer.try_reserve_exact(BUFFER_SIZE)?;
+        Ok(Self { digest, buffer })
+    }
+
+    fn flush(&mut self) -> Result<(), TryReserveError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        TDigest::sort_pairs(&mut self.buffer, 1)?;
+        self.digest.merge_sorted_weighted_inplace(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn push(&mut self, x: f64, w: f64) -> Result<(), TryReserveError> {
+        if self.buffer.len() == BUFFER_SIZE {
+            self.flush()?;
+        }
+        self.buffer.push((OrderedFloat(x), w));
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), TryReserveError> {
+        self.flush()?;
+        self.digest.merge_digest_inplace(&other.digest)?;
+        for &(x, w) in &other.buffer {
+            self.push(x.into_inner(), w)?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn status(result: Result<(), TryReserveError>) -> c_int {
+    match result {
+        Ok(()) => FASTDIGEST_OK,
+        Err(_) => FASTDIGEST_ERR_MEMORY,
+    }
+}
+
+#[inline]
+fn valid_pair(x: f64, w: f64) -> bool {
+    x.is_finite() && w.is_finite() && w > 0.0
+}
+
+/// Creates an empty digest with up to `max_centroids` centroids (1000 in
+/// Python by default). Returns null if it exceeds the platform limit or
+/// memory could not be allocated.
+#[no_mangle]
+pub extern "C" fn fastdigest_new(max_centroids: usize) -> *mut FastDigest {
+    if max_centroids > TD_SIZE_PLATFORM_MAX {
+        return ptr::null_mut();
+    }
+    match TDigest::new_with_size(max_centroids).and_then(FastDigest::wrap) {
+        Ok(digest) => Box::into_raw(Box::new(digest)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a digest. Null is ignored.
+///
+/// # Safety
+///
+/// `digest` must be null or a handle that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_free(digest: *mut FastDigest) {
+    if !digest.is_null() {
+        drop(Box::from_raw(digest));
+    }
+}
+
+/// Adds a value `x` with weight `w` (1 for an unweighted value).
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_update(
+    digest: *mut FastDigest,
+    x: f64,
+    w: f64,
+) -> c_int {
+    let Some(digest) = digest.as_mut() else {
+        return FASTDIGEST_ERR_ARGUMENT;
+    };
+    if !valid_pair(x, w) {
+        return FASTDIGEST_ERR_ARGUMENT;
+    }
+    status(digest.push(x, w))
+}
+
+/// Adds `n` values from the array `x`. If `w` is not null, it holds the
+/// weights of the values. Nothing is added if any of them is invalid.
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle, and `x` (and `w`, if not null)
+/// must point to `n` doubles.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_update_batch(
+    digest: *mut FastDigest,
+    x: *const f64,
+    w: *const f64,
+    n: usize,
+) -> c_int {
+    let Some(digest) = digest.as_mut() else {
+        return FASTDIGEST_ERR_ARGUMENT;
+    };
+    if n == 0 {
+        return FASTDIGEST_OK;
+    }
+    if x.is_null() {
+        return FASTDIGEST_ERR_ARGUMENT;
+    }
+    let x = slice::from_raw_parts(x, n);
+    let result = if w.is_null() {
+        if !x.iter().all(|x| x.is_finite()) {
+            return FASTDIGEST_ERR_ARGUMENT;
+        }
+        TDigest::collect_pairs(x.iter().copied(), iter::repeat(1.0))
+    } else {
+        let w = slice::from_raw_parts(w, n);
+        if !x.iter().zip(w).all(|(&x, &w)| valid_pair(x, w)) {
+            return FASTDIGEST_ERR_ARGUMENT;
+        }
+        TDigest::collect_pairs(x.iter().copied(), w.iter().copied())
+    };
+    status(result.and_then(|mut pairs| {
+        digest.flush()?;
+        TDigest::sort_pairs(&mut pairs, 1)?;
+        digest.digest.merge_sorted_weighted_inplace(&pairs)
+    }))
+}
+
+/// Merges `other` into `digest`, keeping the `max_centroids` of `digest`.
+///
+/// # Safety
+///
+/// `digest` and `other` must be null or valid handles (they may be the
+/// same).
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_merge(
+    digest: *mut FastDigest,
+    other: *const FastDigest,
+) -> c_int {
+    if digest.is_null() || other.is_null() {
+        return FASTDIGEST_ERR_ARGUMENT;
+    }
+    if ptr::eq(digest, other) {
+        let copy = (*digest).clone();
+        return status((*digest).merge(&copy));
+    }
+    status((*digest).merge(&*other))
+}
+
+/// Estimates the quantile for a cumulative probability `q` between 0 and
+/// 1. Returns NaN if the digest is empty or `q` is out of range.
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_quantile(
+    digest: *mut FastDigest,
+    q: f64,
+) -> f64 {
+    let Some(digest) = digest.as_mut() else {
+        return f64::NAN;
+    };
+    if !(0.0..=1.0).contains(&q) || digest.flush().is_err() {
+        return f64::NAN;
+    }
+    if digest.digest.is_empty() {
+        return f64::NAN;
+    }
+    digest.digest.estimate_quantile(q)
+}
+
+/// Estimates the rank (cumulative probability) of a value `x`. Returns NaN
+/// if the digest is empty.
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_cdf(
+    digest: *mut FastDigest,
+    x: f64,
+) -> f64 {
+    let Some(digest) = digest.as_mut() else {
+        return f64::NAN;
+    };
+    if digest.flush().is_err() || digest.digest.is_empty() {
+        return f64::NAN;
+    }
+    digest.digest.estimate_rank(x)
+}
+
+/// Number of values added to the digest (0 for null).
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_count(digest: *const FastDigest) -> u64 {
+    let Some(digest) = digest.as_ref() else {
+        return 0;
+    };
+    let count = digest.digest.count() + digest.buffer.len() as u128;
+    u64::try_from(count).unwrap_or(u64::MAX)
+}
+
+/// Serializes the digest into a new buffer, whose address and length are
+/// written to `out` and `out_len`. The buffer must be freed with
+/// `fastdigest_bytes_free`.
+///
+/// # Safety
+///
+/// `digest` must be null or a valid handle, and `out` and `out_len` must
+/// be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_to_bytes(
+    digest: *mut FastDigest,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    let Some(digest) = digest.as_mut() else {
+        return FASTDIGEST_ERR_ARGUMENT;
+    };
+    if out.is_null() || out_len.is_null() {
+        return FASTDIGEST_ERR_ARGUMENT;
+    }
+    let bytes = match digest.flush().and_then(|()| digest.digest.to_bytes()) {
+        Ok(bytes) => bytes.into_boxed_slice(),
+        Err(_) => return FASTDIGEST_ERR_MEMORY,
+    };
+    *out_len = bytes.len();
+    *out = Box::into_raw(bytes).cast::<u8>();
+    FASTDIGEST_OK
+}
+
+/// Frees a buffer returned by `fastdigest_to_bytes`. Null is ignored.
+///
+/// # Safety
+///
+/// `bytes` must be null or a buffer from `fastdigest_to_bytes` that has not
+/// been freed yet, with the length returned along with it.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_bytes_free(bytes: *mut u8, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(bytes, len)));
+    }
+}
+
+/// Loads a digest from `len` bytes in the format of `fastdigest_to_bytes`
+/// (or `TDigest.to_bytes` in Python) and writes the new handle to `out`.
+///
+/// # Safety
+///
+/// `data` must be null or point to `len` bytes, and `out` must be null or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn fastdigest_from_bytes(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut FastDigest,
+) -> c_int {
+    if data.is_null() || out.is_null() {
+        return FASTDIGEST_ERR_ARGUMENT;
+    }
+    let bytes = slice::from_raw_parts(data, len);
+    let digest = TDigest::from_bytes(bytes).and_then(|digest| {
+        FastDigest::wrap(digest).map_err(BytesError::MemError)
+    });
+    match digest {
+        Ok(digest) => {
+            *out = Box::into_raw(Box::new(digest));
+            FASTDIGEST_OK
+        }
+        Err(BytesError::MemError(_)) => FASTDIGEST_ERR_MEMORY,
+        Err(_) => FASTDIGEST_ERR_DATA,
+    }
+}
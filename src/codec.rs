@@ -0,0 +1,246 @@
+//! Little-endian binary encoding shared by the sketch classes
+//!
+//! Like the TDigest format, each format starts with an 8-byte magic and a
+//! u32 version. Sequences are prefixed with their length as u64.
+//! Compact formats can write integers as LEB128 varints instead.
+
+use crate::tdigest::BytesError;
+use std::borrow::Cow;
+
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn new(magic: &[u8; 8], version: u32) -> Self {
+        let mut writer = Self(Vec::new());
+        writer.0.extend_from_slice(magic);
+        writer.u32(version);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes `value` in 7-bit groups, least significant first.
+    pub fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f64s(&mut self, values: &[f64]) {
+        self.u64(values.len() as u64);
+        for &value in values {
+            self.f64(value);
+        }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    /// Number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Takes the bytes written so far, to continue with an empty buffer.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0)
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Encoded data read in order, from a slice (`Reader`) or from a stream
+/// (`stream::FileReader`), which can also fail with its own errors.
+pub trait Input {
+    type Error: From<BytesError>;
+
+    /// Reads the next `N` bytes.
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Self::Error>;
+
+    /// Reads a sequence length of items of at least `item_size` bytes.
+    fn seq_len(&mut self, item_size: usize) -> Result<usize, Self::Error>;
+
+    /// Reads a byte sequence prefixed with its length.
+    fn bytes(&mut self) -> Result<Cow<'_, [u8]>, Self::Error>;
+
+    fn u8(&mut self) -> Result<u8, Self::Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, Self::Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, Self::Error> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the magic and version at the beginning of `bytes`.
+    pub fn new(
+        bytes: &'a [u8],
+        magic: &[u8; 8],
+        version: u32,
+    ) -> Result<Self, BytesError> {
+        if bytes.is_empty() {
+            return Err(BytesError::EmptyData);
+        }
+        if bytes.len() < 12 || bytes[..8] != magic[..] {
+            return Err(BytesError::WrongFormat);
+        }
+        let mut reader = Self { bytes, offset: 8 };
+        if reader.u32()? != version {
+            return Err(BytesError::WrongVersion);
+        }
+        Ok(reader)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], BytesError> {
+        let end = self.offset.checked_add(N).ok_or(BytesError::CorruptData)?;
+        let slice = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or(BytesError::CorruptData)?;
+        self.offset = end;
+        Ok(slice.try_into().expect("slice of length N"))
+    }
+
+    pub fn u8(&mut self) -> Result<u8, BytesError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, BytesError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, BytesError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, BytesError> {
+        Ok(i64::from_le_bytes(self.take()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, BytesError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    pub fn varint(&mut self) -> Result<u64, BytesError> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            let bits = u64::from(byte & 0x7f);
+            if shift == 63 && bits > 1 {
+                return Err(BytesError::CorruptData);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BytesError::CorruptData)
+    }
+
+    /// Reads a sequence length, checking that the remaining data can hold
+    /// that many items of `item_size` bytes.
+    pub fn seq_len(&mut self, item_size: usize) -> Result<usize, BytesError> {
+        let len = self.u64()?;
+        self.check_len(len, item_size)
+    }
+
+    /// Like `seq_len`, for a length written as a varint.
+    pub fn varint_len(
+        &mut self,
+        item_size: usize,
+    ) -> Result<usize, BytesError> {
+        let len = self.varint()?;
+        self.check_len(len, item_size)
+    }
+
+    fn check_len(
+        &self,
+        len: u64,
+        item_size: usize,
+    ) -> Result<usize, BytesError> {
+        let len = usize::try_from(len).map_err(|_| BytesError::CorruptData)?;
+        let remaining = self.bytes.len() - self.offset;
+        if len > remaining / item_size {
+            return Err(BytesError::CorruptData);
+        }
+        Ok(len)
+    }
+
+    pub fn f64s(&mut self) -> Result<Vec<f64>, BytesError> {
+        let len = self.seq_len(8)?;
+        let mut values: Vec<f64> = Vec::new();
+        values
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            values.push(self.f64()?);
+        }
+        Ok(values)
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], BytesError> {
+        let len = self.seq_len(1)?;
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    /// Checks that all data has been read.
+    pub fn finish(self) -> Result<(), BytesError> {
+        if self.offset == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(BytesError::CorruptData)
+        }
+    }
+}
+
+impl Input for Reader<'_> {
+    type Error = BytesError;
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BytesError> {
+        self.take()
+    }
+
+    fn seq_len(&mut self, item_size: usize) -> Result<usize, BytesError> {
+        Reader::seq_len(self, item_size)
+    }
+
+    fn bytes(&mut self) -> Result<Cow<'_, [u8]>, BytesError> {
+        Ok(Cow::Borrowed(Reader::bytes(self)?))
+    }
+}
@@ -0,0 +1,125 @@
+//! Compact binary format of a TDigest, for network transmission
+//!
+//! Means are quantized to f32 and written as the varint differences of
+//! their order-preserving bit patterns, which are small for neighbouring
+//! centroids. Integer weights (as from unweighted updates) are written as
+//! varints, other weights as f64. The totals are kept exactly. Decoded
+//! means are clamped to the exact min and max, so that encoding a decoded
+//! digest again gives the same bytes.
+
+use crate::codec::{Reader, Writer};
+use crate::tdigest::{BytesError, Centroid, TDigest};
+
+const MAGIC: [u8; 8] = *b"FASTDCP~";
+const VERSION: u32 = 1;
+
+const WEIGHTS_VARINT: u8 = 0;
+const WEIGHTS_F64: u8 = 1;
+
+/// Largest weight that counts as an integer: all integers up to it are
+/// exact in f64.
+const MAX_INT_WEIGHT: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+
+/// Maps an f32 to a u32 in the same order (for non-NaN values).
+fn key(x: f32) -> u32 {
+    let bits = x.to_bits();
+    if bits >> 31 == 1 {
+        !bits
+    } else {
+        bits | 1 << 31
+    }
+}
+
+fn from_key(key: u32) -> f32 {
+    if key >> 31 == 1 {
+        f32::from_bits(key & !(1 << 31))
+    } else {
+        f32::from_bits(!key)
+    }
+}
+
+fn is_int_weight(weight: f64) -> bool {
+    (0.0..=MAX_INT_WEIGHT).contains(&weight) && weight.fract() == 0.0
+}
+
+/// Whether `bytes` are in the compact format (as opposed to the one of
+/// `TDigest::to_bytes`).
+pub fn is_compact(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+pub fn encode(digest: &TDigest) -> Vec<u8> {
+    let centroids = digest.centroids();
+    let mut writer = Writer::new(&MAGIC, VERSION);
+    writer.varint(digest.max_size() as u64);
+    writer.varint(digest.count() as u64);
+    writer.varint((digest.count() >> 64) as u64);
+    writer.f64(digest.mass());
+    writer.f64(digest.sum());
+    writer.f64(digest.min());
+    writer.f64(digest.max());
+    writer.varint(centroids.len() as u64);
+    let mut prev: u32 = 0;
+    for c in centroids {
+        let key = key(c.mean() as f32);
+        writer.varint(u64::from(key.wrapping_sub(prev)));
+        prev = key;
+    }
+    if centroids.iter().all(|c| is_int_weight(c.weight())) {
+        writer.u8(WEIGHTS_VARINT);
+        for c in centroids {
+            writer.varint(c.weight() as u64);
+        }
+    } else {
+        writer.u8(WEIGHTS_F64);
+        for c in centroids {
+            writer.f64(c.weight());
+        }
+    }
+    writer.finish()
+}
+
+pub fn decode(bytes: &[u8]) -> Result<TDigest, BytesError> {
+    let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+    let max_size =
+        usize::try_from(reader.varint()?).map_err(|_| BytesError::WrongArch)?;
+    let count =
+        u128::from(reader.varint()?) | u128::from(reader.varint()?) << 64;
+    let mass = reader.f64()?;
+    let sum = reader.f64()?;
+    let min = reader.f64()?;
+    let max = reader.f64()?;
+    // at least one byte for the mean and the weight each
+    let len = reader.varint_len(2)?;
+    let mut means: Vec<f64> = Vec::new();
+    means.try_reserve_exact(len).map_err(BytesError::MemError)?;
+    let mut key: u32 = 0;
+    for _ in 0..len {
+        let diff = u32::try_from(reader.varint()?)
+            .map_err(|_| BytesError::CorruptData)?;
+        key = key.wrapping_add(diff);
+        means.push(f64::from(from_key(key)).max(min).min(max));
+    }
+    let mut centroids: Vec<Centroid> = Vec::new();
+    centroids
+        .try_reserve_exact(len)
+        .map_err(BytesError::MemError)?;
+    match reader.u8()? {
+        WEIGHTS_VARINT => {
+            for mean in means {
+                let weight = reader.varint()? as f64;
+                centroids.push(Centroid::new(mean, weight));
+            }
+        }
+        WEIGHTS_F64 => {
+            for mean in means {
+                centroids.push(Centroid::new(mean, reader.f64()?));
+            }
+        }
+        _ => return Err(BytesError::CorruptData),
+    }
+    reader.finish()?;
+    Ok(TDigest::from_state(
+        centroids, max_size, mass, sum, min, max, count,
+    ))
+}
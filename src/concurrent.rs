@@ -0,0 +1,144 @@
+//! Thread-safe digest that spreads concurrent updates over several shards
+//!
+//! Each thread is assigned one of the shards (round-robin on first use), so
+//! threads updating at the same time rarely contend for the same lock. A
+//! snapshot locks all shards at once and merges them into a regular TDigest.
+
+use crate::tdigest::TDigest;
+use crate::{
+    extract_values, lock_and_flush, lock_state, malloc_error,
+    record_observation, validate_max_centroids, validate_value,
+    validate_weight, PyTDigest, Sorting, TDigestState, TD_SIZE_DEFAULT,
+};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD_HINT: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+#[pyclass(name = "ConcurrentTDigest", module = "fastdigest", frozen)]
+pub struct PyConcurrentTDigest {
+    shards: Box<[PyTDigest]>,
+    max_size: usize,
+}
+
+#[pymethods]
+impl PyConcurrentTDigest {
+    /// Constructs a new empty ConcurrentTDigest instance.
+    #[new]
+    #[pyo3(signature = (max_centroids=TD_SIZE_DEFAULT as i64, n_shards=None))]
+    pub fn py_new(max_centroids: i64, n_shards: Option<i64>) -> PyResult<Self> {
+        let max_size = validate_max_centroids(max_centroids)?;
+        // one shard per core by default
+        let n_shards = match n_shards {
+            None => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            Some(n) => {
+                usize::try_from(n).ok().filter(|&n| n > 0).ok_or_else(|| {
+                    PyValueError::new_err(
+                        "n_shards must be a positive integer.",
+                    )
+                })?
+            }
+        };
+        let shards = (0..n_shards)
+            .map(|_| PyTDigest::new(max_centroids))
+            .collect::<PyResult<_>>()?;
+        Ok(Self { shards, max_size })
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.max_size
+    }
+
+    /// Getter property: returns the number of shards.
+    #[getter(n_shards)]
+    pub fn get_n_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> PyResult<u128> {
+        let mut n_values = 0;
+        for shard in self.shards.iter() {
+            let state = lock_state(shard)?;
+            n_values += state.digest.count() + state.n_buffered();
+        }
+        Ok(n_values)
+    }
+
+    /// Updates the digest (in-place) with a single float value.
+    #[inline]
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut state = lock_state(self.shard())?;
+        record_observation(&mut state, x, weight)?;
+        Ok(())
+    }
+
+    /// Updates the digest (in-place) with a sequence of float values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let (values, skip_nan) = extract_values(x)?;
+        self.shard()
+            .merge_values(x.py(), &values, w, skip_nan, Sorting::SERIAL)
+    }
+
+    /// Returns a consistent copy of the current state as a TDigest.
+    pub fn snapshot(&self, py: Python<'_>) -> PyResult<PyTDigest> {
+        py.detach(|| {
+            // all shards are locked (in order) before any of them is read
+            let states = self
+                .shards
+                .iter()
+                .map(lock_and_flush)
+                .collect::<PyResult<Vec<_>>>()?;
+            let digests: Vec<TDigest> =
+                states.iter().map(|state| state.digest.clone()).collect();
+            drop(states);
+            let digest = TDigest::merge_digests_parallel(
+                digests,
+                Some(self.max_size),
+                1,
+            )
+            .map_err(malloc_error)?;
+            Ok(PyTDigest {
+                state: Mutex::new(TDigestState::with_digest(digest)),
+            })
+        })
+    }
+
+    /// Magic method: repr/str(ConcurrentTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ConcurrentTDigest(max_centroids={}, n_shards={})",
+            self.max_size,
+            self.shards.len()
+        )
+    }
+}
+
+impl PyConcurrentTDigest {
+    /// Shard assigned to the current thread.
+    #[inline]
+    fn shard(&self) -> &PyTDigest {
+        let hint = SHARD_HINT.with(|hint| *hint);
+        &self.shards[hint % self.shards.len()]
+    }
+}
@@ -0,0 +1,403 @@
+//! Count-Min sketch: estimating the frequencies of items
+//!
+//! A table of `depth` rows of `width` counters; each item increments one
+//! counter per row, chosen by a row-specific hash, and its frequency is
+//! estimated by the smallest of these counters (Cormode & Muthukrishnan,
+//! 2005). Estimates never fall below the true count and exceed it by more
+//! than `epsilon` times the total count only with probability `delta`,
+//! for a width of `ceil(e / epsilon)` and a depth of `ceil(ln(1 / delta))`.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::hash::{hash_value, hash_values};
+use crate::tdigest::BytesError;
+use crate::{bytes_error, malloc_error};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTCMS~";
+const VERSION: u32 = 1;
+const SEED: u64 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    /// Row-major table of `depth * width` counters
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> Result<Self, TryReserveError> {
+        let mut counters = Vec::new();
+        // overflows are reported as a capacity overflow
+        counters.try_reserve_exact(width.saturating_mul(depth))?;
+        counters.resize(width * depth, 0);
+        Ok(Self {
+            width,
+            depth,
+            counters,
+            total: 0,
+        })
+    }
+
+    /// Dimensions for the error bound `epsilon` with probability `delta`.
+    pub fn dimensions(epsilon: f64, delta: f64) -> (usize, usize) {
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        (width, depth)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Smallest `epsilon` guaranteed by the width.
+    pub fn epsilon(&self) -> f64 {
+        std::f64::consts::E / self.width as f64
+    }
+
+    /// Failure probability of the error bound for the depth.
+    pub fn delta(&self) -> f64 {
+        (-(self.depth as f64)).exp()
+    }
+
+    pub fn add_hash(&mut self, hash: u64, count: u64) {
+        for row in 0..self.depth {
+            let cell = row * self.width + column(hash, row, self.width);
+            self.counters[cell] = self.counters[cell].saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+
+    pub fn estimate_hash(&self, hash: u64) -> u64 {
+        self.counters
+            .chunks(self.width)
+            .enumerate()
+            .map(|(row, counters)| counters[column(hash, row, self.width)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (counter, &count) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(count);
+        }
+        self.total = self.total.saturating_add(other.total);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.width as u64);
+        writer.u64(self.depth as u64);
+        writer.u64(self.total);
+        for &counter in &self.counters {
+            writer.u64(counter);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let width = reader.u64()? as usize;
+        let depth = reader.u64()? as usize;
+        let total = reader.u64()?;
+        let len = width.checked_mul(depth).ok_or(BytesError::CorruptData)?;
+        let counters =
+            (0..len).map(|_| reader.u64()).collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Self::from_parts(width, depth, counters, total)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent: each row
+    /// sums up to the total count, unless the counters saturated.
+    fn from_parts(
+        width: usize,
+        depth: usize,
+        counters: Vec<u64>,
+        total: u64,
+    ) -> Option<Self> {
+        let valid = width > 0
+            && depth > 0
+            && counters.len() == width * depth
+            && (total == u64::MAX
+                || counters.chunks(width).all(|row| {
+                    row.iter().try_fold(0u64, |sum, &c| sum.checked_add(c))
+                        == Some(total)
+                }));
+        valid.then_some(Self {
+            width,
+            depth,
+            counters,
+            total,
+        })
+    }
+}
+
+/// Column of an item hash in a row: the hash is remixed with the SplitMix64
+/// finalizer, offset by the row, and mapped to `0..width` by multiplication.
+#[inline]
+fn column(hash: u64, row: usize, width: usize) -> usize {
+    let offset = (row as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let mut z = hash.wrapping_add(offset);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    ((u128::from(z) * width as u128) >> 64) as usize
+}
+
+#[pyclass(name = "CountMinSketch", module = "fastdigest", frozen)]
+pub struct PyCountMinSketch {
+    state: Mutex<CountMinSketch>,
+}
+
+impl PyCountMinSketch {
+    fn from_sketch(sketch: CountMinSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+}
+
+/// Validates an item count, which must be positive.
+#[inline]
+fn validate_count(count: i64) -> PyResult<u64> {
+    u64::try_from(count)
+        .ok()
+        .filter(|&c| c > 0)
+        .ok_or_else(|| PyValueError::new_err("count must be positive."))
+}
+
+#[pymethods]
+impl PyCountMinSketch {
+    /// Constructs a new empty CountMinSketch instance whose estimates
+    /// exceed the true counts by at most `epsilon` times the total count,
+    /// except with probability `delta`.
+    #[new]
+    #[pyo3(signature = (epsilon=0.001, delta=0.01))]
+    pub fn py_new(epsilon: f64, delta: f64) -> PyResult<Self> {
+        if !(epsilon > 0.0 && epsilon < 1.0) {
+            return Err(PyValueError::new_err(
+                "epsilon must be between 0 and 1 (exclusive).",
+            ));
+        }
+        if !(delta > 0.0 && delta < 1.0) {
+            return Err(PyValueError::new_err(
+                "delta must be between 0 and 1 (exclusive).",
+            ));
+        }
+        let (width, depth) = CountMinSketch::dimensions(epsilon, delta);
+        let sketch = CountMinSketch::new(width, depth).map_err(malloc_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Getter property: returns the number of counters per row.
+    #[getter(width)]
+    pub fn get_width(&self) -> usize {
+        self.state.lock().width
+    }
+
+    /// Getter property: returns the number of rows.
+    #[getter(depth)]
+    pub fn get_depth(&self) -> usize {
+        self.state.lock().depth
+    }
+
+    /// Getter property: returns the relative error bound of the width.
+    #[getter(epsilon)]
+    pub fn get_epsilon(&self) -> f64 {
+        self.state.lock().epsilon()
+    }
+
+    /// Getter property: returns the failure probability of the depth.
+    #[getter(delta)]
+    pub fn get_delta(&self) -> f64 {
+        self.state.lock().delta()
+    }
+
+    /// Getter property: returns the total count of all items.
+    #[getter(total)]
+    pub fn get_total(&self) -> u64 {
+        self.state.lock().total
+    }
+
+    /// Adds `count` occurrences of a hashable item (in-place).
+    #[pyo3(signature = (item, count=1))]
+    pub fn update(&self, item: &Bound<'_, PyAny>, count: i64) -> PyResult<()> {
+        let count = validate_count(count)?;
+        let hash = hash_value(item, SEED)?;
+        self.state.lock().add_hash(hash, count);
+        Ok(())
+    }
+
+    /// Adds one occurrence of each item of an iterable or array (in-place).
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hashes = hash_values(x, SEED)?;
+        let mut sketch = self.state.lock();
+        for hash in hashes {
+            sketch.add_hash(hash, 1);
+        }
+        Ok(())
+    }
+
+    /// Returns the estimated count of a hashable item, an upper bound of
+    /// the true count.
+    pub fn estimate(&self, item: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let hash = hash_value(item, SEED)?;
+        Ok(self.state.lock().estimate_hash(hash))
+    }
+
+    /// Returns the estimated counts of several items.
+    pub fn estimate_vec(&self, x: &Bound<'_, PyAny>) -> PyResult<Vec<u64>> {
+        let hashes = hash_values(x, SEED)?;
+        let sketch = self.state.lock();
+        Ok(hashes
+            .into_iter()
+            .map(|h| sketch.estimate_hash(h))
+            .collect())
+    }
+
+    /// Merges this sketch with another, returning a new CountMinSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        if (sketch.width, sketch.depth) != (other.width, other.depth) {
+            return Err(PyValueError::new_err(
+                "Cannot merge CountMinSketches with different dimensions.",
+            ));
+        }
+        sketch.merge(&other);
+        Ok(())
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a CountMinSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = CountMinSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("width", sketch.width)?;
+        dict.set_item("depth", sketch.depth)?;
+        dict.set_item("total", sketch.total)?;
+        dict.set_item(
+            "counters",
+            sketch.counters.chunks(sketch.width).collect::<Vec<_>>(),
+        )?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a CountMinSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let width: usize = get("width")?.extract()?;
+        let depth: usize = get("depth")?.extract()?;
+        let rows: Vec<Vec<u64>> = get("counters")?.extract()?;
+        let total = get("total")?.extract()?;
+        let sketch = rows
+            .iter()
+            .all(|row| row.len() == width)
+            .then(|| {
+                CountMinSketch::from_parts(width, depth, rows.concat(), total)
+            })
+            .flatten()
+            .ok_or_else(|| {
+                SerializationError::new_err(
+                    "Inconsistent CountMinSketch counters.",
+                )
+            })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// CountMinSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via CountMinSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(CountMinSketch) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let sketch = self.state.lock();
+        format!(
+            "CountMinSketch(width={}, depth={})",
+            sketch.width, sketch.depth
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
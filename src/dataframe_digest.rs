@@ -0,0 +1,336 @@
+//! One digest per column of a table, updated a whole table at a time
+//!
+//! Columns are keyed by name (or by position for 2D arrays) in the order
+//! in which they were first seen, in a `DigestMap`. Each update reads every
+//! column once, through the Arrow C data interface or NumPy, and merges it
+//! into the digest of its column in a single batch.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, DigestMap, Pairs, PyTDigestMap};
+use crate::errors::InvalidQuantileError;
+use crate::hash::Value;
+use crate::input::{FloatValues, Matrix};
+use crate::tdigest::{TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, malloc_error, validate_max_centroids, validate_values,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+
+const MAGIC: [u8; 8] = *b"FASTDFD~";
+const VERSION: u32 = 1;
+
+/// Reads the numeric columns of a DataFrame (anything with `columns` that
+/// can be indexed by them) or a 2D array as (key, pairs) batches. Columns
+/// that cannot be read as numbers are skipped; missing values are left out.
+fn read_columns(df: &Bound<'_, PyAny>) -> PyResult<Vec<(Value, Pairs)>> {
+    let py = df.py();
+    let mut batches = Vec::new();
+    if df.hasattr("columns")? {
+        for name in df.getattr("columns")?.try_iter()? {
+            let name = name?;
+            let values = match FloatValues::from_series(&df.get_item(&name)?) {
+                Ok(values) => values,
+                Err(err)
+                    if err.is_instance_of::<PyTypeError>(py)
+                        || err.is_instance_of::<PyValueError>(py) =>
+                {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let pairs: Pairs = values
+                .iter(py)
+                .filter(|x| !x.is_nan())
+                .map(|x| (OrderedFloat(x), 1.0))
+                .collect();
+            batches.push((Value::extract(&name)?, pairs));
+        }
+    } else {
+        let matrix = Matrix::read(df)?;
+        batches = (0..matrix.n_cols)
+            .map(|j| (Value::Int(j as i64), Vec::new()))
+            .collect();
+        for (k, &x) in matrix.values.iter().enumerate() {
+            if !x.is_nan() {
+                batches[k % matrix.n_cols].1.push((OrderedFloat(x), 1.0));
+            }
+        }
+    }
+    for (_, pairs) in &batches {
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+    }
+    Ok(batches)
+}
+
+/// Formats a cumulative probability as a percentage label, like pandas.
+fn percent_label(q: f64) -> String {
+    format!("{}%", (q * 1e8).round() / 1e6)
+}
+
+/// The `describe()` values of a digest: count, mean, std, min, the
+/// quantiles `q` and max, with NaN for an empty digest.
+fn describe_digest(digest: &TDigest, q: &[f64]) -> PyResult<Vec<f64>> {
+    if digest.is_empty() {
+        let mut row = vec![0.0];
+        row.resize(q.len() + 5, f64::NAN);
+        return Ok(row);
+    }
+    let wrapped = wrap_digest(digest.clone());
+    let mut row = vec![
+        digest.count() as f64,
+        wrapped.mean()?.value,
+        wrapped.std()?,
+        wrapped.min()?.value,
+    ];
+    row.extend(q.iter().map(|&q_i| digest.estimate_quantile(q_i)));
+    row.push(wrapped.max()?.value);
+    Ok(row)
+}
+
+#[pyclass(name = "DataFrameDigest", module = "fastdigest", frozen)]
+pub struct PyDataFrameDigest {
+    state: Mutex<DigestMap>,
+}
+
+impl PyDataFrameDigest {
+    fn from_map(map: DigestMap) -> Self {
+        Self {
+            state: Mutex::new(map),
+        }
+    }
+
+    /// Locks the map after flushing all columns.
+    fn lock_flushed(&self) -> PyResult<MutexGuard<'_, DigestMap>> {
+        let mut map = self.state.lock();
+        map.flush().map_err(malloc_error)?;
+        Ok(map)
+    }
+}
+
+#[pymethods]
+impl PyDataFrameDigest {
+    /// Constructs a new DataFrameDigest instance without columns.
+    #[new]
+    #[pyo3(signature = (max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(max_centroids: i64) -> PyResult<Self> {
+        let max_size = validate_max_centroids(max_centroids)?;
+        Ok(Self::from_map(DigestMap::new(max_size)))
+    }
+
+    /// Constructs a new DataFrameDigest instance from a DataFrame or 2D
+    /// array.
+    #[staticmethod]
+    #[pyo3(signature = (df, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn from_dataframe(
+        df: &Bound<'_, PyAny>,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        let digest = Self::py_new(max_centroids)?;
+        digest.update(df)?;
+        Ok(digest)
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size()
+    }
+
+    /// Getter property: returns the column names (or positions) in the
+    /// order in which they were first seen.
+    #[getter(columns)]
+    pub fn get_columns<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let map = self.state.lock();
+        map.keys().map(|key| key.to_object(py)).collect()
+    }
+
+    /// Updates the digest of every numeric column (in-place) with the
+    /// values of a pandas or polars DataFrame, or of a 2D array (whose
+    /// columns are numbered).
+    pub fn update(&self, df: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = df.py();
+        let mut batches = read_columns(df)?;
+        py.detach(|| {
+            let mut map = self.state.lock();
+            for (key, pairs) in &mut batches {
+                map.add_batch(key, pairs).map_err(malloc_error)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns a table (a pandas DataFrame with one column per column) of
+    /// the count, mean, standard deviation, min, estimated quantiles `q`
+    /// and max of every column, like `DataFrame.describe()`.
+    #[pyo3(signature = (q=vec![0.25, 0.5, 0.75]))]
+    pub fn describe<'py>(
+        &self,
+        py: Python<'py>,
+        q: Vec<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let index = PyList::new(py, ["count", "mean", "std", "min"])?;
+        for &q_i in &q {
+            index.append(percent_label(q_i))?;
+        }
+        index.append("max")?;
+        let columns = PyDict::new(py);
+        let map = self.lock_flushed()?;
+        for (key, digest) in map.digests() {
+            let row = describe_digest(digest, &q)?;
+            columns.set_item(key.to_object(py)?, row)?;
+        }
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("index", index)?;
+        py.import("pandas")?
+            .getattr("DataFrame")?
+            .call((columns,), Some(&kwargs))
+    }
+
+    /// Merges this digest with another, returning a new DataFrameDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest into this one (in-place), column by column.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        self.state.lock().merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of all columns.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let map = self.lock_flushed()?.to_bytes().map_err(malloc_error)?;
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.bytes(&map);
+        Ok(PyBytes::new(py, &writer.finish()))
+    }
+
+    /// Reconstructs a DataFrameDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let read = || {
+            let mut reader = Reader::new(data, &MAGIC, VERSION)?;
+            let map = DigestMap::from_bytes(reader.bytes()?)?;
+            reader.finish()?;
+            Ok(map)
+        };
+        Ok(Self::from_map(read().map_err(bytes_error)?))
+    }
+
+    /// Returns a dict representation of all columns, in the format of
+    /// TDigestMap.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        PyTDigestMap::from_map(self.state.lock().clone()).to_dict(py)
+    }
+
+    /// Reconstructs a DataFrameDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(digest_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let map = PyTDigestMap::from_dict(digest_dict)?.into_map();
+        Ok(Self::from_map(map))
+    }
+
+    /// DataFrameDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_map(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via DataFrameDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(digest) returns the number of columns.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().len()
+    }
+
+    /// Magic method: `column in digest` checks for a digest of `column`.
+    pub fn __contains__(&self, column: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key = Value::extract(column)?;
+        Ok(self.state.lock().contains(&key))
+    }
+
+    /// Magic method: digest[column] returns a copy of the digest of
+    /// `column` as a TDigest.
+    pub fn __getitem__(
+        &self,
+        column: &Bound<'_, PyAny>,
+    ) -> PyResult<PyTDigest> {
+        let key = Value::extract(column)?;
+        let mut map = self.state.lock();
+        match map.digest(&key).map_err(malloc_error)? {
+            Some(digest) => Ok(wrap_digest(digest.clone())),
+            None => Err(PyKeyError::new_err(column.clone().unbind())),
+        }
+    }
+
+    /// Magic method: repr/str(DataFrameDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let map = self.state.lock();
+        format!(
+            "DataFrameDigest(n_columns={}, max_centroids={})",
+            map.len(),
+            map.max_size()
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        Ok(self.lock_flushed()?.same_digests(&other))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
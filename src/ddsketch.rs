@@ -0,0 +1,622 @@
+//! DDSketch: quantile sketch with a relative-error guarantee
+//!
+//! Values are counted in logarithmically sized buckets: with
+//! `gamma = (1 + a) / (1 - a)`, bucket `k` holds the values in
+//! `(gamma^(k-1), gamma^k]`, and every value in it is within the relative
+//! accuracy `a` of the bucket's representative `2 gamma^k / (gamma + 1)`
+//! (Masson, Rim & Lee, 2019). Positive and negative values are kept in
+//! separate stores; if a store would exceed `max_bins` buckets, its lowest
+//! buckets are collapsed, so that only values near zero lose accuracy.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::tdigest::BytesError;
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_value, validate_values,
+    validate_weight, validate_weights,
+};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTDDS~";
+const VERSION: u32 = 1;
+const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+const DEFAULT_MAX_BINS: usize = 2048;
+
+/// Dense bucket counts, starting at the key `offset`.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Store {
+    offset: i64,
+    counts: Vec<f64>,
+    total: f64,
+}
+
+impl Store {
+    fn add(
+        &mut self,
+        key: i64,
+        weight: f64,
+        max_bins: usize,
+    ) -> Result<(), TryReserveError> {
+        if self.counts.is_empty() {
+            self.counts.try_reserve(1)?;
+            self.counts.push(0.0);
+            self.offset = key;
+        }
+        let max_key = self.offset + self.counts.len() as i64 - 1;
+        let lowest_key = max_key - max_bins as i64 + 1;
+        let mut key = key;
+        if key < self.offset {
+            // values below the lowest bucket that fits are collapsed into it
+            key = key.max(lowest_key);
+            let n_new = (self.offset - key) as usize;
+            self.counts.try_reserve(n_new)?;
+            self.counts.splice(0..0, std::iter::repeat_n(0.0, n_new));
+            self.offset = key;
+        } else if key > max_key {
+            let n_new = (key - max_key) as usize;
+            self.counts.try_reserve(n_new)?;
+            self.counts.resize(self.counts.len() + n_new, 0.0);
+            if self.counts.len() > max_bins {
+                let excess = self.counts.len() - max_bins;
+                let collapsed: f64 = self.counts.drain(..excess).sum();
+                self.counts[0] += collapsed;
+                self.offset += excess as i64;
+            }
+        }
+        self.counts[(key - self.offset) as usize] += weight;
+        self.total += weight;
+        Ok(())
+    }
+
+    fn merge(
+        &mut self,
+        other: &Store,
+        max_bins: usize,
+    ) -> Result<(), TryReserveError> {
+        for (i, &count) in other.counts.iter().enumerate() {
+            if count > 0.0 {
+                self.add(other.offset + i as i64, count, max_bins)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Key of the bucket holding the value of rank `rank` (0-based).
+    fn key_at_rank(&self, rank: f64, lower: bool) -> i64 {
+        let mut cumulative = 0.0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if (lower && cumulative > rank)
+                || (!lower && cumulative >= rank + 1.0)
+            {
+                return self.offset + i as i64;
+            }
+        }
+        self.offset + self.counts.len() as i64 - 1
+    }
+
+    fn encode(&self, writer: &mut Writer) {
+        writer.i64(self.offset);
+        writer.f64s(&self.counts);
+    }
+
+    fn decode(
+        reader: &mut Reader<'_>,
+        max_bins: usize,
+    ) -> Result<Self, BytesError> {
+        let offset = reader.i64()?;
+        let counts = reader.f64s()?;
+        Self::from_parts(offset, counts, max_bins)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    fn from_parts(
+        offset: i64,
+        counts: Vec<f64>,
+        max_bins: usize,
+    ) -> Option<Self> {
+        let valid = counts.len() <= max_bins
+            && counts.iter().all(|c| c.is_finite() && *c >= 0.0)
+            && offset.checked_add(counts.len() as i64).is_some();
+        valid.then(|| Self {
+            offset,
+            total: counts.iter().sum(),
+            counts,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    /// ln(gamma)
+    gamma_ln: f64,
+    max_bins: usize,
+    positive: Store,
+    negative: Store,
+    zero_count: f64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl DDSketch {
+    pub fn new(relative_accuracy: f64, max_bins: usize) -> Self {
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            gamma_ln: gamma.ln(),
+            max_bins,
+            positive: Store::default(),
+            negative: Store::default(),
+            zero_count: 0.0,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    #[inline]
+    fn key(&self, x: f64) -> i64 {
+        (x.ln() / self.gamma_ln).ceil() as i64
+    }
+
+    #[inline]
+    fn value(&self, key: i64) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        (key as f64 * self.gamma_ln).exp() * 2.0 / (gamma + 1.0)
+    }
+
+    pub fn add(&mut self, x: f64, weight: f64) -> Result<(), TryReserveError> {
+        // subnormal magnitudes are counted as zero
+        if x >= f64::MIN_POSITIVE {
+            self.positive.add(self.key(x), weight, self.max_bins)?;
+        } else if x <= -f64::MIN_POSITIVE {
+            self.negative.add(self.key(-x), weight, self.max_bins)?;
+        } else {
+            self.zero_count += weight;
+        }
+        self.count += 1;
+        self.sum += x * weight;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        Ok(())
+    }
+
+    pub fn merge(&mut self, other: &DDSketch) -> Result<(), TryReserveError> {
+        self.positive.merge(&other.positive, self.max_bins)?;
+        self.negative.merge(&other.negative, self.max_bins)?;
+        self.zero_count += other.zero_count;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn mass(&self) -> f64 {
+        self.negative.total + self.zero_count + self.positive.total
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn quantile(&self, q: f64) -> f64 {
+        let rank = q * (self.mass() - 1.0).max(0.0);
+        let x = if rank < self.negative.total {
+            let reversed = self.negative.total - rank - 1.0;
+            -self.value(self.negative.key_at_rank(reversed, false))
+        } else if rank < self.negative.total + self.zero_count {
+            0.0
+        } else {
+            let rank = rank - self.negative.total - self.zero_count;
+            self.value(self.positive.key_at_rank(rank, true))
+        };
+        // not `clamp`, which panics unless `min <= max`
+        x.max(self.min).min(self.max)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.relative_accuracy);
+        writer.u64(self.max_bins as u64);
+        writer.u64(self.count);
+        writer.f64(self.sum);
+        writer.f64(self.min);
+        writer.f64(self.max);
+        writer.f64(self.zero_count);
+        self.positive.encode(&mut writer);
+        self.negative.encode(&mut writer);
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let relative_accuracy = reader.f64()?;
+        let max_bins = usize::try_from(reader.u64()?)
+            .map_err(|_| BytesError::CorruptData)?;
+        if !valid_relative_accuracy(relative_accuracy) || max_bins == 0 {
+            return Err(BytesError::CorruptData);
+        }
+        let mut sketch = Self::new(relative_accuracy, max_bins);
+        sketch.count = reader.u64()?;
+        sketch.sum = reader.f64()?;
+        sketch.min = reader.f64()?;
+        sketch.max = reader.f64()?;
+        sketch.zero_count = reader.f64()?;
+        sketch.positive = Store::decode(&mut reader, max_bins)?;
+        sketch.negative = Store::decode(&mut reader, max_bins)?;
+        reader.finish()?;
+        sketch.validate().ok_or(BytesError::CorruptData)
+    }
+
+    /// Checks that restored totals match the buckets: an empty sketch has
+    /// the initial totals, and otherwise `min <= max` are finite, with every
+    /// non-empty bucket (and the zero bucket) holding at least one value
+    /// between them.
+    fn validate(self) -> Option<Self> {
+        let n_buckets = [&self.positive, &self.negative]
+            .iter()
+            .flat_map(|store| &store.counts)
+            .filter(|&&count| count > 0.0)
+            .count() as u64
+            + u64::from(self.zero_count > 0.0);
+        let valid = self.zero_count.is_finite()
+            && self.zero_count >= 0.0
+            && if self.count == 0 {
+                n_buckets == 0
+                    && self.sum == 0.0
+                    && self.min == f64::INFINITY
+                    && self.max == f64::NEG_INFINITY
+            } else {
+                self.min.is_finite()
+                    && self.max.is_finite()
+                    && self.min <= self.max
+                    && self.mass().is_finite()
+                    && self.count >= n_buckets
+                    && n_buckets > 0
+                    && (self.positive.total == 0.0
+                        || self.max >= f64::MIN_POSITIVE)
+                    && (self.negative.total == 0.0
+                        || self.min <= -f64::MIN_POSITIVE)
+            };
+        valid.then_some(self)
+    }
+}
+
+#[inline]
+pub(crate) fn valid_relative_accuracy(relative_accuracy: f64) -> bool {
+    relative_accuracy > 0.0 && relative_accuracy < 1.0
+}
+
+#[pyclass(name = "DDSketch", module = "fastdigest", frozen)]
+pub struct PyDDSketch {
+    state: Mutex<DDSketch>,
+}
+
+impl PyDDSketch {
+    fn from_sketch(sketch: DDSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Locks the sketch, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<parking_lot::MutexGuard<'_, DDSketch>> {
+        let sketch = self.state.lock();
+        if sketch.is_empty() {
+            return Err(EmptyDigestError::new_err("DDSketch is empty."));
+        }
+        Ok(sketch)
+    }
+}
+
+#[pymethods]
+impl PyDDSketch {
+    /// Constructs a new empty DDSketch instance.
+    #[new]
+    #[pyo3(signature = (relative_accuracy=DEFAULT_RELATIVE_ACCURACY, max_bins=DEFAULT_MAX_BINS as i64))]
+    pub fn py_new(relative_accuracy: f64, max_bins: i64) -> PyResult<Self> {
+        if !valid_relative_accuracy(relative_accuracy) {
+            return Err(PyValueError::new_err(
+                "relative_accuracy must be between 0 and 1 (exclusive).",
+            ));
+        }
+        let max_bins = usize::try_from(max_bins)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                PyValueError::new_err("max_bins must be a positive integer.")
+            })?;
+        Ok(Self::from_sketch(DDSketch::new(
+            relative_accuracy,
+            max_bins,
+        )))
+    }
+
+    /// Getter property: returns the relative_accuracy parameter.
+    #[getter(relative_accuracy)]
+    pub fn get_relative_accuracy(&self) -> f64 {
+        self.state.lock().relative_accuracy
+    }
+
+    /// Getter property: returns the max_bins parameter.
+    #[getter(max_bins)]
+    pub fn get_max_bins(&self) -> usize {
+        self.state.lock().max_bins
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Getter property: returns the number of buckets in use.
+    #[getter(n_bins)]
+    pub fn get_n_bins(&self) -> usize {
+        let sketch = self.state.lock();
+        sketch.positive.counts.len() + sketch.negative.counts.len()
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        self.state.lock().add(x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let weights = validate_weights(w, values.len())?;
+        let mut sketch = self.state.lock();
+        match weights {
+            Some(weights) => {
+                for (x, w) in values.iter(py).zip(weights) {
+                    if keep(&x) {
+                        sketch.add(x, w).map_err(malloc_error)?;
+                    }
+                }
+            }
+            None => {
+                for x in values.iter(py).filter(keep) {
+                    sketch.add(x, 1.0).map_err(malloc_error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new DDSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        if sketch.relative_accuracy != other.relative_accuracy {
+            return Err(PyValueError::new_err(
+                "Cannot merge DDSketches with different relative_accuracy.",
+            ));
+        }
+        sketch.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(q))
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let sketch = self.lock_nonempty()?;
+        Ok(q.iter().map(|&q_i| sketch.quantile(q_i)).collect())
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.quantile(0.5))
+    }
+
+    /// Returns the sum of the data.
+    pub fn sum(&self) -> f64 {
+        self.state.lock().sum
+    }
+
+    /// Returns the mean of the data.
+    pub fn mean(&self) -> PyResult<f64> {
+        let sketch = self.lock_nonempty()?;
+        Ok(sketch.sum / sketch.mass())
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a DDSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = DDSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("relative_accuracy", sketch.relative_accuracy)?;
+        dict.set_item("max_bins", sketch.max_bins)?;
+        dict.set_item("n_values", sketch.count)?;
+        dict.set_item("sum", sketch.sum)?;
+        dict.set_item("min", sketch.min)?;
+        dict.set_item("max", sketch.max)?;
+        dict.set_item("zero_count", sketch.zero_count)?;
+        for (name, store) in [
+            ("positive", &sketch.positive),
+            ("negative", &sketch.negative),
+        ] {
+            let store_dict = PyDict::new(py);
+            store_dict.set_item("offset", store.offset)?;
+            store_dict.set_item("counts", &store.counts)?;
+            dict.set_item(name, store_dict)?;
+        }
+        Ok(dict)
+    }
+
+    /// Reconstructs a DDSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let relative_accuracy: f64 = get("relative_accuracy")?.extract()?;
+        let max_bins: i64 = get("max_bins")?.extract()?;
+        let template = Self::py_new(relative_accuracy, max_bins)?;
+        let mut sketch = template.state.into_inner();
+        sketch.count = get("n_values")?.extract()?;
+        sketch.sum = get("sum")?.extract()?;
+        sketch.min = get("min")?.extract()?;
+        sketch.max = get("max")?.extract()?;
+        sketch.zero_count = get("zero_count")?.extract()?;
+        for (name, store) in [
+            ("positive", &mut sketch.positive),
+            ("negative", &mut sketch.negative),
+        ] {
+            let store_dict = get(name)?;
+            let offset: i64 = store_dict.get_item("offset")?.extract()?;
+            let counts: Vec<f64> = store_dict.get_item("counts")?.extract()?;
+            *store = Store::from_parts(offset, counts, sketch.max_bins)
+                .ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "Invalid '{name}' bucket counts."
+                    ))
+                })?;
+        }
+        let sketch = sketch.validate().ok_or_else(|| {
+            SerializationError::new_err("Inconsistent DDSketch totals.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// DDSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via DDSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(DDSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        let sketch = self.state.lock();
+        format!(
+            "DDSketch(relative_accuracy={}, max_bins={})",
+            sketch.relative_accuracy, sketch.max_bins
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
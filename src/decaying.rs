@@ -0,0 +1,588 @@
+//! Digest whose weights decay exponentially with the age of the values
+//!
+//! Instead of shrinking all weights as time passes, a value at time `t` is
+//! added with its weight multiplied by `2^((t - landmark) / half_life)`
+//! (forward decay, Cormode et al., 2009). The relative weights are then the
+//! same as with backward decay, so quantiles, the CDF and the mean need no
+//! update at all, and dividing by `2^((now - landmark) / half_life)` gives
+//! the decayed weights at `now`. Before the factors of new values overflow,
+//! the digest is rescaled once and the landmark moved to the present.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTDTD~";
+const VERSION: u32 = 1;
+
+/// The landmark is moved once new values would get a factor above
+/// `2^RESCALE_EXPONENT`.
+const RESCALE_EXPONENT: f64 = 64.0;
+
+#[derive(Clone)]
+pub struct DecayingDigest {
+    half_life: f64,
+    /// Time at which the stored weights equal the decayed weights
+    landmark: Option<f64>,
+    now: Option<f64>,
+    entry: Entry,
+}
+
+impl DecayingDigest {
+    pub fn new(
+        half_life: f64,
+        max_size: usize,
+    ) -> Result<Self, TryReserveError> {
+        Ok(Self {
+            half_life,
+            landmark: None,
+            now: None,
+            entry: Entry::new(max_size)?,
+        })
+    }
+
+    /// The factor of the weights of values at `t` relative to `from`.
+    #[inline]
+    fn factor(&self, from: f64, t: f64) -> f64 {
+        ((t - from) / self.half_life).exp2()
+    }
+
+    /// Moves the landmark to `landmark`, rescaling the digest.
+    fn rescale(&mut self, landmark: f64) -> Result<(), TryReserveError> {
+        if let Some(old) = self.landmark {
+            self.entry.flush()?;
+            let factor = self.factor(landmark, old);
+            self.entry.digest.scale_weights(factor, 0.0);
+        }
+        self.landmark = Some(landmark);
+        Ok(())
+    }
+
+    /// Returns the landmark for values up to `latest`, moving it first if
+    /// their factors would become too large.
+    fn landmark_for(&mut self, latest: f64) -> Result<f64, TryReserveError> {
+        match self.landmark {
+            Some(landmark)
+                if (latest - landmark) / self.half_life <= RESCALE_EXPONENT =>
+            {
+                Ok(landmark)
+            }
+            _ => {
+                self.rescale(latest)?;
+                Ok(latest)
+            }
+        }
+    }
+
+    #[inline]
+    fn tick(&mut self, timestamp: f64) {
+        self.now = Some(self.now.map_or(timestamp, |now| now.max(timestamp)));
+    }
+
+    pub fn count(&self) -> u128 {
+        self.entry.count()
+    }
+
+    pub fn add(
+        &mut self,
+        timestamp: f64,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        let landmark = self.landmark_for(timestamp)?;
+        self.tick(timestamp);
+        let weight = weight * self.factor(landmark, timestamp);
+        // values too old to be represented have no weight left
+        if weight >= f64::MIN_POSITIVE {
+            self.entry.add(x, weight)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the `(value, weight)` pairs at the timestamps of the same
+    /// position, merging the digest once.
+    pub fn add_batch(
+        &mut self,
+        timestamps: &[f64],
+        pairs: &mut Pairs,
+    ) -> Result<(), TryReserveError> {
+        let latest = timestamps.iter().copied().fold(f64::NAN, f64::max);
+        if latest.is_nan() {
+            return Ok(());
+        }
+        let landmark = self.landmark_for(latest)?;
+        self.tick(latest);
+        for (pair, &t) in pairs.iter_mut().zip(timestamps) {
+            pair.1 *= self.factor(landmark, t);
+        }
+        pairs.retain(|pair| pair.1 >= f64::MIN_POSITIVE);
+        self.entry.add_batch(pairs)
+    }
+
+    /// The flushed digest with the weights decayed to `now`.
+    pub fn digest_at(&mut self, now: f64) -> Result<TDigest, TryReserveError> {
+        self.entry.flush()?;
+        let mut digest = self.entry.digest.clone();
+        if let Some(landmark) = self.landmark {
+            digest.scale_weights(self.factor(now, landmark), 0.0);
+        }
+        Ok(digest)
+    }
+
+    /// Merges `other` (with the same half-life) into this digest, moving
+    /// the landmark to the later of both.
+    pub fn merge(
+        &mut self,
+        other: &DecayingDigest,
+    ) -> Result<(), TryReserveError> {
+        let Some(other_landmark) = other.landmark else {
+            return Ok(());
+        };
+        if self.landmark.is_none() {
+            *self = other.clone();
+            return Ok(());
+        }
+        let mut other_entry = other.entry.clone();
+        other_entry.flush()?;
+        let landmark = match self.landmark {
+            Some(landmark) if landmark >= other_landmark => landmark,
+            _ => {
+                self.rescale(other_landmark)?;
+                other_landmark
+            }
+        };
+        let factor = self.factor(landmark, other_landmark);
+        other_entry.digest.scale_weights(factor, 0.0);
+        self.entry.merge(&other_entry)?;
+        if let Some(now) = other.now {
+            self.tick(now);
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        self.entry.flush()
+    }
+
+    /// Whether both hold equal state. Both must be flushed.
+    pub fn same_state(&self, other: &DecayingDigest) -> bool {
+        self.half_life == other.half_life
+            && self.landmark == other.landmark
+            && self.now == other.now
+            && self.entry.digest == other.entry.digest
+    }
+
+    /// Binary representation; the digest must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.half_life);
+        for time in [self.landmark, self.now] {
+            writer.u8(time.is_some() as u8);
+            writer.f64(time.unwrap_or(0.0));
+        }
+        writer.bytes(&self.entry.digest.to_bytes()?);
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let half_life = reader.f64()?;
+        let mut times = [None; 2];
+        for time in &mut times {
+            let has_time = reader.u8()?;
+            let t = reader.f64()?;
+            *time = match has_time {
+                0 => None,
+                1 => Some(t),
+                _ => return Err(BytesError::CorruptData),
+            };
+        }
+        let digest = TDigest::from_bytes(reader.bytes()?)?;
+        reader.finish()?;
+        let [landmark, now] = times;
+        Self::from_parts(half_life, landmark, now, digest)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Returns None unless the landmark and clock are set (and finite)
+    /// exactly if the digest has seen values.
+    fn from_parts(
+        half_life: f64,
+        landmark: Option<f64>,
+        now: Option<f64>,
+        digest: TDigest,
+    ) -> Option<Self> {
+        let valid = valid_half_life(half_life)
+            && match (landmark, now) {
+                (Some(landmark), Some(now)) => {
+                    landmark.is_finite() && now.is_finite()
+                }
+                (None, None) => digest.is_empty(),
+                _ => false,
+            };
+        valid.then(|| Self {
+            half_life,
+            landmark,
+            now,
+            entry: Entry::from_digest(digest),
+        })
+    }
+}
+
+#[inline]
+fn valid_half_life(half_life: f64) -> bool {
+    half_life.is_finite() && half_life > 0.0
+}
+
+#[inline]
+fn validate_timestamp(t: f64) -> PyResult<f64> {
+    if !t.is_finite() {
+        return Err(PyValueError::new_err("Timestamps must be finite."));
+    }
+    Ok(t)
+}
+
+#[pyclass(name = "DecayingTDigest", module = "fastdigest", frozen)]
+pub struct PyDecayingTDigest {
+    state: Mutex<DecayingDigest>,
+}
+
+impl PyDecayingTDigest {
+    fn from_decaying(decaying: DecayingDigest) -> Self {
+        Self {
+            state: Mutex::new(decaying),
+        }
+    }
+
+    /// Runs `f` on the flushed digest (with weights relative to the
+    /// landmark), raising ValueError if it is empty.
+    fn with_digest<T>(&self, f: impl FnOnce(&TDigest) -> T) -> PyResult<T> {
+        let mut decaying = self.state.lock();
+        decaying.flush().map_err(malloc_error)?;
+        if decaying.entry.digest.is_empty() {
+            return Err(EmptyDigestError::new_err("DecayingTDigest is empty."));
+        }
+        Ok(f(&decaying.entry.digest))
+    }
+}
+
+#[pymethods]
+impl PyDecayingTDigest {
+    /// Constructs a new empty DecayingTDigest instance whose weights halve
+    /// every `half_life` time units.
+    #[new]
+    #[pyo3(signature = (half_life, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(half_life: f64, max_centroids: i64) -> PyResult<Self> {
+        if !valid_half_life(half_life) {
+            return Err(PyValueError::new_err(
+                "half_life must be finite and greater than 0.",
+            ));
+        }
+        let max_size = validate_max_centroids(max_centroids)?;
+        let decaying =
+            DecayingDigest::new(half_life, max_size).map_err(malloc_error)?;
+        Ok(Self::from_decaying(decaying))
+    }
+
+    /// Getter property: returns the half-life of the weights.
+    #[getter(half_life)]
+    pub fn get_half_life(&self) -> f64 {
+        self.state.lock().half_life
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().entry.digest.max_size()
+    }
+
+    /// Getter property: returns the latest timestamp, or None if empty.
+    #[getter(now)]
+    pub fn get_now(&self) -> Option<f64> {
+        self.state.lock().now
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Updates the digest (in-place) with a single value at the given
+    /// timestamp.
+    #[pyo3(signature = (timestamp, x, w=None))]
+    pub fn update(
+        &self,
+        timestamp: f64,
+        x: f64,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        validate_timestamp(timestamp)?;
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut decaying = self.state.lock();
+        decaying.add(timestamp, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values at the
+    /// given timestamps (in any order).
+    #[pyo3(signature = (timestamps, x, w=None))]
+    pub fn batch_update(
+        &self,
+        timestamps: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (times, _) = extract_values(timestamps)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if times.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "timestamps must have the same length as x.",
+            ));
+        }
+        let weights = validate_weights(w, values.len())?;
+        let mut kept_times = Vec::new();
+        let mut pairs = Vec::new();
+        for (j, (t, x)) in times.iter(py).zip(values.iter(py)).enumerate() {
+            validate_timestamp(t)?;
+            // missing values are left out together with their timestamps
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            kept_times.push(t);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| {
+            self.state
+                .lock()
+                .add_batch(&kept_times, &mut pairs)
+                .map_err(malloc_error)
+        })
+    }
+
+    /// Estimates the quantile of the decayed distribution for a given
+    /// cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile of the decayed distribution for a given
+    /// cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median of the decayed distribution.
+    pub fn median(&self) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF of the decayed distribution at the value `x`.
+    pub fn cdf(&self, x: f64) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_rank(x))
+    }
+
+    /// Returns the weighted mean of the decayed distribution.
+    pub fn mean(&self) -> PyResult<f64> {
+        self.with_digest(|digest| digest.sum() / digest.mass())
+    }
+
+    /// Returns the total decayed weight at `now` (default: the latest
+    /// timestamp).
+    #[pyo3(signature = (now=None))]
+    pub fn mass(&self, now: Option<f64>) -> PyResult<f64> {
+        let mut decaying = self.state.lock();
+        let Some(now) = now.or(decaying.now) else {
+            return Ok(0.0);
+        };
+        validate_timestamp(now)?;
+        let digest = decaying.digest_at(now).map_err(malloc_error)?;
+        Ok(digest.mass())
+    }
+
+    /// Returns a TDigest with the weights decayed to `now` (default: the
+    /// latest timestamp).
+    #[pyo3(signature = (now=None))]
+    pub fn snapshot(&self, now: Option<f64>) -> PyResult<PyTDigest> {
+        let mut decaying = self.state.lock();
+        let now = match now {
+            Some(now) => validate_timestamp(now)?,
+            None => decaying.now.unwrap_or(0.0),
+        };
+        let digest = decaying.digest_at(now).map_err(malloc_error)?;
+        Ok(wrap_digest(digest))
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> PyResult<bool> {
+        let mut decaying = self.state.lock();
+        decaying.flush().map_err(malloc_error)?;
+        Ok(decaying.entry.digest.is_empty())
+    }
+
+    /// Merges this digest with another with the same half-life, returning
+    /// a new DecayingTDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same half-life into this one
+    /// (in-place).
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut decaying = self.state.lock();
+        if decaying.half_life != other.half_life {
+            return Err(PyValueError::new_err(
+                "Cannot merge DecayingTDigests with different half-lives.",
+            ));
+        }
+        decaying.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the digest.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let mut decaying = self.state.lock();
+        decaying.flush().map_err(malloc_error)?;
+        let bytes = decaying.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a DecayingTDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let decaying = DecayingDigest::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_decaying(decaying))
+    }
+
+    /// Returns a dict representation of the digest, with its weights
+    /// relative to the landmark.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut decaying = self.state.lock();
+        decaying.flush().map_err(malloc_error)?;
+        let digest = wrap_digest(decaying.entry.digest.clone());
+        let dict = PyDict::new(py);
+        dict.set_item("half_life", decaying.half_life)?;
+        dict.set_item("landmark", decaying.landmark)?;
+        dict.set_item("now", decaying.now)?;
+        dict.set_item("digest", digest.to_dict(py)?)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a DecayingTDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(decaying_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            decaying_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let digest = PyTDigest::from_dict(get("digest")?.cast()?, false)?;
+        let decaying = DecayingDigest::from_parts(
+            get("half_life")?.extract()?,
+            get("landmark")?.extract()?,
+            get("now")?.extract()?,
+            digest.state.into_inner().digest,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent DecayingTDigest clock.")
+        })?;
+        Ok(Self::from_decaying(decaying))
+    }
+
+    /// DecayingTDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_decaying(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via DecayingTDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(DecayingTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let decaying = self.state.lock();
+        format!(
+            "DecayingTDigest(half_life={:?}, max_centroids={})",
+            decaying.half_life,
+            decaying.entry.digest.max_size()
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        let mut decaying = self.state.lock();
+        decaying.flush().map_err(malloc_error)?;
+        Ok(decaying.same_state(&other))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
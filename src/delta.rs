@@ -0,0 +1,154 @@
+//! Deltas between two states of a TDigest, for incremental replication
+//!
+//! A delta describes the centroids of the new state as runs copied from
+//! the base state and runs of new centroids, along with the new totals.
+//! Centroids that a compression left untouched are only referenced, so
+//! frequent deltas of a large digest are much smaller than its full
+//! binary representation. The delta also records a fingerprint of the
+//! base state, so that it is only applied to the state it was computed
+//! from.
+
+use crate::codec::{Reader, Writer};
+use crate::hash::murmur3;
+use crate::tdigest::{BytesError, Centroid, TDigest};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTDLT~";
+const VERSION: u32 = 1;
+
+const OP_COPY: u8 = 0;
+const OP_NEW: u8 = 1;
+
+/// Run of centroids of the new state.
+enum Op {
+    /// `len` centroids of the base, starting at `start`
+    Copy { start: usize, len: usize },
+    /// New centroids
+    New(Vec<Centroid>),
+}
+
+/// Identifies the binary state of `digest`.
+pub fn fingerprint(digest: &TDigest) -> Result<u64, TryReserveError> {
+    Ok(murmur3(&digest.to_bytes()?, 0))
+}
+
+fn same(a: &Centroid, b: &Centroid) -> bool {
+    a.mean().to_bits() == b.mean().to_bits()
+        && a.weight().to_bits() == b.weight().to_bits()
+}
+
+/// Encodes the state of `digest` relative to `base`.
+pub fn encode(
+    base: &TDigest,
+    digest: &TDigest,
+) -> Result<Vec<u8>, TryReserveError> {
+    // both are sorted by mean: walk them like a merge
+    let old = base.centroids();
+    let mut ops: Vec<Op> = Vec::new();
+    let mut j = 0;
+    for c in digest.centroids() {
+        while j < old.len() && old[j].mean() < c.mean() {
+            j += 1;
+        }
+        if j < old.len() && same(&old[j], c) {
+            match ops.last_mut() {
+                Some(Op::Copy { start, len }) if *start + *len == j => {
+                    *len += 1;
+                }
+                _ => ops.push(Op::Copy { start: j, len: 1 }),
+            }
+            j += 1;
+        } else {
+            match ops.last_mut() {
+                Some(Op::New(centroids)) => centroids.push(c.clone()),
+                _ => ops.push(Op::New(vec![c.clone()])),
+            }
+        }
+    }
+
+    let mut writer = Writer::new(&MAGIC, VERSION);
+    writer.u64(fingerprint(base)?);
+    writer.u64(digest.max_size() as u64);
+    writer.f64(digest.mass());
+    writer.f64(digest.sum());
+    writer.f64(digest.min());
+    writer.f64(digest.max());
+    writer.u64(digest.count() as u64);
+    writer.u64((digest.count() >> 64) as u64);
+    writer.u64(ops.len() as u64);
+    for op in &ops {
+        match op {
+            Op::Copy { start, len } => {
+                writer.u8(OP_COPY);
+                writer.u64(*start as u64);
+                writer.u64(*len as u64);
+            }
+            Op::New(centroids) => {
+                writer.u8(OP_NEW);
+                writer.u64(centroids.len() as u64);
+                for c in centroids {
+                    writer.f64(c.mean());
+                    writer.f64(c.weight());
+                }
+            }
+        }
+    }
+    Ok(writer.finish())
+}
+
+/// Fingerprint of the base state of a delta.
+pub fn base_fingerprint(delta: &[u8]) -> Result<u64, BytesError> {
+    Reader::new(delta, &MAGIC, VERSION)?.u64()
+}
+
+/// Rebuilds the state encoded by `delta` from `base`, which must be the
+/// state it was computed from (see `base_fingerprint`). The settings of
+/// `base` beyond its binary state are kept.
+pub fn apply(base: &TDigest, delta: &[u8]) -> Result<TDigest, BytesError> {
+    let mut reader = Reader::new(delta, &MAGIC, VERSION)?;
+    reader.u64()?;
+    let max_size =
+        usize::try_from(reader.u64()?).map_err(|_| BytesError::WrongArch)?;
+    let mass = reader.f64()?;
+    let sum = reader.f64()?;
+    let min = reader.f64()?;
+    let max = reader.f64()?;
+    let count = u128::from(reader.u64()?) | u128::from(reader.u64()?) << 64;
+    let n_ops = reader.seq_len(9)?;
+    let old = base.centroids();
+    let mut centroids: Vec<Centroid> = Vec::new();
+    for _ in 0..n_ops {
+        match reader.u8()? {
+            OP_COPY => {
+                let start = usize::try_from(reader.u64()?)
+                    .map_err(|_| BytesError::CorruptData)?;
+                let len = usize::try_from(reader.u64()?)
+                    .map_err(|_| BytesError::CorruptData)?;
+                let run = start
+                    .checked_add(len)
+                    .and_then(|end| old.get(start..end))
+                    .ok_or(BytesError::CorruptData)?;
+                centroids
+                    .try_reserve(run.len())
+                    .map_err(BytesError::MemError)?;
+                centroids.extend_from_slice(run);
+            }
+            OP_NEW => {
+                let len = reader.seq_len(16)?;
+                centroids.try_reserve(len).map_err(BytesError::MemError)?;
+                for _ in 0..len {
+                    let mean = reader.f64()?;
+                    centroids.push(Centroid::new(mean, reader.f64()?));
+                }
+            }
+            _ => return Err(BytesError::CorruptData),
+        }
+    }
+    reader.finish()?;
+    let mut digest =
+        TDigest::from_state(centroids, max_size, mass, sum, min, max, count);
+    digest.set_tail_size(base.tail_size());
+    digest.set_resolution(base.resolution());
+    digest.set_tails(base.tails().cloned());
+    Ok(digest)
+}
@@ -0,0 +1,613 @@
+//! Fixed-size array of digests for per-column or per-group aggregation
+//!
+//! The digests are stored contiguously and addressed by position. Batch
+//! updates carry an index per value; the values are bucketed by index in
+//! one pass (a counting sort), after which every digest merges its bucket
+//! once, optionally on several threads. Queries return one result per
+//! digest as a NumPy array.
+
+use crate::codec::{Input, Reader, Writer};
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::{InvalidQuantileError, SerializationError};
+use crate::stream::{FileReader, FileWriter};
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, f64_array, malloc_error,
+    validate_max_centroids, validate_n_threads, validate_value,
+    validate_values, validate_weight, validate_weights, PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::TryReserveError;
+use std::thread;
+
+const MAGIC: [u8; 8] = *b"FASTTDA~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+pub struct DigestArray {
+    max_size: usize,
+    entries: Vec<Entry>,
+}
+
+impl DigestArray {
+    pub fn new(n: usize, max_size: usize) -> Result<Self, TryReserveError> {
+        let mut entries = Vec::new();
+        entries.try_reserve_exact(n)?;
+        for _ in 0..n {
+            entries.push(Entry::new(max_size)?);
+        }
+        Ok(Self { max_size, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn count(&self) -> u128 {
+        self.entries.iter().map(Entry::count).sum()
+    }
+
+    pub fn add(
+        &mut self,
+        i: usize,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        self.entries[i].add(x, weight)
+    }
+
+    /// Adds a batch of values to the digests at the given indices (which
+    /// must be in range), merging each digest once with all of its values.
+    pub fn add_grouped(
+        &mut self,
+        indices: &[usize],
+        values: &[f64],
+        weights: Option<&[f64]>,
+        n_threads: usize,
+    ) -> Result<(), TryReserveError> {
+        let mut counts: Vec<usize> = Vec::new();
+        counts.try_reserve_exact(self.len())?;
+        counts.resize(self.len(), 0);
+        for &i in indices {
+            counts[i] += 1;
+        }
+        let mut groups: Vec<Pairs> = Vec::new();
+        groups.try_reserve_exact(self.len())?;
+        for &count in &counts {
+            let mut group = Vec::new();
+            group.try_reserve_exact(count)?;
+            groups.push(group);
+        }
+        for (j, (&i, &x)) in indices.iter().zip(values).enumerate() {
+            let weight = weights.map_or(1.0, |weights| weights[j]);
+            groups[i].push((OrderedFloat(x), weight));
+        }
+
+        let n_threads = n_threads.min(self.len());
+        if n_threads <= 1 {
+            for (entry, group) in self.entries.iter_mut().zip(&mut groups) {
+                entry.add_batch(group)?;
+            }
+            return Ok(());
+        }
+        let chunk = self.len().div_ceil(n_threads);
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .entries
+                .chunks_mut(chunk)
+                .zip(groups.chunks_mut(chunk))
+                .map(|(entries, groups)| {
+                    scope.spawn(move || {
+                        for (entry, group) in entries.iter_mut().zip(groups) {
+                            entry.add_batch(group)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            handles.into_iter().try_for_each(|handle| {
+                handle.join().expect("merge thread panicked")
+            })
+        })
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        for entry in &mut self.entries {
+            entry.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The flushed digests, in order.
+    pub fn into_digests(mut self) -> Result<Vec<TDigest>, TryReserveError> {
+        self.flush()?;
+        Ok(self.entries.into_iter().map(|entry| entry.digest).collect())
+    }
+
+    /// The flushed digest at index `i` (which must be in range).
+    pub fn digest(&mut self, i: usize) -> Result<&TDigest, TryReserveError> {
+        let entry = &mut self.entries[i];
+        entry.flush()?;
+        Ok(&entry.digest)
+    }
+
+    /// The digests in order; the array must be flushed.
+    pub fn digests(&self) -> impl Iterator<Item = &TDigest> {
+        self.entries.iter().map(|entry| &entry.digest)
+    }
+
+    /// Applies `f` to every flushed digest, with NaN for the empty ones.
+    pub fn map_digests(
+        &mut self,
+        f: impl Fn(&TDigest) -> f64,
+    ) -> Result<Vec<f64>, TryReserveError> {
+        self.flush()?;
+        let mut results = Vec::new();
+        results.try_reserve_exact(self.len())?;
+        results.extend(self.entries.iter().map(|entry| {
+            if entry.digest.is_empty() {
+                f64::NAN
+            } else {
+                f(&entry.digest)
+            }
+        }));
+        Ok(results)
+    }
+
+    /// Merges each digest of `other` into the digest at the same position,
+    /// keeping the `max_size` of this array. The lengths must match.
+    pub fn merge(
+        &mut self,
+        other: &DigestArray,
+    ) -> Result<(), TryReserveError> {
+        for (entry, other_entry) in self.entries.iter_mut().zip(&other.entries)
+        {
+            entry.merge(other_entry)?;
+        }
+        Ok(())
+    }
+
+    /// Whether both arrays hold equal digests. Both must be flushed.
+    pub fn same_digests(&self, other: &DigestArray) -> bool {
+        self.max_size == other.max_size
+            && self.len() == other.len()
+            && self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .all(|(a, b)| a.digest == b.digest)
+    }
+
+    /// Binary representation; the array must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = self.header();
+        for entry in &self.entries {
+            writer.bytes(&entry.digest.to_bytes()?);
+        }
+        Ok(writer.finish())
+    }
+
+    /// Beginning of the binary representation, up to the digests.
+    fn header(&self) -> Writer {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.max_size as u64);
+        writer.u64(self.entries.len() as u64);
+        writer
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let array = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(array)
+    }
+
+    /// Reads the binary representation after the magic and version.
+    fn read<R: Input>(reader: &mut R) -> Result<Self, R::Error> {
+        let max_size = usize::try_from(reader.u64()?)
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        // each digest is prefixed with its length
+        let len = reader.seq_len(8)?;
+        let mut digests = Vec::new();
+        for _ in 0..len {
+            digests.try_reserve(1).map_err(BytesError::MemError)?;
+            digests.push(TDigest::from_bytes(&reader.bytes()?)?);
+        }
+        Ok(Self::from_digests(max_size, digests)
+            .ok_or(BytesError::CorruptData)?)
+    }
+
+    /// Array of the given digests. Returns None if any of them has a
+    /// different `max_size`.
+    fn from_digests(max_size: usize, digests: Vec<TDigest>) -> Option<Self> {
+        if digests.iter().any(|digest| digest.max_size() != max_size) {
+            return None;
+        }
+        let entries = digests.into_iter().map(Entry::from_digest).collect();
+        Some(Self { max_size, entries })
+    }
+}
+
+#[pyclass(name = "TDigestArray", module = "fastdigest", frozen)]
+pub struct PyTDigestArray {
+    state: Mutex<DigestArray>,
+}
+
+impl PyTDigestArray {
+    pub(crate) fn from_array(array: DigestArray) -> Self {
+        Self {
+            state: Mutex::new(array),
+        }
+    }
+
+    pub(crate) fn into_array(self) -> DigestArray {
+        self.state.into_inner()
+    }
+
+    /// Locks the array after flushing all digests.
+    fn lock_flushed(&self) -> PyResult<MutexGuard<'_, DigestArray>> {
+        let mut array = self.state.lock();
+        array.flush().map_err(malloc_error)?;
+        Ok(array)
+    }
+
+    /// Applies `f` to every non-empty digest, returning a NumPy array.
+    fn map_digests<'py>(
+        &self,
+        py: Python<'py>,
+        f: impl Fn(&TDigest) -> f64 + Send,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let results = py.detach(|| {
+            self.state.lock().map_digests(f).map_err(malloc_error)
+        })?;
+        f64_array(py, &results)
+    }
+}
+
+/// Helper function to check an index into an array of length `len`
+pub fn validate_index(i: i64, len: usize) -> PyResult<usize> {
+    usize::try_from(i)
+        .ok()
+        .filter(|&i| i < len)
+        .ok_or_else(|| PyIndexError::new_err("Index out of range."))
+}
+
+#[pymethods]
+impl PyTDigestArray {
+    /// Constructs a new TDigestArray instance with `n` empty digests.
+    #[new]
+    #[pyo3(signature = (n, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(n: i64, max_centroids: i64) -> PyResult<Self> {
+        let n = usize::try_from(n).map_err(|_| {
+            PyValueError::new_err("n must be a non-negative integer.")
+        })?;
+        let max_size = validate_max_centroids(max_centroids)?;
+        let array = DigestArray::new(n, max_size).map_err(malloc_error)?;
+        Ok(Self::from_array(array))
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Updates the digest at index `i` (in-place) with a single value.
+    #[pyo3(signature = (i, x, w=None))]
+    pub fn update(&self, i: i64, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut array = self.state.lock();
+        let i = validate_index(i, array.len())?;
+        array.add(i, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digests (in-place) with a sequence of float values,
+    /// each added to the digest at the index at the same position.
+    #[pyo3(signature = (indices, x, w=None, n_threads=Some(1)))]
+    pub fn batch_update(
+        &self,
+        indices: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        n_threads: Option<i64>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let n_threads = validate_n_threads(n_threads)?;
+        let (index_values, _) = extract_values(indices)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if index_values.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "indices must have the same length as x.",
+            ));
+        }
+        let len = self.state.lock().len();
+        let weights = validate_weights(w, values.len())?;
+        let mut kept_indices = Vec::new();
+        let mut kept_values = Vec::new();
+        let mut kept_weights = weights.as_ref().map(|_| Vec::new());
+        for (j, (i, x)) in
+            index_values.iter(py).zip(values.iter(py)).enumerate()
+        {
+            if !(i.fract() == 0.0 && i >= 0.0 && i < len as f64) {
+                return Err(PyIndexError::new_err("Index out of range."));
+            }
+            // missing values are left out together with their weights
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            kept_indices.push(i as usize);
+            kept_values.push(x);
+            if let (Some(kept), Some(weights)) = (&mut kept_weights, &weights) {
+                kept.push(weights[j]);
+            }
+        }
+        validate_values(kept_values.iter().copied())?;
+        py.detach(|| {
+            self.state
+                .lock()
+                .add_grouped(
+                    &kept_indices,
+                    &kept_values,
+                    kept_weights.as_deref(),
+                    n_threads,
+                )
+                .map_err(malloc_error)
+        })
+    }
+
+    /// Estimates the quantile of every digest for a given cumulative
+    /// probability `q`, with NaN for empty digests.
+    pub fn quantile<'py>(
+        &self,
+        py: Python<'py>,
+        q: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.map_digests(py, |digest| digest.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile of every digest for a given cumulative
+    /// probability `p` (%), with NaN for empty digests.
+    pub fn percentile<'py>(
+        &self,
+        py: Python<'py>,
+        p: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.map_digests(py, |digest| digest.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median of every digest, with NaN for empty digests.
+    pub fn median<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.map_digests(py, |digest| digest.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF of every digest at the value `x`, with NaN for
+    /// empty digests.
+    pub fn cdf<'py>(
+        &self,
+        py: Python<'py>,
+        x: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.map_digests(py, |digest| digest.estimate_rank(x))
+    }
+
+    /// Returns the total weight of every digest.
+    pub fn mass<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let results: Vec<f64> = {
+            let array = self.lock_flushed()?;
+            array
+                .entries
+                .iter()
+                .map(|entry| entry.digest.mass())
+                .collect()
+        };
+        f64_array(py, &results)
+    }
+
+    /// Merges this array with another elementwise, returning a new
+    /// TDigestArray.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another array into this one (in-place), elementwise.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut array = self.state.lock();
+        if array.len() != other.len() {
+            return Err(PyValueError::new_err(
+                "Cannot merge TDigestArrays of different lengths.",
+            ));
+        }
+        array.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of all digests.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.lock_flushed()?.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a TDigestArray from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let array = DigestArray::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_array(array))
+    }
+
+    /// Writes the binary representation of all digests to a binary file
+    /// object or socket, in chunks.
+    pub fn write_to(&self, file: &Bound<'_, PyAny>) -> PyResult<()> {
+        let writer = FileWriter::new(file)?;
+        let (header, len) = {
+            let array = self.state.lock();
+            (array.header(), array.len())
+        };
+        writer.write_chunked(&self.state, header, len, |array, i, out| {
+            out.bytes(&array.digest(i)?.to_bytes()?);
+            Ok(())
+        })
+    }
+
+    /// Reads a TDigestArray written by `write_to` (or `to_bytes`) from a
+    /// binary file object or socket, digest by digest.
+    #[staticmethod]
+    pub fn read_from(file: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let mut reader = FileReader::new(file)?;
+        reader.header(&MAGIC, VERSION)?;
+        Ok(Self::from_array(DigestArray::read(&mut reader)?))
+    }
+
+    /// Returns a dict representation of all digests.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let array = self.lock_flushed()?;
+        let digests = PyList::empty(py);
+        for entry in &array.entries {
+            digests.append(wrap_digest(entry.digest.clone()).to_dict(py)?)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("max_centroids", array.max_size)?;
+        dict.set_item("digests", digests)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a TDigestArray from a dict.
+    #[staticmethod]
+    pub fn from_dict(array_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            array_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let max_size =
+            validate_max_centroids(get("max_centroids")?.extract()?)?;
+        let digests = get("digests")?
+            .cast::<PyList>()?
+            .iter()
+            .map(|digest_dict| {
+                let digest = PyTDigest::from_dict(digest_dict.cast()?, false)?;
+                Ok(digest.state.into_inner().digest)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let array =
+            DigestArray::from_digests(max_size, digests).ok_or_else(|| {
+                SerializationError::new_err(
+                    "Inconsistent TDigestArray max_centroids.",
+                )
+            })?;
+        Ok(Self::from_array(array))
+    }
+
+    /// TDigestArray.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_array(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest_array) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest_array) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via TDigestArray.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(digest_array) returns the number of digests.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().len()
+    }
+
+    /// Magic method: digest_array[i] returns a copy of the digest at
+    /// index `i` (negative indices count from the end) as a TDigest.
+    pub fn __getitem__(&self, i: i64) -> PyResult<PyTDigest> {
+        let mut array = self.state.lock();
+        let len = array.len();
+        let i = if i < 0 { i + len as i64 } else { i };
+        let i = validate_index(i, len)?;
+        let entry = &mut array.entries[i];
+        entry.flush().map_err(malloc_error)?;
+        Ok(wrap_digest(entry.digest.clone()))
+    }
+
+    /// Magic method: repr/str(TDigestArray) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let array = self.state.lock();
+        format!(
+            "TDigestArray(n={}, max_centroids={})",
+            array.len(),
+            array.max_size
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        Ok(self.lock_flushed()?.same_digests(&other))
+    }
+
+    /// Magic method: arr1 + arr2 returns arr1.merge(arr2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: arr1 += arr2 merges arr2 into arr1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,327 @@
+//! File holding a collection of digests, loaded lazily by key
+//!
+//! The file starts with a magic and version, followed by the digests in
+//! the format of `TDigest::to_bytes` and an index of their keys, offsets
+//! and lengths. The offset of the index is in the last 8 bytes, so that
+//! the file can be written in one pass. Opening a file only reads the
+//! index; each digest is read when it is looked up.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, DigestMap, PyTDigestMap};
+use crate::hash::Value;
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_DEFAULT};
+use crate::wal::replace;
+use crate::{bytes_error, lock_and_flush, malloc_error, PyTDigest};
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+const MAGIC: [u8; 8] = *b"FASTDMF~";
+const VERSION: u32 = 1;
+/// magic and version
+const HEADER_BYTES: u64 = 12;
+/// offset of the index
+const TRAILER_BYTES: u64 = 8;
+
+/// Location of a digest in the file.
+#[derive(Clone, Copy)]
+struct Span {
+    offset: u64,
+    len: u64,
+}
+
+/// Collects the (key, binary digest) pairs of a mapping or a TDigestMap.
+fn collect(digests: &Bound<'_, PyAny>) -> PyResult<Vec<(Value, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    if let Ok(map) = digests.cast::<PyTDigestMap>() {
+        let map = map.get().lock_flushed()?;
+        for (key, digest) in map.digests() {
+            let bytes = digest.to_bytes().map_err(malloc_error)?;
+            entries.push((key.clone(), bytes));
+        }
+        return Ok(entries);
+    }
+    let type_error = || {
+        PyTypeError::new_err(
+            "digests must be a mapping of keys to TDigests, or a TDigestMap.",
+        )
+    };
+    let items = digests.call_method0("items").map_err(|_| type_error())?;
+    let mut seen = HashSet::new();
+    for item in items.try_iter()? {
+        let (key, digest): (Bound<'_, PyAny>, Bound<'_, PyAny>) =
+            item?.extract().map_err(|_| type_error())?;
+        let digest = digest.cast::<PyTDigest>().map_err(|_| type_error())?;
+        let value = Value::extract(&key)?;
+        if !seen.insert(value.clone()) {
+            return Err(PyValueError::new_err(format!(
+                "duplicate key: {}.",
+                key.repr()?
+            )));
+        }
+        let digest = digest.borrow();
+        let state = lock_and_flush(&digest)?;
+        let bytes = state.digest.to_bytes().map_err(malloc_error)?;
+        entries.push((value, bytes));
+    }
+    Ok(entries)
+}
+
+/// Saves a collection of digests to one file at `path`, from which they
+/// can be loaded one by one with `load_many`. The file is replaced
+/// atomically.
+#[pyfunction]
+pub fn save_many(
+    py: Python<'_>,
+    digests: &Bound<'_, PyAny>,
+    path: PathBuf,
+) -> PyResult<()> {
+    let entries = collect(digests)?;
+    py.detach(|| {
+        let mut index = Writer::new(&MAGIC, VERSION);
+        index.u64(entries.len() as u64);
+        let mut offset = HEADER_BYTES;
+        for (key, bytes) in &entries {
+            key.write(&mut index);
+            index.u64(offset);
+            index.u64(bytes.len() as u64);
+            offset += bytes.len() as u64;
+        }
+        let index = index.finish();
+        replace(&path, |file| {
+            let mut out = BufWriter::new(file);
+            out.write_all(&MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+            for (_, bytes) in &entries {
+                out.write_all(bytes)?;
+            }
+            out.write_all(&index)?;
+            out.write_all(&offset.to_le_bytes())?;
+            out.flush()
+        })?;
+        Ok(())
+    })
+}
+
+/// Opens a file written by `save_many`, reading only its index.
+#[pyfunction]
+pub fn load_many(py: Python<'_>, path: PathBuf) -> PyResult<PyDigestFile> {
+    py.detach(|| PyDigestFile::open(path))
+}
+
+/// Reads the index of a file of `size` bytes.
+fn read_index(
+    file: &mut File,
+    size: u64,
+) -> PyResult<(Vec<Value>, HashMap<Value, Span>)> {
+    let mut header = [0u8; HEADER_BYTES as usize];
+    file.read_exact(&mut header).map_err(|_| {
+        bytes_error(if size == 0 {
+            BytesError::EmptyData
+        } else {
+            BytesError::WrongFormat
+        })
+    })?;
+    Reader::new(&header, &MAGIC, VERSION)?;
+    if size < HEADER_BYTES + TRAILER_BYTES {
+        return Err(bytes_error(BytesError::CorruptData));
+    }
+    let mut trailer = [0u8; TRAILER_BYTES as usize];
+    file.seek(SeekFrom::Start(size - TRAILER_BYTES))?;
+    file.read_exact(&mut trailer)?;
+    let start = u64::from_le_bytes(trailer);
+    if !(HEADER_BYTES..=size - TRAILER_BYTES).contains(&start) {
+        return Err(bytes_error(BytesError::CorruptData));
+    }
+    let len = usize::try_from(size - TRAILER_BYTES - start)
+        .map_err(|_| bytes_error(BytesError::WrongArch))?;
+    let mut bytes = Vec::new();
+    bytes.try_reserve_exact(len).map_err(malloc_error)?;
+    bytes.resize(len, 0);
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut bytes)?;
+
+    let mut reader = Reader::new(&bytes, &MAGIC, VERSION)?;
+    // each entry takes at least a key tag, an offset and a length
+    let n = reader.seq_len(17)?;
+    let mut keys = Vec::new();
+    let mut spans = HashMap::new();
+    for _ in 0..n {
+        let key = Value::read(&mut reader)?;
+        let span = Span {
+            offset: reader.u64()?,
+            len: reader.u64()?,
+        };
+        let valid = span.offset >= HEADER_BYTES
+            && span
+                .offset
+                .checked_add(span.len)
+                .is_some_and(|end| end <= start);
+        if !valid || spans.insert(key.clone(), span).is_some() {
+            return Err(bytes_error(BytesError::CorruptData));
+        }
+        keys.push(key);
+    }
+    reader.finish()?;
+    Ok((keys, spans))
+}
+
+#[pyclass(name = "DigestFile", module = "fastdigest", frozen)]
+pub struct PyDigestFile {
+    path: PathBuf,
+    /// Keys in the order in which they were saved
+    keys: Vec<Value>,
+    spans: HashMap<Value, Span>,
+    file: Mutex<Option<File>>,
+}
+
+impl PyDigestFile {
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let mut file = File::open(&path)?;
+        let size = file.metadata()?.len();
+        let (keys, spans) = read_index(&mut file, size)?;
+        Ok(Self {
+            path,
+            keys,
+            spans,
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    /// Reads the digest at `span`.
+    fn read(&self, span: Span) -> PyResult<TDigest> {
+        let mut file = self.file.lock();
+        let file = file
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("DigestFile is closed."))?;
+        let len = usize::try_from(span.len)
+            .map_err(|_| bytes_error(BytesError::WrongArch))?;
+        let mut bytes = Vec::new();
+        bytes.try_reserve_exact(len).map_err(malloc_error)?;
+        bytes.resize(len, 0);
+        file.seek(SeekFrom::Start(span.offset))?;
+        file.read_exact(&mut bytes)?;
+        TDigest::from_bytes(&bytes).map_err(bytes_error)
+    }
+}
+
+#[pymethods]
+impl PyDigestFile {
+    /// Getter property: returns the path of the file.
+    #[getter(path)]
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Getter property: returns whether the file has been closed.
+    #[getter(closed)]
+    pub fn get_closed(&self) -> bool {
+        self.file.lock().is_none()
+    }
+
+    /// Returns the keys in the order in which they were saved.
+    pub fn keys<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        self.keys.iter().map(|key| key.to_object(py)).collect()
+    }
+
+    /// Loads the digest for `key`, or returns `default` if it is not in
+    /// the file.
+    #[pyo3(signature = (key, default=None))]
+    pub fn get(
+        &self,
+        key: &Bound<'_, PyAny>,
+        default: Option<Py<PyAny>>,
+    ) -> PyResult<Py<PyAny>> {
+        let py = key.py();
+        match self.spans.get(&Value::extract(key)?) {
+            Some(&span) => {
+                let digest = wrap_digest(py.detach(|| self.read(span))?);
+                Ok(Py::new(py, digest)?.into_any())
+            }
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Loads all digests into a TDigestMap. They must have the same
+    /// max_centroids.
+    pub fn to_map(&self, py: Python<'_>) -> PyResult<PyTDigestMap> {
+        py.detach(|| {
+            let mut map: Option<DigestMap> = None;
+            for key in &self.keys {
+                let digest = self.read(self.spans[key])?;
+                let map = map
+                    .get_or_insert_with(|| DigestMap::new(digest.max_size()));
+                map.insert(key.clone(), digest).ok_or_else(|| {
+                    PyValueError::new_err(
+                        "All digests must have the same max_centroids.",
+                    )
+                })?;
+            }
+            let map = map.unwrap_or_else(|| DigestMap::new(TD_SIZE_DEFAULT));
+            Ok(PyTDigestMap::from_map(map))
+        })
+    }
+
+    /// Closes the file. Further calls have no effect.
+    pub fn close(&self) {
+        *self.file.lock() = None;
+    }
+
+    pub fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) {
+        self.close()
+    }
+
+    /// Magic method: len(digest_file) returns the number of keys.
+    pub fn __len__(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Magic method: `key in digest_file` checks for a digest for `key`.
+    pub fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.spans.contains_key(&Value::extract(key)?))
+    }
+
+    /// Magic method: iter(digest_file) iterates over the keys.
+    pub fn __iter__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        Ok(self.keys(py)?.into_pyobject(py)?.try_iter()?.into_any())
+    }
+
+    /// Magic method: digest_file[key] loads the digest for `key` as a
+    /// TDigest.
+    pub fn __getitem__(&self, key: &Bound<'_, PyAny>) -> PyResult<PyTDigest> {
+        match self.spans.get(&Value::extract(key)?) {
+            Some(&span) => {
+                Ok(wrap_digest(key.py().detach(|| self.read(span))?))
+            }
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    /// Magic method: repr(digest_file) shows the path and number of keys.
+    pub fn __repr__(&self) -> String {
+        format!(
+            "DigestFile('{}', n_keys={})",
+            self.path.display(),
+            self.keys.len()
+        )
+    }
+}
@@ -0,0 +1,653 @@
+//! Collection of digests keyed by hashable values
+//!
+//! One TDigest is kept per key (e.g. per endpoint or tenant), with keys in
+//! the canonical form of the set-based sketches, so that the same keys are
+//! found again after a round trip through `to_bytes`. Single updates are
+//! buffered per key like those of a TDigest; batch updates are grouped by
+//! key, so that each digest is merged only once per batch.
+
+use crate::codec::{Input, Reader, Writer};
+use crate::errors::{InvalidQuantileError, SerializationError};
+use crate::hash::Value;
+use crate::stream::{FileReader, FileWriter};
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest, TDigestState, CACHE_SIZE,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::{HashMap, TryReserveError};
+
+const MAGIC: [u8; 8] = *b"FASTTDM~";
+const VERSION: u32 = 1;
+
+pub(crate) type Pairs = Vec<(OrderedFloat<f64>, f64)>;
+
+/// Digest with a buffer for single updates, shared with `TDigestArray`
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub digest: TDigest,
+    /// Buffered (value, weight) pairs of single updates
+    pending: Pairs,
+}
+
+impl Entry {
+    pub fn new(max_size: usize) -> Result<Self, TryReserveError> {
+        Ok(Self::from_digest(TDigest::new_with_size(max_size)?))
+    }
+
+    pub fn from_digest(digest: TDigest) -> Self {
+        Self {
+            digest,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, x: f64, weight: f64) -> Result<(), TryReserveError> {
+        self.pending.push((OrderedFloat(x), weight));
+        if self.pending.len() >= CACHE_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts a batch of (value, weight) pairs and merges it into the digest.
+    pub fn add_batch(
+        &mut self,
+        pairs: &mut Pairs,
+    ) -> Result<(), TryReserveError> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        TDigest::sort_pairs(pairs, 1)?;
+        self.digest.merge_sorted_weighted_inplace(pairs)
+    }
+
+    pub fn merge(&mut self, other: &Entry) -> Result<(), TryReserveError> {
+        self.digest.merge_digest_inplace(&other.digest)?;
+        self.pending.extend_from_slice(&other.pending);
+        if self.pending.len() >= CACHE_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        let mut pending = std::mem::take(&mut self.pending);
+        let result = self.add_batch(&mut pending);
+        if result.is_ok() {
+            // the buffer is kept for later updates
+            pending.clear();
+        }
+        self.pending = pending;
+        result
+    }
+
+    pub fn count(&self) -> u128 {
+        self.digest.count() + self.pending.len() as u128
+    }
+
+    /// The buffered (value, weight) pairs of single updates.
+    pub fn pending(&self) -> &Pairs {
+        &self.pending
+    }
+}
+
+#[derive(Clone)]
+pub struct DigestMap {
+    max_size: usize,
+    /// Entries in the order in which their keys were first seen
+    entries: Vec<(Value, Entry)>,
+    index: HashMap<Value, usize>,
+}
+
+impl DigestMap {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn count(&self) -> u128 {
+        self.entries.iter().map(|(_, entry)| entry.count()).sum()
+    }
+
+    pub fn contains(&self, key: &Value) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    /// The keys with their digests; the map must be flushed.
+    pub fn digests(&self) -> impl Iterator<Item = (&Value, &TDigest)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.digest))
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Position of the entry for `key`, which is created if necessary.
+    fn position(&mut self, key: &Value) -> Result<usize, TryReserveError> {
+        if let Some(&i) = self.index.get(key) {
+            return Ok(i);
+        }
+        let entry = Entry::new(self.max_size)?;
+        self.entries.push((key.clone(), entry));
+        self.index.insert(key.clone(), self.entries.len() - 1);
+        Ok(self.entries.len() - 1)
+    }
+
+    pub fn add(
+        &mut self,
+        key: &Value,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        let i = self.position(key)?;
+        self.entries[i].1.add(x, weight)
+    }
+
+    /// Merges a batch of (value, weight) pairs into the digest for `key`.
+    pub fn add_batch(
+        &mut self,
+        key: &Value,
+        pairs: &mut Pairs,
+    ) -> Result<(), TryReserveError> {
+        let i = self.position(key)?;
+        self.entries[i].1.add_batch(pairs)
+    }
+
+    /// Adds a batch of (key, value, weight) triples, merging each digest
+    /// once with all of its values.
+    pub fn add_grouped(
+        &mut self,
+        keys: &[Value],
+        values: &[f64],
+        weights: Option<&[f64]>,
+    ) -> Result<(), TryReserveError> {
+        let mut groups: Vec<Pairs> = Vec::new();
+        for (j, (key, &x)) in keys.iter().zip(values).enumerate() {
+            let i = self.position(key)?;
+            if i >= groups.len() {
+                groups.resize_with(i + 1, Vec::new);
+            }
+            let weight = weights.map_or(1.0, |weights| weights[j]);
+            groups[i].try_reserve(1)?;
+            groups[i].push((OrderedFloat(x), weight));
+        }
+        for (i, mut pairs) in groups.into_iter().enumerate() {
+            self.entries[i].1.add_batch(&mut pairs)?;
+        }
+        Ok(())
+    }
+
+    /// Flushed digest for `key`, if any.
+    pub fn digest(
+        &mut self,
+        key: &Value,
+    ) -> Result<Option<&TDigest>, TryReserveError> {
+        let Some(&i) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let entry = &mut self.entries[i].1;
+        entry.flush()?;
+        Ok(Some(&entry.digest))
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        for (_, entry) in &mut self.entries {
+            entry.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the digest of every key of `other` into the digest for the
+    /// same key, keeping the `max_size` of this map.
+    pub fn merge(&mut self, other: &DigestMap) -> Result<(), TryReserveError> {
+        for (key, other_entry) in &other.entries {
+            let i = self.position(key)?;
+            self.entries[i].1.merge(other_entry)?;
+        }
+        Ok(())
+    }
+
+    /// Whether both maps hold equal digests under the same keys, in any
+    /// order. Both maps must be flushed.
+    pub fn same_digests(&self, other: &DigestMap) -> bool {
+        self.max_size == other.max_size
+            && self.len() == other.len()
+            && self.entries.iter().all(|(key, entry)| {
+                other
+                    .index
+                    .get(key)
+                    .is_some_and(|&i| other.entries[i].1.digest == entry.digest)
+            })
+    }
+
+    /// Binary representation; the map must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = self.header();
+        for i in 0..self.entries.len() {
+            self.write_entry(i, &mut writer)?;
+        }
+        Ok(writer.finish())
+    }
+
+    /// Beginning of the binary representation, up to the entries.
+    fn header(&self) -> Writer {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.max_size as u64);
+        writer.u64(self.entries.len() as u64);
+        writer
+    }
+
+    /// Writes the entry at index `i`, which must be flushed.
+    fn write_entry(
+        &self,
+        i: usize,
+        writer: &mut Writer,
+    ) -> Result<(), TryReserveError> {
+        let (key, entry) = &self.entries[i];
+        key.write(writer);
+        writer.bytes(&entry.digest.to_bytes()?);
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let map = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(map)
+    }
+
+    /// Reads the binary representation after the magic and version.
+    fn read<R: Input>(reader: &mut R) -> Result<Self, R::Error> {
+        let max_size = usize::try_from(reader.u64()?)
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        let mut map = Self::new(max_size);
+        // each entry takes at least a key tag and a length
+        let len = reader.seq_len(9)?;
+        for _ in 0..len {
+            let key = Value::read(reader)?;
+            let digest = TDigest::from_bytes(&reader.bytes()?)?;
+            map.insert(key, digest).ok_or(BytesError::CorruptData)?;
+        }
+        Ok(map)
+    }
+
+    /// Adds a digest under a new key. Returns None if the key is already
+    /// present or the digest has a different `max_size`.
+    pub fn insert(&mut self, key: Value, digest: TDigest) -> Option<()> {
+        if self.contains(&key) || digest.max_size() != self.max_size {
+            return None;
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, Entry::from_digest(digest)));
+        Some(())
+    }
+}
+
+#[pyclass(name = "TDigestMap", module = "fastdigest", frozen)]
+pub struct PyTDigestMap {
+    state: Mutex<DigestMap>,
+}
+
+impl PyTDigestMap {
+    pub(crate) fn from_map(map: DigestMap) -> Self {
+        Self {
+            state: Mutex::new(map),
+        }
+    }
+
+    pub(crate) fn into_map(self) -> DigestMap {
+        self.state.into_inner()
+    }
+
+    /// Runs `f` on the flushed digest for `key`, raising KeyError if the
+    /// key is not in the map.
+    fn with_digest<T>(
+        &self,
+        key: &Bound<'_, PyAny>,
+        f: impl FnOnce(&TDigest) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let value = Value::extract(key)?;
+        let mut map = self.state.lock();
+        match map.digest(&value).map_err(malloc_error)? {
+            Some(digest) => f(digest),
+            None => Err(PyKeyError::new_err(key.clone().unbind())),
+        }
+    }
+
+    /// Locks the map after flushing all entries.
+    pub(crate) fn lock_flushed(&self) -> PyResult<MutexGuard<'_, DigestMap>> {
+        let mut map = self.state.lock();
+        map.flush().map_err(malloc_error)?;
+        Ok(map)
+    }
+}
+
+/// Wraps a digest into a new TDigest instance.
+pub(crate) fn wrap_digest(digest: TDigest) -> PyTDigest {
+    PyTDigest {
+        state: Mutex::new(TDigestState::with_digest(digest)),
+    }
+}
+
+#[pymethods]
+impl PyTDigestMap {
+    /// Constructs a new empty TDigestMap instance.
+    #[new]
+    #[pyo3(signature = (max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(max_centroids: i64) -> PyResult<Self> {
+        let max_size = validate_max_centroids(max_centroids)?;
+        Ok(Self::from_map(DigestMap::new(max_size)))
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Returns the keys in the order in which they were first seen.
+    pub fn keys<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let map = self.state.lock();
+        map.keys().map(|key| key.to_object(py)).collect()
+    }
+
+    /// Updates the digest for `key` (in-place) with a single float value.
+    #[pyo3(signature = (key, x, w=None))]
+    pub fn update(
+        &self,
+        key: &Bound<'_, PyAny>,
+        x: f64,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        let key = Value::extract(key)?;
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        self.state.lock().add(&key, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digests (in-place) with a sequence of float values,
+    /// each added to the digest for the key at the same position.
+    #[pyo3(signature = (keys, x, w=None))]
+    pub fn batch_update(
+        &self,
+        keys: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let mut keys = Value::extract_all(keys)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if keys.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "keys must have the same length as x.",
+            ));
+        }
+        let mut weights = validate_weights(w, values.len())?;
+        let mut values: Vec<f64> = values.iter(py).collect();
+        if skip_nan && values.iter().any(|v| v.is_nan()) {
+            // missing values are left out together with their keys/weights
+            let keep: Vec<bool> = values.iter().map(|v| !v.is_nan()).collect();
+            let mut flags = keep.iter();
+            keys.retain(|_| *flags.next().unwrap_or(&true));
+            if let Some(weights) = &mut weights {
+                let mut flags = keep.iter();
+                weights.retain(|_| *flags.next().unwrap_or(&true));
+            }
+            values.retain(|v| !v.is_nan());
+        }
+        validate_values(values.iter().copied())?;
+        py.detach(|| {
+            self.state
+                .lock()
+                .add_grouped(&keys, &values, weights.as_deref())
+                .map_err(malloc_error)
+        })
+    }
+
+    /// Estimates the quantile of the digest for `key` for a given
+    /// cumulative probability `q`.
+    pub fn quantile(&self, key: &Bound<'_, PyAny>, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(key, |digest| Ok(digest.estimate_quantile(q)))
+    }
+
+    /// Estimates the quantiles of the digest for `key` for given
+    /// cumulative probabilities `q`.
+    pub fn quantile_vec(
+        &self,
+        key: &Bound<'_, PyAny>,
+        q: Vec<f64>,
+    ) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(key, |digest| {
+            Ok(q.iter().map(|&q_i| digest.estimate_quantile(q_i)).collect())
+        })
+    }
+
+    /// Estimates the percentile of the digest for `key` for a given
+    /// cumulative probability `p` (%).
+    pub fn percentile(&self, key: &Bound<'_, PyAny>, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.with_digest(key, |digest| Ok(digest.estimate_quantile(0.01 * p)))
+    }
+
+    /// Estimates the median of the digest for `key`.
+    pub fn median(&self, key: &Bound<'_, PyAny>) -> PyResult<f64> {
+        self.with_digest(key, |digest| Ok(digest.estimate_quantile(0.5)))
+    }
+
+    /// Estimates the CDF of the digest for `key` at the value `x`.
+    pub fn cdf(&self, key: &Bound<'_, PyAny>, x: f64) -> PyResult<f64> {
+        self.with_digest(key, |digest| Ok(digest.estimate_rank(x)))
+    }
+
+    /// Merges this map with another, returning a new TDigestMap.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another map into this one (in-place), key by key.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        self.state.lock().merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of all digests.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.lock_flushed()?.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a TDigestMap from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let map = DigestMap::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_map(map))
+    }
+
+    /// Writes the binary representation of all digests to a binary file
+    /// object or socket, in chunks.
+    pub fn write_to(&self, file: &Bound<'_, PyAny>) -> PyResult<()> {
+        let writer = FileWriter::new(file)?;
+        let (header, len) = {
+            let map = self.state.lock();
+            (map.header(), map.entries.len())
+        };
+        // keys added meanwhile are left out, as the length is written
+        writer.write_chunked(&self.state, header, len, |map, i, out| {
+            map.entries[i].1.flush()?;
+            map.write_entry(i, out)
+        })
+    }
+
+    /// Reads a TDigestMap written by `write_to` (or `to_bytes`) from a
+    /// binary file object or socket, digest by digest.
+    #[staticmethod]
+    pub fn read_from(file: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let mut reader = FileReader::new(file)?;
+        reader.header(&MAGIC, VERSION)?;
+        Ok(Self::from_map(DigestMap::read(&mut reader)?))
+    }
+
+    /// Returns a dict representation of all digests.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let map = self.lock_flushed()?;
+        let digests = PyDict::new(py);
+        for (key, entry) in &map.entries {
+            let digest = wrap_digest(entry.digest.clone());
+            digests.set_item(key.to_object(py)?, digest.to_dict(py)?)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("max_centroids", map.max_size)?;
+        dict.set_item("digests", digests)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a TDigestMap from a dict.
+    #[staticmethod]
+    pub fn from_dict(map_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            map_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let max_size =
+            validate_max_centroids(get("max_centroids")?.extract()?)?;
+        let mut map = DigestMap::new(max_size);
+        for (key, digest_dict) in get("digests")?.cast::<PyDict>()?.iter() {
+            let digest =
+                PyTDigest::from_dict(digest_dict.cast::<PyDict>()?, false)?
+                    .state
+                    .into_inner()
+                    .digest;
+            map.insert(Value::extract(&key)?, digest).ok_or_else(|| {
+                SerializationError::new_err(
+                    "Inconsistent TDigestMap keys or max_centroids.",
+                )
+            })?;
+        }
+        Ok(Self::from_map(map))
+    }
+
+    /// TDigestMap.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_map(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest_map) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest_map) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via TDigestMap.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(digest_map) returns the number of keys.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().len()
+    }
+
+    /// Magic method: `key in digest_map` checks for a digest for `key`.
+    pub fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key = Value::extract(key)?;
+        Ok(self.state.lock().contains(&key))
+    }
+
+    /// Magic method: digest_map[key] returns a copy of the digest for
+    /// `key` as a TDigest.
+    pub fn __getitem__(&self, key: &Bound<'_, PyAny>) -> PyResult<PyTDigest> {
+        self.with_digest(key, |digest| Ok(wrap_digest(digest.clone())))
+    }
+
+    /// Magic method: repr/str(TDigestMap) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("TDigestMap(max_centroids={})", self.state.lock().max_size)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        Ok(self.lock_flushed()?.same_digests(&other))
+    }
+
+    /// Magic method: map1 + map2 returns map1.merge(map2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: map1 += map2 merges map2 into map1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,615 @@
+//! Digest with exact weights for heavily repeated values
+//!
+//! Values that make up at least `min_fraction` of the total weight (e.g.
+//! HTTP status codes mixed into a latency metric) are kept exactly, as
+//! (value, weight) entries beside a TDigest of the other values. A t-digest
+//! merges such ties with their neighbours, which biases the quantiles
+//! around them; here every quantile within the weight of a tracked value is
+//! that value.
+//!
+//! Each new value is tracked at first. Once more than `2 / min_fraction`
+//! values are tracked, the ones below `min_fraction` of the total weight
+//! are moved into the digest, which leaves at most `1 / min_fraction` of
+//! them. A value that only becomes frequent later keeps its earlier weight
+//! in the digest.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, Pairs};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::{BTreeMap, TryReserveError};
+
+const MAGIC: [u8; 8] = *b"FASTDSC~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+pub struct DiscreteDigest {
+    min_fraction: f64,
+    /// Tracked values with their exact weights
+    exact: BTreeMap<OrderedFloat<f64>, f64>,
+    exact_mass: f64,
+    /// The other values; its own count is not the number of values
+    digest: TDigest,
+    count: u64,
+}
+
+impl DiscreteDigest {
+    pub fn new(
+        min_fraction: f64,
+        max_size: usize,
+    ) -> Result<Self, TryReserveError> {
+        Ok(Self {
+            min_fraction,
+            exact: BTreeMap::new(),
+            exact_mass: 0.0,
+            digest: TDigest::new_with_size(max_size)?,
+            count: 0,
+        })
+    }
+
+    /// Number of tracked values above which the light ones are moved into
+    /// the digest.
+    #[inline]
+    fn capacity(&self) -> usize {
+        (2.0 / self.min_fraction).ceil() as usize
+    }
+
+    pub fn mass(&self) -> f64 {
+        self.exact_mass + self.digest.mass()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.digest.is_empty()
+    }
+
+    pub fn min(&self) -> f64 {
+        let first = self.exact.keys().next().map_or(f64::NAN, |x| x.0);
+        first.min(self.digest.min())
+    }
+
+    pub fn max(&self) -> f64 {
+        let last = self.exact.keys().next_back().map_or(f64::NAN, |x| x.0);
+        last.max(self.digest.max())
+    }
+
+    pub fn sum(&self) -> f64 {
+        let exact: f64 = self.exact.iter().map(|(x, w)| x.0 * w).sum();
+        exact + self.digest.sum()
+    }
+
+    /// Adds `(value, weight)` pairs in any order.
+    pub fn add_batch(
+        &mut self,
+        pairs: &[(OrderedFloat<f64>, f64)],
+    ) -> Result<(), TryReserveError> {
+        let mut evicted = Vec::new();
+        let mut evicted_mass = 0.0;
+        for &(x, weight) in pairs {
+            *self.exact.entry(x).or_insert(0.0) += weight;
+            self.exact_mass += weight;
+            if self.exact.len() > self.capacity() {
+                evicted_mass += self.evict(evicted_mass, &mut evicted)?;
+            }
+        }
+        self.count += pairs.len() as u64;
+        self.merge_evicted(&mut evicted)
+    }
+
+    /// Moves the tracked values below `min_fraction` of the total weight
+    /// to `evicted`, where `pending` of the total already waits for the
+    /// digest. Returns the weight that was moved.
+    fn evict(
+        &mut self,
+        pending: f64,
+        evicted: &mut Pairs,
+    ) -> Result<f64, TryReserveError> {
+        let floor = self.min_fraction * (self.mass() + pending);
+        evicted.try_reserve(self.exact.len())?;
+        let mut moved = 0.0;
+        self.exact.retain(|&x, &mut weight| {
+            let keep = weight >= floor;
+            if !keep {
+                evicted.push((x, weight));
+                moved += weight;
+            }
+            keep
+        });
+        self.exact_mass = self.exact.values().sum();
+        Ok(moved)
+    }
+
+    fn merge_evicted(
+        &mut self,
+        evicted: &mut Pairs,
+    ) -> Result<(), TryReserveError> {
+        if evicted.is_empty() {
+            return Ok(());
+        }
+        TDigest::sort_pairs(evicted, 1)?;
+        self.digest.merge_sorted_weighted_inplace(evicted)
+    }
+
+    /// Merges `other` (with the same `min_fraction`) into this digest.
+    pub fn merge(
+        &mut self,
+        other: &DiscreteDigest,
+    ) -> Result<(), TryReserveError> {
+        for (&x, &weight) in &other.exact {
+            *self.exact.entry(x).or_insert(0.0) += weight;
+        }
+        self.exact_mass = self.exact.values().sum();
+        self.digest.merge_digest_inplace(&other.digest)?;
+        self.count += other.count;
+        if self.exact.len() > self.capacity() {
+            let mut evicted = Vec::new();
+            self.evict(0.0, &mut evicted)?;
+            self.merge_evicted(&mut evicted)?;
+        }
+        Ok(())
+    }
+
+    /// Weight of the digest below `x`.
+    #[inline]
+    fn digest_below(&self, x: f64) -> f64 {
+        if self.digest.is_empty() {
+            return 0.0;
+        }
+        self.digest.estimate_rank(x) * self.digest.mass()
+    }
+
+    /// Estimates the quantile `q`. The digest must not be empty.
+    pub fn estimate_quantile(&self, q: f64) -> f64 {
+        let target = q * self.mass();
+        let digest_mass = self.digest.mass();
+        let digest_quantile = |below: f64| {
+            let q = ((target - below) / digest_mass).clamp(0.0, 1.0);
+            self.digest.estimate_quantile(q)
+        };
+        // exact weight below the current value, and the value before it
+        let mut below = 0.0;
+        let mut lower = f64::NEG_INFINITY;
+        for (&x, &weight) in &self.exact {
+            let start = below + self.digest_below(x.0);
+            if target < start {
+                return digest_quantile(below).clamp(lower, x.0);
+            }
+            if target <= start + weight {
+                return x.0;
+            }
+            below += weight;
+            lower = x.0;
+        }
+        if !self.digest.is_empty() {
+            digest_quantile(below).max(lower)
+        } else {
+            lower
+        }
+    }
+
+    /// Estimates the rank of `x`, where a tracked value counts half of its
+    /// weight at itself. The digest must not be empty.
+    pub fn estimate_rank(&self, x: f64) -> f64 {
+        let key = OrderedFloat(x);
+        let below: f64 = self.exact.range(..key).map(|(_, w)| w).sum();
+        let at = self.exact.get(&key).copied().unwrap_or(0.0);
+        let rank = (self.digest_below(x) + below + 0.5 * at) / self.mass();
+        rank.clamp(0.0, 1.0)
+    }
+
+    /// A TDigest of all values, with the tracked ones as centroids.
+    pub fn to_digest(&self) -> Result<TDigest, TryReserveError> {
+        let mut digest = self.digest.clone();
+        let mut pairs = Vec::new();
+        pairs.try_reserve_exact(self.exact.len())?;
+        pairs.extend(self.exact.iter().map(|(&x, &weight)| (x, weight)));
+        digest.merge_sorted_weighted_inplace(&pairs)?;
+        if digest.is_empty() {
+            return Ok(digest);
+        }
+        TDigest::new(
+            digest.centroids().to_vec(),
+            digest.max_size(),
+            digest.mass(),
+            digest.sum(),
+            digest.min(),
+            digest.max(),
+            self.count.into(),
+        )
+    }
+
+    /// Whether both hold equal state.
+    pub fn same_state(&self, other: &DiscreteDigest) -> bool {
+        self.min_fraction == other.min_fraction
+            && self.exact == other.exact
+            && self.count == other.count
+            && self.digest == other.digest
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.min_fraction);
+        writer.u64(self.count);
+        let (values, weights): (Vec<f64>, Vec<f64>) =
+            self.exact.iter().map(|(x, &weight)| (x.0, weight)).unzip();
+        writer.f64s(&values);
+        writer.f64s(&weights);
+        writer.bytes(&self.digest.to_bytes()?);
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let min_fraction = reader.f64()?;
+        let count = reader.u64()?;
+        let values = reader.f64s()?;
+        let weights = reader.f64s()?;
+        let digest = TDigest::from_bytes(reader.bytes()?)?;
+        reader.finish()?;
+        Self::from_parts(min_fraction, count, values, weights, digest)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Returns None unless `min_fraction` is valid, the tracked values are
+    /// distinct and finite, with weights that are finite and greater than 0,
+    /// and the digest passes the strict checks of `TDigest::validate`.
+    fn from_parts(
+        min_fraction: f64,
+        count: u64,
+        values: Vec<f64>,
+        weights: Vec<f64>,
+        digest: TDigest,
+    ) -> Option<Self> {
+        if !valid_min_fraction(min_fraction)
+            || values.len() != weights.len()
+            || digest.validate().is_err()
+        {
+            return None;
+        }
+        let mut exact = BTreeMap::new();
+        for (x, weight) in values.into_iter().zip(weights) {
+            let valid = x.is_finite() && weight.is_finite() && weight > 0.0;
+            if !valid || exact.insert(OrderedFloat(x), weight).is_some() {
+                return None;
+            }
+        }
+        let exact_mass = exact.values().sum();
+        Some(Self {
+            min_fraction,
+            exact,
+            exact_mass,
+            digest,
+            count,
+        })
+    }
+}
+
+#[inline]
+fn valid_min_fraction(min_fraction: f64) -> bool {
+    min_fraction > 0.0 && min_fraction <= 1.0
+}
+
+#[pyclass(name = "DiscreteTDigest", module = "fastdigest", frozen)]
+pub struct PyDiscreteTDigest {
+    state: Mutex<DiscreteDigest>,
+}
+
+impl PyDiscreteTDigest {
+    fn from_discrete(discrete: DiscreteDigest) -> Self {
+        Self {
+            state: Mutex::new(discrete),
+        }
+    }
+
+    /// Runs `f` on the digest, raising EmptyDigestError if it is empty.
+    fn with_digest<T>(
+        &self,
+        f: impl FnOnce(&DiscreteDigest) -> T,
+    ) -> PyResult<T> {
+        let discrete = self.state.lock();
+        if discrete.is_empty() {
+            return Err(EmptyDigestError::new_err("DiscreteTDigest is empty."));
+        }
+        Ok(f(&discrete))
+    }
+}
+
+#[pymethods]
+impl PyDiscreteTDigest {
+    /// Constructs a new empty DiscreteTDigest instance that keeps values
+    /// with at least `min_fraction` of the total weight exactly.
+    #[new]
+    #[pyo3(signature = (min_fraction=0.01, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(min_fraction: f64, max_centroids: i64) -> PyResult<Self> {
+        if !valid_min_fraction(min_fraction) {
+            return Err(PyValueError::new_err(
+                "min_fraction must be greater than 0 and at most 1.",
+            ));
+        }
+        let max_size = validate_max_centroids(max_centroids)?;
+        let discrete = DiscreteDigest::new(min_fraction, max_size)
+            .map_err(malloc_error)?;
+        Ok(Self::from_discrete(discrete))
+    }
+
+    /// Getter property: returns the min_fraction parameter.
+    #[getter(min_fraction)]
+    pub fn get_min_fraction(&self) -> f64 {
+        self.state.lock().min_fraction
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().digest.max_size()
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Updates the digest (in-place) with a single value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut discrete = self.state.lock();
+        discrete
+            .add_batch(&[(OrderedFloat(x), weight)])
+            .map_err(malloc_error)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let weights = validate_weights(w, values.len())?;
+        let mut pairs = Vec::new();
+        for (j, x) in values.iter(py).enumerate() {
+            // missing values are left out together with their weights
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| self.state.lock().add_batch(&pairs).map_err(malloc_error))
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(|discrete| discrete.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.with_digest(|discrete| discrete.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        self.with_digest(|discrete| discrete.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF at the value `x`.
+    pub fn cdf(&self, x: f64) -> PyResult<f64> {
+        self.with_digest(|discrete| discrete.estimate_rank(x))
+    }
+
+    /// Returns the weighted mean of the values.
+    pub fn mean(&self) -> PyResult<f64> {
+        self.with_digest(|discrete| discrete.sum() / discrete.mass())
+    }
+
+    /// Returns the total weight of the values.
+    pub fn mass(&self) -> f64 {
+        self.state.lock().mass()
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        self.with_digest(DiscreteDigest::min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        self.with_digest(DiscreteDigest::max)
+    }
+
+    /// Returns the tracked values with at least min_fraction of the total
+    /// weight as a dict of their weights.
+    pub fn exact_values<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let discrete = self.state.lock();
+        let floor = discrete.min_fraction * discrete.mass();
+        let dict = PyDict::new(py);
+        for (x, &weight) in &discrete.exact {
+            if weight >= floor {
+                dict.set_item(x.0, weight)?;
+            }
+        }
+        Ok(dict)
+    }
+
+    /// Returns a TDigest of all values, with the exactly tracked ones as
+    /// centroids.
+    pub fn snapshot(&self) -> PyResult<PyTDigest> {
+        let digest = self.state.lock().to_digest().map_err(malloc_error)?;
+        Ok(wrap_digest(digest))
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Merges this digest with another with the same min_fraction,
+    /// returning a new DiscreteTDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same min_fraction into this one
+    /// (in-place).
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut discrete = self.state.lock();
+        if discrete.min_fraction != other.min_fraction {
+            return Err(PyValueError::new_err(
+                "Cannot merge DiscreteTDigests with different min_fraction.",
+            ));
+        }
+        discrete.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the digest.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.state.lock().to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a DiscreteTDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let discrete = DiscreteDigest::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_discrete(discrete))
+    }
+
+    /// Returns a dict representation of the digest.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let discrete = self.state.lock();
+        let digest = wrap_digest(discrete.digest.clone());
+        let (values, weights): (Vec<f64>, Vec<f64>) = discrete
+            .exact
+            .iter()
+            .map(|(x, &weight)| (x.0, weight))
+            .unzip();
+        let dict = PyDict::new(py);
+        dict.set_item("min_fraction", discrete.min_fraction)?;
+        dict.set_item("n_values", discrete.count)?;
+        dict.set_item("exact_values", values)?;
+        dict.set_item("exact_weights", weights)?;
+        dict.set_item("digest", digest.to_dict(py)?)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a DiscreteTDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(discrete_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            discrete_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let digest = PyTDigest::from_dict(get("digest")?.cast()?, false)?;
+        let discrete = DiscreteDigest::from_parts(
+            get("min_fraction")?.extract()?,
+            get("n_values")?.extract()?,
+            get("exact_values")?.extract()?,
+            get("exact_weights")?.extract()?,
+            digest.state.into_inner().digest,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent DiscreteTDigest values.")
+        })?;
+        Ok(Self::from_discrete(discrete))
+    }
+
+    /// DiscreteTDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_discrete(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via DiscreteTDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(DiscreteTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let discrete = self.state.lock();
+        format!(
+            "DiscreteTDigest(min_fraction={:?}, max_centroids={})",
+            discrete.min_fraction,
+            discrete.digest.max_size()
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        self.state.lock().same_state(&other)
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
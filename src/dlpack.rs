@@ -0,0 +1,368 @@
+//! Reading of numeric tensors through the DLPack protocol
+//!
+//! Objects implementing `__dlpack__` (e.g. PyTorch, JAX or CuPy tensors)
+//! hand out a `DLManagedTensor` in a capsule. Tensors in memory that the
+//! CPU can access are read in place; those on other devices (e.g. CUDA) are
+//! first copied to the CPU once by their producer. Objects that cannot
+//! export a tensor of numbers are left to be read item by item.
+//! See https://dmlc.github.io/dlpack/latest/
+
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyDict};
+use std::ffi::c_void;
+use std::{ptr, slice};
+
+const DL_CPU: i32 = 1;
+const DL_CUDA_HOST: i32 = 3;
+const DL_ROCM_HOST: i32 = 11;
+const DL_CUDA_MANAGED: i32 = 13;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DLDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DLDataType {
+    code: u8,
+    bits: u8,
+    lanes: u16,
+}
+
+#[repr(C)]
+struct DLTensor {
+    data: *mut c_void,
+    device: DLDevice,
+    ndim: i32,
+    dtype: DLDataType,
+    shape: *const i64,
+    /// Strides in elements, or null for a compact row-major tensor
+    strides: *const i64,
+    byte_offset: u64,
+}
+
+#[repr(C)]
+struct DLManagedTensor {
+    dl_tensor: DLTensor,
+    manager_ctx: *mut c_void,
+    deleter: Option<unsafe extern "C" fn(*mut DLManagedTensor)>,
+}
+
+/// Whether memory on a device of this type can be read by the CPU.
+fn is_cpu_accessible(device_type: i32) -> bool {
+    matches!(
+        device_type,
+        DL_CPU | DL_CUDA_HOST | DL_ROCM_HOST | DL_CUDA_MANAGED
+    )
+}
+
+/// Element types of tensors that can be read as floats.
+#[derive(Clone, Copy)]
+enum DlpackType {
+    F64,
+    F32,
+    F16,
+    BF16,
+    I64,
+    I32,
+    I16,
+    I8,
+    U64,
+    U32,
+    U16,
+    U8,
+}
+
+impl DlpackType {
+    fn from_dtype(dtype: DLDataType) -> Option<Self> {
+        if dtype.lanes != 1 {
+            return None;
+        }
+        match (dtype.code, dtype.bits) {
+            (2, 64) => Some(Self::F64),
+            (2, 32) => Some(Self::F32),
+            (2, 16) => Some(Self::F16),
+            (4, 16) => Some(Self::BF16),
+            (0, 64) => Some(Self::I64),
+            (0, 32) => Some(Self::I32),
+            (0, 16) => Some(Self::I16),
+            (0, 8) => Some(Self::I8),
+            (1, 64) => Some(Self::U64),
+            (1, 32) => Some(Self::U32),
+            (1, 16) => Some(Self::U16),
+            (1, 8) => Some(Self::U8),
+            _ => None,
+        }
+    }
+
+    /// Reads the element at offset `i` (in elements) as `f64`.
+    ///
+    /// # Safety
+    ///
+    /// `data` offset by `i` elements of this type must be within a buffer.
+    #[inline]
+    unsafe fn read(self, data: *const c_void, i: isize) -> f64 {
+        unsafe {
+            match self {
+                Self::F64 => ptr::read_unaligned(data.cast::<f64>().offset(i)),
+                Self::F32 => {
+                    ptr::read_unaligned(data.cast::<f32>().offset(i)) as f64
+                }
+                Self::F16 => f16_to_f64(ptr::read_unaligned(
+                    data.cast::<u16>().offset(i),
+                )),
+                Self::BF16 => {
+                    let bits =
+                        ptr::read_unaligned(data.cast::<u16>().offset(i));
+                    f32::from_bits((bits as u32) << 16) as f64
+                }
+                Self::I64 => {
+                    ptr::read_unaligned(data.cast::<i64>().offset(i)) as f64
+                }
+                Self::I32 => {
+                    ptr::read_unaligned(data.cast::<i32>().offset(i)) as f64
+                }
+                Self::I16 => {
+                    ptr::read_unaligned(data.cast::<i16>().offset(i)) as f64
+                }
+                Self::I8 => {
+                    ptr::read_unaligned(data.cast::<i8>().offset(i)) as f64
+                }
+                Self::U64 => {
+                    ptr::read_unaligned(data.cast::<u64>().offset(i)) as f64
+                }
+                Self::U32 => {
+                    ptr::read_unaligned(data.cast::<u32>().offset(i)) as f64
+                }
+                Self::U16 => {
+                    ptr::read_unaligned(data.cast::<u16>().offset(i)) as f64
+                }
+                Self::U8 => {
+                    ptr::read_unaligned(data.cast::<u8>().offset(i)) as f64
+                }
+            }
+        }
+    }
+}
+
+/// Converts an IEEE 754 half-precision float.
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f64;
+    match exponent {
+        0 => sign * fraction * 2f64.powi(-24),
+        0x1f if fraction == 0.0 => sign * f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => sign * (1.0 + fraction / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// Numeric tensor in CPU-accessible memory, owned until it is dropped.
+pub struct DlpackValues {
+    tensor: *mut DLManagedTensor,
+    dtype: DlpackType,
+    data: *const c_void,
+    len: usize,
+}
+
+impl DlpackValues {
+    /// Reads `obj` through `__dlpack__`, if it implements it and exports a
+    /// tensor of a supported type. Tensors on devices the CPU cannot access
+    /// are requested as a copy on the CPU, falling back to their `cpu`
+    /// method for older producers. `None` for any other object, which can
+    /// still be read item by item.
+    pub fn extract(obj: &Bound<'_, PyAny>) -> Option<Self> {
+        if !obj.hasattr("__dlpack__").unwrap_or(false) {
+            return None;
+        }
+        Self::from_capsule(&Self::export(obj).ok()?)
+    }
+
+    /// Calls `__dlpack__`, with a copy to the CPU if needed.
+    fn export<'py>(obj: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        let (device_type, _): (i32, i32) =
+            obj.call_method0("__dlpack_device__")?.extract()?;
+        if is_cpu_accessible(device_type) {
+            return obj.call_method0("__dlpack__");
+        }
+        let kwargs = PyDict::new(obj.py());
+        kwargs.set_item("dl_device", (DL_CPU, 0))?;
+        kwargs.set_item("copy", true)?;
+        obj.call_method("__dlpack__", (), Some(&kwargs))
+            .or_else(|_| obj.call_method0("cpu")?.call_method0("__dlpack__"))
+    }
+
+    /// Takes ownership of the tensor of a "dltensor" capsule, renaming it
+    /// to "used_dltensor" as the protocol requires. `None` if the capsule is
+    /// invalid or the tensor not a readable array of numbers, in which case
+    /// it is released again.
+    fn from_capsule(capsule: &Bound<'_, PyAny>) -> Option<Self> {
+        let capsule = capsule.cast::<PyCapsule>().ok()?;
+        let tensor = capsule.pointer().cast::<DLManagedTensor>();
+        if capsule.name().ok()? != Some(c"dltensor") || tensor.is_null() {
+            return None;
+        }
+        // SAFETY: the capsule is valid and its tensor not consumed yet
+        if unsafe {
+            ffi::PyCapsule_SetName(capsule.as_ptr(), c"used_dltensor".as_ptr())
+        } != 0
+        {
+            // clear the error indicator; the capsule still owns the tensor
+            drop(PyErr::fetch(capsule.py()));
+            return None;
+        }
+        let mut values = Self {
+            tensor,
+            dtype: DlpackType::F64,
+            data: ptr::null(),
+            len: 0,
+        };
+        // SAFETY: the tensor is owned by `values` from here on
+        let dl_tensor = unsafe { &(*tensor).dl_tensor };
+        if !is_cpu_accessible(dl_tensor.device.device_type) {
+            return None;
+        }
+        values.dtype = DlpackType::from_dtype(dl_tensor.dtype)?;
+        let shape = values.shape();
+        if shape.iter().any(|&n| n < 0) {
+            return None;
+        }
+        values.len = shape.iter().map(|&n| n as usize).product();
+        if values.len > 0 && dl_tensor.data.is_null() {
+            return None;
+        }
+        // SAFETY: the offset points into the data of the tensor
+        values.data = unsafe {
+            dl_tensor
+                .data
+                .cast::<u8>()
+                .add(dl_tensor.byte_offset as usize)
+                .cast()
+        };
+        Some(values)
+    }
+
+    fn shape(&self) -> &[i64] {
+        // SAFETY: the tensor holds `ndim` sizes and (unless null) strides
+        unsafe {
+            let dl_tensor = &(*self.tensor).dl_tensor;
+            match dl_tensor.ndim {
+                0 => &[],
+                ndim => slice::from_raw_parts(dl_tensor.shape, ndim as usize),
+            }
+        }
+    }
+
+    fn strides(&self) -> Option<&[i64]> {
+        // SAFETY: see `shape`
+        unsafe {
+            let dl_tensor = &(*self.tensor).dl_tensor;
+            if dl_tensor.strides.is_null() || dl_tensor.ndim == 0 {
+                return None;
+            }
+            Some(slice::from_raw_parts(
+                dl_tensor.strides,
+                dl_tensor.ndim as usize,
+            ))
+        }
+    }
+
+    /// Whether the elements are stored in row-major order without gaps.
+    pub fn is_contiguous(&self) -> bool {
+        let Some(strides) = self.strides() else {
+            return true;
+        };
+        let mut expected = 1;
+        for (&n, &stride) in self.shape().iter().zip(strides).rev() {
+            // the stride of a dimension of size 1 does not matter
+            if n != 1 && stride != expected {
+                return false;
+            }
+            expected *= n;
+        }
+        true
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Iterates over the elements of a contiguous tensor.
+    pub fn iter(&self) -> DlpackIter<'_> {
+        DlpackIter { values: self, i: 0 }
+    }
+
+    /// Copies the elements of a strided tensor in row-major order.
+    pub fn to_vec(&self) -> Vec<f64> {
+        let mut values = Vec::with_capacity(self.len);
+        match self.strides() {
+            Some(strides) if self.len > 0 => {
+                self.collect_strided(strides, 0, 0, &mut values)
+            }
+            _ => values.extend(self.iter()),
+        }
+        values
+    }
+
+    fn collect_strided(
+        &self,
+        strides: &[i64],
+        dim: usize,
+        offset: isize,
+        out: &mut Vec<f64>,
+    ) {
+        let shape = self.shape();
+        if dim == shape.len() {
+            // SAFETY: the offset is within the tensor by its strides
+            out.push(unsafe { self.dtype.read(self.data, offset) });
+            return;
+        }
+        for j in 0..shape[dim] {
+            let step = (j * strides[dim]) as isize;
+            self.collect_strided(strides, dim + 1, offset + step, out);
+        }
+    }
+}
+
+impl Drop for DlpackValues {
+    fn drop(&mut self) {
+        // SAFETY: the tensor is owned by us and released exactly once
+        unsafe {
+            if let Some(deleter) = (*self.tensor).deleter {
+                deleter(self.tensor)
+            }
+        }
+    }
+}
+
+pub struct DlpackIter<'a> {
+    values: &'a DlpackValues,
+    i: usize,
+}
+
+impl Iterator for DlpackIter<'_> {
+    type Item = f64;
+
+    #[inline]
+    fn next(&mut self) -> Option<f64> {
+        if self.i == self.values.len {
+            return None;
+        }
+        let i = self.i;
+        self.i += 1;
+        // SAFETY: `i` is within the bounds of the contiguous tensor
+        Some(unsafe { self.values.dtype.read(self.values.data, i as isize) })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.values.len - self.i;
+        (remaining, Some(remaining))
+    }
+}
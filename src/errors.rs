@@ -0,0 +1,75 @@
+//! Exceptions of the `fastdigest.errors` submodule
+//!
+//! All of them derive from `FastDigestError`, which derives from
+//! ValueError, so existing `except ValueError` handlers keep catching them.
+//! `PrecisionWarning` is a warning category, ignored by default.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyUserWarning, PyValueError};
+use pyo3::prelude::*;
+
+create_exception!(
+    fastdigest.errors,
+    FastDigestError,
+    PyValueError,
+    "Base class of the errors raised by fastdigest."
+);
+create_exception!(
+    fastdigest.errors,
+    EmptyDigestError,
+    FastDigestError,
+    "A digest or sketch without values was queried."
+);
+create_exception!(
+    fastdigest.errors,
+    InvalidQuantileError,
+    FastDigestError,
+    "A quantile, percentile or rank is out of range."
+);
+create_exception!(
+    fastdigest.errors,
+    InvalidValueError,
+    FastDigestError,
+    "A value is not finite, or a weight is not finite and greater than 0."
+);
+create_exception!(
+    fastdigest.errors,
+    SerializationError,
+    FastDigestError,
+    "A dict or binary representation is missing keys, corrupt or \
+     inconsistent."
+);
+create_exception!(
+    fastdigest.errors,
+    PrecisionWarning,
+    PyUserWarning,
+    "A Decimal value cannot be represented exactly as a float."
+);
+
+/// Adds the exceptions to the `fastdigest.errors` submodule.
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("FastDigestError", py.get_type::<FastDigestError>())?;
+    m.add("EmptyDigestError", py.get_type::<EmptyDigestError>())?;
+    m.add(
+        "InvalidQuantileError",
+        py.get_type::<InvalidQuantileError>(),
+    )?;
+    m.add("InvalidValueError", py.get_type::<InvalidValueError>())?;
+    m.add("SerializationError", py.get_type::<SerializationError>())?;
+    m.add("PrecisionWarning", py.get_type::<PrecisionWarning>())?;
+    Ok(())
+}
+
+/// Ignores PrecisionWarning unless a filter added later enables it.
+pub fn ignore_precision_warnings(py: Python<'_>) -> PyResult<()> {
+    let kwargs = pyo3::types::PyDict::new(py);
+    kwargs.set_item("category", py.get_type::<PrecisionWarning>())?;
+    kwargs.set_item("append", true)?;
+    py.import("warnings")?.call_method(
+        "filterwarnings",
+        ("ignore",),
+        Some(&kwargs),
+    )?;
+    Ok(())
+}
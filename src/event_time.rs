@@ -0,0 +1,641 @@
+//! Digest over event time, as sub-digests of fixed event-time intervals
+//!
+//! Values are tagged with their event timestamps, which may arrive in any
+//! order, and go to the digest of their interval of `interval` time units
+//! (aligned to multiples of that length). A watermark set by `expire`
+//! drops the intervals that end at or before it, and later values older
+//! than the watermark are counted as late instead of being added. Queries
+//! merge the digests of all intervals or of a range of event time, at the
+//! granularity of one interval.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::SerializationError;
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::{BTreeMap, TryReserveError};
+
+const MAGIC: [u8; 8] = *b"FASTETD~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+pub struct EventTimeDigest {
+    interval: f64,
+    max_size: usize,
+    watermark: Option<f64>,
+    /// Number of values dropped for preceding the watermark
+    n_late: u64,
+    /// Buckets by epoch (the number of their interval since time 0)
+    buckets: BTreeMap<i64, Entry>,
+}
+
+impl EventTimeDigest {
+    pub fn new(interval: f64, max_size: usize) -> Self {
+        Self {
+            interval,
+            max_size,
+            watermark: None,
+            n_late: 0,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    #[inline]
+    fn epoch(&self, timestamp: f64) -> i64 {
+        (timestamp / self.interval).floor() as i64
+    }
+
+    /// The event-time range `[start, end)` of the bucket of `epoch`.
+    #[inline]
+    fn range(&self, epoch: i64) -> (f64, f64) {
+        (
+            epoch as f64 * self.interval,
+            (epoch + 1) as f64 * self.interval,
+        )
+    }
+
+    #[inline]
+    fn is_late(&self, timestamp: f64) -> bool {
+        self.watermark
+            .is_some_and(|watermark| timestamp < watermark)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.values().all(|entry| entry.count() == 0)
+    }
+
+    pub fn count(&self) -> u128 {
+        self.buckets.values().map(Entry::count).sum()
+    }
+
+    /// The bucket of `epoch`, created if needed.
+    fn bucket(&mut self, epoch: i64) -> Result<&mut Entry, TryReserveError> {
+        if !self.buckets.contains_key(&epoch) {
+            self.buckets.insert(epoch, Entry::new(self.max_size)?);
+        }
+        Ok(self.buckets.get_mut(&epoch).expect("bucket was inserted"))
+    }
+
+    pub fn add(
+        &mut self,
+        timestamp: f64,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        if self.is_late(timestamp) {
+            self.n_late += 1;
+            return Ok(());
+        }
+        self.bucket(self.epoch(timestamp))?.add(x, weight)
+    }
+
+    /// Adds values at timestamps in any order, merging each bucket once
+    /// with all of its values.
+    pub fn add_batch(
+        &mut self,
+        timestamps: &[f64],
+        pairs: &[(OrderedFloat<f64>, f64)],
+    ) -> Result<(), TryReserveError> {
+        let mut runs: BTreeMap<i64, Pairs> = BTreeMap::new();
+        for (&t, &pair) in timestamps.iter().zip(pairs) {
+            if self.is_late(t) {
+                self.n_late += 1;
+                continue;
+            }
+            let run = runs.entry(self.epoch(t)).or_default();
+            run.try_reserve(1)?;
+            run.push(pair);
+        }
+        for (epoch, mut run) in runs {
+            self.bucket(epoch)?.add_batch(&mut run)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the watermark to `watermark` unless it is already later, and
+    /// drops the buckets that end at or before it.
+    pub fn expire(&mut self, watermark: f64) {
+        let watermark = self.watermark.map_or(watermark, |w| w.max(watermark));
+        self.watermark = Some(watermark);
+        self.buckets = self.buckets.split_off(&self.epoch(watermark));
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        for entry in self.buckets.values_mut() {
+            entry.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The merged digest of the buckets that overlap the event-time range
+    /// `[start, end)`, or of all buckets.
+    pub fn digest(
+        &mut self,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> Result<TDigest, TryReserveError> {
+        self.flush()?;
+        let first = start.map_or(i64::MIN, |start| self.epoch(start));
+        let digests = self
+            .buckets
+            .range(first..)
+            .take_while(|(&epoch, _)| {
+                end.is_none_or(|end| self.range(epoch).0 < end)
+            })
+            .map(|(_, entry)| entry.digest.clone())
+            .collect();
+        TDigest::merge_digests(digests, Some(self.max_size))
+    }
+
+    /// The event-time ranges of the buckets, in order.
+    pub fn ranges(&self) -> Vec<(f64, f64)> {
+        self.buckets
+            .keys()
+            .map(|&epoch| self.range(epoch))
+            .collect()
+    }
+
+    /// Merges the buckets of `other` into the buckets of the same epochs,
+    /// moving the watermark to the later of both. The intervals must
+    /// match.
+    pub fn merge(
+        &mut self,
+        other: &EventTimeDigest,
+    ) -> Result<(), TryReserveError> {
+        for (epoch, entry) in &other.buckets {
+            self.bucket(*epoch)?.merge(entry)?;
+        }
+        self.n_late += other.n_late;
+        if let Some(watermark) = self.watermark.or(other.watermark) {
+            // expire keeps the later of both watermarks
+            self.expire(other.watermark.unwrap_or(watermark));
+        }
+        Ok(())
+    }
+
+    /// Whether both hold equal state. Both must be flushed.
+    pub fn same_state(&self, other: &EventTimeDigest) -> bool {
+        self.interval == other.interval
+            && self.max_size == other.max_size
+            && self.watermark == other.watermark
+            && self.n_late == other.n_late
+            && self.buckets.len() == other.buckets.len()
+            && self
+                .buckets
+                .iter()
+                .zip(&other.buckets)
+                .all(|((e1, a), (e2, b))| e1 == e2 && a.digest == b.digest)
+    }
+
+    /// Binary representation; the buckets must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.interval);
+        writer.u64(self.max_size as u64);
+        writer.u8(self.watermark.is_some() as u8);
+        writer.f64(self.watermark.unwrap_or(0.0));
+        writer.u64(self.n_late);
+        writer.u64(self.buckets.len() as u64);
+        for (epoch, entry) in &self.buckets {
+            writer.i64(*epoch);
+            writer.bytes(&entry.digest.to_bytes()?);
+        }
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let interval = reader.f64()?;
+        let max_size = reader.u64()?;
+        let has_watermark = reader.u8()?;
+        let watermark = reader.f64()?;
+        let watermark = match has_watermark {
+            0 => None,
+            1 => Some(watermark),
+            _ => return Err(BytesError::CorruptData),
+        };
+        let n_late = reader.u64()?;
+        // each bucket has an epoch and a length-prefixed digest
+        let len = reader.seq_len(16)?;
+        let mut buckets = Vec::new();
+        buckets
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            let epoch = reader.i64()?;
+            buckets.push((epoch, TDigest::from_bytes(reader.bytes()?)?));
+        }
+        reader.finish()?;
+        let max_size = usize::try_from(max_size)
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        Self::from_parts(interval, max_size, watermark, n_late, buckets)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles an event-time digest from `(epoch, digest)` buckets in
+    /// increasing order, checking that none of them has expired.
+    fn from_parts(
+        interval: f64,
+        max_size: usize,
+        watermark: Option<f64>,
+        n_late: u64,
+        buckets: Vec<(i64, TDigest)>,
+    ) -> Option<Self> {
+        if !valid_interval(interval)
+            || watermark.is_some_and(|w| !w.is_finite())
+        {
+            return None;
+        }
+        let mut event_time = Self::new(interval, max_size);
+        event_time.watermark = watermark;
+        event_time.n_late = n_late;
+        let oldest = watermark.map_or(i64::MIN, |w| event_time.epoch(w));
+        let mut previous = None;
+        for (epoch, digest) in buckets {
+            let ordered = oldest <= epoch && previous.is_none_or(|p| p < epoch);
+            if !ordered || digest.max_size() != max_size {
+                return None;
+            }
+            event_time.buckets.insert(epoch, Entry::from_digest(digest));
+            previous = Some(epoch);
+        }
+        Some(event_time)
+    }
+}
+
+#[inline]
+fn valid_interval(interval: f64) -> bool {
+    interval.is_finite() && interval > 0.0
+}
+
+fn validate_timestamps(timestamps: &[f64]) -> PyResult<()> {
+    if timestamps.iter().all(|t| t.is_finite()) {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err("Timestamps must be finite."))
+    }
+}
+
+#[pyclass(name = "EventTimeTDigest", module = "fastdigest", frozen)]
+pub struct PyEventTimeTDigest {
+    state: Mutex<EventTimeDigest>,
+}
+
+impl PyEventTimeTDigest {
+    fn from_event_time(event_time: EventTimeDigest) -> Self {
+        Self {
+            state: Mutex::new(event_time),
+        }
+    }
+
+    /// The merged digest of the event-time range as a TDigest.
+    fn range_digest(
+        &self,
+        py: Python<'_>,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<PyTDigest> {
+        py.detach(|| {
+            let digest =
+                self.state.lock().digest(start, end).map_err(malloc_error)?;
+            Ok(wrap_digest(digest))
+        })
+    }
+}
+
+#[pymethods]
+impl PyEventTimeTDigest {
+    /// Constructs a new empty EventTimeTDigest instance with a sub-digest
+    /// for every `interval` time units of event time.
+    #[new]
+    #[pyo3(signature = (interval, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(interval: f64, max_centroids: i64) -> PyResult<Self> {
+        if !valid_interval(interval) {
+            return Err(PyValueError::new_err(
+                "interval must be finite and greater than 0.",
+            ));
+        }
+        let max_size = validate_max_centroids(max_centroids)?;
+        let event_time = EventTimeDigest::new(interval, max_size);
+        Ok(Self::from_event_time(event_time))
+    }
+
+    /// Getter property: returns the interval length.
+    #[getter(interval)]
+    pub fn get_interval(&self) -> f64 {
+        self.state.lock().interval
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size
+    }
+
+    /// Getter property: returns the watermark, or None if nothing has
+    /// expired yet.
+    #[getter(watermark)]
+    pub fn get_watermark(&self) -> Option<f64> {
+        self.state.lock().watermark
+    }
+
+    /// Getter property: returns the number of data points held.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Getter property: returns the number of data points dropped for
+    /// preceding the watermark.
+    #[getter(n_late)]
+    pub fn get_n_late(&self) -> u64 {
+        self.state.lock().n_late
+    }
+
+    /// Updates the digest (in-place) with a single value at the given
+    /// event timestamp.
+    #[pyo3(signature = (timestamp, x, w=None))]
+    pub fn update(
+        &self,
+        timestamp: f64,
+        x: f64,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        validate_timestamps(&[timestamp])?;
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut event_time = self.state.lock();
+        event_time.add(timestamp, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values at the
+    /// given event timestamps, in any order.
+    #[pyo3(signature = (timestamps, x, w=None))]
+    pub fn batch_update(
+        &self,
+        timestamps: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (times, _) = extract_values(timestamps)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if times.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "timestamps must have the same length as x.",
+            ));
+        }
+        let weights = validate_weights(w, values.len())?;
+        let mut kept_times = Vec::new();
+        let mut pairs = Vec::new();
+        for (j, (t, x)) in times.iter(py).zip(values.iter(py)).enumerate() {
+            // missing values are left out together with their timestamps
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            kept_times.push(t);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_timestamps(&kept_times)?;
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| {
+            let mut event_time = self.state.lock();
+            event_time
+                .add_batch(&kept_times, &pairs)
+                .map_err(malloc_error)
+        })
+    }
+
+    /// Moves the watermark (in-place) to `before_ts`, dropping the
+    /// intervals that end at or before it. An earlier watermark than the
+    /// current one has no effect.
+    pub fn expire(&self, before_ts: f64) -> PyResult<()> {
+        validate_timestamps(&[before_ts])?;
+        self.state.lock().expire(before_ts);
+        Ok(())
+    }
+
+    /// Returns the event-time ranges (start, end) of the intervals held,
+    /// in order.
+    pub fn ranges(&self) -> Vec<(f64, f64)> {
+        self.state.lock().ranges()
+    }
+
+    /// Returns the values of the intervals overlapping the event-time
+    /// range [start, end) as a TDigest, or all values.
+    #[pyo3(signature = (start=None, end=None))]
+    pub fn snapshot(
+        &self,
+        py: Python<'_>,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<PyTDigest> {
+        self.range_digest(py, start, end)
+    }
+
+    /// Estimates the quantile of all values held for a given cumulative
+    /// probability `q`.
+    pub fn quantile(&self, py: Python<'_>, q: f64) -> PyResult<f64> {
+        self.range_digest(py, None, None)?
+            .quantile(q)
+            .map(f64::from)
+    }
+
+    /// Estimates the percentile of all values held for a given cumulative
+    /// probability `p` (%).
+    pub fn percentile(&self, py: Python<'_>, p: f64) -> PyResult<f64> {
+        self.range_digest(py, None, None)?
+            .percentile(p)
+            .map(f64::from)
+    }
+
+    /// Estimates the median of all values held.
+    pub fn median(&self, py: Python<'_>) -> PyResult<f64> {
+        self.range_digest(py, None, None)?.median().map(f64::from)
+    }
+
+    /// Estimates the CDF of all values held at the value `x`.
+    pub fn cdf(&self, py: Python<'_>, x: f64) -> PyResult<f64> {
+        self.range_digest(py, None, None)?.rank(x)
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Merges this digest with another with the same interval, returning
+    /// a new EventTimeTDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same interval into this one
+    /// (in-place), interval by interval.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut event_time = self.state.lock();
+        if event_time.interval != other.interval {
+            return Err(PyValueError::new_err(
+                "Cannot merge EventTimeTDigests with different intervals.",
+            ));
+        }
+        event_time.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the digest.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let mut event_time = self.state.lock();
+        event_time.flush().map_err(malloc_error)?;
+        let bytes = event_time.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs an EventTimeTDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let event_time =
+            EventTimeDigest::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_event_time(event_time))
+    }
+
+    /// Returns a dict representation of the digest.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut event_time = self.state.lock();
+        event_time.flush().map_err(malloc_error)?;
+        let epochs: Vec<i64> = event_time.buckets.keys().copied().collect();
+        let digests = PyList::empty(py);
+        for entry in event_time.buckets.values() {
+            digests.append(wrap_digest(entry.digest.clone()).to_dict(py)?)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("interval", event_time.interval)?;
+        dict.set_item("max_centroids", event_time.max_size)?;
+        dict.set_item("watermark", event_time.watermark)?;
+        dict.set_item("n_late", event_time.n_late)?;
+        dict.set_item("epochs", epochs)?;
+        dict.set_item("digests", digests)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs an EventTimeTDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(event_time_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            event_time_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let max_size =
+            validate_max_centroids(get("max_centroids")?.extract()?)?;
+        let epochs: Vec<i64> = get("epochs")?.extract()?;
+        let digests = get("digests")?
+            .cast::<PyList>()?
+            .iter()
+            .map(|digest_dict| {
+                let digest = PyTDigest::from_dict(digest_dict.cast()?, false)?;
+                Ok(digest.state.into_inner().digest)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let inconsistent = || {
+            SerializationError::new_err(
+                "Inconsistent EventTimeTDigest intervals.",
+            )
+        };
+        if epochs.len() != digests.len() {
+            return Err(inconsistent());
+        }
+        let event_time = EventTimeDigest::from_parts(
+            get("interval")?.extract()?,
+            max_size,
+            get("watermark")?.extract()?,
+            get("n_late")?.extract()?,
+            epochs.into_iter().zip(digests).collect(),
+        )
+        .ok_or_else(inconsistent)?;
+        Ok(Self::from_event_time(event_time))
+    }
+
+    /// EventTimeTDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_event_time(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via EventTimeTDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(EventTimeTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let event_time = self.state.lock();
+        format!(
+            "EventTimeTDigest(interval={:?}, max_centroids={})",
+            event_time.interval, event_time.max_size
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        let mut event_time = self.state.lock();
+        event_time.flush().map_err(malloc_error)?;
+        Ok(event_time.same_state(&other))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
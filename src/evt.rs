@@ -0,0 +1,107 @@
+//! Extreme-value extrapolation of the tails of a digest
+//!
+//! The excesses over a threshold quantile are modelled by a generalized
+//! Pareto distribution (peaks over threshold). Its shape and scale are
+//! estimated from the centroids beyond the threshold by probability-weighted
+//! moments (Hosking & Wallis, 1987), which only need the ordered excesses
+//! and their weights.
+
+use crate::tdigest::TDigest;
+
+/// Below this magnitude, the shape is treated as 0 (exponential tail).
+const SHAPE_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tail {
+    Lower,
+    Upper,
+}
+
+/// Generalized Pareto distribution of the excesses over `threshold`, which
+/// make up the fraction `rate` of the total weight of the digest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpdTail {
+    tail: Tail,
+    threshold: f64,
+    rate: f64,
+    shape: f64,
+    scale: f64,
+}
+
+impl GpdTail {
+    /// Fits the `tail` beyond the quantile `level` (or `1 - level` for the
+    /// lower tail). Returns `None` if there are not enough centroids beyond
+    /// the threshold to estimate the distribution.
+    pub fn fit(digest: &TDigest, tail: Tail, level: f64) -> Option<Self> {
+        let threshold = match tail {
+            Tail::Upper => digest.estimate_quantile(level),
+            Tail::Lower => digest.estimate_quantile(1.0 - level),
+        };
+        let excess = |mean: f64| match tail {
+            Tail::Upper => mean - threshold,
+            Tail::Lower => threshold - mean,
+        };
+        // excesses in ascending order, with their weights
+        let centroids = digest.centroids();
+        let excesses: Vec<(f64, f64)> = match tail {
+            Tail::Upper => centroids
+                .iter()
+                .map(|c| (excess(c.mean()), c.weight()))
+                .filter(|&(y, _)| y > 0.0)
+                .collect(),
+            Tail::Lower => centroids
+                .iter()
+                .rev()
+                .map(|c| (excess(c.mean()), c.weight()))
+                .filter(|&(y, _)| y > 0.0)
+                .collect(),
+        };
+        if excesses.len() < 2 {
+            return None;
+        }
+
+        let total: f64 = excesses.iter().map(|&(_, w)| w).sum();
+        let (mut a0, mut a1, mut cumulative) = (0.0, 0.0, 0.0);
+        for &(y, w) in &excesses {
+            let p = (cumulative + 0.5 * w) / total;
+            a0 += w * y;
+            a1 += w * y * (1.0 - p);
+            cumulative += w;
+        }
+        a0 /= total;
+        a1 /= total;
+
+        let denom = a0 - 2.0 * a1;
+        if !(denom > 0.0 && a1 > 0.0) {
+            return None;
+        }
+        let shape = 2.0 - a0 / denom;
+        let scale = 2.0 * a0 * a1 / denom;
+        Some(Self {
+            tail,
+            threshold,
+            rate: total / digest.mass(),
+            shape,
+            scale,
+        })
+    }
+
+    /// Extrapolated quantile `q`. Only meaningful if the probability of a
+    /// value beyond it is at most `rate`.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let p = match self.tail {
+            Tail::Upper => 1.0 - q,
+            Tail::Lower => q,
+        };
+        let ratio = p / self.rate;
+        let excess = if self.shape.abs() < SHAPE_EPSILON {
+            -self.scale * ratio.ln()
+        } else {
+            self.scale / self.shape * (ratio.powf(-self.shape) - 1.0)
+        };
+        match self.tail {
+            Tail::Upper => self.threshold + excess,
+            Tail::Lower => self.threshold - excess,
+        }
+    }
+}
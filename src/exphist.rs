@@ -0,0 +1,494 @@
+//! Exponential histograms: approximate counts and sums over a sliding window
+//!
+//! Following Datar, Gionis, Indyk and Motwani (2002), the values are
+//! grouped into buckets of 2^j values, each with the sum of its values and
+//! the timestamps of its oldest and newest value. There are at most `m`
+//! buckets of each size; when a new value makes it `m + 1`, the two oldest
+//! buckets of that size are merged into one of twice the size, which may
+//! cascade to the larger sizes. Only the oldest bucket overlapping a query
+//! window may be partially inside it; counting half of it bounds the
+//! relative error of the count by `1 / (2 * (m - 1))`.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, validate_value, validate_values};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::VecDeque;
+
+const MAGIC: [u8; 8] = *b"FASTEXH~";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    count: u64,
+    sum: f64,
+    oldest: f64,
+    newest: f64,
+}
+
+impl Bucket {
+    fn merge(self, newer: Self) -> Self {
+        Self {
+            count: self.count + newer.count,
+            sum: self.sum + newer.sum,
+            oldest: self.oldest,
+            newest: newer.newest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialHistogram {
+    window: f64,
+    epsilon: f64,
+    /// Maximum number of buckets of each size.
+    m: usize,
+    now: Option<f64>,
+    /// Buckets of 2^j values at index j, each from oldest to newest.
+    levels: Vec<VecDeque<Bucket>>,
+}
+
+impl ExponentialHistogram {
+    pub fn new(window: f64, epsilon: f64) -> Self {
+        Self {
+            window,
+            epsilon,
+            m: (0.5 / epsilon).ceil() as usize + 1,
+            now: None,
+            levels: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.levels.iter().all(VecDeque::is_empty)
+    }
+
+    pub fn n_buckets(&self) -> usize {
+        self.levels.iter().map(VecDeque::len).sum()
+    }
+
+    /// Adds a value, expecting `timestamp` not to precede the latest one.
+    pub fn add(&mut self, timestamp: f64, value: f64) {
+        self.now = Some(timestamp);
+        self.expire(timestamp - self.window);
+        let mut bucket = Bucket {
+            count: 1,
+            sum: value,
+            oldest: timestamp,
+            newest: timestamp,
+        };
+        let mut j = 0;
+        loop {
+            if j == self.levels.len() {
+                self.levels.push(VecDeque::new());
+            }
+            let level = &mut self.levels[j];
+            level.push_back(bucket);
+            if level.len() <= self.m {
+                break;
+            }
+            let (Some(older), Some(newer)) =
+                (level.pop_front(), level.pop_front())
+            else {
+                unreachable!("the level holds m + 1 >= 2 buckets");
+            };
+            bucket = older.merge(newer);
+            j += 1;
+        }
+    }
+
+    /// Drops the buckets whose values are all at or before `cutoff`.
+    fn expire(&mut self, cutoff: f64) {
+        while let Some(level) = self.levels.last_mut() {
+            while level.front().is_some_and(|b| b.newest <= cutoff) {
+                level.pop_front();
+            }
+            if !level.is_empty() {
+                break;
+            }
+            self.levels.pop();
+        }
+    }
+
+    /// Returns the estimated count and sum of the values after
+    /// `now - window`.
+    pub fn query(&self, window: f64, now: f64) -> (f64, f64) {
+        let cutoff = now - window;
+        let (mut count, mut sum) = (0.0, 0.0);
+        let newest_first = self.levels.iter().flat_map(|l| l.iter().rev());
+        for bucket in newest_first {
+            if bucket.oldest > cutoff {
+                count += bucket.count as f64;
+                sum += bucket.sum;
+            } else {
+                if bucket.newest > cutoff {
+                    count += bucket.count as f64 / 2.0;
+                    sum += bucket.sum / 2.0;
+                }
+                break;
+            }
+        }
+        (count, sum)
+    }
+
+    /// Returns the buckets from oldest to newest.
+    fn buckets(&self) -> impl Iterator<Item = &Bucket> {
+        self.levels.iter().rev().flatten()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.window);
+        writer.f64(self.epsilon);
+        writer.u8(self.now.is_some() as u8);
+        writer.f64(self.now.unwrap_or(0.0));
+        writer.u64(self.n_buckets() as u64);
+        for bucket in self.buckets() {
+            writer.u64(bucket.count);
+            writer.f64(bucket.sum);
+            writer.f64(bucket.oldest);
+            writer.f64(bucket.newest);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let window = reader.f64()?;
+        let epsilon = reader.f64()?;
+        let has_now = reader.u8()?;
+        let now = reader.f64()?;
+        let now = match has_now {
+            0 => None,
+            1 => Some(now),
+            _ => return Err(BytesError::CorruptData),
+        };
+        let len = reader.seq_len(32)?;
+        let mut buckets = Vec::new();
+        buckets
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            buckets.push((
+                reader.u64()?,
+                reader.f64()?,
+                reader.f64()?,
+                reader.f64()?,
+            ));
+        }
+        reader.finish()?;
+        Self::from_parts(window, epsilon, now, buckets)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a histogram from `(count, sum, oldest, newest)` buckets,
+    /// ordered from oldest to newest, checking that they could have been
+    /// built by `add`.
+    fn from_parts(
+        window: f64,
+        epsilon: f64,
+        now: Option<f64>,
+        buckets: Vec<(u64, f64, f64, f64)>,
+    ) -> Option<Self> {
+        if !valid_window(window) || !valid_epsilon(epsilon) {
+            return None;
+        }
+        let mut hist = Self::new(window, epsilon);
+        hist.now = now;
+        let Some(now) = now else {
+            return buckets.is_empty().then_some(hist);
+        };
+        if !now.is_finite() {
+            return None;
+        }
+        let mut previous: Option<Bucket> = None;
+        for (count, sum, oldest, newest) in buckets {
+            let bucket = Bucket {
+                count,
+                sum,
+                oldest,
+                newest,
+            };
+            let ordered = oldest.is_finite()
+                && oldest <= newest
+                && newest <= now
+                && newest > now - window
+                && previous
+                    .is_none_or(|p| p.newest <= oldest && p.count >= count);
+            if !count.is_power_of_two() || !sum.is_finite() || !ordered {
+                return None;
+            }
+            let j = count.trailing_zeros() as usize;
+            while hist.levels.len() <= j {
+                hist.levels.push(VecDeque::new());
+            }
+            hist.levels[j].push_back(bucket);
+            previous = Some(bucket);
+        }
+        let valid = hist.levels.iter().all(|l| l.len() <= hist.m)
+            && hist.levels.last().is_some_and(|l| !l.is_empty());
+        valid.then_some(hist)
+    }
+}
+
+#[inline]
+fn valid_window(window: f64) -> bool {
+    window.is_finite() && window > 0.0
+}
+
+#[inline]
+fn valid_epsilon(epsilon: f64) -> bool {
+    epsilon > 0.0 && epsilon < 1.0
+}
+
+#[pyclass(name = "ExponentialHistogram", module = "fastdigest", frozen)]
+pub struct PyExponentialHistogram {
+    state: Mutex<ExponentialHistogram>,
+}
+
+impl PyExponentialHistogram {
+    fn from_histogram(hist: ExponentialHistogram) -> Self {
+        Self {
+            state: Mutex::new(hist),
+        }
+    }
+}
+
+/// Adds the values to the histogram, checking that the timestamps are
+/// finite and in order before any of them is added.
+fn add_all(
+    hist: &mut ExponentialHistogram,
+    timestamps: &[f64],
+    values: Option<&[f64]>,
+) -> PyResult<()> {
+    let mut latest = hist.now.unwrap_or(f64::NEG_INFINITY);
+    for &t in timestamps {
+        if !t.is_finite() {
+            return Err(PyValueError::new_err("Timestamps must be finite."));
+        }
+        if t < latest {
+            return Err(PyValueError::new_err(
+                "Timestamps must not precede the latest timestamp.",
+            ));
+        }
+        latest = t;
+    }
+    for (i, &t) in timestamps.iter().enumerate() {
+        hist.add(t, values.map_or(1.0, |v| v[i]));
+    }
+    Ok(())
+}
+
+#[pymethods]
+impl PyExponentialHistogram {
+    /// Constructs a new empty ExponentialHistogram instance for windows of
+    /// up to `window` time units, with a relative error of the counts of
+    /// at most `epsilon`.
+    #[new]
+    #[pyo3(signature = (window, epsilon=0.01))]
+    pub fn py_new(window: f64, epsilon: f64) -> PyResult<Self> {
+        if !valid_window(window) {
+            return Err(PyValueError::new_err(
+                "window must be finite and greater than 0.",
+            ));
+        }
+        if !valid_epsilon(epsilon) {
+            return Err(PyValueError::new_err(
+                "epsilon must be between 0 and 1 (exclusive).",
+            ));
+        }
+        let hist = ExponentialHistogram::new(window, epsilon);
+        Ok(Self::from_histogram(hist))
+    }
+
+    /// Getter property: returns the maximum window length.
+    #[getter(window)]
+    pub fn get_window(&self) -> f64 {
+        self.state.lock().window
+    }
+
+    /// Getter property: returns the relative error bound of the counts.
+    #[getter(epsilon)]
+    pub fn get_epsilon(&self) -> f64 {
+        self.state.lock().epsilon
+    }
+
+    /// Getter property: returns the latest timestamp, or None if empty.
+    #[getter(now)]
+    pub fn get_now(&self) -> Option<f64> {
+        self.state.lock().now
+    }
+
+    /// Getter property: returns the number of buckets.
+    #[getter(n_buckets)]
+    pub fn get_n_buckets(&self) -> usize {
+        self.state.lock().n_buckets()
+    }
+
+    /// Adds a value (in-place) at the given timestamp.
+    #[pyo3(signature = (timestamp, value=1.0))]
+    pub fn add(&self, timestamp: f64, value: f64) -> PyResult<()> {
+        validate_value(value)?;
+        add_all(&mut self.state.lock(), &[timestamp], Some(&[value]))
+    }
+
+    /// Adds a sequence of values (in-place) at the given timestamps.
+    #[pyo3(signature = (timestamps, values=None))]
+    pub fn batch_add(
+        &self,
+        timestamps: &Bound<'_, PyAny>,
+        values: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = timestamps.py();
+        let timestamps: Vec<f64> =
+            extract_values(timestamps)?.0.iter(py).collect();
+        let values: Option<Vec<f64>> = match values {
+            Some(values) => Some(extract_values(values)?.0.iter(py).collect()),
+            None => None,
+        };
+        if let Some(values) = &values {
+            if values.len() != timestamps.len() {
+                return Err(PyValueError::new_err(
+                    "values must have the same length as timestamps.",
+                ));
+            }
+            validate_values(values.iter().copied())?;
+        }
+        add_all(&mut self.state.lock(), &timestamps, values.as_deref())
+    }
+
+    /// Returns the estimated (count, sum) of the values in the last
+    /// `window` time units before `now`.
+    #[pyo3(signature = (window=None, now=None))]
+    pub fn query(
+        &self,
+        window: Option<f64>,
+        now: Option<f64>,
+    ) -> PyResult<(f64, f64)> {
+        let hist = self.state.lock();
+        let window = window.unwrap_or(hist.window);
+        if !(window >= 0.0 && window <= hist.window) {
+            return Err(PyValueError::new_err(
+                "window must be between 0 and the histogram's window.",
+            ));
+        }
+        let latest = hist.now.unwrap_or(f64::NEG_INFINITY);
+        let now = now.unwrap_or(latest);
+        if now.is_nan() || now < latest {
+            return Err(PyValueError::new_err(
+                "now must not precede the latest timestamp.",
+            ));
+        }
+        Ok(hist.query(window, now))
+    }
+
+    /// Returns True if the histogram holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns a binary representation of the histogram.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs an ExponentialHistogram from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let hist =
+            ExponentialHistogram::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_histogram(hist))
+    }
+
+    /// Returns a dict representation of the histogram.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let hist = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("window", hist.window)?;
+        dict.set_item("epsilon", hist.epsilon)?;
+        dict.set_item("now", hist.now)?;
+        let buckets: Vec<(u64, f64, f64, f64)> = hist
+            .buckets()
+            .map(|b| (b.count, b.sum, b.oldest, b.newest))
+            .collect();
+        dict.set_item("buckets", buckets)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs an ExponentialHistogram from a dict.
+    #[staticmethod]
+    pub fn from_dict(hist_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            hist_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let hist = ExponentialHistogram::from_parts(
+            get("window")?.extract()?,
+            get("epsilon")?.extract()?,
+            get("now")?.extract()?,
+            get("buckets")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err(
+                "Inconsistent ExponentialHistogram buckets.",
+            )
+        })?;
+        Ok(Self::from_histogram(hist))
+    }
+
+    /// ExponentialHistogram.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_histogram(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(hist) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(hist) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via ExponentialHistogram.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(ExponentialHistogram) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let hist = self.state.lock();
+        format!(
+            "ExponentialHistogram(window={:?}, epsilon={:?})",
+            hist.window, hist.epsilon
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+}
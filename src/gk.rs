@@ -0,0 +1,580 @@
+//! Greenwald-Khanna sketch: deterministic epsilon-approximate quantiles
+//!
+//! The summary keeps samples `(value, g, delta)` in ascending order, where
+//! `g` is the difference between the minimum rank of a sample and that of
+//! the previous one, and `delta` bounds the uncertainty of its rank
+//! (Greenwald & Khanna, 2001). Adjacent samples are combined as long as the
+//! rank of every value stays known within `2 * epsilon * n`, so each
+//! quantile estimate is off by at most `epsilon * n` ranks, without any
+//! randomness. New values are buffered and inserted in sorted batches, as
+//! in Spark's `QuantileSummaries`. Merged summaries keep the guarantee of
+//! the larger epsilon.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::quantile_summaries::Stats;
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, malloc_error, validate_values};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTGKS~";
+const VERSION: u32 = 1;
+const DEFAULT_EPSILON: f64 = 0.01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GkSketch {
+    epsilon: f64,
+    count: u64,
+    sampled: Vec<Stats>,
+    /// Values not inserted into the samples yet
+    buffer: Vec<f64>,
+}
+
+impl GkSketch {
+    pub fn new(epsilon: f64) -> Self {
+        Self {
+            epsilon,
+            count: 0,
+            sampled: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 && self.buffer.is_empty()
+    }
+
+    #[inline]
+    pub fn n_values(&self) -> u64 {
+        self.count + self.buffer.len() as u64
+    }
+
+    #[inline]
+    fn buffer_size(&self) -> usize {
+        (0.5 / self.epsilon).ceil() as usize
+    }
+
+    pub fn add(&mut self, x: f64) -> Result<(), TryReserveError> {
+        self.buffer.try_reserve(1)?;
+        self.buffer.push(x);
+        if self.buffer.len() >= self.buffer_size() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the buffered values into the samples and compresses them.
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.sort_unstable_by(f64::total_cmp);
+        let mut samples: Vec<Stats> = Vec::new();
+        samples.try_reserve_exact(self.sampled.len() + buffer.len())?;
+        let mut old = self.sampled.iter().peekable();
+        for (i, &value) in buffer.iter().enumerate() {
+            while let Some(stats) = old.next_if(|s| s.value <= value) {
+                samples.push(*stats);
+            }
+            self.count += 1;
+            // the new extremes have exactly known ranks
+            let is_last = old.peek().is_none() && i == buffer.len() - 1;
+            let delta = if samples.is_empty() || is_last {
+                0
+            } else {
+                (2.0 * self.epsilon * self.count as f64).floor() as u64
+            };
+            samples.push(Stats { value, g: 1, delta });
+        }
+        samples.extend(old);
+        buffer.clear();
+        self.buffer = buffer;
+        self.sampled = samples;
+        self.compress();
+        Ok(())
+    }
+
+    fn compress(&mut self) {
+        let threshold = 2.0 * self.epsilon * self.count as f64;
+        self.sampled = compress_samples(&self.sampled, threshold);
+    }
+
+    /// Merges `other` into this sketch. The result has the larger epsilon
+    /// of the two. Both sketches must be flushed.
+    pub fn merge(&mut self, other: &GkSketch) -> Result<(), TryReserveError> {
+        if other.sampled.is_empty() {
+            return Ok(());
+        }
+        self.sampled = merge_samples(
+            &self.sampled,
+            &other.sampled,
+            self.count,
+            other.count,
+        )?;
+        self.epsilon = self.epsilon.max(other.epsilon);
+        self.count += other.count;
+        self.compress();
+        Ok(())
+    }
+
+    /// Quantile estimate whose rank is within `epsilon * n` of `q * n`.
+    /// The sketch must be flushed and nonempty.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let (first, last) =
+            (&self.sampled[0], &self.sampled[self.sampled.len() - 1]);
+        if q <= 0.0 {
+            return first.value;
+        }
+        let rank = (q * self.count as f64).ceil() as u64;
+        let target_error = (self.epsilon * self.count as f64).ceil() as u64;
+        let mut min_rank = 0;
+        for stats in &self.sampled[..self.sampled.len() - 1] {
+            min_rank += stats.g;
+            let max_rank = min_rank + stats.delta;
+            if max_rank <= rank + target_error
+                && rank <= min_rank + target_error
+            {
+                return stats.value;
+            }
+        }
+        last.value
+    }
+
+    /// Estimated fraction of values less than or equal to `x`. The sketch
+    /// must be flushed and nonempty.
+    pub fn rank(&self, x: f64) -> f64 {
+        let i = self.sampled.partition_point(|s| s.value <= x);
+        if i == 0 {
+            return 0.0;
+        }
+        if i == self.sampled.len() {
+            return 1.0;
+        }
+        // the rank of x lies between the minimum rank of the last sample
+        // not above x and the maximum rank of the next one, exclusive
+        let min_rank: u64 = self.sampled[..i].iter().map(|s| s.g).sum();
+        let next = &self.sampled[i];
+        let max_rank = min_rank + next.g + next.delta - 1;
+        0.5 * (min_rank + max_rank) as f64 / self.count as f64
+    }
+
+    pub fn min(&self) -> f64 {
+        self.sampled[0].value
+    }
+
+    pub fn max(&self) -> f64 {
+        self.sampled[self.sampled.len() - 1].value
+    }
+
+    /// Binary representation of the (flushed) sketch.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.epsilon);
+        writer.u64(self.count);
+        writer.u64(self.sampled.len() as u64);
+        for stats in &self.sampled {
+            writer.f64(stats.value);
+            writer.u64(stats.g);
+            writer.u64(stats.delta);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let epsilon = reader.f64()?;
+        let count = reader.u64()?;
+        let len = reader.seq_len(24)?;
+        let mut sampled: Vec<Stats> = Vec::new();
+        sampled
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            let value = reader.f64()?;
+            let g = reader.u64()?;
+            let delta = reader.u64()?;
+            sampled.push(Stats { value, g, delta });
+        }
+        reader.finish()?;
+        Self::from_parts(epsilon, count, sampled).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent.
+    fn from_parts(
+        epsilon: f64,
+        count: u64,
+        sampled: Vec<Stats>,
+    ) -> Option<Self> {
+        let total = sampled
+            .iter()
+            .try_fold(0u64, |total, stats| total.checked_add(stats.g))?;
+        let valid = valid_epsilon(epsilon)
+            && total == count
+            && sampled
+                .iter()
+                .all(|s| s.value.is_finite() && s.g > 0 && s.delta <= count)
+            && sampled.is_sorted_by(|a, b| a.value <= b.value);
+        valid.then(|| Self {
+            epsilon,
+            count,
+            sampled,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+/// Minimum and maximum rank of each sample.
+fn rank_bounds(samples: &[Stats]) -> Vec<(u64, u64)> {
+    let mut min_rank = 0;
+    samples
+        .iter()
+        .map(|s| {
+            min_rank += s.g;
+            (min_rank, min_rank + s.delta)
+        })
+        .collect()
+}
+
+/// Interleaves the samples of two summaries of `n_a` and `n_b` values. The
+/// rank bounds of each sample are widened by those of its neighbours in
+/// the other summary: at least the values up to the preceding sample, at
+/// most those before the following one.
+fn merge_samples(
+    a: &[Stats],
+    b: &[Stats],
+    n_a: u64,
+    n_b: u64,
+) -> Result<Vec<Stats>, TryReserveError> {
+    let (ranks_a, ranks_b) = (rank_bounds(a), rank_bounds(b));
+    let mut merged: Vec<Stats> = Vec::new();
+    merged.try_reserve_exact(a.len() + b.len())?;
+    let (mut i, mut j, mut prev_min_rank) = (0, 0, 0);
+    while i < a.len() || j < b.len() {
+        let take_a = j == b.len() || (i < a.len() && a[i].value <= b[j].value);
+        let (value, (min_rank, max_rank), other, other_ranks, k, n_other) =
+            if take_a {
+                i += 1;
+                (a[i - 1].value, ranks_a[i - 1], b, &ranks_b, j, n_b)
+            } else {
+                j += 1;
+                (b[j - 1].value, ranks_b[j - 1], a, &ranks_a, i, n_a)
+            };
+        let below = k.checked_sub(1).map_or(0, |k| other_ranks[k].0);
+        let above = if k < other.len() {
+            other_ranks[k].1 - 1
+        } else {
+            n_other
+        };
+        let (min_rank, max_rank) = (min_rank + below, max_rank + above);
+        merged.push(Stats {
+            value,
+            g: min_rank - prev_min_rank,
+            delta: max_rank - min_rank,
+        });
+        prev_min_rank = min_rank;
+    }
+    Ok(merged)
+}
+
+/// Combines adjacent samples, from the top down, as long as their combined
+/// rank uncertainty stays below `threshold`. The first and last samples are
+/// always kept.
+fn compress_samples(samples: &[Stats], threshold: f64) -> Vec<Stats> {
+    let Some((&last, rest)) = samples.split_last() else {
+        return Vec::new();
+    };
+    let mut compressed = Vec::with_capacity(samples.len());
+    let mut head = last;
+    for &stats in rest.iter().skip(1).rev() {
+        if ((stats.g + head.g + head.delta) as f64) < threshold {
+            head.g += stats.g;
+        } else {
+            compressed.push(head);
+            head = stats;
+        }
+    }
+    compressed.push(head);
+    if samples.len() > 1 {
+        compressed.push(samples[0]);
+    }
+    compressed.reverse();
+    compressed
+}
+
+#[inline]
+fn valid_epsilon(epsilon: f64) -> bool {
+    epsilon > 0.0 && epsilon < 1.0
+}
+
+#[pyclass(name = "GKSketch", module = "fastdigest", frozen)]
+pub struct PyGkSketch {
+    state: Mutex<GkSketch>,
+}
+
+impl PyGkSketch {
+    fn from_sketch(sketch: GkSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Locks and flushes the sketch.
+    fn lock_and_flush(&self) -> PyResult<MutexGuard<'_, GkSketch>> {
+        let mut sketch = self.state.lock();
+        sketch.flush().map_err(malloc_error)?;
+        Ok(sketch)
+    }
+
+    /// Locks and flushes the sketch, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, GkSketch>> {
+        let sketch = self.lock_and_flush()?;
+        if sketch.is_empty() {
+            return Err(EmptyDigestError::new_err("GKSketch is empty."));
+        }
+        Ok(sketch)
+    }
+}
+
+#[pymethods]
+impl PyGkSketch {
+    /// Constructs a new empty GKSketch instance.
+    #[new]
+    #[pyo3(signature = (epsilon=DEFAULT_EPSILON))]
+    pub fn py_new(epsilon: f64) -> PyResult<Self> {
+        if !valid_epsilon(epsilon) {
+            return Err(PyValueError::new_err(
+                "epsilon must be between 0 and 1 (exclusive).",
+            ));
+        }
+        Ok(Self::from_sketch(GkSketch::new(epsilon)))
+    }
+
+    /// Getter property: returns the epsilon parameter.
+    #[getter(epsilon)]
+    pub fn get_epsilon(&self) -> f64 {
+        self.state.lock().epsilon
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().n_values()
+    }
+
+    /// Getter property: returns the number of samples in the summary.
+    #[getter(n_samples)]
+    pub fn get_n_samples(&self) -> PyResult<usize> {
+        Ok(self.lock_and_flush()?.sampled.len())
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    pub fn update(&self, x: f64) -> PyResult<()> {
+        validate_values([x])?;
+        self.state.lock().add(x).map_err(malloc_error)
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let mut sketch = self.state.lock();
+        for x in values.iter(py).filter(keep) {
+            sketch.add(x).map_err(malloc_error)?;
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new GKSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy()?;
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.lock_and_flush()?.clone();
+        self.lock_and_flush()?.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(q))
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let sketch = self.lock_nonempty()?;
+        Ok(q.iter().map(|&q_i| sketch.quantile(q_i)).collect())
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.quantile(0.5))
+    }
+
+    /// Estimates the rank (cumulative probability) of a given value `x`.
+    pub fn rank(&self, x: f64) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.rank(x))
+    }
+
+    /// Estimates the ranks (cumulative probabilities) of given values `x`.
+    pub fn rank_vec(&self, x: Vec<f64>) -> PyResult<Vec<f64>> {
+        let sketch = self.lock_nonempty()?;
+        Ok(x.iter().map(|&x_i| sketch.rank(x_i)).collect())
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min())
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max())
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.lock_and_flush()?.to_bytes()))
+    }
+
+    /// Reconstructs a GKSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = GkSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.lock_and_flush()?;
+        let dict = PyDict::new(py);
+        dict.set_item("epsilon", sketch.epsilon)?;
+        dict.set_item("n_values", sketch.count)?;
+        let samples: Vec<(f64, u64, u64)> = sketch
+            .sampled
+            .iter()
+            .map(|s| (s.value, s.g, s.delta))
+            .collect();
+        dict.set_item("samples", samples)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a GKSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let samples: Vec<(f64, u64, u64)> = get("samples")?.extract()?;
+        let sampled = samples
+            .into_iter()
+            .map(|(value, g, delta)| Stats { value, g, delta })
+            .collect();
+        let sketch = GkSketch::from_parts(
+            get("epsilon")?.extract()?,
+            get("n_values")?.extract()?,
+            sampled,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent GKSketch samples.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// GKSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> PyResult<Self> {
+        Ok(Self::from_sketch(self.lock_and_flush()?.clone()))
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> PyResult<Self> {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<Self> {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via GKSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(GKSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("GKSketch(epsilon={})", self.state.lock().epsilon)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let other = other.lock_and_flush()?.clone();
+        Ok(*self.lock_and_flush()? == other)
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,380 @@
+//! Stable hashing of Python values for the set-based sketches
+//!
+//! Python's built-in `hash` is salted per process for strings and bytes,
+//! so sketches built with it could neither be merged across processes nor
+//! restored from their serialized form. Values are instead encoded into a
+//! canonical byte string and hashed with the 64-bit half of MurmurHash3
+//! (x64, 128-bit variant). The encoding follows Python's equality: `True`,
+//! `1` and `1.0` hash the same, as do `0.0` and `-0.0`. Sketches that keep
+//! the items themselves store them in this canonical form as a `Value`.
+
+use crate::codec::{Input, Writer};
+use crate::input::FloatValues;
+use crate::tdigest::BytesError;
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyByteArray, PyBytes, PyFloat, PyInt, PyString, PyTuple};
+
+const TAG_INT: u8 = 0;
+const TAG_BIG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_STR: u8 = 3;
+const TAG_BYTES: u8 = 4;
+const TAG_NONE: u8 = 5;
+const TAG_TUPLE: u8 = 6;
+
+#[inline]
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^ (k >> 33)
+}
+
+/// First 64 bits of MurmurHash3_x64_128 of `data`.
+pub fn murmur3(data: &[u8], seed: u64) -> u64 {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+    let (mut h1, mut h2) = (seed, seed);
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 = h1.rotate_left(27).wrapping_add(h2);
+        h1 = h1.wrapping_mul(5).wrapping_add(0x52dc_e729);
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 = h2.rotate_left(31).wrapping_add(h1);
+        h2 = h2.wrapping_mul(5).wrapping_add(0x3849_5ab5);
+    }
+    let tail = blocks.remainder();
+    let word = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |word, &byte| word << 8 | u64::from(byte))
+    };
+    if tail.len() > 8 {
+        let k2 = word(&tail[8..]);
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    }
+    if !tail.is_empty() {
+        let k1 = word(&tail[..tail.len().min(8)]);
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    }
+    let len = data.len() as u64;
+    h1 ^= len;
+    h2 ^= len;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1.wrapping_add(h2)
+}
+
+#[inline]
+fn hash_tagged(tag: u8, payload: &[u8], seed: u64) -> u64 {
+    let mut data = Vec::with_capacity(payload.len() + 1);
+    data.push(tag);
+    data.extend_from_slice(payload);
+    murmur3(&data, seed)
+}
+
+#[inline]
+pub fn hash_i64(x: i64, seed: u64) -> u64 {
+    let mut data = [TAG_INT; 9];
+    data[1..].copy_from_slice(&x.to_le_bytes());
+    murmur3(&data, seed)
+}
+
+#[inline]
+pub fn hash_u64(x: u64, seed: u64) -> u64 {
+    Value::from_u64(x).hash(seed)
+}
+
+/// Hashes a float; integral floats hash like the equal integer.
+#[inline]
+pub fn hash_f64(x: f64, seed: u64) -> u64 {
+    Value::from_f64(x).hash(seed)
+}
+
+/// Hashes an int, float, str, bytes, bytearray, None or a tuple thereof.
+pub fn hash_value(obj: &Bound<'_, PyAny>, seed: u64) -> PyResult<u64> {
+    // bool is a subclass of int
+    if obj.is_instance_of::<PyInt>() {
+        if let Ok(x) = obj.extract::<i64>() {
+            return Ok(hash_i64(x, seed));
+        }
+        let digits = obj.str()?;
+        return Ok(hash_tagged(TAG_BIG_INT, digits.to_str()?.as_bytes(), seed));
+    }
+    if let Ok(x) = obj.cast::<PyFloat>() {
+        return Ok(hash_f64(x.value(), seed));
+    }
+    if let Ok(s) = obj.cast::<PyString>() {
+        return Ok(hash_tagged(TAG_STR, s.to_str()?.as_bytes(), seed));
+    }
+    if let Ok(b) = obj.cast::<PyBytes>() {
+        return Ok(hash_tagged(TAG_BYTES, b.as_bytes(), seed));
+    }
+    if let Ok(b) = obj.cast::<PyByteArray>() {
+        return Ok(hash_tagged(TAG_BYTES, &b.to_vec(), seed));
+    }
+    if obj.is_none() {
+        return Ok(hash_tagged(TAG_NONE, &[], seed));
+    }
+    if let Ok(t) = obj.cast::<PyTuple>() {
+        let mut payload = Vec::with_capacity(8 * t.len());
+        for item in t.iter() {
+            payload.extend_from_slice(&hash_value(&item, seed)?.to_le_bytes());
+        }
+        return Ok(hash_tagged(TAG_TUPLE, &payload, seed));
+    }
+    // other integer types, like NumPy integer scalars
+    if let Ok(x) = obj.extract::<i64>() {
+        return Ok(hash_i64(x, seed));
+    }
+    Err(PyTypeError::new_err(format!(
+        "Cannot hash values of type '{}'.",
+        obj.get_type().name()?
+    )))
+}
+
+/// Hashes each item of `x`: a numeric buffer (e.g. a NumPy array), whose
+/// 64-bit integers are hashed exactly, or any iterable of hashable values.
+pub fn hash_values(x: &Bound<'_, PyAny>, seed: u64) -> PyResult<Vec<u64>> {
+    let py = x.py();
+    if let Some(values) = FloatValues::from_buffer(x) {
+        return Ok(match &values {
+            FloatValues::I64(buffer) => {
+                let cells = buffer.as_slice(py).unwrap_or_default();
+                cells.iter().map(|x| hash_i64(x.get(), seed)).collect()
+            }
+            FloatValues::U64(buffer) => {
+                let cells = buffer.as_slice(py).unwrap_or_default();
+                cells.iter().map(|x| hash_u64(x.get(), seed)).collect()
+            }
+            _ => values.iter(py).map(|x| hash_f64(x, seed)).collect(),
+        });
+    }
+    x.try_iter()?.map(|item| hash_value(&item?, seed)).collect()
+}
+
+/// Deepest nesting of tuples accepted when reading serialized values.
+const MAX_DEPTH: usize = 32;
+
+/// Canonical form of a hashable value. Equal Python values have the same
+/// form (`True`, `1` and `1.0` are all `Int(1)`), whose hash is that of the
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Int(i64),
+    /// Integer outside of the i64 range, as decimal digits
+    BigInt(String),
+    /// Bits of a non-integral float, with a canonical NaN
+    Float(u64),
+    Str(String),
+    Bytes(Vec<u8>),
+    None,
+    Tuple(Vec<Value>),
+}
+
+impl Value {
+    pub fn from_u64(x: u64) -> Self {
+        i64::try_from(x).map_or_else(|_| Self::BigInt(x.to_string()), Self::Int)
+    }
+
+    pub fn from_f64(x: f64) -> Self {
+        // the range check excludes 2^63, which does not fit into an i64
+        if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 {
+            return Self::Int(x as i64);
+        }
+        Self::Float(if x.is_nan() { f64::NAN } else { x }.to_bits())
+    }
+
+    /// Canonical form of an int, float, str, bytes, bytearray, None or a
+    /// tuple thereof.
+    pub fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if obj.is_instance_of::<PyInt>() {
+            if let Ok(x) = obj.extract::<i64>() {
+                return Ok(Self::Int(x));
+            }
+            return Ok(Self::BigInt(obj.str()?.to_str()?.to_owned()));
+        }
+        if let Ok(x) = obj.cast::<PyFloat>() {
+            return Ok(Self::from_f64(x.value()));
+        }
+        if let Ok(s) = obj.cast::<PyString>() {
+            return Ok(Self::Str(s.to_str()?.to_owned()));
+        }
+        if let Ok(b) = obj.cast::<PyBytes>() {
+            return Ok(Self::Bytes(b.as_bytes().to_vec()));
+        }
+        if let Ok(b) = obj.cast::<PyByteArray>() {
+            return Ok(Self::Bytes(b.to_vec()));
+        }
+        if obj.is_none() {
+            return Ok(Self::None);
+        }
+        if let Ok(t) = obj.cast::<PyTuple>() {
+            let items = t.iter().map(|item| Self::extract(&item));
+            return Ok(Self::Tuple(items.collect::<PyResult<_>>()?));
+        }
+        if let Ok(x) = obj.extract::<i64>() {
+            return Ok(Self::Int(x));
+        }
+        Err(PyTypeError::new_err(format!(
+            "Cannot hash values of type '{}'.",
+            obj.get_type().name()?
+        )))
+    }
+
+    /// Canonical forms of the items of a numeric buffer or an iterable.
+    pub fn extract_all(x: &Bound<'_, PyAny>) -> PyResult<Vec<Self>> {
+        let py = x.py();
+        if let Some(values) = FloatValues::from_buffer(x) {
+            return Ok(match &values {
+                FloatValues::I64(buffer) => {
+                    let cells = buffer.as_slice(py).unwrap_or_default();
+                    cells.iter().map(|x| Self::Int(x.get())).collect()
+                }
+                FloatValues::U64(buffer) => {
+                    let cells = buffer.as_slice(py).unwrap_or_default();
+                    cells.iter().map(|x| Self::from_u64(x.get())).collect()
+                }
+                _ => values.iter(py).map(Self::from_f64).collect(),
+            });
+        }
+        x.try_iter()?.map(|item| Self::extract(&item?)).collect()
+    }
+
+    pub fn hash(&self, seed: u64) -> u64 {
+        match self {
+            Self::Int(x) => hash_i64(*x, seed),
+            Self::BigInt(digits) => {
+                hash_tagged(TAG_BIG_INT, digits.as_bytes(), seed)
+            }
+            Self::Float(bits) => {
+                let mut data = [TAG_FLOAT; 9];
+                data[1..].copy_from_slice(&bits.to_le_bytes());
+                murmur3(&data, seed)
+            }
+            Self::Str(s) => hash_tagged(TAG_STR, s.as_bytes(), seed),
+            Self::Bytes(b) => hash_tagged(TAG_BYTES, b, seed),
+            Self::None => hash_tagged(TAG_NONE, &[], seed),
+            Self::Tuple(items) => {
+                let payload: Vec<u8> = items
+                    .iter()
+                    .flat_map(|item| item.hash(seed).to_le_bytes())
+                    .collect();
+                hash_tagged(TAG_TUPLE, &payload, seed)
+            }
+        }
+    }
+
+    pub fn to_object<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        Ok(match self {
+            Self::Int(x) => x.into_pyobject(py)?.into_any(),
+            Self::BigInt(digits) => py.get_type::<PyInt>().call1((digits,))?,
+            Self::Float(bits) => {
+                f64::from_bits(*bits).into_pyobject(py)?.into_any()
+            }
+            Self::Str(s) => PyString::new(py, s).into_any(),
+            Self::Bytes(b) => PyBytes::new(py, b).into_any(),
+            Self::None => py.None().into_bound(py),
+            Self::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.to_object(py))
+                    .collect::<PyResult<Vec<_>>>()?;
+                PyTuple::new(py, items)?.into_any()
+            }
+        })
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        match self {
+            Self::Int(x) => {
+                writer.u8(TAG_INT);
+                writer.i64(*x);
+            }
+            Self::BigInt(digits) => {
+                writer.u8(TAG_BIG_INT);
+                writer.bytes(digits.as_bytes());
+            }
+            Self::Float(bits) => {
+                writer.u8(TAG_FLOAT);
+                writer.u64(*bits);
+            }
+            Self::Str(s) => {
+                writer.u8(TAG_STR);
+                writer.bytes(s.as_bytes());
+            }
+            Self::Bytes(b) => {
+                writer.u8(TAG_BYTES);
+                writer.bytes(b);
+            }
+            Self::None => writer.u8(TAG_NONE),
+            Self::Tuple(items) => {
+                writer.u8(TAG_TUPLE);
+                writer.u64(items.len() as u64);
+                for item in items {
+                    item.write(writer);
+                }
+            }
+        }
+    }
+
+    pub fn read<R: Input>(reader: &mut R) -> Result<Self, R::Error> {
+        Self::read_nested(reader, 0)
+    }
+
+    /// Reads a value, checking that it is in canonical form.
+    fn read_nested<R: Input>(
+        reader: &mut R,
+        depth: usize,
+    ) -> Result<Self, R::Error> {
+        let text = |bytes: &[u8]| {
+            String::from_utf8(bytes.to_vec())
+                .map_err(|_| BytesError::CorruptData)
+        };
+        let value = match reader.u8()? {
+            TAG_INT => Self::Int(reader.i64()?),
+            TAG_BIG_INT => {
+                let digits = text(&reader.bytes()?)?;
+                let unsigned = digits.strip_prefix('-').unwrap_or(&digits);
+                let valid = !unsigned.is_empty()
+                    && unsigned.bytes().all(|b| b.is_ascii_digit())
+                    && digits.parse::<i64>().is_err();
+                if !valid {
+                    return Err(BytesError::CorruptData.into());
+                }
+                Self::BigInt(digits)
+            }
+            TAG_FLOAT => {
+                let bits = reader.u64()?;
+                let value = Self::Float(bits);
+                if Self::from_f64(f64::from_bits(bits)) != value {
+                    return Err(BytesError::CorruptData.into());
+                }
+                value
+            }
+            TAG_STR => Self::Str(text(&reader.bytes()?)?),
+            TAG_BYTES => Self::Bytes(reader.bytes()?.to_vec()),
+            TAG_NONE => Self::None,
+            TAG_TUPLE if depth < MAX_DEPTH => {
+                let len = reader.seq_len(1)?;
+                let items = (0..len)
+                    .map(|_| Self::read_nested(reader, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Self::Tuple(items)
+            }
+            _ => return Err(BytesError::CorruptData.into()),
+        };
+        Ok(value)
+    }
+}
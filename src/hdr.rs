@@ -0,0 +1,800 @@
+//! HDR histograms: integer values with a fixed number of significant digits
+//!
+//! The layout is that of the reference implementation by Gil Tene: the
+//! range of values is split into buckets that double in size, each with
+//! enough linear sub-buckets to resolve `sig_figs` decimal digits, so that
+//! every recorded value is counted with a relative error of at most
+//! `10^-sig_figs`. Histograms are (de)serialized in the standard V2
+//! encoding, which stores the counts as ZigZag LEB128 varints with runs of
+//! zeros collapsed, optionally deflated and Base64-encoded as in HdrHistogram
+//! interval logs.
+
+use crate::bytes_error;
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, InvalidValueError,
+    SerializationError,
+};
+use crate::tdigest::BytesError;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+const ENCODING_COOKIE: u32 = 0x1c84_9303;
+const COMPRESSED_COOKIE: u32 = 0x1c84_9304;
+/// Word size flag of the cookies, which is ignored when decoding.
+const COOKIE_FLAGS: u32 = 0x10;
+const HEADER_LEN: usize = 40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrHistogram {
+    lowest: u64,
+    highest: u64,
+    sig_figs: u32,
+    unit_magnitude: u32,
+    sub_bucket_half_count_magnitude: u32,
+    total: u64,
+    counts: Vec<u64>,
+}
+
+/// Checks the histogram parameters, returning the unit magnitude, the
+/// sub-bucket half count magnitude and the length of the counts array.
+fn layout(
+    lowest: u64,
+    highest: u64,
+    sig_figs: u32,
+) -> Result<(u32, u32, usize), &'static str> {
+    if lowest < 1 {
+        return Err("lowest must be at least 1.");
+    }
+    if highest < lowest.saturating_mul(2) || highest > i64::MAX as u64 {
+        return Err("highest must be at least twice lowest.");
+    }
+    if sig_figs > 5 {
+        return Err("sig_figs must be between 0 and 5.");
+    }
+    let largest_single_unit = 2 * 10u64.pow(sig_figs);
+    let count_magnitude = 64 - (largest_single_unit - 1).leading_zeros();
+    let half_count_magnitude = count_magnitude.max(1) - 1;
+    let unit_magnitude = 63 - lowest.leading_zeros();
+    if unit_magnitude + half_count_magnitude > 61 {
+        return Err("lowest is too large for sig_figs.");
+    }
+    let sub_bucket_count = 1u64 << (half_count_magnitude + 1);
+    let mut smallest_untrackable = sub_bucket_count << unit_magnitude;
+    let mut bucket_count = 1;
+    while smallest_untrackable <= highest {
+        if smallest_untrackable > i64::MAX as u64 / 2 {
+            bucket_count += 1;
+            break;
+        }
+        smallest_untrackable <<= 1;
+        bucket_count += 1;
+    }
+    let len = (bucket_count + 1) << half_count_magnitude;
+    Ok((unit_magnitude, half_count_magnitude, len))
+}
+
+impl HdrHistogram {
+    pub fn new(
+        lowest: u64,
+        highest: u64,
+        sig_figs: u32,
+    ) -> Result<Self, &'static str> {
+        let (unit_magnitude, half_magnitude, len) =
+            layout(lowest, highest, sig_figs)?;
+        Ok(Self {
+            lowest,
+            highest,
+            sig_figs,
+            unit_magnitude,
+            sub_bucket_half_count_magnitude: half_magnitude,
+            total: 0,
+            counts: vec![0; len],
+        })
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    #[inline]
+    fn half_count(&self) -> u64 {
+        1 << self.sub_bucket_half_count_magnitude
+    }
+
+    /// Returns the bucket and sub-bucket indices of a value.
+    #[inline]
+    fn indices(&self, value: u64) -> (u32, u64) {
+        let mask = (2 * self.half_count() - 1) << self.unit_magnitude;
+        let base =
+            63 - self.unit_magnitude - self.sub_bucket_half_count_magnitude;
+        let bucket = base - (value | mask).leading_zeros();
+        (bucket, value >> (bucket + self.unit_magnitude))
+    }
+
+    /// Returns the index of the count of a value, which may exceed the
+    /// length of the counts array.
+    #[inline]
+    fn index_of(&self, value: u64) -> usize {
+        let (bucket, sub_bucket) = self.indices(value);
+        let base = (bucket as u64 + 1) << self.sub_bucket_half_count_magnitude;
+        (base + sub_bucket - self.half_count()) as usize
+    }
+
+    /// Returns the lowest value counted at an index.
+    #[inline]
+    fn value_at(&self, index: usize) -> u64 {
+        let index = index as u64;
+        let half_count = self.half_count();
+        let bucket = index >> self.sub_bucket_half_count_magnitude;
+        let sub_bucket = index & (half_count - 1);
+        if bucket == 0 {
+            sub_bucket << self.unit_magnitude
+        } else {
+            let shift = bucket - 1 + self.unit_magnitude as u64;
+            (sub_bucket + half_count) << shift
+        }
+    }
+
+    /// Returns the highest value counted at the same index as `value`.
+    #[inline]
+    fn highest_equivalent(&self, value: u64) -> u64 {
+        let (bucket, sub_bucket) = self.indices(value);
+        let lowest = sub_bucket << (bucket + self.unit_magnitude);
+        lowest.saturating_add((1 << (bucket + self.unit_magnitude)) - 1)
+    }
+
+    /// Returns the value in the middle of the range counted at the same
+    /// index as `value`.
+    #[inline]
+    fn median_equivalent(&self, value: u64) -> u64 {
+        let (bucket, sub_bucket) = self.indices(value);
+        let lowest = sub_bucket << (bucket + self.unit_magnitude);
+        lowest + (1 << (bucket + self.unit_magnitude) >> 1)
+    }
+
+    /// Returns true if `value` fits into the counts array.
+    #[inline]
+    pub fn fits(&self, value: u64) -> bool {
+        self.index_of(value) < self.counts.len()
+    }
+
+    /// Records a value, expecting it to fit.
+    pub fn record(&mut self, value: u64, count: u64) {
+        let index = self.index_of(value);
+        self.counts[index] = self.counts[index].saturating_add(count);
+        self.total = self.total.saturating_add(count);
+    }
+
+    fn nonzero(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c > 0)
+            .map(|(i, &c)| (i, c))
+    }
+
+    fn last_index(&self) -> Option<usize> {
+        self.counts.iter().rposition(|&c| c > 0)
+    }
+
+    pub fn min(&self) -> u64 {
+        self.counts
+            .iter()
+            .position(|&c| c > 0)
+            .map_or(0, |i| self.value_at(i))
+    }
+
+    pub fn max(&self) -> u64 {
+        match self.last_index() {
+            None | Some(0) => 0,
+            Some(i) => self.highest_equivalent(self.value_at(i)),
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        let sum: f64 = self
+            .nonzero()
+            .map(|(i, c)| {
+                self.median_equivalent(self.value_at(i)) as f64 * c as f64
+            })
+            .sum();
+        sum / self.total as f64
+    }
+
+    pub fn std(&self) -> f64 {
+        let mean = self.mean();
+        let sum: f64 = self
+            .nonzero()
+            .map(|(i, c)| {
+                let d = self.median_equivalent(self.value_at(i)) as f64 - mean;
+                d * d * c as f64
+            })
+            .sum();
+        (sum / self.total as f64).sqrt()
+    }
+
+    /// Returns the value at a percentile; like the reference
+    /// implementation, the highest value equivalent to the one at the
+    /// rank `ceil(p / 100 * n)`.
+    pub fn value_at_percentile(&self, p: f64) -> u64 {
+        let requested = p.next_down().clamp(0.0, 100.0);
+        let rank = ((requested * self.total as f64) / 100.0).ceil() as u64;
+        let rank = rank.max(1);
+        let mut cumulative = 0;
+        for (i, c) in self.nonzero() {
+            cumulative += c;
+            if cumulative >= rank {
+                let value = self.value_at(i);
+                return if p == 0.0 {
+                    value
+                } else {
+                    self.highest_equivalent(value)
+                };
+            }
+        }
+        0
+    }
+
+    /// Returns the fraction of values at or below `value`.
+    pub fn rank(&self, value: u64) -> f64 {
+        let last = self.index_of(value).min(self.counts.len() - 1);
+        let below: u64 = self.counts[..=last].iter().sum();
+        below as f64 / self.total as f64
+    }
+
+    /// Adds the counts of another histogram, checking that all of its
+    /// values fit before changing anything.
+    pub fn merge(&mut self, other: &Self) -> Result<(), &'static str> {
+        let Some(last) = other.last_index() else {
+            return Ok(());
+        };
+        let same_layout = self.unit_magnitude == other.unit_magnitude
+            && self.sub_bucket_half_count_magnitude
+                == other.sub_bucket_half_count_magnitude;
+        if same_layout && last < self.counts.len() {
+            for (i, c) in other.nonzero() {
+                self.counts[i] = self.counts[i].saturating_add(c);
+            }
+            self.total = self.total.saturating_add(other.total);
+            return Ok(());
+        }
+        let top = self.value_at(self.counts.len() - 1);
+        let other_top = other.value_at(last);
+        if other.highest_equivalent(other_top) > self.highest_equivalent(top) {
+            return Err(
+                "The other histogram includes values that do not fit in this one.",
+            );
+        }
+        for (i, c) in other.nonzero() {
+            self.record(other.value_at(i), c);
+        }
+        Ok(())
+    }
+
+    /// Returns the uncompressed V2 encoding of the histogram.
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.last_index().map_or(1, |i| i + 1);
+        let mut payload = Vec::new();
+        let mut i = 0;
+        while i < len {
+            let count = self.counts[i];
+            let zeros =
+                self.counts[i..len].iter().take_while(|&&c| c == 0).count();
+            if zeros > 1 {
+                put_zigzag(&mut payload, -(zeros as i64));
+                i += zeros;
+            } else {
+                put_zigzag(&mut payload, count as i64);
+                i += 1;
+            }
+        }
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend((ENCODING_COOKIE | COOKIE_FLAGS).to_be_bytes());
+        bytes.extend((payload.len() as u32).to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(self.sig_figs.to_be_bytes());
+        bytes.extend(self.lowest.to_be_bytes());
+        bytes.extend(self.highest.to_be_bytes());
+        bytes.extend(1.0f64.to_be_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    /// Returns an empty histogram with the parameters of a V2 header and
+    /// the length of its payload.
+    pub fn from_header(header: &[u8]) -> Result<(Self, usize), BytesError> {
+        let word = |i: usize, n: usize| -> Result<u64, BytesError> {
+            let bytes = header.get(i..i + n).ok_or(BytesError::CorruptData)?;
+            Ok(bytes.iter().fold(0, |acc, &b| acc << 8 | b as u64))
+        };
+        if word(0, 4)? as u32 & !0xf0 != ENCODING_COOKIE {
+            return Err(BytesError::WrongFormat);
+        }
+        let payload_len = word(4, 4)? as usize;
+        if word(8, 4)? != 0 {
+            // index offsets of normalized histograms are not supported
+            return Err(BytesError::CorruptData);
+        }
+        let sig_figs = word(12, 4)? as u32;
+        let hist = Self::new(word(16, 8)?, word(24, 8)?, sig_figs)
+            .map_err(|_| BytesError::CorruptData)?;
+        Ok((hist, payload_len))
+    }
+
+    /// Reconstructs a histogram from its uncompressed V2 encoding.
+    pub fn decode(bytes: &[u8]) -> Result<Self, BytesError> {
+        if bytes.is_empty() {
+            return Err(BytesError::EmptyData);
+        }
+        let (mut hist, payload_len) = Self::from_header(bytes)?;
+        let payload = &bytes[HEADER_LEN.min(bytes.len())..];
+        if payload.len() != payload_len {
+            return Err(BytesError::CorruptData);
+        }
+        let mut reader = payload;
+        let mut i = 0usize;
+        while !reader.is_empty() {
+            let count = get_zigzag(&mut reader)?;
+            if count < 0 {
+                i = i.saturating_add(count.unsigned_abs() as usize);
+                continue;
+            }
+            let slot = hist.counts.get_mut(i).ok_or(BytesError::CorruptData)?;
+            *slot = count as u64;
+            hist.total = hist
+                .total
+                .checked_add(count as u64)
+                .ok_or(BytesError::CorruptData)?;
+            i += 1;
+        }
+        if i > hist.counts.len() {
+            return Err(BytesError::CorruptData);
+        }
+        Ok(hist)
+    }
+
+    /// Assembles a histogram from `(value, count)` pairs of increasing
+    /// values, each the lowest value counted at its index.
+    fn from_parts(
+        lowest: u64,
+        highest: u64,
+        sig_figs: u32,
+        counts: Vec<(u64, u64)>,
+    ) -> Option<Self> {
+        let mut hist = Self::new(lowest, highest, sig_figs).ok()?;
+        let mut previous = None;
+        for (value, count) in counts {
+            let index = hist.index_of(value);
+            let valid = count > 0
+                && index < hist.counts.len()
+                && hist.value_at(index) == value
+                && previous.is_none_or(|p| p < index);
+            if !valid {
+                return None;
+            }
+            hist.counts[index] = count;
+            hist.total = hist.total.checked_add(count)?;
+            previous = Some(index);
+        }
+        Some(hist)
+    }
+}
+
+/// Appends a ZigZag LEB128 varint as in the reference implementation,
+/// where the ninth byte holds the eight highest bits.
+fn put_zigzag(bytes: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    for _ in 0..8 {
+        if value >> 7 == 0 {
+            bytes.push(value as u8);
+            return;
+        }
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn get_zigzag(bytes: &mut &[u8]) -> Result<i64, BytesError> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let (&byte, rest) =
+            bytes.split_first().ok_or(BytesError::CorruptData)?;
+        *bytes = rest;
+        if i == 8 {
+            value |= (byte as u64) << 56;
+            break;
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn hdr_error(e: BytesError) -> PyErr {
+    match e {
+        BytesError::WrongFormat => SerializationError::new_err(
+            "Data is not in HdrHistogram V2 format.",
+        ),
+        e => bytes_error(e),
+    }
+}
+
+/// Deflates the V2 encoding into the compressed V2 encoding.
+fn compress(py: Python<'_>, encoded: &[u8]) -> PyResult<Vec<u8>> {
+    let deflated = py
+        .import("zlib")?
+        .call_method1("compress", (PyBytes::new(py, encoded),))?;
+    let deflated = deflated.cast::<PyBytes>()?.as_bytes();
+    let mut bytes = Vec::with_capacity(8 + deflated.len());
+    bytes.extend((COMPRESSED_COOKIE | COOKIE_FLAGS).to_be_bytes());
+    bytes.extend((deflated.len() as u32).to_be_bytes());
+    bytes.extend(deflated);
+    Ok(bytes)
+}
+
+/// Decodes the compressed or uncompressed V2 encoding. The deflated data
+/// is inflated no further than the largest payload possible for the
+/// parameters in its header.
+fn decompress(py: Python<'_>, data: &[u8]) -> PyResult<HdrHistogram> {
+    if data.is_empty() {
+        return Err(hdr_error(BytesError::EmptyData));
+    }
+    let cookie = data
+        .get(..4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+    if cookie.map(|c| c & !0xf0) != Some(COMPRESSED_COOKIE) {
+        return HdrHistogram::decode(data).map_err(hdr_error);
+    }
+    let len = data
+        .get(4..8)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    if len != Some(data.len().saturating_sub(8)) {
+        return Err(hdr_error(BytesError::CorruptData));
+    }
+    let corrupt = |_| hdr_error(BytesError::CorruptData);
+    let inflater = py.import("zlib")?.call_method0("decompressobj")?;
+    let deflated = PyBytes::new(py, &data[8..]);
+    let header = inflater
+        .call_method1("decompress", (deflated, HEADER_LEN))
+        .map_err(corrupt)?;
+    let mut encoded = header.cast::<PyBytes>()?.as_bytes().to_vec();
+    let (hist, payload_len) =
+        HdrHistogram::from_header(&encoded).map_err(hdr_error)?;
+    if payload_len > 9 * hist.counts.len() {
+        return Err(hdr_error(BytesError::CorruptData));
+    }
+    let tail = inflater.getattr("unconsumed_tail")?;
+    let payload = inflater
+        .call_method1("decompress", (tail, payload_len + 1))
+        .map_err(corrupt)?;
+    encoded.extend(payload.cast::<PyBytes>()?.as_bytes());
+    if !inflater.getattr("eof")?.is_truthy()? {
+        return Err(hdr_error(BytesError::CorruptData));
+    }
+    HdrHistogram::decode(&encoded).map_err(hdr_error)
+}
+
+#[pyclass(name = "HdrHistogram", module = "fastdigest", frozen)]
+pub struct PyHdrHistogram {
+    state: Mutex<HdrHistogram>,
+}
+
+impl PyHdrHistogram {
+    fn from_histogram(hist: HdrHistogram) -> Self {
+        Self {
+            state: Mutex::new(hist),
+        }
+    }
+
+    /// Locks the histogram, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, HdrHistogram>> {
+        let hist = self.state.lock();
+        if hist.is_empty() {
+            return Err(EmptyDigestError::new_err("HdrHistogram is empty."));
+        }
+        Ok(hist)
+    }
+}
+
+/// Checks that a value can be recorded in the histogram.
+fn validate_record(hist: &HdrHistogram, value: i64) -> PyResult<u64> {
+    let value = u64::try_from(value).map_err(|_| {
+        InvalidValueError::new_err("Values must be non-negative.")
+    })?;
+    if !hist.fits(value) {
+        return Err(PyValueError::new_err(format!(
+            "Value {value} exceeds the highest trackable value {}.",
+            hist.highest
+        )));
+    }
+    Ok(value)
+}
+
+#[pymethods]
+impl PyHdrHistogram {
+    /// Constructs a new empty HdrHistogram instance for integer values
+    /// from `lowest` to `highest`, resolved to `sig_figs` significant
+    /// decimal digits.
+    #[new]
+    pub fn py_new(lowest: i64, highest: i64, sig_figs: i64) -> PyResult<Self> {
+        let param = |x: i64| u64::try_from(x).unwrap_or(0);
+        let sig_figs = u32::try_from(sig_figs).unwrap_or(u32::MAX);
+        let hist = HdrHistogram::new(param(lowest), param(highest), sig_figs)
+            .map_err(PyValueError::new_err)?;
+        Ok(Self::from_histogram(hist))
+    }
+
+    /// Getter property: returns the lowest discernible value.
+    #[getter(lowest)]
+    pub fn get_lowest(&self) -> u64 {
+        self.state.lock().lowest
+    }
+
+    /// Getter property: returns the highest trackable value.
+    #[getter(highest)]
+    pub fn get_highest(&self) -> u64 {
+        self.state.lock().highest
+    }
+
+    /// Getter property: returns the number of significant decimal digits.
+    #[getter(sig_figs)]
+    pub fn get_sig_figs(&self) -> u32 {
+        self.state.lock().sig_figs
+    }
+
+    /// Getter property: returns the total number of data points recorded.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().total
+    }
+
+    /// Records (in-place) a single integer value `count` times.
+    #[pyo3(signature = (value, count=1))]
+    pub fn record(&self, value: i64, count: i64) -> PyResult<()> {
+        let count = u64::try_from(count)
+            .ok()
+            .filter(|&c| c > 0)
+            .ok_or_else(|| PyValueError::new_err("count must be positive."))?;
+        let mut hist = self.state.lock();
+        let value = validate_record(&hist, value)?;
+        hist.record(value, count);
+        Ok(())
+    }
+
+    /// Records (in-place) a sequence of integer values.
+    pub fn batch_record(&self, values: Vec<i64>) -> PyResult<()> {
+        let mut hist = self.state.lock();
+        let values = values
+            .into_iter()
+            .map(|v| validate_record(&hist, v))
+            .collect::<PyResult<Vec<u64>>>()?;
+        for value in values {
+            hist.record(value, 1);
+        }
+        Ok(())
+    }
+
+    /// Merges this histogram with another, returning a new HdrHistogram.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this histogram with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        self.state
+            .lock()
+            .merge(&other)
+            .map_err(PyValueError::new_err)
+    }
+
+    /// Returns True if the histogram is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<u64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.value_at_percentile(100.0 * q))
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<u64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let hist = self.lock_nonempty()?;
+        Ok(q.iter()
+            .map(|&q_i| hist.value_at_percentile(100.0 * q_i))
+            .collect())
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<u64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.value_at_percentile(p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<u64> {
+        Ok(self.lock_nonempty()?.value_at_percentile(50.0))
+    }
+
+    /// Returns the fraction of values at or below `x`.
+    pub fn rank(&self, x: i64) -> PyResult<f64> {
+        let hist = self.lock_nonempty()?;
+        Ok(u64::try_from(x).map_or(0.0, |x| hist.rank(x)))
+    }
+
+    /// Returns the lowest recorded value, rounded down to its resolution.
+    pub fn min(&self) -> PyResult<u64> {
+        Ok(self.lock_nonempty()?.min())
+    }
+
+    /// Returns the highest recorded value, rounded up to its resolution.
+    pub fn max(&self) -> PyResult<u64> {
+        Ok(self.lock_nonempty()?.max())
+    }
+
+    /// Returns the mean of the recorded values.
+    pub fn mean(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.mean())
+    }
+
+    /// Returns the standard deviation of the recorded values.
+    pub fn std(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.std())
+    }
+
+    /// Returns the compressed V2 encoding of the histogram.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let encoded = self.state.lock().encode();
+        Ok(PyBytes::new(py, &compress(py, &encoded)?))
+    }
+
+    /// Reconstructs an HdrHistogram from its compressed or uncompressed V2
+    /// encoding.
+    #[staticmethod]
+    pub fn from_bytes(py: Python<'_>, data: &[u8]) -> PyResult<Self> {
+        Ok(Self::from_histogram(decompress(py, data)?))
+    }
+
+    /// Returns the Base64 compressed V2 encoding of the histogram, as used
+    /// in HdrHistogram interval logs.
+    pub fn encode<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let bytes = self.to_bytes(py)?;
+        py.import("base64")?
+            .call_method1("b64encode", (bytes,))?
+            .call_method1("decode", ("ascii",))
+    }
+
+    /// Reconstructs an HdrHistogram from its Base64 compressed V2 encoding.
+    #[staticmethod]
+    pub fn decode(
+        py: Python<'_>,
+        encoded: &Bound<'_, PyAny>,
+    ) -> PyResult<Self> {
+        let data = py
+            .import("base64")?
+            .call_method1("b64decode", (encoded,))
+            .map_err(|_| {
+            SerializationError::new_err("Data is not valid Base64.")
+        })?;
+        Self::from_bytes(py, data.cast::<PyBytes>()?.as_bytes())
+    }
+
+    /// Returns a dict representation of the histogram.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let hist = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("lowest", hist.lowest)?;
+        dict.set_item("highest", hist.highest)?;
+        dict.set_item("sig_figs", hist.sig_figs)?;
+        let counts: Vec<(u64, u64)> =
+            hist.nonzero().map(|(i, c)| (hist.value_at(i), c)).collect();
+        dict.set_item("counts", counts)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs an HdrHistogram from a dict.
+    #[staticmethod]
+    pub fn from_dict(hist_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            hist_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let hist = HdrHistogram::from_parts(
+            get("lowest")?.extract()?,
+            get("highest")?.extract()?,
+            get("sig_figs")?.extract()?,
+            get("counts")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent HdrHistogram counts.")
+        })?;
+        Ok(Self::from_histogram(hist))
+    }
+
+    /// HdrHistogram.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_histogram(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(hist) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(hist) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via HdrHistogram.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(HdrHistogram) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let hist = self.state.lock();
+        format!(
+            "HdrHistogram(lowest={}, highest={}, sig_figs={})",
+            hist.lowest, hist.highest, hist.sig_figs
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: h1 + h2 returns h1.merge(h2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: h1 += h2 merges h2 into h1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,363 @@
+//! HyperLogLog: estimating the number of distinct values
+//!
+//! Each value is hashed to 64 bits (see `hash`); the first `precision` bits
+//! select one of `2^precision` registers, which keeps the largest number of
+//! leading zeros (plus one) seen in the remaining bits. Merging takes the
+//! register-wise maximum. The cardinality is estimated from the register
+//! histogram with the improved estimator of Ertl (2017), which needs no
+//! empirical bias correction and is accurate over the whole range, from a
+//! handful of values up to the linear-counting and saturation regimes.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::hash::{hash_value, hash_values};
+use crate::tdigest::BytesError;
+use crate::{bytes_error, malloc_error};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTHLL~";
+const VERSION: u32 = 1;
+const MIN_PRECISION: u8 = 4;
+const MAX_PRECISION: u8 = 18;
+const SEED: u64 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Result<Self, TryReserveError> {
+        let mut registers = Vec::new();
+        registers.try_reserve_exact(1 << precision)?;
+        registers.resize(1 << precision, 0);
+        Ok(Self {
+            precision,
+            registers,
+        })
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.registers.iter().all(|&r| r == 0)
+    }
+
+    /// Largest possible register value: one plus the number of hash bits
+    /// left after the register index.
+    #[inline]
+    fn max_rank(&self) -> u8 {
+        65 - self.precision
+    }
+
+    /// Relative standard error of the estimate, `1.04 / sqrt(m)`.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    #[inline]
+    pub fn add_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rank = ((hash << self.precision).leading_zeros() as u8 + 1)
+            .min(self.max_rank());
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (register, &rank) in self.registers.iter_mut().zip(&other.registers)
+        {
+            *register = (*register).max(rank);
+        }
+    }
+
+    /// Estimated number of distinct values.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let q = usize::from(self.max_rank()) - 1;
+        let mut counts = vec![0u32; q + 2];
+        for &rank in &self.registers {
+            counts[usize::from(rank)] += 1;
+        }
+        let mut z = m * tau(1.0 - f64::from(counts[q + 1]) / m);
+        for &count in counts[1..=q].iter().rev() {
+            z = 0.5 * (z + f64::from(count));
+        }
+        z += m * sigma(f64::from(counts[0]) / m);
+        m * m / (2.0 * std::f64::consts::LN_2 * z)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u8(self.precision);
+        for &rank in &self.registers {
+            writer.u8(rank);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let precision = reader.u8()?;
+        if !valid_precision(precision) {
+            return Err(BytesError::CorruptData);
+        }
+        let registers = (0..1usize << precision)
+            .map(|_| reader.u8())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Self::from_parts(precision, registers).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent.
+    fn from_parts(precision: u8, registers: Vec<u8>) -> Option<Self> {
+        let sketch = Self {
+            precision,
+            registers,
+        };
+        let valid = valid_precision(precision)
+            && sketch.registers.len() == 1 << precision
+            && sketch.registers.iter().all(|&r| r <= sketch.max_rank());
+        valid.then_some(sketch)
+    }
+}
+
+#[inline]
+fn valid_precision(precision: u8) -> bool {
+    (MIN_PRECISION..=MAX_PRECISION).contains(&precision)
+}
+
+/// Contribution of the empty registers (Ertl, 2017, Algorithm 6).
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+/// Contribution of the saturated registers (Ertl, 2017, Algorithm 6).
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+#[pyclass(name = "HyperLogLog", module = "fastdigest", frozen)]
+pub struct PyHyperLogLog {
+    state: Mutex<HyperLogLog>,
+}
+
+impl PyHyperLogLog {
+    fn from_sketch(sketch: HyperLogLog) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+}
+
+#[pymethods]
+impl PyHyperLogLog {
+    /// Constructs a new empty HyperLogLog instance with `2^precision`
+    /// registers.
+    #[new]
+    #[pyo3(signature = (precision=14))]
+    pub fn py_new(precision: i64) -> PyResult<Self> {
+        let precision = u8::try_from(precision)
+            .ok()
+            .filter(|&p| valid_precision(p))
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "precision must be between {MIN_PRECISION} and \
+                     {MAX_PRECISION}."
+                ))
+            })?;
+        let sketch = HyperLogLog::new(precision).map_err(malloc_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Getter property: returns the precision (log2 of the number of
+    /// registers).
+    #[getter(precision)]
+    pub fn get_precision(&self) -> u8 {
+        self.state.lock().precision
+    }
+
+    /// Getter property: returns the relative standard error of the
+    /// estimate.
+    #[getter(relative_error)]
+    pub fn get_relative_error(&self) -> f64 {
+        self.state.lock().relative_error()
+    }
+
+    /// Updates the sketch (in-place) with a single hashable value.
+    pub fn update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hash = hash_value(x, SEED)?;
+        self.state.lock().add_hash(hash);
+        Ok(())
+    }
+
+    /// Updates the sketch (in-place) with an iterable or array of hashable
+    /// values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hashes = hash_values(x, SEED)?;
+        let mut sketch = self.state.lock();
+        for hash in hashes {
+            sketch.add_hash(hash);
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new HyperLogLog.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        if sketch.precision != other.precision {
+            return Err(PyValueError::new_err(
+                "Cannot merge HyperLogLogs with different precision.",
+            ));
+        }
+        sketch.merge(&other);
+        Ok(())
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns the estimated number of distinct values.
+    pub fn estimate(&self) -> f64 {
+        self.state.lock().estimate()
+    }
+
+    /// Magic method: len(sketch) returns the rounded estimate.
+    pub fn __len__(&self) -> usize {
+        self.estimate().round() as usize
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a HyperLogLog from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = HyperLogLog::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("precision", sketch.precision)?;
+        dict.set_item("registers", PyList::new(py, &sketch.registers)?)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a HyperLogLog from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let sketch = HyperLogLog::from_parts(
+            get("precision")?.extract()?,
+            get("registers")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent HyperLogLog registers.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// HyperLogLog.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via HyperLogLog.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(HyperLogLog) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("HyperLogLog(precision={})", self.state.lock().precision)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
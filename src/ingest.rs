@@ -0,0 +1,392 @@
+//! Streaming ingestion of files, chunk by chunk, without going through
+//! Python objects
+//!
+//! The readers pass the numbers of a binary, CSV or text source to a
+//! callback in chunks, whose error type only needs to convert from
+//! [`IngestError`].
+
+use std::borrow::Cow;
+use std::collections::TryReserveError;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+/// Error while reading numbers.
+#[derive(Debug)]
+pub enum IngestError {
+    Io(io::Error),
+    MemError(TryReserveError),
+    /// Invalid content or arguments, with a message for the user
+    Invalid(String),
+}
+
+impl From<io::Error> for IngestError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<TryReserveError> for IngestError {
+    fn from(e: TryReserveError) -> Self {
+        Self::MemError(e)
+    }
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::MemError(e) => e.fmt(f),
+            Self::Invalid(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for IngestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::MemError(e) => Some(e),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+/// Element type and byte order of a raw binary file, named like NumPy
+/// dtypes (e.g. `"f8"`, `"<i4"`, `">u2"`).
+#[derive(Clone, Copy)]
+pub struct FileDtype {
+    kind: Kind,
+    big_endian: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    F64,
+    F32,
+    I64,
+    I32,
+    I16,
+    I8,
+    U64,
+    U32,
+    U16,
+    U8,
+}
+
+macro_rules! decode {
+    ($bytes:expr, $big_endian:expr, $out:expr, $ty:ty) => {{
+        const SIZE: usize = std::mem::size_of::<$ty>();
+        let items = $bytes.chunks_exact(SIZE).map(|item| {
+            let item: [u8; SIZE] = item.try_into().expect("item size");
+            if $big_endian {
+                <$ty>::from_be_bytes(item) as f64
+            } else {
+                <$ty>::from_le_bytes(item) as f64
+            }
+        });
+        $out.extend(items);
+    }};
+}
+
+impl FileDtype {
+    pub fn parse(dtype: &str) -> Result<Self, IngestError> {
+        let (big_endian, code) = match dtype.as_bytes().first() {
+            Some(b'<') => (false, &dtype[1..]),
+            Some(b'>') => (true, &dtype[1..]),
+            Some(b'=') => (cfg!(target_endian = "big"), &dtype[1..]),
+            _ => (cfg!(target_endian = "big"), dtype),
+        };
+        let kind = match code {
+            "f8" => Kind::F64,
+            "f4" => Kind::F32,
+            "i8" => Kind::I64,
+            "i4" => Kind::I32,
+            "i2" => Kind::I16,
+            "i1" => Kind::I8,
+            "u8" => Kind::U64,
+            "u4" => Kind::U32,
+            "u2" => Kind::U16,
+            "u1" => Kind::U8,
+            _ => {
+                return Err(IngestError::Invalid(format!(
+                    "Unsupported dtype '{dtype}'; expected e.g. 'f8', 'f4', \
+                     'i8' or 'u1' with an optional byte order ('<', '>')."
+                )))
+            }
+        };
+        Ok(Self { kind, big_endian })
+    }
+
+    pub fn item_size(self) -> usize {
+        match self.kind {
+            Kind::F64 | Kind::I64 | Kind::U64 => 8,
+            Kind::F32 | Kind::I32 | Kind::U32 => 4,
+            Kind::I16 | Kind::U16 => 2,
+            Kind::I8 | Kind::U8 => 1,
+        }
+    }
+
+    /// Appends the items of `bytes` (a multiple of the item size) to `out`.
+    fn decode(self, bytes: &[u8], out: &mut Vec<f64>) {
+        let be = self.big_endian;
+        match self.kind {
+            Kind::F64 => decode!(bytes, be, out, f64),
+            Kind::F32 => decode!(bytes, be, out, f32),
+            Kind::I64 => decode!(bytes, be, out, i64),
+            Kind::I32 => decode!(bytes, be, out, i32),
+            Kind::I16 => decode!(bytes, be, out, i16),
+            Kind::I8 => decode!(bytes, be, out, i8),
+            Kind::U64 => decode!(bytes, be, out, u64),
+            Kind::U32 => decode!(bytes, be, out, u32),
+            Kind::U16 => decode!(bytes, be, out, u16),
+            Kind::U8 => decode!(bytes, be, out, u8),
+        }
+    }
+}
+
+/// Reads raw binary `dtype` items and passes them to `ingest` in chunks of
+/// (at most) `chunk` values. Only one chunk is held in memory.
+pub fn read_binary<E: From<IngestError>>(
+    mut reader: impl Read,
+    dtype: FileDtype,
+    chunk: usize,
+    mut ingest: impl FnMut(&[f64]) -> Result<(), E>,
+) -> Result<(), E> {
+    let chunk_bytes = chunk.saturating_mul(dtype.item_size());
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes
+        .try_reserve_exact(chunk_bytes)
+        .map_err(IngestError::from)?;
+    bytes.resize(chunk_bytes, 0);
+    let mut values: Vec<f64> = Vec::new();
+    values.try_reserve_exact(chunk).map_err(IngestError::from)?;
+    loop {
+        let n =
+            read_full(&mut reader, &mut bytes).map_err(IngestError::from)?;
+        if n % dtype.item_size() != 0 {
+            return Err(IngestError::Invalid(
+                "File size is not a multiple of the dtype's item size.".into(),
+            )
+            .into());
+        }
+        if n == 0 {
+            return Ok(());
+        }
+        values.clear();
+        dtype.decode(&bytes[..n], &mut values);
+        ingest(&values)?;
+        if n < chunk_bytes {
+            return Ok(());
+        }
+    }
+}
+
+/// Fills `buf` as far as possible, returning the number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Column of a CSV file, selected by its header name or by its position.
+pub enum CsvColumn {
+    Name(String),
+    Index(usize),
+}
+
+/// Reads the numbers in `column` of a CSV file with a header row and passes
+/// them to `ingest` in chunks of (at most) `chunk` values. Empty fields are
+/// skipped. Quoted fields follow RFC 4180 and may span several lines.
+pub fn read_csv<E: From<IngestError>>(
+    mut reader: impl BufRead,
+    column: &CsvColumn,
+    delimiter: u8,
+    chunk: usize,
+    mut ingest: impl FnMut(&[f64]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut record: Vec<u8> = Vec::new();
+    let mut line = 0;
+    if !read_record(&mut reader, &mut record, &mut line)? {
+        return Err(IngestError::Invalid("CSV file is empty.".into()).into());
+    }
+    let header = record.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&record);
+    let index = match column {
+        CsvColumn::Index(index) => *index,
+        CsvColumn::Name(name) => Fields::new(header, delimiter)
+            .position(|field| *field == *name.as_bytes())
+            .ok_or_else(|| {
+                IngestError::Invalid(format!(
+                    "Column '{name}' not found in the CSV header."
+                ))
+            })?,
+    };
+
+    let mut values: Vec<f64> = Vec::new();
+    values.try_reserve_exact(chunk).map_err(IngestError::from)?;
+    while read_record(&mut reader, &mut record, &mut line)? {
+        if record.is_empty() {
+            continue;
+        }
+        let Some(field) = Fields::new(&record, delimiter).nth(index) else {
+            return Err(IngestError::Invalid(format!(
+                "Line {line} of the CSV file has no column {index}."
+            ))
+            .into());
+        };
+        if field.is_empty() {
+            continue;
+        }
+        let value = std::str::from_utf8(&field)
+            .ok()
+            .and_then(|field| field.parse::<f64>().ok())
+            .ok_or_else(|| {
+                IngestError::Invalid(format!(
+                    "Could not parse '{}' in line {line} of the CSV file as \
+                     a number.",
+                    String::from_utf8_lossy(&field)
+                ))
+            })?;
+        values.push(value);
+        if values.len() == chunk {
+            ingest(&values)?;
+            values.clear();
+        }
+    }
+    if !values.is_empty() {
+        ingest(&values)?;
+    }
+    Ok(())
+}
+
+/// Reads numbers separated by whitespace (e.g. one per line) and passes
+/// them to `ingest` in chunks of (at most) `chunk` values.
+pub fn read_text<E: From<IngestError>>(
+    mut reader: impl BufRead,
+    chunk: usize,
+    mut ingest: impl FnMut(&[f64]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut record: Vec<u8> = Vec::new();
+    let mut line = 0;
+    let mut values: Vec<f64> = Vec::new();
+    values.try_reserve_exact(chunk).map_err(IngestError::from)?;
+    loop {
+        record.clear();
+        if reader
+            .read_until(b'\n', &mut record)
+            .map_err(IngestError::from)?
+            == 0
+        {
+            break;
+        }
+        line += 1;
+        for field in record.split(u8::is_ascii_whitespace) {
+            if field.is_empty() {
+                continue;
+            }
+            let value = std::str::from_utf8(field)
+                .ok()
+                .and_then(|field| field.parse::<f64>().ok())
+                .ok_or_else(|| {
+                    IngestError::Invalid(format!(
+                        "Could not parse '{}' in line {line} as a number.",
+                        String::from_utf8_lossy(field)
+                    ))
+                })?;
+            values.push(value);
+            if values.len() == chunk {
+                ingest(&values)?;
+                values.clear();
+            }
+        }
+    }
+    if !values.is_empty() {
+        ingest(&values)?;
+    }
+    Ok(())
+}
+
+/// Reads the next record into `record` without its line terminator, joining
+/// lines inside quotes. Returns `false` at the end of the file.
+fn read_record(
+    reader: &mut impl BufRead,
+    record: &mut Vec<u8>,
+    line: &mut usize,
+) -> Result<bool, IngestError> {
+    record.clear();
+    let mut quotes = 0;
+    loop {
+        let start = record.len();
+        if reader.read_until(b'\n', record)? == 0 {
+            return Ok(start > 0);
+        }
+        *line += 1;
+        quotes += record[start..].iter().filter(|&&b| b == b'"').count();
+        if quotes % 2 == 0 {
+            break;
+        }
+    }
+    while record.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+        record.pop();
+    }
+    Ok(true)
+}
+
+/// Iterator over the (trimmed and unquoted) fields of a CSV record.
+struct Fields<'a> {
+    record: &'a [u8],
+    delimiter: u8,
+    start: Option<usize>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(record: &'a [u8], delimiter: u8) -> Self {
+        Self {
+            record,
+            delimiter,
+            start: Some(0),
+        }
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.start?;
+        let mut end = start;
+        let mut quoted = false;
+        while let Some(&b) = self.record.get(end) {
+            if b == b'"' {
+                quoted = !quoted;
+            } else if b == self.delimiter && !quoted {
+                break;
+            }
+            end += 1;
+        }
+        self.start = (end < self.record.len()).then_some(end + 1);
+        let field = self.record[start..end].trim_ascii();
+        match field {
+            [b'"', inner @ .., b'"'] if inner.contains(&b'"') => {
+                let mut unquoted = Vec::with_capacity(inner.len());
+                let mut bytes = inner.iter();
+                while let Some(&b) = bytes.next() {
+                    unquoted.push(b);
+                    if b == b'"' {
+                        bytes.next();
+                    }
+                }
+                Some(Cow::Owned(unquoted))
+            }
+            [b'"', inner @ .., b'"'] => Some(Cow::Borrowed(inner)),
+            _ => Some(Cow::Borrowed(field)),
+        }
+    }
+}
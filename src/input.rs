@@ -0,0 +1,286 @@
+//! Extraction of numeric input from Python objects
+
+use crate::arrow::{ArrowIter, ArrowValues};
+use crate::dlpack::{DlpackIter, DlpackValues};
+use crate::errors::PrecisionWarning;
+use pyo3::buffer::{Element, PyBuffer, ReadOnlyCell};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyFloat, PyInt};
+use std::ffi::CString;
+use std::slice;
+
+fn cells<'a, T: Element>(
+    buffer: &'a PyBuffer<T>,
+    py: Python<'a>,
+) -> slice::Iter<'a, ReadOnlyCell<T>> {
+    buffer
+        .as_slice(py)
+        .expect("buffer should be C-contiguous")
+        .iter()
+}
+
+macro_rules! float_values {
+    ($($variant:ident($ty:ty)),* $(,)?) => {
+        /// Numeric values passed from Python. Objects exposing a C-contiguous
+        /// buffer of floats or integers (e.g. NumPy arrays) are read in place
+        /// without copying; each element is converted to `f64` on the fly.
+        pub enum FloatValues {
+            $($variant(PyBuffer<$ty>),)*
+            Arrow(ArrowValues),
+            Dlpack(DlpackValues),
+            Vec(Vec<f64>),
+        }
+
+        impl FloatValues {
+            /// Reads `obj` in place if it exposes a C-contiguous buffer of a
+            /// supported element type.
+            pub fn from_buffer(obj: &Bound<'_, PyAny>) -> Option<Self> {
+                $(
+                    if let Ok(buffer) = PyBuffer::<$ty>::get(obj) {
+                        return buffer
+                            .is_c_contiguous()
+                            .then_some(Self::$variant(buffer));
+                    }
+                )*
+                None
+            }
+
+            #[inline]
+            pub fn len(&self) -> usize {
+                match self {
+                    $(Self::$variant(buffer) => buffer.item_count(),)*
+                    Self::Arrow(values) => values.len(),
+                    Self::Dlpack(values) => values.len(),
+                    Self::Vec(values) => values.len(),
+                }
+            }
+
+            pub fn iter<'a>(&'a self, py: Python<'a>) -> FloatIter<'a> {
+                match self {
+                    $(Self::$variant(buffer) => {
+                        FloatIter::$variant(cells(buffer, py))
+                    })*
+                    Self::Arrow(values) => FloatIter::Arrow(values.iter()),
+                    Self::Dlpack(values) => FloatIter::Dlpack(values.iter()),
+                    Self::Vec(values) => FloatIter::Vec(values.iter()),
+                }
+            }
+        }
+
+        pub enum FloatIter<'a> {
+            $($variant(slice::Iter<'a, ReadOnlyCell<$ty>>),)*
+            Arrow(ArrowIter<'a>),
+            Dlpack(DlpackIter<'a>),
+            Vec(slice::Iter<'a, f64>),
+        }
+
+        impl Iterator for FloatIter<'_> {
+            type Item = f64;
+
+            #[inline]
+            fn next(&mut self) -> Option<f64> {
+                match self {
+                    $(Self::$variant(iter) => {
+                        iter.next().map(|x| x.get() as f64)
+                    })*
+                    Self::Arrow(iter) => iter.next(),
+                    Self::Dlpack(iter) => iter.next(),
+                    Self::Vec(iter) => iter.next().copied(),
+                }
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                match self {
+                    $(Self::$variant(iter) => iter.size_hint(),)*
+                    Self::Arrow(iter) => iter.size_hint(),
+                    Self::Dlpack(iter) => iter.size_hint(),
+                    Self::Vec(iter) => iter.size_hint(),
+                }
+            }
+        }
+    };
+}
+
+float_values!(
+    F64(f64),
+    F32(f32),
+    I64(i64),
+    I32(i32),
+    I16(i16),
+    I8(i8),
+    U64(u64),
+    U32(u32),
+    U16(u16),
+    U8(u8),
+);
+
+impl FloatValues {
+    /// Reads a buffer or a DLPack tensor in place, or converts the items of
+    /// any other iterable (see `extract_value`).
+    pub fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Some(values) = Self::from_buffer(obj) {
+            return Ok(values);
+        }
+        if let Some(values) = Self::from_dlpack(obj) {
+            return Ok(values);
+        }
+        let items = obj.try_iter().map_err(|_| {
+            PyTypeError::new_err(format!(
+                "Expected an iterable of numbers, got '{}'.",
+                type_name(obj)
+            ))
+        })?;
+        let mut values = Vec::with_capacity(obj.len().unwrap_or(0));
+        let mut check = PrecisionCheck::default();
+        for (i, item) in items.enumerate() {
+            let item = item?;
+            let x = check.extract(&item).map_err(|e| {
+                if e.is_instance_of::<PyTypeError>(obj.py()) {
+                    PyTypeError::new_err(format!(
+                        "Values must be numbers, got '{}' at index {i}.",
+                        type_name(&item)
+                    ))
+                } else {
+                    e
+                }
+            })?;
+            values.push(x);
+        }
+        Ok(Self::Vec(values))
+    }
+
+    /// Reads the values of a Series-like object through the Arrow C data
+    /// interface or its `to_numpy` method. Missing values (null/NA) are
+    /// converted to NaN.
+    pub fn from_series(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Some(values) = ArrowValues::extract(obj)? {
+            return Ok(Self::Arrow(values));
+        }
+        // numeric dtypes without NA are exposed as a view, not a copy
+        if let Some(values) = Self::from_buffer(&obj.call_method0("to_numpy")?)
+        {
+            return Ok(values);
+        }
+        let kwargs = PyDict::new(obj.py());
+        kwargs.set_item("dtype", "float64")?;
+        kwargs.set_item("na_value", f64::NAN)?;
+        Self::extract(&obj.call_method("to_numpy", (), Some(&kwargs))?)
+    }
+
+    /// Reads a tensor through the DLPack protocol: in place if it is
+    /// contiguous, and copied (in row-major order) otherwise.
+    pub fn from_dlpack(obj: &Bound<'_, PyAny>) -> Option<Self> {
+        let values = DlpackValues::extract(obj)?;
+        if values.is_contiguous() {
+            Some(Self::Dlpack(values))
+        } else {
+            Some(Self::Vec(values.to_vec()))
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ExactSizeIterator for FloatIter<'_> {}
+
+/// Converts a number to `f64`, e.g. a `decimal.Decimal` (with `float()`).
+pub fn extract_value(obj: &Bound<'_, PyAny>) -> PyResult<f64> {
+    PrecisionCheck::default().extract(obj)
+}
+
+/// Issues a PrecisionWarning for the first Decimal of a batch that a float
+/// cannot represent exactly. The warning is ignored unless enabled with a
+/// warnings filter, which may also turn it into an error.
+#[derive(Default)]
+struct PrecisionCheck {
+    warned: bool,
+}
+
+impl PrecisionCheck {
+    fn extract(&mut self, obj: &Bound<'_, PyAny>) -> PyResult<f64> {
+        if let Ok(x) = obj.cast_exact::<PyFloat>() {
+            return Ok(x.value());
+        }
+        let x: f64 = obj.extract()?;
+        // non-finite values are rejected later on
+        if self.warned || !x.is_finite() || obj.is_instance_of::<PyInt>() {
+            return Ok(x);
+        }
+        let py = obj.py();
+        let decimal = py.import("decimal")?.getattr("Decimal")?;
+        if obj.is_instance(&decimal)?
+            && !decimal.call_method1("from_float", (x,))?.eq(obj)?
+        {
+            self.warned = true;
+            let message = format!(
+                "{} cannot be represented exactly as a float.",
+                obj.repr()?
+            );
+            let message = CString::new(message)
+                .unwrap_or_else(|_| c"Inexact Decimal value.".to_owned());
+            let category = py.get_type::<PrecisionWarning>();
+            PyErr::warn(py, &category, &message, 1)?;
+        }
+        Ok(x)
+    }
+}
+
+fn type_name(obj: &Bound<'_, PyAny>) -> String {
+    obj.get_type()
+        .name()
+        .map_or_else(|_| "object".to_owned(), |name| name.to_string())
+}
+
+/// Row-major values of a 2D array.
+pub struct Matrix {
+    pub values: Vec<f64>,
+    pub n_cols: usize,
+}
+
+impl Matrix {
+    /// Reads `x` as a 2D float64 NumPy array (which copies it only if
+    /// necessary), e.g. a nested list or a DataFrame.
+    pub fn read(x: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let py = x.py();
+        let array = py
+            .import("numpy")?
+            .call_method1("ascontiguousarray", (x, "float64"))?;
+        let ndim: usize = array.getattr("ndim")?.extract()?;
+        if ndim != 2 {
+            return Err(PyValueError::new_err(format!(
+                "Expected a 2D array, got a {ndim}D array."
+            )));
+        }
+        let (_, n_cols): (usize, usize) = array.getattr("shape")?.extract()?;
+        let values = PyBuffer::<f64>::get(&array)?.to_vec(py)?;
+        Ok(Self { values, n_cols })
+    }
+
+    pub fn n_rows(&self) -> usize {
+        self.values.len().checked_div(self.n_cols).unwrap_or(0)
+    }
+}
+
+/// Checks whether `obj` is a pandas Series (without importing pandas) or
+/// implements the Arrow PyCapsule interface, like polars Series or pyarrow
+/// arrays.
+pub fn is_series(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(obj.hasattr("__arrow_c_stream__")?
+        || obj.hasattr("__arrow_c_array__")?
+        || is_pandas_series(obj)?)
+}
+
+/// Checks whether `obj` is a pandas Series without importing pandas.
+fn is_pandas_series(obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+    let py = obj.py();
+    let modules = py.import("sys")?.getattr("modules")?;
+    match modules.cast::<PyDict>()?.get_item("pandas")? {
+        Some(pandas) => obj.is_instance(&pandas.getattr("Series")?),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,98 @@
+//! Dask tree reduction building a single TDigest from a collection
+
+use super::update_from_array;
+use crate::errors::InvalidQuantileError;
+use crate::{validate_max_centroids, PyTDigest};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use pyo3::wrap_pyfunction;
+
+/// Task body: builds a digest from one partition (or chunk).
+#[pyfunction]
+#[pyo3(signature = (values, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn build_partition(
+    values: &Bound<'_, PyAny>,
+    max_centroids: i64,
+) -> PyResult<PyTDigest> {
+    let digest = PyTDigest::new(max_centroids)?;
+    update_from_array(&digest, values)?;
+    Ok(digest)
+}
+
+/// Builds a TDigest from a Dask Series (or Array) with one digest per
+/// partition, which are then merged in a tree of `merge_all` calls.
+///
+/// Returns the digest, or the estimated `quantiles` if given. With
+/// `compute=False`, the corresponding Dask Delayed object is returned.
+#[pyfunction]
+#[pyo3(signature = (
+    collection,
+    max_centroids=crate::TD_SIZE_DEFAULT as i64,
+    quantiles=None,
+    split_every=8,
+    compute=true,
+))]
+pub fn tdigest<'py>(
+    collection: &Bound<'py, PyAny>,
+    max_centroids: i64,
+    quantiles: Option<Vec<f64>>,
+    split_every: usize,
+    compute: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = collection.py();
+    validate_max_centroids(max_centroids)?;
+    if split_every < 2 {
+        return Err(PyValueError::new_err("split_every must be at least 2."));
+    }
+    if let Some(q) = &quantiles {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+    }
+
+    let delayed = py.import("dask")?.getattr("delayed")?;
+    let m = py.import("fastdigest.dask")?;
+    let build = delayed.call1((m.getattr("build_partition")?,))?;
+    let merge_all =
+        delayed.call1((py.import("fastdigest")?.getattr("merge_all")?,))?;
+
+    let mut parts = collection.call_method0("to_delayed")?;
+    if parts.hasattr("ravel")? {
+        // Dask arrays return an ndarray of Delayed chunks
+        parts = parts.call_method0("ravel")?.call_method0("tolist")?;
+    }
+    let mut level = parts
+        .try_iter()?
+        .map(|part| build.call1((part?, max_centroids)))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let mut result = if level.is_empty() {
+        delayed.call1((PyTDigest::new(max_centroids)?,))?
+    } else {
+        while level.len() > 1 {
+            level = level
+                .chunks(split_every)
+                .map(|group| merge_all.call1((PyList::new(py, group)?,)))
+                .collect::<PyResult<Vec<_>>>()?;
+        }
+        level.pop().unwrap()
+    };
+    if let Some(q) = quantiles {
+        result = result.getattr("quantile_vec")?.call1((q,))?;
+    }
+
+    if compute {
+        result.call_method0("compute")
+    } else {
+        Ok(result)
+    }
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(tdigest, m)?)?;
+    m.add_function(wrap_pyfunction!(build_partition, m)?)?;
+    Ok(())
+}
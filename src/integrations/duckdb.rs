@@ -0,0 +1,89 @@
+//! DuckDB functions backed by TDigest, exchanged as binary BLOBs
+//!
+//! DuckDB's Python API only supports scalar UDFs, so the aggregates are
+//! registered as macros that collect the column with `list()` first. Each
+//! group is therefore materialized as one list before it is digested.
+
+use super::{tdigest_cdf, tdigest_quantile};
+use crate::input::FloatValues;
+use crate::{merge_all, PyTDigest, Sorting};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::wrap_pyfunction;
+
+/// Builds a serialized TDigest from a list of values, ignoring NULLs.
+#[pyfunction]
+pub fn tdigest_from_list<'py>(
+    py: Python<'py>,
+    values: Vec<Option<f64>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let x = FloatValues::Vec(values.into_iter().flatten().collect());
+    let digest = PyTDigest::new(crate::TD_SIZE_DEFAULT as i64)?;
+    digest.merge_values(py, &x, None, false, Sorting::SERIAL)?;
+    digest.to_bytes(py, "exact")
+}
+
+/// Merges a list of serialized TDigests, ignoring NULLs.
+#[pyfunction]
+pub fn tdigest_merge_list<'py>(
+    py: Python<'py>,
+    blobs: Vec<Option<Vec<u8>>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let digests = blobs
+        .into_iter()
+        .flatten()
+        .map(|blob| PyTDigest::from_bytes(&blob, false))
+        .collect::<PyResult<Vec<_>>>()?;
+    let digests = pyo3::types::PyList::new(py, digests)?;
+    merge_all(digests.as_any(), None, Some(1), false, false)?
+        .to_bytes(py, "exact")
+}
+
+/// Registers the TDigest functions and aggregate macros on a connection:
+///
+/// - `tdigest_agg(x)`: aggregate, builds a digest BLOB from a column
+/// - `tdigest_merge_agg(d)`: aggregate, merges a column of digest BLOBs
+/// - `tdigest_quantile(d, q)`: estimated quantile of a digest BLOB
+/// - `tdigest_cdf(d, x)`: estimated CDF of a digest BLOB
+#[pyfunction]
+pub fn register(conn: &Bound<'_, PyAny>) -> PyResult<()> {
+    let py = conn.py();
+    let m = py.import("fastdigest.duckdb")?;
+    let sqltype = |t: &str| conn.call_method1("sqltype", (t,));
+    let define = |name: &str, params: &[&str], ret: &str| -> PyResult<()> {
+        let params = params
+            .iter()
+            .map(|t| sqltype(t))
+            .collect::<PyResult<Vec<_>>>()?;
+        conn.call_method1(
+            "create_function",
+            (name, m.getattr(name)?, params, sqltype(ret)?),
+        )?;
+        Ok(())
+    };
+
+    define("tdigest_from_list", &["DOUBLE[]"], "BLOB")?;
+    define("tdigest_merge_list", &["BLOB[]"], "BLOB")?;
+    define("tdigest_quantile", &["BLOB", "DOUBLE"], "DOUBLE")?;
+    define("tdigest_cdf", &["BLOB", "DOUBLE"], "DOUBLE")?;
+    conn.call_method1(
+        "execute",
+        ("CREATE OR REPLACE MACRO tdigest_agg(x) AS \
+          tdigest_from_list(list(x::DOUBLE))",),
+    )?;
+    conn.call_method1(
+        "execute",
+        ("CREATE OR REPLACE MACRO tdigest_merge_agg(d) AS \
+          tdigest_merge_list(list(d))",),
+    )?;
+    Ok(())
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(register, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_from_list, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_merge_list, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_quantile, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_cdf, m)?)?;
+    Ok(())
+}
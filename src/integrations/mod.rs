@@ -0,0 +1,66 @@
+//! Helpers for using TDigest from other data processing libraries
+
+pub mod dask;
+pub mod duckdb;
+pub mod pandas;
+pub mod polars;
+pub mod sklearn;
+pub mod spark;
+pub mod sqlite;
+
+use crate::input::FloatValues;
+use crate::{PyTDigest, Sorting};
+use pyo3::prelude::*;
+
+/// Extends `digest` with the non-null values of a pandas Series or NumPy
+/// array (NaN values are skipped).
+pub fn update_from_array(
+    digest: &PyTDigest,
+    values: &Bound<'_, PyAny>,
+) -> PyResult<()> {
+    let x = if values.hasattr("to_numpy")? {
+        FloatValues::from_series(values)?
+    } else {
+        FloatValues::extract(values)?
+    };
+    digest.merge_values(values.py(), &x, None, true, Sorting::SERIAL)
+}
+
+/// Estimates a quantile of a serialized TDigest (NULL if empty).
+#[pyfunction]
+pub fn tdigest_quantile(blob: &[u8], q: f64) -> PyResult<Option<f64>> {
+    let digest = PyTDigest::from_bytes(blob, false)?;
+    if digest.is_empty()? {
+        return Ok(None);
+    }
+    digest.quantile(q).map(|x| Some(x.value))
+}
+
+/// Estimates the CDF of a serialized TDigest at `x` (NULL if empty).
+#[pyfunction]
+pub fn tdigest_cdf(blob: &[u8], x: f64) -> PyResult<Option<f64>> {
+    let digest = PyTDigest::from_bytes(blob, false)?;
+    if digest.is_empty()? {
+        return Ok(None);
+    }
+    digest.rank(x).map(Some)
+}
+
+/// Creates the submodule `fastdigest.<name>` and makes it importable. The
+/// qualified name is set first so that its functions pickle by reference.
+pub fn add_submodule(
+    parent: &Bound<'_, PyModule>,
+    name: &str,
+    init: fn(&Bound<'_, PyModule>) -> PyResult<()>,
+) -> PyResult<()> {
+    let py = parent.py();
+    let qualname = format!("fastdigest.{name}");
+    let module = PyModule::new(py, &qualname)?;
+    module.gil_used(false)?;
+    init(&module)?;
+    parent.add(name, &module)?;
+    py.import("sys")?
+        .getattr("modules")?
+        .set_item(qualname, module)?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! pandas groupby aggregation building one TDigest per group
+//!
+//! `SeriesGroupBy.agg` calls its function once per group, so `tdigest_agg`
+//! can only make each of these calls cheap. `tdigest_groupby` takes the
+//! groupby object itself instead: the group number of every row (from
+//! `ngroup`) and the values are read once, and the values are bucketed by
+//! group in Rust, merging each digest once.
+
+use super::update_from_array;
+use crate::digest_array::DigestArray;
+use crate::digest_map::wrap_digest;
+use crate::errors::InvalidQuantileError;
+use crate::input::FloatValues;
+use crate::{
+    malloc_error, validate_max_centroids, validate_n_threads, validate_values,
+    PyTDigest,
+};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::wrap_pyfunction;
+
+/// Aggregation function for `SeriesGroupBy.agg`: builds a digest from the
+/// non-null values of one group.
+#[pyfunction]
+#[pyo3(signature = (values, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn tdigest_agg(
+    values: &Bound<'_, PyAny>,
+    max_centroids: i64,
+) -> PyResult<PyTDigest> {
+    let digest = PyTDigest::new(max_centroids)?;
+    update_from_array(&digest, values)?;
+    Ok(digest)
+}
+
+/// Builds one TDigest per group of a SeriesGroupBy in a single pass over
+/// the data, ignoring null values and rows whose key is dropped (NaN keys
+/// with `dropna=True`).
+///
+/// Returns a Series of digests indexed by the group keys, or with
+/// `quantiles`, a DataFrame of the estimated quantiles with one column per
+/// quantile (NaN for groups without values).
+#[pyfunction]
+#[pyo3(signature = (
+    grouped,
+    max_centroids=crate::TD_SIZE_DEFAULT as i64,
+    quantiles=None,
+    n_threads=Some(1),
+))]
+pub fn tdigest_groupby<'py>(
+    grouped: &Bound<'py, PyAny>,
+    max_centroids: i64,
+    quantiles: Option<Vec<f64>>,
+    n_threads: Option<i64>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = grouped.py();
+    let max_size = validate_max_centroids(max_centroids)?;
+    let n_threads = validate_n_threads(n_threads)?;
+    if let Some(q) = &quantiles {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+    }
+    let series = grouped.getattr("obj")?;
+    if series.hasattr("columns")? {
+        return Err(PyTypeError::new_err(
+            "Pass a SeriesGroupBy, e.g. df.groupby(key)[column].",
+        ));
+    }
+
+    // group numbers follow the order of the result index
+    let index = grouped.call_method0("size")?.getattr("index")?;
+    let codes = FloatValues::extract(
+        &grouped.call_method0("ngroup")?.call_method0("to_numpy")?,
+    )?;
+    let values = FloatValues::from_series(&series)?;
+    let mut indices = Vec::new();
+    let mut kept = Vec::new();
+    for (code, x) in codes.iter(py).zip(values.iter(py)) {
+        if code >= 0.0 && !x.is_nan() {
+            indices.push(code as usize);
+            kept.push(x);
+        }
+    }
+    validate_values(kept.iter().copied())?;
+    let n_groups = index.len()?;
+    if indices.iter().any(|&i| i >= n_groups) {
+        return Err(PyValueError::new_err(
+            "Group numbers do not match the groups.",
+        ));
+    }
+    let mut array = py.detach(|| {
+        let mut array =
+            DigestArray::new(n_groups, max_size).map_err(malloc_error)?;
+        array
+            .add_grouped(&indices, &kept, None, n_threads)
+            .map_err(malloc_error)?;
+        Ok::<_, PyErr>(array)
+    })?;
+
+    let pd = py.import("pandas")?;
+    match quantiles {
+        Some(q) => {
+            let columns = PyDict::new(py);
+            for q_i in q {
+                let estimates = array
+                    .map_digests(|digest| digest.estimate_quantile(q_i))
+                    .map_err(malloc_error)?;
+                columns.set_item(q_i, estimates)?;
+            }
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("index", index)?;
+            pd.getattr("DataFrame")?.call((columns,), Some(&kwargs))
+        }
+        None => {
+            let digests: Vec<PyTDigest> = array
+                .into_digests()
+                .map_err(malloc_error)?
+                .into_iter()
+                .map(wrap_digest)
+                .collect();
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("index", index)?;
+            kwargs.set_item("name", series.getattr("name")?)?;
+            kwargs.set_item("dtype", "object")?;
+            pd.getattr("Series")?.call((digests,), Some(&kwargs))
+        }
+    }
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(tdigest_agg, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_groupby, m)?)?;
+    Ok(())
+}
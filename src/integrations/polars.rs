@@ -0,0 +1,167 @@
+//! Polars expressions backed by TDigest, exchanged as binary columns
+//!
+//! The expressions wrap the module-level bodies below with `map_batches`,
+//! which polars calls once per group in a `group_by().agg()` context (and
+//! once per column otherwise), so they also work in lazy queries. As with
+//! the Spark UDFs, the bodies are referenced by name so that the query
+//! plans pickle.
+
+use super::update_from_array;
+use crate::errors::InvalidQuantileError;
+use crate::{merge_all, validate_max_centroids, PyTDigest};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyString};
+use pyo3::wrap_pyfunction;
+
+fn to_expr<'py>(
+    pl: &Bound<'py, PyModule>,
+    col: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    if col.is_instance_of::<PyString>() {
+        pl.call_method1("col", (col,))
+    } else {
+        Ok(col.clone())
+    }
+}
+
+fn new_series<'py>(
+    py: Python<'py>,
+    values: impl IntoPyObject<'py>,
+    dtype: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let pl = py.import("polars")?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("values", values)?;
+    kwargs.set_item("dtype", pl.getattr(dtype)?)?;
+    pl.getattr("Series")?.call((), Some(&kwargs))
+}
+
+/// Applies the body `name` of this module with `map_batches`, binding the
+/// keyword arguments `bound` first.
+fn map_batches<'py>(
+    expr: &Bound<'py, PyAny>,
+    name: &str,
+    bound: Option<&Bound<'py, PyDict>>,
+    return_dtype: &str,
+    returns_scalar: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = expr.py();
+    let mut function = py.import("fastdigest.polars")?.getattr(name)?;
+    if let Some(bound) = bound {
+        function = py
+            .import("functools")?
+            .getattr("partial")?
+            .call((function,), Some(bound))?;
+    }
+    let kwargs = PyDict::new(py);
+    kwargs.set_item(
+        "return_dtype",
+        py.import("polars")?.getattr(return_dtype)?,
+    )?;
+    kwargs.set_item("returns_scalar", returns_scalar)?;
+    expr.call_method("map_batches", (function,), Some(&kwargs))
+}
+
+/// Expression body: builds a serialized digest from a Series of values.
+#[pyfunction]
+#[pyo3(signature = (values, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn build_series<'py>(
+    values: &Bound<'py, PyAny>,
+    max_centroids: i64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = values.py();
+    let digest = PyTDigest::new(max_centroids)?;
+    update_from_array(&digest, values)?;
+    new_series(py, [digest.to_bytes(py, "exact")?], "Binary")
+}
+
+/// Expression body: merges a Series of serialized digests.
+#[pyfunction]
+pub fn merge_series<'py>(
+    blobs: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = blobs.py();
+    let digests = blobs
+        .call_method0("drop_nulls")?
+        .try_iter()?
+        .map(|blob| PyTDigest::from_bytes(&blob?.extract::<Vec<u8>>()?, false))
+        .collect::<PyResult<Vec<_>>>()?;
+    let digests = PyList::new(py, digests)?;
+    let merged = merge_all(digests.as_any(), None, Some(1), false, false)?;
+    new_series(py, [merged.to_bytes(py, "exact")?], "Binary")
+}
+
+/// Expression body: estimates the quantile `q` of each serialized digest.
+#[pyfunction]
+pub fn quantile_series<'py>(
+    blobs: &Bound<'py, PyAny>,
+    q: f64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let results = blobs
+        .try_iter()?
+        .map(|blob| {
+            let blob = blob?;
+            if blob.is_none() {
+                return Ok(None);
+            }
+            super::tdigest_quantile(&blob.extract::<Vec<u8>>()?, q)
+        })
+        .collect::<PyResult<Vec<Option<f64>>>>()?;
+    new_series(blobs.py(), results, "Float64")
+}
+
+/// Aggregation expression: builds a serialized digest of a column (per
+/// group in `group_by().agg()`).
+#[pyfunction]
+#[pyo3(signature = (col, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn tdigest<'py>(
+    col: &Bound<'py, PyAny>,
+    max_centroids: i64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = col.py();
+    validate_max_centroids(max_centroids)?;
+    let pl = py.import("polars")?;
+    let expr =
+        to_expr(&pl, col)?.call_method1("cast", (pl.getattr("Float64")?,))?;
+    let bound = PyDict::new(py);
+    bound.set_item("max_centroids", max_centroids)?;
+    map_batches(&expr, "build_series", Some(&bound), "Binary", true)
+}
+
+/// Aggregation expression: merges a column of serialized digests.
+#[pyfunction]
+pub fn tdigest_merge<'py>(
+    col: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let expr = to_expr(&col.py().import("polars")?, col)?;
+    map_batches(&expr, "merge_series", None, "Binary", true)
+}
+
+/// Expression of the estimated quantiles of a column of serialized
+/// digests.
+#[pyfunction]
+pub fn tdigest_quantile<'py>(
+    col: &Bound<'py, PyAny>,
+    q: f64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = col.py();
+    if !(0.0..=1.0).contains(&q) {
+        return Err(InvalidQuantileError::new_err(
+            "q must be between 0 and 1.",
+        ));
+    }
+    let expr = to_expr(&py.import("polars")?, col)?;
+    let bound = PyDict::new(py);
+    bound.set_item("q", q)?;
+    map_batches(&expr, "quantile_series", Some(&bound), "Float64", false)
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(tdigest, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_merge, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_quantile, m)?)?;
+    m.add_function(wrap_pyfunction!(build_series, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_series, m)?)?;
+    m.add_function(wrap_pyfunction!(quantile_series, m)?)?;
+    Ok(())
+}
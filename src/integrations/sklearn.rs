@@ -0,0 +1,857 @@
+//! scikit-learn compatible transformers fitting one TDigest per feature
+//!
+//! The estimators follow the scikit-learn conventions without depending on
+//! it: the constructor arguments are kept as given (so that `clone` can
+//! rebuild an estimator from `get_params`), `fit` and `partial_fit` return
+//! the estimator itself, and the inputs are read as 2D float64 arrays.
+//! `partial_fit` merges the digests of each batch, so that the transforms
+//! can be fitted on data that does not fit in memory.
+
+use crate::digest_array::DigestArray;
+use crate::digest_map::wrap_digest;
+use crate::input::Matrix;
+use crate::tdigest::{TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, f64_array, malloc_error, validate_max_centroids,
+    validate_values, PyTDigest,
+};
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyAttributeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+/// Output values are at most this close to 0 or 1 before being mapped to
+/// the normal distribution, as in scikit-learn.
+const BOUNDS_THRESHOLD: f64 = 1e-7;
+
+/// Maps every value of `x` with `f(column, x)`, keeping NaN values, into
+/// a NumPy array of the same shape.
+fn map_matrix<'py>(
+    py: Python<'py>,
+    x: &Matrix,
+    f: impl Fn(usize, f64) -> f64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let results: Vec<f64> = x
+        .values
+        .iter()
+        .enumerate()
+        .map(|(k, &x_k)| {
+            if x_k.is_nan() {
+                x_k
+            } else {
+                f(k % x.n_cols, x_k)
+            }
+        })
+        .collect();
+    f64_array(py, &results)?.call_method1("reshape", ((x.n_rows(), x.n_cols),))
+}
+
+/// The digests of the training data, one per feature (column).
+struct Features {
+    max_size: usize,
+    array: Option<DigestArray>,
+}
+
+impl Features {
+    fn new() -> Self {
+        Self {
+            max_size: TD_SIZE_DEFAULT,
+            array: None,
+        }
+    }
+
+    /// Fits the digests to `x`, extending the current ones unless `reset`.
+    /// Missing values are ignored.
+    fn fit(&mut self, py: Python<'_>, x: &Matrix, reset: bool) -> PyResult<()> {
+        if !reset {
+            self.check(x)?;
+        }
+        let mut indices = Vec::new();
+        let mut kept = Vec::new();
+        for (k, &x_k) in x.values.iter().enumerate() {
+            if !x_k.is_nan() {
+                indices.push(k % x.n_cols);
+                kept.push(x_k);
+            }
+        }
+        validate_values(kept.iter().copied())?;
+        let max_size = self.max_size;
+        let batch = py.detach(|| {
+            let mut batch = DigestArray::new(x.n_cols, max_size)?;
+            batch.add_grouped(&indices, &kept, None, 1)?;
+            batch.flush()?;
+            Ok(batch)
+        });
+        let batch = batch.map_err(malloc_error)?;
+        match &mut self.array {
+            Some(array) if !reset => {
+                array.merge(&batch).map_err(malloc_error)?;
+                array.flush().map_err(malloc_error)
+            }
+            _ => {
+                self.array = Some(batch);
+                Ok(())
+            }
+        }
+    }
+
+    fn fitted(&self) -> PyResult<&DigestArray> {
+        self.array.as_ref().ok_or_else(|| {
+            PyValueError::new_err(
+                "This estimator is not fitted yet. Call 'fit' first.",
+            )
+        })
+    }
+
+    /// The fitted digests, checking that `x` has as many features.
+    fn check(&self, x: &Matrix) -> PyResult<Vec<&TDigest>> {
+        let Some(array) = &self.array else {
+            return Ok(Vec::new());
+        };
+        if array.len() != x.n_cols {
+            return Err(PyValueError::new_err(format!(
+                "X has {} features, but the estimator is expecting {} \
+                features as input.",
+                x.n_cols,
+                array.len()
+            )));
+        }
+        Ok(array.digests().collect())
+    }
+
+    fn n_features(&self) -> PyResult<usize> {
+        self.array
+            .as_ref()
+            .map(DigestArray::len)
+            .ok_or_else(|| PyAttributeError::new_err("n_features_in_"))
+    }
+
+    fn digests(&self) -> PyResult<Vec<PyTDigest>> {
+        let array = self
+            .array
+            .as_ref()
+            .ok_or_else(|| PyAttributeError::new_err("digests_"))?;
+        Ok(array.digests().cloned().map(wrap_digest).collect())
+    }
+
+    fn to_state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        match &self.array {
+            Some(array) => {
+                let bytes = array.to_bytes().map_err(malloc_error)?;
+                Ok(PyBytes::new(py, &bytes).into_any())
+            }
+            None => Ok(py.None().into_bound(py)),
+        }
+    }
+
+    fn set_state(&mut self, state: Option<&[u8]>) -> PyResult<()> {
+        self.array = state
+            .map(DigestArray::from_bytes)
+            .transpose()
+            .map_err(bytes_error)?;
+        Ok(())
+    }
+}
+
+/// Maps every value of `x` with `f(column, digest, x)`, validating `x`
+/// first. Missing values and values of features without training data are
+/// NaN.
+fn transform_with<'py>(
+    x: &Bound<'py, PyAny>,
+    features: &Features,
+    f: impl Fn(usize, &TDigest, f64) -> f64,
+) -> PyResult<Bound<'py, PyAny>> {
+    features.fitted()?;
+    let py = x.py();
+    let x = Matrix::read(x)?;
+    let digests = features.check(&x)?;
+    map_matrix(py, &x, |j, x_k| {
+        let digest = digests[j];
+        if digest.is_empty() {
+            f64::NAN
+        } else {
+            f(j, digest, x_k)
+        }
+    })
+}
+
+/// Constructor arguments and fitted digests of an estimator.
+trait Estimator {
+    const NAME: &'static str;
+    const PARAMS: &'static [&'static str];
+
+    fn param(&self, key: &str) -> &Py<PyAny>;
+
+    /// Validates and sets the parameter `key`, keeping `value` as given.
+    fn set_param(
+        &mut self,
+        key: &str,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()>;
+
+    fn features(&self) -> &Features;
+
+    fn features_mut(&mut self) -> &mut Features;
+
+    /// Sets the parameters passed to the constructor.
+    fn init(
+        &mut self,
+        params: &[(&str, Option<&Bound<'_, PyAny>>)],
+    ) -> PyResult<()> {
+        for (key, value) in params {
+            if let Some(value) = value {
+                self.set_param(key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid_param<E: Estimator>(key: &str) -> PyErr {
+    PyValueError::new_err(format!(
+        "Invalid parameter '{key}' for estimator {}.",
+        E::NAME
+    ))
+}
+
+fn get_params<'py, E: Estimator>(
+    py: Python<'py>,
+    state: &E,
+) -> PyResult<Bound<'py, PyDict>> {
+    let params = PyDict::new(py);
+    for key in E::PARAMS {
+        params.set_item(key, state.param(key))?;
+    }
+    Ok(params)
+}
+
+fn set_params<E: Estimator>(
+    state: &mut E,
+    params: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    if let Some(params) = params {
+        for (key, value) in params {
+            state.set_param(&key.extract::<String>()?, &value)?;
+        }
+    }
+    Ok(())
+}
+
+fn fit<E: Estimator>(
+    state: &Mutex<E>,
+    x: &Bound<'_, PyAny>,
+    reset: bool,
+) -> PyResult<()> {
+    let py = x.py();
+    let x = Matrix::read(x)?;
+    state.lock().features_mut().fit(py, &x, reset)
+}
+
+fn repr<E: Estimator>(py: Python<'_>, state: &E) -> PyResult<String> {
+    let params = E::PARAMS
+        .iter()
+        .map(|key| Ok(format!("{key}={}", state.param(key).bind(py).repr()?)))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(format!("{}({})", E::NAME, params.join(", ")))
+}
+
+/// Pickles as the constructor arguments and the serialized digests.
+fn reduce<'py, E: Estimator>(
+    cls: Bound<'py, PyAny>,
+    state: &E,
+) -> PyResult<Bound<'py, PyTuple>> {
+    let py = cls.py();
+    let args = PyTuple::new(py, E::PARAMS.iter().map(|key| state.param(key)))?;
+    PyTuple::new(py, [cls, args.into_any(), state.features().to_state(py)?])
+}
+
+/// Estimator tags of scikit-learn 1.6 or later: a transformer that
+/// accepts missing values. Older versions do not look for them, so they
+/// are missing (an AttributeError) there.
+fn sklearn_tags(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    let utils = py
+        .import("sklearn.utils")
+        .map_err(|_| PyAttributeError::new_err("__sklearn_tags__"))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("allow_nan", true)?;
+    let input_tags = utils.getattr("InputTags")?.call((), Some(&kwargs))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("required", false)?;
+    let target_tags = utils.getattr("TargetTags")?.call((), Some(&kwargs))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("estimator_type", py.None())?;
+    kwargs.set_item("target_tags", target_tags)?;
+    kwargs.set_item(
+        "transformer_tags",
+        utils.getattr("TransformerTags")?.call0()?,
+    )?;
+    kwargs.set_item("input_tags", input_tags)?;
+    utils.getattr("Tags")?.call((), Some(&kwargs))
+}
+
+struct TransformerState {
+    output_distribution: Py<PyAny>,
+    max_centroids: Py<PyAny>,
+    normal: bool,
+    features: Features,
+}
+
+impl Estimator for TransformerState {
+    const NAME: &'static str = "QuantileTransformer";
+    const PARAMS: &'static [&'static str] =
+        &["output_distribution", "max_centroids"];
+
+    fn param(&self, key: &str) -> &Py<PyAny> {
+        match key {
+            "output_distribution" => &self.output_distribution,
+            _ => &self.max_centroids,
+        }
+    }
+
+    fn set_param(
+        &mut self,
+        key: &str,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        match key {
+            "output_distribution" => {
+                self.normal = match value.extract::<String>()?.as_str() {
+                    "uniform" => false,
+                    "normal" => true,
+                    _ => {
+                        return Err(PyValueError::new_err(
+                            "output_distribution must be 'uniform' or \
+                            'normal'.",
+                        ))
+                    }
+                };
+                self.output_distribution = value.clone().unbind();
+            }
+            "max_centroids" => {
+                self.features.max_size =
+                    validate_max_centroids(value.extract()?)?;
+                self.max_centroids = value.clone().unbind();
+            }
+            _ => return Err(invalid_param::<Self>(key)),
+        }
+        Ok(())
+    }
+
+    fn features(&self) -> &Features {
+        &self.features
+    }
+
+    fn features_mut(&mut self) -> &mut Features {
+        &mut self.features
+    }
+}
+
+/// Maps each feature to its estimated CDF (uniform output) or further to
+/// a standard normal distribution.
+#[pyclass(name = "QuantileTransformer", module = "fastdigest.sklearn", frozen)]
+pub struct PyQuantileTransformer {
+    state: Mutex<TransformerState>,
+}
+
+#[pymethods]
+impl PyQuantileTransformer {
+    /// Constructs a new QuantileTransformer with the output distribution
+    /// "uniform" (default) or "normal".
+    #[new]
+    #[pyo3(signature = (output_distribution=None, max_centroids=None))]
+    pub fn new(
+        py: Python<'_>,
+        output_distribution: Option<&Bound<'_, PyAny>>,
+        max_centroids: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let mut state = TransformerState {
+            output_distribution: "uniform"
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+            max_centroids: TD_SIZE_DEFAULT
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+            normal: false,
+            features: Features::new(),
+        };
+        state.init(&[
+            ("output_distribution", output_distribution),
+            ("max_centroids", max_centroids),
+        ])?;
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Returns the constructor arguments as a dict.
+    #[pyo3(signature = (deep=true))]
+    pub fn get_params<'py>(
+        &self,
+        py: Python<'py>,
+        deep: bool,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let _ = deep;
+        get_params(py, &*self.state.lock())
+    }
+
+    /// Sets constructor arguments and returns the estimator.
+    #[pyo3(signature = (**params))]
+    pub fn set_params<'py>(
+        slf: &Bound<'py, Self>,
+        params: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        set_params(&mut *slf.get().state.lock(), params)?;
+        Ok(slf.clone())
+    }
+
+    /// Fits one digest per feature of `X` and returns the estimator.
+    #[pyo3(signature = (x, y=None))]
+    pub fn fit<'py>(
+        slf: &Bound<'py, Self>,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        let _ = y;
+        fit(&slf.get().state, x, true)?;
+        Ok(slf.clone())
+    }
+
+    /// Extends the fitted digests with `X` and returns the estimator.
+    #[pyo3(signature = (x, y=None))]
+    pub fn partial_fit<'py>(
+        slf: &Bound<'py, Self>,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        let _ = y;
+        fit(&slf.get().state, x, false)?;
+        Ok(slf.clone())
+    }
+
+    /// Maps the values of `X` to the output distribution.
+    pub fn transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let state = self.state.lock();
+        let normal = state.normal;
+        transform_with(x, &state.features, |_, digest, x| {
+            let p = digest.estimate_rank(x);
+            if normal {
+                normal_ppf(p.clamp(BOUNDS_THRESHOLD, 1.0 - BOUNDS_THRESHOLD))
+            } else {
+                p
+            }
+        })
+    }
+
+    /// Fits the digests to `X` and transforms it.
+    #[pyo3(signature = (x, y=None))]
+    pub fn fit_transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let _ = y;
+        fit(&self.state, x, true)?;
+        self.transform(x)
+    }
+
+    /// Maps values of the output distribution back to the feature values.
+    pub fn inverse_transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let state = self.state.lock();
+        let normal = state.normal;
+        transform_with(x, &state.features, |_, digest, y| {
+            let p = if normal {
+                normal_cdf(y)
+            } else {
+                y.clamp(0.0, 1.0)
+            };
+            digest.estimate_quantile(p)
+        })
+    }
+
+    /// Getter property: returns the number of features seen in `fit`.
+    #[getter(n_features_in_)]
+    pub fn get_n_features_in(&self) -> PyResult<usize> {
+        self.state.lock().features.n_features()
+    }
+
+    /// Getter property: returns a copy of the fitted digest per feature.
+    #[getter(digests_)]
+    pub fn get_digests(&self) -> PyResult<Vec<PyTDigest>> {
+        self.state.lock().features.digests()
+    }
+
+    pub fn __sklearn_tags__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        sklearn_tags(py)
+    }
+
+    pub fn __sklearn_is_fitted__(&self) -> bool {
+        self.state.lock().features.array.is_some()
+    }
+
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        repr(py, &*self.state.lock())
+    }
+
+    pub fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        reduce(slf.get_type().into_any(), &*slf.get().state.lock())
+    }
+
+    pub fn __setstate__(&self, state: Option<&[u8]>) -> PyResult<()> {
+        self.state.lock().features.set_state(state)
+    }
+}
+
+struct BinnerState {
+    n_bins: Py<PyAny>,
+    max_centroids: Py<PyAny>,
+    bins: usize,
+    features: Features,
+}
+
+impl BinnerState {
+    /// The estimated bin edges of every feature, from the minimum to the
+    /// maximum (NaN for features without training data).
+    fn edges(&self) -> PyResult<Vec<Vec<f64>>> {
+        let bins = self.bins;
+        Ok(self
+            .features
+            .fitted()?
+            .digests()
+            .map(|digest| {
+                (0..=bins)
+                    .map(|i| {
+                        if digest.is_empty() {
+                            f64::NAN
+                        } else {
+                            digest.estimate_quantile(i as f64 / bins as f64)
+                        }
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl Estimator for BinnerState {
+    const NAME: &'static str = "QuantileBinner";
+    const PARAMS: &'static [&'static str] = &["n_bins", "max_centroids"];
+
+    fn param(&self, key: &str) -> &Py<PyAny> {
+        match key {
+            "n_bins" => &self.n_bins,
+            _ => &self.max_centroids,
+        }
+    }
+
+    fn set_param(
+        &mut self,
+        key: &str,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        match key {
+            "n_bins" => {
+                self.bins = value
+                    .extract::<usize>()
+                    .ok()
+                    .filter(|&bins| bins >= 2)
+                    .ok_or_else(|| {
+                        PyValueError::new_err(
+                            "n_bins must be an integer of at least 2.",
+                        )
+                    })?;
+                self.n_bins = value.clone().unbind();
+            }
+            "max_centroids" => {
+                self.features.max_size =
+                    validate_max_centroids(value.extract()?)?;
+                self.max_centroids = value.clone().unbind();
+            }
+            _ => return Err(invalid_param::<Self>(key)),
+        }
+        Ok(())
+    }
+
+    fn features(&self) -> &Features {
+        &self.features
+    }
+
+    fn features_mut(&mut self) -> &mut Features {
+        &mut self.features
+    }
+}
+
+/// Discretizes each feature into `n_bins` bins of (approximately) equal
+/// frequency, with bin edges estimated from the fitted digests.
+#[pyclass(name = "QuantileBinner", module = "fastdigest.sklearn", frozen)]
+pub struct PyQuantileBinner {
+    state: Mutex<BinnerState>,
+}
+
+#[pymethods]
+impl PyQuantileBinner {
+    /// Constructs a new QuantileBinner with `n_bins` bins per feature
+    /// (default: 5).
+    #[new]
+    #[pyo3(signature = (n_bins=None, max_centroids=None))]
+    pub fn new(
+        py: Python<'_>,
+        n_bins: Option<&Bound<'_, PyAny>>,
+        max_centroids: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let mut state = BinnerState {
+            n_bins: 5usize.into_pyobject(py)?.into_any().unbind(),
+            max_centroids: TD_SIZE_DEFAULT
+                .into_pyobject(py)?
+                .into_any()
+                .unbind(),
+            bins: 5,
+            features: Features::new(),
+        };
+        state.init(&[("n_bins", n_bins), ("max_centroids", max_centroids)])?;
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Returns the constructor arguments as a dict.
+    #[pyo3(signature = (deep=true))]
+    pub fn get_params<'py>(
+        &self,
+        py: Python<'py>,
+        deep: bool,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let _ = deep;
+        get_params(py, &*self.state.lock())
+    }
+
+    /// Sets constructor arguments and returns the estimator.
+    #[pyo3(signature = (**params))]
+    pub fn set_params<'py>(
+        slf: &Bound<'py, Self>,
+        params: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        set_params(&mut *slf.get().state.lock(), params)?;
+        Ok(slf.clone())
+    }
+
+    /// Fits one digest per feature of `X` and returns the estimator.
+    #[pyo3(signature = (x, y=None))]
+    pub fn fit<'py>(
+        slf: &Bound<'py, Self>,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        let _ = y;
+        fit(&slf.get().state, x, true)?;
+        Ok(slf.clone())
+    }
+
+    /// Extends the fitted digests with `X` and returns the estimator.
+    #[pyo3(signature = (x, y=None))]
+    pub fn partial_fit<'py>(
+        slf: &Bound<'py, Self>,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, Self>> {
+        let _ = y;
+        fit(&slf.get().state, x, false)?;
+        Ok(slf.clone())
+    }
+
+    /// Replaces the values of `X` by their bin numbers (0 to n_bins - 1).
+    pub fn transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let state = self.state.lock();
+        let edges = state.edges()?;
+        let bins = state.bins;
+        transform_with(x, &state.features, |j, _, x| {
+            let inner = &edges[j][1..bins];
+            inner.partition_point(|&edge| edge <= x) as f64
+        })
+    }
+
+    /// Fits the digests to `X` and transforms it.
+    #[pyo3(signature = (x, y=None))]
+    pub fn fit_transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+        y: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let _ = y;
+        fit(&self.state, x, true)?;
+        self.transform(x)
+    }
+
+    /// Replaces bin numbers by the centers of the bins.
+    pub fn inverse_transform<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let state = self.state.lock();
+        let edges = state.edges()?;
+        let bins = state.bins;
+        transform_with(x, &state.features, |j, _, y| {
+            let i = y.clamp(0.0, (bins - 1) as f64) as usize;
+            0.5 * (edges[j][i] + edges[j][i + 1])
+        })
+    }
+
+    /// Getter property: returns the number of features seen in `fit`.
+    #[getter(n_features_in_)]
+    pub fn get_n_features_in(&self) -> PyResult<usize> {
+        self.state.lock().features.n_features()
+    }
+
+    /// Getter property: returns a copy of the fitted digest per feature.
+    #[getter(digests_)]
+    pub fn get_digests(&self) -> PyResult<Vec<PyTDigest>> {
+        self.state.lock().features.digests()
+    }
+
+    /// Getter property: returns the n_bins + 1 bin edges of each feature
+    /// as NumPy arrays.
+    #[getter(bin_edges_)]
+    pub fn get_bin_edges<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let state = self.state.lock();
+        if state.features.array.is_none() {
+            return Err(PyAttributeError::new_err("bin_edges_"));
+        }
+        state
+            .edges()?
+            .iter()
+            .map(|edges| f64_array(py, edges))
+            .collect()
+    }
+
+    pub fn __sklearn_tags__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        sklearn_tags(py)
+    }
+
+    pub fn __sklearn_is_fitted__(&self) -> bool {
+        self.state.lock().features.array.is_some()
+    }
+
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        repr(py, &*self.state.lock())
+    }
+
+    pub fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        reduce(slf.get_type().into_any(), &*slf.get().state.lock())
+    }
+
+    pub fn __setstate__(&self, state: Option<&[u8]>) -> PyResult<()> {
+        self.state.lock().features.set_state(state)
+    }
+}
+
+/// Complementary error function (Numerical Recipes `erfcc`, with a
+/// fractional error below 1.2e-7).
+fn erfc(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 10] = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ];
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = COEFFICIENTS.iter().rev().fold(0.0, |acc, &c| acc * t + c);
+    let r = t * (-z * z + poly).exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation,
+/// with a relative error below 1.2e-9).
+fn normal_ppf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |p: f64| {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < P_LOW {
+        tail(p)
+    } else if p > 1.0 - P_LOW {
+        -tail(1.0 - p)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5])
+            * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r
+                + 1.0)
+    }
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyQuantileBinner>()?;
+    m.add_class::<PyQuantileTransformer>()?;
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! PySpark aggregations backed by TDigest, exchanged as binary columns
+//!
+//! The UDF bodies are module-level functions so that Spark can pickle them
+//! by reference and ship them to the executors.
+
+use super::update_from_array;
+use crate::errors::InvalidQuantileError;
+use crate::{merge_all, validate_max_centroids, PyTDigest};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString};
+use pyo3::wrap_pyfunction;
+
+/// Reads the (constant) first element of a literal column.
+fn first_element<'py>(
+    series: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    series.getattr("iloc")?.get_item(0)
+}
+
+fn to_column<'py>(
+    functions: &Bound<'py, PyModule>,
+    col: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    if col.is_instance_of::<PyString>() {
+        functions.call_method1("col", (col,))
+    } else {
+        Ok(col.clone())
+    }
+}
+
+fn pandas_udf<'py>(
+    py: Python<'py>,
+    name: &str,
+    return_type: &str,
+    function_type: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let m = py.import("fastdigest.spark")?;
+    let udf_type = py
+        .import("pyspark.sql.functions")?
+        .getattr("PandasUDFType")?
+        .getattr(function_type)?;
+    py.import("pyspark.sql.functions")?
+        .call_method1("pandas_udf", (m.getattr(name)?, return_type, udf_type))
+}
+
+/// UDF body: builds a serialized digest from a Series of values.
+#[pyfunction]
+pub fn build_series<'py>(
+    values: &Bound<'py, PyAny>,
+    max_centroids: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let max_centroids: i64 = first_element(max_centroids)?.extract()?;
+    let digest = PyTDigest::new(max_centroids)?;
+    update_from_array(&digest, values)?;
+    digest.to_bytes(values.py(), "exact")
+}
+
+/// UDF body: merges a Series of serialized digests.
+#[pyfunction]
+pub fn merge_series<'py>(
+    blobs: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let py = blobs.py();
+    let digests = blobs
+        .call_method0("dropna")?
+        .try_iter()?
+        .map(|blob| PyTDigest::from_bytes(&blob?.extract::<Vec<u8>>()?, false))
+        .collect::<PyResult<Vec<_>>>()?;
+    let digests = PyList::new(py, digests)?;
+    merge_all(digests.as_any(), None, Some(1), false, false)?
+        .to_bytes(py, "exact")
+}
+
+/// UDF body: estimates the quantile `q` of each serialized digest.
+#[pyfunction]
+pub fn quantile_series<'py>(
+    blobs: &Bound<'py, PyAny>,
+    q: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = blobs.py();
+    let q: f64 = first_element(q)?.extract()?;
+    let results = blobs
+        .try_iter()?
+        .map(|blob| {
+            let blob = blob?;
+            if blob.is_none() {
+                return Ok(None);
+            }
+            super::tdigest_quantile(&blob.extract::<Vec<u8>>()?, q)
+        })
+        .collect::<PyResult<Vec<Option<f64>>>>()?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", "float64")?;
+    py.import("pandas")?
+        .getattr("Series")?
+        .call((results,), Some(&kwargs))
+}
+
+/// mapPartitions body: builds one serialized digest per partition.
+#[pyfunction]
+#[pyo3(signature = (rows, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn build_partition<'py>(
+    rows: &Bound<'py, PyAny>,
+    max_centroids: i64,
+) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+    let digest = PyTDigest::new(max_centroids)?;
+    for row in rows.try_iter()? {
+        let value: Option<f64> = row?.get_item(0)?.extract()?;
+        if let Some(x) = value {
+            digest.update_value(x, None)?;
+        }
+    }
+    Ok(vec![digest.to_bytes(rows.py(), "exact")?])
+}
+
+/// treeReduce body: merges two serialized digests.
+#[pyfunction]
+pub fn merge_pair<'py>(
+    py: Python<'py>,
+    blob1: &[u8],
+    blob2: &[u8],
+) -> PyResult<Bound<'py, PyBytes>> {
+    PyTDigest::from_bytes(blob1, false)?
+        .merge(py, &PyTDigest::from_bytes(blob2, false)?, None)?
+        .to_bytes(py, "exact")
+}
+
+/// Aggregate column: builds a serialized digest per group.
+#[pyfunction]
+#[pyo3(signature = (col, max_centroids=crate::TD_SIZE_DEFAULT as i64))]
+pub fn tdigest_agg<'py>(
+    col: &Bound<'py, PyAny>,
+    max_centroids: i64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = col.py();
+    validate_max_centroids(max_centroids)?;
+    let functions = py.import("pyspark.sql.functions")?;
+    let col = to_column(&functions, col)?.call_method1("cast", ("double",))?;
+    let max_centroids = functions.call_method1("lit", (max_centroids,))?;
+    pandas_udf(py, "build_series", "binary", "GROUPED_AGG")?
+        .call1((col, max_centroids))
+}
+
+/// Aggregate column: merges serialized digests per group.
+#[pyfunction]
+pub fn tdigest_merge<'py>(
+    col: &Bound<'py, PyAny>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = col.py();
+    let functions = py.import("pyspark.sql.functions")?;
+    let col = to_column(&functions, col)?;
+    pandas_udf(py, "merge_series", "binary", "GROUPED_AGG")?.call1((col,))
+}
+
+/// Column of estimated quantiles of serialized digests.
+#[pyfunction]
+pub fn tdigest_quantile<'py>(
+    col: &Bound<'py, PyAny>,
+    q: f64,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = col.py();
+    if !(0.0..=1.0).contains(&q) {
+        return Err(InvalidQuantileError::new_err(
+            "q must be between 0 and 1.",
+        ));
+    }
+    let functions = py.import("pyspark.sql.functions")?;
+    let col = to_column(&functions, col)?;
+    let q = functions.call_method1("lit", (q,))?;
+    pandas_udf(py, "quantile_series", "double", "SCALAR")?.call1((col, q))
+}
+
+/// Builds a single TDigest of a DataFrame column: one digest per partition,
+/// merged on the executors with `treeReduce`.
+#[pyfunction]
+#[pyo3(signature = (df, col, max_centroids=crate::TD_SIZE_DEFAULT as i64, depth=2))]
+pub fn tdigest(
+    df: &Bound<'_, PyAny>,
+    col: &Bound<'_, PyAny>,
+    max_centroids: i64,
+    depth: usize,
+) -> PyResult<PyTDigest> {
+    let py = df.py();
+    validate_max_centroids(max_centroids)?;
+    let m = py.import("fastdigest.spark")?;
+    let functions = py.import("pyspark.sql.functions")?;
+    let col = to_column(&functions, col)?.call_method1("cast", ("double",))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("max_centroids", max_centroids)?;
+    let build = py
+        .import("functools")?
+        .getattr("partial")?
+        .call((m.getattr("build_partition")?,), Some(&kwargs))?;
+    let blob: Vec<u8> = df
+        .call_method1("select", (col,))?
+        .getattr("rdd")?
+        .call_method1("mapPartitions", (build,))?
+        .call_method1("treeReduce", (m.getattr("merge_pair")?, depth))?
+        .extract()?;
+    PyTDigest::from_bytes(&blob, false)
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(tdigest_agg, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_merge, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_quantile, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest, m)?)?;
+    m.add_function(wrap_pyfunction!(build_series, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_series, m)?)?;
+    m.add_function(wrap_pyfunction!(quantile_series, m)?)?;
+    m.add_function(wrap_pyfunction!(build_partition, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_pair, m)?)?;
+    Ok(())
+}
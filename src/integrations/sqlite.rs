@@ -0,0 +1,103 @@
+//! SQLite aggregates backed by TDigest, exchanged as binary BLOBs
+
+use super::{tdigest_cdf, tdigest_quantile};
+use crate::{merge_all, PyTDigest};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+use pyo3::wrap_pyfunction;
+
+/// Aggregate class building a TDigest from (optionally weighted) values.
+#[pyclass(module = "fastdigest.sqlite")]
+pub struct TDigestAggregate {
+    digest: PyTDigest,
+}
+
+#[pymethods]
+impl TDigestAggregate {
+    #[new]
+    pub fn new() -> PyResult<Self> {
+        Ok(Self {
+            digest: PyTDigest::new(crate::TD_SIZE_DEFAULT as i64)?,
+        })
+    }
+
+    /// Adds a value, ignoring NULLs.
+    #[pyo3(signature = (x, w=None))]
+    pub fn step(&self, x: Option<f64>, w: Option<f64>) -> PyResult<()> {
+        match x {
+            Some(x) => self.digest.update_value(x, w),
+            None => Ok(()),
+        }
+    }
+
+    pub fn finalize<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        self.digest.to_bytes(py, "exact")
+    }
+}
+
+/// Aggregate class merging serialized TDigests.
+#[pyclass(module = "fastdigest.sqlite")]
+#[derive(Default)]
+pub struct TDigestMergeAggregate {
+    digests: Vec<PyTDigest>,
+}
+
+#[pymethods]
+impl TDigestMergeAggregate {
+    #[new]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a serialized digest, ignoring NULLs.
+    pub fn step(&mut self, blob: Option<&[u8]>) -> PyResult<()> {
+        if let Some(blob) = blob {
+            self.digests.push(PyTDigest::from_bytes(blob, false)?);
+        }
+        Ok(())
+    }
+
+    pub fn finalize<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let digests = PyList::new(py, std::mem::take(&mut self.digests))?;
+        merge_all(digests.as_any(), None, Some(1), false, false)?
+            .to_bytes(py, "exact")
+    }
+}
+
+/// Registers the TDigest functions on a `sqlite3.Connection`:
+///
+/// - `tdigest(x)`, `tdigest(x, w)`: aggregate, builds a digest BLOB
+/// - `tdigest_merge(d)`: aggregate, merges digest BLOBs
+/// - `tdigest_quantile(d, q)`: estimated quantile of a digest BLOB
+/// - `tdigest_cdf(d, x)`: estimated CDF of a digest BLOB
+#[pyfunction]
+pub fn register(conn: &Bound<'_, PyAny>) -> PyResult<()> {
+    let py = conn.py();
+    let m = py.import("fastdigest.sqlite")?;
+    let aggregate = m.getattr("TDigestAggregate")?;
+    conn.call_method1("create_aggregate", ("tdigest", 1, &aggregate))?;
+    conn.call_method1("create_aggregate", ("tdigest", 2, &aggregate))?;
+    conn.call_method1(
+        "create_aggregate",
+        ("tdigest_merge", 1, m.getattr("TDigestMergeAggregate")?),
+    )?;
+    for name in ["tdigest_quantile", "tdigest_cdf"] {
+        conn.call_method1("create_function", (name, 2, m.getattr(name)?))?;
+    }
+    Ok(())
+}
+
+pub fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<TDigestAggregate>()?;
+    m.add_class::<TDigestMergeAggregate>()?;
+    m.add_function(wrap_pyfunction!(register, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_quantile, m)?)?;
+    m.add_function(wrap_pyfunction!(tdigest_cdf, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,552 @@
+//! KLL sketch: quantiles with a worst-case rank-error guarantee
+//!
+//! The sketch keeps a hierarchy of compactors (Karnin, Lang & Liberty,
+//! 2016). Items at level `h` stand for `2^h` input values. When the sketch
+//! is full, the lowest level at capacity is sorted and every other item
+//! (starting at a random offset) is promoted to the next level, the rest is
+//! discarded. Capacities shrink geometrically by a factor of 2/3 towards the
+//! lower levels, as in the Apache DataSketches implementation, so that the
+//! normalized rank error is about `2.296 / k^0.9723` with 99% confidence,
+//! independently of the data distribution.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::rng::SplitMix64;
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, malloc_error, validate_values};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTKLL~";
+const VERSION: u32 = 1;
+const DEFAULT_K: usize = 200;
+const MIN_K: usize = 8;
+const MAX_K: usize = 65535;
+/// Capacity of the lowest levels, however small `2/3^depth * k` is.
+const MIN_LEVEL_CAPACITY: usize = 8;
+const MAX_LEVELS: usize = 61;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KllSketch {
+    k: usize,
+    levels: Vec<Vec<f64>>,
+    n: u64,
+    min: f64,
+    max: f64,
+    rng: SplitMix64,
+}
+
+impl KllSketch {
+    pub fn new(k: usize, rng: SplitMix64) -> Self {
+        Self {
+            k,
+            levels: vec![Vec::new()],
+            n: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            rng,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    fn capacity(&self, level: usize) -> usize {
+        let depth = (self.levels.len() - level - 1) as i32;
+        let capacity = (self.k as f64 * (2.0f64 / 3.0).powi(depth)).ceil();
+        (capacity as usize).max(MIN_LEVEL_CAPACITY)
+    }
+
+    fn total_capacity(&self) -> usize {
+        (0..self.levels.len()).map(|h| self.capacity(h)).sum()
+    }
+
+    pub fn n_retained(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    /// Approximate normalized rank error (99% confidence), as estimated for
+    /// the DataSketches implementation.
+    pub fn rank_error(&self) -> f64 {
+        2.296 / (self.k as f64).powf(0.9723)
+    }
+
+    pub fn add(&mut self, x: f64) -> Result<(), TryReserveError> {
+        self.levels[0].try_reserve(1)?;
+        self.levels[0].push(x);
+        self.n += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self.compress()
+    }
+
+    /// Compacts levels until the sketch fits its capacity again.
+    fn compress(&mut self) -> Result<(), TryReserveError> {
+        while self.n_retained() >= self.total_capacity() {
+            let Some(level) = (0..self.levels.len())
+                .find(|&h| self.levels[h].len() >= self.capacity(h))
+            else {
+                break;
+            };
+            if level + 1 == self.levels.len() {
+                self.levels.try_reserve(1)?;
+                self.levels.push(Vec::new());
+            }
+            self.compact(level)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&mut self, level: usize) -> Result<(), TryReserveError> {
+        let mut items = std::mem::take(&mut self.levels[level]);
+        items.sort_unstable_by(f64::total_cmp);
+        // with an odd number of items, the lowest one stays behind
+        let leftover = (items.len() % 2 == 1).then(|| items.remove(0));
+        let offset = self.rng.next_bool() as usize;
+        let next = &mut self.levels[level + 1];
+        next.try_reserve(items.len() / 2)?;
+        next.extend(items.iter().skip(offset).step_by(2));
+        items.clear();
+        items.extend(leftover);
+        self.levels[level] = items;
+        Ok(())
+    }
+
+    /// Merges `other` into this sketch. The result has the smaller `k` of
+    /// the two.
+    pub fn merge(&mut self, other: &KllSketch) -> Result<(), TryReserveError> {
+        if other.is_empty() {
+            return Ok(());
+        }
+        self.k = self.k.min(other.k);
+        while self.levels.len() < other.levels.len() {
+            self.levels.try_reserve(1)?;
+            self.levels.push(Vec::new());
+        }
+        for (level, items) in self.levels.iter_mut().zip(&other.levels) {
+            level.try_reserve(items.len())?;
+            level.extend_from_slice(items);
+        }
+        self.n += other.n;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.compress()
+    }
+
+    fn sorted_view(&self) -> SortedView {
+        let levels = self.levels.iter().map(Vec::as_slice);
+        SortedView::new(levels, self.n, self.min, self.max)
+    }
+
+    pub fn quantiles(&self, q: &[f64]) -> Vec<f64> {
+        let view = self.sorted_view();
+        q.iter().map(|&q_i| view.quantile(q_i)).collect()
+    }
+
+    pub fn ranks(&self, x: &[f64]) -> Vec<f64> {
+        let view = self.sorted_view();
+        x.iter().map(|&x_i| view.rank(x_i)).collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.k as u64);
+        writer.u64(self.n);
+        writer.f64(self.min);
+        writer.f64(self.max);
+        writer.u64(self.rng.state());
+        writer.u64(self.levels.len() as u64);
+        for items in &self.levels {
+            writer.f64s(items);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let k = usize::try_from(reader.u64()?)
+            .map_err(|_| BytesError::CorruptData)?;
+        let n = reader.u64()?;
+        let min = reader.f64()?;
+        let max = reader.f64()?;
+        let rng = SplitMix64::new(reader.u64()?);
+        let n_levels = reader.u64()?;
+        if n_levels > MAX_LEVELS as u64 {
+            return Err(BytesError::CorruptData);
+        }
+        let levels = (0..n_levels)
+            .map(|_| reader.f64s())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Self::from_parts(k, levels, n, min, max, rng)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent.
+    fn from_parts(
+        k: usize,
+        levels: Vec<Vec<f64>>,
+        n: u64,
+        min: f64,
+        max: f64,
+        rng: SplitMix64,
+    ) -> Option<Self> {
+        if !(MIN_K..=MAX_K).contains(&k)
+            || levels.is_empty()
+            || levels.len() > MAX_LEVELS
+        {
+            return None;
+        }
+        let mut weight: u64 = 0;
+        for (h, items) in levels.iter().enumerate() {
+            if items
+                .iter()
+                .any(|x| !(x.is_finite() && min <= *x && *x <= max))
+            {
+                return None;
+            }
+            weight = (items.len() as u64)
+                .checked_mul(1 << h)
+                .and_then(|w| weight.checked_add(w))?;
+        }
+        let valid_bounds = if n == 0 {
+            min == f64::INFINITY && max == f64::NEG_INFINITY
+        } else {
+            min.is_finite() && max.is_finite()
+        };
+        (weight == n && valid_bounds).then_some(Self {
+            k,
+            levels,
+            n,
+            min,
+            max,
+            rng,
+        })
+    }
+}
+
+/// Retained items of a hierarchy of compactors in ascending order, with
+/// their cumulative weights. Items at level `h` weigh `2^h`.
+pub struct SortedView {
+    items: Vec<(f64, u64)>,
+    n: u64,
+    min: f64,
+    max: f64,
+}
+
+impl SortedView {
+    pub fn new<'a>(
+        levels: impl IntoIterator<Item = &'a [f64]>,
+        n: u64,
+        min: f64,
+        max: f64,
+    ) -> Self {
+        let mut items: Vec<(f64, u64)> = levels
+            .into_iter()
+            .enumerate()
+            .flat_map(|(h, items)| items.iter().map(move |&x| (x, 1 << h)))
+            .collect();
+        items.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let mut cumulative = 0;
+        for item in &mut items {
+            cumulative += item.1;
+            item.1 = cumulative;
+        }
+        Self { items, n, min, max }
+    }
+
+    /// Lowest item whose (inclusive) rank is at least `q`.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if q <= 0.0 {
+            return self.min;
+        }
+        if q >= 1.0 {
+            return self.max;
+        }
+        let target = q * self.n as f64;
+        let i = self.items.partition_point(|&(_, w)| (w as f64) < target);
+        self.items.get(i).map_or(self.max, |&(x, _)| x)
+    }
+
+    /// Fraction of the weight at or below `x`.
+    pub fn rank(&self, x: f64) -> f64 {
+        if x < self.min {
+            return 0.0;
+        }
+        if x >= self.max {
+            return 1.0;
+        }
+        let i = self.items.partition_point(|&(y, _)| y <= x);
+        let weight = i.checked_sub(1).map_or(0, |i| self.items[i].1);
+        weight as f64 / self.n as f64
+    }
+}
+
+fn validate_k(k: i64) -> PyResult<usize> {
+    usize::try_from(k)
+        .ok()
+        .filter(|k| (MIN_K..=MAX_K).contains(k))
+        .ok_or_else(|| {
+            PyValueError::new_err("k must be an integer between 8 and 65535.")
+        })
+}
+
+#[pyclass(name = "KLLSketch", module = "fastdigest", frozen)]
+pub struct PyKllSketch {
+    state: Mutex<KllSketch>,
+}
+
+impl PyKllSketch {
+    fn from_sketch(sketch: KllSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Locks the sketch, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, KllSketch>> {
+        let sketch = self.state.lock();
+        if sketch.is_empty() {
+            return Err(EmptyDigestError::new_err("KLLSketch is empty."));
+        }
+        Ok(sketch)
+    }
+}
+
+#[pymethods]
+impl PyKllSketch {
+    /// Constructs a new empty KLLSketch instance.
+    #[new]
+    #[pyo3(signature = (k=DEFAULT_K as i64, seed=None))]
+    pub fn py_new(k: i64, seed: Option<u64>) -> PyResult<Self> {
+        let k = validate_k(k)?;
+        let rng = SplitMix64::from_seed(seed);
+        Ok(Self::from_sketch(KllSketch::new(k, rng)))
+    }
+
+    /// Getter property: returns the k parameter.
+    #[getter(k)]
+    pub fn get_k(&self) -> usize {
+        self.state.lock().k
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().n
+    }
+
+    /// Getter property: returns the number of items kept in the sketch.
+    #[getter(n_retained)]
+    pub fn get_n_retained(&self) -> usize {
+        self.state.lock().n_retained()
+    }
+
+    /// Getter property: returns the approximate normalized rank error.
+    #[getter(rank_error)]
+    pub fn get_rank_error(&self) -> f64 {
+        self.state.lock().rank_error()
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    pub fn update(&self, x: f64) -> PyResult<()> {
+        validate_values([x])?;
+        self.state.lock().add(x).map_err(malloc_error)
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let mut sketch = self.state.lock();
+        for x in values.iter(py).filter(keep) {
+            sketch.add(x).map_err(malloc_error)?;
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new KLLSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        self.state.lock().merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&[q])[0])
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&q))
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&[0.01 * p])[0])
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.quantiles(&[0.5])[0])
+    }
+
+    /// Estimates the rank (cumulative probability) of a given value `x`.
+    pub fn rank(&self, x: f64) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.ranks(&[x])[0])
+    }
+
+    /// Estimates the ranks (cumulative probabilities) of given values `x`.
+    pub fn rank_vec(&self, x: Vec<f64>) -> PyResult<Vec<f64>> {
+        Ok(self.lock_nonempty()?.ranks(&x))
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a KLLSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = KllSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("k", sketch.k)?;
+        dict.set_item("n_values", sketch.n)?;
+        dict.set_item("min", sketch.min)?;
+        dict.set_item("max", sketch.max)?;
+        dict.set_item("rng_state", sketch.rng.state())?;
+        dict.set_item("levels", &sketch.levels)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a KLLSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let k = validate_k(get("k")?.extract()?)?;
+        let sketch = KllSketch::from_parts(
+            k,
+            get("levels")?.extract()?,
+            get("n_values")?.extract()?,
+            get("min")?.extract()?,
+            get("max")?.extract()?,
+            SplitMix64::new(get("rng_state")?.extract()?),
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent KLLSketch levels.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// KLLSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via KLLSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(KLLSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("KLLSketch(k={})", self.state.lock().k)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,317 @@
+//! Command-line tool, compiled with the `cli` feature
+//!
+//! Builds a digest from the numbers of files or stdin and prints quantiles,
+//! or writes the serialized digest, e.g. to be loaded with
+//! `TDigest.from_bytes` in Python. Serialized digests can be read back to
+//! validate or merge them.
+
+use fastdigest::ingest::{self, CsvColumn, FileDtype, IngestError};
+use fastdigest::{TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::ExitCode;
+use std::{env, fmt};
+
+const USAGE: &str = "\
+Usage: fastdigest [OPTIONS] [FILE]...
+
+Builds a t-digest from the numbers in the FILEs (or stdin, also for '-') and
+prints the requested quantiles as 'q<TAB>value' lines.
+
+Input (numbers separated by whitespace, e.g. one per line, by default):
+      --csv COLUMN         CSV with a header row; COLUMN is a name or index
+      --delimiter CHAR     Delimiter of the CSV (default: ,)
+      --dtype DTYPE        Raw binary items of DTYPE, e.g. f8, <i4 or u1
+      --digest             Serialized digests (TDigest.to_bytes), validated
+                           and merged
+
+Output:
+  -q, --quantiles LIST     Comma-separated quantiles
+                           (default: 0,0.25,0.5,0.75,1)
+  -o, --output PATH        Write the serialized digest to PATH ('-': stdout)
+                           instead of printing quantiles (unless -q is given)
+  -m, --max-centroids N    Maximum number of centroids (default: 1000)
+  -h, --help               Print this help
+  -V, --version            Print the version
+";
+
+const CHUNK: usize = 1 << 16;
+
+/// Format of the input files.
+enum Input {
+    Text,
+    Csv(CsvColumn, u8),
+    Binary(FileDtype),
+    Digest,
+}
+
+struct Options {
+    input: Input,
+    files: Vec<String>,
+    quantiles: Option<Vec<f64>>,
+    output: Option<String>,
+    max_centroids: Option<usize>,
+}
+
+/// Error with a message for stderr; usage errors also print a hint.
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<IngestError> for CliError {
+    fn from(e: IngestError) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(msg) | Self::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(env::args().skip(1))
+        .and_then(|options| options.map_or(Ok(()), run));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(msg)) => {
+            eprintln!("fastdigest: {msg}\nTry 'fastdigest --help'.");
+            ExitCode::from(2)
+        }
+        Err(CliError::Failed(msg)) => {
+            eprintln!("fastdigest: {msg}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses the arguments; `None` if only the help or version was printed.
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<Options>, CliError> {
+    let mut format = None;
+    let mut csv_column = None;
+    let mut delimiter = b',';
+    let mut options = Options {
+        input: Input::Text,
+        files: Vec::new(),
+        quantiles: None,
+        output: None,
+        max_centroids: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next().ok_or_else(|| {
+                CliError::Usage(format!("option '{name}' requires a value"))
+            })
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(None);
+            }
+            "-V" | "--version" => {
+                println!("fastdigest {}", env!("CARGO_PKG_VERSION"));
+                return Ok(None);
+            }
+            "--csv" => {
+                let column = value(&arg)?;
+                csv_column = Some(match column.parse::<usize>() {
+                    Ok(index) => CsvColumn::Index(index),
+                    Err(_) => CsvColumn::Name(column),
+                });
+                format = set_format(format, "--csv")?;
+            }
+            "--delimiter" => delimiter = parse_delimiter(&value(&arg)?)?,
+            "--dtype" => {
+                let dtype = FileDtype::parse(&value(&arg)?)
+                    .map_err(|e| CliError::Usage(e.to_string()))?;
+                options.input = Input::Binary(dtype);
+                format = set_format(format, "--dtype")?;
+            }
+            "--digest" => {
+                options.input = Input::Digest;
+                format = set_format(format, "--digest")?;
+            }
+            "-q" | "--quantiles" => {
+                options.quantiles = Some(parse_quantiles(&value(&arg)?)?);
+            }
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "-m" | "--max-centroids" => {
+                let n = value(&arg)?;
+                options.max_centroids = Some(
+                    n.parse::<usize>()
+                        .ok()
+                        .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+                        .ok_or_else(|| {
+                            CliError::Usage(format!(
+                                "invalid number of centroids '{n}'"
+                            ))
+                        })?,
+                );
+            }
+            "-" => options.files.push(arg),
+            _ if arg.starts_with('-') => {
+                return Err(CliError::Usage(format!("unknown option '{arg}'")))
+            }
+            _ => options.files.push(arg),
+        }
+    }
+    if let Some(column) = csv_column {
+        options.input = Input::Csv(column, delimiter);
+    }
+    if options.files.is_empty() {
+        options.files.push("-".into());
+    }
+    let to_stdout = options.output.as_deref() == Some("-");
+    if to_stdout && options.quantiles.is_some() {
+        return Err(CliError::Usage(
+            "cannot print quantiles and write the digest to stdout".into(),
+        ));
+    }
+    Ok(Some(options))
+}
+
+/// Rejects a second input format.
+fn set_format(
+    format: Option<&'static str>,
+    option: &'static str,
+) -> Result<Option<&'static str>, CliError> {
+    match format {
+        Some(other) if other != option => Err(CliError::Usage(format!(
+            "options '{other}' and '{option}' cannot be combined"
+        ))),
+        _ => Ok(Some(option)),
+    }
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, CliError> {
+    match delimiter.as_bytes() {
+        &[b] if b.is_ascii() && !matches!(b, b'"' | b'\n' | b'\r') => Ok(b),
+        _ => Err(CliError::Usage(
+            "the delimiter must be a single ASCII character".into(),
+        )),
+    }
+}
+
+fn parse_quantiles(list: &str) -> Result<Vec<f64>, CliError> {
+    list.split(',')
+        .map(|q| {
+            q.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|q| (0.0..=1.0).contains(q))
+                .ok_or_else(|| {
+                    CliError::Usage(format!(
+                        "invalid quantile '{q}', expected a number between \
+                         0 and 1"
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn run(options: Options) -> Result<(), CliError> {
+    let digest = build_digest(&options)?;
+
+    if let Some(path) = &options.output {
+        let bytes = digest.to_bytes().map_err(IngestError::from)?;
+        if path == "-" {
+            io::stdout().lock().write_all(&bytes)?;
+        } else {
+            File::create(path)
+                .and_then(|mut file| file.write_all(&bytes))
+                .map_err(|e| CliError::Failed(format!("{path}: {e}")))?;
+        }
+        if options.quantiles.is_none() {
+            return Ok(());
+        }
+    }
+
+    if digest.is_empty() {
+        return Err(CliError::Failed("no values in the input".into()));
+    }
+    let quantiles = options
+        .quantiles
+        .unwrap_or_else(|| vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+    let mut stdout = io::stdout().lock();
+    for q in quantiles {
+        writeln!(stdout, "{q}\t{}", digest.estimate_quantile(q))?;
+    }
+    Ok(())
+}
+
+fn build_digest(options: &Options) -> Result<TDigest, CliError> {
+    if let Input::Digest = options.input {
+        let mut digests = Vec::new();
+        for path in &options.files {
+            let mut bytes = Vec::new();
+            open(path)?
+                .read_to_end(&mut bytes)
+                .map_err(|e| CliError::Failed(format!("{path}: {e}")))?;
+            let digest = TDigest::from_bytes(&bytes)
+                .map_err(|e| e.to_string())
+                .and_then(|digest| digest.validate().map(|()| digest))
+                .map_err(|e| CliError::Failed(format!("{path}: {e}")))?;
+            digests.push(digest);
+        }
+        if digests.len() == 1 && options.max_centroids.is_none() {
+            return Ok(digests.remove(0));
+        }
+        return TDigest::merge_digests(digests, options.max_centroids)
+            .map_err(|e| IngestError::from(e).into());
+    }
+
+    let max_size = options.max_centroids.unwrap_or(TD_SIZE_DEFAULT);
+    let mut digest =
+        TDigest::new_with_size(max_size).map_err(IngestError::from)?;
+    for path in &options.files {
+        let reader = open(path)?;
+        let merge = |values: &[f64]| merge_chunk(&mut digest, values);
+        let result = match &options.input {
+            Input::Text => ingest::read_text(reader, CHUNK, merge),
+            Input::Csv(column, delimiter) => {
+                ingest::read_csv(reader, column, *delimiter, CHUNK, merge)
+            }
+            Input::Binary(dtype) => {
+                ingest::read_binary(reader, *dtype, CHUNK, merge)
+            }
+            Input::Digest => unreachable!("digests are read above"),
+        };
+        result.map_err(|e| CliError::Failed(format!("{path}: {e}")))?;
+    }
+    Ok(digest)
+}
+
+/// Opens a file, or stdin for `-`.
+fn open(path: &str) -> Result<Box<dyn BufRead>, CliError> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(path)
+        .map_err(|e| CliError::Failed(format!("{path}: {e}")))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+fn merge_chunk(
+    digest: &mut TDigest,
+    values: &[f64],
+) -> Result<(), IngestError> {
+    if !values.iter().all(|x| x.is_finite()) {
+        return Err(IngestError::Invalid("Values must be finite.".into()));
+    }
+    let mut sorted = TDigest::collect_values(values.iter().copied())?;
+    TDigest::sort_values(&mut sorted, 1)?;
+    digest.merge_sorted_inplace(&sorted)?;
+    Ok(())
+}
@@ -0,0 +1,661 @@
+//! Digest persisted in a memory-mapped file
+//!
+//! The file holds a header and two slots, each with room for a state of
+//! the digest: its binary representation (as in `to_bytes`) and the
+//! buffered single updates. A writer fills the slot that is not current
+//! and then advances the sequence number in the header, whose parity
+//! selects the current slot. So the file always holds a complete state,
+//! even if the writer is killed while writing, and it is found again
+//! after a restart. Readers (in any number of processes) copy the
+//! current slot and retry if the sequence number has changed meanwhile,
+//! like readers of a seqlock.
+
+use crate::digest_map::{wrap_digest, Entry};
+use crate::errors::{EmptyDigestError, InvalidQuantileError};
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest, CACHE_SIZE,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: [u8; 8] = *b"FASTDMM~";
+const VERSION: u32 = 1;
+
+/// magic, version, padding, sequence number, max_size and slot size,
+/// padded to leave room for later fields
+const HEADER_BYTES: usize = 64;
+const SEQ_OFFSET: usize = 16;
+const MAX_SIZE_OFFSET: usize = 24;
+const SLOT_SIZE_OFFSET: usize = 32;
+/// digest length and number of buffered updates at the start of a slot
+const SLOT_HEADER_BYTES: usize = 16;
+
+/// Bytes of a slot for a digest of `max_size` centroids.
+fn slot_size(max_size: usize) -> Option<usize> {
+    max_size
+        .checked_add(CACHE_SIZE)?
+        .checked_mul(16)?
+        .checked_add(SLOT_HEADER_BYTES + TDigest::HEADER_BYTES)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SyncMode {
+    /// The state is written to the file after every update
+    Update,
+    /// Only by `flush` and `close`
+    Manual,
+}
+
+impl SyncMode {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "update" => Ok(Self::Update),
+            "manual" => Ok(Self::Manual),
+            _ => {
+                Err(PyValueError::new_err("sync must be 'update' or 'manual'."))
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Update => "update",
+            Self::Manual => "manual",
+        }
+    }
+}
+
+/// A file mapped by a Python `mmap` object, which stays exported (and so
+/// at a fixed address) while this exists.
+struct MappedFile {
+    mmap: Py<PyAny>,
+    buffer: PyBuffer<u8>,
+    max_size: usize,
+    slot_size: usize,
+}
+
+impl MappedFile {
+    /// Maps the file at `path`. A new (or empty) file is only initialized
+    /// if `writable`, for a digest of `max_size` centroids.
+    fn open(
+        py: Python<'_>,
+        path: &PathBuf,
+        writable: bool,
+        max_size: usize,
+    ) -> PyResult<(Self, bool)> {
+        let os = py.import("os")?;
+        let mmap = py.import("mmap")?;
+        let flags = if writable {
+            os.getattr("O_RDWR")?.extract::<i64>()?
+                | os.getattr("O_CREAT")?.extract::<i64>()?
+        } else {
+            os.getattr("O_RDONLY")?.extract::<i64>()?
+        };
+        let fd = os.call_method1("open", (path, flags))?;
+        let size = slot_size(max_size)
+            .and_then(|slot| slot.checked_mul(2))
+            .and_then(|slots| slots.checked_add(HEADER_BYTES))
+            .ok_or_else(|| bytes_error(BytesError::WrongArch))?;
+        let mapped = (|| {
+            let stat = os.call_method1("fstat", (&fd,))?;
+            let len: usize = stat.getattr("st_size")?.extract()?;
+            if len == 0 && !writable {
+                return Err(bytes_error(BytesError::EmptyData));
+            }
+            if len == 0 {
+                os.call_method1("ftruncate", (&fd, size))?;
+            }
+            let kwargs = PyDict::new(py);
+            if !writable {
+                kwargs.set_item("access", mmap.getattr("ACCESS_READ")?)?;
+            }
+            mmap.call_method("mmap", (&fd, 0), Some(&kwargs))
+        })();
+        os.call_method1("close", (fd,))?;
+        let map = mapped?;
+        let mut file = Self {
+            buffer: PyBuffer::<u8>::get(&map)?,
+            mmap: map.unbind(),
+            max_size,
+            slot_size: 0,
+        };
+        // the magic is written last, so that an interrupted initialization
+        // is started over
+        let new = writable
+            && file.len() >= size
+            && file.bytes(0, 8).iter().all(|&b| b == 0);
+        let result = if new {
+            file.init(max_size)
+        } else {
+            file.read_header().map_err(bytes_error)
+        };
+        match result {
+            Ok(()) => Ok((file, new)),
+            Err(e) => {
+                file.release(py)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Releases the buffer and closes the mapping.
+    fn release(self, py: Python<'_>) -> PyResult<()> {
+        self.buffer.release(py);
+        self.mmap.call_method0(py, "close")?;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.buffer.len_bytes()
+    }
+
+    fn ptr(&self) -> *mut u8 {
+        self.buffer.buf_ptr().cast::<u8>()
+    }
+
+    /// Copy of `len` bytes of the file at `offset`, which must be in range.
+    fn bytes(&self, offset: usize, len: usize) -> Vec<u8> {
+        assert!(offset + len <= self.len());
+        let mut bytes = vec![0u8; len];
+        // SAFETY: in range of the mapping, which outlives `self`
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.ptr().add(offset),
+                bytes.as_mut_ptr(),
+                len,
+            );
+        }
+        bytes
+    }
+
+    /// Writes `bytes` at `offset`, which must be in range of a writable
+    /// mapping.
+    fn write(&self, offset: usize, bytes: &[u8]) {
+        assert!(!self.buffer.readonly());
+        assert!(offset + bytes.len() <= self.len());
+        // SAFETY: in range of the mapping, which outlives `self`
+        unsafe {
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.ptr().add(offset),
+                bytes.len(),
+            );
+        }
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.bytes(offset, 8).try_into().unwrap())
+    }
+
+    /// Sequence number of the current state (little-endian like the rest
+    /// of the file).
+    fn seq(&self, order: Ordering) -> u64 {
+        u64::from_le(self.seq_atomic().load(order))
+    }
+
+    fn seq_atomic(&self) -> &AtomicU64 {
+        // SAFETY: the mapping is page-aligned (so the offset is aligned
+        // for a u64), at least HEADER_BYTES long and outlives `self`
+        unsafe { &*self.ptr().add(SEQ_OFFSET).cast::<AtomicU64>() }
+    }
+
+    fn init(&mut self, max_size: usize) -> PyResult<()> {
+        self.slot_size = slot_size(max_size).expect("checked in open");
+        self.write(MAX_SIZE_OFFSET, &(max_size as u64).to_le_bytes());
+        self.write(SLOT_SIZE_OFFSET, &(self.slot_size as u64).to_le_bytes());
+        let entry = Entry::new(max_size).map_err(malloc_error)?;
+        self.write_state(&entry)?;
+        self.write(8, &VERSION.to_le_bytes());
+        self.write(0, &MAGIC);
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> Result<(), BytesError> {
+        if self.len() < HEADER_BYTES || self.bytes(0, 8) != MAGIC {
+            return Err(BytesError::WrongFormat);
+        }
+        let version = u32::from_le_bytes(self.bytes(8, 4).try_into().unwrap());
+        if version != VERSION {
+            return Err(BytesError::WrongVersion);
+        }
+        let max_size = usize::try_from(self.u64(MAX_SIZE_OFFSET))
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        let slot_size = slot_size(max_size).ok_or(BytesError::WrongArch)?;
+        if self.u64(SLOT_SIZE_OFFSET) != slot_size as u64
+            || self.len() < HEADER_BYTES + 2 * slot_size
+        {
+            return Err(BytesError::CorruptData);
+        }
+        self.max_size = max_size;
+        self.slot_size = slot_size;
+        Ok(())
+    }
+
+    fn slot_offset(&self, seq: u64) -> usize {
+        HEADER_BYTES + (seq % 2) as usize * self.slot_size
+    }
+
+    /// Writes the state of `entry` to the slot that is not current, then
+    /// makes it current.
+    fn write_state(&self, entry: &Entry) -> PyResult<()> {
+        let digest = entry.digest.to_bytes().map_err(malloc_error)?;
+        let pending = entry.pending();
+        let len = SLOT_HEADER_BYTES + digest.len() + 16 * pending.len();
+        if len > self.slot_size {
+            return Err(PyValueError::new_err(
+                "The digest does not fit into its file.",
+            ));
+        }
+        let mut slot: Vec<u8> = Vec::new();
+        slot.try_reserve_exact(len).map_err(malloc_error)?;
+        slot.extend_from_slice(&(digest.len() as u64).to_le_bytes());
+        slot.extend_from_slice(&(pending.len() as u64).to_le_bytes());
+        slot.extend_from_slice(&digest);
+        for (x, w) in pending {
+            slot.extend_from_slice(&x.0.to_le_bytes());
+            slot.extend_from_slice(&w.to_le_bytes());
+        }
+        // the only writer, so the sequence number is not contended
+        let seq = self.seq(Ordering::Relaxed) + 1;
+        // the previous sequence number has to be visible before the slot
+        // of the one before it is overwritten
+        fence(Ordering::Release);
+        self.write(self.slot_offset(seq), &slot);
+        self.seq_atomic().store(seq.to_le(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Reads the current state, along with its sequence number.
+    fn read_state(&self) -> Result<(u64, Entry), BytesError> {
+        let (seq, slot) = loop {
+            let seq = self.seq(Ordering::Acquire);
+            let slot = self.bytes(self.slot_offset(seq), self.slot_size);
+            fence(Ordering::Acquire);
+            if self.seq(Ordering::Relaxed) == seq {
+                break (seq, slot);
+            }
+            std::hint::spin_loop();
+        };
+        let field = |i: usize| {
+            let bytes = slot[8 * i..8 * i + 8].try_into().unwrap();
+            usize::try_from(u64::from_le_bytes(bytes))
+                .map_err(|_| BytesError::CorruptData)
+        };
+        let (digest_len, n_pending) = (field(0)?, field(1)?);
+        let rest = &slot[SLOT_HEADER_BYTES..];
+        let pending_start = digest_len;
+        if digest_len > rest.len() || n_pending > (rest.len() - digest_len) / 16
+        {
+            return Err(BytesError::CorruptData);
+        }
+        let digest = TDigest::from_bytes(&rest[..digest_len])?;
+        if digest.max_size() != self.max_size {
+            return Err(BytesError::CorruptData);
+        }
+        let mut entry = Entry::from_digest(digest);
+        for pair in rest[pending_start..].chunks_exact(16).take(n_pending) {
+            let x = f64::from_le_bytes(pair[..8].try_into().unwrap());
+            let w = f64::from_le_bytes(pair[8..].try_into().unwrap());
+            entry.add(x, w).map_err(BytesError::MemError)?;
+        }
+        Ok((seq, entry))
+    }
+}
+
+struct MappedState {
+    /// None once closed
+    file: Option<MappedFile>,
+    entry: Entry,
+    writable: bool,
+    sync: SyncMode,
+    /// Sequence number of the state in `entry`, for readers
+    seq: u64,
+    /// Whether `entry` has changed since it was last written
+    dirty: bool,
+}
+
+impl MappedState {
+    fn file(&self) -> PyResult<&MappedFile> {
+        self.file
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("MappedTDigest is closed."))
+    }
+
+    /// Checks that the digest may be updated.
+    fn check_writable(&self) -> PyResult<()> {
+        self.file()?;
+        if self.writable {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(
+                "MappedTDigest was opened read-only (mode='r').",
+            ))
+        }
+    }
+
+    /// Marks the state as changed, and writes it with sync='update'.
+    fn updated(&mut self) -> PyResult<()> {
+        self.dirty = true;
+        if self.sync == SyncMode::Update {
+            self.write()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self) -> PyResult<()> {
+        if self.dirty {
+            self.file()?.write_state(&self.entry)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Loads the current state of the file (in a reader), then flushes the
+    /// buffered updates.
+    fn refresh(&mut self) -> PyResult<()> {
+        let file = self.file()?;
+        if !self.writable && file.seq(Ordering::Acquire) != self.seq {
+            let (seq, entry) = file.read_state().map_err(bytes_error)?;
+            self.seq = seq;
+            self.entry = entry;
+        }
+        self.entry.flush().map_err(malloc_error)
+    }
+}
+
+impl Drop for MappedState {
+    fn drop(&mut self) {
+        // without the GIL the mapping can still be written, but not synced
+        if self.writable && self.file.is_some() {
+            let _ = self.write();
+        }
+    }
+}
+
+#[pyclass(name = "MappedTDigest", module = "fastdigest", frozen)]
+pub struct PyMappedTDigest {
+    path: PathBuf,
+    state: Mutex<MappedState>,
+}
+
+impl PyMappedTDigest {
+    /// Locks the state, with the latest state of the file loaded.
+    fn lock_refreshed(&self) -> PyResult<MutexGuard<'_, MappedState>> {
+        let mut state = self.state.lock();
+        state.refresh()?;
+        Ok(state)
+    }
+
+    /// Runs `f` on the digest, raising EmptyDigestError if it is empty.
+    fn with_digest<T>(&self, f: impl FnOnce(&TDigest) -> T) -> PyResult<T> {
+        let state = self.lock_refreshed()?;
+        if state.entry.digest.is_empty() {
+            return Err(EmptyDigestError::new_err("MappedTDigest is empty."));
+        }
+        Ok(f(&state.entry.digest))
+    }
+}
+
+#[pymethods]
+impl PyMappedTDigest {
+    /// Opens a digest persisted in the file at `path`, which is created
+    /// with mode='w' if it does not exist.
+    #[new]
+    #[pyo3(signature = (path, max_centroids=None, mode="w", sync="update"))]
+    pub fn py_new(
+        py: Python<'_>,
+        path: PathBuf,
+        max_centroids: Option<i64>,
+        mode: &str,
+        sync: &str,
+    ) -> PyResult<Self> {
+        let writable = match mode {
+            "w" => true,
+            "r" => false,
+            _ => return Err(PyValueError::new_err("mode must be 'w' or 'r'.")),
+        };
+        let sync = SyncMode::from_name(sync)?;
+        let max_size = validate_max_centroids(
+            max_centroids.unwrap_or(TD_SIZE_DEFAULT as i64),
+        )?;
+        // the slot of the file is sized for max_centroids centroids, so an
+        // uncompressed digest would outgrow it
+        if max_size == 0 {
+            return Err(PyValueError::new_err(
+                "max_centroids must be positive for a MappedTDigest.",
+            ));
+        }
+        let (file, new) = MappedFile::open(py, &path, writable, max_size)?;
+        let loaded = if new {
+            Entry::new(max_size).map_err(malloc_error).map(|e| (1, e))
+        } else if max_centroids.is_some() && file.max_size != max_size {
+            Err(PyValueError::new_err(format!(
+                "max_centroids does not match the file ({}).",
+                file.max_size
+            )))
+        } else {
+            file.read_state().map_err(bytes_error)
+        };
+        let (seq, entry) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                file.release(py)?;
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            path,
+            state: Mutex::new(MappedState {
+                file: Some(file),
+                entry,
+                writable,
+                sync,
+                seq,
+                dirty: false,
+            }),
+        })
+    }
+
+    /// Getter property: returns the path of the file.
+    #[getter(path)]
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().entry.digest.max_size()
+    }
+
+    /// Getter property: returns the mode ('w' or 'r').
+    #[getter(mode)]
+    pub fn get_mode(&self) -> &'static str {
+        if self.state.lock().writable {
+            "w"
+        } else {
+            "r"
+        }
+    }
+
+    /// Getter property: returns the sync parameter.
+    #[getter(sync)]
+    pub fn get_sync(&self) -> &'static str {
+        self.state.lock().sync.name()
+    }
+
+    /// Getter property: returns True once the digest has been closed.
+    #[getter(closed)]
+    pub fn get_closed(&self) -> bool {
+        self.state.lock().file.is_none()
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> PyResult<u128> {
+        let mut state = self.state.lock();
+        if !state.writable {
+            state.refresh()?;
+        }
+        Ok(state.entry.count())
+    }
+
+    /// Updates the digest (in-place) with a single value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut state = self.state.lock();
+        state.check_writable()?;
+        state.entry.add(x, weight).map_err(malloc_error)?;
+        state.updated()
+    }
+
+    /// Updates the digest (in-place) with a sequence of values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let weights = validate_weights(w, values.len())?;
+        let mut pairs = Vec::new();
+        for (j, x) in values.iter(py).enumerate() {
+            // missing values are left out together with their weights
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| {
+            let mut state = self.state.lock();
+            state.check_writable()?;
+            state.entry.add_batch(&mut pairs).map_err(malloc_error)?;
+            state.updated()
+        })
+    }
+
+    /// Writes the state to the file (with sync='manual') and the file to
+    /// disk.
+    pub fn flush(&self, py: Python<'_>) -> PyResult<()> {
+        let mut state = self.state.lock();
+        state.check_writable()?;
+        state.write()?;
+        state.file()?.mmap.call_method0(py, "flush")?;
+        Ok(())
+    }
+
+    /// Writes the state to the file (without syncing it to disk) and
+    /// releases the mapping. Further calls have no effect.
+    pub fn close(&self, py: Python<'_>) -> PyResult<()> {
+        let mut state = self.state.lock();
+        if state.writable && state.file.is_some() {
+            state.write()?;
+        }
+        if let Some(file) = state.file.take() {
+            file.release(py)?;
+        }
+        Ok(())
+    }
+
+    pub fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.close(py)
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF at the value `x`.
+    pub fn cdf(&self, x: f64) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_rank(x))
+    }
+
+    /// Returns the weighted mean of the values.
+    pub fn mean(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::mean)
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::max)
+    }
+
+    /// Returns the total weight of the values.
+    pub fn mass(&self) -> PyResult<f64> {
+        Ok(self.lock_refreshed()?.entry.digest.mass())
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> PyResult<bool> {
+        Ok(self.lock_refreshed()?.entry.digest.is_empty())
+    }
+
+    /// Returns a copy of the digest as a TDigest.
+    pub fn snapshot(&self) -> PyResult<PyTDigest> {
+        Ok(wrap_digest(self.lock_refreshed()?.entry.digest.clone()))
+    }
+
+    /// Magic method: repr(digest) shows the path and mode.
+    pub fn __repr__(&self) -> String {
+        format!(
+            "MappedTDigest('{}', mode='{}')",
+            self.path.display(),
+            self.get_mode()
+        )
+    }
+}
@@ -0,0 +1,459 @@
+//! Exact, mergeable central moments up to the fourth order
+//!
+//! The sketch keeps the total weight, the mean and the central moment sums
+//! `M2`, `M3` and `M4`. Two sets of moments are combined with the pairwise
+//! update formulas of Pébay (2008), which are numerically stable for any
+//! merge order; batches are first summarized in two passes (mean, then
+//! central sums) and combined as a whole.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{EmptyDigestError, SerializationError};
+use crate::tdigest::BytesError;
+use crate::{
+    bytes_error, extract_values, validate_value, validate_values,
+    validate_weight, validate_weights,
+};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+const MAGIC: [u8; 8] = *b"FASTMOM~";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moments {
+    count: u64,
+    weight: f64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Moments {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            weight: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Moments {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Moments of a batch of values with optional weights, in two passes.
+    pub fn from_values(values: &[f64], weights: Option<&[f64]>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        let weight_at = |i: usize| weights.map_or(1.0, |w| w[i]);
+        let weight: f64 = (0..values.len()).map(weight_at).sum();
+        let sum: f64 = values
+            .iter()
+            .enumerate()
+            .map(|(i, x)| x * weight_at(i))
+            .sum();
+        let mean = sum / weight;
+        let mut moments = Self {
+            count: values.len() as u64,
+            weight,
+            mean,
+            ..Self::default()
+        };
+        for (i, &x) in values.iter().enumerate() {
+            let (d, w) = (x - mean, weight_at(i));
+            let d2 = d * d;
+            moments.m2 += w * d2;
+            moments.m3 += w * d2 * d;
+            moments.m4 += w * d2 * d2;
+            moments.min = moments.min.min(x);
+            moments.max = moments.max.max(x);
+        }
+        moments
+    }
+
+    pub fn add(&mut self, x: f64, w: f64) {
+        self.merge(&Self {
+            count: 1,
+            weight: w,
+            mean: x,
+            min: x,
+            max: x,
+            ..Self::default()
+        });
+    }
+
+    pub fn merge(&mut self, other: &Moments) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = *other;
+            return;
+        }
+        let (na, nb) = (self.weight, other.weight);
+        let n = na + nb;
+        let delta = other.mean - self.mean;
+        let (d2, d_n) = (delta * delta, delta / n);
+        let m2 = self.m2 + other.m2 + d2 * na * nb / n;
+        let m3 = self.m3
+            + other.m3
+            + d2 * delta * na * nb * (na - nb) / (n * n)
+            + 3.0 * d_n * (na * other.m2 - nb * self.m2);
+        let m4 = self.m4
+            + other.m4
+            + d2 * d2 * na * nb * (na * na - na * nb + nb * nb) / (n * n * n)
+            + 6.0 * d2 * (na * na * other.m2 + nb * nb * self.m2) / (n * n)
+            + 4.0 * d_n * (na * other.m3 - nb * self.m3);
+        self.mean += d_n * nb;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+        self.weight = n;
+        self.count = self.count.saturating_add(other.count);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Population variance.
+    #[inline]
+    pub fn var(&self) -> f64 {
+        self.m2 / self.weight
+    }
+
+    /// Population skewness (Fisher-Pearson coefficient); NaN if the
+    /// variance is 0.
+    pub fn skew(&self) -> f64 {
+        if self.m2 == 0.0 {
+            return f64::NAN;
+        }
+        self.weight.sqrt() * self.m3 / self.m2.powf(1.5)
+    }
+
+    /// Population kurtosis, as excess kurtosis (0 for a normal
+    /// distribution) if `fisher`; NaN if the variance is 0.
+    pub fn kurtosis(&self, fisher: bool) -> f64 {
+        if self.m2 == 0.0 {
+            return f64::NAN;
+        }
+        let kurtosis = self.weight * self.m4 / (self.m2 * self.m2);
+        if fisher {
+            kurtosis - 3.0
+        } else {
+            kurtosis
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.count);
+        for value in [
+            self.weight,
+            self.mean,
+            self.m2,
+            self.m3,
+            self.m4,
+            self.min,
+            self.max,
+        ] {
+            writer.f64(value);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let count = reader.u64()?;
+        let mut values = [0.0; 7];
+        for value in &mut values {
+            *value = reader.f64()?;
+        }
+        reader.finish()?;
+        let [weight, mean, m2, m3, m4, min, max] = values;
+        Self {
+            count,
+            weight,
+            mean,
+            m2,
+            m3,
+            m4,
+            min,
+            max,
+        }
+        .validate()
+        .ok_or(BytesError::CorruptData)
+    }
+
+    /// Checks the consistency of restored moments, including a count that
+    /// fits them: a single value is both the minimum and the maximum.
+    fn validate(self) -> Option<Self> {
+        let valid = if self.count == 0 {
+            self == Self::default()
+        } else {
+            [self.weight, self.mean, self.m2, self.m3, self.m4]
+                .iter()
+                .chain(&[self.min, self.max])
+                .all(|v| v.is_finite())
+                && self.weight > 0.0
+                && self.m2 >= 0.0
+                && self.m4 >= 0.0
+                && (self.count > 1 || self.min == self.max)
+        };
+        valid.then_some(self)
+    }
+}
+
+#[pyclass(name = "MomentsSketch", module = "fastdigest", frozen)]
+pub struct PyMomentsSketch {
+    state: Mutex<Moments>,
+}
+
+impl PyMomentsSketch {
+    fn from_moments(moments: Moments) -> Self {
+        Self {
+            state: Mutex::new(moments),
+        }
+    }
+
+    /// Locks the moments, raising ValueError if they are empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, Moments>> {
+        let moments = self.state.lock();
+        if moments.is_empty() {
+            return Err(EmptyDigestError::new_err("MomentsSketch is empty."));
+        }
+        Ok(moments)
+    }
+}
+
+#[pymethods]
+impl PyMomentsSketch {
+    /// Constructs a new empty MomentsSketch instance.
+    #[new]
+    pub fn py_new() -> Self {
+        Self::from_moments(Moments::default())
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let w = validate_weight(w.unwrap_or(1.0))?;
+        self.state.lock().add(x, w);
+        Ok(())
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let weights = validate_weights(w, values.len())?;
+        let (values, weights): (Vec<f64>, Option<Vec<f64>>) = match weights {
+            Some(weights) => {
+                let (values, weights) = values
+                    .iter(py)
+                    .zip(weights)
+                    .filter(|(x, _)| keep(x))
+                    .unzip();
+                (values, Some(weights))
+            }
+            None => (values.iter(py).filter(keep).collect(), None),
+        };
+        let batch =
+            py.detach(|| Moments::from_values(&values, weights.as_deref()));
+        self.state.lock().merge(&batch);
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new MomentsSketch.
+    pub fn merge(&self, other: &Self) -> Self {
+        let merged = self.copy();
+        merged.merge_inplace(other);
+        merged
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) {
+        let other = *other.state.lock();
+        self.state.lock().merge(&other);
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns the total weight of the data.
+    pub fn mass(&self) -> f64 {
+        self.state.lock().weight
+    }
+
+    /// Returns the sum of the data.
+    pub fn sum(&self) -> f64 {
+        let moments = self.state.lock();
+        moments.mean * moments.weight
+    }
+
+    /// Returns the mean of the data.
+    pub fn mean(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.mean)
+    }
+
+    /// Returns the population variance of the data.
+    pub fn var(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.var())
+    }
+
+    /// Returns the population standard deviation of the data.
+    pub fn std(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.var().sqrt())
+    }
+
+    /// Returns the skewness of the data.
+    pub fn skew(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.skew())
+    }
+
+    /// Returns the kurtosis of the data (excess kurtosis if `fisher`).
+    #[pyo3(signature = (fisher=true))]
+    pub fn kurtosis(&self, fisher: bool) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.kurtosis(fisher))
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a MomentsSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let moments = Moments::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_moments(moments))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let moments = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("n_values", moments.count)?;
+        dict.set_item("mass", moments.weight)?;
+        dict.set_item("mean", moments.mean)?;
+        dict.set_item("m2", moments.m2)?;
+        dict.set_item("m3", moments.m3)?;
+        dict.set_item("m4", moments.m4)?;
+        dict.set_item("min", moments.min)?;
+        dict.set_item("max", moments.max)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a MomentsSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let moments = Moments {
+            count: get("n_values")?.extract()?,
+            weight: get("mass")?.extract()?,
+            mean: get("mean")?.extract()?,
+            m2: get("m2")?.extract()?,
+            m3: get("m3")?.extract()?,
+            m4: get("m4")?.extract()?,
+            min: get("min")?.extract()?,
+            max: get("max")?.extract()?,
+        }
+        .validate()
+        .ok_or_else(|| SerializationError::new_err("Inconsistent moments."))?;
+        Ok(Self::from_moments(moments))
+    }
+
+    /// MomentsSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_moments(*self.state.lock())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via MomentsSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(MomentsSketch) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        format!("MomentsSketch(n_values={})", self.state.lock().count)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        let other = *other.state.lock();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> Self {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) {
+        self.merge_inplace(other)
+    }
+}
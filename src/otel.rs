@@ -0,0 +1,295 @@
+//! Conversion between TDigest and OpenTelemetry exponential histograms
+
+use crate::tdigest::{Centroid, TDigest};
+use std::collections::TryReserveError;
+use std::f64::consts::LN_2;
+
+pub const OTEL_SCALE_MIN: i32 = -10;
+pub const OTEL_SCALE_MAX: i32 = 20;
+pub const OTEL_MAX_SIZE_DEFAULT: usize = 160;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Buckets {
+    pub offset: i64,
+    pub bucket_counts: Vec<u64>,
+}
+
+/// Data point of an OTLP exponential histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialHistogram {
+    pub scale: i32,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    pub zero_count: u64,
+    pub zero_threshold: f64,
+    pub positive: Buckets,
+    pub negative: Buckets,
+}
+
+/// Index of the bucket (base^i, base^(i+1)] containing `value` (> 0).
+#[inline]
+fn bucket_index(value: f64, scale: i32) -> i64 {
+    let factor = (scale as f64).exp2() / LN_2;
+    (value.ln() * factor).ceil() as i64 - 1
+}
+
+/// Lower and upper boundary of the bucket with the given index.
+#[inline]
+fn bucket_bounds(index: i64, scale: i32) -> (f64, f64) {
+    let factor = (scale as f64).exp2() / LN_2;
+    (
+        (index as f64 / factor).exp(),
+        ((index + 1) as f64 / factor).exp(),
+    )
+}
+
+/// Highest scale (<= `scale`) at which the index range fits `max_size`.
+fn fit_scale(
+    centroids: &[Centroid],
+    zero_threshold: f64,
+    scale: i32,
+    max_size: usize,
+) -> i32 {
+    let mut scale = scale;
+    while scale > OTEL_SCALE_MIN {
+        let mut pos: Option<(i64, i64)> = None;
+        let mut neg: Option<(i64, i64)> = None;
+        for c in centroids {
+            let m = c.mean();
+            if m.abs() <= zero_threshold {
+                continue;
+            }
+            let idx = bucket_index(m.abs(), scale);
+            let range = if m > 0.0 { &mut pos } else { &mut neg };
+            *range = match *range {
+                Some((lo, hi)) => Some((lo.min(idx), hi.max(idx))),
+                None => Some((idx, idx)),
+            };
+        }
+        let fits = |r: Option<(i64, i64)>| {
+            r.is_none_or(|(lo, hi)| ((hi - lo) as u64) < max_size as u64)
+        };
+        if fits(pos) && fits(neg) {
+            break;
+        }
+        scale -= 1;
+    }
+    scale
+}
+
+fn dense_buckets(entries: &[(i64, u64)]) -> Result<Buckets, TryReserveError> {
+    let (Some(&(lo, _)), Some(&(hi, _))) = (
+        entries.iter().min_by_key(|e| e.0),
+        entries.iter().max_by_key(|e| e.0),
+    ) else {
+        return Ok(Buckets::default());
+    };
+    let mut bucket_counts: Vec<u64> = Vec::new();
+    bucket_counts.try_reserve_exact((hi - lo + 1) as usize)?;
+    bucket_counts.resize((hi - lo + 1) as usize, 0);
+    for &(idx, count) in entries {
+        bucket_counts[(idx - lo) as usize] += count;
+    }
+    Ok(Buckets {
+        offset: lo,
+        bucket_counts,
+    })
+}
+
+impl ExponentialHistogram {
+    /// Buckets the centroids of `digest` by their means. Fractional weights
+    /// are rounded cumulatively so that the total count matches the mass.
+    pub fn from_digest(
+        digest: &TDigest,
+        scale: i32,
+        max_size: usize,
+        zero_threshold: f64,
+    ) -> Result<Self, TryReserveError> {
+        let centroids = digest.centroids();
+        let scale = fit_scale(centroids, zero_threshold, scale, max_size);
+
+        let mut pos: Vec<(i64, u64)> = Vec::new();
+        let mut neg: Vec<(i64, u64)> = Vec::new();
+        pos.try_reserve_exact(centroids.len())?;
+        neg.try_reserve_exact(centroids.len())?;
+        let mut zero_count: u64 = 0;
+        let mut cumulative: f64 = 0.0;
+        let mut rounded: u64 = 0;
+
+        for c in centroids {
+            cumulative += c.weight();
+            let count = (cumulative.round() as u64).saturating_sub(rounded);
+            rounded += count;
+            let m = c.mean();
+            if m.abs() <= zero_threshold {
+                zero_count += count;
+            } else if m > 0.0 {
+                pos.push((bucket_index(m, scale), count));
+            } else {
+                neg.push((bucket_index(-m, scale), count));
+            }
+        }
+
+        Ok(Self {
+            scale,
+            count: rounded,
+            sum: digest.sum(),
+            min: digest.min(),
+            max: digest.max(),
+            zero_count,
+            zero_threshold,
+            positive: dense_buckets(&pos)?,
+            negative: dense_buckets(&neg)?,
+        })
+    }
+
+    /// Total count of the zero bucket and all other buckets, or `None` if it
+    /// overflows.
+    pub fn bucket_total(&self) -> Option<u64> {
+        self.positive
+            .bucket_counts
+            .iter()
+            .chain(&self.negative.bucket_counts)
+            .try_fold(self.zero_count, |total, &count| total.checked_add(count))
+    }
+
+    /// Checks that a histogram loaded from outside is consistent: finite
+    /// boundaries of the non-empty buckets, a count that matches them, and
+    /// (if present) finite statistics with `min <= max`. The error describes
+    /// the first violation.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.zero_threshold.is_finite() && self.zero_threshold >= 0.0) {
+            return Err(format!(
+                "zero_threshold must be finite and non-negative, not {:?}.",
+                self.zero_threshold
+            ));
+        }
+        for (name, buckets) in
+            [("positive", &self.positive), ("negative", &self.negative)]
+        {
+            for (i, &count) in buckets.bucket_counts.iter().enumerate() {
+                if count == 0 {
+                    continue;
+                }
+                let upper = buckets
+                    .offset
+                    .checked_add(i as i64)
+                    .filter(|&index| index < i64::MAX)
+                    .map(|index| bucket_bounds(index, self.scale).1);
+                if !upper.is_some_and(f64::is_finite) {
+                    return Err(format!(
+                        "Bucket {i} of '{name}' has non-finite bounds at \
+                         scale {}.",
+                        self.scale
+                    ));
+                }
+            }
+        }
+        match self.bucket_total() {
+            Some(total) if total == self.count => {}
+            Some(total) => {
+                return Err(format!(
+                    "count is {}, but the buckets hold {total} values.",
+                    self.count
+                ));
+            }
+            None => return Err("The bucket counts overflow.".to_string()),
+        }
+        for (name, value) in
+            [("sum", self.sum), ("min", self.min), ("max", self.max)]
+        {
+            if value.is_infinite() {
+                return Err(format!("{name} must be finite, not {value:?}."));
+            }
+        }
+        if self.min > self.max {
+            return Err(format!(
+                "min ({:?}) is greater than max ({:?}).",
+                self.min, self.max
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rebuilds an approximate digest with one centroid per non-empty bucket,
+    /// located at the bucket midpoint (clamped to `min`/`max`).
+    pub fn to_digest(
+        &self,
+        max_size: usize,
+    ) -> Result<TDigest, TryReserveError> {
+        let n_buckets = self.positive.bucket_counts.len()
+            + self.negative.bucket_counts.len()
+            + 1;
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids.try_reserve_exact(n_buckets)?;
+
+        let clamp = |x: f64| {
+            let x = if self.min.is_nan() {
+                x
+            } else {
+                x.max(self.min)
+            };
+            if self.max.is_nan() {
+                x
+            } else {
+                x.min(self.max)
+            }
+        };
+
+        let neg = &self.negative;
+        for (i, &count) in neg.bucket_counts.iter().enumerate().rev() {
+            if count > 0 {
+                let (lower, upper) =
+                    bucket_bounds(neg.offset + i as i64, self.scale);
+                centroids.push(Centroid::new(
+                    clamp(-0.5 * (lower + upper)),
+                    count as f64,
+                ));
+            }
+        }
+        if self.zero_count > 0 {
+            centroids.push(Centroid::new(clamp(0.0), self.zero_count as f64));
+        }
+        let pos = &self.positive;
+        for (i, &count) in pos.bucket_counts.iter().enumerate() {
+            if count > 0 {
+                let (lower, upper) =
+                    bucket_bounds(pos.offset + i as i64, self.scale);
+                centroids.push(Centroid::new(
+                    clamp(0.5 * (lower + upper)),
+                    count as f64,
+                ));
+            }
+        }
+
+        if centroids.is_empty() {
+            return TDigest::new_with_size(max_size);
+        }
+
+        let mass: f64 = centroids.iter().map(|c| c.weight()).sum();
+        let min = if self.min.is_nan() {
+            centroids[0].mean()
+        } else {
+            self.min
+        };
+        let max = if self.max.is_nan() {
+            centroids[centroids.len() - 1].mean()
+        } else {
+            self.max
+        };
+        let sum = if self.sum.is_nan() {
+            centroids.iter().map(|c| c.mean() * c.weight()).sum()
+        } else {
+            self.sum
+        };
+        let count = if self.count > 0 {
+            self.count as u128
+        } else {
+            mass.round() as u128
+        };
+
+        TDigest::new(centroids, max_size, mass, sum, min, max, count)
+    }
+}
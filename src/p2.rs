@@ -0,0 +1,369 @@
+//! P² algorithm: tracking a single quantile in constant memory
+//!
+//! Five markers follow the minimum, the maximum, the target quantile `q`
+//! and the quantiles `q/2` and `(1+q)/2` (Jain & Chlamtac, 1985). Each new
+//! value shifts the positions of the markers above it; markers that drift
+//! from their desired positions are moved by one, with their heights
+//! adjusted by piecewise-parabolic (or, if that is not monotonic, linear)
+//! interpolation. Unlike the other sketches, the estimate cannot be merged.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, validate_values};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+const MAGIC: [u8; 8] = *b"FASTP2Q~";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2Quantile {
+    q: f64,
+    count: u64,
+    /// Marker heights; the first values in ascending order until there are
+    /// five of them
+    heights: [f64; 5],
+    /// Actual marker positions (1-based ranks)
+    positions: [f64; 5],
+    desired: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(q: f64) -> Self {
+        Self {
+            q,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * q, 1.0 + 4.0 * q, 3.0 + 2.0 * q, 5.0],
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    fn increments(&self) -> [f64; 5] {
+        [0.0, 0.5 * self.q, self.q, 0.5 * (1.0 + self.q), 1.0]
+    }
+
+    pub fn add(&mut self, x: f64) {
+        if self.count < 5 {
+            let n = self.count as usize;
+            let i = self.heights[..n].partition_point(|&h| h <= x);
+            self.heights.copy_within(i..n, i + 1);
+            self.heights[i] = x;
+            self.count += 1;
+            return;
+        }
+        self.count += 1;
+        let h = &mut self.heights;
+        // cell of x between the markers, extending the extremes if needed
+        let k = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            h[1..4].partition_point(|&m| m <= x)
+        };
+        for position in &mut self.positions[k + 1..] {
+            *position += 1.0;
+        }
+        let increments = self.increments();
+        for (desired, increment) in self.desired.iter_mut().zip(increments) {
+            *desired += increment;
+        }
+        for i in 1..4 {
+            self.adjust(i);
+        }
+    }
+
+    fn adjust(&mut self, i: usize) {
+        let (h, n) = (&mut self.heights, &mut self.positions);
+        let d = self.desired[i] - n[i];
+        let movable = (d >= 1.0 && n[i + 1] - n[i] > 1.0)
+            || (d <= -1.0 && n[i - 1] - n[i] < -1.0);
+        if !movable {
+            return;
+        }
+        let d = d.signum();
+        let parabolic = h[i]
+            + d / (n[i + 1] - n[i - 1])
+                * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i])
+                    / (n[i + 1] - n[i])
+                    + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1])
+                        / (n[i] - n[i - 1]));
+        h[i] = if h[i - 1] < parabolic && parabolic < h[i + 1] {
+            parabolic
+        } else {
+            let j = if d > 0.0 { i + 1 } else { i - 1 };
+            h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+        };
+        n[i] += d;
+    }
+
+    /// Current estimate of the quantile. Until five values have been seen,
+    /// this is the nearest-rank quantile of the values. The estimator must
+    /// be nonempty.
+    pub fn estimate(&self) -> f64 {
+        if self.count < 5 {
+            let i = (self.q * (self.count - 1) as f64).round() as usize;
+            return self.heights[i];
+        }
+        self.heights[2]
+    }
+
+    pub fn min(&self) -> f64 {
+        self.heights[0]
+    }
+
+    pub fn max(&self) -> f64 {
+        let n = self.count.min(5) as usize;
+        self.heights[n - 1]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.q);
+        writer.u64(self.count);
+        for markers in [&self.heights, &self.positions, &self.desired] {
+            for &value in markers {
+                writer.f64(value);
+            }
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let q = reader.f64()?;
+        let count = reader.u64()?;
+        let mut markers = [[0.0; 5]; 3];
+        for values in &mut markers {
+            for value in values {
+                *value = reader.f64()?;
+            }
+        }
+        reader.finish()?;
+        let [heights, positions, desired] = markers;
+        Self::from_parts(q, count, heights, positions, desired)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles an estimator, checking that the parts are consistent.
+    fn from_parts(
+        q: f64,
+        count: u64,
+        heights: [f64; 5],
+        positions: [f64; 5],
+        desired: [f64; 5],
+    ) -> Option<Self> {
+        let n = count.min(5) as usize;
+        let valid = valid_q(q)
+            && heights[..n].is_sorted_by(|a, b| a <= b)
+            && heights
+                .iter()
+                .chain(&positions)
+                .chain(&desired)
+                .all(|v| v.is_finite())
+            && positions.is_sorted_by(|a, b| a < b);
+        valid.then_some(Self {
+            q,
+            count,
+            heights,
+            positions,
+            desired,
+        })
+    }
+}
+
+#[inline]
+fn valid_q(q: f64) -> bool {
+    q > 0.0 && q < 1.0
+}
+
+#[pyclass(name = "P2Quantile", module = "fastdigest", frozen)]
+pub struct PyP2Quantile {
+    state: Mutex<P2Quantile>,
+}
+
+impl PyP2Quantile {
+    fn from_estimator(estimator: P2Quantile) -> Self {
+        Self {
+            state: Mutex::new(estimator),
+        }
+    }
+
+    /// Locks the estimator, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, P2Quantile>> {
+        let estimator = self.state.lock();
+        if estimator.is_empty() {
+            return Err(EmptyDigestError::new_err("P2Quantile is empty."));
+        }
+        Ok(estimator)
+    }
+}
+
+#[pymethods]
+impl PyP2Quantile {
+    /// Constructs a new empty P2Quantile instance for the quantile `q`.
+    #[new]
+    pub fn py_new(q: f64) -> PyResult<Self> {
+        if !valid_q(q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1 (exclusive).",
+            ));
+        }
+        Ok(Self::from_estimator(P2Quantile::new(q)))
+    }
+
+    /// Getter property: returns the tracked quantile `q`.
+    #[getter(q)]
+    pub fn get_q(&self) -> f64 {
+        self.state.lock().q
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Updates the estimator (in-place) with a single float value.
+    pub fn update(&self, x: f64) -> PyResult<()> {
+        validate_values([x])?;
+        self.state.lock().add(x);
+        Ok(())
+    }
+
+    /// Updates the estimator (in-place) with a sequence of float values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let mut estimator = self.state.lock();
+        for x in values.iter(py).filter(keep) {
+            estimator.add(x);
+        }
+        Ok(())
+    }
+
+    /// Returns True if the estimator is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns the current estimate of the quantile.
+    pub fn estimate(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.estimate())
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min())
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max())
+    }
+
+    /// Returns a binary representation of the estimator.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a P2Quantile from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let estimator = P2Quantile::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_estimator(estimator))
+    }
+
+    /// Returns a dict representation of the estimator.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let estimator = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("q", estimator.q)?;
+        dict.set_item("n_values", estimator.count)?;
+        dict.set_item("heights", estimator.heights)?;
+        dict.set_item("positions", estimator.positions)?;
+        dict.set_item("desired", estimator.desired)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a P2Quantile from a dict.
+    #[staticmethod]
+    pub fn from_dict(estimator_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            estimator_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let estimator = P2Quantile::from_parts(
+            get("q")?.extract()?,
+            get("n_values")?.extract()?,
+            get("heights")?.extract()?,
+            get("positions")?.extract()?,
+            get("desired")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent P2Quantile markers.")
+        })?;
+        Ok(Self::from_estimator(estimator))
+    }
+
+    /// P2Quantile.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_estimator(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(estimator) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(estimator) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via P2Quantile.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(P2Quantile) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("P2Quantile(q={})", self.state.lock().q)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+}
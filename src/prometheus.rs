@@ -0,0 +1,136 @@
+//! Rendering of TDigests in the Prometheus text exposition format
+
+use crate::tdigest::TDigest;
+use std::fmt::Write;
+
+pub const PROM_QUANTILES_DEFAULT: [f64; 3] = [0.5, 0.9, 0.99];
+
+pub enum MetricKind<'a> {
+    Summary { quantiles: &'a [f64] },
+    Histogram { buckets: &'a [f64] },
+}
+
+pub fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+pub fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
+
+/// Formats a sample value the way Prometheus clients do.
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    extra: Option<(&str, f64)>,
+    value: f64,
+) {
+    out.push_str(name);
+    if !labels.is_empty() || extra.is_some() {
+        let mut pairs: Vec<String> = labels
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+            .collect();
+        if let Some((k, v)) = extra {
+            pairs.push(format!("{k}=\"{}\"", format_value(v)));
+        }
+        let _ = write!(out, "{{{}}}", pairs.join(","));
+    }
+    let _ = writeln!(out, " {}", format_value(value));
+}
+
+/// Renders the digest as a single Prometheus metric family. Histogram bucket
+/// counts are estimated from the CDF, so they are cumulative and monotonic.
+pub fn render(
+    digest: &TDigest,
+    name: &str,
+    kind: MetricKind<'_>,
+    labels: &[(String, String)],
+    help: Option<&str>,
+) -> String {
+    let mut out = String::new();
+    if let Some(help) = help {
+        let _ = writeln!(out, "# HELP {name} {}", escape_help(help));
+    }
+
+    let mass = digest.mass();
+    match kind {
+        MetricKind::Summary { quantiles } => {
+            let _ = writeln!(out, "# TYPE {name} summary");
+            for &q in quantiles {
+                let value = if digest.is_empty() {
+                    f64::NAN
+                } else {
+                    digest.estimate_quantile(q)
+                };
+                write_sample(
+                    &mut out,
+                    name,
+                    labels,
+                    Some(("quantile", q)),
+                    value,
+                );
+            }
+        }
+        MetricKind::Histogram { buckets } => {
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            let bucket_name = format!("{name}_bucket");
+            for &le in buckets.iter().filter(|le| le.is_finite()) {
+                let count = if digest.is_empty() {
+                    0.0
+                } else if mass.fract() == 0.0 {
+                    (digest.estimate_rank(le) * mass).round()
+                } else {
+                    digest.estimate_rank(le) * mass
+                };
+                write_sample(
+                    &mut out,
+                    &bucket_name,
+                    labels,
+                    Some(("le", le)),
+                    count,
+                );
+            }
+            write_sample(
+                &mut out,
+                &bucket_name,
+                labels,
+                Some(("le", f64::INFINITY)),
+                mass,
+            );
+        }
+    }
+
+    write_sample(&mut out, &format!("{name}_sum"), labels, None, digest.sum());
+    write_sample(&mut out, &format!("{name}_count"), labels, None, mass);
+    out
+}
@@ -0,0 +1,2629 @@
+//! Python bindings, compiled with the `python` feature
+//!
+//! The `TDigest` class wraps the Rust digest with a buffer for single
+//! updates, and the module definition registers the classes of the other
+//! modules.
+
+use crate::aio::AsyncUpdate;
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, InvalidValueError,
+    SerializationError,
+};
+use crate::evt::{GpdTail, Tail};
+use crate::ingest::{CsvColumn, FileDtype, IngestError};
+use crate::input::FloatValues;
+use crate::otel::{
+    Buckets, ExponentialHistogram, OTEL_MAX_SIZE_DEFAULT, OTEL_SCALE_MAX,
+    OTEL_SCALE_MIN,
+};
+use crate::prometheus::{
+    is_valid_label_name, is_valid_metric_name, MetricKind,
+    PROM_QUANTILES_DEFAULT,
+};
+use crate::quantile_summaries::QuantileSummaries;
+use crate::stream::{FileReader, FileWriter};
+use crate::tdigest::{
+    thread_limit, BytesError, Centroid, PackedCentroids, Resolution, TDigest,
+    TailSample, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::temporal::{Quantity, TimeUnit};
+use crate::tree_digest::TreeDigest;
+use crate::{
+    binned_digest, bloom, compact, concurrent, countmin, dataframe_digest,
+    ddsketch, decaying, delta, digest_array, digest_file, digest_map, discrete,
+    errors, event_time, exphist, gk, hdr, hll, ingest, input, integrations,
+    kll, mapped, moments, p2, prometheus, registry, req, reservoir, rolling,
+    temporal, theta, timeseries, topk, uddsketch, wal,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{
+    PyIndexError, PyMemoryError, PyTypeError, PyValueError,
+};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PySlice, PyString, PyTuple};
+use std::collections::TryReserveError;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::{mem, ptr, slice};
+
+pub(crate) const CACHE_SIZE: usize = 256;
+
+/// Precision in which the centroids are kept between operations.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Storage {
+    F64,
+    F32,
+}
+
+impl Storage {
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "f64" => Ok(Self::F64),
+            "f32" => Ok(Self::F32),
+            _ => Err(PyValueError::new_err("storage must be 'f64' or 'f32'.")),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::F64 => "f64",
+            Self::F32 => "f32",
+        }
+    }
+}
+
+/// How `merge_values` sorts a batch of values: with up to `n_threads`
+/// threads, not at all if `presorted` (which is checked), and if `chunked`,
+/// in chunks that are clustered into digests in parallel as well.
+#[derive(Clone, Copy)]
+pub(crate) struct Sorting {
+    pub n_threads: usize,
+    pub presorted: bool,
+    pub chunked: bool,
+}
+
+impl Sorting {
+    /// Sorting on the caller's thread
+    pub(crate) const SERIAL: Self = Self {
+        n_threads: 1,
+        presorted: false,
+        chunked: false,
+    };
+}
+
+#[derive(Clone)]
+pub(crate) struct TDigestState {
+    pub(crate) digest: TDigest,
+    telemetry: Telemetry,
+    /// Insert buffer of single updates, sorted in place when it is flushed
+    x_cache: [OrderedFloat<f64>; CACHE_SIZE],
+    w_cache: [f64; CACHE_SIZE],
+    w_cache_set: bool,
+    pub(crate) i: usize,
+    storage: Storage,
+    /// With f32 storage: the centroids while the state is not locked
+    packed: Option<PackedCentroids>,
+    /// dtype of the datetime64 or timedelta64 values fed to the digest
+    time_unit: Option<TimeUnit>,
+    /// With the tree backend: the single updates since the last flush
+    tree: Option<TreeDigest>,
+}
+
+/// Counters of the compressions and merges performed on a digest
+#[derive(Clone, Copy, Default)]
+struct Telemetry {
+    compressions: u64,
+    merges: u64,
+    /// Centroids (plus incoming values) before / after the last compression
+    last_compression_ratio: Option<f64>,
+}
+
+impl TDigestState {
+    /// State holding `digest`, with an empty cache.
+    pub(crate) fn with_digest(digest: TDigest) -> Self {
+        Self {
+            digest,
+            ..Self::default()
+        }
+    }
+
+    /// Number of single updates that are not merged yet.
+    pub(crate) fn n_buffered(&self) -> u128 {
+        self.i as u128 + self.tree.as_ref().map_or(0, TreeDigest::count)
+    }
+
+    /// Records a compression that started from `n_before` centroids and
+    /// incoming values or centroids.
+    fn record_compression(&mut self, n_before: usize) {
+        let n_after = self.digest.centroids().len().max(1);
+        self.telemetry.compressions += 1;
+        self.telemetry.last_compression_ratio =
+            Some(n_before as f64 / n_after as f64);
+    }
+}
+
+/// Locked state. With f32 storage, the centroids are unpacked for the
+/// duration of the lock and packed again when it is released.
+pub(crate) struct StateGuard<'a>(MutexGuard<'a, TDigestState>);
+
+impl Deref for StateGuard<'_> {
+    type Target = TDigestState;
+
+    fn deref(&self) -> &TDigestState {
+        &self.0
+    }
+}
+
+impl DerefMut for StateGuard<'_> {
+    fn deref_mut(&mut self) -> &mut TDigestState {
+        &mut self.0
+    }
+}
+
+impl Drop for StateGuard<'_> {
+    fn drop(&mut self) {
+        let state = &mut *self.0;
+        if state.storage == Storage::F32 && state.packed.is_none() {
+            // if the allocation fails, the centroids just stay unpacked
+            state.packed = state.digest.pack_centroids().ok();
+        }
+    }
+}
+
+impl Default for TDigestState {
+    fn default() -> Self {
+        let digest: TDigest = TDigest::new_with_size(TD_SIZE_DEFAULT)
+            .expect("default max size should be allocatable");
+        Self {
+            digest,
+            telemetry: Telemetry::default(),
+            x_cache: [OrderedFloat(0.0); CACHE_SIZE],
+            w_cache: [1.0; CACHE_SIZE],
+            w_cache_set: false,
+            i: 0,
+            storage: Storage::F64,
+            packed: None,
+            time_unit: None,
+            tree: None,
+        }
+    }
+}
+
+#[pyclass(name = "TDigest", module = "fastdigest")]
+pub struct PyTDigest {
+    pub(crate) state: Mutex<TDigestState>,
+}
+
+impl Clone for PyTDigest {
+    fn clone(&self) -> Self {
+        let state = self.state.lock().clone();
+        Self {
+            state: Mutex::new(state),
+        }
+    }
+}
+
+#[pymethods]
+impl PyTDigest {
+    /// Constructs a new empty TDigest instance.
+    #[new]
+    #[pyo3(signature = (max_centroids=TD_SIZE_DEFAULT as i64, storage="f64", tail_singletons=0, resolution="both", exact_tails=0, backend="merging"))]
+    pub fn py_new(
+        max_centroids: i64,
+        storage: &str,
+        tail_singletons: i64,
+        resolution: &str,
+        exact_tails: i64,
+        backend: &str,
+    ) -> PyResult<Self> {
+        let digest = Self::new(max_centroids)?;
+        digest.set_storage(storage)?;
+        digest.set_tail_singletons(tail_singletons)?;
+        digest.set_resolution(resolution)?;
+        let k = usize::try_from(exact_tails).map_err(|_| {
+            PyValueError::new_err("exact_tails must be a non-negative integer.")
+        })?;
+        if k > 0 {
+            lock_state(&digest)?
+                .digest
+                .set_tails(Some(TailSample::new(k)));
+        }
+        digest.set_backend(backend)?;
+        Ok(digest)
+    }
+
+    /// Constructs a new TDigest from a sequence of float values.
+    #[staticmethod]
+    #[pyo3(signature = (x, w=None, max_centroids=TD_SIZE_DEFAULT as i64, n_threads=Some(1), presorted=false, chunked=false))]
+    pub fn from_values(
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        max_centroids: i64,
+        n_threads: Option<i64>,
+        presorted: bool,
+        chunked: bool,
+    ) -> PyResult<Self> {
+        let n_threads = validate_n_threads(n_threads)?;
+        let digest = Self::new(max_centroids)?;
+        let sorting = Sorting {
+            n_threads,
+            presorted,
+            chunked,
+        };
+        digest.merge_input(x, w, sorting)?;
+        Ok(digest)
+    }
+
+    /// Constructs a new TDigest from a pandas Series, skipping NaN/NA values.
+    #[staticmethod]
+    #[pyo3(signature = (s, w=None, max_centroids=TD_SIZE_DEFAULT as i64, n_threads=Some(1)))]
+    pub fn from_series(
+        s: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        max_centroids: i64,
+        n_threads: Option<i64>,
+    ) -> PyResult<Self> {
+        let n_threads = validate_n_threads(n_threads)?;
+        let digest = Self::new(max_centroids)?;
+        let (values, time_unit) = match temporal::extract(s)? {
+            Some((values, unit)) => (values, Some(unit)),
+            None => (FloatValues::from_series(s)?, None),
+        };
+        let sorting = Sorting {
+            n_threads,
+            ..Sorting::SERIAL
+        };
+        digest.merge_values(s.py(), &values, w, true, sorting)?;
+        lock_state(&digest)?.time_unit = time_unit;
+        Ok(digest)
+    }
+
+    /// Constructs a new TDigest from a raw binary file of numbers, reading
+    /// it in chunks of `chunk` values.
+    #[staticmethod]
+    #[pyo3(signature = (path, dtype="f8", chunk=1 << 20, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn ingest_file(
+        py: Python<'_>,
+        path: PathBuf,
+        dtype: &str,
+        chunk: i64,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        let dtype = FileDtype::parse(dtype)?;
+        let chunk = validate_chunk(chunk)?;
+        let digest = Self::new(max_centroids)?;
+        py.detach(|| {
+            let mut state = lock_and_flush(&digest)?;
+            let file = File::open(&path)?;
+            ingest::read_binary(file, dtype, chunk, |values| {
+                merge_chunk(&mut state, values)
+            })
+        })?;
+        Ok(digest)
+    }
+
+    /// Constructs a new TDigest from a numeric column of a CSV file with a
+    /// header row, selected by name or by index.
+    #[staticmethod]
+    #[pyo3(signature = (path, column, delimiter=",", chunk=1 << 20, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn from_csv(
+        py: Python<'_>,
+        path: PathBuf,
+        column: &Bound<'_, PyAny>,
+        delimiter: &str,
+        chunk: i64,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        let column = if let Ok(name) = column.cast::<PyString>() {
+            CsvColumn::Name(name.to_str()?.to_owned())
+        } else {
+            CsvColumn::Index(column.extract().map_err(|_| {
+                PyTypeError::new_err(
+                    "column must be a column name or a non-negative index.",
+                )
+            })?)
+        };
+        let delimiter = match delimiter.as_bytes() {
+            &[b] if b.is_ascii() && !matches!(b, b'"' | b'\n' | b'\r') => b,
+            _ => {
+                return Err(PyValueError::new_err(
+                    "delimiter must be a single ASCII character.",
+                ))
+            }
+        };
+        let chunk = validate_chunk(chunk)?;
+        let digest = Self::new(max_centroids)?;
+        py.detach(|| {
+            let mut state = lock_and_flush(&digest)?;
+            let reader = BufReader::new(File::open(&path)?);
+            ingest::read_csv(reader, &column, delimiter, chunk, |values| {
+                merge_chunk(&mut state, values)
+            })
+        })?;
+        Ok(digest)
+    }
+
+    /// Constructs a new TDigest from a numeric column of a Parquet file,
+    /// reading it one row group at a time with pyarrow.
+    #[staticmethod]
+    #[pyo3(signature = (path, column, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn from_parquet(
+        py: Python<'_>,
+        path: &Bound<'_, PyAny>,
+        column: &str,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        let digest = Self::new(max_centroids)?;
+        let file = py
+            .import("pyarrow.parquet")?
+            .call_method1("ParquetFile", (path,))?;
+        let n_row_groups: usize = file.getattr("num_row_groups")?.extract()?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("columns", [column])?;
+        for i in 0..n_row_groups {
+            let col = file
+                .call_method("read_row_group", (i,), Some(&kwargs))?
+                .call_method1("column", (column,))?;
+            let values = FloatValues::from_series(&col)?;
+            digest.merge_values(py, &values, None, true, Sorting::SERIAL)?;
+        }
+        Ok(digest)
+    }
+
+    /// Reconstructs a TDigest from its binary representation. With
+    /// `strict`, inconsistent centroids or totals are rejected.
+    #[staticmethod]
+    #[pyo3(signature = (data, strict=false))]
+    pub fn from_bytes(data: &[u8], strict: bool) -> PyResult<Self> {
+        let digest = if compact::is_compact(data) {
+            compact::decode(data)
+        } else {
+            TDigest::from_bytes(data)
+        }
+        .map_err(bytes_error)?;
+        if strict {
+            digest.validate().map_err(SerializationError::new_err)?;
+        }
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Approximates a TDigest from the serialized state of Spark's
+    /// `approx_percentile` (QuantileSummaries).
+    #[staticmethod]
+    #[pyo3(signature = (data, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn from_spark_quantile_summaries(
+        data: &[u8],
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        let max_cent_valid = validate_max_centroids(max_centroids)?;
+        let summaries =
+            QuantileSummaries::from_bytes(data).map_err(bytes_error)?;
+        let digest =
+            summaries.to_digest(max_cent_valid).map_err(malloc_error)?;
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Reconstructs a TDigest from a dict. With `strict`, inconsistent
+    /// centroids or totals are rejected.
+    #[staticmethod]
+    #[pyo3(signature = (tdigest_dict, strict=false))]
+    pub fn from_dict(
+        tdigest_dict: &Bound<'_, PyDict>,
+        strict: bool,
+    ) -> PyResult<Self> {
+        let centroids_obj =
+            tdigest_dict.get_item("centroids")?.ok_or_else(|| {
+                SerializationError::new_err(
+                    "Key 'centroids' not found in dict.",
+                )
+            })?;
+        let centroids_list = centroids_obj.cast::<PyList>()?;
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids
+            .try_reserve_exact(centroids_list.len())
+            .map_err(malloc_error)?;
+        let mut sum = 0.0;
+        let mut mass = 0.0;
+        let mut min = f64::NAN;
+        let mut max = f64::NAN;
+
+        for item in centroids_list.iter() {
+            let d = item.cast::<PyDict>()?;
+            let mean: f64 = d
+                .get_item("m")?
+                .ok_or_else(|| {
+                    SerializationError::new_err("Centroid missing 'm' key.")
+                })?
+                .extract()?;
+            let weight: f64 = d
+                .get_item("c")?
+                .ok_or_else(|| {
+                    SerializationError::new_err("Centroid missing 'c' key.")
+                })?
+                .extract()?;
+            centroids.push(Centroid::new(mean, weight));
+            sum += mean * weight;
+            mass += weight;
+            min = min.min(mean);
+            max = max.max(mean);
+        }
+
+        let max_centroids: usize =
+            match tdigest_dict.get_item("max_centroids")? {
+                Some(obj) => validate_max_centroids(obj.extract::<i64>()?)?,
+                _ => TD_SIZE_DEFAULT,
+            };
+        let mass: f64 = match tdigest_dict.get_item("mass")? {
+            Some(obj) => obj.extract()?,
+            _ => mass,
+        };
+        let sum: f64 = match tdigest_dict.get_item("sum")? {
+            Some(obj) => obj.extract()?,
+            _ => sum,
+        };
+        let min: f64 = match tdigest_dict.get_item("min")? {
+            Some(obj) => obj.extract()?,
+            _ => min,
+        };
+        let max: f64 = match tdigest_dict.get_item("max")? {
+            Some(obj) => obj.extract()?,
+            _ => max,
+        };
+        let n_values: u128 = match tdigest_dict.get_item("n_values")? {
+            Some(obj) => obj.extract()?,
+            _ => (mass.round() as u128).max(1),
+        };
+
+        if strict {
+            TDigest::validate_centroids(&centroids)
+                .map_err(SerializationError::new_err)?;
+        }
+        let digest = if !centroids.is_empty() {
+            TDigest::new(
+                centroids,
+                max_centroids,
+                mass,
+                sum,
+                min,
+                max,
+                n_values,
+            )
+            .map_err(malloc_error)?
+        } else {
+            TDigest::new_with_size(max_centroids).map_err(malloc_error)?
+        };
+        if strict {
+            digest.validate().map_err(SerializationError::new_err)?;
+        }
+        let time_unit = match tdigest_dict.get_item("dtype")? {
+            Some(obj) if !obj.is_none() => {
+                Some(TimeUnit::from_name(obj.extract()?)?)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                time_unit,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Reconstructs an approximate TDigest from an OpenTelemetry
+    /// exponential histogram data point given as a dict.
+    #[staticmethod]
+    #[pyo3(signature = (data, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn from_otel_exponential_histogram(
+        data: &Bound<'_, PyDict>,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        fn extract_buckets(
+            data: &Bound<'_, PyDict>,
+            key: &str,
+        ) -> PyResult<Buckets> {
+            let Some(obj) = data.get_item(key)? else {
+                return Ok(Buckets::default());
+            };
+            let d = obj.cast::<PyDict>()?;
+            let offset: i64 = match d.get_item("offset")? {
+                Some(obj) => obj.extract()?,
+                _ => 0,
+            };
+            let bucket_counts: Vec<u64> = d
+                .get_item("bucket_counts")?
+                .ok_or_else(|| {
+                    SerializationError::new_err(format!(
+                        "Key 'bucket_counts' not found in '{key}'."
+                    ))
+                })?
+                .extract()?;
+            Ok(Buckets {
+                offset,
+                bucket_counts,
+            })
+        }
+
+        let max_cent_valid = validate_max_centroids(max_centroids)?;
+        let scale: i32 = data
+            .get_item("scale")?
+            .ok_or_else(|| {
+                SerializationError::new_err("Key 'scale' not found in dict.")
+            })?
+            .extract()?;
+        validate_otel_scale(scale)?;
+        let get_f64 = |key: &str| -> PyResult<f64> {
+            match data.get_item(key)? {
+                Some(obj) if !obj.is_none() => obj.extract(),
+                _ => Ok(f64::NAN),
+            }
+        };
+        let mut histogram = ExponentialHistogram {
+            scale,
+            count: 0,
+            sum: get_f64("sum")?,
+            min: get_f64("min")?,
+            max: get_f64("max")?,
+            zero_count: match data.get_item("zero_count")? {
+                Some(obj) => obj.extract()?,
+                _ => 0,
+            },
+            zero_threshold: match data.get_item("zero_threshold")? {
+                Some(obj) => obj.extract()?,
+                _ => 0.0,
+            },
+            positive: extract_buckets(data, "positive")?,
+            negative: extract_buckets(data, "negative")?,
+        };
+        histogram.count = match data.get_item("count")? {
+            Some(obj) => obj.extract()?,
+            _ => histogram.bucket_total().unwrap_or(u64::MAX),
+        };
+        histogram.validate().map_err(SerializationError::new_err)?;
+
+        let digest =
+            histogram.to_digest(max_cent_valid).map_err(malloc_error)?;
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> PyResult<usize> {
+        Ok(lock_state(self)?.digest.max_size())
+    }
+
+    /// Setter property: sets the max_centroids parameter, recompressing
+    /// the digest if it has more centroids.
+    #[setter(max_centroids)]
+    pub fn set_max_centroids(&self, max_centroids: i64) -> PyResult<()> {
+        let max_cent_valid = validate_max_centroids(max_centroids)?;
+        let mut state = lock_and_flush(self)?;
+        let n_before = state.digest.centroids().len();
+        let merged = state
+            .digest
+            .recompress(max_cent_valid)
+            .map_err(malloc_error)?;
+        if merged {
+            state.record_compression(n_before);
+        }
+        if state.tree.is_some() {
+            state.tree = Some(TreeDigest::for_digest(&state.digest));
+        }
+        Ok(())
+    }
+
+    /// Getter property: returns the precision of the centroid storage.
+    #[getter(storage)]
+    pub fn get_storage(&self) -> &'static str {
+        self.state.lock().storage.name()
+    }
+
+    /// Setter property: sets the precision of the centroid storage.
+    #[setter(storage)]
+    pub fn set_storage(&self, storage: &str) -> PyResult<()> {
+        let storage = Storage::from_name(storage)?;
+        // the centroids are packed (if f32) when the lock is released
+        lock_state(self)?.storage = storage;
+        Ok(())
+    }
+
+    /// Getter property: returns the structure that single updates are added
+    /// to.
+    #[getter(backend)]
+    pub fn get_backend(&self) -> &'static str {
+        match self.state.lock().tree {
+            Some(_) => "tree",
+            None => "merging",
+        }
+    }
+
+    /// Getter property: returns the number of centroids at either end that
+    /// are never merged.
+    #[getter(tail_singletons)]
+    pub fn get_tail_singletons(&self) -> PyResult<usize> {
+        Ok(lock_state(self)?.digest.tail_size())
+    }
+
+    /// Setter property: sets the number of centroids at either end that are
+    /// never merged.
+    #[setter(tail_singletons)]
+    pub fn set_tail_singletons(&self, tail_singletons: i64) -> PyResult<()> {
+        let tail_size = usize::try_from(tail_singletons).map_err(|_| {
+            PyValueError::new_err(
+                "tail_singletons must be a non-negative integer.",
+            )
+        })?;
+        lock_state(self)?.digest.set_tail_size(tail_size);
+        Ok(())
+    }
+
+    /// Getter property: returns the part of the distribution that is
+    /// resolved most finely.
+    #[getter(resolution)]
+    pub fn get_resolution(&self) -> PyResult<&'static str> {
+        Ok(lock_state(self)?.digest.resolution().name())
+    }
+
+    /// Setter property: sets the part of the distribution that is resolved
+    /// most finely.
+    #[setter(resolution)]
+    pub fn set_resolution(&self, resolution: &str) -> PyResult<()> {
+        let resolution =
+            Resolution::from_name(resolution).ok_or_else(|| {
+                PyValueError::new_err(
+                    "resolution must be 'both', 'upper' or 'lower'.",
+                )
+            })?;
+        lock_state(self)?.digest.set_resolution(resolution);
+        Ok(())
+    }
+
+    /// Getter property: returns the number of lowest and highest values
+    /// that are stored exactly.
+    #[getter(exact_tails)]
+    pub fn get_exact_tails(&self) -> PyResult<usize> {
+        Ok(lock_state(self)?.digest.tails().map_or(0, TailSample::k))
+    }
+
+    /// Getter property: returns the dtype of the datetime64 or timedelta64
+    /// values fed to the digest, in which its estimates are returned.
+    #[getter(dtype)]
+    pub fn get_dtype(&self) -> PyResult<Option<String>> {
+        Ok(lock_state(self)?.time_unit.map(TimeUnit::name))
+    }
+
+    /// Setter property: sets the dtype in which the estimates are returned
+    /// (None for floats).
+    #[setter(dtype)]
+    pub fn set_dtype(&self, dtype: Option<&str>) -> PyResult<()> {
+        let time_unit = dtype.map(TimeUnit::from_name).transpose()?;
+        lock_state(self)?.time_unit = time_unit;
+        Ok(())
+    }
+
+    /// Returns the number of bytes used by the digest: its buffers and
+    /// the centroid storage allocated on the heap.
+    pub fn memory_bytes(&self) -> usize {
+        mem::size_of::<TDigestState>() + self.heap_bytes()
+    }
+
+    /// Returns a dict of counters describing the compressions and merges
+    /// performed on the digest, without flushing its buffer.
+    pub fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let state = lock_state(self)?;
+        let stats = PyDict::new(py);
+        stats.set_item("compressions", state.telemetry.compressions)?;
+        stats.set_item("merges", state.telemetry.merges)?;
+        stats.set_item(
+            "last_compression_ratio",
+            state.telemetry.last_compression_ratio,
+        )?;
+        stats.set_item("values_buffered", state.n_buffered())?;
+        stats.set_item("values_merged", state.digest.count())?;
+        stats.set_item("n_centroids", state.digest.centroids().len())?;
+        stats.set_item("max_centroids", state.digest.max_size())?;
+        Ok(stats)
+    }
+
+    /// Magic method: sys.getsizeof(TDigest) includes the heap allocations.
+    pub fn __sizeof__(slf: &Bound<'_, Self>) -> PyResult<usize> {
+        let basic_size: usize =
+            slf.get_type().getattr("__basicsize__")?.extract()?;
+        Ok(basic_size + slf.borrow().heap_bytes())
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> PyResult<u128> {
+        let state = lock_state(self)?;
+        Ok(state.digest.count() + state.n_buffered())
+    }
+
+    /// Getter property: returns the number of centroids.
+    #[getter(n_centroids)]
+    pub fn get_n_centroids(&self) -> PyResult<usize> {
+        let state = lock_and_flush(self)?;
+        Ok(state.digest.centroids().len())
+    }
+
+    /// Getter property: returns the centroids as a list of tuples.
+    #[getter(centroids)]
+    pub fn get_centroids<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let state = lock_and_flush(self)?;
+        let centroid_list = PyList::empty(py);
+        for centroid in state.digest.centroids() {
+            let t = PyTuple::new(py, [centroid.mean(), centroid.weight()])?;
+            centroid_list.append(t)?;
+        }
+        Ok(centroid_list)
+    }
+
+    /// Returns the total weight.
+    pub fn mass(&self) -> PyResult<f64> {
+        let state = lock_state(self)?;
+        let w_cache_sum = if state.w_cache_set {
+            Vec::from(&state.w_cache[0..state.i]).iter().sum()
+        } else {
+            state.i as f64
+        };
+        let tree_mass = state.tree.as_ref().map_or(0.0, TreeDigest::mass);
+        Ok(state.digest.mass() + w_cache_sum + tree_mass)
+    }
+
+    /// Returns the sum of the data.
+    pub fn sum(&self) -> PyResult<f64> {
+        let state = lock_and_flush(self)?;
+        Ok(state.digest.sum())
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<Quantity> {
+        let state = lock_flush_check(self)?;
+        Ok(Quantity::new(state.digest.min(), state.time_unit))
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<Quantity> {
+        let state = lock_flush_check(self)?;
+        Ok(Quantity::new(state.digest.max(), state.time_unit))
+    }
+
+    /// Returns True if the digest is empty.
+    pub fn is_empty(&self) -> PyResult<bool> {
+        let state = lock_state(self)?;
+        Ok(state.digest.is_empty() && (state.n_buffered() == 0))
+    }
+
+    /// Multiplies the weights of all centroids by a factor in (0, 1]
+    /// (in-place), e.g. to let subsequent values weigh more. Centroids
+    /// whose weight falls below `min_weight` are dropped.
+    #[pyo3(signature = (factor, min_weight=None))]
+    pub fn scale_weights(
+        &self,
+        factor: f64,
+        min_weight: Option<f64>,
+    ) -> PyResult<()> {
+        if !(factor > 0.0 && factor <= 1.0) {
+            return Err(PyValueError::new_err(
+                "factor must be greater than 0 and at most 1.",
+            ));
+        }
+        let min_weight = min_weight.unwrap_or(0.0);
+        if !min_weight.is_finite() || min_weight < 0.0 {
+            return Err(PyValueError::new_err(
+                "min_weight must be finite and non-negative.",
+            ));
+        }
+        let mut state = lock_and_flush(self)?;
+        state.digest.scale_weights(factor, min_weight);
+        Ok(())
+    }
+
+    /// Merges this digest with another, returning a new TDigest. With a
+    /// `weight`, the weights of the other digest are scaled by it first.
+    #[pyo3(signature = (other, weight=None))]
+    pub fn merge(
+        &self,
+        py: Python<'_>,
+        other: &Self,
+        weight: Option<f64>,
+    ) -> PyResult<Self> {
+        let weight = validate_merge_weight(weight)?;
+        let merged = py.detach(|| {
+            let (first, second) = order_by_address(self, other);
+            let (mut digest1, unit1) = {
+                let state = lock_and_flush(first)?;
+                (state.digest.clone(), state.time_unit)
+            };
+            let (mut digest2, unit2) = {
+                let state = lock_and_flush(second)?;
+                (state.digest.clone(), state.time_unit)
+            };
+            let time_unit = TimeUnit::combine(unit1, unit2)?;
+            if let Some(weight) = weight {
+                let other_digest = if ptr::eq(first, other) {
+                    &mut digest1
+                } else {
+                    &mut digest2
+                };
+                other_digest.scale_weights(weight, 0.0);
+            }
+            let n_before =
+                digest1.centroids().len() + digest2.centroids().len();
+            // canonical order, so that a.merge(b) equals b.merge(a)
+            let mut digests: Vec<TDigest> = vec![digest1, digest2];
+            digests.sort_by(TDigest::canonical_cmp);
+            let merged =
+                TDigest::merge_digests(digests, None).map_err(malloc_error)?;
+            Ok::<_, PyErr>((merged, n_before, time_unit))
+        })?;
+        let (merged, n_before, time_unit) = merged;
+        let mut state = TDigestState {
+            digest: merged,
+            time_unit,
+            ..TDigestState::default()
+        };
+        state.telemetry.merges = 1;
+        state.record_compression(n_before);
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Merges this digest with another, modifying the current instance.
+    /// With a `weight`, the weights of the other digest are scaled by it
+    /// first.
+    #[pyo3(signature = (other, weight=None))]
+    pub fn merge_inplace(
+        &self,
+        py: Python<'_>,
+        other: &Self,
+        weight: Option<f64>,
+    ) -> PyResult<()> {
+        let weight = validate_merge_weight(weight)?;
+        py.detach(|| self.merge_inplace_detached(other, weight))
+    }
+
+    /// Merges many digests into a new instance in one operation.
+    #[staticmethod]
+    #[pyo3(signature = (digests, max_centroids=None, n_threads=Some(1), deterministic=false, streaming=false))]
+    pub fn merge_all(
+        digests: &Bound<'_, PyAny>,
+        max_centroids: Option<i64>,
+        n_threads: Option<i64>,
+        deterministic: bool,
+        streaming: bool,
+    ) -> PyResult<Self> {
+        merge_all(digests, max_centroids, n_threads, deterministic, streaming)
+    }
+
+    /// Approximately removes the values of another digest that were
+    /// merged into this one before, returning a new TDigest.
+    pub fn difference(&self, py: Python<'_>, other: &Self) -> PyResult<Self> {
+        let other_digest = lock_and_flush(other)?.digest.clone();
+        let mut digest = lock_and_flush(self)?.digest.clone();
+        check_removable(&digest, other_digest.mass())?;
+        py.detach(|| {
+            digest
+                .remove_weights(other_digest.centroids(), other_digest.count())
+        });
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Updates the digest (in-place) with a sequence of float values.
+    #[pyo3(signature = (x, w=None, n_threads=Some(1), presorted=false, chunked=false))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        n_threads: Option<i64>,
+        presorted: bool,
+        chunked: bool,
+    ) -> PyResult<()> {
+        let n_threads = validate_n_threads(n_threads)?;
+        let sorting = Sorting {
+            n_threads,
+            presorted,
+            chunked,
+        };
+        self.merge_input(x, w, sorting)?;
+        Ok(())
+    }
+
+    /// Updates the digest (in-place) with every array yielded by an iterable,
+    /// calling `callback(n_chunks, n_values)` every `every` arrays.
+    #[pyo3(signature = (chunks, callback=None, every=1))]
+    pub fn batch_update_chunks(
+        &self,
+        chunks: &Bound<'_, PyAny>,
+        callback: Option<Bound<'_, PyAny>>,
+        every: i64,
+    ) -> PyResult<()> {
+        let py = chunks.py();
+        let every = usize::try_from(every).ok().filter(|&n| n > 0).ok_or_else(
+            || PyValueError::new_err("every must be a positive integer."),
+        )?;
+        let (mut n_chunks, mut n_values) = (0_usize, 0_usize);
+        for chunk in chunks.try_iter()? {
+            n_values += self.merge_input(&chunk?, None, Sorting::SERIAL)?;
+            n_chunks += 1;
+            py.check_signals()?;
+            let Some(callback) = &callback else {
+                continue;
+            };
+            // returning False from the callback stops the ingestion
+            if n_chunks % every == 0
+                && matches!(
+                    callback.call1((n_chunks, n_values))?.extract(),
+                    Ok(false)
+                )
+            {
+                return Ok(());
+            }
+        }
+        if let Some(callback) = callback.filter(|_| n_chunks % every != 0) {
+            callback.call1((n_chunks, n_values))?;
+        }
+        Ok(())
+    }
+
+    /// Returns an awaitable that updates the digest (in-place) with every
+    /// value or array yielded by an async iterable.
+    pub fn aupdate(
+        slf: &Bound<'_, Self>,
+        iterable: &Bound<'_, PyAny>,
+    ) -> PyResult<AsyncUpdate> {
+        if !iterable.hasattr("__aiter__")? {
+            return Err(PyTypeError::new_err("Provide an async iterable."));
+        }
+        let iterator = iterable.call_method0("__aiter__")?;
+        Ok(AsyncUpdate::new(slf.clone().unbind(), iterator.unbind()))
+    }
+
+    /// Updates the digest (in-place) with a single float value.
+    #[inline]
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: &Bound<'_, PyAny>, w: Option<f64>) -> PyResult<()> {
+        self.update_value(input::extract_value(x)?, w)
+    }
+
+    /// Approximately removes a previously added value (in-place), taking
+    /// its weight from the centroids nearest to it.
+    #[pyo3(signature = (x, w=None))]
+    pub fn remove(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut state = lock_and_flush(self)?;
+        check_removable(&state.digest, weight)?;
+        let removed = [Centroid::new(x, weight)];
+        state.digest.remove_weights(&removed, 1);
+        Ok(())
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<Quantity> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        Ok(Quantity::new(
+            state.digest.estimate_quantile(q),
+            state.time_unit,
+        ))
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(
+        &self,
+        py: Python<'_>,
+        q: Vec<f64>,
+    ) -> PyResult<Vec<Quantity>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let (x, time_unit) = py.detach(|| {
+            let state = lock_flush_check(self)?;
+            let d = &state.digest;
+            let x = match q.len() {
+                0 => vec![],
+                1 | 2 => {
+                    q.iter().map(|&q_i| d.estimate_quantile(q_i)).collect()
+                }
+                _ => d.estimate_quantiles(&q).map_err(malloc_error)?,
+            };
+            Ok::<_, PyErr>((x, state.time_unit))
+        })?;
+        Ok(x.into_iter()
+            .map(|x_i| Quantity::new(x_i, time_unit))
+            .collect())
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<Quantity> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        let x = state.digest.estimate_quantile(0.01 * p);
+        Ok(Quantity::new(x, state.time_unit))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<Quantity> {
+        let state = lock_flush_check(self)?;
+        Ok(Quantity::new(
+            state.digest.estimate_quantile(0.5),
+            state.time_unit,
+        ))
+    }
+
+    /// Estimates the quantile for a cumulative probability `q` in one of
+    /// the tails, extrapolating beyond the observed range with a
+    /// generalized Pareto distribution fitted to the values beyond the
+    /// `threshold` quantile.
+    #[pyo3(signature = (q, method="gpd", threshold=0.95))]
+    pub fn tail_quantile(
+        &self,
+        q: f64,
+        method: &str,
+        threshold: f64,
+    ) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        if method != "gpd" {
+            return Err(PyValueError::new_err("method must be 'gpd'."));
+        }
+        if !(threshold > 0.5 && threshold < 1.0) {
+            return Err(PyValueError::new_err(
+                "threshold must be between 0.5 and 1 (exclusive).",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        let digest = &state.digest;
+        let tail = if q > 0.5 { Tail::Upper } else { Tail::Lower };
+        if (1.0 - threshold..=threshold).contains(&q) {
+            return Ok(digest.estimate_quantile(q));
+        }
+        let fit = GpdTail::fit(digest, tail, threshold).ok_or_else(|| {
+            PyValueError::new_err(
+                "Not enough data beyond the threshold to fit the tail.",
+            )
+        })?;
+        Ok(fit.quantile(q))
+    }
+
+    /// Estimates the inter-quartile range.
+    pub fn iqr(&self) -> PyResult<Quantity> {
+        let state = lock_flush_check(self)?;
+        let d = &state.digest;
+        let iqr = d.estimate_quantile(0.75) - d.estimate_quantile(0.25);
+        Ok(Quantity::new(iqr, state.time_unit.map(TimeUnit::duration)))
+    }
+
+    /// Estimates the rank (cumulative probability) of a given value `x`.
+    pub fn cdf(&self, x: &Bound<'_, PyAny>) -> PyResult<f64> {
+        self.rank(temporal::extract_scalar(x)?)
+    }
+
+    /// Estimates the ranks (cumulative probabilities) of given values `x`.
+    /// With `out` (a float64 array of the same length), they are written
+    /// into it instead of a new list, and it is returned.
+    #[pyo3(signature = (x, out=None, n_threads=Some(1)))]
+    pub fn cdf_vec<'py>(
+        &self,
+        x: &Bound<'py, PyAny>,
+        out: Option<Bound<'py, PyAny>>,
+        n_threads: Option<i64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = x.py();
+        let n_threads = validate_n_threads(n_threads)?;
+        // C-contiguous float64 arrays are read in place
+        let input = PyBuffer::<f64>::get(x)
+            .ok()
+            .filter(|buffer| buffer.is_c_contiguous());
+        let copy: Vec<f64>;
+        let xs: &[f64] = match &input {
+            Some(buffer) => buffer_slice(buffer),
+            None => {
+                copy = match temporal::extract(x)? {
+                    Some((values, _)) => values.iter(py).collect(),
+                    None => x.extract()?,
+                };
+                &copy
+            }
+        };
+        let Some(out) = out else {
+            let q = py.detach(|| {
+                let state = lock_flush_check(self)?;
+                let d = &state.digest;
+                let q = match xs.len() {
+                    0 => vec![],
+                    1 | 2 => {
+                        xs.iter().map(|&x_i| d.estimate_rank(x_i)).collect()
+                    }
+                    _ => {
+                        d.estimate_ranks(xs, n_threads).map_err(malloc_error)?
+                    }
+                };
+                Ok::<_, PyErr>(q)
+            })?;
+            return Ok(q.into_pyobject(py)?.into_any());
+        };
+
+        if xs.is_empty() && out.len().is_ok_and(|len| len == 0) {
+            // empty buffers may not have a valid address
+            lock_flush_check(self)?;
+            return Ok(out);
+        }
+        let output = output_buffer(&out, xs.len())?;
+        let overlap = {
+            let x_range = xs.as_ptr_range();
+            let out_ptr = output.buf_ptr().cast::<f64>().cast_const();
+            let out_range = out_ptr..out_ptr.wrapping_add(xs.len());
+            x_range.start < out_range.end && out_range.start < x_range.end
+        };
+        let copy: Vec<f64>;
+        let xs = if overlap {
+            copy = xs.to_vec();
+            &copy
+        } else {
+            xs
+        };
+        let q: &mut [f64] = if xs.is_empty() {
+            &mut []
+        } else {
+            // SAFETY: the buffer is writable, aligned, of the same length as
+            // `xs` and disjoint from it, and stays exported until `output`
+            // is dropped
+            unsafe {
+                slice::from_raw_parts_mut(
+                    output.buf_ptr().cast::<f64>(),
+                    xs.len(),
+                )
+            }
+        };
+        py.detach(|| {
+            let state = lock_flush_check(self)?;
+            state
+                .digest
+                .estimate_ranks_into(xs, q, n_threads)
+                .map_err(malloc_error)
+        })?;
+        Ok(out)
+    }
+
+    /// Estimates the empirical probability of a value being in
+    /// the interval \[`x1`, `x2`\].
+    pub fn probability(&self, x1: f64, x2: f64) -> PyResult<f64> {
+        if x1 > x2 {
+            return Err(PyValueError::new_err(
+                "x1 must be less than or equal to x2.",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        let d = &state.digest;
+        Ok(d.estimate_rank(x2) - d.estimate_rank(x1))
+    }
+
+    /// Returns the mean of the data.
+    pub fn mean(&self) -> PyResult<Quantity> {
+        let state = lock_flush_check(self)?;
+        Ok(Quantity::new(state.digest.mean(), state.time_unit))
+    }
+
+    /// Returns the trimmed mean of the data between the q1 and q2 quantiles.
+    pub fn trimmed_mean(&self, q1: f64, q2: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q1) || !(0.0..=1.0).contains(&q2) || q1 >= q2
+        {
+            return Err(InvalidQuantileError::new_err(
+                "q1 must be >= 0, q2 must be <= 1, and q1 < q2.",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.estimate_trimmed_mean(q1, q2))
+    }
+
+    /// Estimates the median absolute deviation.
+    pub fn mad(&self) -> PyResult<f64> {
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.estimate_mad())
+    }
+
+    /// Estimates the variance.
+    pub fn var(&self) -> PyResult<f64> {
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.estimate_var())
+    }
+
+    /// Estimates the standard deviation.
+    pub fn std(&self) -> PyResult<f64> {
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.estimate_var().sqrt())
+    }
+
+    /// Performs a KS test to determine normality.
+    #[pyo3(signature = (alpha=0.05))]
+    pub fn is_normal(&self, alpha: f64) -> PyResult<bool> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err(PyValueError::new_err(
+                "alpha must be strictly greater than 0 and less than 1.",
+            ));
+        }
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.test_cdf_is_normal(alpha))
+    }
+
+    /// Returns a binary representation of the digest. The "compact" mode
+    /// quantizes the means to f32 for a smaller payload.
+    #[pyo3(signature = (mode="exact"))]
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+        mode: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let state = lock_and_flush(self)?;
+        let bytes = match mode {
+            "exact" => state.digest.to_bytes().map_err(malloc_error)?,
+            "compact" => compact::encode(&state.digest),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "mode must be 'exact' or 'compact'.",
+                ))
+            }
+        };
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Writes the binary representation of the digest (like `to_bytes`) to
+    /// a binary file object or socket.
+    pub fn write_to(&self, file: &Bound<'_, PyAny>) -> PyResult<()> {
+        let writer = FileWriter::new(file)?;
+        let bytes = lock_and_flush(self)?
+            .digest
+            .to_bytes()
+            .map_err(malloc_error)?;
+        writer.write(&bytes)
+    }
+
+    /// Reads a TDigest written by `write_to` (or `to_bytes`) from a binary
+    /// file object or socket, taking only its bytes from the stream.
+    #[staticmethod]
+    #[pyo3(signature = (file, strict=false))]
+    pub fn read_from(file: &Bound<'_, PyAny>, strict: bool) -> PyResult<Self> {
+        let digest = FileReader::new(file)?.digest()?;
+        if strict {
+            digest.validate().map_err(SerializationError::new_err)?;
+        }
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Returns a compact binary delta from the state of `snapshot` (an
+    /// earlier copy of this digest) to the current state, which
+    /// `apply_delta` applies to another copy of `snapshot`.
+    pub fn delta_since<'py>(
+        &self,
+        py: Python<'py>,
+        snapshot: &Self,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let delta = py.detach(|| {
+            let base = lock_and_flush(snapshot)?.digest.clone();
+            let state = lock_and_flush(self)?;
+            delta::encode(&base, &state.digest).map_err(malloc_error)
+        })?;
+        Ok(PyBytes::new(py, &delta))
+    }
+
+    /// Updates the digest (in-place) to the state encoded by a delta from
+    /// `delta_since`, which must have been computed from the current state.
+    pub fn apply_delta(&self, py: Python<'_>, delta: &[u8]) -> PyResult<()> {
+        let base = delta::base_fingerprint(delta).map_err(bytes_error)?;
+        py.detach(|| {
+            let mut state = lock_and_flush(self)?;
+            if base
+                != delta::fingerprint(&state.digest).map_err(malloc_error)?
+            {
+                return Err(SerializationError::new_err(
+                    "Delta was computed from a different state of the \
+                     TDigest.",
+                ));
+            }
+            state.digest =
+                delta::apply(&state.digest, delta).map_err(bytes_error)?;
+            Ok(())
+        })
+    }
+
+    /// Returns a dict representation of the digest.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let state = lock_and_flush(self)?;
+        let dict = PyDict::new(py);
+
+        dict.set_item("max_centroids", state.digest.max_size())?;
+        dict.set_item("mass", state.digest.mass())?;
+        dict.set_item("sum", state.digest.sum())?;
+        dict.set_item("min", state.digest.min())?;
+        dict.set_item("max", state.digest.max())?;
+        dict.set_item("n_values", state.digest.count())?;
+        if let Some(time_unit) = state.time_unit {
+            dict.set_item("dtype", time_unit.name())?;
+        }
+
+        let centroid_list = PyList::empty(py);
+        for centroid in state.digest.centroids() {
+            let centroid_dict = PyDict::new(py);
+            centroid_dict.set_item("m", centroid.mean())?;
+            centroid_dict.set_item("c", centroid.weight())?;
+            centroid_list.append(centroid_dict)?;
+        }
+        dict.set_item("centroids", centroid_list)?;
+        Ok(dict)
+    }
+
+    /// Returns the centroid means and weights as two NumPy arrays.
+    pub fn to_numpy<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let state = lock_and_flush(self)?;
+        let centroids = state.digest.centroids();
+        let mut means: Vec<f64> = Vec::new();
+        let mut weights: Vec<f64> = Vec::new();
+        means
+            .try_reserve_exact(centroids.len())
+            .map_err(malloc_error)?;
+        weights
+            .try_reserve_exact(centroids.len())
+            .map_err(malloc_error)?;
+        for c in centroids {
+            means.push(c.mean());
+            weights.push(c.weight());
+        }
+        drop(state);
+
+        PyTuple::new(py, [f64_array(py, &means)?, f64_array(py, &weights)?])
+    }
+
+    /// Returns an OpenTelemetry exponential histogram data point as a dict.
+    #[pyo3(signature = (scale, max_size=OTEL_MAX_SIZE_DEFAULT, zero_threshold=0.0))]
+    pub fn to_otel_exponential_histogram<'py>(
+        &self,
+        py: Python<'py>,
+        scale: i32,
+        max_size: usize,
+        zero_threshold: f64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        validate_otel_scale(scale)?;
+        if max_size == 0 {
+            return Err(PyValueError::new_err(
+                "max_size must be greater than 0.",
+            ));
+        }
+        if !zero_threshold.is_finite() || zero_threshold < 0.0 {
+            return Err(PyValueError::new_err(
+                "zero_threshold must be finite and non-negative.",
+            ));
+        }
+        let state = lock_and_flush(self)?;
+        let histogram = ExponentialHistogram::from_digest(
+            &state.digest,
+            scale,
+            max_size,
+            zero_threshold,
+        )
+        .map_err(malloc_error)?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("scale", histogram.scale)?;
+        dict.set_item("count", histogram.count)?;
+        dict.set_item("sum", histogram.sum)?;
+        dict.set_item("min", histogram.min)?;
+        dict.set_item("max", histogram.max)?;
+        dict.set_item("zero_count", histogram.zero_count)?;
+        dict.set_item("zero_threshold", histogram.zero_threshold)?;
+        for (key, buckets) in [
+            ("positive", &histogram.positive),
+            ("negative", &histogram.negative),
+        ] {
+            let buckets_dict = PyDict::new(py);
+            buckets_dict.set_item("offset", buckets.offset)?;
+            buckets_dict.set_item("bucket_counts", &buckets.bucket_counts)?;
+            dict.set_item(key, buckets_dict)?;
+        }
+        Ok(dict)
+    }
+
+    /// Returns the digest rendered in the Prometheus text exposition format.
+    #[pyo3(signature = (
+        name,
+        kind="summary",
+        quantiles=None,
+        buckets=None,
+        labels=None,
+        help=None,
+    ))]
+    pub fn to_prometheus(
+        &self,
+        name: &str,
+        kind: &str,
+        quantiles: Option<Vec<f64>>,
+        buckets: Option<Vec<f64>>,
+        labels: Option<Bound<'_, PyDict>>,
+        help: Option<&str>,
+    ) -> PyResult<String> {
+        if !is_valid_metric_name(name) {
+            return Err(PyValueError::new_err(format!(
+                "Invalid Prometheus metric name: '{name}'."
+            )));
+        }
+        let quantiles_given = quantiles.is_some();
+        let quantiles = quantiles.unwrap_or(PROM_QUANTILES_DEFAULT.to_vec());
+        let (metric_kind, reserved_label) = match kind {
+            "summary" => {
+                if buckets.is_some() {
+                    return Err(PyValueError::new_err(
+                        "buckets can only be used with kind='histogram'.",
+                    ));
+                }
+                if quantiles.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+                    return Err(InvalidQuantileError::new_err(
+                        "All q values must be between 0 and 1.",
+                    ));
+                }
+                let kind = MetricKind::Summary {
+                    quantiles: &quantiles,
+                };
+                (kind, "quantile")
+            }
+            "histogram" => {
+                if quantiles_given {
+                    return Err(PyValueError::new_err(
+                        "quantiles can only be used with kind='summary'.",
+                    ));
+                }
+                let Some(buckets) = &buckets else {
+                    return Err(PyValueError::new_err(
+                        "kind='histogram' requires buckets.",
+                    ));
+                };
+                if buckets.iter().any(|b| b.is_nan())
+                    || buckets.windows(2).any(|w| w[0] >= w[1])
+                {
+                    return Err(PyValueError::new_err(
+                        "buckets must be sorted in strictly increasing order.",
+                    ));
+                }
+                (MetricKind::Histogram { buckets }, "le")
+            }
+            _ => {
+                return Err(PyValueError::new_err(
+                    "kind must be 'summary' or 'histogram'.",
+                ))
+            }
+        };
+
+        let mut label_pairs: Vec<(String, String)> = Vec::new();
+        if let Some(labels) = labels {
+            for (k, v) in labels.iter() {
+                let k: String = k.extract()?;
+                if !is_valid_label_name(&k) {
+                    return Err(PyValueError::new_err(format!(
+                        "Invalid Prometheus label name: '{k}'."
+                    )));
+                }
+                if k == reserved_label {
+                    return Err(PyValueError::new_err(format!(
+                        "The label name '{k}' is reserved for kind='{kind}'."
+                    )));
+                }
+                label_pairs.push((k, v.str()?.to_string()));
+            }
+        }
+
+        let state = lock_and_flush(self)?;
+        Ok(prometheus::render(
+            &state.digest,
+            name,
+            metric_kind,
+            &label_pairs,
+            help,
+        ))
+    }
+
+    /// Returns true if two digests are equal. Caches are flushed
+    /// to ensure accurate results across disparate states.
+    pub fn equals(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+
+        fn summary_equal(d1: &TDigest, d2: &TDigest) -> bool {
+            (d1.max_size() == d2.max_size())
+                && (d1.mass() == d2.mass())
+                && (d1.sum() == d2.sum())
+                && ((d1.min().is_nan() && d2.min().is_nan())
+                    || (d1.min() == d2.min()))
+                && ((d1.max().is_nan() && d2.max().is_nan())
+                    || (d1.max() == d2.max()))
+                && (d1.count() == d2.count())
+        }
+
+        fn centroids_equal(c1: &Centroid, c2: &Centroid) -> bool {
+            (c1.mean() == c2.mean()) && (c1.weight() == c2.weight())
+        }
+
+        let (first, second) = order_by_address(self, other);
+        let digest1 = &lock_and_flush(first)?.digest;
+        let digest2 = &lock_and_flush(second)?.digest;
+        let cents1 = digest1.centroids();
+        let cents2 = digest2.centroids();
+
+        if !summary_equal(digest1, digest2) {
+            return Ok(false);
+        }
+        if cents1.len() != cents2.len() {
+            return Ok(false);
+        }
+        for (c1, c2) in cents1.iter().zip(cents2.iter()) {
+            if !centroids_equal(c1, c2) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// TDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> PyResult<Self> {
+        Ok(self.clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> PyResult<Self> {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<Self> {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via TDigest.from_bytes(state). A storage other than f64
+    /// as well as tail_singletons, resolution, the exactly stored tails, the
+    /// dtype and the backend are passed as a third element to
+    /// `__setstate__`.
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let bytes = self.to_bytes(py, "exact")?;
+        let cls = py.get_type::<PyTDigest>();
+        let from_bytes = cls.getattr("from_bytes")?;
+        let args = PyTuple::new(py, &[bytes])?;
+        let state = PyDict::new(py);
+        let storage = self.get_storage();
+        if storage != Storage::F64.name() {
+            state.set_item("storage", storage)?;
+        }
+        let tail_singletons = self.get_tail_singletons()?;
+        if tail_singletons > 0 {
+            state.set_item("tail_singletons", tail_singletons)?;
+        }
+        let resolution = self.get_resolution()?;
+        if resolution != Resolution::Both.name() {
+            state.set_item("resolution", resolution)?;
+        }
+        if let Some(tails) = lock_and_flush(self)?.digest.tails() {
+            let pairs = |centroids: &[Centroid]| -> Vec<(f64, f64)> {
+                centroids.iter().map(|c| (c.mean(), c.weight())).collect()
+            };
+            state.set_item(
+                "exact_tails",
+                (tails.k(), pairs(tails.lowest()), pairs(tails.highest())),
+            )?;
+        }
+        if let Some(dtype) = self.get_dtype()? {
+            state.set_item("dtype", dtype)?;
+        }
+        let backend = self.get_backend();
+        if backend != "merging" {
+            state.set_item("backend", backend)?;
+        }
+        if state.is_empty() {
+            return PyTuple::new(py, &[from_bytes, args.into_any()]);
+        }
+        PyTuple::new(py, &[from_bytes, args.into_any(), state.into_any()])
+    }
+
+    /// Restores the attributes pickled by `__reduce__`.
+    pub fn __setstate__(&self, state: &Bound<'_, PyDict>) -> PyResult<()> {
+        if let Some(storage) = state.get_item("storage")? {
+            self.set_storage(storage.extract()?)?;
+        }
+        if let Some(tail_singletons) = state.get_item("tail_singletons")? {
+            self.set_tail_singletons(tail_singletons.extract()?)?;
+        }
+        if let Some(resolution) = state.get_item("resolution")? {
+            self.set_resolution(resolution.extract()?)?;
+        }
+        if let Some(tails) = state.get_item("exact_tails")? {
+            type Pairs = Vec<(f64, f64)>;
+            let (k, lowest, highest): (usize, Pairs, Pairs) =
+                tails.extract()?;
+            let centroids = |pairs: Pairs| -> Vec<Centroid> {
+                pairs
+                    .into_iter()
+                    .map(|(m, w)| Centroid::new(m, w))
+                    .collect()
+            };
+            let tails = TailSample::from_parts(
+                k,
+                centroids(lowest),
+                centroids(highest),
+            );
+            lock_state(self)?.digest.set_tails(Some(tails));
+        }
+        if let Some(dtype) = state.get_item("dtype")? {
+            self.set_dtype(Some(dtype.extract()?))?;
+        }
+        if let Some(backend) = state.get_item("backend")? {
+            self.set_backend(backend.extract()?)?;
+        }
+        Ok(())
+    }
+
+    /// Magic method: bool(TDigest) returns the negation of is_empty().
+    pub fn __bool__(&self) -> PyResult<bool> {
+        self.is_empty().map(|empty| !empty)
+    }
+
+    /// Magic method: len(TDigest) returns the number of centroids.
+    pub fn __len__(&self) -> PyResult<usize> {
+        self.get_n_centroids()
+    }
+
+    // Magic method: returns an iterator over the list of centroids.
+    pub fn __iter__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let centroid_list = self.get_centroids(py)?;
+        centroid_list.call_method0("__iter__")
+    }
+
+    /// Magic method: digest[i] returns a centroid tuple and digest[i:j]
+    /// a list of centroid tuples.
+    pub fn __getitem__<'py>(
+        &self,
+        py: Python<'py>,
+        index: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let state = lock_and_flush(self)?;
+        let centroids = state.digest.centroids();
+        let to_tuple = |c: &Centroid| PyTuple::new(py, [c.mean(), c.weight()]);
+
+        if let Ok(slice) = index.cast::<PySlice>() {
+            let indices = slice.indices(centroids.len() as isize)?;
+            let centroid_list = PyList::empty(py);
+            for k in 0..indices.slicelength as isize {
+                let i = (indices.start + k * indices.step) as usize;
+                centroid_list.append(to_tuple(&centroids[i])?)?;
+            }
+            return Ok(centroid_list.into_any());
+        }
+
+        let i: isize = index.extract().map_err(|_| {
+            PyTypeError::new_err("Centroid indices must be integers or slices.")
+        })?;
+        let len = centroids.len() as isize;
+        let i = if i < 0 { i + len } else { i };
+        if !(0..len).contains(&i) {
+            return Err(PyIndexError::new_err("Centroid index out of range."));
+        }
+        Ok(to_tuple(&centroids[i as usize])?.into_any())
+    }
+
+    /// Magic method: repr/str(TDigest) returns a string representation.
+    pub fn __repr__(&self) -> PyResult<String> {
+        let state = lock_state(self)?;
+        let mut repr =
+            format!("TDigest(max_centroids={}", state.digest.max_size());
+        if state.storage == Storage::F32 {
+            repr.push_str(", storage='f32'");
+        }
+        if state.digest.tail_size() > 0 {
+            repr += &format!(", tail_singletons={}", state.digest.tail_size());
+        }
+        if state.digest.resolution() != Resolution::Both {
+            repr +=
+                &format!(", resolution='{}'", state.digest.resolution().name());
+        }
+        if let Some(tails) = state.digest.tails() {
+            repr += &format!(", exact_tails={}", tails.k());
+        }
+        if let Some(time_unit) = state.time_unit {
+            repr += &format!(", dtype='{}'", time_unit.name());
+        }
+        if state.tree.is_some() {
+            repr.push_str(", backend='tree'");
+        }
+        repr.push(')');
+        Ok(repr)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        self.equals(other)
+    }
+
+    /// Magic method: enables inequality checking (!=).
+    pub fn __ne__(&self, other: &Self) -> PyResult<bool> {
+        self.equals(other).map(|eq| !eq)
+    }
+
+    /// Magic method: dig1 + dig2 returns dig1.merge(dig2).
+    pub fn __add__(&self, py: Python<'_>, other: &Self) -> PyResult<Self> {
+        self.merge(py, other, None)
+    }
+
+    /// Magic method: 0 + digest returns a copy of the digest, so that
+    /// sum(digests) works.
+    pub fn __radd__(
+        &self,
+        py: Python<'_>,
+        other: &Bound<'_, PyAny>,
+    ) -> PyResult<Py<PyAny>> {
+        match other.extract::<i64>() {
+            Ok(0) => Ok(Py::new(py, self.copy()?)?.into_any()),
+            _ => Ok(py.NotImplemented()),
+        }
+    }
+
+    /// Magic method: dig1 - dig2 returns dig1.difference(dig2).
+    pub fn __sub__(&self, py: Python<'_>, other: &Self) -> PyResult<Self> {
+        self.difference(py, other)
+    }
+
+    /// Magic method: dig1 += dig2 calls dig1.merge_inplace(dig2).
+    pub fn __iadd__(&self, py: Python<'_>, other: &Self) -> PyResult<()> {
+        self.merge_inplace(py, other, None)
+    }
+}
+
+/// Top-level function for more efficient merging of many TDigest instances.
+#[pyfunction]
+#[pyo3(signature = (digests, max_centroids=None, n_threads=Some(1), deterministic=false, streaming=false))]
+pub fn merge_all(
+    digests: &Bound<'_, PyAny>,
+    max_centroids: Option<i64>,
+    n_threads: Option<i64>,
+    deterministic: bool,
+    streaming: bool,
+) -> PyResult<PyTDigest> {
+    let py = digests.py();
+    if streaming {
+        let max_cent_valid: Option<usize> = match max_centroids {
+            Some(v) => Some(validate_max_centroids(v)?),
+            None => None,
+        };
+        validate_n_threads(n_threads)?;
+        return merge_all_streaming(digests, max_cent_valid, deterministic);
+    }
+    let mut digests: Vec<TDigest> = digests
+        .try_iter()?
+        .map(|item| {
+            let py_tdigest =
+                item.and_then(|x| x.extract::<PyTDigest>()).map_err(|_| {
+                    PyTypeError::new_err("Provide an iterable of TDigests.")
+                })?;
+            let state = lock_and_flush(&py_tdigest)?;
+            Ok(state.digest.clone())
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let max_cent_valid: Option<usize> = match max_centroids {
+        Some(v) => Some(validate_max_centroids(v)?),
+        None => None,
+    };
+    let n_threads = validate_n_threads(n_threads)?;
+
+    let merged = py
+        .detach(|| {
+            if deterministic {
+                // canonical order, merged in a single pass
+                digests.sort_by(TDigest::canonical_cmp);
+                return TDigest::merge_digests(digests, max_cent_valid);
+            }
+            TDigest::merge_digests_parallel(digests, max_cent_valid, n_threads)
+        })
+        .map_err(malloc_error)?;
+    Ok(PyTDigest {
+        state: Mutex::new(TDigestState {
+            digest: merged,
+            ..TDigestState::default()
+        }),
+    })
+}
+
+/// Implementation of `merge_all(..., streaming=True)`: the digests are
+/// locked (in address order, once each) for the duration of the merge and
+/// merged without copying their centroids.
+fn merge_all_streaming(
+    digests: &Bound<'_, PyAny>,
+    max_centroids: Option<usize>,
+    deterministic: bool,
+) -> PyResult<PyTDigest> {
+    let py = digests.py();
+    let items: Vec<PyRef<'_, PyTDigest>> = digests
+        .try_iter()?
+        .map(|item| {
+            item.and_then(|x| x.extract::<PyRef<'_, PyTDigest>>())
+                .map_err(|_| {
+                    PyTypeError::new_err("Provide an iterable of TDigests.")
+                })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let mut unique: Vec<&PyTDigest> = items.iter().map(|r| &**r).collect();
+    unique.sort_by_key(|pytd| *pytd as *const PyTDigest);
+    unique.dedup_by(|a, b| ptr::eq(*a, *b));
+    let positions: Vec<usize> = items
+        .iter()
+        .map(|item| {
+            unique
+                .binary_search_by_key(&(&**item as *const PyTDigest), |pytd| {
+                    *pytd as *const PyTDigest
+                })
+                .expect("digest is in unique")
+        })
+        .collect();
+
+    // the digests are locked (in address order) only while detached, so
+    // that threads waiting for a lock can still take the GIL
+    let merged = py.detach(|| {
+        let guards: Vec<StateGuard<'_>> = unique
+            .iter()
+            .map(|pytd| lock_and_flush(pytd))
+            .collect::<PyResult<Vec<_>>>()?;
+        let mut refs: Vec<&TDigest> =
+            positions.iter().map(|&i| &guards[i].digest).collect();
+        if deterministic {
+            refs.sort_by(|a, b| a.canonical_cmp(b));
+        }
+        TDigest::merge_digests_streaming(&refs, max_centroids)
+            .map_err(malloc_error)
+    })?;
+    Ok(PyTDigest {
+        state: Mutex::new(TDigestState {
+            digest: merged,
+            ..TDigestState::default()
+        }),
+    })
+}
+
+impl PyTDigest {
+    /// Constructs a new empty TDigest instance with f64 storage.
+    pub fn new(max_centroids: i64) -> PyResult<Self> {
+        let max_cent_valid = validate_max_centroids(max_centroids)?;
+        let digest =
+            TDigest::new_with_size(max_cent_valid).map_err(malloc_error)?;
+        Ok(Self {
+            state: Mutex::new(TDigestState {
+                digest,
+                ..TDigestState::default()
+            }),
+        })
+    }
+
+    /// Updates the digest with a single value, like `update`.
+    #[inline]
+    pub(crate) fn update_value(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut state = lock_state(self)?;
+        record_observation(&mut state, x, weight)?;
+        Ok(())
+    }
+
+    /// Estimates the rank (cumulative probability) of `x`.
+    pub(crate) fn rank(&self, x: f64) -> PyResult<f64> {
+        let state = lock_flush_check(self)?;
+        Ok(state.digest.estimate_rank(x))
+    }
+
+    /// Bytes allocated on the heap for the (possibly packed) centroids.
+    fn heap_bytes(&self) -> usize {
+        // not unpacked, to measure the storage as it is held between calls
+        let state = self.state.lock();
+        state.digest.heap_bytes()
+            + state.packed.as_ref().map_or(0, |p| p.heap_bytes())
+            + state.tree.as_ref().map_or(0, |t| t.heap_bytes())
+    }
+
+    /// Selects the structure that single updates are added to: the buffer
+    /// that is merged when full, or a balanced tree of centroids.
+    fn set_backend(&self, backend: &str) -> PyResult<()> {
+        let mut state = lock_and_flush(self)?;
+        state.tree = match backend {
+            "merging" => None,
+            "tree" if state.digest.tails().is_some() => {
+                return Err(PyValueError::new_err(
+                    "exact_tails is not supported with the tree backend.",
+                ))
+            }
+            "tree" => Some(TreeDigest::for_digest(&state.digest)),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "backend must be 'merging' or 'tree'.",
+                ))
+            }
+        };
+        Ok(())
+    }
+
+    /// Implementation of `merge_inplace`, to be called without the GIL.
+    fn merge_inplace_detached(
+        &self,
+        other: &Self,
+        weight: Option<f64>,
+    ) -> PyResult<()> {
+        if ptr::eq(self, other) {
+            // same object -> merge with a snapshot of the locked state
+            let mut state = lock_and_flush(self)?;
+            let mut snapshot = state.digest.clone();
+            if let Some(weight) = weight {
+                snapshot.scale_weights(weight, 0.0);
+            }
+            let n_before = 2 * snapshot.centroids().len();
+            state
+                .digest
+                .merge_digest_inplace(&snapshot)
+                .map_err(malloc_error)?;
+            state.telemetry.merges += 1;
+            state.record_compression(n_before);
+            return Ok(());
+        }
+        // lock in address order to avoid deadlocks
+        let (first, second) = order_by_address(self, other);
+        let mut first_state = lock_and_flush(first)?;
+        let mut second_state = lock_and_flush(second)?;
+        let (state, other_state) = if ptr::eq(first, self) {
+            (&mut first_state, &second_state)
+        } else {
+            (&mut second_state, &first_state)
+        };
+        let time_unit =
+            TimeUnit::combine(state.time_unit, other_state.time_unit)?;
+        let scaled;
+        let other_digest = match weight {
+            Some(weight) => {
+                let mut digest = other_state.digest.clone();
+                digest.scale_weights(weight, 0.0);
+                scaled = digest;
+                &scaled
+            }
+            None => &other_state.digest,
+        };
+        let n_before =
+            state.digest.centroids().len() + other_digest.centroids().len();
+        state
+            .digest
+            .merge_digest_inplace(other_digest)
+            .map_err(malloc_error)?;
+        state.time_unit = time_unit;
+        state.telemetry.merges += 1;
+        state.record_compression(n_before);
+        Ok(())
+    }
+
+    /// Merges the values of `x` (see `extract_values`) into the digest,
+    /// keeping track of the dtype of datetime64 or timedelta64 values.
+    /// Returns the number of values read.
+    fn merge_input(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        sorting: Sorting,
+    ) -> PyResult<usize> {
+        let (values, skip_nan, unit) = extract_timed_values(x)?;
+        let time_unit = TimeUnit::combine(lock_state(self)?.time_unit, unit)?;
+        self.merge_values(x.py(), &values, w, skip_nan, sorting)?;
+        lock_state(self)?.time_unit = time_unit;
+        Ok(values.len())
+    }
+
+    /// Merges a batch of values with optional weights into the digest.
+    /// With `skip_nan`, NaN values (and their weights) are left out instead
+    /// of being rejected. The values are sorted as set by `sorting`.
+    pub(crate) fn merge_values(
+        &self,
+        py: Python<'_>,
+        x: &FloatValues,
+        w: Option<Bound<'_, PyAny>>,
+        skip_nan: bool,
+        sorting: Sorting,
+    ) -> PyResult<()> {
+        if x.is_empty() {
+            return Ok(());
+        }
+        let Sorting {
+            n_threads,
+            presorted,
+            chunked,
+        } = sorting;
+
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(x.iter(py).filter(keep))?;
+        let w_vec = validate_weights(w, x.len())?;
+        // the input is read while attached, sorting and merging run detached
+        match w_vec {
+            Some(weights) => {
+                let weights = x
+                    .iter(py)
+                    .zip(weights)
+                    .filter_map(|(v, w)| keep(&v).then_some(w));
+                let mut pairs =
+                    TDigest::collect_pairs(x.iter(py).filter(keep), weights)
+                        .map_err(malloc_error)?;
+                if presorted {
+                    check_sorted(pairs.is_sorted_by_key(|pair| pair.0))?;
+                }
+                py.detach(move || {
+                    if chunked {
+                        let mut state = lock_and_flush(self)?;
+                        let n_before =
+                            state.digest.centroids().len() + pairs.len();
+                        state
+                            .digest
+                            .merge_weighted_chunks_inplace(
+                                &mut pairs, n_threads, presorted,
+                            )
+                            .map_err(malloc_error)?;
+                        state.record_compression(n_before);
+                        return Ok(());
+                    }
+                    if !presorted {
+                        TDigest::sort_pairs(&mut pairs, n_threads)
+                            .map_err(malloc_error)?;
+                    }
+                    let mut state = lock_and_flush(self)?;
+                    let n_before = state.digest.centroids().len() + pairs.len();
+                    state
+                        .digest
+                        .merge_sorted_weighted_inplace(&pairs)
+                        .map_err(malloc_error)?;
+                    state.record_compression(n_before);
+                    Ok(())
+                })
+            }
+            None => {
+                let mut values =
+                    TDigest::collect_values(x.iter(py).filter(keep))
+                        .map_err(malloc_error)?;
+                if presorted {
+                    check_sorted(values.is_sorted())?;
+                }
+                py.detach(move || {
+                    if chunked {
+                        let mut state = lock_and_flush(self)?;
+                        let n_before =
+                            state.digest.centroids().len() + values.len();
+                        state
+                            .digest
+                            .merge_chunks_inplace(
+                                &mut values,
+                                n_threads,
+                                presorted,
+                            )
+                            .map_err(malloc_error)?;
+                        state.record_compression(n_before);
+                        return Ok(());
+                    }
+                    if !presorted {
+                        TDigest::sort_values(&mut values, n_threads)
+                            .map_err(malloc_error)?;
+                    }
+                    let mut state = lock_and_flush(self)?;
+                    let n_before =
+                        state.digest.centroids().len() + values.len();
+                    state
+                        .digest
+                        .merge_sorted_inplace(&values)
+                        .map_err(malloc_error)?;
+                    state.record_compression(n_before);
+                    Ok(())
+                })
+            }
+        }
+    }
+}
+
+/// Online TDigest algorithm by kvc0 (https://github.com/MnO2/t-digest/pull/2)
+#[inline]
+pub(crate) fn record_observation(
+    state: &mut TDigestState,
+    observation: f64,
+    weight: f64,
+) -> PyResult<()> {
+    if let Some(tree) = &mut state.tree {
+        return tree.add(observation, weight).map_err(malloc_error);
+    }
+    state.x_cache[state.i] = OrderedFloat(observation);
+    if weight != 1.0 {
+        state.w_cache[state.i] = weight;
+        state.w_cache_set = true;
+    }
+    state.i += 1;
+    if state.i == CACHE_SIZE {
+        flush_cache(state)?;
+    }
+    Ok(())
+}
+
+/// MergingDigest-style flush: the buffered values are sorted in place and
+/// clustered into the existing centroids in a single pass, without building
+/// a new digest.
+/// With the tree backend, the tree is merged instead.
+#[inline]
+fn flush_cache(state: &mut TDigestState) -> PyResult<()> {
+    if let Some(tree) = state.tree.as_mut().filter(|tree| !tree.is_empty()) {
+        let n_before = state.digest.centroids().len() + tree.len();
+        tree.flush_into(&mut state.digest).map_err(malloc_error)?;
+        state.record_compression(n_before);
+    }
+    if state.i < 1 {
+        return Ok(());
+    }
+    let n = state.i;
+    let n_before = state.digest.centroids().len() + n;
+    if state.w_cache_set {
+        let mut pairs = [(OrderedFloat(0.0), 0.0); CACHE_SIZE];
+        for (pair, (&x, &w)) in pairs
+            .iter_mut()
+            .zip(state.x_cache[..n].iter().zip(&state.w_cache[..n]))
+        {
+            *pair = (x, w);
+        }
+        let pairs = &mut pairs[..n];
+        pairs.sort_unstable_by_key(|pair| pair.0);
+        state
+            .digest
+            .merge_sorted_weighted_inplace(pairs)
+            .map_err(malloc_error)?;
+        state.w_cache = [1.0; CACHE_SIZE];
+        state.w_cache_set = false;
+    } else {
+        let values = &mut state.x_cache[..n];
+        values.sort_unstable();
+        state
+            .digest
+            .merge_sorted_inplace(values)
+            .map_err(malloc_error)?;
+    }
+    state.i = 0;
+    state.record_compression(n_before);
+    Ok(())
+}
+
+/// Helper function to extract `x`. Missing values are only skipped (instead
+/// of rejected) for pandas/polars Series, other Arrow data and NaT times.
+pub(crate) fn extract_values(
+    x: &Bound<'_, PyAny>,
+) -> PyResult<(FloatValues, bool)> {
+    let (values, skip_nan, _) = extract_timed_values(x)?;
+    Ok((values, skip_nan))
+}
+
+/// Like `extract_values`, also returning the dtype of datetime64 or
+/// timedelta64 values, which are read as float seconds.
+fn extract_timed_values(
+    x: &Bound<'_, PyAny>,
+) -> PyResult<(FloatValues, bool, Option<TimeUnit>)> {
+    if let Some(values) = FloatValues::from_buffer(x) {
+        return Ok((values, false, None));
+    }
+    if let Some((values, unit)) = temporal::extract(x)? {
+        return Ok((values, true, Some(unit)));
+    }
+    if input::is_series(x)? {
+        return Ok((FloatValues::from_series(x)?, true, None));
+    }
+    Ok((FloatValues::extract(x)?, false, None))
+}
+
+/// Helper function to raise EmptyDigestError on empty digests
+#[inline]
+fn check_nonempty(state: &TDigestState) -> PyResult<()> {
+    if state.digest.is_empty() {
+        Err(EmptyDigestError::new_err("TDigest is empty."))
+    } else {
+        Ok(())
+    }
+}
+
+/// Helper function for mutex acquisition
+#[inline]
+pub(crate) fn lock_state(pytd: &PyTDigest) -> PyResult<StateGuard<'_>> {
+    let mut state = pytd.state.lock();
+    if let Some(packed) = state.packed.take() {
+        if let Err(e) = state.digest.unpack_centroids(&packed) {
+            state.packed = Some(packed);
+            return Err(malloc_error(e));
+        }
+    }
+    Ok(StateGuard(state))
+}
+
+/// Helper function to `lock_state` + `flush_cache`
+#[inline]
+pub(crate) fn lock_and_flush(pytd: &PyTDigest) -> PyResult<StateGuard<'_>> {
+    let mut state = lock_state(pytd)?;
+    flush_cache(&mut state)?;
+    Ok(state)
+}
+
+/// Helper function to `lock_state` + `flush_cache` + `check_nonempty`
+#[inline]
+fn lock_flush_check(pytd: &PyTDigest) -> PyResult<StateGuard<'_>> {
+    let state = lock_and_flush(pytd)?;
+    check_nonempty(&state)?;
+    Ok(state)
+}
+
+/// Helper function to determine lock order based on pointer addresses
+#[inline]
+fn order_by_address<'a>(
+    first: &'a PyTDigest,
+    second: &'a PyTDigest,
+) -> (&'a PyTDigest, &'a PyTDigest) {
+    if (first as *const _) < (second as *const _) {
+        (first, second)
+    } else {
+        (second, first)
+    }
+}
+
+/// Helper function to convert a deserialization error into a
+/// SerializationError
+pub(crate) fn bytes_error(e: BytesError) -> PyErr {
+    match e {
+        BytesError::MemError(e) => malloc_error(e),
+        BytesError::CorruptData => {
+            SerializationError::new_err("Data is corrupt.")
+        }
+        BytesError::EmptyData => SerializationError::new_err("Data is empty."),
+        BytesError::WrongArch => SerializationError::new_err(
+            "Data requires 64-bit architecture to load into TDigest.",
+        ),
+        BytesError::WrongFormat => SerializationError::new_err(
+            "Data is not in fastDigest binary format.",
+        ),
+        BytesError::WrongVersion => SerializationError::new_err(format!(
+            "Data format version is incompatible with fastDigest v{}",
+            env!("CARGO_PKG_VERSION")
+        )),
+    }
+}
+
+impl From<BytesError> for PyErr {
+    fn from(e: BytesError) -> Self {
+        bytes_error(e)
+    }
+}
+
+/// Helper function to copy values into a new float64 NumPy array
+pub(crate) fn f64_array<'py>(
+    py: Python<'py>,
+    values: &[f64],
+) -> PyResult<Bound<'py, PyAny>> {
+    let empty = py.import("numpy")?.getattr("empty")?;
+    let array = empty.call1((values.len(), "float64"))?;
+    if !values.is_empty() {
+        PyBuffer::<f64>::get(&array)?.copy_from_slice(py, values)?;
+    }
+    Ok(array)
+}
+
+/// Helper function to safely convert max_centroids to usize
+pub(crate) fn validate_max_centroids(max_centroids: i64) -> PyResult<usize> {
+    let max_centroids_usize = usize::try_from(max_centroids).map_err(|_| {
+        PyValueError::new_err("max_centroids must be a non-negative integer.")
+    })?;
+    if max_centroids_usize > TD_SIZE_PLATFORM_MAX {
+        return Err(PyValueError::new_err(
+            "max_centroids exceeds the platform limit.",
+        ));
+    }
+    Ok(max_centroids_usize)
+}
+
+/// Helper function to sort and merge a chunk of values read from a file
+fn merge_chunk(state: &mut TDigestState, values: &[f64]) -> PyResult<()> {
+    validate_values(values.iter().copied())?;
+    let mut sorted = TDigest::collect_values(values.iter().copied())
+        .map_err(malloc_error)?;
+    TDigest::sort_values(&mut sorted, 1).map_err(malloc_error)?;
+    let n_before = state.digest.centroids().len() + sorted.len();
+    state
+        .digest
+        .merge_sorted_inplace(&sorted)
+        .map_err(malloc_error)?;
+    state.record_compression(n_before);
+    Ok(())
+}
+
+/// Helper function to check the chunk size of streaming ingestion
+fn validate_chunk(chunk: i64) -> PyResult<usize> {
+    usize::try_from(chunk)
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| {
+            PyValueError::new_err("chunk must be a positive integer.")
+        })
+}
+
+/// Helper function to reject unsorted input with `presorted=True`
+#[inline]
+fn check_sorted(is_sorted: bool) -> PyResult<()> {
+    if is_sorted {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(
+            "Values must be sorted in ascending order if presorted=True.",
+        ))
+    }
+}
+
+/// Helper function to view a C-contiguous float64 buffer as a slice
+fn buffer_slice(buffer: &PyBuffer<f64>) -> &[f64] {
+    if buffer.item_count() == 0 {
+        return &[];
+    }
+    // SAFETY: PyBuffer checks the alignment, and the buffer stays
+    // exported while it is borrowed
+    unsafe {
+        slice::from_raw_parts(
+            buffer.buf_ptr().cast::<f64>(),
+            buffer.item_count(),
+        )
+    }
+}
+
+/// Helper function to validate an `out` array for `len` results
+fn output_buffer(
+    out: &Bound<'_, PyAny>,
+    len: usize,
+) -> PyResult<PyBuffer<f64>> {
+    let buffer = PyBuffer::<f64>::get(out)
+        .map_err(|_| PyTypeError::new_err("out must be a float64 array."))?;
+    if buffer.readonly() || !buffer.is_c_contiguous() {
+        return Err(PyValueError::new_err(
+            "out must be writable and C-contiguous.",
+        ));
+    }
+    if buffer.item_count() != len {
+        return Err(PyValueError::new_err(
+            "out must have the same length as x.",
+        ));
+    }
+    Ok(buffer)
+}
+
+/// Helper function to resolve `n_threads` (None: all available cores)
+pub(crate) fn validate_n_threads(n_threads: Option<i64>) -> PyResult<usize> {
+    let n_threads = match n_threads {
+        None => std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        Some(n) => {
+            usize::try_from(n).ok().filter(|&n| n > 0).ok_or_else(|| {
+                PyValueError::new_err("n_threads must be a positive integer.")
+            })?
+        }
+    };
+    Ok(thread_limit(n_threads))
+}
+
+/// Helper function to check the OpenTelemetry scale range
+fn validate_otel_scale(scale: i32) -> PyResult<()> {
+    if !(OTEL_SCALE_MIN..=OTEL_SCALE_MAX).contains(&scale) {
+        return Err(PyValueError::new_err(format!(
+            "scale must be between {OTEL_SCALE_MIN} and {OTEL_SCALE_MAX}."
+        )));
+    }
+    Ok(())
+}
+
+#[inline]
+pub(crate) fn validate_value(value: f64) -> PyResult<f64> {
+    if !value.is_finite() {
+        return Err(InvalidValueError::new_err("Values must be finite."));
+    }
+    Ok(value)
+}
+
+#[inline]
+pub(crate) fn validate_values(
+    values: impl IntoIterator<Item = f64>,
+) -> PyResult<()> {
+    for x in values {
+        validate_value(x)?;
+    }
+    Ok(())
+}
+
+/// Checks that `weight` does not exceed the mass of `digest` (up to
+/// rounding).
+#[inline]
+fn check_removable(digest: &TDigest, weight: f64) -> PyResult<()> {
+    if weight > digest.mass() * (1.0 + 1e-9) {
+        return Err(PyValueError::new_err(
+            "Cannot remove more weight than the digest holds.",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the optional weight of a merged digest, leaving out the neutral
+/// weight of 1.
+#[inline]
+fn validate_merge_weight(weight: Option<f64>) -> PyResult<Option<f64>> {
+    match weight {
+        Some(weight) if !weight.is_finite() || weight <= 0.0 => Err(
+            PyValueError::new_err("weight must be finite and greater than 0."),
+        ),
+        weight => Ok(weight.filter(|&weight| weight != 1.0)),
+    }
+}
+
+pub(crate) fn validate_weight(weight: f64) -> PyResult<f64> {
+    if !weight.is_finite() || weight <= 0.0 {
+        return Err(InvalidValueError::new_err(
+            "Weights must be finite and greater than 0.",
+        ));
+    }
+    Ok(weight)
+}
+
+/// Helper function to validate `w`. If scalar, creates Vec of length `x_len`.
+#[inline]
+pub(crate) fn validate_weights(
+    w: Option<Bound<'_, PyAny>>,
+    x_len: usize,
+) -> PyResult<Option<Vec<f64>>> {
+    match w {
+        None => Ok(None),
+        Some(obj) => {
+            if let Ok(single_weight) = obj.extract::<f64>() {
+                let w = validate_weight(single_weight)?;
+                return Ok(Some(vec![w; x_len]));
+            }
+
+            let w_vec: Vec<f64> = obj.extract::<Vec<f64>>().map_err(|_| {
+                PyTypeError::new_err(
+                    "w (weight) must be a number or sequence of numbers.",
+                )
+            })?;
+            if w_vec.len() != x_len {
+                return Err(PyValueError::new_err(
+                    "w (weight) sequence must have the same length as x.",
+                ));
+            }
+            for &w in &w_vec {
+                validate_weight(w)?;
+            }
+            Ok(Some(w_vec))
+        }
+    }
+}
+
+impl From<IngestError> for PyErr {
+    fn from(e: IngestError) -> Self {
+        match e {
+            IngestError::Io(e) => e.into(),
+            IngestError::MemError(e) => malloc_error(e),
+            IngestError::Invalid(msg) => PyValueError::new_err(msg),
+        }
+    }
+}
+
+/// Helper function to raise memory allocation errors
+#[cold]
+pub(crate) fn malloc_error(_err: TryReserveError) -> PyErr {
+    PyMemoryError::new_err("Failed to allocate sufficient memory for TDigest.")
+}
+
+/// Python module definition
+#[pymodule(gil_used = false)]
+fn fastdigest(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTDigest>()?;
+    m.add_class::<concurrent::PyConcurrentTDigest>()?;
+    m.add_class::<digest_array::PyTDigestArray>()?;
+    m.add_class::<digest_map::PyTDigestMap>()?;
+    m.add_class::<digest_file::PyDigestFile>()?;
+    m.add_class::<registry::PyRegistry>()?;
+    m.add_class::<dataframe_digest::PyDataFrameDigest>()?;
+    m.add_class::<binned_digest::PyBinnedTDigest>()?;
+    m.add_class::<rolling::PyRollingTDigest>()?;
+    m.add_class::<decaying::PyDecayingTDigest>()?;
+    m.add_class::<event_time::PyEventTimeTDigest>()?;
+    m.add_class::<timeseries::PyTimeSeriesDigest>()?;
+    m.add_class::<discrete::PyDiscreteTDigest>()?;
+    m.add_class::<mapped::PyMappedTDigest>()?;
+    m.add_class::<wal::PyWalTDigest>()?;
+    m.add_class::<bloom::PyBloomFilter>()?;
+    m.add_class::<countmin::PyCountMinSketch>()?;
+    m.add_class::<ddsketch::PyDDSketch>()?;
+    m.add_class::<exphist::PyExponentialHistogram>()?;
+    m.add_class::<gk::PyGkSketch>()?;
+    m.add_class::<hdr::PyHdrHistogram>()?;
+    m.add_class::<hll::PyHyperLogLog>()?;
+    m.add_class::<kll::PyKllSketch>()?;
+    m.add_class::<moments::PyMomentsSketch>()?;
+    m.add_class::<p2::PyP2Quantile>()?;
+    m.add_class::<req::PyReqSketch>()?;
+    m.add_class::<reservoir::PyReservoirSampler>()?;
+    m.add_class::<theta::PyThetaSketch>()?;
+    m.add_class::<topk::PyTopK>()?;
+    m.add_class::<uddsketch::PyUDDSketch>()?;
+    m.add_function(wrap_pyfunction!(merge_all, m)?)?;
+    m.add_function(wrap_pyfunction!(digest_file::save_many, m)?)?;
+    m.add_function(wrap_pyfunction!(digest_file::load_many, m)?)?;
+    m.add("registry", registry::PyRegistry::default())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    errors::init(m)?;
+    errors::ignore_precision_warnings(m.py())?;
+    integrations::add_submodule(m, "errors", errors::init)?;
+    integrations::add_submodule(m, "dask", integrations::dask::init)?;
+    integrations::add_submodule(m, "duckdb", integrations::duckdb::init)?;
+    integrations::add_submodule(m, "pandas", integrations::pandas::init)?;
+    integrations::add_submodule(m, "polars", integrations::polars::init)?;
+    integrations::add_submodule(m, "sklearn", integrations::sklearn::init)?;
+    integrations::add_submodule(m, "spark", integrations::spark::init)?;
+    integrations::add_submodule(m, "sqlite", integrations::sqlite::init)?;
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Import of Spark's QuantileSummaries, the state of `approx_percentile`
+//!
+//! Spark serializes the Greenwald-Khanna summary behind `approx_percentile`
+//! (`PercentileDigest`) as big-endian fields: the relative error (f64), the
+//! count (i64) and the number of samples (i32), followed by one
+//! `(value, g, delta)` triple per sample. Since Spark 3.0, `g` and `delta`
+//! are stored as i64, before that as i32.
+
+use crate::tdigest::{BytesError, Centroid, TDigest};
+use std::collections::TryReserveError;
+
+const HEADER_SIZE: usize = 8 + 8 + 4;
+
+/// Sample of a Greenwald-Khanna summary. `g` is the difference between the
+/// minimum rank of this sample and that of the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub value: f64,
+    pub g: u64,
+    pub delta: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantileSummaries {
+    pub relative_error: f64,
+    pub count: u64,
+    pub sampled: Vec<Stats>,
+}
+
+impl QuantileSummaries {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        if bytes.is_empty() {
+            return Err(BytesError::EmptyData);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(BytesError::CorruptData);
+        }
+        let relative_error = f64::from_be_bytes(read(bytes, 0));
+        let count = i64::from_be_bytes(read(bytes, 8));
+        let n_samples = i32::from_be_bytes(read(bytes, 16));
+        let (Ok(count), Ok(n_samples)) =
+            (u64::try_from(count), usize::try_from(n_samples))
+        else {
+            return Err(BytesError::CorruptData);
+        };
+
+        let data_size = bytes.len() - HEADER_SIZE;
+        let wide = if data_size == n_samples * 24 {
+            true
+        } else if data_size == n_samples * 16 {
+            false
+        } else {
+            return Err(BytesError::CorruptData);
+        };
+
+        let mut sampled: Vec<Stats> = Vec::new();
+        sampled
+            .try_reserve_exact(n_samples)
+            .map_err(BytesError::MemError)?;
+        let mut offset = HEADER_SIZE;
+        for _ in 0..n_samples {
+            let value = f64::from_be_bytes(read(bytes, offset));
+            let (g, delta) = if wide {
+                (
+                    i64::from_be_bytes(read(bytes, offset + 8)),
+                    i64::from_be_bytes(read(bytes, offset + 16)),
+                )
+            } else {
+                (
+                    i32::from_be_bytes(read(bytes, offset + 8)) as i64,
+                    i32::from_be_bytes(read(bytes, offset + 12)) as i64,
+                )
+            };
+            offset += if wide { 24 } else { 16 };
+
+            let (Ok(g), Ok(delta)) = (u64::try_from(g), u64::try_from(delta))
+            else {
+                return Err(BytesError::CorruptData);
+            };
+            let sorted = sampled.last().is_none_or(|last| last.value <= value);
+            if !value.is_finite() || !sorted {
+                return Err(BytesError::CorruptData);
+            }
+            sampled.push(Stats { value, g, delta });
+        }
+
+        Ok(Self {
+            relative_error,
+            count,
+            sampled,
+        })
+    }
+
+    /// Approximates the summary by a digest with one centroid per sample,
+    /// weighted by `g` (the number of values it stands for).
+    pub fn to_digest(
+        &self,
+        max_size: usize,
+    ) -> Result<TDigest, TryReserveError> {
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids.try_reserve_exact(self.sampled.len())?;
+        centroids.extend(
+            self.sampled
+                .iter()
+                .filter(|stats| stats.g > 0)
+                .map(|stats| Centroid::new(stats.value, stats.g as f64)),
+        );
+
+        let (Some(first), Some(last)) = (centroids.first(), centroids.last())
+        else {
+            return TDigest::new_with_size(max_size);
+        };
+        let (min, max) = (first.mean(), last.mean());
+        let mass: f64 = centroids.iter().map(|c| c.weight()).sum();
+        let sum: f64 = centroids.iter().map(|c| c.mean() * c.weight()).sum();
+        let count = if self.count > 0 {
+            self.count as u128
+        } else {
+            mass.round() as u128
+        };
+
+        TDigest::new(centroids, max_size, mass, sum, min, max, count)
+    }
+}
+
+#[inline]
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    bytes[offset..offset + N]
+        .try_into()
+        .expect("offset should be within bounds")
+}
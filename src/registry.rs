@@ -0,0 +1,312 @@
+//! Registry of named digests, for application metrics
+//!
+//! The registry holds shared TDigest instances, which can be updated
+//! through it by name from anywhere in the process, or directly. A
+//! snapshot locks all registered digests at once (in address order, as
+//! `merge_inplace` does), so that the exported digests are consistent with
+//! each other.
+
+use crate::digest_file::save_many;
+use crate::tdigest::TD_SIZE_DEFAULT;
+use crate::{lock_and_flush, PyTDigest, TDigestState};
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+struct Metric {
+    name: String,
+    digest: Py<PyTDigest>,
+    help: Option<String>,
+}
+
+#[derive(Default)]
+struct Metrics {
+    /// Metrics in the order in which they were registered
+    metrics: Vec<Metric>,
+    index: HashMap<String, usize>,
+}
+
+impl Metrics {
+    fn get(&self, name: &str) -> Option<&Metric> {
+        self.index.get(name).map(|&i| &self.metrics[i])
+    }
+
+    fn insert(&mut self, metric: Metric) {
+        self.index.insert(metric.name.clone(), self.metrics.len());
+        self.metrics.push(metric);
+    }
+
+    fn remove(&mut self, name: &str) -> Option<Metric> {
+        let i = self.index.remove(name)?;
+        let metric = self.metrics.remove(i);
+        for j in self.index.values_mut() {
+            if *j > i {
+                *j -= 1;
+            }
+        }
+        Some(metric)
+    }
+}
+
+#[pyclass(name = "Registry", module = "fastdigest", frozen)]
+#[derive(Default)]
+pub struct PyRegistry {
+    metrics: Mutex<Metrics>,
+}
+
+impl PyRegistry {
+    /// Returns the digest registered as `name`, registering a new one if
+    /// there is none.
+    fn get_or_create(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        max_centroids: Option<i64>,
+        help: Option<&str>,
+    ) -> PyResult<Py<PyTDigest>> {
+        let mut metrics = self.metrics.lock();
+        if let Some(metric) = metrics.get(name) {
+            if let Some(max_centroids) = max_centroids {
+                let max_size = lock_and_flush(&metric.digest.borrow(py))?
+                    .digest
+                    .max_size();
+                if i64::try_from(max_size) != Ok(max_centroids) {
+                    return Err(PyValueError::new_err(format!(
+                        "'{name}' is registered with max_centroids={max_size}."
+                    )));
+                }
+            }
+            return Ok(metric.digest.clone_ref(py));
+        }
+        let digest =
+            PyTDigest::new(max_centroids.unwrap_or(TD_SIZE_DEFAULT as i64))?;
+        let digest = Py::new(py, digest)?;
+        metrics.insert(Metric {
+            name: name.to_owned(),
+            digest: digest.clone_ref(py),
+            help: help.map(str::to_owned),
+        });
+        Ok(digest)
+    }
+
+    /// Copies of all digests, with their names and help texts, taken
+    /// while all of them are locked.
+    fn copies(
+        &self,
+        py: Python<'_>,
+    ) -> PyResult<Vec<(String, PyTDigest, Option<String>)>> {
+        let metrics = self.metrics.lock();
+        let digests: Vec<PyRef<'_, PyTDigest>> = metrics
+            .metrics
+            .iter()
+            .map(|metric| metric.digest.borrow(py))
+            .collect();
+        // lock in address order to avoid deadlocks
+        let mut order: Vec<usize> = (0..digests.len()).collect();
+        order.sort_by_key(|&i| &*digests[i] as *const PyTDigest);
+        order.dedup_by_key(|&mut i| &*digests[i] as *const PyTDigest);
+        let mut states = HashMap::new();
+        for &i in &order {
+            let ptr = &*digests[i] as *const PyTDigest;
+            states.insert(ptr, lock_and_flush(&digests[i])?);
+        }
+        Ok(metrics
+            .metrics
+            .iter()
+            .zip(&digests)
+            .map(|(metric, digest)| {
+                let state = &states[&(&**digest as *const PyTDigest)];
+                let copy = PyTDigest {
+                    state: Mutex::new(TDigestState::clone(state)),
+                };
+                (metric.name.clone(), copy, metric.help.clone())
+            })
+            .collect())
+    }
+}
+
+#[pymethods]
+impl PyRegistry {
+    /// Constructs a new empty registry (`fastdigest.registry` is the one
+    /// of the process).
+    #[new]
+    pub fn py_new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the digest registered as `name`, registering a new one
+    /// (with `max_centroids`, default: 1000) if there is none.
+    #[pyo3(signature = (name, max_centroids=None, help=None))]
+    pub fn digest(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        max_centroids: Option<i64>,
+        help: Option<&str>,
+    ) -> PyResult<Py<PyTDigest>> {
+        self.get_or_create(py, name, max_centroids, help)
+    }
+
+    /// Registers an existing digest as `name`.
+    #[pyo3(signature = (name, digest, help=None))]
+    pub fn register(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        digest: Py<PyTDigest>,
+        help: Option<&str>,
+    ) -> PyResult<()> {
+        let mut metrics = self.metrics.lock();
+        if let Some(metric) = metrics.get(name) {
+            if metric.digest.is(&digest) {
+                return Ok(());
+            }
+            return Err(PyValueError::new_err(format!(
+                "'{name}' is already registered."
+            )));
+        }
+        metrics.insert(Metric {
+            name: name.to_owned(),
+            digest: digest.clone_ref(py),
+            help: help.map(str::to_owned),
+        });
+        Ok(())
+    }
+
+    /// Removes the digest registered as `name`, and returns it.
+    pub fn unregister(&self, name: &str) -> PyResult<Py<PyTDigest>> {
+        match self.metrics.lock().remove(name) {
+            Some(metric) => Ok(metric.digest),
+            None => Err(PyKeyError::new_err(name.to_owned())),
+        }
+    }
+
+    /// Removes all digests.
+    pub fn clear(&self) {
+        *self.metrics.lock() = Metrics::default();
+    }
+
+    /// Returns the names in the order in which they were registered.
+    pub fn names(&self) -> Vec<String> {
+        let metrics = self.metrics.lock();
+        metrics.metrics.iter().map(|m| m.name.clone()).collect()
+    }
+
+    /// Returns the digest registered as `name`, or None.
+    pub fn get(&self, py: Python<'_>, name: &str) -> Option<Py<PyTDigest>> {
+        let metrics = self.metrics.lock();
+        metrics.get(name).map(|metric| metric.digest.clone_ref(py))
+    }
+
+    /// Updates the digest registered as `name` (in-place) with a single
+    /// value, registering a new digest if there is none.
+    #[pyo3(signature = (name, x, w=None))]
+    pub fn update(
+        &self,
+        name: &str,
+        x: &Bound<'_, PyAny>,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        // the registry is not locked during the update, which may release
+        // the GIL
+        let digest = self.get_or_create(py, name, None, None)?;
+        let result = digest.borrow(py).update(x, w);
+        result
+    }
+
+    /// Updates the digest registered as `name` (in-place) with a sequence
+    /// of values, registering a new digest if there is none.
+    #[pyo3(signature = (name, x, w=None))]
+    pub fn batch_update(
+        &self,
+        name: &str,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let digest = self.get_or_create(py, name, None, None)?;
+        let result =
+            digest.borrow(py).batch_update(x, w, Some(1), false, false);
+        result
+    }
+
+    /// Returns copies of all digests as a dict of TDigests by name, taken
+    /// at one point in time.
+    pub fn snapshot<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, digest, _) in self.copies(py)? {
+            dict.set_item(name, digest)?;
+        }
+        Ok(dict)
+    }
+
+    /// Returns all digests rendered in the Prometheus text exposition
+    /// format, as of one point in time.
+    #[pyo3(signature = (
+        kind="summary",
+        quantiles=None,
+        buckets=None,
+        labels=None,
+    ))]
+    pub fn to_prometheus(
+        &self,
+        py: Python<'_>,
+        kind: &str,
+        quantiles: Option<Vec<f64>>,
+        buckets: Option<Vec<f64>>,
+        labels: Option<Bound<'_, PyDict>>,
+    ) -> PyResult<String> {
+        let mut families = Vec::new();
+        for (name, digest, help) in self.copies(py)? {
+            families.push(digest.to_prometheus(
+                &name,
+                kind,
+                quantiles.clone(),
+                buckets.clone(),
+                labels.clone(),
+                help.as_deref(),
+            )?);
+        }
+        Ok(families.concat())
+    }
+
+    /// Saves all digests, as of one point in time, to one file with
+    /// `save_many`.
+    pub fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        let snapshot = self.snapshot(py)?;
+        save_many(py, &snapshot, path)
+    }
+
+    /// Magic method: len(registry) returns the number of digests.
+    pub fn __len__(&self) -> usize {
+        self.metrics.lock().metrics.len()
+    }
+
+    /// Magic method: `name in registry` checks for a digest for `name`.
+    pub fn __contains__(&self, name: &str) -> bool {
+        self.metrics.lock().index.contains_key(name)
+    }
+
+    /// Magic method: registry[name] returns the digest registered as
+    /// `name`.
+    pub fn __getitem__(
+        &self,
+        py: Python<'_>,
+        name: &str,
+    ) -> PyResult<Py<PyTDigest>> {
+        self.get(py, name)
+            .ok_or_else(|| PyKeyError::new_err(name.to_owned()))
+    }
+
+    /// Magic method: repr(registry) shows the number of digests.
+    pub fn __repr__(&self) -> String {
+        format!("Registry(n_digests={})", self.__len__())
+    }
+}
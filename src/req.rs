@@ -0,0 +1,714 @@
+//! REQ sketch: quantiles with a relative rank-error guarantee
+//!
+//! Like KLL, the sketch is a hierarchy of compactors whose items at level
+//! `h` stand for `2^h` input values (Cormode, Karnin, Liberty, Thaler &
+//! Veselý, 2021). A compactor only ever compacts its lowest items and
+//! protects the half of its buffer with the highest ones, so that the rank
+//! error shrinks towards the top ranks: at rank `r`, it is proportional to
+//! `1 - r`, and the highest values are kept exactly. With
+//! `high_rank_accuracy=False`, the roles are swapped and the low ranks are
+//! accurate instead. The number of sections compacted at a time follows the
+//! deterministic schedule of the Apache DataSketches implementation.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::kll::SortedView;
+use crate::rng::SplitMix64;
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, malloc_error, validate_values};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::TryReserveError;
+
+const MAGIC: [u8; 8] = *b"FASTREQ~";
+const VERSION: u32 = 1;
+const DEFAULT_K: usize = 12;
+const MIN_K: usize = 4;
+const MAX_K: usize = 1024;
+const INIT_NUM_SECTIONS: usize = 3;
+const MAX_NUM_SECTIONS: usize = INIT_NUM_SECTIONS << 30;
+const MAX_LEVELS: usize = 61;
+/// Factors of the relative and the fixed part of the standard rank error.
+const RELATIVE_RSE_FACTOR: f64 = 0.130_639_4;
+const FIXED_RSE_FACTOR: f64 = 0.084;
+
+#[inline]
+fn nearest_even(x: f64) -> usize {
+    2 * (x / 2.0).round() as usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Compactor {
+    /// Unrounded section size, shrinking by a factor of sqrt(2) whenever
+    /// the number of sections doubles
+    section_size_flt: f64,
+    section_size: usize,
+    num_sections: usize,
+    /// Number of compactions so far, which determines how many sections the
+    /// next compaction covers
+    state: u64,
+    /// Offset of the last compaction
+    coin: bool,
+    /// Kept in ascending order
+    items: Vec<f64>,
+}
+
+impl Compactor {
+    fn new(k: usize) -> Self {
+        Self {
+            section_size_flt: k as f64,
+            section_size: k,
+            num_sections: INIT_NUM_SECTIONS,
+            state: 0,
+            coin: false,
+            items: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn nom_capacity(&self) -> usize {
+        2 * self.num_sections * self.section_size
+    }
+
+    /// Halves the section size, doubling their number, once the state has
+    /// used up the current sections. Returns false if nothing changed.
+    fn ensure_enough_sections(&mut self) -> bool {
+        let exhausted = self.num_sections >= 64
+            || self.state >= 1 << (self.num_sections - 1);
+        if !exhausted
+            || self.section_size <= MIN_K
+            || self.num_sections >= MAX_NUM_SECTIONS
+        {
+            return false;
+        }
+        self.section_size_flt /= std::f64::consts::SQRT_2;
+        let section_size = nearest_even(self.section_size_flt);
+        if section_size < MIN_K {
+            return false;
+        }
+        self.section_size = section_size;
+        self.num_sections <<= 1;
+        true
+    }
+
+    fn insert_sorted(
+        &mut self,
+        mut new: Vec<f64>,
+    ) -> Result<(), TryReserveError> {
+        new.sort_unstable_by(f64::total_cmp);
+        let mut merged = Vec::new();
+        merged.try_reserve_exact(self.items.len() + new.len())?;
+        let (mut a, mut b) =
+            (self.items.iter().peekable(), new.iter().peekable());
+        while let (Some(&&x), Some(&&y)) = (a.peek(), b.peek()) {
+            if x <= y {
+                merged.push(x);
+                a.next();
+            } else {
+                merged.push(y);
+                b.next();
+            }
+        }
+        merged.extend(a);
+        merged.extend(b);
+        self.items = merged;
+        Ok(())
+    }
+
+    /// Removes the compacted range from the buffer and returns every other
+    /// item of it, to be promoted to the next level.
+    fn compact(&mut self, rng: &mut SplitMix64, hra: bool) -> Vec<f64> {
+        let secs_to_compact =
+            ((self.state.trailing_ones() + 1) as usize).min(self.num_sections);
+        let len = self.items.len();
+        let mut non_compact = self.nom_capacity() / 2
+            + (self.num_sections - secs_to_compact) * self.section_size;
+        // the compacted range must have an even length
+        if (len - non_compact) % 2 == 1 {
+            non_compact += 1;
+        }
+        let range = if hra {
+            0..len - non_compact
+        } else {
+            non_compact..len
+        };
+        // successive compactions alternate the offset, as in DataSketches
+        self.coin = if self.state & 1 == 1 {
+            !self.coin
+        } else {
+            rng.next_bool()
+        };
+        let promoted = self.items[range.clone()]
+            .iter()
+            .copied()
+            .skip(self.coin as usize)
+            .step_by(2)
+            .collect();
+        self.items.drain(range);
+        self.state += 1;
+        self.ensure_enough_sections();
+        promoted
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReqSketch {
+    k: usize,
+    high_rank_accuracy: bool,
+    levels: Vec<Compactor>,
+    n: u64,
+    min: f64,
+    max: f64,
+    rng: SplitMix64,
+}
+
+impl ReqSketch {
+    pub fn new(k: usize, high_rank_accuracy: bool, rng: SplitMix64) -> Self {
+        Self {
+            k,
+            high_rank_accuracy,
+            levels: vec![Compactor::new(k)],
+            n: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            rng,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    pub fn n_retained(&self) -> usize {
+        self.levels.iter().map(|c| c.items.len()).sum()
+    }
+
+    fn max_nom_size(&self) -> usize {
+        self.levels.iter().map(Compactor::nom_capacity).sum()
+    }
+
+    pub fn add(&mut self, x: f64) -> Result<(), TryReserveError> {
+        let items = &mut self.levels[0].items;
+        items.try_reserve(1)?;
+        let i = items.partition_point(|&y| y <= x);
+        items.insert(i, x);
+        self.n += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        if self.n_retained() >= self.max_nom_size() {
+            self.compress()?;
+        }
+        Ok(())
+    }
+
+    fn compress(&mut self) -> Result<(), TryReserveError> {
+        let mut h = 0;
+        while h < self.levels.len() {
+            if self.levels[h].items.len() >= self.levels[h].nom_capacity() {
+                if h + 1 == self.levels.len() {
+                    self.levels.try_reserve(1)?;
+                    self.levels.push(Compactor::new(self.k));
+                }
+                let hra = self.high_rank_accuracy;
+                let promoted = self.levels[h].compact(&mut self.rng, hra);
+                self.levels[h + 1].insert_sorted(promoted)?;
+            }
+            h += 1;
+        }
+        Ok(())
+    }
+
+    pub fn merge(&mut self, other: &ReqSketch) -> Result<(), TryReserveError> {
+        if other.is_empty() {
+            return Ok(());
+        }
+        while self.levels.len() < other.levels.len() {
+            self.levels.try_reserve(1)?;
+            self.levels.push(Compactor::new(self.k));
+        }
+        for (level, other_level) in self.levels.iter_mut().zip(&other.levels) {
+            level.state |= other_level.state;
+            while level.ensure_enough_sections() {}
+            level.insert_sorted(other_level.items.clone())?;
+        }
+        self.n += other.n;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        if self.n_retained() >= self.max_nom_size() {
+            self.compress()?;
+        }
+        Ok(())
+    }
+
+    /// Approximate standard error of a rank estimate around `rank`, which is
+    /// 0 for the top (or bottom) ranks that are still kept exactly.
+    pub fn rank_error(&self, rank: f64) -> f64 {
+        let distance = if self.high_rank_accuracy {
+            1.0 - rank
+        } else {
+            rank
+        };
+        let exact = (self.k * INIT_NUM_SECTIONS) as f64 / self.n as f64;
+        if self.levels.len() == 1 || distance <= exact {
+            return 0.0;
+        }
+        let relative = RELATIVE_RSE_FACTOR / self.k as f64 * distance;
+        relative.min(FIXED_RSE_FACTOR / self.k as f64)
+    }
+
+    fn sorted_view(&self) -> SortedView {
+        let levels = self.levels.iter().map(|c| c.items.as_slice());
+        SortedView::new(levels, self.n, self.min, self.max)
+    }
+
+    pub fn quantiles(&self, q: &[f64]) -> Vec<f64> {
+        let view = self.sorted_view();
+        q.iter().map(|&q_i| view.quantile(q_i)).collect()
+    }
+
+    pub fn ranks(&self, x: &[f64]) -> Vec<f64> {
+        let view = self.sorted_view();
+        x.iter().map(|&x_i| view.rank(x_i)).collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.k as u64);
+        writer.u8(self.high_rank_accuracy as u8);
+        writer.u64(self.n);
+        writer.f64(self.min);
+        writer.f64(self.max);
+        writer.u64(self.rng.state());
+        writer.u64(self.levels.len() as u64);
+        for level in &self.levels {
+            writer.f64(level.section_size_flt);
+            writer.u64(level.num_sections as u64);
+            writer.u64(level.state);
+            writer.u8(level.coin as u8);
+            writer.f64s(&level.items);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let k = usize::try_from(reader.u64()?)
+            .map_err(|_| BytesError::CorruptData)?;
+        let high_rank_accuracy = read_bool(&mut reader)?;
+        let n = reader.u64()?;
+        let min = reader.f64()?;
+        let max = reader.f64()?;
+        let rng = SplitMix64::new(reader.u64()?);
+        let n_levels = reader.u64()?;
+        if n_levels > MAX_LEVELS as u64 {
+            return Err(BytesError::CorruptData);
+        }
+        let mut levels = Vec::new();
+        for _ in 0..n_levels {
+            let section_size_flt = reader.f64()?;
+            let num_sections = usize::try_from(reader.u64()?)
+                .map_err(|_| BytesError::CorruptData)?;
+            let state = reader.u64()?;
+            let coin = read_bool(&mut reader)?;
+            let items = reader.f64s()?;
+            let level = Compactor::from_parts(
+                section_size_flt,
+                num_sections,
+                state,
+                coin,
+                items,
+            )
+            .ok_or(BytesError::CorruptData)?;
+            levels.push(level);
+        }
+        reader.finish()?;
+        let mut sketch = Self::new(k, high_rank_accuracy, rng);
+        sketch.levels = levels;
+        sketch.n = n;
+        sketch.min = min;
+        sketch.max = max;
+        sketch.validate().ok_or(BytesError::CorruptData)
+    }
+
+    /// Checks that the parts of a restored sketch are consistent.
+    fn validate(self) -> Option<Self> {
+        if !(MIN_K..=MAX_K).contains(&self.k)
+            || self.k % 2 == 1
+            || self.levels.is_empty()
+            || self.levels.len() > MAX_LEVELS
+        {
+            return None;
+        }
+        let (min, max) = (self.min, self.max);
+        let mut weight: u64 = 0;
+        for (h, level) in self.levels.iter().enumerate() {
+            if level.items.iter().any(|x| !(min <= *x && *x <= max)) {
+                return None;
+            }
+            weight = (level.items.len() as u64)
+                .checked_mul(1 << h)
+                .and_then(|w| weight.checked_add(w))?;
+        }
+        let valid_bounds = if self.n == 0 {
+            min == f64::INFINITY && max == f64::NEG_INFINITY
+        } else {
+            min.is_finite() && max.is_finite()
+        };
+        (weight == self.n && valid_bounds).then_some(self)
+    }
+}
+
+impl Compactor {
+    fn from_parts(
+        section_size_flt: f64,
+        num_sections: usize,
+        state: u64,
+        coin: bool,
+        items: Vec<f64>,
+    ) -> Option<Self> {
+        let valid = section_size_flt.is_finite()
+            && section_size_flt >= MIN_K as f64 - 1.0
+            && section_size_flt <= MAX_K as f64
+            && (INIT_NUM_SECTIONS..=MAX_NUM_SECTIONS).contains(&num_sections)
+            && items.is_sorted_by(|a, b| a <= b);
+        valid.then(|| Self {
+            section_size_flt,
+            section_size: nearest_even(section_size_flt).max(MIN_K),
+            num_sections,
+            state,
+            coin,
+            items,
+        })
+    }
+}
+
+fn read_bool(reader: &mut Reader<'_>) -> Result<bool, BytesError> {
+    match reader.u8()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(BytesError::CorruptData),
+    }
+}
+
+fn validate_k(k: i64) -> PyResult<usize> {
+    usize::try_from(k)
+        .ok()
+        .filter(|k| (MIN_K..=MAX_K).contains(k) && k % 2 == 0)
+        .ok_or_else(|| {
+            PyValueError::new_err(
+                "k must be an even integer between 4 and 1024.",
+            )
+        })
+}
+
+#[pyclass(name = "REQSketch", module = "fastdigest", frozen)]
+pub struct PyReqSketch {
+    state: Mutex<ReqSketch>,
+}
+
+impl PyReqSketch {
+    fn from_sketch(sketch: ReqSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Locks the sketch, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, ReqSketch>> {
+        let sketch = self.state.lock();
+        if sketch.is_empty() {
+            return Err(EmptyDigestError::new_err("REQSketch is empty."));
+        }
+        Ok(sketch)
+    }
+}
+
+#[pymethods]
+impl PyReqSketch {
+    /// Constructs a new empty REQSketch instance.
+    #[new]
+    #[pyo3(signature = (k=DEFAULT_K as i64, high_rank_accuracy=true, seed=None))]
+    pub fn py_new(
+        k: i64,
+        high_rank_accuracy: bool,
+        seed: Option<u64>,
+    ) -> PyResult<Self> {
+        let k = validate_k(k)?;
+        let rng = SplitMix64::from_seed(seed);
+        Ok(Self::from_sketch(ReqSketch::new(
+            k,
+            high_rank_accuracy,
+            rng,
+        )))
+    }
+
+    /// Getter property: returns the k parameter.
+    #[getter(k)]
+    pub fn get_k(&self) -> usize {
+        self.state.lock().k
+    }
+
+    /// Getter property: returns the high_rank_accuracy parameter.
+    #[getter(high_rank_accuracy)]
+    pub fn get_high_rank_accuracy(&self) -> bool {
+        self.state.lock().high_rank_accuracy
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().n
+    }
+
+    /// Getter property: returns the number of items kept in the sketch.
+    #[getter(n_retained)]
+    pub fn get_n_retained(&self) -> usize {
+        self.state.lock().n_retained()
+    }
+
+    /// Returns the approximate standard error of a rank estimate at `rank`.
+    pub fn rank_error(&self, rank: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&rank) {
+            return Err(InvalidQuantileError::new_err(
+                "rank must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.rank_error(rank))
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    pub fn update(&self, x: f64) -> PyResult<()> {
+        validate_values([x])?;
+        self.state.lock().add(x).map_err(malloc_error)
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let mut sketch = self.state.lock();
+        for x in values.iter(py).filter(keep) {
+            sketch.add(x).map_err(malloc_error)?;
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new REQSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        if sketch.high_rank_accuracy != other.high_rank_accuracy {
+            return Err(PyValueError::new_err(
+                "Cannot merge REQSketches with different high_rank_accuracy.",
+            ));
+        }
+        sketch.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&[q])[0])
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&q))
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantiles(&[0.01 * p])[0])
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.quantiles(&[0.5])[0])
+    }
+
+    /// Estimates the rank (cumulative probability) of a given value `x`.
+    pub fn rank(&self, x: f64) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.ranks(&[x])[0])
+    }
+
+    /// Estimates the ranks (cumulative probabilities) of given values `x`.
+    pub fn rank_vec(&self, x: Vec<f64>) -> PyResult<Vec<f64>> {
+        Ok(self.lock_nonempty()?.ranks(&x))
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a REQSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = ReqSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("k", sketch.k)?;
+        dict.set_item("high_rank_accuracy", sketch.high_rank_accuracy)?;
+        dict.set_item("n_values", sketch.n)?;
+        dict.set_item("min", sketch.min)?;
+        dict.set_item("max", sketch.max)?;
+        dict.set_item("rng_state", sketch.rng.state())?;
+        let levels = PyList::empty(py);
+        for level in &sketch.levels {
+            let level_dict = PyDict::new(py);
+            level_dict.set_item("section_size", level.section_size_flt)?;
+            level_dict.set_item("num_sections", level.num_sections)?;
+            level_dict.set_item("state", level.state)?;
+            level_dict.set_item("coin", level.coin)?;
+            level_dict.set_item("items", &level.items)?;
+            levels.append(level_dict)?;
+        }
+        dict.set_item("levels", levels)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a REQSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let invalid =
+            || SerializationError::new_err("Inconsistent REQSketch levels.");
+        let mut levels = Vec::new();
+        for level in get("levels")?.try_iter()? {
+            let level = level?;
+            let compactor = Compactor::from_parts(
+                level.get_item("section_size")?.extract()?,
+                level.get_item("num_sections")?.extract()?,
+                level.get_item("state")?.extract()?,
+                level.get_item("coin")?.extract()?,
+                level.get_item("items")?.extract()?,
+            )
+            .ok_or_else(invalid)?;
+            levels.push(compactor);
+        }
+        let mut sketch = ReqSketch::new(
+            validate_k(get("k")?.extract()?)?,
+            get("high_rank_accuracy")?.extract()?,
+            SplitMix64::new(get("rng_state")?.extract()?),
+        );
+        sketch.levels = levels;
+        sketch.n = get("n_values")?.extract()?;
+        sketch.min = get("min")?.extract()?;
+        sketch.max = get("max")?.extract()?;
+        let sketch = sketch.validate().ok_or_else(invalid)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// REQSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via REQSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(REQSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        let sketch = self.state.lock();
+        let hra = if sketch.high_rank_accuracy {
+            "True"
+        } else {
+            "False"
+        };
+        format!("REQSketch(k={}, high_rank_accuracy={hra})", sketch.k)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,324 @@
+//! Reservoir sampling: a uniform random sample of the stream
+//!
+//! The sampler keeps `k` values; the `n`-th value replaces a random one of
+//! them with probability `k / n` (Vitter's Algorithm R), so that every
+//! value of the stream is in the sample with the same probability. Two
+//! samples are merged by weighted subsampling: each slot of the merged
+//! sample is drawn from the first sample with probability proportional to
+//! the number of its stream values not drawn yet, which makes the merged
+//! sample a uniform sample of the combined streams.
+
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::rng::SplitMix64;
+use crate::tdigest::BytesError;
+use crate::{bytes_error, extract_values, f64_array, validate_values};
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+const MAGIC: [u8; 8] = *b"FASTRSV~";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservoir {
+    k: usize,
+    count: u64,
+    sample: Vec<f64>,
+    rng: SplitMix64,
+}
+
+impl Reservoir {
+    pub fn new(k: usize, rng: SplitMix64) -> Self {
+        Self {
+            k,
+            count: 0,
+            sample: Vec::new(),
+            rng,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn add(&mut self, x: f64) {
+        self.count += 1;
+        if self.sample.len() < self.k {
+            self.sample.push(x);
+            return;
+        }
+        let i = self.rng.next_below(self.count);
+        if let Some(slot) = self.sample.get_mut(i as usize) {
+            *slot = x;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        let k = self.k.min(other.k);
+        let mut ours = self.take_shuffled();
+        let mut theirs = other.sample.clone();
+        let (mut left_ours, mut left_theirs) = (self.count, other.count);
+        let mut sample = Vec::with_capacity(k);
+        while sample.len() < k && left_ours + left_theirs > 0 {
+            let total = (left_ours + left_theirs) as f64;
+            let pick_ours = self.rng.next_f64() * total < left_ours as f64;
+            let value = if pick_ours {
+                left_ours -= 1;
+                ours.pop()
+            } else {
+                left_theirs -= 1;
+                let i = self.rng.next_below(theirs.len() as u64) as usize;
+                Some(theirs.swap_remove(i))
+            };
+            sample.extend(value);
+        }
+        self.k = k;
+        self.count += other.count;
+        self.sample = sample;
+    }
+
+    /// Takes the sample in a random order.
+    fn take_shuffled(&mut self) -> Vec<f64> {
+        let mut sample = std::mem::take(&mut self.sample);
+        for i in (1..sample.len()).rev() {
+            let j = self.rng.next_below(i as u64 + 1) as usize;
+            sample.swap(i, j);
+        }
+        sample
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.k as u64);
+        writer.u64(self.count);
+        writer.u64(self.rng.state());
+        writer.f64s(&self.sample);
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let k = reader.u64()? as usize;
+        let count = reader.u64()?;
+        let rng = SplitMix64::new(reader.u64()?);
+        let sample = reader.f64s()?;
+        reader.finish()?;
+        Self::from_parts(k, count, sample, rng).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sampler, checking that the parts are consistent: the
+    /// sample is full unless fewer values have been seen.
+    fn from_parts(
+        k: usize,
+        count: u64,
+        sample: Vec<f64>,
+        rng: SplitMix64,
+    ) -> Option<Self> {
+        let valid = k > 0
+            && sample.len() as u64 == count.min(k as u64)
+            && sample.iter().all(|x| !x.is_nan());
+        valid.then_some(Self {
+            k,
+            count,
+            sample,
+            rng,
+        })
+    }
+}
+
+#[pyclass(name = "ReservoirSampler", module = "fastdigest", frozen)]
+pub struct PyReservoirSampler {
+    state: Mutex<Reservoir>,
+}
+
+impl PyReservoirSampler {
+    fn from_sampler(sampler: Reservoir) -> Self {
+        Self {
+            state: Mutex::new(sampler),
+        }
+    }
+}
+
+#[pymethods]
+impl PyReservoirSampler {
+    /// Constructs a new empty ReservoirSampler instance that keeps `k`
+    /// values, optionally seeding its random generator.
+    #[new]
+    #[pyo3(signature = (k, seed=None))]
+    pub fn py_new(k: i64, seed: Option<u64>) -> PyResult<Self> {
+        let k = usize::try_from(k)
+            .ok()
+            .filter(|&k| k > 0)
+            .ok_or_else(|| PyValueError::new_err("k must be positive."))?;
+        let rng = SplitMix64::from_seed(seed);
+        Ok(Self::from_sampler(Reservoir::new(k, rng)))
+    }
+
+    /// Getter property: returns the sample size.
+    #[getter(k)]
+    pub fn get_k(&self) -> usize {
+        self.state.lock().k
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Updates the sampler (in-place) with a single float value.
+    pub fn update(&self, x: f64) -> PyResult<()> {
+        validate_values([x])?;
+        self.state.lock().add(x);
+        Ok(())
+    }
+
+    /// Updates the sampler (in-place) with a sequence of float values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let mut sampler = self.state.lock();
+        for x in values.iter(py).filter(keep) {
+            sampler.add(x);
+        }
+        Ok(())
+    }
+
+    /// Returns the sampled values as a NumPy array.
+    pub fn values<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let sample = self.state.lock().sample.clone();
+        f64_array(py, &sample)
+    }
+
+    /// Returns the sampled values as a list.
+    pub fn to_list(&self) -> Vec<f64> {
+        self.state.lock().sample.clone()
+    }
+
+    /// Merges this sampler with another, returning a new ReservoirSampler.
+    pub fn merge(&self, other: &Self) -> Self {
+        let merged = self.copy();
+        merged.merge_inplace(other);
+        merged
+    }
+
+    /// Merges this sampler with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) {
+        let other = other.state.lock().clone();
+        self.state.lock().merge(&other);
+    }
+
+    /// Returns True if the sampler is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns a binary representation of the sampler.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a ReservoirSampler from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sampler = Reservoir::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sampler(sampler))
+    }
+
+    /// Returns a dict representation of the sampler.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sampler = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("k", sampler.k)?;
+        dict.set_item("n_values", sampler.count)?;
+        dict.set_item("rng_state", sampler.rng.state())?;
+        dict.set_item("sample", &sampler.sample)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a ReservoirSampler from a dict.
+    #[staticmethod]
+    pub fn from_dict(sampler_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sampler_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let sampler = Reservoir::from_parts(
+            get("k")?.extract()?,
+            get("n_values")?.extract()?,
+            get("sample")?.extract()?,
+            SplitMix64::new(get("rng_state")?.extract()?),
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent ReservoirSampler sample.")
+        })?;
+        Ok(Self::from_sampler(sampler))
+    }
+
+    /// ReservoirSampler.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sampler(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sampler) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sampler) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via ReservoirSampler.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(sampler) returns the number of sampled values.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().sample.len()
+    }
+
+    /// Magic method: repr/str(ReservoirSampler) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        format!("ReservoirSampler(k={})", self.state.lock().k)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: s1 + s2 returns s1.merge(s2).
+    pub fn __add__(&self, other: &Self) -> Self {
+        self.merge(other)
+    }
+
+    /// Magic method: s1 += s2 merges s2 into s1.
+    pub fn __iadd__(&self, other: &Self) {
+        self.merge_inplace(other)
+    }
+}
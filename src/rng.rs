@@ -0,0 +1,60 @@
+//! Small seedable pseudo-random generator for the sampling sketches
+//!
+//! SplitMix64 (Steele, Lea & Flood, 2014): fast, with a 64-bit state that
+//! is easily serialized, so that a restored sketch continues with the same
+//! random sequence.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeds the generator from the per-process random keys of the standard
+    /// library's hash maps.
+    pub fn from_entropy() -> Self {
+        use std::hash::{BuildHasher, RandomState};
+        Self::new(RandomState::new().hash_one(0u64))
+    }
+
+    /// Uses `seed` if given, otherwise seeds from entropy.
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        seed.map_or_else(Self::from_entropy, Self::new)
+    }
+
+    #[inline]
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    #[inline]
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u64() >> 63 == 1
+    }
+
+    /// Uniform integer in `0..n` (by multiplication, with a bias below
+    /// `n / 2^64`).
+    #[inline]
+    pub fn next_below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    /// Uniform float in `[0, 1)` with 53 random bits.
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * f64::EPSILON / 2.0
+    }
+}
@@ -0,0 +1,641 @@
+//! Digest over a sliding time window, as a ring of sub-digests
+//!
+//! The window of `window` time units is split into `n_buckets` intervals
+//! of equal length, aligned to multiples of that length. Each interval with
+//! values has its own digest; when the clock moves past the window, the
+//! oldest digests are dropped whole. Queries merge the remaining digests,
+//! so they cover the last `window` time units at the granularity of one
+//! interval (the oldest one ends up to one interval early).
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::SerializationError;
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::{TryReserveError, VecDeque};
+
+const MAGIC: [u8; 8] = *b"FASTRTD~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+pub struct RollingDigest {
+    window: f64,
+    n_buckets: usize,
+    max_size: usize,
+    now: Option<f64>,
+    /// Buckets in the window from oldest to newest, each with its epoch
+    /// (the number of its interval since time 0)
+    buckets: VecDeque<(i64, Entry)>,
+}
+
+impl RollingDigest {
+    pub fn new(window: f64, n_buckets: usize, max_size: usize) -> Self {
+        Self {
+            window,
+            n_buckets,
+            max_size,
+            now: None,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    fn epoch(&self, timestamp: f64) -> i64 {
+        (timestamp / self.window * self.n_buckets as f64).floor() as i64
+    }
+
+    /// The oldest epoch in the window at `now`.
+    #[inline]
+    fn oldest_epoch(&self, now: f64) -> i64 {
+        self.epoch(now).saturating_sub(self.n_buckets as i64 - 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(|(_, entry)| entry.count() == 0)
+    }
+
+    pub fn count(&self) -> u128 {
+        self.buckets.iter().map(|(_, entry)| entry.count()).sum()
+    }
+
+    /// Moves the clock to `now`, expecting it not to precede the latest
+    /// timestamp, and drops the buckets that left the window.
+    pub fn advance(&mut self, now: f64) {
+        self.now = Some(now);
+        let oldest = self.oldest_epoch(now);
+        while self.buckets.front().is_some_and(|(e, _)| *e < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// The bucket of `timestamp`, after moving the clock to it.
+    fn bucket(
+        &mut self,
+        timestamp: f64,
+    ) -> Result<&mut Entry, TryReserveError> {
+        self.advance(timestamp);
+        let epoch = self.epoch(timestamp);
+        if self.buckets.back().is_none_or(|(e, _)| *e != epoch) {
+            self.buckets.push_back((epoch, Entry::new(self.max_size)?));
+        }
+        Ok(&mut self.buckets.back_mut().expect("bucket was pushed").1)
+    }
+
+    pub fn add(
+        &mut self,
+        timestamp: f64,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        self.bucket(timestamp)?.add(x, weight)
+    }
+
+    /// Adds values at non-decreasing timestamps, merging each bucket once
+    /// with all of its values.
+    pub fn add_batch(
+        &mut self,
+        timestamps: &[f64],
+        pairs: &[(OrderedFloat<f64>, f64)],
+    ) -> Result<(), TryReserveError> {
+        let mut start = 0;
+        while start < timestamps.len() {
+            let epoch = self.epoch(timestamps[start]);
+            let len = timestamps[start..]
+                .iter()
+                .take_while(|&&t| self.epoch(t) == epoch)
+                .count();
+            let end = start + len;
+            let mut run: Pairs = Vec::new();
+            run.try_reserve_exact(len)?;
+            run.extend_from_slice(&pairs[start..end]);
+            self.bucket(timestamps[end - 1])?.add_batch(&mut run)?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        for (_, entry) in &mut self.buckets {
+            entry.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The merged digest of all values in the window.
+    pub fn digest(&mut self) -> Result<TDigest, TryReserveError> {
+        self.flush()?;
+        let digests = self
+            .buckets
+            .iter()
+            .map(|(_, entry)| entry.digest.clone())
+            .collect();
+        TDigest::merge_digests(digests, Some(self.max_size))
+    }
+
+    /// Merges the buckets of `other` into the buckets of the same epochs,
+    /// moving the clock to the later of both. The windows and bucket counts
+    /// must match.
+    pub fn merge(
+        &mut self,
+        other: &RollingDigest,
+    ) -> Result<(), TryReserveError> {
+        for (epoch, entry) in &other.buckets {
+            match self.buckets.binary_search_by_key(epoch, |(e, _)| *e) {
+                Ok(i) => self.buckets[i].1.merge(entry)?,
+                Err(i) => {
+                    let mut merged = Entry::new(self.max_size)?;
+                    merged.merge(entry)?;
+                    self.buckets.insert(i, (*epoch, merged));
+                }
+            }
+        }
+        let now = match (self.now, other.now) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        if let Some(now) = now {
+            self.advance(now);
+        }
+        Ok(())
+    }
+
+    /// Whether both hold equal state. Both must be flushed.
+    pub fn same_state(&self, other: &RollingDigest) -> bool {
+        self.window == other.window
+            && self.n_buckets == other.n_buckets
+            && self.max_size == other.max_size
+            && self.now == other.now
+            && self.buckets.len() == other.buckets.len()
+            && self
+                .buckets
+                .iter()
+                .zip(&other.buckets)
+                .all(|((e1, a), (e2, b))| e1 == e2 && a.digest == b.digest)
+    }
+
+    /// Binary representation; the buckets must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.window);
+        writer.u64(self.n_buckets as u64);
+        writer.u64(self.max_size as u64);
+        writer.u8(self.now.is_some() as u8);
+        writer.f64(self.now.unwrap_or(0.0));
+        writer.u64(self.buckets.len() as u64);
+        for (epoch, entry) in &self.buckets {
+            writer.i64(*epoch);
+            writer.bytes(&entry.digest.to_bytes()?);
+        }
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let window = reader.f64()?;
+        let n_buckets = reader.u64()?;
+        let max_size = reader.u64()?;
+        let has_now = reader.u8()?;
+        let now = reader.f64()?;
+        let now = match has_now {
+            0 => None,
+            1 => Some(now),
+            _ => return Err(BytesError::CorruptData),
+        };
+        // each bucket has an epoch and a length-prefixed digest
+        let len = reader.seq_len(16)?;
+        let mut buckets = Vec::new();
+        buckets
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            let epoch = reader.i64()?;
+            let digest = TDigest::from_bytes(reader.bytes()?)?;
+            digest.validate().map_err(|_| BytesError::CorruptData)?;
+            buckets.push((epoch, digest));
+        }
+        reader.finish()?;
+        let n_buckets =
+            usize::try_from(n_buckets).map_err(|_| BytesError::CorruptData)?;
+        let max_size = usize::try_from(max_size)
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        Self::from_parts(window, n_buckets, max_size, now, buckets)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a rolling digest from `(epoch, digest)` buckets, ordered
+    /// from oldest to newest, checking that they are in the window at
+    /// `now`.
+    fn from_parts(
+        window: f64,
+        n_buckets: usize,
+        max_size: usize,
+        now: Option<f64>,
+        buckets: Vec<(i64, TDigest)>,
+    ) -> Option<Self> {
+        if !valid_window(window) || n_buckets == 0 {
+            return None;
+        }
+        let mut rolling = Self::new(window, n_buckets, max_size);
+        rolling.now = now;
+        let Some(now) = now else {
+            return buckets.is_empty().then_some(rolling);
+        };
+        if !now.is_finite() {
+            return None;
+        }
+        let (oldest, newest) = (rolling.oldest_epoch(now), rolling.epoch(now));
+        let mut previous = None;
+        for (epoch, digest) in buckets {
+            let ordered = oldest <= epoch
+                && epoch <= newest
+                && previous.is_none_or(|p| p < epoch);
+            if !ordered || digest.max_size() != max_size {
+                return None;
+            }
+            rolling
+                .buckets
+                .push_back((epoch, Entry::from_digest(digest)));
+            previous = Some(epoch);
+        }
+        Some(rolling)
+    }
+}
+
+#[inline]
+fn valid_window(window: f64) -> bool {
+    window.is_finite() && window > 0.0
+}
+
+/// Checks that the timestamps are finite and do not precede `latest` or
+/// each other.
+fn validate_timestamps(
+    latest: Option<f64>,
+    timestamps: &[f64],
+) -> PyResult<()> {
+    let mut latest = latest.unwrap_or(f64::NEG_INFINITY);
+    for &t in timestamps {
+        if !t.is_finite() {
+            return Err(PyValueError::new_err("Timestamps must be finite."));
+        }
+        if t < latest {
+            return Err(PyValueError::new_err(
+                "Timestamps must not precede the latest timestamp.",
+            ));
+        }
+        latest = t;
+    }
+    Ok(())
+}
+
+#[pyclass(name = "RollingTDigest", module = "fastdigest", frozen)]
+pub struct PyRollingTDigest {
+    state: Mutex<RollingDigest>,
+}
+
+impl PyRollingTDigest {
+    fn from_rolling(rolling: RollingDigest) -> Self {
+        Self {
+            state: Mutex::new(rolling),
+        }
+    }
+
+    /// The merged digest of the window as a TDigest.
+    fn window_digest(&self, py: Python<'_>) -> PyResult<PyTDigest> {
+        py.detach(|| {
+            let digest = self.state.lock().digest().map_err(malloc_error)?;
+            Ok(wrap_digest(digest))
+        })
+    }
+}
+
+#[pymethods]
+impl PyRollingTDigest {
+    /// Constructs a new empty RollingTDigest instance for the last `window`
+    /// time units, split into `n_buckets` sub-digests.
+    #[new]
+    #[pyo3(signature = (
+        window,
+        n_buckets=10,
+        max_centroids=TD_SIZE_DEFAULT as i64,
+    ))]
+    pub fn py_new(
+        window: f64,
+        n_buckets: i64,
+        max_centroids: i64,
+    ) -> PyResult<Self> {
+        if !valid_window(window) {
+            return Err(PyValueError::new_err(
+                "window must be finite and greater than 0.",
+            ));
+        }
+        let n_buckets = usize::try_from(n_buckets)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                PyValueError::new_err("n_buckets must be a positive integer.")
+            })?;
+        let max_size = validate_max_centroids(max_centroids)?;
+        let rolling = RollingDigest::new(window, n_buckets, max_size);
+        Ok(Self::from_rolling(rolling))
+    }
+
+    /// Getter property: returns the window length.
+    #[getter(window)]
+    pub fn get_window(&self) -> f64 {
+        self.state.lock().window
+    }
+
+    /// Getter property: returns the number of buckets of the window.
+    #[getter(n_buckets)]
+    pub fn get_n_buckets(&self) -> usize {
+        self.state.lock().n_buckets
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size
+    }
+
+    /// Getter property: returns the latest timestamp, or None if the clock
+    /// has not started.
+    #[getter(now)]
+    pub fn get_now(&self) -> Option<f64> {
+        self.state.lock().now
+    }
+
+    /// Getter property: returns the number of data points in the window.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Updates the digest (in-place) with a single value at the given
+    /// timestamp.
+    #[pyo3(signature = (timestamp, x, w=None))]
+    pub fn update(
+        &self,
+        timestamp: f64,
+        x: f64,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut rolling = self.state.lock();
+        validate_timestamps(rolling.now, &[timestamp])?;
+        rolling.add(timestamp, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values at the
+    /// given (non-decreasing) timestamps.
+    #[pyo3(signature = (timestamps, x, w=None))]
+    pub fn batch_update(
+        &self,
+        timestamps: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (times, _) = extract_values(timestamps)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if times.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "timestamps must have the same length as x.",
+            ));
+        }
+        let weights = validate_weights(w, values.len())?;
+        let mut kept_times = Vec::new();
+        let mut pairs = Vec::new();
+        for (j, (t, x)) in times.iter(py).zip(values.iter(py)).enumerate() {
+            // missing values are left out together with their timestamps
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            kept_times.push(t);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| {
+            let mut rolling = self.state.lock();
+            validate_timestamps(rolling.now, &kept_times)?;
+            rolling.add_batch(&kept_times, &pairs).map_err(malloc_error)
+        })
+    }
+
+    /// Moves the clock (in-place) to `now`, dropping the values that left
+    /// the window.
+    pub fn advance(&self, now: f64) -> PyResult<()> {
+        let mut rolling = self.state.lock();
+        validate_timestamps(rolling.now, &[now])?;
+        rolling.advance(now);
+        Ok(())
+    }
+
+    /// Returns the values in the window as a TDigest.
+    pub fn snapshot(&self, py: Python<'_>) -> PyResult<PyTDigest> {
+        self.window_digest(py)
+    }
+
+    /// Estimates the quantile of the values in the window for a given
+    /// cumulative probability `q`.
+    pub fn quantile(&self, py: Python<'_>, q: f64) -> PyResult<f64> {
+        self.window_digest(py)?.quantile(q).map(f64::from)
+    }
+
+    /// Estimates the percentile of the values in the window for a given
+    /// cumulative probability `p` (%).
+    pub fn percentile(&self, py: Python<'_>, p: f64) -> PyResult<f64> {
+        self.window_digest(py)?.percentile(p).map(f64::from)
+    }
+
+    /// Estimates the median of the values in the window.
+    pub fn median(&self, py: Python<'_>) -> PyResult<f64> {
+        self.window_digest(py)?.median().map(f64::from)
+    }
+
+    /// Estimates the CDF of the values in the window at the value `x`.
+    pub fn cdf(&self, py: Python<'_>, x: f64) -> PyResult<f64> {
+        self.window_digest(py)?.rank(x)
+    }
+
+    /// Returns True if the window holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Merges this digest with another with the same window and buckets,
+    /// returning a new RollingTDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same window and buckets into this
+    /// one (in-place), bucket by bucket.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut rolling = self.state.lock();
+        if rolling.window != other.window
+            || rolling.n_buckets != other.n_buckets
+        {
+            return Err(PyValueError::new_err(
+                "Cannot merge RollingTDigests with different windows or \
+                 buckets.",
+            ));
+        }
+        rolling.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the digest.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let mut rolling = self.state.lock();
+        rolling.flush().map_err(malloc_error)?;
+        let bytes = rolling.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a RollingTDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let rolling = RollingDigest::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_rolling(rolling))
+    }
+
+    /// Returns a dict representation of the digest.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut rolling = self.state.lock();
+        rolling.flush().map_err(malloc_error)?;
+        let epochs: Vec<i64> =
+            rolling.buckets.iter().map(|(e, _)| *e).collect();
+        let digests = PyList::empty(py);
+        for (_, entry) in &rolling.buckets {
+            digests.append(wrap_digest(entry.digest.clone()).to_dict(py)?)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("window", rolling.window)?;
+        dict.set_item("n_buckets", rolling.n_buckets)?;
+        dict.set_item("max_centroids", rolling.max_size)?;
+        dict.set_item("now", rolling.now)?;
+        dict.set_item("epochs", epochs)?;
+        dict.set_item("digests", digests)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a RollingTDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(rolling_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            rolling_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let max_size =
+            validate_max_centroids(get("max_centroids")?.extract()?)?;
+        let epochs: Vec<i64> = get("epochs")?.extract()?;
+        let digests = get("digests")?
+            .cast::<PyList>()?
+            .iter()
+            .map(|digest_dict| {
+                let digest = PyTDigest::from_dict(digest_dict.cast()?, true)?;
+                Ok(digest.state.into_inner().digest)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let inconsistent = || {
+            SerializationError::new_err("Inconsistent RollingTDigest buckets.")
+        };
+        if epochs.len() != digests.len() {
+            return Err(inconsistent());
+        }
+        let rolling = RollingDigest::from_parts(
+            get("window")?.extract()?,
+            get("n_buckets")?.extract()?,
+            max_size,
+            get("now")?.extract()?,
+            epochs.into_iter().zip(digests).collect(),
+        )
+        .ok_or_else(inconsistent)?;
+        Ok(Self::from_rolling(rolling))
+    }
+
+    /// RollingTDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_rolling(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via RollingTDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(RollingTDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let rolling = self.state.lock();
+        format!(
+            "RollingTDigest(window={:?}, n_buckets={}, max_centroids={})",
+            rolling.window, rolling.n_buckets, rolling.max_size
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        let mut rolling = self.state.lock();
+        rolling.flush().map_err(malloc_error)?;
+        Ok(rolling.same_state(&other))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,198 @@
+//! Streaming serialization to and from Python file objects
+//!
+//! The formats are the same as those of `to_bytes`. Containers are written
+//! in chunks of about `CHUNK_SIZE` bytes and read entry by entry, so that
+//! their whole binary representation is never held in memory. Reading
+//! takes exactly the bytes of one object from the stream, so that several
+//! objects can be sent over one stream (e.g. a socket) one after another.
+
+use crate::codec::{Input, Writer};
+use crate::tdigest::{BytesError, TDigest};
+use crate::{bytes_error, malloc_error};
+use parking_lot::Mutex;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::borrow::Cow;
+use std::collections::TryReserveError;
+
+pub(crate) const CHUNK_SIZE: usize = 1 << 16;
+
+/// Writes to a binary file object, or a socket.
+pub(crate) struct FileWriter<'a, 'py> {
+    file: &'a Bound<'py, PyAny>,
+    method: &'static str,
+}
+
+impl<'a, 'py> FileWriter<'a, 'py> {
+    pub fn new(file: &'a Bound<'py, PyAny>) -> PyResult<Self> {
+        let method = if file.hasattr("write")? {
+            "write"
+        } else if file.hasattr("sendall")? {
+            "sendall"
+        } else {
+            return Err(PyTypeError::new_err(
+                "file must be a binary file object or a socket.",
+            ));
+        };
+        Ok(Self { file, method })
+    }
+
+    pub fn write(&self, bytes: &[u8]) -> PyResult<()> {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let py = self.file.py();
+            let written = self
+                .file
+                .call_method1(self.method, (PyBytes::new(py, rest),))?;
+            // raw files may write only a part, others return None
+            match written.extract::<usize>() {
+                Ok(n) if n < rest.len() => rest = &rest[n..],
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a header (in `writer`) and `len` entries in chunks, each
+    /// encoded by `entry` while `state` is locked. Other threads can use
+    /// the object between chunks (without the lock held during writes).
+    pub fn write_chunked<T>(
+        &self,
+        state: &Mutex<T>,
+        mut writer: Writer,
+        len: usize,
+        mut entry: impl FnMut(
+            &mut T,
+            usize,
+            &mut Writer,
+        ) -> Result<(), TryReserveError>,
+    ) -> PyResult<()> {
+        let mut i = 0;
+        loop {
+            {
+                let mut state = state.lock();
+                while i < len && writer.written() < CHUNK_SIZE {
+                    entry(&mut state, i, &mut writer).map_err(malloc_error)?;
+                    i += 1;
+                }
+            }
+            self.write(&writer.take())?;
+            if i == len {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads from a binary file object, or a socket.
+pub(crate) struct FileReader<'a, 'py> {
+    file: &'a Bound<'py, PyAny>,
+    method: &'static str,
+    /// Whether any bytes have been read, to tell empty from cut-off data
+    started: bool,
+}
+
+impl<'a, 'py> FileReader<'a, 'py> {
+    pub fn new(file: &'a Bound<'py, PyAny>) -> PyResult<Self> {
+        let method = if file.hasattr("read")? {
+            "read"
+        } else if file.hasattr("recv")? {
+            "recv"
+        } else {
+            return Err(PyTypeError::new_err(
+                "file must be a binary file object or a socket.",
+            ));
+        };
+        Ok(Self {
+            file,
+            method,
+            started: false,
+        })
+    }
+
+    /// Fills `buf` with the next bytes of the stream.
+    fn fill(&mut self, buf: &mut [u8]) -> PyResult<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let chunk =
+                self.file.call_method1(self.method, (buf.len() - filled,))?;
+            let chunk = chunk.cast::<PyBytes>().map_err(|_| {
+                PyTypeError::new_err("file must be opened in binary mode.")
+            })?;
+            let bytes = chunk.as_bytes();
+            if bytes.is_empty() {
+                return Err(bytes_error(if self.started {
+                    BytesError::CorruptData
+                } else {
+                    BytesError::EmptyData
+                }));
+            }
+            let n = bytes.len();
+            if n > buf.len() - filled {
+                return Err(PyValueError::new_err(
+                    "file returned more bytes than requested.",
+                ));
+            }
+            buf[filled..filled + n].copy_from_slice(bytes);
+            filled += n;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    /// Reads the next `len` bytes, in chunks, so that a corrupt length
+    /// does not allocate more than the stream holds.
+    fn read_vec(&mut self, len: usize) -> PyResult<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        while bytes.len() < len {
+            let start = bytes.len();
+            let n = (len - start).min(CHUNK_SIZE);
+            bytes.try_reserve_exact(n).map_err(malloc_error)?;
+            bytes.resize(start + n, 0);
+            self.fill(&mut bytes[start..])?;
+        }
+        Ok(bytes)
+    }
+
+    /// Checks the magic and version of a format (see `codec::Reader`).
+    pub fn header(&mut self, magic: &[u8; 8], version: u32) -> PyResult<()> {
+        if self.array::<8>()? != *magic {
+            return Err(bytes_error(BytesError::WrongFormat));
+        }
+        if u32::from_le_bytes(self.array()?) != version {
+            return Err(bytes_error(BytesError::WrongVersion));
+        }
+        Ok(())
+    }
+
+    /// Reads a TDigest in the format of `TDigest::to_bytes`.
+    pub fn digest(&mut self) -> PyResult<TDigest> {
+        let mut bytes = self.read_vec(TDigest::PREFIX_BYTES)?;
+        let len = TDigest::bytes_len(&bytes).map_err(bytes_error)?;
+        bytes.extend(self.read_vec(len - TDigest::PREFIX_BYTES)?);
+        TDigest::from_bytes(&bytes).map_err(bytes_error)
+    }
+}
+
+impl Input for FileReader<'_, '_> {
+    type Error = PyErr;
+
+    fn array<const N: usize>(&mut self) -> PyResult<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.fill(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// As the length of a stream is unknown, this only checks that the
+    /// length fits into memory.
+    fn seq_len(&mut self, _item_size: usize) -> PyResult<usize> {
+        usize::try_from(self.u64()?)
+            .map_err(|_| bytes_error(BytesError::CorruptData))
+    }
+
+    fn bytes(&mut self) -> PyResult<Cow<'_, [u8]>> {
+        let len = self.seq_len(1)?;
+        Ok(Cow::Owned(self.read_vec(len)?))
+    }
+}
@@ -1,12 +1,19 @@
 //! Backend originally by Paul Meng (https://github.com/MnO2/t-digest)
 
 use ordered_float::OrderedFloat;
-use std::cmp::Ordering;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
 use std::collections::TryReserveError;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::OnceLock;
+use std::{fmt, iter, mem, thread};
 
 pub const TD_SIZE_DEFAULT: usize = 1000;
 pub const TD_SIZE_PLATFORM_MAX: usize = (isize::MAX / 16) as usize;
-pub const TD_SIZE_GLOBAL_MAX: usize = (i64::MAX / 16) as usize;
+pub const TD_SIZE_GLOBAL_MAX: u64 = (i64::MAX / 16) as u64;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -66,6 +73,76 @@ impl Default for Centroid {
     }
 }
 
+/// Centroids stored in single precision, taking half the memory.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PackedCentroids(Vec<[f32; 2]>);
+
+impl PackedCentroids {
+    /// Number of bytes allocated on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.0.capacity() * mem::size_of::<[f32; 2]>()
+    }
+}
+
+/// Centroid means and midpoint ranks, as from `prefix_ranks`.
+struct PrefixRanks {
+    means: Vec<f64>,
+    ranks: Vec<f64>,
+}
+
+/// Prefix ranks of an unchanged digest, for repeated single queries: the
+/// first query after a change scans the centroids as usual, the second
+/// computes the prefix ranks, on which the ones after it binary search.
+/// It is not part of the state, so it is left out of comparisons and
+/// copies, and must be reset by every change to the centroids.
+#[derive(Default)]
+struct RankCache {
+    queried: AtomicBool,
+    ranks: OnceLock<PrefixRanks>,
+}
+
+impl RankCache {
+    /// Prefix ranks of `digest`, if it has been queried before.
+    fn get(&self, digest: &TDigest) -> Option<&PrefixRanks> {
+        if let Some(ranks) = self.ranks.get() {
+            return Some(ranks);
+        }
+        if !self.queried.swap(true, AtomicOrdering::Relaxed) {
+            return None;
+        }
+        // if the allocation fails, the centroids are scanned instead
+        let (means, ranks) = digest.prefix_ranks().ok()?;
+        Some(self.ranks.get_or_init(|| PrefixRanks { means, ranks }))
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.ranks.get().map_or(0, |ranks| {
+            (ranks.means.capacity() + ranks.ranks.capacity())
+                * mem::size_of::<f64>()
+        })
+    }
+}
+
+impl Clone for RankCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for RankCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RankCache {}
+
+impl fmt::Debug for RankCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RankCache")
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
 pub struct TDigest {
@@ -76,12 +153,404 @@ pub struct TDigest {
     min: OrderedFloat<f64>,
     max: OrderedFloat<f64>,
     count: u128,
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    tail_size: usize,
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    resolution: Resolution,
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    tails: Option<TailSample>,
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    ranks: RankCache,
+}
+
+/// Part of the distribution that the compression resolves most finely.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum Resolution {
+    /// Both tails (the default)
+    #[default]
+    Both,
+    /// The upper tail, at the expense of the lower one
+    Upper,
+    /// The lower tail, at the expense of the upper one
+    Lower,
+}
+
+impl Resolution {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "both" => Some(Self::Both),
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Both => "both",
+            Self::Upper => "upper",
+            Self::Lower => "lower",
+        }
+    }
+
+    /// Scale function: upper quantile bound of the `k`-th of `d` centroids.
+    fn k_to_q(self, k: f64, d: f64) -> f64 {
+        let k_div_d = k / d;
+        match self {
+            Self::Both if k_div_d >= 0.5 => {
+                let base = 1.0 - k_div_d;
+                1.0 - 2.0 * base * base
+            }
+            Self::Both => 2.0 * k_div_d * k_div_d,
+            Self::Upper => {
+                let base = 1.0 - k_div_d;
+                1.0 - base * base * base
+            }
+            Self::Lower => k_div_d * k_div_d * k_div_d,
+        }
+    }
+
+    /// Derivative of `k_to_q` at quantile `q`: the largest fraction of the
+    /// mass that a centroid around `q` may hold with `d` centroids.
+    #[cfg(feature = "python")]
+    pub(crate) fn max_weight_fraction(self, q: f64, d: f64) -> f64 {
+        match self {
+            Self::Both => 2.0 * (2.0 * q.min(1.0 - q)).sqrt() / d,
+            Self::Upper => 3.0 * (1.0 - q).cbrt().powi(2) / d,
+            Self::Lower => 3.0 * q.cbrt().powi(2) / d,
+        }
+    }
+}
+
+/// Settings and tail sample that a digest merged from others inherits: the
+/// largest `tail_size`, a `resolution` all inputs agree on (or else both
+/// tails), and the merged tail samples if all non-empty inputs have one.
+struct Inherited {
+    tail_size: usize,
+    resolution: Resolution,
+    tails: Option<TailSample>,
+}
+
+impl Inherited {
+    fn from_digests<'a>(
+        digests: impl IntoIterator<Item = &'a TDigest>,
+    ) -> Result<Self, TryReserveError> {
+        let mut tail_size = 0;
+        let mut resolution: Option<Resolution> = None;
+        let mut tails: Option<TailSample> = None;
+        let mut exact = true;
+        for digest in digests {
+            tail_size = tail_size.max(digest.tail_size);
+            resolution = match resolution {
+                Some(r) if r != digest.resolution => Some(Resolution::Both),
+                _ => Some(digest.resolution),
+            };
+            match &digest.tails {
+                Some(other) => {
+                    tails = Some(match tails {
+                        Some(tails) => tails.merge(other)?,
+                        None => other.clone(),
+                    })
+                }
+                // digests without values don't affect the tails
+                None => exact &= digest.centroids.is_empty(),
+            }
+        }
+        Ok(Self {
+            tail_size,
+            resolution: resolution.unwrap_or_default(),
+            tails: tails.filter(|_| exact),
+        })
+    }
+
+    fn apply(self, digest: &mut TDigest) {
+        digest.tail_size = self.tail_size;
+        digest.resolution = self.resolution;
+        digest.tails = self.tails;
+    }
+}
+
+/// Positions (in merge order) of the inputs that are kept out of the
+/// compression: the first and last `tail_size` of `n` inputs.
+#[derive(Clone, Copy)]
+struct Tails {
+    head_end: usize,
+    tail_start: usize,
+}
+
+impl Tails {
+    fn new(tail_size: usize, n: usize) -> Self {
+        Self {
+            head_end: tail_size,
+            tail_start: n.saturating_sub(tail_size),
+        }
+    }
+
+    /// Whether the input at position `next` may be merged into the centroid
+    /// that was started by the input at position `curr`.
+    #[inline]
+    fn allow(self, curr: usize, next: usize) -> bool {
+        curr >= self.head_end && next < self.tail_start
+    }
+}
+
+/// The `k` lowest and highest values ingested (with their weights), which
+/// answer quantile queries in either tail exactly.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub struct TailSample {
+    k: usize,
+    /// ascending
+    lowest: Vec<Centroid>,
+    /// ascending
+    highest: Vec<Centroid>,
+}
+
+impl TailSample {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            lowest: Vec::new(),
+            highest: Vec::new(),
+        }
+    }
+
+    pub fn from_parts(
+        k: usize,
+        mut lowest: Vec<Centroid>,
+        mut highest: Vec<Centroid>,
+    ) -> Self {
+        lowest.sort();
+        lowest.truncate(k);
+        highest.sort();
+        highest.drain(..highest.len().saturating_sub(k));
+        Self { k, lowest, highest }
+    }
+
+    #[inline]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline]
+    pub fn lowest(&self) -> &[Centroid] {
+        &self.lowest
+    }
+
+    #[inline]
+    pub fn highest(&self) -> &[Centroid] {
+        &self.highest
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        (self.lowest.capacity() + self.highest.capacity())
+            * mem::size_of::<Centroid>()
+    }
+
+    /// Removes up to `weight` of the value `x` from the samples that hold
+    /// it.
+    fn remove(&mut self, x: f64, weight: f64) {
+        for sample in [&mut self.lowest, &mut self.highest] {
+            let mut left = weight;
+            sample.retain_mut(|c| {
+                if left > 0.0 && c.mean() == x {
+                    let taken = left.min(c.weight());
+                    c.weight = OrderedFloat(c.weight() - taken);
+                    left -= taken;
+                }
+                c.weight() > 0.0
+            });
+        }
+    }
+
+    /// Adds the values of the ascending sequence `sorted`.
+    fn add_sorted<I>(&mut self, sorted: I) -> Result<(), TryReserveError>
+    where
+        I: DoubleEndedIterator<Item = Centroid> + Clone,
+    {
+        let lowest = Self::merge_first(
+            self.lowest.iter().cloned(),
+            sorted.clone(),
+            self.k,
+            |a, b| a.mean <= b.mean,
+        )?;
+        let mut highest = Self::merge_first(
+            self.highest.iter().rev().cloned(),
+            sorted.rev(),
+            self.k,
+            |a, b| a.mean >= b.mean,
+        )?;
+        highest.reverse();
+        self.lowest = lowest;
+        self.highest = highest;
+        Ok(())
+    }
+
+    /// Combines the samples of two digests, keeping the smaller `k`.
+    fn merge(&self, other: &Self) -> Result<Self, TryReserveError> {
+        let mut merged = Self::new(self.k.min(other.k));
+        merged.lowest = Self::merge_first(
+            self.lowest.iter().cloned(),
+            other.lowest.iter().cloned(),
+            merged.k,
+            |a, b| a.mean <= b.mean,
+        )?;
+        merged.highest = Self::merge_first(
+            self.highest.iter().rev().cloned(),
+            other.highest.iter().rev().cloned(),
+            merged.k,
+            |a, b| a.mean >= b.mean,
+        )?;
+        merged.highest.reverse();
+        Ok(merged)
+    }
+
+    /// First `k` items of the merge of the sequences `a` and `b`, which are
+    /// both sorted such that `first(x, y)` holds for consecutive items.
+    fn merge_first(
+        a: impl Iterator<Item = Centroid>,
+        b: impl Iterator<Item = Centroid>,
+        k: usize,
+        first: impl Fn(&Centroid, &Centroid) -> bool,
+    ) -> Result<Vec<Centroid>, TryReserveError> {
+        let mut out: Vec<Centroid> = Vec::new();
+        out.try_reserve_exact(k)?;
+        let (mut a, mut b) = (a.peekable(), b.peekable());
+        while out.len() < k {
+            let next = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if first(x, y) => a.next(),
+                (Some(_), Some(_)) => b.next(),
+                (Some(_), None) => a.next(),
+                (None, _) => b.next(),
+            };
+            match next {
+                Some(c) => out.push(c),
+                None => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Exact quantile `q` of a digest of total weight `mass`, if it lies
+    /// within the stored values. Uses the same interpolation between the
+    /// centroid midpoints as the digest estimate.
+    fn quantile(&self, q: f64, mass: f64) -> Option<f64> {
+        if mass <= 1.0 {
+            return None;
+        }
+        let rank = |cumulative: f64, c: &Centroid| {
+            midpoint_rank(cumulative, c.weight(), mass)
+        };
+        let interpolate =
+            |q: f64, (q1, x1): (f64, f64), (q2, x2): (f64, f64)| {
+                if q2 == q1 {
+                    x2
+                } else {
+                    interpolate(x1, x2, (q - q1) / (q2 - q1))
+                }
+            };
+
+        let mut cumulative = 0.0;
+        let mut prev: Option<(f64, f64)> = None;
+        for c in &self.lowest {
+            let curr = (rank(cumulative, c), c.mean());
+            if curr.0 >= q {
+                return Some(prev.map_or(curr.1, |p| interpolate(q, p, curr)));
+            }
+            cumulative += c.weight();
+            prev = Some(curr);
+        }
+
+        let mut cumulative = 0.0;
+        let mut prev: Option<(f64, f64)> = None;
+        for c in self.highest.iter().rev() {
+            let curr = (1.0 - rank(cumulative, c), c.mean());
+            if curr.0 <= q {
+                return Some(prev.map_or(curr.1, |p| interpolate(q, curr, p)));
+            }
+            cumulative += c.weight();
+            prev = Some(curr);
+        }
+        None
+    }
+
+    /// Exact rank of `x` in a digest of total weight `mass`, if it lies
+    /// within the stored values: the inverse of `quantile`.
+    fn rank(&self, x: f64, mass: f64) -> Option<f64> {
+        if mass <= 1.0 {
+            return None;
+        }
+        let rank = |cumulative: f64, c: &Centroid| {
+            midpoint_rank(cumulative, c.weight(), mass)
+        };
+        let interpolate =
+            |x: f64, (q1, x1): (f64, f64), (q2, x2): (f64, f64)| {
+                interpolate(q1, q2, (x - x1) / (x2 - x1))
+            };
+
+        if self.lowest.last().is_some_and(|c| x <= c.mean()) {
+            let mut cumulative = 0.0;
+            let mut prev: Option<(f64, f64)> = None;
+            for c in &self.lowest {
+                let curr = (rank(cumulative, c), c.mean());
+                if curr.1 >= x {
+                    return Some(prev.map_or(0.0, |p| interpolate(x, p, curr)));
+                }
+                cumulative += c.weight();
+                prev = Some(curr);
+            }
+        }
+
+        if self.highest.first().is_some_and(|c| x > c.mean()) {
+            let mut cumulative = 0.0;
+            let mut next: Option<(f64, f64)> = None;
+            for c in self.highest.iter().rev() {
+                let curr = (1.0 - rank(cumulative, c), c.mean());
+                if curr.1 < x {
+                    return Some(next.map_or(1.0, |n| interpolate(x, curr, n)));
+                }
+                cumulative += c.weight();
+                next = Some(curr);
+            }
+        }
+        None
+    }
+
+    /// Ranks of the innermost values of the lowest and highest parts of
+    /// the sample, between which the digest estimates the ranks.
+    fn rank_range(&self, mass: f64) -> (f64, f64) {
+        let rank = |cumulative: f64, c: &Centroid| {
+            midpoint_rank(cumulative, c.weight(), mass)
+        };
+        // summed in the order of `quantile` to get the same ranks
+        let lo = self.lowest.split_last().map_or(0.0, |(c, rest)| {
+            rank(rest.iter().map(Centroid::weight).sum(), c)
+        });
+        let hi = self.highest.split_first().map_or(1.0, |(c, rest)| {
+            1.0 - rank(rest.iter().rev().map(Centroid::weight).sum(), c)
+        });
+        (lo, hi)
+    }
+
+    /// Clamps `x` to the range between the lowest and highest parts of the
+    /// sample, unless they overlap.
+    fn clamp(&self, x: f64) -> f64 {
+        let lo = self.lowest.last().map_or(f64::NEG_INFINITY, Centroid::mean);
+        let hi = self.highest.first().map_or(f64::INFINITY, Centroid::mean);
+        if lo <= hi {
+            x.max(lo).min(hi)
+        } else {
+            x
+        }
+    }
 }
 
 impl TDigest {
     const MAGIC: [u8; 8] = *b"FASTDIG~";
     const VERSION: u32 = 1;
-    const HEADER_BYTES: usize = 80; // beginning of centroids in binary format
+    pub const HEADER_BYTES: usize = 80; // start of centroids in binary format
+    pub const PREFIX_BYTES: usize = 20; // magic, version and centroids_len
     const PADDING_BYTES: usize = 4; // HEADER_BYTES - sum(used header bytes)
     const TARGET_DIGITS: u32 = 8;
     const RECOMP_THRESH: u128 = 10u128.pow(f64::DIGITS - Self::TARGET_DIGITS);
@@ -98,6 +567,10 @@ impl TDigest {
             min: OrderedFloat::from(f64::NAN),
             max: OrderedFloat::from(f64::NAN),
             count: 0,
+            tail_size: 0,
+            resolution: Resolution::Both,
+            tails: None,
+            ranks: RankCache::default(),
         })
     }
 
@@ -119,6 +592,10 @@ impl TDigest {
                 min: OrderedFloat::from(min),
                 max: OrderedFloat::from(max),
                 count,
+                tail_size: 0,
+                resolution: Resolution::Both,
+                tails: None,
+                ranks: RankCache::default(),
             })
         } else {
             let sz = centroids.len();
@@ -141,9 +618,7 @@ impl TDigest {
 
         fn validate_u64_size(value: u64) -> Result<usize, BytesError> {
             match value {
-                n if n > TD_SIZE_GLOBAL_MAX as u64 => {
-                    Err(BytesError::CorruptData)
-                }
+                n if n > TD_SIZE_GLOBAL_MAX => Err(BytesError::CorruptData),
                 n if n > TD_SIZE_PLATFORM_MAX as u64 => {
                     Err(BytesError::WrongArch)
                 }
@@ -200,7 +675,45 @@ impl TDigest {
             centroids.push(Centroid::new(mean, weight));
         }
 
-        Ok(Self {
+        Ok(Self::from_state(
+            centroids, max_size, mass, sum, min, max, count,
+        ))
+    }
+
+    /// Length of the binary representation that starts with `prefix`, its
+    /// first `PREFIX_BYTES` bytes, to read it from a stream.
+    pub fn bytes_len(prefix: &[u8]) -> Result<usize, BytesError> {
+        if prefix.len() < Self::PREFIX_BYTES || prefix[..8] != Self::MAGIC {
+            return Err(BytesError::WrongFormat);
+        }
+        let version = u32::from_le_bytes(prefix[8..12].try_into().unwrap());
+        if version != Self::VERSION {
+            return Err(BytesError::WrongVersion);
+        }
+        let centroids_len =
+            u64::from_le_bytes(prefix[12..20].try_into().unwrap());
+        if centroids_len > TD_SIZE_GLOBAL_MAX {
+            return Err(BytesError::CorruptData);
+        }
+        usize::try_from(centroids_len)
+            .ok()
+            .and_then(|n| n.checked_mul(16))
+            .and_then(|n| n.checked_add(Self::HEADER_BYTES))
+            .ok_or(BytesError::WrongArch)
+    }
+
+    /// Digest with the given centroids and totals, taken as they are (like
+    /// in `from_bytes`) instead of being compressed to `max_size`.
+    pub(crate) fn from_state(
+        centroids: Vec<Centroid>,
+        max_size: usize,
+        mass: f64,
+        sum: f64,
+        min: f64,
+        max: f64,
+        count: u128,
+    ) -> Self {
+        Self {
             centroids,
             max_size,
             mass: OrderedFloat::from(mass),
@@ -208,7 +721,11 @@ impl TDigest {
             min: OrderedFloat::from(min),
             max: OrderedFloat::from(max),
             count,
-        })
+            tail_size: 0,
+            resolution: Resolution::Both,
+            tails: None,
+            ranks: RankCache::default(),
+        }
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
@@ -235,6 +752,129 @@ impl TDigest {
         Ok(buf)
     }
 
+    /// Checks centroids loaded from outside: finite means in ascending
+    /// order and finite positive weights. The error describes the first
+    /// violation.
+    pub fn validate_centroids(centroids: &[Centroid]) -> Result<(), String> {
+        let mut prev = f64::NEG_INFINITY;
+        for (i, c) in centroids.iter().enumerate() {
+            let (mean, weight) = (c.mean(), c.weight());
+            if !mean.is_finite() {
+                return Err(format!(
+                    "Centroid {i} has a non-finite mean ({mean:?})."
+                ));
+            }
+            if !(weight.is_finite() && weight > 0.0) {
+                return Err(format!(
+                    "Centroid {i} has a weight of {weight:?}, but weights \
+                     must be finite and greater than 0."
+                ));
+            }
+            if mean < prev {
+                return Err(format!(
+                    "Centroid {i} is out of order: its mean {mean:?} is less \
+                     than the mean {prev:?} of centroid {}.",
+                    i - 1
+                ));
+            }
+            prev = mean;
+        }
+        Ok(())
+    }
+
+    /// Checks that a digest loaded from outside is consistent: valid
+    /// centroids (see `validate_centroids`), no more of them than
+    /// `max_size`, and totals that match them, with a count of values
+    /// between 1 and `u64::MAX` if there are centroids. The error describes
+    /// the first violation.
+    pub fn validate(&self) -> Result<(), String> {
+        Self::validate_centroids(&self.centroids)?;
+        let n = self.centroids.len();
+        if n > self.max_size {
+            return Err(format!(
+                "The digest has {n} centroids, more than max_centroids ({}).",
+                self.max_size
+            ));
+        }
+        for (name, value) in [("mass", self.mass()), ("sum", self.sum())] {
+            if !value.is_finite() {
+                return Err(format!("{name} must be finite, not {value:?}."));
+            }
+        }
+        let (Some(first), Some(last)) =
+            (self.centroids.first(), self.centroids.last())
+        else {
+            if self.mass() != 0.0 {
+                return Err(format!(
+                    "The digest has no centroids, but a mass of {:?}.",
+                    self.mass()
+                ));
+            }
+            if self.count != 0 {
+                return Err(format!(
+                    "The digest has no centroids, but n_values is {}.",
+                    self.count
+                ));
+            }
+            return Ok(());
+        };
+        // merges add up the counts, which must not come near an overflow
+        if self.count == 0 || self.count > u64::MAX as u128 {
+            return Err(format!(
+                "n_values must be between 1 and {} for a digest with \
+                 centroids, not {}.",
+                u64::MAX,
+                self.count
+            ));
+        }
+
+        // the totals are accumulated separately, so allow for rounding
+        const TOLERANCE: f64 = 1e-6;
+        let weights = self
+            .centroids
+            .iter()
+            .map(Centroid::weight)
+            .sum::<CompensatedSum>()
+            .value();
+        if (self.mass() - weights).abs() > TOLERANCE * weights {
+            return Err(format!(
+                "mass is {:?}, but the weights of the centroids sum to \
+                 {weights:?}.",
+                self.mass()
+            ));
+        }
+        let (mut sum, mut scale) =
+            (CompensatedSum::default(), CompensatedSum::default());
+        for c in &self.centroids {
+            let x = c.mean() * c.weight();
+            sum.add(x);
+            scale.add(x.abs());
+        }
+        let (sum, scale) = (sum.value(), scale.value());
+        if (self.sum() - sum).abs() > TOLERANCE * scale {
+            return Err(format!(
+                "sum is {:?}, but the weighted means of the centroids sum \
+                 to {sum:?}.",
+                self.sum()
+            ));
+        }
+        if self.min().is_nan() || self.min() > first.mean() {
+            return Err(format!(
+                "min is {:?}, but the smallest centroid mean is {:?}.",
+                self.min(),
+                first.mean()
+            ));
+        }
+        if self.max().is_nan() || self.max() < last.mean() {
+            return Err(format!(
+                "max is {:?}, but the largest centroid mean is {:?}.",
+                self.max(),
+                last.mean()
+            ));
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn mean(&self) -> f64 {
         self.sum() / self.mass()
@@ -245,6 +885,15 @@ impl TDigest {
         &self.centroids
     }
 
+    /// Number of bytes allocated on the heap for the centroids, the tail
+    /// sample and the cached prefix ranks.
+    #[inline]
+    pub fn heap_bytes(&self) -> usize {
+        self.centroids.capacity() * mem::size_of::<Centroid>()
+            + self.tails.as_ref().map_or(0, TailSample::heap_bytes)
+            + self.ranks.heap_bytes()
+    }
+
     #[inline]
     pub fn max_size(&self) -> usize {
         self.max_size
@@ -255,6 +904,59 @@ impl TDigest {
         self.max_size = max_size
     }
 
+    /// Sets `max_size` and, if there are more centroids than that, merges
+    /// them down to it. Returns whether the centroids were merged.
+    pub fn recompress(
+        &mut self,
+        max_size: usize,
+    ) -> Result<bool, TryReserveError> {
+        let merge = max_size > 0 && self.centroids.len() > max_size;
+        if merge {
+            *self = Self::merge_digests(vec![self.clone()], Some(max_size))?;
+        }
+        self.max_size = max_size;
+        Ok(merge)
+    }
+
+    /// Number of centroids at either end that are never merged.
+    #[inline]
+    pub fn tail_size(&self) -> usize {
+        self.tail_size
+    }
+
+    /// Sets the number of centroids at either end that are never merged,
+    /// taking effect from the next compression.
+    #[inline]
+    pub fn set_tail_size(&mut self, tail_size: usize) {
+        self.tail_size = tail_size
+    }
+
+    /// Part of the distribution that is resolved most finely.
+    #[inline]
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Sets the part of the distribution that is resolved most finely,
+    /// taking effect from the next compression.
+    #[inline]
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution
+    }
+
+    /// Exactly stored lowest and highest values, if enabled.
+    #[inline]
+    pub fn tails(&self) -> Option<&TailSample> {
+        self.tails.as_ref()
+    }
+
+    /// Replaces the exactly stored lowest and highest values. They are only
+    /// exact if they were collected from all values of the digest.
+    #[inline]
+    pub fn set_tails(&mut self, tails: Option<TailSample>) {
+        self.tails = tails
+    }
+
     #[inline]
     pub fn mass(&self) -> f64 {
         self.mass.into_inner()
@@ -294,49 +996,263 @@ impl Default for TDigest {
 }
 
 impl TDigest {
-    fn k_to_q(k: f64, d: f64) -> f64 {
-        let k_div_d = k / d;
-        if k_div_d >= 0.5 {
-            let base = 1.0 - k_div_d;
-            1.0 - 2.0 * base * base
-        } else {
-            2.0 * k_div_d * k_div_d
+    /// Moves the centroids into single-precision storage and frees their
+    /// buffer. Leaves the digest unchanged if the allocation fails.
+    pub fn pack_centroids(
+        &mut self,
+    ) -> Result<PackedCentroids, TryReserveError> {
+        self.ranks = RankCache::default();
+        let mut packed: Vec<[f32; 2]> = Vec::new();
+        packed.try_reserve_exact(self.centroids.len())?;
+        packed.extend(
+            self.centroids
+                .iter()
+                .map(|c| [c.mean() as f32, c.weight() as f32]),
+        );
+        self.centroids = Vec::new();
+        Ok(PackedCentroids(packed))
+    }
+
+    /// Restores the centroids from single-precision storage.
+    pub fn unpack_centroids(
+        &mut self,
+        packed: &PackedCentroids,
+    ) -> Result<(), TryReserveError> {
+        self.ranks = RankCache::default();
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids.try_reserve_exact(packed.0.len())?;
+        centroids.extend(
+            packed.0.iter().map(|&[mean, weight]| {
+                Centroid::new(mean as f64, weight as f64)
+            }),
+        );
+        self.centroids = centroids;
+        Ok(())
+    }
+
+    /// Multiplies the weights of all centroids (and the mass and sum) by
+    /// `factor`, which must be finite and positive. Centroids whose weight
+    /// falls below `floor` (or becomes too small to be represented) are
+    /// dropped, moving the min or max to the outermost remaining centroid.
+    pub fn scale_weights(&mut self, factor: f64, floor: f64) {
+        self.ranks = RankCache::default();
+        let floor = floor.max(f64::MIN_POSITIVE);
+        let scale = |centroids: &mut Vec<Centroid>| {
+            for centroid in centroids.iter_mut() {
+                centroid.weight = OrderedFloat(centroid.weight() * factor);
+            }
+            centroids.retain(|c| c.weight() >= floor);
+        };
+        if let Some(tails) = &mut self.tails {
+            scale(&mut tails.lowest);
+            scale(&mut tails.highest);
+        }
+        let dropped = |c: Option<&Centroid>| {
+            c.is_some_and(|c| c.weight() * factor < floor)
+        };
+        let (first_dropped, last_dropped) = (
+            dropped(self.centroids.first()),
+            dropped(self.centroids.last()),
+        );
+        let len = self.centroids.len();
+        scale(&mut self.centroids);
+        if self.centroids.len() == len {
+            self.mass = OrderedFloat(self.mass() * factor);
+            self.sum = OrderedFloat(self.sum() * factor);
+            return;
+        }
+        self.refresh_totals(first_dropped, last_dropped);
+    }
+
+    /// Approximately removes the weighted values `removed`, which should
+    /// have been added before, and `count` from the count of values. Each
+    /// weight is taken from the centroids with the nearest means, so a
+    /// value is removed exactly only where the centroids still resolve it;
+    /// the exact tail samples lose the values they hold. Returns the weight
+    /// that was left to remove once the digest was empty.
+    pub fn remove_weights(&mut self, removed: &[Centroid], count: u128) -> f64 {
+        self.ranks = RankCache::default();
+        let mut left = 0.0;
+        for c in removed {
+            left += self.take_nearest(c.mean(), c.weight());
+            if let Some(tails) = &mut self.tails {
+                tails.remove(c.mean(), c.weight());
+            }
+        }
+        let emptied =
+            |c: Option<&Centroid>| c.is_some_and(|c| c.weight() <= 0.0);
+        let (first_dropped, last_dropped) = (
+            emptied(self.centroids.first()),
+            emptied(self.centroids.last()),
+        );
+        self.centroids.retain(|c| c.weight() > 0.0);
+        self.count = match self.centroids.is_empty() {
+            true => 0,
+            false => self.count.saturating_sub(count),
+        };
+        self.refresh_totals(first_dropped, last_dropped);
+        left
+    }
+
+    /// Takes up to `weight` from the centroids nearest to `x`, leaving
+    /// emptied centroids with a weight of 0. Returns the weight left.
+    fn take_nearest(&mut self, x: f64, weight: f64) -> f64 {
+        let centroids = &mut self.centroids;
+        // candidates are centroids[lo - 1] below and centroids[hi] above
+        let mut lo = centroids.partition_point(|c| c.mean() < x);
+        let mut hi = lo;
+        let mut left = weight;
+        while left > 0.0 {
+            while lo > 0 && centroids[lo - 1].weight() <= 0.0 {
+                lo -= 1;
+            }
+            while hi < centroids.len() && centroids[hi].weight() <= 0.0 {
+                hi += 1;
+            }
+            let nearest = match (lo > 0, hi < centroids.len()) {
+                (true, true) => {
+                    let below = x - centroids[lo - 1].mean();
+                    let above = centroids[hi].mean() - x;
+                    if below <= above {
+                        lo - 1
+                    } else {
+                        hi
+                    }
+                }
+                (true, false) => lo - 1,
+                (false, true) => hi,
+                (false, false) => break,
+            };
+            let centroid = &mut centroids[nearest];
+            let taken = left.min(centroid.weight());
+            centroid.weight = OrderedFloat(centroid.weight() - taken);
+            left -= taken;
+        }
+        left
+    }
+
+    /// Recomputes the mass and sum from the centroids after some were
+    /// dropped, moving the min or max to the outermost remaining centroid
+    /// if the first or last one was among them.
+    fn refresh_totals(&mut self, first_dropped: bool, last_dropped: bool) {
+        self.recompute_totals();
+        match (self.centroids.first(), self.centroids.last()) {
+            (Some(new_first), Some(new_last)) => {
+                if first_dropped {
+                    self.min = new_first.mean;
+                }
+                if last_dropped {
+                    self.max = new_last.mean;
+                }
+            }
+            _ => {
+                self.min = OrderedFloat(f64::NAN);
+                self.max = OrderedFloat(f64::NAN);
+            }
         }
     }
 
-    pub fn merge_unsorted(
+    pub fn merge_unsorted<I>(
         &self,
-        unsorted_values: Vec<f64>,
+        unsorted_values: I,
+    ) -> Result<TDigest, TryReserveError>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        self.merge_collected(Self::collect_values(unsorted_values)?, 1)
+    }
+
+    pub fn merge_unsorted_weighted<I, J>(
+        &self,
+        unsorted_values: I,
+        unsorted_weights: J,
+    ) -> Result<TDigest, TryReserveError>
+    where
+        I: IntoIterator<Item = f64>,
+        J: IntoIterator<Item = f64>,
+    {
+        let pairs = Self::collect_pairs(unsorted_values, unsorted_weights)?;
+        self.merge_collected_weighted(pairs, 1)
+    }
+
+    /// Collects unsorted values into a buffer for `merge_collected`.
+    pub fn collect_values<I>(
+        unsorted_values: I,
+    ) -> Result<Vec<OrderedFloat<f64>>, TryReserveError>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let iter = unsorted_values.into_iter();
+        let (lower, upper) = iter.size_hint();
+        let mut values: Vec<OrderedFloat<f64>> = Vec::new();
+        values.try_reserve_exact(upper.unwrap_or(lower))?;
+        values.extend(iter.map(OrderedFloat::from));
+        Ok(values)
+    }
+
+    /// Collects unsorted (value, weight) pairs into a buffer for
+    /// `merge_collected_weighted`.
+    pub fn collect_pairs<I, J>(
+        unsorted_values: I,
+        unsorted_weights: J,
+    ) -> Result<Vec<(OrderedFloat<f64>, f64)>, TryReserveError>
+    where
+        I: IntoIterator<Item = f64>,
+        J: IntoIterator<Item = f64>,
+    {
+        let iter = unsorted_values.into_iter().zip(unsorted_weights);
+        let (lower, upper) = iter.size_hint();
+        let mut pairs: Vec<(OrderedFloat<f64>, f64)> = Vec::new();
+        pairs.try_reserve_exact(upper.unwrap_or(lower))?;
+        pairs.extend(
+            iter.map(|(value, weight)| (OrderedFloat::from(value), weight)),
+        );
+        Ok(pairs)
+    }
+
+    /// Sorts collected values in place, using up to `n_threads` threads.
+    pub fn sort_values(
+        values: &mut Vec<OrderedFloat<f64>>,
+        n_threads: usize,
+    ) -> Result<(), TryReserveError> {
+        par_sort_by_key(values, |v| *v, n_threads)
+    }
+
+    /// Sorts collected (value, weight) pairs in place by value, using up to
+    /// `n_threads` threads.
+    pub fn sort_pairs(
+        pairs: &mut Vec<(OrderedFloat<f64>, f64)>,
+        n_threads: usize,
+    ) -> Result<(), TryReserveError> {
+        par_sort_by_key(pairs, |a| a.0, n_threads)
+    }
+
+    /// Sorts the collected values in place, using up to `n_threads`
+    /// threads, and merges them.
+    pub fn merge_collected(
+        &self,
+        mut values: Vec<OrderedFloat<f64>>,
+        n_threads: usize,
     ) -> Result<TDigest, TryReserveError> {
-        if unsorted_values.is_empty() {
+        if values.is_empty() {
             return Ok(self.clone());
         }
+        Self::sort_values(&mut values, n_threads)?;
 
-        let mut sorted_values: Vec<OrderedFloat<f64>> = unsorted_values
-            .into_iter()
-            .map(OrderedFloat::from)
-            .collect();
-        sorted_values.sort();
-
-        self.merge_sorted(sorted_values)
+        self.merge_sorted(values)
     }
 
-    pub fn merge_unsorted_weighted(
+    /// Sorts the collected pairs in place, using up to `n_threads` threads,
+    /// and merges them.
+    pub fn merge_collected_weighted(
         &self,
-        unsorted_values: Vec<f64>,
-        unsorted_weights: Vec<f64>,
+        mut pairs: Vec<(OrderedFloat<f64>, f64)>,
+        n_threads: usize,
     ) -> Result<TDigest, TryReserveError> {
-        debug_assert_eq!(unsorted_values.len(), unsorted_weights.len());
-        if unsorted_values.is_empty() {
+        if pairs.is_empty() {
             return Ok(self.clone());
         }
-
-        let mut pairs: Vec<(OrderedFloat<f64>, f64)> = unsorted_values
-            .into_iter()
-            .zip(unsorted_weights)
-            .map(|(value, weight)| (OrderedFloat::from(value), weight))
-            .collect();
-        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        Self::sort_pairs(&mut pairs, n_threads)?;
 
         self.merge_sorted_weighted(pairs)
     }
@@ -350,7 +1266,18 @@ impl TDigest {
         }
 
         let mut result = TDigest::new_with_size(self.max_size)?;
-        result.count = self.count + sorted_values.len() as u128;
+        result.tail_size = self.tail_size;
+        result.resolution = self.resolution;
+        if let Some(tails) = &self.tails {
+            let mut tails = tails.clone();
+            tails.add_sorted(
+                sorted_values
+                    .iter()
+                    .map(|v| Centroid::new(v.into_inner(), 1.0)),
+            )?;
+            result.tails = Some(tails);
+        }
+        result.count = self.count.saturating_add(sorted_values.len() as u128);
         result.mass =
             OrderedFloat::from(self.mass() + (sorted_values.len() as f64));
 
@@ -370,9 +1297,14 @@ impl TDigest {
 
         let mut k_limit: f64 = 1.0;
         let mut q_limit_times_mass: f64 =
-            Self::k_to_q(k_limit, self.max_size as f64) * result.mass();
+            self.resolution.k_to_q(k_limit, self.max_size as f64)
+                * result.mass();
         k_limit += 1.0;
 
+        let tails = Tails::new(
+            self.tail_size,
+            self.centroids.len() + sorted_values.len(),
+        );
         let mut iter_centroids = self.centroids.iter().peekable();
         let mut iter_sorted_values = sorted_values.iter().peekable();
 
@@ -392,6 +1324,7 @@ impl TDigest {
         let mut weight_so_far: f64 = curr.weight();
         let mut sums_to_merge: f64 = 0.0;
         let mut weights_to_merge: f64 = 0.0;
+        let (mut curr_pos, mut next_pos) = (0, 0);
 
         while iter_centroids.peek().is_some()
             || iter_sorted_values.peek().is_some()
@@ -417,8 +1350,10 @@ impl TDigest {
 
             let next_sum: f64 = next.mean() * next.weight();
             weight_so_far += next.weight();
+            next_pos += 1;
 
-            if weight_so_far <= q_limit_times_mass {
+            let within_limit = weight_so_far <= q_limit_times_mass;
+            if within_limit && tails.allow(curr_pos, next_pos) {
                 sums_to_merge += next_sum;
                 weights_to_merge += next.weight();
             } else {
@@ -429,10 +1364,14 @@ impl TDigest {
                 weights_to_merge = 0.0;
 
                 compressed.push(curr.clone());
-                q_limit_times_mass =
-                    Self::k_to_q(k_limit, self.max_size as f64) * result.mass();
-                k_limit += 1.0;
+                if !within_limit {
+                    q_limit_times_mass =
+                        self.resolution.k_to_q(k_limit, self.max_size as f64)
+                            * result.mass();
+                    k_limit += 1.0;
+                }
                 curr = next;
+                curr_pos = next_pos;
             }
         }
 
@@ -457,13 +1396,27 @@ impl TDigest {
             return Ok(self.clone());
         }
 
-        let total_new_weight: f64 = sorted_values_weights
+        let total_new_weight = sorted_values_weights
             .iter()
             .map(|(_, weight)| *weight)
-            .sum();
+            .sum::<CompensatedSum>()
+            .value();
 
         let mut result = TDigest::new_with_size(self.max_size)?;
-        result.count = self.count + sorted_values_weights.len() as u128;
+        result.tail_size = self.tail_size;
+        result.resolution = self.resolution;
+        if let Some(tails) = &self.tails {
+            let mut tails = tails.clone();
+            tails.add_sorted(
+                sorted_values_weights
+                    .iter()
+                    .map(|&(v, w)| Centroid::new(v.into_inner(), w)),
+            )?;
+            result.tails = Some(tails);
+        }
+        result.count = self
+            .count
+            .saturating_add(sorted_values_weights.len() as u128);
         result.mass = OrderedFloat::from(self.mass() + total_new_weight);
 
         let maybe_min = sorted_values_weights.first().unwrap().0;
@@ -482,9 +1435,14 @@ impl TDigest {
 
         let mut k_limit: f64 = 1.0;
         let mut q_limit_times_mass: f64 =
-            Self::k_to_q(k_limit, self.max_size as f64) * result.mass();
+            self.resolution.k_to_q(k_limit, self.max_size as f64)
+                * result.mass();
         k_limit += 1.0;
 
+        let tails = Tails::new(
+            self.tail_size,
+            self.centroids.len() + sorted_values_weights.len(),
+        );
         let mut iter_centroids = self.centroids.iter().peekable();
         let mut iter_values_weights = sorted_values_weights.iter().peekable();
 
@@ -503,6 +1461,7 @@ impl TDigest {
         let mut weight_so_far: f64 = curr.weight();
         let mut sums_to_merge: f64 = 0.0;
         let mut weights_to_merge: f64 = 0.0;
+        let (mut curr_pos, mut next_pos) = (0, 0);
 
         while iter_centroids.peek().is_some()
             || iter_values_weights.peek().is_some()
@@ -524,8 +1483,10 @@ impl TDigest {
 
             let next_sum: f64 = next.mean() * next.weight();
             weight_so_far += next.weight();
+            next_pos += 1;
 
-            if weight_so_far <= q_limit_times_mass {
+            let within_limit = weight_so_far <= q_limit_times_mass;
+            if within_limit && tails.allow(curr_pos, next_pos) {
                 sums_to_merge += next_sum;
                 weights_to_merge += next.weight();
             } else {
@@ -536,10 +1497,14 @@ impl TDigest {
                 weights_to_merge = 0.0;
 
                 compressed.push(curr.clone());
-                q_limit_times_mass =
-                    Self::k_to_q(k_limit, self.max_size as f64) * result.mass();
-                k_limit += 1.0;
+                if !within_limit {
+                    q_limit_times_mass =
+                        self.resolution.k_to_q(k_limit, self.max_size as f64)
+                            * result.mass();
+                    k_limit += 1.0;
+                }
                 curr = next;
+                curr_pos = next_pos;
             }
         }
 
@@ -617,129 +1582,686 @@ impl TDigest {
                 .max()
                 .unwrap_or(TD_SIZE_DEFAULT)
         };
-
-        let n_centroids: usize =
-            digests.iter().map(|d| d.centroids.len()).sum();
-        if n_centroids == 0 {
-            return TDigest::new_with_size(max_size);
+
+        let inherited = Inherited::from_digests(&digests)?;
+        let n_centroids: usize =
+            digests.iter().map(|d| d.centroids.len()).sum();
+        if n_centroids == 0 {
+            let mut result = TDigest::new_with_size(max_size)?;
+            inherited.apply(&mut result);
+            return Ok(result);
+        }
+
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids.try_reserve_exact(n_centroids)?;
+        let mut starts: Vec<usize> = Vec::new();
+        starts.try_reserve_exact(digests.len())?;
+
+        let count = digests
+            .iter()
+            .map(|d| d.count)
+            .fold(0, u128::saturating_add);
+        let max_count: u128 = digests.iter().map(|d| d.count).max().unwrap();
+
+        let mut mass = CompensatedSum::default();
+        let mut min = OrderedFloat::from(f64::INFINITY);
+        let mut max = OrderedFloat::from(f64::NEG_INFINITY);
+
+        let mut start: usize = 0;
+        for digest in digests.into_iter() {
+            starts.push(start);
+
+            let curr_mass: f64 = digest.mass();
+            if curr_mass > 0.0 {
+                min = std::cmp::min(min, digest.min);
+                max = std::cmp::max(max, digest.max);
+                mass.add(curr_mass);
+                for centroid in digest.centroids {
+                    centroids.push(centroid);
+                    start += 1;
+                }
+            }
+        }
+        let mass = mass.value();
+
+        let mut digests_per_block: usize = 1;
+        while digests_per_block < starts.len() {
+            for i in (0..starts.len()).step_by(digests_per_block * 2) {
+                if i + digests_per_block < starts.len() {
+                    let first = starts[i];
+                    let middle = starts[i + digests_per_block];
+                    let last = if i + 2 * digests_per_block < starts.len() {
+                        starts[i + 2 * digests_per_block]
+                    } else {
+                        centroids.len()
+                    };
+
+                    debug_assert!(first <= middle && middle <= last);
+                    Self::external_merge(&mut centroids, first, middle, last)?;
+                }
+            }
+
+            digests_per_block *= 2;
+        }
+
+        let n_centroids = centroids.len();
+        let mut result = Self::compress_merged(
+            centroids.into_iter(),
+            n_centroids,
+            mass,
+            max_size,
+            inherited,
+        )?;
+        result.min = min;
+        result.max = max;
+        result.count = count;
+
+        result.maybe_recompute_totals(max_count);
+
+        Ok(result)
+    }
+
+    /// Variant of `merge_digests` that borrows the digests and merges their
+    /// centroid lists with a heap instead of concatenating and sorting them
+    /// first, so that the memory needed besides the inputs is bounded by the
+    /// size of the result. The result is identical to that of
+    /// `merge_digests`.
+    pub fn merge_digests_streaming(
+        digests: &[&TDigest],
+        max_size: Option<usize>,
+    ) -> Result<TDigest, TryReserveError> {
+        let max_size = max_size.unwrap_or_else(|| {
+            digests
+                .iter()
+                .map(|digest| digest.max_size)
+                .max()
+                .unwrap_or(TD_SIZE_DEFAULT)
+        });
+
+        let inherited = Inherited::from_digests(digests.iter().copied())?;
+        let count = digests
+            .iter()
+            .map(|d| d.count)
+            .fold(0, u128::saturating_add);
+        let max_count: u128 =
+            digests.iter().map(|d| d.count).max().unwrap_or(0);
+
+        let mut mass = CompensatedSum::default();
+        let mut min = OrderedFloat::from(f64::INFINITY);
+        let mut max = OrderedFloat::from(f64::NEG_INFINITY);
+        let mut n_centroids: usize = 0;
+        // (mean, digest index, position) of the next centroid of each input,
+        // which orders equal means like the stable merge of `merge_digests`
+        let mut heap: BinaryHeap<Reverse<(OrderedFloat<f64>, usize, usize)>> =
+            BinaryHeap::new();
+        heap.try_reserve_exact(digests.len())?;
+        for (i, digest) in digests.iter().enumerate() {
+            let curr_mass: f64 = digest.mass();
+            if curr_mass > 0.0 {
+                min = std::cmp::min(min, digest.min);
+                max = std::cmp::max(max, digest.max);
+                mass.add(curr_mass);
+                n_centroids += digest.centroids.len();
+                if let Some(first) = digest.centroids.first() {
+                    heap.push(Reverse((first.mean, i, 0)));
+                }
+            }
+        }
+
+        if n_centroids == 0 {
+            let mut result = TDigest::new_with_size(max_size)?;
+            inherited.apply(&mut result);
+            return Ok(result);
+        }
+
+        let merged = iter::from_fn(|| {
+            let Reverse((_, i, pos)) = heap.pop()?;
+            let centroids = &digests[i].centroids;
+            if let Some(next) = centroids.get(pos + 1) {
+                heap.push(Reverse((next.mean, i, pos + 1)));
+            }
+            Some(centroids[pos].clone())
+        });
+        let mut result = Self::compress_merged(
+            merged,
+            n_centroids,
+            mass.value(),
+            max_size,
+            inherited,
+        )?;
+        result.min = min;
+        result.max = max;
+        result.count = count;
+
+        result.maybe_recompute_totals(max_count);
+
+        Ok(result)
+    }
+
+    /// Compresses the sorted centroids of merged digests, `n_centroids` in
+    /// total with a `mass`, into a new digest with the inherited settings.
+    /// The caller sets `min`, `max` and `count`.
+    fn compress_merged(
+        mut centroids: impl Iterator<Item = Centroid>,
+        n_centroids: usize,
+        mass: f64,
+        max_size: usize,
+        inherited: Inherited,
+    ) -> Result<TDigest, TryReserveError> {
+        let mut result = TDigest::new_with_size(max_size)?;
+        let tails = Tails::new(inherited.tail_size, n_centroids);
+        let resolution = inherited.resolution;
+        inherited.apply(&mut result);
+        let mut compressed: Vec<Centroid> = Vec::new();
+        compressed.try_reserve_exact(max_size)?;
+
+        let mut k_limit: f64 = 1.0;
+        let mut q_limit_times_mass: f64 =
+            resolution.k_to_q(k_limit, max_size as f64) * mass;
+
+        let mut curr = match centroids.next() {
+            Some(centroid) => centroid,
+            None => return Ok(result),
+        };
+        let mut weight_so_far: f64 = curr.weight();
+        let mut sums_to_merge: f64 = 0.0;
+        let mut weights_to_merge: f64 = 0.0;
+        let mut curr_pos = 0;
+
+        for (next_pos, centroid) in centroids.enumerate() {
+            weight_so_far += centroid.weight();
+
+            let within_limit = weight_so_far <= q_limit_times_mass;
+            if within_limit && tails.allow(curr_pos, next_pos + 1) {
+                sums_to_merge += centroid.mean() * centroid.weight();
+                weights_to_merge += centroid.weight();
+            } else {
+                result.sum = OrderedFloat::from(
+                    result.sum() + curr.add(sums_to_merge, weights_to_merge),
+                );
+                sums_to_merge = 0.0;
+                weights_to_merge = 0.0;
+                compressed.push(curr);
+                if !within_limit {
+                    q_limit_times_mass =
+                        resolution.k_to_q(k_limit, max_size as f64) * mass;
+                    k_limit += 1.0;
+                }
+                curr = centroid;
+                curr_pos = next_pos + 1;
+            }
+        }
+
+        result.sum = OrderedFloat::from(
+            result.sum() + curr.add(sums_to_merge, weights_to_merge),
+        );
+        compressed.push(curr);
+        compressed.shrink_to_fit();
+        compressed.sort();
+
+        result.centroids = compressed;
+        result.mass = OrderedFloat::from(mass);
+
+        Ok(result)
+    }
+
+    /// Total order on the contents of digests. Merging digests sorted by it
+    /// gives the same result regardless of the order they were given in.
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        let key = |d: &Self| {
+            (
+                d.count,
+                d.mass,
+                d.sum,
+                d.min,
+                d.max,
+                d.max_size,
+                d.tail_size,
+                d.resolution,
+            )
+        };
+        self.centroids
+            .iter()
+            .map(|c| (c.mean, c.weight))
+            .cmp(other.centroids.iter().map(|c| (c.mean, c.weight)))
+            .then_with(|| key(self).cmp(&key(other)))
+    }
+
+    /// Tree reduction of `merge_digests` on up to `n_threads` threads: each
+    /// thread merges a contiguous chunk of the digests, and the partial
+    /// results are merged at the end.
+    pub fn merge_digests_parallel(
+        digests: Vec<TDigest>,
+        max_size: Option<usize>,
+        n_threads: usize,
+    ) -> Result<TDigest, TryReserveError> {
+        let max_size = max_size.unwrap_or_else(|| {
+            digests
+                .iter()
+                .map(|digest| digest.max_size)
+                .max()
+                .unwrap_or(TD_SIZE_DEFAULT)
+        });
+        // every thread should merge at least two digests
+        let n_threads = thread_limit(n_threads).min(digests.len() / 2);
+        if n_threads <= 1 {
+            return Self::merge_digests(digests, Some(max_size));
+        }
+
+        let chunk_size = digests.len().div_ceil(n_threads);
+        let mut digests = digests.into_iter();
+        let partials = thread::scope(|scope| {
+            let handles: Vec<_> = (0..n_threads)
+                .map(|_| {
+                    let chunk: Vec<TDigest> =
+                        digests.by_ref().take(chunk_size).collect();
+                    scope.spawn(move || {
+                        Self::merge_digests(chunk, Some(max_size))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("merge thread panicked"))
+                .collect::<Result<Vec<TDigest>, TryReserveError>>()
+        })?;
+
+        Self::merge_digests(partials, Some(max_size))
+    }
+
+    /// Merges unsorted values into the digest by splitting them into up to
+    /// `n_threads` chunks, which are sorted (unless `presorted`) and
+    /// clustered into digests in parallel, and merging these with the
+    /// digest in one pass. Unlike `sort_values` with `merge_sorted_inplace`,
+    /// this spreads the clustering over the threads as well, at the cost of
+    /// the small error of a merge.
+    pub fn merge_chunks_inplace(
+        &mut self,
+        values: &mut [OrderedFloat<f64>],
+        n_threads: usize,
+        presorted: bool,
+    ) -> Result<(), TryReserveError> {
+        self.merge_chunks(values, n_threads, |chunk, digest| {
+            if !presorted {
+                chunk.sort();
+            }
+            digest.merge_sorted_inplace(chunk)
+        })
+    }
+
+    /// Weighted variant of `merge_chunks_inplace`.
+    pub fn merge_weighted_chunks_inplace(
+        &mut self,
+        pairs: &mut [(OrderedFloat<f64>, f64)],
+        n_threads: usize,
+        presorted: bool,
+    ) -> Result<(), TryReserveError> {
+        self.merge_chunks(pairs, n_threads, |chunk, digest| {
+            if !presorted {
+                chunk.sort_by_key(|pair| pair.0);
+            }
+            digest.merge_sorted_weighted_inplace(chunk)
+        })
+    }
+
+    /// Implementation of `merge_chunks_inplace`, with `build` merging a
+    /// chunk into an empty digest with the settings of this one.
+    fn merge_chunks<T, F>(
+        &mut self,
+        items: &mut [T],
+        n_threads: usize,
+        build: F,
+    ) -> Result<(), TryReserveError>
+    where
+        T: Send,
+        F: Fn(&mut [T], &mut TDigest) -> Result<(), TryReserveError> + Sync,
+    {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let n_threads = thread_limit(n_threads)
+            .min(items.len() / PAR_CHUNK_MIN_LEN)
+            .max(1);
+        if n_threads == 1 {
+            return build(items, self);
+        }
+        let chunk_size = items.len().div_ceil(n_threads);
+        let empty = || {
+            let mut digest = TDigest::new_with_size(self.max_size)?;
+            digest.tail_size = self.tail_size;
+            digest.resolution = self.resolution;
+            digest.tails = self.tails.as_ref().map(|t| TailSample::new(t.k()));
+            Ok::<_, TryReserveError>(digest)
+        };
+        let build = &build;
+        let partials = thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks_mut(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let mut digest = empty()?;
+                        build(chunk, &mut digest)?;
+                        Ok(digest)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("merge thread panicked"))
+                .collect::<Result<Vec<TDigest>, TryReserveError>>()
+        })?;
+
+        let mut digests: Vec<TDigest> = Vec::new();
+        digests.try_reserve_exact(partials.len() + 1)?;
+        digests.push(self.clone());
+        digests.extend(partials);
+        *self = Self::merge_digests(digests, Some(self.max_size))?;
+        Ok(())
+    }
+
+    /// In-place variant of `merge_sorted`: the values are merged into the
+    /// existing centroid buffer instead of a new digest.
+    pub fn merge_sorted_inplace(
+        &mut self,
+        sorted_values: &[OrderedFloat<f64>],
+    ) -> Result<(), TryReserveError> {
+        self.ranks = RankCache::default();
+        let (Some(&maybe_min), Some(&maybe_max)) =
+            (sorted_values.first(), sorted_values.last())
+        else {
+            return Ok(());
+        };
+        let mass = self.mass() + sorted_values.len() as f64;
+        let incoming = sorted_values
+            .iter()
+            .map(|value| Centroid::new(value.into_inner(), 1.0));
+        if let Some(tails) = &mut self.tails {
+            tails.add_sorted(incoming.clone())?;
+        }
+        self.merge_new_values_inplace(
+            incoming,
+            mass,
+            sorted_values.len(),
+            maybe_min,
+            maybe_max,
+        )
+    }
+
+    /// In-place variant of `merge_sorted_weighted`.
+    pub fn merge_sorted_weighted_inplace(
+        &mut self,
+        sorted_values_weights: &[(OrderedFloat<f64>, f64)],
+    ) -> Result<(), TryReserveError> {
+        self.ranks = RankCache::default();
+        let (Some(&(maybe_min, _)), Some(&(maybe_max, _))) =
+            (sorted_values_weights.first(), sorted_values_weights.last())
+        else {
+            return Ok(());
+        };
+        let total_new_weight = sorted_values_weights
+            .iter()
+            .map(|(_, weight)| *weight)
+            .sum::<CompensatedSum>()
+            .value();
+        let mass = self.mass() + total_new_weight;
+        let incoming = sorted_values_weights
+            .iter()
+            .map(|&(value, weight)| Centroid::new(value.into_inner(), weight));
+        if let Some(tails) = &mut self.tails {
+            tails.add_sorted(incoming.clone())?;
         }
+        self.merge_new_values_inplace(
+            incoming,
+            mass,
+            sorted_values_weights.len(),
+            maybe_min,
+            maybe_max,
+        )
+    }
 
-        let mut centroids: Vec<Centroid> = Vec::new();
-        centroids.try_reserve_exact(n_centroids)?;
-        let mut starts: Vec<usize> = Vec::new();
-        starts.try_reserve_exact(digests.len())?;
+    fn merge_new_values_inplace<I>(
+        &mut self,
+        incoming: I,
+        mass: f64,
+        n_values: usize,
+        maybe_min: OrderedFloat<f64>,
+        maybe_max: OrderedFloat<f64>,
+    ) -> Result<(), TryReserveError>
+    where
+        I: ExactSizeIterator<Item = Centroid>,
+    {
+        if self.mass() > 0.0 {
+            self.min = std::cmp::min(self.min, maybe_min);
+            self.max = std::cmp::max(self.max, maybe_max);
+        } else {
+            self.min = maybe_min;
+            self.max = maybe_max;
+        }
+        let old_count = self.count;
+        self.sum = OrderedFloat::from(
+            self.compress_inplace(incoming, mass, 2.0, true)?,
+        );
+        self.mass = OrderedFloat::from(mass);
+        self.count = old_count.saturating_add(n_values as u128);
+        self.maybe_recompute_totals(old_count);
+        Ok(())
+    }
 
-        let count: u128 = digests.iter().map(|d| d.count).sum();
-        let max_count: u128 = digests.iter().map(|d| d.count).max().unwrap();
+    /// In-place variant of `merge_digests` for this digest and `other`,
+    /// keeping the `max_size` of this digest.
+    pub fn merge_digest_inplace(
+        &mut self,
+        other: &TDigest,
+    ) -> Result<(), TryReserveError> {
+        self.ranks = RankCache::default();
+        let count = self.count.saturating_add(other.count);
+        let max_count = self.count.max(other.count);
+        let inherited = Inherited::from_digests([&*self, other])?;
+        if self.centroids.is_empty() && other.centroids.is_empty() {
+            *self = TDigest::new_with_size(self.max_size)?;
+            inherited.apply(self);
+            return Ok(());
+        }
+        inherited.apply(self);
 
         let mut mass: f64 = 0.0;
         let mut min = OrderedFloat::from(f64::INFINITY);
         let mut max = OrderedFloat::from(f64::NEG_INFINITY);
-
-        let mut start: usize = 0;
-        for digest in digests.into_iter() {
-            starts.push(start);
-
-            let curr_mass: f64 = digest.mass();
-            if curr_mass > 0.0 {
+        for digest in [&*self, other] {
+            if digest.mass() > 0.0 {
                 min = std::cmp::min(min, digest.min);
                 max = std::cmp::max(max, digest.max);
-                mass += curr_mass;
-                for centroid in digest.centroids {
-                    centroids.push(centroid);
-                    start += 1;
-                }
+                mass += digest.mass();
             }
         }
+        if self.mass() <= 0.0 {
+            self.centroids.clear();
+        }
+        let incoming: &[Centroid] = if other.mass() > 0.0 {
+            &other.centroids
+        } else {
+            &[]
+        };
 
-        let mut digests_per_block: usize = 1;
-        while digests_per_block < starts.len() {
-            for i in (0..starts.len()).step_by(digests_per_block * 2) {
-                if i + digests_per_block < starts.len() {
-                    let first = starts[i];
-                    let middle = starts[i + digests_per_block];
-                    let last = if i + 2 * digests_per_block < starts.len() {
-                        starts[i + 2 * digests_per_block]
-                    } else {
-                        centroids.len()
-                    };
-
-                    debug_assert!(first <= middle && middle <= last);
-                    Self::external_merge(&mut centroids, first, middle, last)?;
-                }
-            }
+        self.sum = OrderedFloat::from(self.compress_inplace(
+            incoming.iter().cloned(),
+            mass,
+            1.0,
+            false,
+        )?);
+        self.mass = OrderedFloat::from(mass);
+        self.min = min;
+        self.max = max;
+        self.count = count;
+        self.maybe_recompute_totals(max_count);
+        Ok(())
+    }
 
-            digests_per_block *= 2;
+    /// Merges the sorted `incoming` centroids with the existing ones and
+    /// compresses the result, reusing the centroid buffer. Returns the sum
+    /// of the compressed centroids.
+    ///
+    /// The existing centroids are moved behind a gap of free slots, into
+    /// which the compressed centroids are written as they are produced;
+    /// only if the gap fills up is it widened. `k_next` is the
+    /// first k limit after the initial one, and `incoming_first` decides
+    /// ties between existing and incoming centroids, so that the result is
+    /// the same as that of `merge_sorted` or `merge_digests`. If an
+    /// allocation fails, the digest is left empty.
+    fn compress_inplace<I>(
+        &mut self,
+        incoming: I,
+        mass: f64,
+        k_next: f64,
+        incoming_first: bool,
+    ) -> Result<f64, TryReserveError>
+    where
+        I: ExactSizeIterator<Item = Centroid>,
+    {
+        let max_size = self.max_size;
+        let resolution = self.resolution;
+        // each compressed centroid consumes at least one input, so the
+        // number of incoming centroids is enough room as well
+        let n_incoming = incoming.len();
+        let gap = max_size.min(n_incoming).max(1);
+        let mut buf = mem::take(&mut self.centroids);
+        let n_existing = buf.len();
+        let tails = Tails::new(self.tail_size, n_existing + n_incoming);
+        if let Err(error) = buf.try_reserve(gap) {
+            self.centroids = buf;
+            return Err(error);
         }
+        buf.resize(n_existing + gap, Centroid::default());
+        buf.rotate_right(gap);
+
+        let mut read = gap;
+        let mut write = 0;
+        let mut incoming = incoming.peekable();
+        let mut pop = |buf: &[Centroid], read: &mut usize| {
+            let existing = buf.get(*read);
+            let take_existing = match (existing, incoming.peek()) {
+                (Some(c), Some(next)) if incoming_first => c.mean < next.mean,
+                (Some(c), Some(next)) => c.mean <= next.mean,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if take_existing {
+                *read += 1;
+                existing.cloned()
+            } else {
+                incoming.next()
+            }
+        };
 
-        let mut result = TDigest::new_with_size(max_size)?;
-        let mut compressed: Vec<Centroid> = Vec::new();
-        compressed.try_reserve_exact(max_size)?;
+        let Some(mut curr) = pop(&buf, &mut read) else {
+            return Ok(0.0);
+        };
 
         let mut k_limit: f64 = 1.0;
         let mut q_limit_times_mass: f64 =
-            Self::k_to_q(k_limit, max_size as f64) * mass;
+            resolution.k_to_q(k_limit, max_size as f64) * mass;
+        k_limit = k_next;
 
-        let mut iter_centroids = centroids.iter_mut();
-        let mut curr = iter_centroids.next().unwrap();
+        let mut sum: f64 = 0.0;
         let mut weight_so_far: f64 = curr.weight();
         let mut sums_to_merge: f64 = 0.0;
         let mut weights_to_merge: f64 = 0.0;
+        let (mut curr_pos, mut next_pos) = (0, 0);
 
-        for centroid in iter_centroids {
-            weight_so_far += centroid.weight();
+        while let Some(next) = pop(&buf, &mut read) {
+            weight_so_far += next.weight();
+            next_pos += 1;
 
-            if weight_so_far <= q_limit_times_mass {
-                sums_to_merge += centroid.mean() * centroid.weight();
-                weights_to_merge += centroid.weight();
+            let within_limit = weight_so_far <= q_limit_times_mass;
+            if within_limit && tails.allow(curr_pos, next_pos) {
+                sums_to_merge += next.mean() * next.weight();
+                weights_to_merge += next.weight();
             } else {
-                result.sum = OrderedFloat::from(
-                    result.sum() + curr.add(sums_to_merge, weights_to_merge),
-                );
+                sum += curr.add(sums_to_merge, weights_to_merge);
                 sums_to_merge = 0.0;
                 weights_to_merge = 0.0;
-                compressed.push(curr.clone());
-                q_limit_times_mass =
-                    Self::k_to_q(k_limit, max_size as f64) * mass;
-                k_limit += 1.0;
-                curr = centroid;
+
+                if write == read {
+                    // the gap is full: widen it before overwriting
+                    let extra = gap.max(buf.len() - read);
+                    if let Err(error) = buf.try_reserve(extra) {
+                        *self = TDigest {
+                            centroids: Vec::new(),
+                            max_size,
+                            mass: OrderedFloat::from(0.0),
+                            sum: OrderedFloat::from(0.0),
+                            min: OrderedFloat::from(f64::NAN),
+                            max: OrderedFloat::from(f64::NAN),
+                            count: 0,
+                            tail_size: self.tail_size,
+                            resolution: self.resolution,
+                            tails: self
+                                .tails
+                                .as_ref()
+                                .map(|t| TailSample::new(t.k)),
+                            ranks: RankCache::default(),
+                        };
+                        return Err(error);
+                    }
+                    buf.splice(
+                        read..read,
+                        iter::repeat_n(Centroid::default(), extra),
+                    );
+                    read += extra;
+                }
+                buf[write] = curr;
+                write += 1;
+                if !within_limit {
+                    q_limit_times_mass =
+                        resolution.k_to_q(k_limit, max_size as f64) * mass;
+                    k_limit += 1.0;
+                }
+                curr = next;
+                curr_pos = next_pos;
             }
         }
 
-        result.sum = OrderedFloat::from(
-            result.sum() + curr.add(sums_to_merge, weights_to_merge),
-        );
-        compressed.push(curr.clone());
-        compressed.shrink_to_fit();
-        compressed.sort();
-
-        result.centroids = compressed;
-        result.mass = OrderedFloat::from(mass);
-        result.min = min;
-        result.max = max;
-        result.count = count;
-
-        result.maybe_recompute_totals(max_count);
+        sum += curr.add(sums_to_merge, weights_to_merge);
+        buf.truncate(write);
+        buf.push(curr);
+        buf.sort();
 
-        Ok(result)
+        self.centroids = buf;
+        Ok(sum)
     }
 
     /// Function by Andy Lok (https://github.com/andylokandy/tdigests)
+    ///
+    /// The estimate is non-decreasing in `q` and within `min..=max`, also
+    /// if rounding has left neighboring centroids with (nearly) the same
+    /// mean slightly out of order; it matches `estimate_quantiles` exactly.
     pub fn estimate_quantile(&self, q: f64) -> f64 {
+        if let Some(x) = self.exact_quantile(q) {
+            return x;
+        }
         if self.centroids.len() == 1 {
-            return self.centroids[0].mean();
+            return self.clamp_estimate(self.centroids[0].mean());
+        }
+        if let Some(prefix) = self.ranks.get(self) {
+            return self.quantile_from_prefix(prefix, q);
         }
 
         let mut cumulative = 0.0;
-        let mut cum_left = 0.0;
-        let mut cum_right = 0.0;
+        let mut cum_left = f64::NEG_INFINITY;
+        let mut cum_right = f64::NEG_INFINITY;
+        let mut mean_left = f64::NEG_INFINITY;
+        let mut mean_right = f64::NEG_INFINITY;
         let mut position = 0;
 
         for (k, centroid) in self.centroids.iter().enumerate() {
             cum_left = cum_right;
-            cum_right = (2.0 * cumulative + centroid.weight() - 1.0)
-                / 2.0
-                / (self.mass() - 1.0);
+            cum_right = cum_right.max(midpoint_rank(
+                cumulative,
+                centroid.weight(),
+                self.mass(),
+            ));
+            mean_left = mean_right;
+            mean_right = mean_right.max(centroid.mean());
             cumulative += centroid.weight();
 
             if cum_right >= q {
@@ -750,21 +2272,83 @@ impl TDigest {
         }
 
         if position == 0 {
-            return self.centroids[0].mean();
+            return self.clamp_estimate(self.centroids[0].mean());
         }
 
         if position >= self.centroids.len() {
-            return self.centroids[self.centroids.len() - 1].mean();
+            return self.clamp_estimate(mean_right);
         }
 
-        let centroid_left = &self.centroids[position - 1];
-        let centroid_right = &self.centroids[position];
-
         let weight_between = cum_right - cum_left;
+        if weight_between == 0.0 {
+            return self.clamp_estimate(mean_right);
+        }
         let fraction = (q - cum_left) / weight_between;
+        self.clamp_estimate(interpolate(mean_left, mean_right, fraction))
+    }
+
+    /// Quantile `q` from the tail sample, if it lies within the stored values.
+    #[inline]
+    fn exact_quantile(&self, q: f64) -> Option<f64> {
+        self.tails.as_ref()?.quantile(q, self.mass())
+    }
+
+    /// Centroid means and the normalized ranks of their midpoints, i.e.
+    /// the prefix sums of the weights used by the batched estimates. Both
+    /// are running maxima, so that they are sorted even where rounding has
+    /// left them slightly out of order.
+    fn prefix_ranks(&self) -> Result<(Vec<f64>, Vec<f64>), TryReserveError> {
+        let mut means: Vec<f64> = Vec::new();
+        let mut ranks: Vec<f64> = Vec::new();
+        means.try_reserve_exact(self.centroids.len())?;
+        ranks.try_reserve_exact(self.centroids.len())?;
+
+        let mut cumulative = 0.0;
+        let mut mean = f64::NEG_INFINITY;
+        let mut rank = f64::NEG_INFINITY;
+        for centroid in &self.centroids {
+            mean = mean.max(centroid.mean());
+            rank = rank.max(midpoint_rank(
+                cumulative,
+                centroid.weight(),
+                self.mass(),
+            ));
+            means.push(mean);
+            ranks.push(rank);
+            cumulative += centroid.weight();
+        }
+        Ok((means, ranks))
+    }
+
+    /// Clamps a rank estimated from the centroids to `0..=1`, and to the
+    /// range between the ranks of the stored tail values.
+    #[inline]
+    fn clamp_rank(&self, r: f64) -> f64 {
+        let r = r.clamp(0.0, 1.0);
+        match &self.tails {
+            Some(tails) if self.mass() > 1.0 => {
+                let (lo, hi) = tails.rank_range(self.mass());
+                if lo <= hi {
+                    r.max(lo).min(hi)
+                } else {
+                    r
+                }
+            }
+            _ => r,
+        }
+    }
 
-        centroid_left.mean() * (1.0 - fraction)
-            + centroid_right.mean() * fraction
+    /// Clamps an estimate from the centroids to the observed range, and to
+    /// the range between the stored tail values, which the exact quantiles
+    /// next to it come from. Rounding in merges (or `f32` storage) can leave
+    /// the outermost means slightly beyond `min` and `max`.
+    #[inline]
+    fn clamp_estimate(&self, x: f64) -> f64 {
+        let x = x.max(self.min()).min(self.max());
+        match &self.tails {
+            Some(tails) => tails.clamp(x),
+            None => x,
+        }
     }
 
     pub fn estimate_quantiles(
@@ -778,71 +2362,74 @@ impl TDigest {
         }
 
         if n_centroids == 1 {
-            let m = self.centroids[0].mean();
-            return Ok(qs.iter().map(|_| m).collect());
-        }
-
-        let mut cum_left: Vec<f64> = Vec::new();
-        let mut cum_right: Vec<f64> = Vec::new();
-        cum_left.try_reserve_exact(n_centroids)?;
-        cum_right.try_reserve_exact(n_centroids)?;
-
-        let mut cumulative = 0.0;
-        let mut prev_right = 0.0;
-
-        for centroid in &self.centroids {
-            let left = prev_right;
-            let right = (2.0 * cumulative + centroid.weight() - 1.0)
-                / 2.0
-                / (self.mass() - 1.0);
-            cumulative += centroid.weight();
-            prev_right = right;
-            cum_left.push(left);
-            cum_right.push(right);
+            let m = self.clamp_estimate(self.centroids[0].mean());
+            return Ok(qs
+                .iter()
+                .map(|&q| self.exact_quantile(q).unwrap_or(m))
+                .collect());
         }
 
-        let means: Vec<f64> = self.centroids.iter().map(|c| c.mean()).collect();
+        let computed;
+        let prefix = match self.ranks.get(self) {
+            Some(prefix) => prefix,
+            None => {
+                let (means, ranks) = self.prefix_ranks()?;
+                computed = PrefixRanks { means, ranks };
+                &computed
+            }
+        };
 
         let mut out: Vec<f64> = Vec::new();
         out.try_reserve_exact(qs.len())?;
 
         for &q in qs {
-            let idx = cum_right
-                .binary_search_by(|x| x.partial_cmp(&q).unwrap())
-                .unwrap_or_else(|i| i);
-
-            if idx == 0 {
-                out.push(means[0]);
-                continue;
+            match self.exact_quantile(q) {
+                Some(x) => out.push(x),
+                None => out.push(self.quantile_from_prefix(prefix, q)),
             }
+        }
+        Ok(out)
+    }
 
-            if idx >= n_centroids {
-                out.push(means[n_centroids - 1]);
-                continue;
-            }
+    /// Quantile `q` estimated from the centroids by a binary search of
+    /// their prefix ranks.
+    fn quantile_from_prefix(&self, prefix: &PrefixRanks, q: f64) -> f64 {
+        let PrefixRanks { means, ranks } = prefix;
+        let idx = lower_bound(ranks, q);
 
-            let left = cum_left[idx];
-            let right = cum_right[idx];
-            let weight_between = right - left;
+        if idx == 0 {
+            return self.clamp_estimate(means[0]);
+        }
 
-            if weight_between == 0.0 {
-                out.push(means[idx]);
-                continue;
-            }
+        if idx >= means.len() {
+            return self.clamp_estimate(means[means.len() - 1]);
+        }
+
+        let left = ranks[idx - 1];
+        let right = ranks[idx];
+        let weight_between = right - left;
 
-            let fraction = (q - left) / weight_between;
-            let m_left = means[idx - 1];
-            let m_right = means[idx];
-            out.push(m_left * (1.0 - fraction) + m_right * fraction);
+        if weight_between == 0.0 {
+            return self.clamp_estimate(means[idx]);
         }
-        Ok(out)
+
+        let fraction = (q - left) / weight_between;
+        self.clamp_estimate(interpolate(means[idx - 1], means[idx], fraction))
     }
 
     /// Function by Andy Lok (https://github.com/andylokandy/tdigests)
+    ///
+    /// The inverse of `estimate_quantile`: between the midpoint ranks of
+    /// two neighboring centroids, the rank is interpolated within them, so
+    /// that the quantile of the rank of `x` lies between their means. It
+    /// matches `estimate_ranks` exactly.
     pub fn estimate_rank(&self, x: f64) -> f64 {
         if x.is_nan() {
             return f64::NAN;
         }
+        if let Some(r) = self.exact_rank(x) {
+            return r;
+        }
 
         if self.centroids.len() == 1 {
             match self.centroids[0].mean().partial_cmp(&x).unwrap() {
@@ -851,20 +2438,29 @@ impl TDigest {
                 Ordering::Greater => return 0.0,
             }
         }
+        if let Some(prefix) = self.ranks.get(self) {
+            return self.rank_from_prefix(prefix, x);
+        }
 
         let mut cumulative = 0.0;
-        let mut cum_left = 0.0;
-        let mut cum_right = 0.0;
+        let mut cum_left = f64::NEG_INFINITY;
+        let mut cum_right = f64::NEG_INFINITY;
+        let mut mean_left = f64::NEG_INFINITY;
+        let mut mean_right = f64::NEG_INFINITY;
         let mut position = 0;
 
         for (k, centroid) in self.centroids.iter().enumerate() {
             cum_left = cum_right;
-            cum_right = (2.0 * cumulative + centroid.weight() - 1.0)
-                / 2.0
-                / (self.mass() - 1.0);
+            cum_right = cum_right.max(midpoint_rank(
+                cumulative,
+                centroid.weight(),
+                self.mass(),
+            ));
+            mean_left = mean_right;
+            mean_right = mean_right.max(centroid.mean());
             cumulative += centroid.weight();
 
-            if centroid.mean() >= x {
+            if mean_right >= x {
                 break;
             }
 
@@ -872,127 +2468,141 @@ impl TDigest {
         }
 
         if position == 0 {
-            return 0.0;
+            return self.clamp_rank(0.0);
         }
 
         if position >= self.centroids.len() {
-            return 1.0;
+            return self.clamp_rank(1.0);
         }
 
-        let centroid_left = &self.centroids[position - 1];
-        let centroid_right = &self.centroids[position];
-
-        let weight_between = cum_right - cum_left;
-        let fraction = (x - centroid_left.mean())
-            / (centroid_right.mean() - centroid_left.mean());
+        let fraction = (x - mean_left) / (mean_right - mean_left);
+        self.clamp_rank(interpolate(cum_left, cum_right, fraction))
+    }
 
-        cum_left + fraction * weight_between
+    /// Exact rank of `x` from the tail sample, if it lies within the stored
+    /// values.
+    #[inline]
+    fn exact_rank(&self, x: f64) -> Option<f64> {
+        self.tails.as_ref()?.rank(x, self.mass())
     }
 
+    /// Estimated ranks of `xs`, computed on up to `n_threads` threads (see
+    /// `estimate_ranks_into`).
     pub fn estimate_ranks(
         &self,
         xs: &[f64],
+        n_threads: usize,
     ) -> Result<Vec<f64>, TryReserveError> {
+        if self.centroids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut out: Vec<f64> = Vec::new();
+        out.try_reserve_exact(xs.len())?;
+        out.resize(xs.len(), 0.0);
+        self.estimate_ranks_into(xs, &mut out, n_threads)?;
+        Ok(out)
+    }
+
+    /// Writes the estimated ranks of `xs` to `out`, which must have the
+    /// same length, splitting the values among up to `n_threads` threads.
+    /// The ranks of an empty digest are NaN.
+    pub fn estimate_ranks_into(
+        &self,
+        xs: &[f64],
+        out: &mut [f64],
+        n_threads: usize,
+    ) -> Result<(), TryReserveError> {
+        assert_eq!(xs.len(), out.len());
         let n_centroids = self.centroids.len();
 
         if n_centroids == 0 {
-            return Ok(vec![]);
+            out.fill(f64::NAN);
+            return Ok(());
         }
 
         if n_centroids == 1 {
             let m = self.centroids[0].mean();
-            let ranks = xs
-                .iter()
-                .map(|&x| {
-                    if x.is_nan() {
-                        f64::NAN
-                    } else {
-                        match m.partial_cmp(&x).unwrap() {
-                            std::cmp::Ordering::Less => 1.0,
-                            std::cmp::Ordering::Equal => 0.5,
-                            std::cmp::Ordering::Greater => 0.0,
-                        }
-                    }
-                })
-                .collect();
-            return Ok(ranks);
-        }
-
-        let mut cum_left: Vec<f64> = Vec::new();
-        let mut cum_right: Vec<f64> = Vec::new();
-        cum_left.try_reserve_exact(n_centroids)?;
-        cum_right.try_reserve_exact(n_centroids)?;
-
-        let mut cumulative = 0.0;
-        let mut prev_right = 0.0;
-
-        for centroid in &self.centroids {
-            let left = prev_right;
-
-            let right = (2.0 * cumulative + centroid.weight() - 1.0)
-                / 2.0
-                / (self.mass() - 1.0);
-
-            cumulative += centroid.weight();
-            prev_right = right;
-
-            cum_left.push(left);
-            cum_right.push(right);
+            for (x, r) in xs.iter().zip(out.iter_mut()) {
+                *r = match m.partial_cmp(x) {
+                    None => f64::NAN,
+                    Some(Ordering::Less) => 1.0,
+                    Some(Ordering::Equal) => 0.5,
+                    Some(Ordering::Greater) => 0.0,
+                };
+            }
+            return Ok(());
         }
 
-        let means: Vec<f64> = self.centroids.iter().map(|c| c.mean()).collect();
-
-        let mut out: Vec<f64> = Vec::new();
-        out.try_reserve_exact(xs.len())?;
-
-        for &x in xs {
-            if x.is_nan() {
-                out.push(f64::NAN);
-                continue;
+        let computed;
+        let prefix = match self.ranks.get(self) {
+            Some(prefix) => prefix,
+            None => {
+                let (means, ranks) = self.prefix_ranks()?;
+                computed = PrefixRanks { means, ranks };
+                &computed
             }
-            let idx = means
-                .binary_search_by(|m| m.partial_cmp(&x).unwrap())
-                .unwrap_or_else(|i| i);
+        };
 
-            if idx == 0 {
-                out.push(0.0);
-                continue;
+        let ranks = |xs: &[f64], out: &mut [f64]| {
+            for (&x, r) in xs.iter().zip(out.iter_mut()) {
+                *r = if x.is_nan() {
+                    f64::NAN
+                } else {
+                    match self.exact_rank(x) {
+                        Some(r) => r,
+                        None => self.rank_from_prefix(prefix, x),
+                    }
+                };
             }
-
-            if idx >= n_centroids {
-                out.push(1.0);
-                continue;
+        };
+        let n_threads =
+            thread_limit(n_threads).min(xs.len() / PAR_RANK_MIN_CHUNK);
+        if n_threads <= 1 {
+            ranks(xs, out);
+            return Ok(());
+        }
+        let chunk = xs.len().div_ceil(n_threads);
+        thread::scope(|scope| {
+            for (xs, out) in xs.chunks(chunk).zip(out.chunks_mut(chunk)) {
+                scope.spawn(move || ranks(xs, out));
             }
+        });
+        Ok(())
+    }
 
-            let left_mean = means[idx - 1];
-            let right_mean = means[idx];
-            let left = cum_left[idx];
-            let right = cum_right[idx];
-            let weight_between = right - left;
+    /// Rank of `x` estimated from the centroids by a binary search of
+    /// their means.
+    fn rank_from_prefix(&self, prefix: &PrefixRanks, x: f64) -> f64 {
+        let PrefixRanks { means, ranks } = prefix;
+        let idx = lower_bound(means, x);
 
-            if right_mean == left_mean {
-                out.push(left);
-                continue;
-            }
+        if idx == 0 {
+            return self.clamp_rank(0.0);
+        }
 
-            let fraction = (x - left_mean) / (right_mean - left_mean);
-            out.push(left + fraction * weight_between);
+        if idx >= means.len() {
+            return self.clamp_rank(1.0);
         }
-        Ok(out)
+
+        let left_mean = means[idx - 1];
+        let right_mean = means[idx];
+        let fraction = (x - left_mean) / (right_mean - left_mean);
+        self.clamp_rank(interpolate(ranks[idx - 1], ranks[idx], fraction))
     }
 
     pub fn estimate_trimmed_mean(&self, q1: f64, q2: f64) -> f64 {
         let lower_weight_threshold = q1 * self.mass();
         let upper_weight_threshold = q2 * self.mass();
 
-        let mut cum_weight = 0.0;
-        let mut trimmed_sum = 0.0;
-        let mut trimmed_weight = 0.0;
+        let mut cum_weight = CompensatedSum::default();
+        let mut trimmed_sum = CompensatedSum::default();
+        let mut trimmed_weight = CompensatedSum::default();
 
         for centroid in self.centroids().iter() {
-            let c_start = cum_weight;
-            let c_end = cum_weight + centroid.weight();
-            cum_weight = c_end;
+            let c_start = cum_weight.value();
+            cum_weight.add(centroid.weight());
+            let c_end = cum_weight.value();
 
             if c_end <= lower_weight_threshold {
                 continue;
@@ -1001,18 +2611,27 @@ impl TDigest {
                 break;
             }
 
-            let overlap = (c_end.min(upper_weight_threshold)
-                - c_start.max(lower_weight_threshold))
-            .max(0.0);
-            trimmed_sum += overlap * centroid.mean();
-            trimmed_weight += overlap;
+            // the difference of the cumulative weights loses the weight of
+            // light centroids next to heavy ones, so take inner ones whole
+            let overlap = if c_start >= lower_weight_threshold
+                && c_end <= upper_weight_threshold
+            {
+                centroid.weight()
+            } else {
+                (c_end.min(upper_weight_threshold)
+                    - c_start.max(lower_weight_threshold))
+                .max(0.0)
+            };
+            trimmed_sum.add(overlap * centroid.mean());
+            trimmed_weight.add(overlap);
         }
 
+        let trimmed_weight = trimmed_weight.value();
         if trimmed_weight == 0.0 {
             return f64::NAN;
         }
 
-        trimmed_sum / trimmed_weight
+        trimmed_sum.value() / trimmed_weight
     }
 
     pub fn estimate_mad(&self) -> f64 {
@@ -1054,17 +2673,21 @@ impl TDigest {
             .unwrap_or(f64::NAN)
     }
 
-    /// Estimates population variance using Var(X) = E[X^2] - (E[X])^2.
+    /// Estimates population variance from the squared deviations of the
+    /// centroids from the mean, which unlike E[X^2] - (E[X])^2 does not
+    /// cancel out for data far from 0.
     pub fn estimate_var(&self) -> f64 {
         if self.mass() == 0.0 {
             return f64::NAN;
         }
-        let m2: f64 = self
+        let mean = self.mean();
+        let m2 = self
             .centroids
             .iter()
-            .map(|c| c.mean() * c.mean() * c.weight())
-            .sum();
-        m2 / self.mass() - self.mean() * self.mean()
+            .map(|c| (c.mean() - mean).powi(2) * c.weight())
+            .sum::<CompensatedSum>()
+            .value();
+        m2 / self.mass()
     }
 
     /// Approximate error function (Abramowitz-Stegun 7.1.26).
@@ -1144,17 +2767,189 @@ impl TDigest {
     }
 
     fn recompute_totals(&mut self) {
-        let mut mass = 0.0;
-        let mut sum = 0.0;
+        let mut mass = CompensatedSum::default();
+        let mut sum = CompensatedSum::default();
         for c in self.centroids.iter() {
-            mass += c.weight();
-            sum += c.mean() * c.weight();
+            mass.add(c.weight());
+            sum.add(c.mean() * c.weight());
+        }
+        self.mass = OrderedFloat::from(mass.value());
+        self.sum = OrderedFloat::from(sum.value());
+    }
+}
+
+/// Normalized rank of the midpoint of a centroid of weight `weight` after
+/// a `cumulative` weight, in a digest of total weight `mass`. It is clamped
+/// to `0..=1`, which it can leave for centroids lighter than 1 at the ends.
+#[inline]
+fn midpoint_rank(cumulative: f64, weight: f64, mass: f64) -> f64 {
+    let rank = (2.0 * cumulative + weight - 1.0) / 2.0 / (mass - 1.0);
+    rank.clamp(0.0, 1.0)
+}
+
+/// Linear interpolation from `left` to `right` (with `left <= right`),
+/// clamped to them: `left * (1 - fraction) + right * fraction` can round
+/// beyond both, which would break the monotonicity of the quantiles, and
+/// `fraction = 1` must give `right` exactly for ranks and quantiles to be
+/// inverse to each other.
+#[inline]
+fn interpolate(left: f64, right: f64, fraction: f64) -> f64 {
+    if fraction >= 1.0 {
+        return right;
+    }
+    (left + (right - left) * fraction).max(left).min(right)
+}
+
+/// Neumaier's compensated summation, whose rounding error does not grow
+/// with the number of terms, e.g. for the weights of a digest with
+/// billions of values.
+#[derive(Clone, Copy, Default)]
+struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    #[inline]
+    fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        if self.sum.abs() >= x.abs() {
+            self.compensation += (self.sum - t) + x;
+        } else {
+            self.compensation += (x - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    #[inline]
+    fn value(self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+impl iter::Sum<f64> for CompensatedSum {
+    fn sum<I: Iterator<Item = f64>>(iter: I) -> Self {
+        let mut sum = Self::default();
+        iter.for_each(|x| sum.add(x));
+        sum
+    }
+}
+
+/// Index of the first element of the sorted slice `xs` that is not less
+/// than `target` (`xs.len()` if there is none).
+///
+/// Branchless binary search: the loop has a fixed number of iterations for
+/// a given length, and the comparison compiles to a conditional move, so
+/// there are no mispredicted branches.
+#[inline]
+fn lower_bound(xs: &[f64], target: f64) -> usize {
+    if xs.is_empty() {
+        return 0;
+    }
+    let mut base = 0;
+    let mut size = xs.len();
+    while size > 1 {
+        let half = size / 2;
+        let mid = base + half;
+        base = if xs[mid] < target { mid } else { base };
+        size -= half;
+    }
+    base + (xs[base] < target) as usize
+}
+
+/// Caps `n_threads` at what the target supports: WebAssembly builds (such
+/// as Pyodide) cannot spawn threads, so they do all work on the caller's.
+#[inline]
+pub(crate) fn thread_limit(n_threads: usize) -> usize {
+    if cfg!(target_family = "wasm") {
+        1
+    } else {
+        n_threads
+    }
+}
+
+/// Minimum number of items per thread for which sorting in parallel pays
+/// off.
+const PAR_SORT_MIN_CHUNK: usize = 1 << 16;
+
+/// Minimum number of values per chunk of `merge_chunks_inplace`.
+const PAR_CHUNK_MIN_LEN: usize = 1 << 16;
+
+/// Minimum number of values per thread for which estimating ranks in
+/// parallel pays off.
+const PAR_RANK_MIN_CHUNK: usize = 1 << 16;
+
+/// Stable sort that splits `v` into up to `n_threads` runs, sorts them in
+/// parallel and merges them pairwise (in parallel as well). The result is
+/// identical to `v.sort_by_key(key)`.
+fn par_sort_by_key<T, K, F>(
+    v: &mut Vec<T>,
+    key: F,
+    n_threads: usize,
+) -> Result<(), TryReserveError>
+where
+    T: Copy + Send + Sync,
+    K: Ord,
+    F: Fn(&T) -> K + Copy + Send,
+{
+    let n_threads = thread_limit(n_threads).min(v.len() / PAR_SORT_MIN_CHUNK);
+    if n_threads <= 1 {
+        v.sort_by_key(key);
+        return Ok(());
+    }
+
+    let mut run = v.len().div_ceil(n_threads);
+    thread::scope(|scope| {
+        for chunk in v.chunks_mut(run) {
+            scope.spawn(move || chunk.sort_by_key(key));
+        }
+    });
+
+    let mut scratch: Vec<T> = Vec::new();
+    scratch.try_reserve_exact(v.len())?;
+    scratch.extend_from_slice(v);
+    let (mut src, mut dst) = (v.as_mut_slice(), scratch.as_mut_slice());
+    let mut in_scratch = false;
+    while run < src.len() {
+        thread::scope(|scope| {
+            for (from, to) in src.chunks(2 * run).zip(dst.chunks_mut(2 * run)) {
+                scope.spawn(move || merge_runs(from, run, to, key));
+            }
+        });
+        (src, dst) = (dst, src);
+        in_scratch = !in_scratch;
+        run *= 2;
+    }
+    if in_scratch {
+        v.copy_from_slice(&scratch);
+    }
+    Ok(())
+}
+
+/// Merges the sorted runs `from[..mid]` and `from[mid..]` into `to`,
+/// preferring the left run on ties.
+fn merge_runs<T: Copy, K: Ord>(
+    from: &[T],
+    mid: usize,
+    to: &mut [T],
+    key: impl Fn(&T) -> K,
+) {
+    let (left, right) = from.split_at(mid.min(from.len()));
+    let (mut i, mut j) = (0, 0);
+    for slot in to.iter_mut() {
+        let take_left = j == right.len()
+            || (i < left.len() && key(&left[i]) <= key(&right[j]));
+        if take_left {
+            *slot = left[i];
+            i += 1;
+        } else {
+            *slot = right[j];
+            j += 1;
         }
-        self.mass = OrderedFloat::from(mass);
-        self.sum = OrderedFloat::from(sum);
     }
 }
 
+/// Error when loading a digest from its binary format.
 #[derive(Debug)]
 pub enum BytesError {
     MemError(TryReserveError),
@@ -1164,3 +2959,31 @@ pub enum BytesError {
     WrongFormat,
     WrongVersion,
 }
+
+impl fmt::Display for BytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemError(e) => e.fmt(f),
+            Self::CorruptData => f.write_str("data is corrupt"),
+            Self::EmptyData => f.write_str("data is empty"),
+            Self::WrongArch => {
+                f.write_str("data requires 64-bit architecture to load")
+            }
+            Self::WrongFormat => {
+                f.write_str("data is not in fastDigest binary format")
+            }
+            Self::WrongVersion => {
+                f.write_str("data format version is incompatible")
+            }
+        }
+    }
+}
+
+impl Error for BytesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::MemError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! NumPy datetime64 and timedelta64 values
+//!
+//! Times are converted to float seconds (since the Unix epoch for
+//! datetimes) on the way in, and a TDigest that was fed with them
+//! remembers their dtype to return its estimates as NumPy scalars again.
+
+use crate::input::FloatValues;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyFloat;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TimeKind {
+    Datetime,
+    Timedelta,
+}
+
+impl TimeKind {
+    /// Maps a NumPy dtype kind ('M' or 'm').
+    fn from_dtype_kind(kind: &str) -> Option<Self> {
+        match kind {
+            "M" => Some(Self::Datetime),
+            "m" => Some(Self::Timedelta),
+            _ => None,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        match self {
+            Self::Datetime => "datetime64",
+            Self::Timedelta => "timedelta64",
+        }
+    }
+}
+
+/// Unit of the returned scalars, ordered from coarse to fine.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Unit {
+    S,
+    Ms,
+    Us,
+    Ns,
+}
+
+impl Unit {
+    const ALL: [Self; 4] = [Self::S, Self::Ms, Self::Us, Self::Ns];
+
+    /// Maps a NumPy unit code. Coarser units than seconds (and generic
+    /// units) are returned in seconds, finer ones than nanoseconds in
+    /// nanoseconds.
+    fn from_numpy(code: &str) -> Self {
+        match code {
+            "ms" => Self::Ms,
+            "us" => Self::Us,
+            "ns" | "ps" | "fs" | "as" => Self::Ns,
+            _ => Self::S,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::S => "s",
+            Self::Ms => "ms",
+            Self::Us => "us",
+            Self::Ns => "ns",
+        }
+    }
+
+    fn per_second(self) -> f64 {
+        match self {
+            Self::S => 1.0,
+            Self::Ms => 1e3,
+            Self::Us => 1e6,
+            Self::Ns => 1e9,
+        }
+    }
+}
+
+/// dtype of the datetime64 or timedelta64 values fed to a digest.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimeUnit {
+    kind: TimeKind,
+    unit: Unit,
+}
+
+impl TimeUnit {
+    /// Parses a dtype name like 'datetime64[ns]' or 'timedelta64[s]'.
+    pub(crate) fn from_name(name: &str) -> PyResult<Self> {
+        for kind in [TimeKind::Datetime, TimeKind::Timedelta] {
+            let unit = name
+                .strip_prefix(kind.type_name())
+                .and_then(|rest| rest.strip_prefix('['))
+                .and_then(|rest| rest.strip_suffix(']'))
+                .and_then(|code| {
+                    Unit::ALL.into_iter().find(|unit| unit.name() == code)
+                });
+            if let Some(unit) = unit {
+                return Ok(Self { kind, unit });
+            }
+        }
+        Err(PyValueError::new_err(
+            "dtype must be 'datetime64[unit]' or 'timedelta64[unit]', with \
+             unit 's', 'ms', 'us' or 'ns'.",
+        ))
+    }
+
+    pub(crate) fn name(self) -> String {
+        format!("{}[{}]", self.kind.type_name(), self.unit.name())
+    }
+
+    /// dtype of differences between values of this dtype.
+    pub(crate) fn duration(self) -> Self {
+        Self {
+            kind: TimeKind::Timedelta,
+            ..self
+        }
+    }
+
+    /// dtype of a digest combining values of dtype `a` and `b`, with the
+    /// finer of two units.
+    pub(crate) fn combine(
+        a: Option<Self>,
+        b: Option<Self>,
+    ) -> PyResult<Option<Self>> {
+        match (a, b) {
+            (Some(a), Some(b)) if a.kind != b.kind => {
+                Err(PyTypeError::new_err(
+                    "Cannot mix datetime64 and timedelta64 values in one \
+                     TDigest.",
+                ))
+            }
+            (Some(a), Some(b)) => Ok(Some(a.max_unit(b))),
+            (a, b) => Ok(a.or(b)),
+        }
+    }
+
+    fn max_unit(self, other: Self) -> Self {
+        Self {
+            unit: self.unit.max(other.unit),
+            ..self
+        }
+    }
+
+    /// Converts `seconds` to a NumPy scalar of this dtype, rounded to its
+    /// unit.
+    pub(crate) fn to_numpy<'py>(
+        self,
+        py: Python<'py>,
+        seconds: f64,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let count = (seconds * self.unit.per_second()).round() as i64;
+        py.import("numpy")?
+            .call_method1(self.kind.type_name(), (count, self.unit.name()))
+    }
+}
+
+/// An estimate in float seconds, returned as a NumPy datetime64 or
+/// timedelta64 scalar if it has a dtype, and as a float otherwise.
+pub struct Quantity {
+    pub value: f64,
+    unit: Option<TimeUnit>,
+}
+
+impl Quantity {
+    #[inline]
+    pub(crate) fn new(value: f64, unit: Option<TimeUnit>) -> Self {
+        Self { value, unit }
+    }
+}
+
+impl From<Quantity> for f64 {
+    fn from(quantity: Quantity) -> f64 {
+        quantity.value
+    }
+}
+
+impl<'py> IntoPyObject<'py> for Quantity {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        match self.unit {
+            Some(unit) => unit.to_numpy(py, self.value),
+            None => Ok(PyFloat::new(py, self.value).into_any()),
+        }
+    }
+}
+
+/// Kind of `obj` if it is a NumPy array, scalar or pandas Series of
+/// datetime64 or timedelta64 values. Does not import NumPy otherwise.
+fn time_kind(obj: &Bound<'_, PyAny>) -> PyResult<Option<TimeKind>> {
+    if !obj.hasattr("dtype")? {
+        return Ok(None);
+    }
+    // other dtype objects (e.g. of polars) have no kind
+    let kind = obj
+        .getattr("dtype")?
+        .getattr("kind")
+        .ok()
+        .and_then(|kind| kind.extract::<String>().ok());
+    Ok(kind.as_deref().and_then(TimeKind::from_dtype_kind))
+}
+
+/// Converts the datetime64 or timedelta64 values of `obj` to float64
+/// seconds (NaN for NaT), along with their dtype.
+fn to_seconds<'py>(
+    obj: &Bound<'py, PyAny>,
+    kind: TimeKind,
+) -> PyResult<(Bound<'py, PyAny>, TimeUnit)> {
+    let np = obj.py().import("numpy")?;
+    let array = np.call_method1("asarray", (obj,))?;
+    let dtype = array.getattr("dtype")?;
+    let (code, _): (String, i64) =
+        np.call_method1("datetime_data", (&dtype,))?.extract()?;
+    let unit = TimeUnit {
+        kind,
+        unit: Unit::from_numpy(&code),
+    };
+    let offsets = match kind {
+        TimeKind::Datetime => {
+            array.sub(np.call_method1("datetime64", (0, "s"))?)?
+        }
+        TimeKind::Timedelta => array,
+    };
+    let seconds = offsets.div(np.call_method1("timedelta64", (1, "s"))?)?;
+    Ok((seconds, unit))
+}
+
+/// Reads `obj` as float seconds if it holds datetime64 or timedelta64
+/// values. NaT values are read as NaN, to be skipped.
+pub(crate) fn extract(
+    obj: &Bound<'_, PyAny>,
+) -> PyResult<Option<(FloatValues, TimeUnit)>> {
+    let Some(kind) = time_kind(obj)? else {
+        return Ok(None);
+    };
+    let (seconds, unit) = to_seconds(obj, kind)?;
+    let array = obj
+        .py()
+        .import("numpy")?
+        .call_method1("ascontiguousarray", (seconds, "float64"))?;
+    Ok(Some((FloatValues::extract(&array)?, unit)))
+}
+
+/// Reads a float, or a datetime64 or timedelta64 scalar as float seconds.
+pub(crate) fn extract_scalar(obj: &Bound<'_, PyAny>) -> PyResult<f64> {
+    match time_kind(obj)? {
+        Some(kind) => to_seconds(obj, kind)?.0.extract(),
+        None => obj.extract(),
+    }
+}
@@ -0,0 +1,430 @@
+//! Theta sketch: distinct counts with set operations
+//!
+//! As in Apache DataSketches, the sketch keeps the 64-bit hashes of the
+//! values (see `hash`) that are below a threshold `theta`, at most `k` of
+//! them: once there are more, `theta` drops to the largest retained hash,
+//! which is discarded (the "k minimum values" sketch). The distinct count
+//! is estimated as the number of retained hashes divided by `theta` (as a
+//! fraction of the hash range). Unions, intersections and differences are
+//! computed on the retained hashes below the smaller of both thresholds,
+//! so their results are theta sketches again, with the same kind of
+//! estimate. The bounds of an estimate approximate the binomial
+//! distribution of the number of retained hashes.
+
+use crate::bytes_error;
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::hash::{hash_value, hash_values};
+use crate::tdigest::BytesError;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::BTreeSet;
+
+const MAGIC: [u8; 8] = *b"FASTTHT~";
+const VERSION: u32 = 1;
+const MIN_LG_K: u8 = 4;
+const MAX_LG_K: u8 = 26;
+const SEED: u64 = 0;
+/// Threshold of a sketch that has retained every hash
+const THETA_MAX: u64 = u64::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThetaSketch {
+    lg_k: u8,
+    /// Retained hashes are below `theta`
+    theta: u64,
+    hashes: BTreeSet<u64>,
+}
+
+impl ThetaSketch {
+    pub fn new(lg_k: u8) -> Self {
+        Self {
+            lg_k,
+            theta: THETA_MAX,
+            hashes: BTreeSet::new(),
+        }
+    }
+
+    #[inline]
+    fn k(&self) -> usize {
+        1 << self.lg_k
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.theta == THETA_MAX && self.hashes.is_empty()
+    }
+
+    #[inline]
+    pub fn is_estimation_mode(&self) -> bool {
+        self.theta < THETA_MAX
+    }
+
+    /// Threshold as a fraction of the hash range.
+    #[inline]
+    pub fn theta_fraction(&self) -> f64 {
+        if self.is_estimation_mode() {
+            self.theta as f64 / 2f64.powi(64)
+        } else {
+            1.0
+        }
+    }
+
+    pub fn add_hash(&mut self, hash: u64) {
+        if hash < self.theta && self.hashes.insert(hash) {
+            self.trim();
+        }
+    }
+
+    /// Drops the largest hashes until at most `k` are left, lowering theta.
+    fn trim(&mut self) {
+        while self.hashes.len() > self.k() {
+            if let Some(largest) = self.hashes.pop_last() {
+                self.theta = largest;
+            }
+        }
+    }
+
+    pub fn estimate(&self) -> f64 {
+        self.hashes.len() as f64 / self.theta_fraction()
+    }
+
+    /// Estimate minus or plus `num_std_devs` approximate standard
+    /// deviations; exact without sampling.
+    pub fn bounds(&self, num_std_devs: f64) -> (f64, f64) {
+        let n = self.hashes.len() as f64;
+        let p = self.theta_fraction();
+        if p == 1.0 {
+            return (n, n);
+        }
+        let estimate = n / p;
+        // the retained count of a binomial sample with probability p; at
+        // least one hash is added to bound empty estimates from above
+        let std_dev = (n.max(1.0) * (1.0 - p)).sqrt() / p;
+        let lower = (estimate - num_std_devs * std_dev).max(n);
+        (lower, estimate + num_std_devs * std_dev)
+    }
+
+    /// Combines the hashes below the smaller of both thresholds that are
+    /// kept by `keep(in_self, in_other)`.
+    fn combine(&self, other: &Self, keep: impl Fn(bool, bool) -> bool) -> Self {
+        let theta = self.theta.min(other.theta);
+        let candidates = self.hashes.union(&other.hashes);
+        let hashes = candidates
+            .take_while(|&&h| h < theta)
+            .filter(|h| keep(self.hashes.contains(h), other.hashes.contains(h)))
+            .copied()
+            .collect();
+        let mut sketch = Self {
+            lg_k: self.lg_k.min(other.lg_k),
+            theta,
+            hashes,
+        };
+        sketch.trim();
+        sketch
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, |_, _| true)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a && b)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u8(self.lg_k);
+        writer.u64(self.theta);
+        writer.u64(self.hashes.len() as u64);
+        for &hash in &self.hashes {
+            writer.u64(hash);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let lg_k = reader.u8()?;
+        let theta = reader.u64()?;
+        let len = reader.seq_len(8)?;
+        let hashes =
+            (0..len).map(|_| reader.u64()).collect::<Result<_, _>>()?;
+        reader.finish()?;
+        Self::from_parts(lg_k, theta, hashes).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent.
+    fn from_parts(lg_k: u8, theta: u64, hashes: Vec<u64>) -> Option<Self> {
+        let valid = valid_lg_k(lg_k)
+            && theta > 0
+            && hashes.len() <= 1 << lg_k
+            && hashes.is_sorted_by(|a, b| a < b)
+            && hashes.last().is_none_or(|&h| h < theta);
+        valid.then(|| Self {
+            lg_k,
+            theta,
+            hashes: hashes.into_iter().collect(),
+        })
+    }
+}
+
+#[inline]
+fn valid_lg_k(lg_k: u8) -> bool {
+    (MIN_LG_K..=MAX_LG_K).contains(&lg_k)
+}
+
+#[pyclass(name = "ThetaSketch", module = "fastdigest", frozen)]
+pub struct PyThetaSketch {
+    state: Mutex<ThetaSketch>,
+}
+
+impl PyThetaSketch {
+    fn from_sketch(sketch: ThetaSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Applies a set operation to copies of both sketches.
+    fn combine(
+        &self,
+        other: &Self,
+        operation: fn(&ThetaSketch, &ThetaSketch) -> ThetaSketch,
+    ) -> Self {
+        let other = other.state.lock().clone();
+        Self::from_sketch(operation(&self.state.lock(), &other))
+    }
+}
+
+/// Validates the number of standard deviations of a bound.
+#[inline]
+fn validate_num_std_devs(num_std_devs: u8) -> PyResult<f64> {
+    if !(1..=3).contains(&num_std_devs) {
+        return Err(PyValueError::new_err("num_std_devs must be 1, 2 or 3."));
+    }
+    Ok(f64::from(num_std_devs))
+}
+
+#[pymethods]
+impl PyThetaSketch {
+    /// Constructs a new empty ThetaSketch instance that retains up to
+    /// `2^lg_k` hashes.
+    #[new]
+    #[pyo3(signature = (lg_k=12))]
+    pub fn py_new(lg_k: i64) -> PyResult<Self> {
+        let lg_k = u8::try_from(lg_k)
+            .ok()
+            .filter(|&lg_k| valid_lg_k(lg_k))
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "lg_k must be between {MIN_LG_K} and {MAX_LG_K}."
+                ))
+            })?;
+        Ok(Self::from_sketch(ThetaSketch::new(lg_k)))
+    }
+
+    /// Getter property: returns log2 of the maximum number of retained
+    /// hashes.
+    #[getter(lg_k)]
+    pub fn get_lg_k(&self) -> u8 {
+        self.state.lock().lg_k
+    }
+
+    /// Getter property: returns the sampling threshold as a fraction of
+    /// the hash range.
+    #[getter(theta)]
+    pub fn get_theta(&self) -> f64 {
+        self.state.lock().theta_fraction()
+    }
+
+    /// Getter property: returns the number of retained hashes.
+    #[getter(n_retained)]
+    pub fn get_n_retained(&self) -> usize {
+        self.state.lock().hashes.len()
+    }
+
+    /// Updates the sketch (in-place) with a single hashable value.
+    pub fn update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hash = hash_value(x, SEED)?;
+        self.state.lock().add_hash(hash);
+        Ok(())
+    }
+
+    /// Updates the sketch (in-place) with an iterable or array of hashable
+    /// values.
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let hashes = hash_values(x, SEED)?;
+        let mut sketch = self.state.lock();
+        for hash in hashes {
+            sketch.add_hash(hash);
+        }
+        Ok(())
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns True if the sketch has dropped hashes, so that its estimate
+    /// is no longer exact.
+    pub fn is_estimation_mode(&self) -> bool {
+        self.state.lock().is_estimation_mode()
+    }
+
+    /// Returns the estimated number of distinct values.
+    pub fn estimate(&self) -> f64 {
+        self.state.lock().estimate()
+    }
+
+    /// Returns the approximate lower bound of the distinct count at
+    /// `num_std_devs` standard deviations.
+    #[pyo3(signature = (num_std_devs=2))]
+    pub fn lower_bound(&self, num_std_devs: u8) -> PyResult<f64> {
+        let num_std_devs = validate_num_std_devs(num_std_devs)?;
+        Ok(self.state.lock().bounds(num_std_devs).0)
+    }
+
+    /// Returns the approximate upper bound of the distinct count at
+    /// `num_std_devs` standard deviations.
+    #[pyo3(signature = (num_std_devs=2))]
+    pub fn upper_bound(&self, num_std_devs: u8) -> PyResult<f64> {
+        let num_std_devs = validate_num_std_devs(num_std_devs)?;
+        Ok(self.state.lock().bounds(num_std_devs).1)
+    }
+
+    /// Returns a sketch of the union of both sets.
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, ThetaSketch::union)
+    }
+
+    /// Unites another sketch with this one (in-place).
+    pub fn union_inplace(&self, other: &Self) {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        *sketch = sketch.union(&other);
+    }
+
+    /// Returns a sketch of the intersection of both sets.
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, ThetaSketch::intersection)
+    }
+
+    /// Returns a sketch of the values in this set but not in the other.
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, ThetaSketch::difference)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a ThetaSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = ThetaSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("lg_k", sketch.lg_k)?;
+        dict.set_item("theta", sketch.theta)?;
+        dict.set_item("hashes", sketch.hashes.iter().collect::<Vec<_>>())?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a ThetaSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let sketch = ThetaSketch::from_parts(
+            get("lg_k")?.extract()?,
+            get("theta")?.extract()?,
+            get("hashes")?.extract()?,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent ThetaSketch hashes.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// ThetaSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via ThetaSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(ThetaSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("ThetaSketch(lg_k={})", self.state.lock().lg_k)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 | sk2 returns sk1.union(sk2).
+    pub fn __or__(&self, other: &Self) -> Self {
+        self.union(other)
+    }
+
+    /// Magic method: sk1 |= sk2 unites sk2 with sk1.
+    pub fn __ior__(&self, other: &Self) {
+        self.union_inplace(other)
+    }
+
+    /// Magic method: sk1 & sk2 returns sk1.intersection(sk2).
+    pub fn __and__(&self, other: &Self) -> Self {
+        self.intersection(other)
+    }
+
+    /// Magic method: sk1 - sk2 returns sk1.difference(sk2).
+    pub fn __sub__(&self, other: &Self) -> Self {
+        self.difference(other)
+    }
+}
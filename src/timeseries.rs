@@ -0,0 +1,762 @@
+//! Digests of consecutive time intervals, with rollups to coarser ones
+//!
+//! Each bucket covers a range of whole intervals of `resolution` time
+//! units (aligned to multiples of that length): one interval when it is
+//! created, more after a rollup merged the buckets of a coarser interval
+//! into one. Buckets never overlap, and values go to the bucket covering
+//! their timestamp, in any order. Queries merge the buckets overlapping a
+//! time range, at the granularity of the buckets.
+
+use crate::codec::{Reader, Writer};
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::SerializationError;
+use crate::tdigest::{
+    BytesError, TDigest, TD_SIZE_DEFAULT, TD_SIZE_PLATFORM_MAX,
+};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::{BTreeMap, TryReserveError};
+
+const MAGIC: [u8; 8] = *b"FASTTSD~";
+const VERSION: u32 = 1;
+
+#[derive(Clone)]
+struct Bucket {
+    /// Number of intervals covered
+    len: i64,
+    entry: Entry,
+}
+
+#[derive(Clone)]
+pub struct TimeSeries {
+    resolution: f64,
+    max_size: usize,
+    /// Buckets by their first epoch (the number of the interval since
+    /// time 0)
+    buckets: BTreeMap<i64, Bucket>,
+}
+
+impl TimeSeries {
+    pub fn new(resolution: f64, max_size: usize) -> Self {
+        Self {
+            resolution,
+            max_size,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    #[inline]
+    fn epoch(&self, timestamp: f64) -> i64 {
+        (timestamp / self.resolution).floor() as i64
+    }
+
+    /// The time range `[start, end)` of the epochs `[first, first + len)`.
+    #[inline]
+    fn range(&self, first: i64, len: i64) -> (f64, f64) {
+        let start = first as f64 * self.resolution;
+        (start, first.saturating_add(len) as f64 * self.resolution)
+    }
+
+    /// The first epochs of the buckets overlapping the epochs
+    /// `[start, end)`, in order.
+    fn overlapping(&self, start: i64, end: i64) -> Vec<i64> {
+        if start >= end {
+            return Vec::new();
+        }
+        let before = self
+            .buckets
+            .range(..start)
+            .next_back()
+            .filter(|(first, bucket)| first.saturating_add(bucket.len) > start)
+            .map(|(&first, _)| first);
+        before
+            .into_iter()
+            .chain(self.buckets.range(start..end).map(|(&first, _)| first))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets
+            .values()
+            .all(|bucket| bucket.entry.count() == 0)
+    }
+
+    pub fn count(&self) -> u128 {
+        self.buckets
+            .values()
+            .map(|bucket| bucket.entry.count())
+            .sum()
+    }
+
+    /// The bucket covering `epoch`, created (for one interval) if needed.
+    fn bucket(&mut self, epoch: i64) -> Result<&mut Entry, TryReserveError> {
+        let next = epoch.saturating_add(1);
+        let first = match self.overlapping(epoch, next).first() {
+            Some(&first) => first,
+            None => {
+                let entry = Entry::new(self.max_size)?;
+                self.buckets.insert(epoch, Bucket { len: 1, entry });
+                epoch
+            }
+        };
+        let bucket = self.buckets.get_mut(&first).expect("bucket exists");
+        Ok(&mut bucket.entry)
+    }
+
+    pub fn add(
+        &mut self,
+        timestamp: f64,
+        x: f64,
+        weight: f64,
+    ) -> Result<(), TryReserveError> {
+        self.bucket(self.epoch(timestamp))?.add(x, weight)
+    }
+
+    /// Adds values at timestamps in any order, merging each bucket once
+    /// with all of its values.
+    pub fn add_batch(
+        &mut self,
+        timestamps: &[f64],
+        pairs: &[(OrderedFloat<f64>, f64)],
+    ) -> Result<(), TryReserveError> {
+        let mut runs: BTreeMap<i64, Pairs> = BTreeMap::new();
+        for (&t, &pair) in timestamps.iter().zip(pairs) {
+            let run = runs.entry(self.epoch(t)).or_default();
+            run.try_reserve(1)?;
+            run.push(pair);
+        }
+        for (epoch, mut run) in runs {
+            self.bucket(epoch)?.add_batch(&mut run)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the buckets of `firsts` by one bucket for the epochs
+    /// `[start, end)`, merging their digests (and `extra`).
+    fn coalesce(
+        &mut self,
+        firsts: &[i64],
+        start: i64,
+        end: i64,
+        extra: Option<&Entry>,
+    ) -> Result<(), TryReserveError> {
+        let mut merged = Entry::new(self.max_size)?;
+        for first in firsts {
+            let bucket = self.buckets.remove(first).expect("bucket exists");
+            merged.merge(&bucket.entry)?;
+        }
+        if let Some(extra) = extra {
+            merged.merge(extra)?;
+        }
+        merged.flush()?;
+        let len = end - start;
+        self.buckets.insert(start, Bucket { len, entry: merged });
+        Ok(())
+    }
+
+    /// Merges the buckets within each interval of `factor` epochs (aligned
+    /// to multiples of it) that ends at or before the epoch `limit` into
+    /// one bucket. Intervals overlapping a bucket that extends beyond them
+    /// are left as they are.
+    pub fn rollup(
+        &mut self,
+        factor: i64,
+        limit: Option<i64>,
+    ) -> Result<(), TryReserveError> {
+        let coarse: Vec<i64> = self
+            .buckets
+            .keys()
+            .map(|first| first.div_euclid(factor))
+            .filter(|c| {
+                limit.is_none_or(|l| (c + 1).saturating_mul(factor) <= l)
+            })
+            .collect();
+        let mut previous = None;
+        for c in coarse {
+            if previous == Some(c) {
+                continue;
+            }
+            previous = Some(c);
+            let start = c * factor;
+            let end = start.saturating_add(factor);
+            let firsts = self.overlapping(start, end);
+            let end_of = |first: &i64| first + self.buckets[first].len;
+            let contained = firsts
+                .iter()
+                .all(|first| *first >= start && end_of(first) <= end);
+            let whole = firsts == [start] && end_of(&start) == end;
+            if contained && !whole {
+                self.coalesce(&firsts, start, end, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), TryReserveError> {
+        for bucket in self.buckets.values_mut() {
+            bucket.entry.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The merged digest of the buckets that overlap the time range
+    /// `[start, end)`, or of all buckets.
+    pub fn digest(
+        &mut self,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> Result<TDigest, TryReserveError> {
+        self.flush()?;
+        let start = start.map_or(i64::MIN, |start| self.epoch(start));
+        // the epoch of `end` is included unless `end` is its first moment
+        let end = end.map_or(i64::MAX, |end| {
+            let epoch = self.epoch(end);
+            if self.range(epoch, 1).0 < end {
+                epoch.saturating_add(1)
+            } else {
+                epoch
+            }
+        });
+        let digests = self
+            .overlapping(start, end)
+            .iter()
+            .map(|first| self.buckets[first].entry.digest.clone())
+            .collect();
+        TDigest::merge_digests(digests, Some(self.max_size))
+    }
+
+    /// The time ranges of the buckets, in order.
+    pub fn ranges(&self) -> Vec<(f64, f64)> {
+        self.buckets
+            .iter()
+            .map(|(&first, bucket)| self.range(first, bucket.len))
+            .collect()
+    }
+
+    /// Merges the buckets of `other` into this digest. Where buckets of
+    /// both overlap without matching (after different rollups), they are
+    /// merged into one bucket covering all of them. The resolutions must
+    /// match.
+    pub fn merge(&mut self, other: &TimeSeries) -> Result<(), TryReserveError> {
+        for (&first, bucket) in &other.buckets {
+            let end = first.saturating_add(bucket.len);
+            let firsts = self.overlapping(first, end);
+            match firsts[..] {
+                [only]
+                    if only <= first
+                        && only + self.buckets[&only].len >= end =>
+                {
+                    let own = self.buckets.get_mut(&only).expect("exists");
+                    own.entry.merge(&bucket.entry)?;
+                }
+                _ => {
+                    let start = firsts.first().map_or(first, |&f| f.min(first));
+                    let last_end = firsts.last().map_or(end, |last| {
+                        end.max(last + self.buckets[last].len)
+                    });
+                    self.coalesce(
+                        &firsts,
+                        start,
+                        last_end,
+                        Some(&bucket.entry),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether both hold equal state. Both must be flushed.
+    pub fn same_state(&self, other: &TimeSeries) -> bool {
+        self.resolution == other.resolution
+            && self.max_size == other.max_size
+            && self.buckets.len() == other.buckets.len()
+            && self.buckets.iter().zip(&other.buckets).all(
+                |((f1, a), (f2, b))| {
+                    f1 == f2
+                        && a.len == b.len
+                        && a.entry.digest == b.entry.digest
+                },
+            )
+    }
+
+    /// Binary representation; the buckets must be flushed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, TryReserveError> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.resolution);
+        writer.u64(self.max_size as u64);
+        writer.u64(self.buckets.len() as u64);
+        for (first, bucket) in &self.buckets {
+            writer.i64(*first);
+            writer.i64(bucket.len);
+            writer.bytes(&bucket.entry.digest.to_bytes()?);
+        }
+        Ok(writer.finish())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let resolution = reader.f64()?;
+        let max_size = reader.u64()?;
+        // each bucket has an epoch, a length and a length-prefixed digest
+        let len = reader.seq_len(24)?;
+        let mut buckets = Vec::new();
+        buckets
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            let first = reader.i64()?;
+            let len = reader.i64()?;
+            let digest = TDigest::from_bytes(reader.bytes()?)?;
+            buckets.push((first, len, digest));
+        }
+        reader.finish()?;
+        let max_size = usize::try_from(max_size)
+            .ok()
+            .filter(|&n| n <= TD_SIZE_PLATFORM_MAX)
+            .ok_or(BytesError::CorruptData)?;
+        Self::from_parts(resolution, max_size, buckets)
+            .ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a time series from `(epoch, length, digest)` buckets in
+    /// increasing order, checking that they do not overlap.
+    fn from_parts(
+        resolution: f64,
+        max_size: usize,
+        buckets: Vec<(i64, i64, TDigest)>,
+    ) -> Option<Self> {
+        if !valid_resolution(resolution) {
+            return None;
+        }
+        let mut series = Self::new(resolution, max_size);
+        let mut previous_end = None;
+        for (first, len, digest) in buckets {
+            let end = first.checked_add(len)?;
+            let ordered = len > 0 && previous_end.is_none_or(|e| e <= first);
+            if !ordered || digest.max_size() != max_size {
+                return None;
+            }
+            let entry = Entry::from_digest(digest);
+            series.buckets.insert(first, Bucket { len, entry });
+            previous_end = Some(end);
+        }
+        Some(series)
+    }
+}
+
+#[inline]
+fn valid_resolution(resolution: f64) -> bool {
+    resolution.is_finite() && resolution > 0.0
+}
+
+fn validate_timestamps(timestamps: &[f64]) -> PyResult<()> {
+    if timestamps.iter().all(|t| t.is_finite()) {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err("Timestamps must be finite."))
+    }
+}
+
+#[pyclass(name = "TimeSeriesDigest", module = "fastdigest", frozen)]
+pub struct PyTimeSeriesDigest {
+    state: Mutex<TimeSeries>,
+}
+
+impl PyTimeSeriesDigest {
+    fn from_series(series: TimeSeries) -> Self {
+        Self {
+            state: Mutex::new(series),
+        }
+    }
+
+    /// The merged digest of the time range as a TDigest.
+    fn range_digest(
+        &self,
+        py: Python<'_>,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<PyTDigest> {
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                return Err(PyValueError::new_err(
+                    "start must not be greater than end.",
+                ));
+            }
+        }
+        py.detach(|| {
+            let digest =
+                self.state.lock().digest(start, end).map_err(malloc_error)?;
+            Ok(wrap_digest(digest))
+        })
+    }
+}
+
+#[pymethods]
+impl PyTimeSeriesDigest {
+    /// Constructs a new empty TimeSeriesDigest instance with a sub-digest
+    /// for every `resolution` time units.
+    #[new]
+    #[pyo3(signature = (resolution, max_centroids=TD_SIZE_DEFAULT as i64))]
+    pub fn py_new(resolution: f64, max_centroids: i64) -> PyResult<Self> {
+        if !valid_resolution(resolution) {
+            return Err(PyValueError::new_err(
+                "resolution must be finite and greater than 0.",
+            ));
+        }
+        let max_size = validate_max_centroids(max_centroids)?;
+        Ok(Self::from_series(TimeSeries::new(resolution, max_size)))
+    }
+
+    /// Getter property: returns the length of the finest intervals.
+    #[getter(resolution)]
+    pub fn get_resolution(&self) -> f64 {
+        self.state.lock().resolution
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().max_size
+    }
+
+    /// Getter property: returns the number of buckets.
+    #[getter(n_buckets)]
+    pub fn get_n_buckets(&self) -> usize {
+        self.state.lock().buckets.len()
+    }
+
+    /// Getter property: returns the number of data points.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().count()
+    }
+
+    /// Updates the digest (in-place) with a single value at the given
+    /// timestamp.
+    #[pyo3(signature = (timestamp, x, w=None))]
+    pub fn update(
+        &self,
+        timestamp: f64,
+        x: f64,
+        w: Option<f64>,
+    ) -> PyResult<()> {
+        validate_timestamps(&[timestamp])?;
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut series = self.state.lock();
+        series.add(timestamp, x, weight).map_err(malloc_error)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values at the
+    /// given timestamps, in any order.
+    #[pyo3(signature = (timestamps, x, w=None))]
+    pub fn batch_update(
+        &self,
+        timestamps: &Bound<'_, PyAny>,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (times, _) = extract_values(timestamps)?;
+        let (values, skip_nan) = extract_values(x)?;
+        if times.len() != values.len() {
+            return Err(PyValueError::new_err(
+                "timestamps must have the same length as x.",
+            ));
+        }
+        let weights = validate_weights(w, values.len())?;
+        let mut kept_times = Vec::new();
+        let mut pairs = Vec::new();
+        for (j, (t, x)) in times.iter(py).zip(values.iter(py)).enumerate() {
+            // missing values are left out together with their timestamps
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            kept_times.push(t);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_timestamps(&kept_times)?;
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        py.detach(|| {
+            let mut series = self.state.lock();
+            series.add_batch(&kept_times, &pairs).map_err(malloc_error)
+        })
+    }
+
+    /// Merges the buckets within each interval of `resolution` time units
+    /// (in-place) into one bucket, for the intervals that end at or before
+    /// `before`, or all of them.
+    #[pyo3(signature = (resolution, before=None))]
+    pub fn rollup(&self, resolution: f64, before: Option<f64>) -> PyResult<()> {
+        if let Some(before) = before {
+            validate_timestamps(&[before])?;
+        }
+        let mut series = self.state.lock();
+        let ratio = resolution / series.resolution;
+        let factor = ratio.round();
+        if !(factor >= 1.0 && factor < i64::MAX as f64)
+            || (ratio - factor).abs() > 1e-9 * factor
+        {
+            return Err(PyValueError::new_err(
+                "resolution must be a positive multiple of the resolution \
+                 of the digest.",
+            ));
+        }
+        // an interval ends at or before `before` if it ends at or before
+        // the first epoch of `before`
+        let limit = before.map(|before| series.epoch(before));
+        series.rollup(factor as i64, limit).map_err(malloc_error)
+    }
+
+    /// Returns the time ranges (start, end) of the buckets, in order.
+    pub fn ranges(&self) -> Vec<(f64, f64)> {
+        self.state.lock().ranges()
+    }
+
+    /// Returns the values of the buckets overlapping the time range
+    /// [start, end) as a TDigest, or all values.
+    #[pyo3(signature = (start=None, end=None))]
+    pub fn snapshot(
+        &self,
+        py: Python<'_>,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<PyTDigest> {
+        self.range_digest(py, start, end)
+    }
+
+    /// Estimates the quantile of the values in the time range for a given
+    /// cumulative probability `q`.
+    #[pyo3(signature = (q, start=None, end=None))]
+    pub fn quantile(
+        &self,
+        py: Python<'_>,
+        q: f64,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<f64> {
+        self.range_digest(py, start, end)?
+            .quantile(q)
+            .map(f64::from)
+    }
+
+    /// Estimates the percentile of the values in the time range for a
+    /// given cumulative probability `p` (%).
+    #[pyo3(signature = (p, start=None, end=None))]
+    pub fn percentile(
+        &self,
+        py: Python<'_>,
+        p: f64,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<f64> {
+        self.range_digest(py, start, end)?
+            .percentile(p)
+            .map(f64::from)
+    }
+
+    /// Estimates the median of the values in the time range.
+    #[pyo3(signature = (start=None, end=None))]
+    pub fn median(
+        &self,
+        py: Python<'_>,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<f64> {
+        self.range_digest(py, start, end)?.median().map(f64::from)
+    }
+
+    /// Estimates the CDF of the values in the time range at the value `x`.
+    #[pyo3(signature = (x, start=None, end=None))]
+    pub fn cdf(
+        &self,
+        py: Python<'_>,
+        x: f64,
+        start: Option<f64>,
+        end: Option<f64>,
+    ) -> PyResult<f64> {
+        self.range_digest(py, start, end)?.rank(x)
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Merges this digest with another with the same resolution, returning
+    /// a new TimeSeriesDigest.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges another digest with the same resolution into this one
+    /// (in-place), bucket by bucket.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        // cloned first, so that merging an instance into itself works
+        let other = other.state.lock().clone();
+        let mut series = self.state.lock();
+        if series.resolution != other.resolution {
+            return Err(PyValueError::new_err(
+                "Cannot merge TimeSeriesDigests with different resolutions.",
+            ));
+        }
+        series.merge(&other).map_err(malloc_error)
+    }
+
+    /// Returns a binary representation of the digest.
+    pub fn to_bytes<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let mut series = self.state.lock();
+        series.flush().map_err(malloc_error)?;
+        let bytes = series.to_bytes().map_err(malloc_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reconstructs a TimeSeriesDigest from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let series = TimeSeries::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_series(series))
+    }
+
+    /// Returns a dict representation of the digest.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let mut series = self.state.lock();
+        series.flush().map_err(malloc_error)?;
+        let epochs: Vec<i64> = series.buckets.keys().copied().collect();
+        let lengths: Vec<i64> =
+            series.buckets.values().map(|bucket| bucket.len).collect();
+        let digests = PyList::empty(py);
+        for bucket in series.buckets.values() {
+            let digest = wrap_digest(bucket.entry.digest.clone());
+            digests.append(digest.to_dict(py)?)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("resolution", series.resolution)?;
+        dict.set_item("max_centroids", series.max_size)?;
+        dict.set_item("epochs", epochs)?;
+        dict.set_item("lengths", lengths)?;
+        dict.set_item("digests", digests)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a TimeSeriesDigest from a dict.
+    #[staticmethod]
+    pub fn from_dict(series_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            series_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let max_size =
+            validate_max_centroids(get("max_centroids")?.extract()?)?;
+        let epochs: Vec<i64> = get("epochs")?.extract()?;
+        let lengths: Vec<i64> = get("lengths")?.extract()?;
+        let digests = get("digests")?
+            .cast::<PyList>()?
+            .iter()
+            .map(|digest_dict| {
+                let digest = PyTDigest::from_dict(digest_dict.cast()?, false)?;
+                Ok(digest.state.into_inner().digest)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let inconsistent = || {
+            SerializationError::new_err(
+                "Inconsistent TimeSeriesDigest buckets.",
+            )
+        };
+        if epochs.len() != digests.len() || lengths.len() != digests.len() {
+            return Err(inconsistent());
+        }
+        let buckets = epochs
+            .into_iter()
+            .zip(lengths)
+            .zip(digests)
+            .map(|((first, len), digest)| (first, len, digest))
+            .collect();
+        let series = TimeSeries::from_parts(
+            get("resolution")?.extract()?,
+            max_size,
+            buckets,
+        )
+        .ok_or_else(inconsistent)?;
+        Ok(Self::from_series(series))
+    }
+
+    /// TimeSeriesDigest.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_series(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(digest) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(digest) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via TimeSeriesDigest.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)?])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(TimeSeriesDigest) returns a string
+    /// representation.
+    pub fn __repr__(&self) -> String {
+        let series = self.state.lock();
+        format!(
+            "TimeSeriesDigest(resolution={:?}, max_centroids={})",
+            series.resolution, series.max_size
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> PyResult<bool> {
+        if std::ptr::eq(self, other) {
+            return Ok(true);
+        }
+        let mut other = other.state.lock().clone();
+        other.flush().map_err(malloc_error)?;
+        let mut series = self.state.lock();
+        series.flush().map_err(malloc_error)?;
+        Ok(series.same_state(&other))
+    }
+
+    /// Magic method: digest1 + digest2 returns digest1.merge(digest2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: digest1 += digest2 merges digest2 into digest1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,498 @@
+//! SpaceSaving: tracking the most frequent items (heavy hitters)
+//!
+//! The sketch monitors at most `k` items with a counter each (Metwally et
+//! al., 2005). A new item that finds all counters taken replaces the item
+//! with the smallest count `min`, inheriting `min` as its count and as the
+//! overestimation error of that count. Each count thus overestimates the
+//! true frequency by at most its error, which is at most `total / k`, and
+//! every item occurring more than `total / k` times is monitored. Sketches
+//! are merged as described by Agarwal et al. (2012): an item missing from
+//! one sketch may have occurred up to that sketch's `min` times there.
+
+use crate::bytes_error;
+use crate::codec::{Reader, Writer};
+use crate::errors::SerializationError;
+use crate::hash::Value;
+use crate::tdigest::BytesError;
+use parking_lot::Mutex;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyTuple};
+use std::collections::{BTreeSet, HashMap};
+
+const MAGIC: [u8; 8] = *b"FASTTOPK";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Counter {
+    item: Value,
+    count: u64,
+    /// Upper bound of the overestimation of `count`
+    error: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpaceSaving {
+    k: usize,
+    total: u64,
+    counters: Vec<Counter>,
+    /// Position of each monitored item in `counters`
+    index: HashMap<Value, usize>,
+    /// Counters ordered by count (and position)
+    by_count: BTreeSet<(u64, usize)>,
+}
+
+impl PartialEq for SpaceSaving {
+    fn eq(&self, other: &Self) -> bool {
+        self.k == other.k
+            && self.total == other.total
+            && self.counters == other.counters
+    }
+}
+
+impl SpaceSaving {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            total: 0,
+            counters: Vec::new(),
+            index: HashMap::new(),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// Largest possible overestimation of any count, and the count of any
+    /// item that is not monitored: the smallest count once all `k` counters
+    /// are taken, zero before.
+    pub fn max_error(&self) -> u64 {
+        if self.counters.len() < self.k {
+            return 0;
+        }
+        self.by_count.first().map_or(0, |&(count, _)| count)
+    }
+
+    pub fn add(&mut self, item: Value, count: u64) {
+        self.total = self.total.saturating_add(count);
+        if let Some(&i) = self.index.get(&item) {
+            let counter = &mut self.counters[i];
+            self.by_count.remove(&(counter.count, i));
+            counter.count = counter.count.saturating_add(count);
+            self.by_count.insert((counter.count, i));
+            return;
+        }
+        if self.counters.len() < self.k {
+            let i = self.counters.len();
+            self.index.insert(item.clone(), i);
+            self.by_count.insert((count, i));
+            self.counters.push(Counter {
+                item,
+                count,
+                error: 0,
+            });
+            return;
+        }
+        let Some((min, i)) = self.by_count.pop_first() else {
+            return;
+        };
+        let replaced = &mut self.counters[i];
+        self.index.remove(&replaced.item);
+        self.index.insert(item.clone(), i);
+        *replaced = Counter {
+            item,
+            count: min.saturating_add(count),
+            error: min,
+        };
+        self.by_count.insert((replaced.count, i));
+    }
+
+    /// Upper bound of the frequency of an item.
+    pub fn estimate(&self, item: &Value) -> u64 {
+        match self.index.get(item) {
+            Some(&i) => self.counters[i].count,
+            None => self.max_error(),
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, item: &Value) -> bool {
+        self.index.contains_key(item)
+    }
+
+    /// Monitored counters by descending count (and ascending error).
+    fn sorted(&self) -> Vec<&Counter> {
+        let mut counters: Vec<_> = self.counters.iter().collect();
+        counters.sort_by(|a, b| {
+            b.count.cmp(&a.count).then_with(|| a.error.cmp(&b.error))
+        });
+        counters
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        let (min_self, min_other) = (self.max_error(), other.max_error());
+        let mut merged: Vec<Counter> = Vec::new();
+        for counter in &self.counters {
+            let (count, error) = match other.index.get(&counter.item) {
+                Some(&j) => (other.counters[j].count, other.counters[j].error),
+                None => (min_other, min_other),
+            };
+            merged.push(Counter {
+                item: counter.item.clone(),
+                count: counter.count.saturating_add(count),
+                error: counter.error.saturating_add(error),
+            });
+        }
+        for counter in &other.counters {
+            if !self.index.contains_key(&counter.item) {
+                merged.push(Counter {
+                    item: counter.item.clone(),
+                    count: counter.count.saturating_add(min_self),
+                    error: counter.error.saturating_add(min_self),
+                });
+            }
+        }
+        // the sort is stable, which keeps merges deterministic
+        merged.sort_by_key(|counter| std::cmp::Reverse(counter.count));
+        let k = self.k.min(other.k);
+        merged.truncate(k);
+        let total = self.total.saturating_add(other.total);
+        *self = Self::with_counters(k, total, merged);
+    }
+
+    fn with_counters(k: usize, total: u64, counters: Vec<Counter>) -> Self {
+        let index = counters
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| (counter.item.clone(), i))
+            .collect();
+        let by_count = counters
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| (counter.count, i))
+            .collect();
+        Self {
+            k,
+            total,
+            counters,
+            index,
+            by_count,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.u64(self.k as u64);
+        writer.u64(self.total);
+        writer.u64(self.counters.len() as u64);
+        for counter in &self.counters {
+            counter.item.write(&mut writer);
+            writer.u64(counter.count);
+            writer.u64(counter.error);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let k = reader.u64()? as usize;
+        let total = reader.u64()?;
+        let len = reader.seq_len(17)?;
+        let mut counters = Vec::new();
+        counters
+            .try_reserve_exact(len)
+            .map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            counters.push(Counter {
+                item: Value::read(&mut reader)?,
+                count: reader.u64()?,
+                error: reader.u64()?,
+            });
+        }
+        reader.finish()?;
+        Self::from_parts(k, total, counters).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a sketch, checking that the parts are consistent.
+    fn from_parts(
+        k: usize,
+        total: u64,
+        counters: Vec<Counter>,
+    ) -> Option<Self> {
+        let valid = k > 0
+            && counters.len() <= k
+            && counters
+                .iter()
+                .all(|c| c.error < c.count && c.count <= total);
+        let sketch = Self::with_counters(k, total, counters);
+        // duplicate items would share an index entry
+        (valid && sketch.index.len() == sketch.counters.len()).then_some(sketch)
+    }
+}
+
+#[pyclass(name = "TopK", module = "fastdigest", frozen)]
+pub struct PyTopK {
+    state: Mutex<SpaceSaving>,
+}
+
+impl PyTopK {
+    fn from_sketch(sketch: SpaceSaving) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+}
+
+/// Converts counters to a list of (item, count, error) tuples.
+fn counter_list<'py, 'a>(
+    py: Python<'py>,
+    counters: impl IntoIterator<Item = &'a Counter>,
+) -> PyResult<Bound<'py, PyList>> {
+    let tuples = counters
+        .into_iter()
+        .map(|c| (c.item.to_object(py)?, c.count, c.error).into_pyobject(py))
+        .collect::<PyResult<Vec<_>>>()?;
+    PyList::new(py, tuples)
+}
+
+#[pymethods]
+impl PyTopK {
+    /// Constructs a new empty TopK instance that monitors up to `k` items.
+    #[new]
+    pub fn py_new(k: i64) -> PyResult<Self> {
+        let k = usize::try_from(k)
+            .ok()
+            .filter(|&k| k > 0)
+            .ok_or_else(|| PyValueError::new_err("k must be positive."))?;
+        Ok(Self::from_sketch(SpaceSaving::new(k)))
+    }
+
+    /// Getter property: returns the maximum number of monitored items.
+    #[getter(k)]
+    pub fn get_k(&self) -> usize {
+        self.state.lock().k
+    }
+
+    /// Getter property: returns the total count of all items.
+    #[getter(total)]
+    pub fn get_total(&self) -> u64 {
+        self.state.lock().total
+    }
+
+    /// Getter property: returns the largest possible overestimation of any
+    /// count.
+    #[getter(max_error)]
+    pub fn get_max_error(&self) -> u64 {
+        self.state.lock().max_error()
+    }
+
+    /// Adds `count` occurrences of a hashable item (in-place).
+    #[pyo3(signature = (item, count=1))]
+    pub fn update(&self, item: &Bound<'_, PyAny>, count: i64) -> PyResult<()> {
+        let count = u64::try_from(count)
+            .ok()
+            .filter(|&c| c > 0)
+            .ok_or_else(|| PyValueError::new_err("count must be positive."))?;
+        let item = Value::extract(item)?;
+        self.state.lock().add(item, count);
+        Ok(())
+    }
+
+    /// Adds one occurrence of each item of an iterable or array (in-place).
+    pub fn batch_update(&self, x: &Bound<'_, PyAny>) -> PyResult<()> {
+        let items = Value::extract_all(x)?;
+        let mut sketch = self.state.lock();
+        for item in items {
+            sketch.add(item, 1);
+        }
+        Ok(())
+    }
+
+    /// Returns the `n` (default: all) monitored items with the highest
+    /// counts as (item, count, error) tuples.
+    #[pyo3(signature = (n=None))]
+    pub fn top<'py>(
+        &self,
+        py: Python<'py>,
+        n: Option<usize>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let sketch = self.state.lock();
+        let sorted = sketch.sorted();
+        let n = n.unwrap_or(sorted.len()).min(sorted.len());
+        counter_list(py, sorted[..n].iter().copied())
+    }
+
+    /// Returns the monitored items whose count exceeds `phi` times the
+    /// total count. If `guaranteed`, only items whose count minus error
+    /// exceeds it.
+    #[pyo3(signature = (phi, guaranteed=false))]
+    pub fn heavy_hitters<'py>(
+        &self,
+        py: Python<'py>,
+        phi: f64,
+        guaranteed: bool,
+    ) -> PyResult<Bound<'py, PyList>> {
+        if !(phi > 0.0 && phi <= 1.0) {
+            return Err(PyValueError::new_err(
+                "phi must be greater than 0 and at most 1.",
+            ));
+        }
+        let sketch = self.state.lock();
+        let threshold = phi * sketch.total as f64;
+        let hitters = sketch.sorted().into_iter().filter(|c| {
+            let bound = if guaranteed {
+                c.count - c.error
+            } else {
+                c.count
+            };
+            bound as f64 > threshold
+        });
+        counter_list(py, hitters)
+    }
+
+    /// Returns an upper bound of the count of a hashable item.
+    pub fn estimate(&self, item: &Bound<'_, PyAny>) -> PyResult<u64> {
+        let item = Value::extract(item)?;
+        Ok(self.state.lock().estimate(&item))
+    }
+
+    /// Merges this sketch with another, returning a new TopK.
+    pub fn merge(&self, other: &Self) -> Self {
+        let merged = self.copy();
+        merged.merge_inplace(other);
+        merged
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) {
+        let other = other.state.lock().clone();
+        self.state.lock().merge(&other);
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a TopK from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = SpaceSaving::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("k", sketch.k)?;
+        dict.set_item("total", sketch.total)?;
+        dict.set_item("counters", counter_list(py, &sketch.counters)?)?;
+        Ok(dict)
+    }
+
+    /// Reconstructs a TopK from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let counters = get("counters")?
+            .try_iter()?
+            .map(|counter| {
+                let (item, count, error): (Bound<'_, PyAny>, u64, u64) =
+                    counter?.extract()?;
+                Ok(Counter {
+                    item: Value::extract(&item)?,
+                    count,
+                    error,
+                })
+            })
+            .collect::<PyResult<_>>()?;
+        let sketch = SpaceSaving::from_parts(
+            get("k")?.extract()?,
+            get("total")?.extract()?,
+            counters,
+        )
+        .ok_or_else(|| {
+            SerializationError::new_err("Inconsistent TopK counters.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// TopK.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via TopK.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: len(sketch) returns the number of monitored items.
+    pub fn __len__(&self) -> usize {
+        self.state.lock().counters.len()
+    }
+
+    /// Magic method: `item in sketch` checks whether an item is monitored.
+    pub fn __contains__(&self, item: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let item = Value::extract(item)?;
+        Ok(self.state.lock().contains(&item))
+    }
+
+    /// Magic method: repr/str(TopK) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        format!("TopK(k={})", self.state.lock().k)
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> Self {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) {
+        self.merge_inplace(other)
+    }
+}
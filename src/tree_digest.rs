@@ -0,0 +1,341 @@
+//! Balanced-tree backend for single updates
+//!
+//! The centroids are kept in an AVL tree ordered by mean, where every node
+//! also holds the total weight of its subtree (like the AVLTreeDigest of
+//! the reference implementation). A new value is added to the closest
+//! centroid if the scale function allows that centroid to grow, and is
+//! inserted as a new centroid otherwise, in O(log n) either way. Unlike
+//! the buffer of single updates, nothing has to be flushed while values
+//! come in: the tree is folded into the merging digest when that is read.
+
+use crate::tdigest::{Centroid, Resolution, TDigest};
+use std::collections::TryReserveError;
+
+const NIL: usize = usize::MAX;
+/// Upper bound on the height of an AVL tree (1.44 * log2 of the number of
+/// nodes), and thus on the length of a search path
+const MAX_HEIGHT: usize = 96;
+/// The tree is compressed when it holds this many times `max_size`
+/// centroids, which the scale function keeps rare.
+const COMPRESS_FACTOR: usize = 20;
+
+#[derive(Debug, Clone)]
+struct Node {
+    centroid: Centroid,
+    left: usize,
+    right: usize,
+    height: u32,
+    /// Total weight of the subtree
+    weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeDigest {
+    nodes: Vec<Node>,
+    root: usize,
+    max_size: usize,
+    resolution: Resolution,
+    sum: f64,
+    min: f64,
+    max: f64,
+    count: u128,
+}
+
+impl TreeDigest {
+    /// Empty tree with the compression settings of `digest`.
+    pub fn for_digest(digest: &TDigest) -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: NIL,
+            max_size: digest.max_size(),
+            resolution: digest.resolution(),
+            sum: 0.0,
+            min: f64::NAN,
+            max: f64::NAN,
+            count: 0,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    /// Number of centroids.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn count(&self) -> u128 {
+        self.count
+    }
+
+    pub fn mass(&self) -> f64 {
+        self.subtree_weight(self.root)
+    }
+
+    /// Bytes allocated on the heap for the nodes.
+    pub fn heap_bytes(&self) -> usize {
+        self.nodes.capacity() * size_of::<Node>()
+    }
+
+    /// Adds a value with a weight greater than 0.
+    pub fn add(&mut self, x: f64, weight: f64) -> Result<(), TryReserveError> {
+        self.nodes.try_reserve(1)?;
+        let mass = self.mass() + weight;
+        if self.is_empty() {
+            self.min = x;
+            self.max = x;
+        } else {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+        }
+        self.sum += x * weight;
+        self.count += 1;
+
+        let mut path = [NIL; MAX_HEIGHT];
+        // without a size limit (max_size 0), every value is a centroid
+        let fitting = match self.max_size {
+            0 => None,
+            _ => self.closest_fitting(x, weight, mass, &mut path),
+        };
+        if let Some(depth) = fitting {
+            for &node in &path[..=depth] {
+                self.nodes[node].weight += weight;
+            }
+            self.nodes[path[depth]].centroid.add(x * weight, weight);
+            return Ok(());
+        }
+
+        self.nodes.push(Node {
+            centroid: Centroid::new(x, weight),
+            left: NIL,
+            right: NIL,
+            height: 1,
+            weight,
+        });
+        self.root = self.insert(self.root, self.nodes.len() - 1);
+        if self.max_size > 0
+            && self.nodes.len() > COMPRESS_FACTOR * self.max_size
+        {
+            self.compress()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the centroids into `digest` and empties the tree, which then
+    /// takes over the current settings of `digest`.
+    pub fn flush_into(
+        &mut self,
+        digest: &mut TDigest,
+    ) -> Result<(), TryReserveError> {
+        if !self.is_empty() {
+            digest.merge_digest_inplace(&self.to_digest()?)?;
+        }
+        *self = Self {
+            nodes: std::mem::take(&mut self.nodes),
+            ..Self::for_digest(digest)
+        };
+        self.nodes.clear();
+        Ok(())
+    }
+
+    /// Walks the search path of `x` into `path`, returning the depth of the
+    /// closest centroid on it that can take `weight` in a total `mass`.
+    /// Both neighbors of `x` are on the path, and if they are equally close,
+    /// either of them may be chosen.
+    fn closest_fitting(
+        &self,
+        x: f64,
+        weight: f64,
+        mass: f64,
+        path: &mut [usize; MAX_HEIGHT],
+    ) -> Option<usize> {
+        // (depth, weight of all centroids before) of the neighbors
+        let mut floor: Option<(usize, f64)> = None;
+        let mut ceil: Option<(usize, f64)> = None;
+        let mut head = 0.0;
+        let mut node = self.root;
+        let mut depth = 0;
+        while node != NIL {
+            path[depth] = node;
+            let n = &self.nodes[node];
+            let left_weight = self.subtree_weight(n.left);
+            if n.centroid.mean() <= x {
+                floor = Some((depth, head + left_weight));
+                head += left_weight + n.centroid.weight();
+                node = n.right;
+            } else {
+                ceil = Some((depth, head + left_weight));
+                node = n.left;
+            }
+            depth += 1;
+        }
+
+        let distance = |(depth, _): (usize, f64)| {
+            (self.nodes[path[depth]].centroid.mean() - x).abs()
+        };
+        let nearest = [floor, ceil]
+            .into_iter()
+            .flatten()
+            .map(distance)
+            .reduce(f64::min)?;
+        let d = self.max_size as f64;
+        [floor, ceil]
+            .into_iter()
+            .flatten()
+            .find_map(|(depth, head)| {
+                let centroid = &self.nodes[path[depth]].centroid;
+                let q = (head + 0.5 * centroid.weight()) / mass;
+                let limit = mass * self.resolution.max_weight_fraction(q, d);
+                let fits = distance((depth, head)) == nearest
+                    && centroid.weight() + weight <= limit;
+                fits.then_some(depth)
+            })
+    }
+
+    /// Digest with all centroids of the tree, uncompressed.
+    fn to_digest(&self) -> Result<TDigest, TryReserveError> {
+        let mut centroids: Vec<Centroid> = Vec::new();
+        centroids.try_reserve_exact(self.nodes.len())?;
+        self.collect(self.root, &mut centroids);
+        // rounding of the updated means may leave ties out of order
+        centroids.sort();
+        let n_centroids = centroids.len();
+        let mut digest = TDigest::new(
+            centroids,
+            n_centroids,
+            self.mass(),
+            self.sum,
+            self.min,
+            self.max,
+            self.count,
+        )?;
+        digest.set_resolution(self.resolution);
+        Ok(digest)
+    }
+
+    /// Replaces the tree by a balanced one of its centroids merged down to
+    /// `max_size`.
+    fn compress(&mut self) -> Result<(), TryReserveError> {
+        let mut compressed = TDigest::new_with_size(self.max_size)?;
+        compressed.set_resolution(self.resolution);
+        compressed.merge_digest_inplace(&self.to_digest()?)?;
+        self.nodes.clear();
+        self.root = self.build(compressed.centroids());
+        Ok(())
+    }
+
+    fn collect(&self, node: usize, out: &mut Vec<Centroid>) {
+        if node != NIL {
+            let n = &self.nodes[node];
+            self.collect(n.left, out);
+            out.push(n.centroid.clone());
+            self.collect(n.right, out);
+        }
+    }
+
+    /// Builds a balanced subtree of sorted centroids, whose nodes must fit
+    /// into the allocated capacity.
+    fn build(&mut self, centroids: &[Centroid]) -> usize {
+        if centroids.is_empty() {
+            return NIL;
+        }
+        let middle = centroids.len() / 2;
+        let left = self.build(&centroids[..middle]);
+        let right = self.build(&centroids[middle + 1..]);
+        self.nodes.push(Node {
+            centroid: centroids[middle].clone(),
+            left,
+            right,
+            height: 0,
+            weight: 0.0,
+        });
+        let node = self.nodes.len() - 1;
+        self.fix(node);
+        node
+    }
+
+    fn insert(&mut self, node: usize, new: usize) -> usize {
+        if node == NIL {
+            return new;
+        }
+        if self.nodes[node].centroid.mean() <= self.nodes[new].centroid.mean() {
+            let right = self.insert(self.nodes[node].right, new);
+            self.nodes[node].right = right;
+        } else {
+            let left = self.insert(self.nodes[node].left, new);
+            self.nodes[node].left = left;
+        }
+        self.rebalance(node)
+    }
+
+    fn rebalance(&mut self, node: usize) -> usize {
+        self.fix(node);
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        let balance = self.height(left) as i64 - self.height(right) as i64;
+        if balance > 1 {
+            let l = &self.nodes[left];
+            if self.height(l.left) < self.height(l.right) {
+                self.nodes[node].left = self.rotate_left(left);
+            }
+            return self.rotate_right(node);
+        }
+        if balance < -1 {
+            let r = &self.nodes[right];
+            if self.height(r.right) < self.height(r.left) {
+                self.nodes[node].right = self.rotate_right(right);
+            }
+            return self.rotate_left(node);
+        }
+        node
+    }
+
+    fn rotate_left(&mut self, node: usize) -> usize {
+        let right = self.nodes[node].right;
+        self.nodes[node].right = self.nodes[right].left;
+        self.fix(node);
+        self.nodes[right].left = node;
+        self.fix(right);
+        right
+    }
+
+    fn rotate_right(&mut self, node: usize) -> usize {
+        let left = self.nodes[node].left;
+        self.nodes[node].left = self.nodes[left].right;
+        self.fix(node);
+        self.nodes[left].right = node;
+        self.fix(left);
+        left
+    }
+
+    /// Recomputes the height and subtree weight of a node from its children.
+    fn fix(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        let height = 1 + self.height(left).max(self.height(right));
+        let weight = self.subtree_weight(left)
+            + self.subtree_weight(right)
+            + self.nodes[node].centroid.weight();
+        let n = &mut self.nodes[node];
+        n.height = height;
+        n.weight = weight;
+    }
+
+    #[inline]
+    fn height(&self, node: usize) -> u32 {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node].height
+        }
+    }
+
+    #[inline]
+    fn subtree_weight(&self, node: usize) -> f64 {
+        if node == NIL {
+            0.0
+        } else {
+            self.nodes[node].weight
+        }
+    }
+}
@@ -0,0 +1,727 @@
+//! UDDSketch: DDSketch with uniformly collapsing buckets
+//!
+//! Values are counted in the logarithmic buckets of DDSketch, bucket `k`
+//! holding the values in `(gamma^(k-1), gamma^k]` with initially
+//! `gamma = (1 + a) / (1 - a)`. Instead of collapsing its lowest buckets,
+//! the sketch keeps at most `max_bins` non-empty buckets by merging every
+//! pair of adjacent ones, i.e. squaring `gamma`, after which the relative
+//! accuracy is `2a / (1 + a^2)` (Epicoco, Melle, Cafaro, Pulimeno &
+//! Morleo, 2020). The guarantee thus holds for all quantiles, and degrades
+//! only as much as the data range requires.
+
+use crate::codec::{Reader, Writer};
+use crate::ddsketch::valid_relative_accuracy;
+use crate::errors::{
+    EmptyDigestError, InvalidQuantileError, SerializationError,
+};
+use crate::tdigest::BytesError;
+use crate::{
+    bytes_error, extract_values, validate_value, validate_values,
+    validate_weight, validate_weights,
+};
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use std::collections::BTreeMap;
+
+const MAGIC: [u8; 8] = *b"FASTUDD~";
+const VERSION: u32 = 1;
+const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+const DEFAULT_MAX_BINS: usize = 1024;
+/// After this many collapses, any data fits into four buckets.
+const MAX_COLLAPSES: u32 = 64;
+
+/// Sparse bucket counts by key.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Store {
+    bins: BTreeMap<i64, f64>,
+    total: f64,
+}
+
+impl Store {
+    fn add(&mut self, key: i64, weight: f64) {
+        *self.bins.entry(key).or_insert(0.0) += weight;
+        self.total += weight;
+    }
+
+    /// Merges each pair of adjacent buckets `2k - 1` and `2k` into `k`.
+    fn collapse(&mut self) {
+        let mut bins = BTreeMap::new();
+        for (&key, &count) in &self.bins {
+            *bins.entry((key + 1).div_euclid(2)).or_insert(0.0) += count;
+        }
+        self.bins = bins;
+    }
+
+    /// Returns true if collapsing would change any key.
+    fn collapsible(&self) -> bool {
+        self.bins.keys().any(|k| !(0..=1).contains(k))
+    }
+
+    fn merge(&mut self, other: &Store) {
+        for (&key, &count) in &other.bins {
+            self.add(key, count);
+        }
+    }
+
+    /// Key of the bucket holding the value of rank `rank` (0-based).
+    fn key_at_rank(&self, rank: f64, lower: bool) -> i64 {
+        let mut cumulative = 0.0;
+        for (&key, &count) in &self.bins {
+            cumulative += count;
+            if (lower && cumulative > rank)
+                || (!lower && cumulative >= rank + 1.0)
+            {
+                return key;
+            }
+        }
+        self.bins.last_key_value().map_or(0, |(&key, _)| key)
+    }
+
+    /// Mass of the buckets below `key`, plus half of its own.
+    fn mass_below(&self, key: i64) -> f64 {
+        let below: f64 = self.bins.range(..key).map(|(_, &c)| c).sum();
+        below + self.bins.get(&key).map_or(0.0, |&c| c / 2.0)
+    }
+
+    fn encode(&self, writer: &mut Writer) {
+        writer.u64(self.bins.len() as u64);
+        for (&key, &count) in &self.bins {
+            writer.i64(key);
+            writer.f64(count);
+        }
+    }
+
+    fn decode(reader: &mut Reader<'_>) -> Result<Self, BytesError> {
+        let len = reader.seq_len(16)?;
+        let mut bins = Vec::new();
+        bins.try_reserve_exact(len).map_err(BytesError::MemError)?;
+        for _ in 0..len {
+            bins.push((reader.i64()?, reader.f64()?));
+        }
+        Self::from_parts(bins).ok_or(BytesError::CorruptData)
+    }
+
+    /// Assembles a store from `(key, count)` pairs of increasing keys.
+    fn from_parts(bins: Vec<(i64, f64)>) -> Option<Self> {
+        let valid = bins.windows(2).all(|w| w[0].0 < w[1].0)
+            && bins.iter().all(|&(_, c)| c.is_finite() && c > 0.0);
+        valid.then(|| Self {
+            total: bins.iter().map(|&(_, c)| c).sum(),
+            bins: bins.into_iter().collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UDDSketch {
+    relative_accuracy: f64,
+    max_bins: usize,
+    n_collapses: u32,
+    /// ln(gamma) after `n_collapses` collapses
+    gamma_ln: f64,
+    positive: Store,
+    negative: Store,
+    zero_count: f64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl UDDSketch {
+    pub fn new(relative_accuracy: f64, max_bins: usize) -> Self {
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            max_bins,
+            n_collapses: 0,
+            gamma_ln: gamma.ln(),
+            positive: Store::default(),
+            negative: Store::default(),
+            zero_count: 0.0,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    #[inline]
+    fn key(&self, x: f64) -> i64 {
+        (x.ln() / self.gamma_ln).ceil() as i64
+    }
+
+    /// Returns the representative `2 gamma^k / (gamma + 1)` of a bucket,
+    /// computed in log space so that it stays finite for huge `gamma`.
+    #[inline]
+    fn value(&self, key: i64) -> f64 {
+        // ln(gamma + 1) = ln(gamma) + ln(1 + 1 / gamma)
+        let ln_1p_inv = (-self.gamma_ln).exp().ln_1p();
+        ((key - 1) as f64 * self.gamma_ln + 2f64.ln() - ln_1p_inv).exp()
+    }
+
+    /// Returns the relative accuracy after the collapses so far.
+    #[inline]
+    pub fn current_relative_accuracy(&self) -> f64 {
+        (self.gamma_ln / 2.0).tanh()
+    }
+
+    #[inline]
+    pub fn n_bins(&self) -> usize {
+        self.positive.bins.len() + self.negative.bins.len()
+    }
+
+    fn collapse(&mut self) {
+        self.positive.collapse();
+        self.negative.collapse();
+        self.n_collapses += 1;
+        self.gamma_ln *= 2.0;
+    }
+
+    /// Collapses until the sketch fits into `max_bins` buckets, or the
+    /// buckets cannot be merged any further: keys `0` and `1` stay.
+    fn shrink(&mut self) {
+        while self.n_bins() > self.max_bins
+            && (self.positive.collapsible() || self.negative.collapsible())
+        {
+            self.collapse();
+        }
+    }
+
+    pub fn add(&mut self, x: f64, weight: f64) {
+        // subnormal magnitudes are counted as zero
+        if x >= f64::MIN_POSITIVE {
+            self.positive.add(self.key(x), weight);
+        } else if x <= -f64::MIN_POSITIVE {
+            self.negative.add(self.key(-x), weight);
+        } else {
+            self.zero_count += weight;
+        }
+        self.count += 1;
+        self.sum += x * weight;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self.shrink();
+    }
+
+    /// Merges another sketch with the same initial relative accuracy,
+    /// collapsing the finer of the two to the coarser one first.
+    pub fn merge(&mut self, other: &UDDSketch) {
+        let mut other = other.clone();
+        while self.n_collapses < other.n_collapses {
+            self.collapse();
+        }
+        while other.n_collapses < self.n_collapses {
+            other.collapse();
+        }
+        self.positive.merge(&other.positive);
+        self.negative.merge(&other.negative);
+        self.zero_count += other.zero_count;
+        self.count = self.count.saturating_add(other.count);
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.shrink();
+    }
+
+    #[inline]
+    pub fn mass(&self) -> f64 {
+        self.negative.total + self.zero_count + self.positive.total
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn quantile(&self, q: f64) -> f64 {
+        let rank = q * (self.mass() - 1.0).max(0.0);
+        let x = if rank < self.negative.total {
+            let reversed = self.negative.total - rank - 1.0;
+            -self.value(self.negative.key_at_rank(reversed, false))
+        } else if rank < self.negative.total + self.zero_count {
+            0.0
+        } else {
+            let rank = rank - self.negative.total - self.zero_count;
+            self.value(self.positive.key_at_rank(rank, true))
+        };
+        x.clamp(self.min, self.max)
+    }
+
+    /// Estimates the fraction of the mass at or below `x`, counting half
+    /// of the bucket that holds `x`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        if x < self.min {
+            return 0.0;
+        }
+        if x >= self.max {
+            return 1.0;
+        }
+        let below = if x >= f64::MIN_POSITIVE {
+            self.negative.total
+                + self.zero_count
+                + self.positive.mass_below(self.key(x))
+        } else if x <= -f64::MIN_POSITIVE {
+            // negative values are stored by magnitude, in reverse order
+            let key = self.key(-x);
+            self.negative.total - self.negative.mass_below(key + 1)
+                + self.negative.bins.get(&key).map_or(0.0, |&c| c / 2.0)
+        } else {
+            self.negative.total + self.zero_count / 2.0
+        };
+        below / self.mass()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(&MAGIC, VERSION);
+        writer.f64(self.relative_accuracy);
+        writer.u64(self.max_bins as u64);
+        writer.u32(self.n_collapses);
+        writer.u64(self.count);
+        writer.f64(self.sum);
+        writer.f64(self.min);
+        writer.f64(self.max);
+        writer.f64(self.zero_count);
+        self.positive.encode(&mut writer);
+        self.negative.encode(&mut writer);
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytesError> {
+        let mut reader = Reader::new(bytes, &MAGIC, VERSION)?;
+        let relative_accuracy = reader.f64()?;
+        let max_bins = usize::try_from(reader.u64()?)
+            .map_err(|_| BytesError::CorruptData)?;
+        let n_collapses = reader.u32()?;
+        let mut sketch =
+            Self::with_collapses(relative_accuracy, max_bins, n_collapses)
+                .ok_or(BytesError::CorruptData)?;
+        sketch.count = reader.u64()?;
+        sketch.sum = reader.f64()?;
+        sketch.min = reader.f64()?;
+        sketch.max = reader.f64()?;
+        sketch.zero_count = reader.f64()?;
+        sketch.positive = Store::decode(&mut reader)?;
+        sketch.negative = Store::decode(&mut reader)?;
+        reader.finish()?;
+        sketch.validate().ok_or(BytesError::CorruptData)
+    }
+
+    /// Checks that restored totals match the buckets: an empty sketch has
+    /// the initial totals, and otherwise `min <= max` are finite, with every
+    /// non-empty bucket (and the zero bucket) holding at least one value
+    /// between them.
+    fn validate(self) -> Option<Self> {
+        let n_buckets = self.n_bins() as u64 + u64::from(self.zero_count > 0.0);
+        let valid = self.zero_count.is_finite()
+            && self.zero_count >= 0.0
+            && if self.count == 0 {
+                n_buckets == 0
+                    && self.sum == 0.0
+                    && self.min == f64::INFINITY
+                    && self.max == f64::NEG_INFINITY
+            } else {
+                [self.sum, self.min, self.max].iter().all(|v| v.is_finite())
+                    && self.min <= self.max
+                    && self.count >= n_buckets
+                    && n_buckets > 0
+                    && (self.positive.bins.is_empty()
+                        || self.max >= f64::MIN_POSITIVE)
+                    && (self.negative.bins.is_empty()
+                        || self.min <= -f64::MIN_POSITIVE)
+            };
+        valid.then_some(self)
+    }
+
+    /// Returns an empty sketch that has been collapsed `n_collapses` times,
+    /// checking the parameters.
+    fn with_collapses(
+        relative_accuracy: f64,
+        max_bins: usize,
+        n_collapses: u32,
+    ) -> Option<Self> {
+        let valid = valid_relative_accuracy(relative_accuracy)
+            && max_bins > 0
+            && n_collapses <= MAX_COLLAPSES;
+        valid.then(|| {
+            let mut sketch = Self::new(relative_accuracy, max_bins);
+            sketch.n_collapses = n_collapses;
+            sketch.gamma_ln *= 2f64.powi(n_collapses as i32);
+            sketch
+        })
+    }
+}
+
+#[pyclass(name = "UDDSketch", module = "fastdigest", frozen)]
+pub struct PyUDDSketch {
+    state: Mutex<UDDSketch>,
+}
+
+impl PyUDDSketch {
+    fn from_sketch(sketch: UDDSketch) -> Self {
+        Self {
+            state: Mutex::new(sketch),
+        }
+    }
+
+    /// Locks the sketch, raising ValueError if it is empty.
+    fn lock_nonempty(&self) -> PyResult<MutexGuard<'_, UDDSketch>> {
+        let sketch = self.state.lock();
+        if sketch.is_empty() {
+            return Err(EmptyDigestError::new_err("UDDSketch is empty."));
+        }
+        Ok(sketch)
+    }
+}
+
+#[pymethods]
+impl PyUDDSketch {
+    /// Constructs a new empty UDDSketch instance.
+    #[new]
+    #[pyo3(signature = (relative_accuracy=DEFAULT_RELATIVE_ACCURACY, max_bins=DEFAULT_MAX_BINS as i64))]
+    pub fn py_new(relative_accuracy: f64, max_bins: i64) -> PyResult<Self> {
+        if !valid_relative_accuracy(relative_accuracy) {
+            return Err(PyValueError::new_err(
+                "relative_accuracy must be between 0 and 1 (exclusive).",
+            ));
+        }
+        let max_bins = usize::try_from(max_bins)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                PyValueError::new_err("max_bins must be a positive integer.")
+            })?;
+        Ok(Self::from_sketch(UDDSketch::new(
+            relative_accuracy,
+            max_bins,
+        )))
+    }
+
+    /// Constructs a new UDDSketch from a sequence of float values.
+    #[staticmethod]
+    #[pyo3(signature = (x, w=None, relative_accuracy=DEFAULT_RELATIVE_ACCURACY, max_bins=DEFAULT_MAX_BINS as i64))]
+    pub fn from_values(
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+        relative_accuracy: f64,
+        max_bins: i64,
+    ) -> PyResult<Self> {
+        let sketch = Self::py_new(relative_accuracy, max_bins)?;
+        sketch.batch_update(x, w)?;
+        Ok(sketch)
+    }
+
+    /// Getter property: returns the initial relative_accuracy parameter.
+    #[getter(relative_accuracy)]
+    pub fn get_relative_accuracy(&self) -> f64 {
+        self.state.lock().relative_accuracy
+    }
+
+    /// Getter property: returns the relative accuracy guaranteed after
+    /// the collapses so far.
+    #[getter(current_relative_accuracy)]
+    pub fn get_current_relative_accuracy(&self) -> f64 {
+        self.state.lock().current_relative_accuracy()
+    }
+
+    /// Getter property: returns the max_bins parameter.
+    #[getter(max_bins)]
+    pub fn get_max_bins(&self) -> usize {
+        self.state.lock().max_bins
+    }
+
+    /// Getter property: returns the number of uniform collapses so far.
+    #[getter(n_collapses)]
+    pub fn get_n_collapses(&self) -> u32 {
+        self.state.lock().n_collapses
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u64 {
+        self.state.lock().count
+    }
+
+    /// Getter property: returns the number of non-empty buckets.
+    #[getter(n_bins)]
+    pub fn get_n_bins(&self) -> usize {
+        self.state.lock().n_bins()
+    }
+
+    /// Updates the sketch (in-place) with a single float value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        self.state.lock().add(x, weight);
+        Ok(())
+    }
+
+    /// Updates the sketch (in-place) with a sequence of float values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let keep = |v: &f64| !(skip_nan && v.is_nan());
+        validate_values(values.iter(py).filter(keep))?;
+        let weights = validate_weights(w, values.len())?;
+        let mut sketch = self.state.lock();
+        match weights {
+            Some(weights) => {
+                for (x, w) in values.iter(py).zip(weights) {
+                    if keep(&x) {
+                        sketch.add(x, w);
+                    }
+                }
+            }
+            None => {
+                for x in values.iter(py).filter(keep) {
+                    sketch.add(x, 1.0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges this sketch with another, returning a new UDDSketch.
+    pub fn merge(&self, other: &Self) -> PyResult<Self> {
+        let merged = self.copy();
+        merged.merge_inplace(other)?;
+        Ok(merged)
+    }
+
+    /// Merges this sketch with another, modifying the current instance.
+    pub fn merge_inplace(&self, other: &Self) -> PyResult<()> {
+        let other = other.state.lock().clone();
+        let mut sketch = self.state.lock();
+        if sketch.relative_accuracy != other.relative_accuracy {
+            return Err(PyValueError::new_err(
+                "Cannot merge UDDSketches with different relative_accuracy.",
+            ));
+        }
+        sketch.merge(&other);
+        Ok(())
+    }
+
+    /// Returns True if the sketch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().is_empty()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(q))
+    }
+
+    /// Estimates the quantiles for given cumulative probabilities `q`.
+    pub fn quantile_vec(&self, q: Vec<f64>) -> PyResult<Vec<f64>> {
+        if q.iter().any(|q_i| !(0.0..=1.0).contains(q_i)) {
+            return Err(InvalidQuantileError::new_err(
+                "All q values must be between 0 and 1.",
+            ));
+        }
+        let sketch = self.lock_nonempty()?;
+        Ok(q.iter().map(|&q_i| sketch.quantile(q_i)).collect())
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        Ok(self.lock_nonempty()?.quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.quantile(0.5))
+    }
+
+    /// Estimates the inter-quartile range.
+    pub fn iqr(&self) -> PyResult<f64> {
+        let sketch = self.lock_nonempty()?;
+        Ok(sketch.quantile(0.75) - sketch.quantile(0.25))
+    }
+
+    /// Estimates the rank (cumulative probability) of a given value `x`.
+    pub fn cdf(&self, x: f64) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.cdf(x))
+    }
+
+    /// Estimates the ranks (cumulative probabilities) of given values `x`.
+    pub fn cdf_vec(&self, x: Vec<f64>) -> PyResult<Vec<f64>> {
+        let sketch = self.lock_nonempty()?;
+        Ok(x.iter().map(|&x_i| sketch.cdf(x_i)).collect())
+    }
+
+    /// Returns the total weight.
+    pub fn mass(&self) -> f64 {
+        self.state.lock().mass()
+    }
+
+    /// Returns the sum of the data.
+    pub fn sum(&self) -> f64 {
+        self.state.lock().sum
+    }
+
+    /// Returns the mean of the data.
+    pub fn mean(&self) -> PyResult<f64> {
+        let sketch = self.lock_nonempty()?;
+        Ok(sketch.sum / sketch.mass())
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        Ok(self.lock_nonempty()?.max)
+    }
+
+    /// Returns a binary representation of the sketch.
+    pub fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.lock().to_bytes())
+    }
+
+    /// Reconstructs a UDDSketch from its binary representation.
+    #[staticmethod]
+    pub fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let sketch = UDDSketch::from_bytes(data).map_err(bytes_error)?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// Returns a dict representation of the sketch.
+    pub fn to_dict<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let sketch = self.state.lock();
+        let dict = PyDict::new(py);
+        dict.set_item("relative_accuracy", sketch.relative_accuracy)?;
+        dict.set_item("max_bins", sketch.max_bins)?;
+        dict.set_item("n_collapses", sketch.n_collapses)?;
+        dict.set_item("n_values", sketch.count)?;
+        dict.set_item("sum", sketch.sum)?;
+        dict.set_item("min", sketch.min)?;
+        dict.set_item("max", sketch.max)?;
+        dict.set_item("zero_count", sketch.zero_count)?;
+        for (name, store) in [
+            ("positive", &sketch.positive),
+            ("negative", &sketch.negative),
+        ] {
+            let bins: Vec<(i64, f64)> =
+                store.bins.iter().map(|(&k, &c)| (k, c)).collect();
+            dict.set_item(name, bins)?;
+        }
+        Ok(dict)
+    }
+
+    /// Reconstructs a UDDSketch from a dict.
+    #[staticmethod]
+    pub fn from_dict(sketch_dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let get = |key: &str| {
+            sketch_dict.get_item(key)?.ok_or_else(|| {
+                SerializationError::new_err(format!(
+                    "Key '{key}' not found in dict."
+                ))
+            })
+        };
+        let mut sketch = UDDSketch::with_collapses(
+            get("relative_accuracy")?.extract()?,
+            get("max_bins")?.extract()?,
+            get("n_collapses")?.extract()?,
+        )
+        .ok_or_else(|| {
+            PyValueError::new_err("Invalid UDDSketch parameters.")
+        })?;
+        sketch.count = get("n_values")?.extract()?;
+        sketch.sum = get("sum")?.extract()?;
+        sketch.min = get("min")?.extract()?;
+        sketch.max = get("max")?.extract()?;
+        sketch.zero_count = get("zero_count")?.extract()?;
+        for (name, store) in [
+            ("positive", &mut sketch.positive),
+            ("negative", &mut sketch.negative),
+        ] {
+            *store =
+                Store::from_parts(get(name)?.extract()?).ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "Invalid '{name}' bucket counts."
+                    ))
+                })?;
+        }
+        let sketch = sketch.validate().ok_or_else(|| {
+            PyValueError::new_err("Inconsistent UDDSketch totals.")
+        })?;
+        Ok(Self::from_sketch(sketch))
+    }
+
+    /// UDDSketch.copy() returns a copy of the instance.
+    pub fn copy(&self) -> Self {
+        Self::from_sketch(self.state.lock().clone())
+    }
+
+    /// Magic method: copy(sketch) returns a copy of the instance.
+    pub fn __copy__(&self) -> Self {
+        self.copy()
+    }
+
+    /// Magic method: deepcopy(sketch) returns a copy of the instance.
+    pub fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> Self {
+        self.copy()
+    }
+
+    /// Returns a tuple (callable, args) so that pickle can reconstruct
+    /// the object via UDDSketch.from_bytes(state).
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let from_bytes = py.get_type::<Self>().getattr("from_bytes")?;
+        let args = PyTuple::new(py, [self.to_bytes(py)])?;
+        PyTuple::new(py, [from_bytes, args.into_any()])
+    }
+
+    /// Magic method: repr/str(UDDSketch) returns a string representation.
+    pub fn __repr__(&self) -> String {
+        let sketch = self.state.lock();
+        format!(
+            "UDDSketch(relative_accuracy={}, max_bins={})",
+            sketch.relative_accuracy, sketch.max_bins
+        )
+    }
+
+    /// Magic method: enables equality checking (==).
+    pub fn __eq__(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        let other = other.state.lock().clone();
+        *self.state.lock() == other
+    }
+
+    /// Magic method: sk1 + sk2 returns sk1.merge(sk2).
+    pub fn __add__(&self, other: &Self) -> PyResult<Self> {
+        self.merge(other)
+    }
+
+    /// Magic method: sk1 += sk2 merges sk2 into sk1.
+    pub fn __iadd__(&self, other: &Self) -> PyResult<()> {
+        self.merge_inplace(other)
+    }
+}
@@ -0,0 +1,544 @@
+//! Digest persisted in an append-only write-ahead log
+//!
+//! The log starts with a snapshot of the digest (its binary representation,
+//! as in `to_bytes`), followed by one record per update with the raw
+//! (value, weight) pairs. Every record is written to the file before the
+//! update returns, so that it survives the process being killed. Records
+//! carry a checksum, and recovery replays them up to the first incomplete
+//! or corrupt one (as left by a crash during a write). The log is
+//! compacted into a new snapshot after a given number of values, by
+//! writing the new log next to the old one and renaming it over the old
+//! one, so that the file always holds a complete state.
+
+use crate::digest_map::{wrap_digest, Entry, Pairs};
+use crate::errors::{EmptyDigestError, InvalidQuantileError};
+use crate::hash::murmur3;
+use crate::tdigest::{BytesError, TDigest, TD_SIZE_DEFAULT};
+use crate::{
+    bytes_error, extract_values, malloc_error, validate_max_centroids,
+    validate_value, validate_values, validate_weight, validate_weights,
+    PyTDigest,
+};
+use ordered_float::OrderedFloat;
+use parking_lot::{Mutex, MutexGuard};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 8] = *b"FASTDWL~";
+const VERSION: u32 = 1;
+
+/// A single update, replayed through the buffer of single updates
+const RECORD_UPDATE: u8 = 0;
+/// A batch update
+const RECORD_BATCH: u8 = 1;
+/// kind and number of pairs
+const RECORD_HEADER_BYTES: usize = 9;
+const CHECKSUM_BYTES: usize = 8;
+
+const COMPACT_EVERY_DEFAULT: u64 = 100_000;
+
+/// State recovered from a log.
+struct Log {
+    entry: Entry,
+    /// Length of the complete records (and the snapshot)
+    len: usize,
+    /// Number of values logged since the snapshot
+    logged: u64,
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_BYTES] {
+    murmur3(bytes, 0).to_le_bytes()
+}
+
+/// Encodes a log holding only a snapshot of `digest`.
+fn snapshot(digest: &TDigest) -> PyResult<Vec<u8>> {
+    let digest = digest.to_bytes().map_err(malloc_error)?;
+    let mut bytes = Vec::new();
+    bytes
+        .try_reserve_exact(12 + digest.len() + CHECKSUM_BYTES)
+        .map_err(malloc_error)?;
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&digest);
+    let sum = checksum(&bytes);
+    bytes.extend_from_slice(&sum);
+    Ok(bytes)
+}
+
+fn record(kind: u8, pairs: &[(OrderedFloat<f64>, f64)]) -> PyResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bytes
+        .try_reserve_exact(
+            RECORD_HEADER_BYTES + 16 * pairs.len() + CHECKSUM_BYTES,
+        )
+        .map_err(malloc_error)?;
+    bytes.push(kind);
+    bytes.extend_from_slice(&(pairs.len() as u64).to_le_bytes());
+    for (x, w) in pairs {
+        bytes.extend_from_slice(&x.0.to_le_bytes());
+        bytes.extend_from_slice(&w.to_le_bytes());
+    }
+    let sum = checksum(&bytes);
+    bytes.extend_from_slice(&sum);
+    Ok(bytes)
+}
+
+fn f64_at(bytes: &[u8], offset: usize) -> f64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    f64::from_le_bytes(buf)
+}
+
+/// Length of the complete record at the start of `bytes`, if any.
+fn record_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < RECORD_HEADER_BYTES {
+        return None;
+    }
+    let mut n = [0u8; 8];
+    n.copy_from_slice(&bytes[1..RECORD_HEADER_BYTES]);
+    let len = usize::try_from(u64::from_le_bytes(n))
+        .ok()?
+        .checked_mul(16)?
+        .checked_add(RECORD_HEADER_BYTES)?;
+    let end = len.checked_add(CHECKSUM_BYTES)?;
+    let valid = matches!(bytes[0], RECORD_UPDATE | RECORD_BATCH)
+        && bytes.len() >= end
+        && checksum(&bytes[..len]) == bytes[len..end];
+    valid.then_some(end)
+}
+
+/// Loads the snapshot of a log and replays its complete records.
+fn recover(bytes: &[u8]) -> Result<Log, BytesError> {
+    if bytes.is_empty() {
+        return Err(BytesError::EmptyData);
+    }
+    if bytes.len() < 12 + TDigest::PREFIX_BYTES || bytes[..8] != MAGIC {
+        return Err(BytesError::WrongFormat);
+    }
+    if bytes[8..12] != VERSION.to_le_bytes() {
+        return Err(BytesError::WrongVersion);
+    }
+    let digest_end = TDigest::bytes_len(&bytes[12..])?
+        .checked_add(12)
+        .ok_or(BytesError::CorruptData)?;
+    let mut len = digest_end + CHECKSUM_BYTES;
+    if bytes.len() < len
+        || checksum(&bytes[..digest_end]) != bytes[digest_end..len]
+    {
+        return Err(BytesError::CorruptData);
+    }
+    let digest = TDigest::from_bytes(&bytes[12..digest_end])?;
+    let mut entry = Entry::from_digest(digest);
+    let mut logged = 0;
+    while let Some(end) = record_len(&bytes[len..]) {
+        let record = &bytes[len..end + len];
+        let mut pairs: Pairs = Vec::new();
+        let n = (end - RECORD_HEADER_BYTES - CHECKSUM_BYTES) / 16;
+        pairs.try_reserve_exact(n).map_err(BytesError::MemError)?;
+        for i in 0..n {
+            let offset = RECORD_HEADER_BYTES + 16 * i;
+            pairs.push((
+                OrderedFloat(f64_at(record, offset)),
+                f64_at(record, offset + 8),
+            ));
+        }
+        if record[0] == RECORD_UPDATE {
+            for (x, w) in pairs {
+                entry.add(x.0, w).map_err(BytesError::MemError)?;
+            }
+        } else {
+            entry.add_batch(&mut pairs).map_err(BytesError::MemError)?;
+        }
+        logged += n as u64;
+        len += end;
+    }
+    Ok(Log { entry, len, logged })
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).open(path)
+}
+
+/// Replaces the file at `path` by the one that `write` writes: either the
+/// old or the new file is found there after a crash.
+pub(crate) fn replace(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = File::create(&tmp)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    // the rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+struct WalState {
+    file: Option<File>,
+    entry: Entry,
+    /// Length of the log
+    len: u64,
+    /// Number of values logged since the last snapshot
+    logged: u64,
+}
+
+impl WalState {
+    fn file(&mut self) -> PyResult<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("WalTDigest is closed."))
+    }
+
+    /// Appends a record. A record that is only partly written is cut off
+    /// again, so that later records are not lost behind it.
+    fn append(
+        &mut self,
+        kind: u8,
+        pairs: &[(OrderedFloat<f64>, f64)],
+        fsync: bool,
+    ) -> PyResult<()> {
+        let bytes = record(kind, pairs)?;
+        let len = self.len;
+        let file = self.file()?;
+        if let Err(e) = file.write_all(&bytes) {
+            let _ = file.set_len(len);
+            return Err(e.into());
+        }
+        if fsync {
+            file.sync_data()?;
+        }
+        self.len += bytes.len() as u64;
+        self.logged += pairs.len() as u64;
+        Ok(())
+    }
+
+    /// Writes a new log with a snapshot of the current state.
+    fn compact(&mut self, path: &Path) -> PyResult<()> {
+        self.file()?;
+        self.entry.flush().map_err(malloc_error)?;
+        let bytes = snapshot(&self.entry.digest)?;
+        // the old log is closed first, as it cannot be replaced while it is
+        // open on some platforms
+        self.file = None;
+        let replaced = replace(path, |file| file.write_all(&bytes));
+        self.file = Some(open_append(path)?);
+        replaced?;
+        self.len = bytes.len() as u64;
+        self.logged = 0;
+        Ok(())
+    }
+
+    /// Compacts the log once `every` values have been logged.
+    fn compact_if_due(&mut self, path: &Path, every: u64) -> PyResult<()> {
+        if self.logged >= every {
+            self.compact(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(name = "WalTDigest", module = "fastdigest", frozen)]
+pub struct PyWalTDigest {
+    path: PathBuf,
+    compact_every: u64,
+    fsync: bool,
+    state: Mutex<WalState>,
+}
+
+impl PyWalTDigest {
+    /// Locks the state, with the buffered updates flushed.
+    fn lock_flushed(&self) -> PyResult<MutexGuard<'_, WalState>> {
+        let mut state = self.state.lock();
+        state.file()?;
+        state.entry.flush().map_err(malloc_error)?;
+        Ok(state)
+    }
+
+    /// Runs `f` on the digest, raising EmptyDigestError if it is empty.
+    fn with_digest<T>(&self, f: impl FnOnce(&TDigest) -> T) -> PyResult<T> {
+        let state = self.lock_flushed()?;
+        if state.entry.digest.is_empty() {
+            return Err(EmptyDigestError::new_err("WalTDigest is empty."));
+        }
+        Ok(f(&state.entry.digest))
+    }
+}
+
+#[pymethods]
+impl PyWalTDigest {
+    /// Opens a digest persisted in the log at `path`, which is created if
+    /// it does not exist, and recovers its state.
+    #[new]
+    #[pyo3(signature = (path, max_centroids=None, compact_every=COMPACT_EVERY_DEFAULT as i64, fsync=false))]
+    pub fn py_new(
+        py: Python<'_>,
+        path: PathBuf,
+        max_centroids: Option<i64>,
+        compact_every: i64,
+        fsync: bool,
+    ) -> PyResult<Self> {
+        let max_size = validate_max_centroids(
+            max_centroids.unwrap_or(TD_SIZE_DEFAULT as i64),
+        )?;
+        let compact_every = u64::try_from(compact_every)
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                PyValueError::new_err(
+                    "compact_every must be a positive integer.",
+                )
+            })?;
+        let log = py.detach(|| -> PyResult<Log> {
+            let bytes = match fs::read(&path) {
+                Ok(bytes) if !bytes.is_empty() => bytes,
+                Ok(_) => Vec::new(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            if bytes.is_empty() {
+                let entry = Entry::new(max_size).map_err(malloc_error)?;
+                let bytes = snapshot(&entry.digest)?;
+                replace(&path, |file| file.write_all(&bytes))?;
+                return Ok(Log {
+                    entry,
+                    len: bytes.len(),
+                    logged: 0,
+                });
+            }
+            let log = recover(&bytes).map_err(bytes_error)?;
+            let file_size = log.entry.digest.max_size();
+            if max_centroids.is_some() && file_size != max_size {
+                return Err(PyValueError::new_err(format!(
+                    "max_centroids does not match the file ({file_size})."
+                )));
+            }
+            if log.len < bytes.len() {
+                // an incomplete last record is dropped
+                open_append(&path)?.set_len(log.len as u64)?;
+            }
+            Ok(log)
+        })?;
+        Ok(Self {
+            state: Mutex::new(WalState {
+                file: Some(open_append(&path)?),
+                entry: log.entry,
+                len: log.len as u64,
+                logged: log.logged,
+            }),
+            path,
+            compact_every,
+            fsync,
+        })
+    }
+
+    /// Recovers the digest from the log at `path` (e.g. after a crash),
+    /// without opening it for updates.
+    #[staticmethod]
+    pub fn recover(py: Python<'_>, path: PathBuf) -> PyResult<PyTDigest> {
+        py.detach(|| {
+            let bytes = fs::read(&path)?;
+            let mut entry = recover(&bytes).map_err(bytes_error)?.entry;
+            entry.flush().map_err(malloc_error)?;
+            Ok(wrap_digest(entry.digest))
+        })
+    }
+
+    /// Getter property: returns the path of the log.
+    #[getter(path)]
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Getter property: returns the max_centroids parameter.
+    #[getter(max_centroids)]
+    pub fn get_max_centroids(&self) -> usize {
+        self.state.lock().entry.digest.max_size()
+    }
+
+    /// Getter property: returns the number of values after which the log
+    /// is compacted.
+    #[getter(compact_every)]
+    pub fn get_compact_every(&self) -> u64 {
+        self.compact_every
+    }
+
+    /// Getter property: returns whether every update is synced to disk.
+    #[getter(fsync)]
+    pub fn get_fsync(&self) -> bool {
+        self.fsync
+    }
+
+    /// Getter property: returns whether the log has been closed.
+    #[getter(closed)]
+    pub fn get_closed(&self) -> bool {
+        self.state.lock().file.is_none()
+    }
+
+    /// Getter property: returns the number of values logged since the last
+    /// snapshot.
+    #[getter(n_logged)]
+    pub fn get_n_logged(&self) -> u64 {
+        self.state.lock().logged
+    }
+
+    /// Getter property: returns the total number of data points ingested.
+    #[getter(n_values)]
+    pub fn get_n_values(&self) -> u128 {
+        self.state.lock().entry.count()
+    }
+
+    /// Updates the digest (in-place) with a single value.
+    #[pyo3(signature = (x, w=None))]
+    pub fn update(&self, x: f64, w: Option<f64>) -> PyResult<()> {
+        validate_value(x)?;
+        let weight = validate_weight(w.unwrap_or(1.0))?;
+        let mut state = self.state.lock();
+        // the update is only applied once it is logged
+        state.append(
+            RECORD_UPDATE,
+            &[(OrderedFloat(x), weight)],
+            self.fsync,
+        )?;
+        state.entry.add(x, weight).map_err(malloc_error)?;
+        state.compact_if_due(&self.path, self.compact_every)
+    }
+
+    /// Updates the digest (in-place) with a sequence of values.
+    #[pyo3(signature = (x, w=None))]
+    pub fn batch_update(
+        &self,
+        x: &Bound<'_, PyAny>,
+        w: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let py = x.py();
+        let (values, skip_nan) = extract_values(x)?;
+        let weights = validate_weights(w, values.len())?;
+        let mut pairs = Vec::new();
+        for (j, x) in values.iter(py).enumerate() {
+            // missing values are left out together with their weights
+            if skip_nan && x.is_nan() {
+                continue;
+            }
+            let weight = weights.as_ref().map_or(1.0, |weights| weights[j]);
+            pairs.push((OrderedFloat(x), weight));
+        }
+        validate_values(pairs.iter().map(|(x, _)| x.0))?;
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        py.detach(|| {
+            let mut state = self.state.lock();
+            state.append(RECORD_BATCH, &pairs, self.fsync)?;
+            state.entry.add_batch(&mut pairs).map_err(malloc_error)?;
+            state.compact_if_due(&self.path, self.compact_every)
+        })
+    }
+
+    /// Compacts the log into a snapshot of the current state.
+    pub fn compact(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| self.state.lock().compact(&self.path))
+    }
+
+    /// Syncs the log to disk (with fsync=False).
+    pub fn flush(&self, py: Python<'_>) -> PyResult<()> {
+        py.detach(|| Ok(self.state.lock().file()?.sync_data()?))
+    }
+
+    /// Closes the log. Further calls have no effect.
+    pub fn close(&self) {
+        self.state.lock().file = None;
+    }
+
+    pub fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    pub fn __exit__(
+        &self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) {
+        self.close()
+    }
+
+    /// Estimates the quantile for a given cumulative probability `q`.
+    pub fn quantile(&self, q: f64) -> PyResult<f64> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(InvalidQuantileError::new_err(
+                "q must be between 0 and 1.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(q))
+    }
+
+    /// Estimates the percentile for a given cumulative probability `p` (%).
+    pub fn percentile(&self, p: f64) -> PyResult<f64> {
+        if !(0.0..=100.0).contains(&p) {
+            return Err(InvalidQuantileError::new_err(
+                "p must be between 0 and 100.",
+            ));
+        }
+        self.with_digest(|digest| digest.estimate_quantile(0.01 * p))
+    }
+
+    /// Estimates the median.
+    pub fn median(&self) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_quantile(0.5))
+    }
+
+    /// Estimates the CDF at the value `x`.
+    pub fn cdf(&self, x: f64) -> PyResult<f64> {
+        self.with_digest(|digest| digest.estimate_rank(x))
+    }
+
+    /// Returns the weighted mean of the values.
+    pub fn mean(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::mean)
+    }
+
+    /// Returns the lowest ingested value.
+    pub fn min(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::min)
+    }
+
+    /// Returns the highest ingested value.
+    pub fn max(&self) -> PyResult<f64> {
+        self.with_digest(TDigest::max)
+    }
+
+    /// Returns the total weight of the values.
+    pub fn mass(&self) -> PyResult<f64> {
+        Ok(self.lock_flushed()?.entry.digest.mass())
+    }
+
+    /// Returns True if the digest holds no values.
+    pub fn is_empty(&self) -> PyResult<bool> {
+        Ok(self.lock_flushed()?.entry.digest.is_empty())
+    }
+
+    /// Returns a copy of the digest as a TDigest.
+    pub fn snapshot(&self) -> PyResult<PyTDigest> {
+        Ok(wrap_digest(self.lock_flushed()?.entry.digest.clone()))
+    }
+
+    /// Magic method: repr(digest) shows the path.
+    pub fn __repr__(&self) -> String {
+        format!("WalTDigest('{}')", self.path.display())
+    }
+}
@@ -0,0 +1,148 @@
+import copy
+import math
+import pickle
+import pytest
+import random
+from fastdigest import BinnedTDigest, TDigest
+
+EDGES = [0.0, 10.0, 100.0, 1000.0]
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    sizes = [rng.uniform(0.0, 1000.0) for _ in range(n)]
+    latencies = [rng.expovariate(1.0) * (1.0 + x / 100.0) for x in sizes]
+    return sizes, latencies
+
+
+def binned(sizes: list, latencies: list, lo: float, hi: float) -> TDigest:
+    return TDigest.from_values(
+        [y for x, y in zip(sizes, latencies) if lo <= x < hi]
+    )
+
+
+def test_init() -> None:
+    b = BinnedTDigest(EDGES)
+    assert len(b) == 3
+    assert b.edges == EDGES
+    assert b.max_centroids == 1000
+    assert b.n_values == 0
+    assert b[0].is_empty()
+    assert repr(b) == "BinnedTDigest(n_bins=3, max_centroids=1000)"
+    for bad in ([], [1.0], [1.0, 1.0], [2.0, 1.0], [0.0, float("inf")]):
+        with pytest.raises(ValueError):
+            BinnedTDigest(bad)
+    with pytest.raises(ValueError):
+        BinnedTDigest(EDGES, max_centroids=-1)
+    with pytest.raises(IndexError):
+        b[3]
+
+
+def test_update() -> None:
+    b = BinnedTDigest(EDGES)
+    b.update(0.0, 1.0)
+    b.update(10.0, 2.0, w=2.0)
+    b.update(1000.0, 3.0)
+    b.update(-1.0, 4.0)
+    b.update(1001.0, 5.0)
+    b.update(float("nan"), 6.0)
+    assert b.n_values == 3
+    assert [b[i].mass() for i in range(3)] == [1.0, 2.0, 1.0]
+    assert b[-1].max() == 3.0
+    with pytest.raises(ValueError):
+        b.update(1.0, float("nan"))
+    with pytest.raises(ValueError):
+        b.update(1.0, 1.0, w=0.0)
+
+
+def test_batch_update() -> None:
+    sizes, latencies = sample_data(20_000)
+    b = BinnedTDigest(EDGES)
+    b.batch_update(sizes + [-5.0, 2000.0], latencies + [1.0, 1.0])
+    assert b.n_values == 20_000
+    for i in range(3):
+        assert b[i] == binned(sizes, latencies, EDGES[i], EDGES[i + 1])
+    threaded = BinnedTDigest(EDGES)
+    threaded.batch_update(sizes, latencies, n_threads=3)
+    assert threaded == b
+    mass = b[0].mass()
+    b.batch_update([1.0, 5.0], [1.0, 2.0], w=[1.0, 3.0])
+    assert b[0].mass() == mass + 4.0
+    with pytest.raises(ValueError):
+        b.batch_update([1.0, 2.0], [1.0])
+    with pytest.raises(ValueError):
+        b.batch_update([1.0, 2.0], [1.0, float("inf")])
+    with pytest.raises(ValueError):
+        b.batch_update([1.0], [1.0], n_threads=0)
+
+
+def test_queries() -> None:
+    np = pytest.importorskip("numpy")
+    sizes, latencies = sample_data(10_000)
+    b = BinnedTDigest(EDGES + [2000.0])
+    b.batch_update(np.array(sizes), np.array(latencies))
+    expected = [
+        binned(sizes, latencies, EDGES[i], EDGES[i + 1]) for i in range(3)
+    ]
+    medians = b.median()
+    assert isinstance(medians, np.ndarray) and medians.shape == (4,)
+    assert list(medians[:3]) == [d.median() for d in expected]
+    assert math.isnan(medians[3])
+    assert list(b.quantile(0.95)[:3]) == [d.quantile(0.95) for d in expected]
+    assert list(b.percentile(5)[:3]) == [d.quantile(0.05) for d in expected]
+    assert list(b.cdf(1.0)[:3]) == [d.cdf(1.0) for d in expected]
+    assert list(b.mass()) == [d.mass() for d in expected] + [0.0]
+    assert list(b.centers) == [5.0, 55.0, 550.0, 1500.0]
+    curve = b.quantile_curve([0.5, 0.95])
+    assert curve.shape == (4, 2)
+    assert list(curve[1]) == [expected[1].median(), expected[1].quantile(0.95)]
+    assert all(math.isnan(x) for x in curve[3])
+    with pytest.raises(ValueError):
+        b.quantile(1.5)
+    with pytest.raises(ValueError):
+        b.quantile_curve([0.5, -0.1])
+
+
+def test_merge() -> None:
+    sizes, latencies = sample_data(4000)
+    a, b = BinnedTDigest(EDGES), BinnedTDigest(EDGES)
+    a.batch_update(sizes[:2000], latencies[:2000])
+    b.batch_update(sizes[2000:], latencies[2000:])
+    merged = a + b
+    assert merged.n_values == 4000
+    for i in range(3):
+        assert merged[i].n_values == a[i].n_values + b[i].n_values
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 8000
+    with pytest.raises(ValueError):
+        a.merge(BinnedTDigest([0.0, 10.0, 100.0, 500.0]))
+
+
+def test_serialization() -> None:
+    sizes, latencies = sample_data(3000)
+    b = BinnedTDigest(EDGES, max_centroids=100)
+    b.batch_update(sizes, latencies)
+    b.update(999.0, 1e6)
+    for restore in (
+        lambda b: BinnedTDigest.from_bytes(b.to_bytes()),
+        lambda b: BinnedTDigest.from_dict(b.to_dict()),
+        lambda b: pickle.loads(pickle.dumps(b)),
+        copy.deepcopy,
+    ):
+        restored = restore(b)
+        assert restored == b
+        assert restored.edges == EDGES
+        assert restored[2].max() == 1e6
+    d = b.to_dict()
+    assert d["edges"] == EDGES
+    assert d["max_centroids"] == 100
+    assert d["digests"][1] == b[1].to_dict()
+    d["edges"] = [0.0, 1.0]
+    with pytest.raises(ValueError):
+        BinnedTDigest.from_dict(d)
+    data = b.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            BinnedTDigest.from_bytes(bad)
@@ -0,0 +1,84 @@
+import array
+import copy
+import pickle
+import pytest
+from fastdigest import BloomFilter
+from fastdigest.errors import SerializationError
+
+
+def test_init() -> None:
+    b = BloomFilter(1000)
+    assert b.capacity == 1000
+    assert b.fp_rate == 0.01
+    assert b.n_bits == 9586
+    assert b.n_hashes == 7
+    assert b.is_empty()
+    assert b.current_fp_rate() == 0.0
+    assert "a" not in b
+    assert repr(b) == "BloomFilter(capacity=1000, fp_rate=0.01)"
+    for kwargs in [
+        {"capacity": 0},
+        {"capacity": -5},
+        {"capacity": 10, "fp_rate": 0.0},
+        {"capacity": 10, "fp_rate": 1.0},
+    ]:
+        with pytest.raises(ValueError):
+            BloomFilter(**kwargs)
+
+
+def test_membership() -> None:
+    for capacity, fp_rate in [(1_000, 0.01), (20_000, 0.001), (500, 0.2)]:
+        b = BloomFilter(capacity, fp_rate)
+        b.batch_add(f"item-{i}" for i in range(capacity))
+        assert not b.is_empty()
+        assert all(f"item-{i}" in b for i in range(capacity))
+        trials = 50_000
+        fp = sum(f"other-{i}" in b for i in range(trials)) / trials
+        assert fp < 1.5 * fp_rate
+        assert b.current_fp_rate() == pytest.approx(fp_rate, rel=0.2)
+    b = BloomFilter(100)
+    b.add(1)
+    b.add(("a", None))
+    assert 1.0 in b and True in b
+    assert ("a", None) in b
+    b.batch_add(array.array("q", [2**62]))
+    assert 2**62 in b
+    with pytest.raises(TypeError):
+        b.add([1])
+    with pytest.raises(TypeError):
+        [1] in b
+
+
+def test_union() -> None:
+    a = BloomFilter(1_000)
+    a.batch_add(range(0, 500))
+    b = BloomFilter(1_000)
+    b.batch_add(range(500, 1_000))
+    both = BloomFilter(1_000)
+    both.batch_add(range(1_000))
+    assert a.union(b) == both
+    assert (a | b) == both
+    a |= b
+    assert a == both
+    assert all(i in a for i in range(1_000))
+    with pytest.raises(ValueError):
+        a.union(BloomFilter(2_000))
+
+
+def test_serialization() -> None:
+    b = BloomFilter(300, fp_rate=0.05)
+    b.batch_add(["x", "y", b"z"])
+    assert BloomFilter.from_bytes(b.to_bytes()) == b
+    assert BloomFilter.from_dict(b.to_dict()) == b
+    assert pickle.loads(pickle.dumps(b)) == b
+    assert copy.deepcopy(b) == b
+    # the bit array takes about 1.2 bytes per item at a 5% rate
+    assert len(b.to_bytes()) < 400
+    with pytest.raises(ValueError):
+        BloomFilter.from_bytes(b.to_bytes()[:-1])
+    bad = b.to_dict()
+    bad["words"] = bad["words"][:-1]
+    with pytest.raises(ValueError):
+        BloomFilter.from_dict(bad)
+    with pytest.raises(SerializationError):
+        BloomFilter.from_dict({"capacity": 300})
@@ -0,0 +1,67 @@
+import pytest
+import threading
+from fastdigest import ConcurrentTDigest, TDigest
+from utils import (
+    SAMPLE_QUANTILES,
+    calculate_sample_quantiles,
+    check_sample_quantiles,
+    compare_values,
+)
+
+
+def test_init() -> None:
+    d = ConcurrentTDigest(max_centroids=50, n_shards=4)
+    assert d.max_centroids == 50
+    assert d.n_shards == 4
+    assert d.n_values == 0
+    assert repr(d) == "ConcurrentTDigest(max_centroids=50, n_shards=4)"
+    assert ConcurrentTDigest().n_shards >= 1
+    assert d.snapshot() == TDigest(max_centroids=50)
+    with pytest.raises(ValueError):
+        ConcurrentTDigest(n_shards=0)
+    with pytest.raises(ValueError):
+        ConcurrentTDigest(max_centroids=-1)
+
+
+def test_updates() -> None:
+    d = ConcurrentTDigest(n_shards=1)
+    d.batch_update(range(1, 51))
+    for x in range(51, 101):
+        d.update(x)
+    assert d.n_values == 100
+    snapshot = d.snapshot()
+    assert isinstance(snapshot, TDigest)
+    assert snapshot.n_values == 100
+    check_sample_quantiles(snapshot, calculate_sample_quantiles(range(1, 101)))
+    d.update(1000, w=2.0)
+    assert snapshot.n_values == 100
+    assert d.snapshot().mass() == 102.0
+    with pytest.raises(ValueError):
+        d.update(float("nan"))
+    with pytest.raises(ValueError):
+        d.batch_update([1.0, 2.0], w=[1.0])
+
+
+def test_threaded_updates() -> None:
+    d = ConcurrentTDigest(n_shards=3)
+
+    def work(offset: int) -> None:
+        for x in range(offset, offset + 250):
+            d.update(x)
+        d.batch_update(range(offset + 250, offset + 500))
+        d.snapshot()
+
+    threads = [
+        threading.Thread(target=work, args=(i * 500 + 1,)) for i in range(8)
+    ]
+    for t in threads:
+        t.start()
+    for t in threads:
+        t.join()
+    snapshot = d.snapshot()
+    assert snapshot.n_values == d.n_values == 4000
+    assert snapshot.min() == 1
+    assert snapshot.max() == 4000
+    estimated = [snapshot.quantile(q) for q in SAMPLE_QUANTILES]
+    expected = calculate_sample_quantiles(range(1, 4001))
+    compare_values("quantile", SAMPLE_QUANTILES, expected, estimated, 0.01)
@@ -0,0 +1,96 @@
+import copy
+import pickle
+import pytest
+import random
+from collections import Counter
+from fastdigest import CountMinSketch
+from fastdigest.errors import SerializationError
+
+
+def test_init() -> None:
+    s = CountMinSketch()
+    assert s.width == 2719
+    assert s.depth == 5
+    assert s.epsilon <= 0.001
+    assert s.delta <= 0.01
+    assert s.total == 0
+    assert s.is_empty()
+    assert s.estimate("a") == 0
+    assert repr(s) == "CountMinSketch(width=2719, depth=5)"
+    s = CountMinSketch(epsilon=0.1, delta=0.5)
+    assert (s.width, s.depth) == (28, 1)
+    for kwargs in [
+        {"epsilon": 0.0},
+        {"epsilon": 1.0},
+        {"delta": 0.0},
+        {"delta": 1.5},
+    ]:
+        with pytest.raises(ValueError):
+            CountMinSketch(**kwargs)
+
+
+def test_estimate() -> None:
+    rng = random.Random(0)
+    items = [rng.randrange(20_000) for _ in range(100_000)]
+    counts = Counter(items)
+    s = CountMinSketch(epsilon=0.01, delta=0.01)
+    s.batch_update(items[:50_000])
+    for item in items[50_000:]:
+        s.update(item)
+    assert s.total == len(items)
+    bound = s.epsilon * s.total
+    errors = [s.estimate(k) - v for k, v in counts.items()]
+    assert min(errors) >= 0
+    # the bound may be exceeded with probability delta per item
+    assert sum(e > bound for e in errors) <= 0.01 * len(errors)
+    assert s.estimate_vec(list(counts)[:10]) == [
+        s.estimate(k) for k in list(counts)[:10]
+    ]
+
+
+def test_update_count() -> None:
+    s = CountMinSketch()
+    s.update("apple", 3)
+    s.update("apple")
+    s.update(("pear", 1), count=10)
+    assert s.estimate("apple") == 4
+    assert s.estimate(("pear", 1)) == 10
+    assert s.estimate(1.0) == s.estimate(1) == 0
+    assert s.total == 14
+    for bad_count in [0, -1]:
+        with pytest.raises(ValueError):
+            s.update("apple", bad_count)
+    with pytest.raises(TypeError):
+        s.update(["apple"])
+
+
+def test_merge() -> None:
+    a = CountMinSketch(epsilon=0.01)
+    a.batch_update(["x"] * 5 + ["y"] * 2)
+    b = CountMinSketch(epsilon=0.01)
+    b.batch_update(["x"] * 3 + ["z"])
+    merged = a + b
+    assert merged.estimate("x") == 8
+    assert merged.estimate("z") == 1
+    assert merged.total == 11
+    a += b
+    assert a == merged
+    with pytest.raises(ValueError):
+        a.merge(CountMinSketch(epsilon=0.02))
+
+
+def test_serialization() -> None:
+    s = CountMinSketch(epsilon=0.05, delta=0.1)
+    s.batch_update(["a", "b", "a", 7])
+    assert CountMinSketch.from_bytes(s.to_bytes()) == s
+    assert CountMinSketch.from_dict(s.to_dict()) == s
+    assert pickle.loads(pickle.dumps(s)) == s
+    assert copy.deepcopy(s) == s
+    with pytest.raises(ValueError):
+        CountMinSketch.from_bytes(s.to_bytes()[:-1])
+    bad = s.to_dict()
+    bad["total"] = 5
+    with pytest.raises(ValueError):
+        CountMinSketch.from_dict(bad)
+    with pytest.raises(SerializationError):
+        CountMinSketch.from_dict({"width": 55, "depth": 3})
@@ -0,0 +1,25 @@
+import pytest
+import fastdigest.dask as fd_dask
+from fastdigest import TDigest
+
+
+def test_dask_build_partition() -> None:
+    digest = fd_dask.build_partition([1.0, float("nan"), 2.0], max_centroids=5)
+    assert digest == TDigest.from_values([1.0, 2.0], max_centroids=5)
+
+
+def test_dask_tdigest() -> None:
+    pd = pytest.importorskip("pandas")
+    dd = pytest.importorskip("dask.dataframe")
+    values = pd.Series([float(i) for i in range(1, 101)] + [None])
+    series = dd.from_pandas(values, npartitions=20)
+    digest = fd_dask.tdigest(series, split_every=3)
+    assert digest == TDigest.from_values(range(1, 101))
+    quantiles = fd_dask.tdigest(series, quantiles=[0.5, 1.0])
+    assert quantiles == [50.5, 100.0]
+    lazy = fd_dask.tdigest(series, max_centroids=10, compute=False)
+    assert lazy.compute().max_centroids == 10
+    with pytest.raises(ValueError):
+        fd_dask.tdigest(series, split_every=1)
+    with pytest.raises(ValueError):
+        fd_dask.tdigest(series, quantiles=[2.0])
@@ -0,0 +1,112 @@
+import copy
+import math
+import pickle
+import pytest
+import random
+from fastdigest import DataFrameDigest, TDigest
+
+
+def sample_frame(pd, n: int, seed: int = 0):
+    rng = random.Random(seed)
+    return pd.DataFrame(
+        {
+            "latency": [rng.expovariate(1.0) for _ in range(n)],
+            "size": [rng.gauss(100.0, 10.0) for _ in range(n)],
+            "endpoint": [rng.choice(["/api", "/login"]) for _ in range(n)],
+        }
+    )
+
+
+def test_init() -> None:
+    d = DataFrameDigest()
+    assert d.max_centroids == 1000
+    assert d.columns == []
+    assert len(d) == 0
+    assert repr(d) == "DataFrameDigest(n_columns=0, max_centroids=1000)"
+    assert DataFrameDigest.from_bytes(d.to_bytes()) == d
+    with pytest.raises(ValueError):
+        DataFrameDigest(max_centroids=-1)
+    with pytest.raises(KeyError):
+        d["latency"]
+
+
+def test_update_dataframe() -> None:
+    pd = pytest.importorskip("pandas")
+    df = sample_frame(pd, 2000)
+    df.loc[3, "latency"] = None
+    d = DataFrameDigest.from_dataframe(df)
+    assert d.columns == ["latency", "size"]
+    assert "endpoint" not in d
+    assert d["latency"] == TDigest.from_values(df["latency"].dropna())
+    assert d["size"].n_values == 2000
+    d.update(df.iloc[:100])
+    assert d["size"].n_values == 2100
+    with pytest.raises(ValueError):
+        d.update(pd.DataFrame({"size": [math.inf]}))
+    assert d["size"].n_values == 2100
+
+
+def test_update_array() -> None:
+    pytest.importorskip("numpy")
+    rows = [[float(i), float(-i)] for i in range(100)]
+    d = DataFrameDigest.from_dataframe(rows, max_centroids=50)
+    assert d.columns == [0, 1]
+    assert d[0] == TDigest.from_values(range(100), max_centroids=50)
+    assert d[1].max() == 0.0
+    with pytest.raises(ValueError):
+        d.update([1.0, 2.0])
+
+
+def test_describe() -> None:
+    pd = pytest.importorskip("pandas")
+    df = sample_frame(pd, 5000)
+    d = DataFrameDigest.from_dataframe(df)
+    table = d.describe()
+    assert list(table.index) == [
+        "count", "mean", "std", "min", "25%", "50%", "75%", "max"
+    ]
+    assert list(table.columns) == ["latency", "size"]
+    assert table.loc["count", "latency"] == 5000
+    assert table.loc["50%", "size"] == d["size"].median()
+    assert table.loc["max", "latency"] == df["latency"].max()
+    expected = df.describe()
+    assert table.loc["mean", "size"] == pytest.approx(
+        expected.loc["mean", "size"]
+    )
+    table = d.describe(q=[0.025, 0.999])
+    assert list(table.index)[4:6] == ["2.5%", "99.9%"]
+    with pytest.raises(ValueError):
+        d.describe(q=[1.5])
+
+
+def test_merge() -> None:
+    pd = pytest.importorskip("pandas")
+    a = DataFrameDigest.from_dataframe(sample_frame(pd, 1000))
+    b = DataFrameDigest.from_dataframe(sample_frame(pd, 1000, seed=1))
+    b.update(pd.DataFrame({"extra": [1.0, 2.0]}))
+    merged = a + b
+    assert merged.columns == ["latency", "size", "extra"]
+    assert merged["latency"].n_values == 2000
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a["extra"].n_values == 4
+
+
+def test_serialization() -> None:
+    pd = pytest.importorskip("pandas")
+    d = DataFrameDigest.from_dataframe(sample_frame(pd, 3000), 100)
+    for restore in (
+        lambda d: DataFrameDigest.from_bytes(d.to_bytes()),
+        lambda d: DataFrameDigest.from_dict(d.to_dict()),
+        lambda d: pickle.loads(pickle.dumps(d)),
+        copy.deepcopy,
+    ):
+        restored = restore(d)
+        assert restored == d
+        assert restored.columns == d.columns
+    assert d.to_dict()["digests"]["size"] == d["size"].to_dict()
+    data = d.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            DataFrameDigest.from_bytes(bad)
@@ -0,0 +1,126 @@
+import copy
+import math
+import pickle
+import pytest
+import struct
+from fastdigest import DDSketch
+from fastdigest.errors import SerializationError
+
+
+def check_relative(sketch: DDSketch, data: list, alpha: float) -> None:
+    data = sorted(data)
+    for q in [0.0, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 1.0]:
+        expected = data[int(q * (len(data) - 1))]
+        estimated = sketch.quantile(q)
+        assert abs(estimated - expected) <= alpha * abs(expected) + 1e-12
+
+
+def test_init() -> None:
+    s = DDSketch(relative_accuracy=0.02, max_bins=100)
+    assert s.relative_accuracy == 0.02
+    assert s.max_bins == 100
+    assert s.n_values == 0
+    assert s.is_empty()
+    assert repr(s) == "DDSketch(relative_accuracy=0.02, max_bins=100)"
+    assert repr(DDSketch()) == "DDSketch(relative_accuracy=0.01, max_bins=2048)"
+    with pytest.raises(ValueError):
+        s.quantile(0.5)
+    for bad_accuracy in [0.0, 1.0, -0.1]:
+        with pytest.raises(ValueError):
+            DDSketch(relative_accuracy=bad_accuracy)
+    with pytest.raises(ValueError):
+        DDSketch(max_bins=0)
+
+
+def test_relative_accuracy() -> None:
+    data = [1.0001**i for i in range(-20000, 60000, 7)]
+    s = DDSketch(relative_accuracy=0.01)
+    s.batch_update(data)
+    assert s.n_values == len(data)
+    assert s.min() == min(data)
+    assert s.max() == max(data)
+    assert s.sum() == pytest.approx(sum(data))
+    check_relative(s, data, 0.01)
+    assert s.percentile(50) == s.median() == s.quantile(0.5)
+    assert s.quantile_vec([0.1, 0.9]) == [s.quantile(0.1), s.quantile(0.9)]
+    with pytest.raises(ValueError):
+        s.quantile(1.5)
+
+
+def test_signed_values() -> None:
+    data = [x / 10 for x in range(-5000, 5001)]
+    s = DDSketch(relative_accuracy=0.05)
+    for x in data:
+        s.update(x)
+    check_relative(s, data, 0.05)
+    assert s.mean() == pytest.approx(0.0, abs=1e-9)
+    s.update(1.0, w=10.0)
+    assert s.n_values == len(data) + 1
+    with pytest.raises(ValueError):
+        s.update(float("inf"))
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, 2.0], w=[1.0])
+
+
+def test_max_bins() -> None:
+    data = [2.0**i for i in range(-100, 101)]
+    s = DDSketch(relative_accuracy=0.01, max_bins=500)
+    s.batch_update(data)
+    assert s.n_bins <= 500
+    assert s.quantile(1.0) == pytest.approx(2.0**100, rel=0.01)
+    assert s.quantile(0.99) == pytest.approx(2.0**98, rel=0.01)
+    assert s.quantile(0.5) < 2.0**86
+
+
+def test_merge() -> None:
+    a = DDSketch()
+    a.batch_update(range(1, 501))
+    b = DDSketch()
+    b.batch_update(range(501, 1001))
+    merged = a + b
+    assert merged.n_values == 1000
+    check_relative(merged, list(range(1, 1001)), 0.01)
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 2000
+    with pytest.raises(ValueError):
+        merged.merge(DDSketch(relative_accuracy=0.05))
+
+
+def test_serialization() -> None:
+    s = DDSketch(relative_accuracy=0.02, max_bins=300)
+    s.batch_update([-3.0, 0.0, 1.5, 2.5, 1e6])
+    assert DDSketch.from_bytes(s.to_bytes()) == s
+    assert DDSketch.from_dict(s.to_dict()) == s
+    assert pickle.loads(pickle.dumps(s)) == s
+    assert copy.deepcopy(s) == s
+    c = s.copy()
+    c.update(4.0)
+    assert c != s
+    with pytest.raises(ValueError):
+        DDSketch.from_bytes(b"")
+    with pytest.raises(ValueError):
+        DDSketch.from_bytes(s.to_bytes()[:-1])
+    # totals that do not match the buckets
+    d = DDSketch().to_dict()
+    d["n_values"] = 1
+    with pytest.raises(SerializationError):
+        DDSketch.from_dict(d)
+    invalid = [
+        {"min": 5.0, "max": 3.0},
+        {"min": math.nan},
+        {"max": math.nan},
+        {"zero_count": -1.0},
+    ]
+    for changes in invalid:
+        d = s.to_dict()
+        d.update(changes)
+        with pytest.raises(SerializationError):
+            DDSketch.from_dict(d)
+    raw = s.to_bytes()
+    min_bytes = struct.pack("<d", s.min())
+    assert raw.count(min_bytes) == 1
+    corrupt = raw.replace(min_bytes, struct.pack("<d", s.max() + 1.0))
+    with pytest.raises(SerializationError):
+        DDSketch.from_bytes(corrupt)
@@ -0,0 +1,155 @@
+import copy
+import math
+import pickle
+import pytest
+import random
+from fastdigest import DecayingTDigest
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    timestamps = [100.0 * i / n for i in range(n)]
+    # the distribution shifts from around 0 to around 10
+    values = [rng.gauss(t / 10.0, 1.0) for t in timestamps]
+    return timestamps, values
+
+
+def test_init() -> None:
+    d = DecayingTDigest(60.0)
+    assert d.half_life == 60.0
+    assert d.max_centroids == 1000
+    assert d.now is None
+    assert d.n_values == 0
+    assert d.is_empty()
+    assert d.mass() == 0.0
+    assert d.snapshot().is_empty()
+    assert repr(d) == "DecayingTDigest(half_life=60.0, max_centroids=1000)"
+    for half_life in (0.0, -1.0, float("inf"), float("nan")):
+        with pytest.raises(ValueError):
+            DecayingTDigest(half_life)
+    with pytest.raises(ValueError):
+        DecayingTDigest(60.0, max_centroids=-1)
+    with pytest.raises(ValueError):
+        d.median()
+
+
+def test_update() -> None:
+    d = DecayingTDigest(1.0)
+    d.update(0.0, 1.0)
+    d.update(1.0, 2.0, w=2.0)
+    assert d.now == 1.0
+    assert d.n_values == 2
+    assert d.mass() == 2.5
+    assert d.mass(now=2.0) == 1.25
+    assert d.snapshot().mass() == 2.5
+    assert d.mean() == pytest.approx((0.5 * 1.0 + 2.0 * 2.0) / 2.5)
+    # older values may arrive late, with less weight
+    d.update(-2.0, 3.0)
+    assert d.now == 1.0
+    assert d.mass() == 2.625
+    with pytest.raises(ValueError):
+        d.update(float("nan"), 1.0)
+    with pytest.raises(ValueError):
+        d.update(2.0, float("inf"))
+    with pytest.raises(ValueError):
+        d.update(2.0, 1.0, w=-1.0)
+
+
+def test_rescaling() -> None:
+    d = DecayingTDigest(1.0)
+    d.update(0.0, 1.0)
+    d.update(1000.0, 2.0)
+    assert d.mass() == pytest.approx(1.0)
+    assert d.median() == 2.0
+    assert d.snapshot(now=0.0).mass() == pytest.approx(2.0**1000)
+    # values without any weight left are dropped
+    d.update(2000.0, 3.0)
+    assert d.mass() == 1.0
+    assert d.snapshot().min() == 2.0
+    d.update(0.0, 4.0)
+    assert d.mass() == 1.0
+
+
+def test_batch_update() -> None:
+    timestamps, values = sample_data(10_000)
+    d = DecayingTDigest(10.0)
+    d.batch_update(timestamps, values)
+    assert d.now == timestamps[-1]
+    assert d.n_values == 10_000
+    # recent values dominate
+    assert 8.0 < d.median() < 10.0
+    assert d.percentile(50) == d.median()
+    assert d.quantile(0.9) > d.median()
+    assert 0.4 < d.cdf(d.median()) < 0.6
+    expected = sum(2.0 ** ((t - timestamps[-1]) / 10.0) for t in timestamps)
+    assert d.mass() == pytest.approx(expected)
+    single = DecayingTDigest(10.0)
+    for t, x in zip(timestamps, values):
+        single.update(t, x)
+    assert single.mass() == pytest.approx(expected)
+    assert abs(single.median() - d.median()) < 0.05
+    shuffled = list(zip(timestamps, values))
+    random.Random(1).shuffle(shuffled)
+    out_of_order = DecayingTDigest(10.0)
+    out_of_order.batch_update(*zip(*shuffled))
+    assert out_of_order.mass() == pytest.approx(expected)
+    with pytest.raises(ValueError):
+        d.batch_update([1.0], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        d.batch_update([1.0, float("nan")], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        d.batch_update([1.0, 2.0], [1.0, float("inf")])
+
+
+def test_merge() -> None:
+    timestamps, values = sample_data(4000)
+    a, b = DecayingTDigest(10.0), DecayingTDigest(10.0)
+    a.batch_update(timestamps[:2000], values[:2000])
+    b.batch_update(timestamps[2000:], values[2000:])
+    whole = DecayingTDigest(10.0)
+    whole.batch_update(timestamps, values)
+    merged = a + b
+    assert merged.now == timestamps[-1]
+    assert merged.n_values == 4000
+    assert merged.mass() == pytest.approx(whole.mass())
+    assert abs(merged.median() - whole.median()) < 0.05
+    assert (b + a).mass() == pytest.approx(whole.mass())
+    a += b
+    assert a == merged
+    mass = a.mass()
+    a.merge_inplace(a)
+    assert a.mass() == pytest.approx(2 * mass)
+    assert (DecayingTDigest(10.0) + b) == b.merge(DecayingTDigest(10.0))
+    with pytest.raises(ValueError):
+        a.merge(DecayingTDigest(5.0))
+
+
+def test_serialization() -> None:
+    timestamps, values = sample_data(3000)
+    d = DecayingTDigest(10.0, max_centroids=100)
+    d.batch_update(timestamps, values)
+    d.update(100.0, 1e6)
+    for restore in (
+        lambda d: DecayingTDigest.from_bytes(d.to_bytes()),
+        lambda d: DecayingTDigest.from_dict(d.to_dict()),
+        lambda d: pickle.loads(pickle.dumps(d)),
+        copy.deepcopy,
+    ):
+        restored = restore(d)
+        assert restored == d
+        assert restored.mass() == d.mass()
+        assert restored.snapshot().max() == 1e6
+    empty = DecayingTDigest(1.0)
+    assert DecayingTDigest.from_bytes(empty.to_bytes()) == empty
+    dct = d.to_dict()
+    assert dct["half_life"] == 10.0
+    assert dct["now"] == 100.0
+    assert dct["digest"]["max_centroids"] == 100
+    assert math.isfinite(dct["landmark"])
+    dct["landmark"] = None
+    with pytest.raises(ValueError):
+        DecayingTDigest.from_dict(dct)
+    data = d.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            DecayingTDigest.from_bytes(bad)
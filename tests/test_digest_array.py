@@ -0,0 +1,144 @@
+import copy
+import io
+import math
+import pickle
+import pytest
+import random
+from fastdigest import TDigest, TDigestArray
+
+
+def sample_data(n: int, n_groups: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    indices = [rng.randrange(n_groups) for _ in range(n)]
+    values = [rng.gauss(i, 1.0) for i in indices]
+    return indices, values
+
+
+def group(indices: list, values: list, i: int) -> TDigest:
+    return TDigest.from_values([x for j, x in zip(indices, values) if j == i])
+
+
+def test_init() -> None:
+    a = TDigestArray(3)
+    assert len(a) == 3
+    assert a.max_centroids == 1000
+    assert a.n_values == 0
+    assert a[0].is_empty()
+    assert repr(a) == "TDigestArray(n=3, max_centroids=1000)"
+    assert len(TDigestArray(0)) == 0
+    with pytest.raises(ValueError):
+        TDigestArray(-1)
+    with pytest.raises(ValueError):
+        TDigestArray(3, max_centroids=-1)
+    with pytest.raises(IndexError):
+        a[3]
+
+
+def test_update() -> None:
+    a = TDigestArray(2)
+    a.update(0, 1.0)
+    a.update(0, 3.0, w=2.0)
+    a.update(1, 5.0)
+    assert a.n_values == 3
+    assert a[0].mass() == 3.0
+    assert a[-1].max() == 5.0
+    with pytest.raises(IndexError):
+        a.update(2, 1.0)
+    with pytest.raises(IndexError):
+        a.update(-1, 1.0)
+    with pytest.raises(ValueError):
+        a.update(0, float("nan"))
+
+
+def test_batch_update() -> None:
+    indices, values = sample_data(20_000, 5)
+    a = TDigestArray(6)
+    a.batch_update(indices, values)
+    assert a.n_values == 20_000
+    for i in range(5):
+        assert a[i] == group(indices, values, i)
+    assert a[5].is_empty()
+    b = TDigestArray(6)
+    b.batch_update(indices, values, n_threads=3)
+    assert b == a
+    mass = a[0].mass()
+    a.batch_update([0, 0], [1.0, 2.0], w=[1.0, 3.0])
+    assert a[0].mass() == mass + 4.0
+    for bad in ([6], [-1], [0.5]):
+        with pytest.raises(IndexError):
+            a.batch_update(bad, [1.0])
+    with pytest.raises(ValueError):
+        a.batch_update([0, 1], [1.0])
+    with pytest.raises(ValueError):
+        a.batch_update([0, 1], [1.0, float("inf")])
+    with pytest.raises(ValueError):
+        a.batch_update([0], [1.0], n_threads=0)
+
+
+def test_queries() -> None:
+    np = pytest.importorskip("numpy")
+    indices, values = sample_data(10_000, 4)
+    a = TDigestArray(5)
+    a.batch_update(np.array(indices), np.array(values))
+    expected = [group(indices, values, i) for i in range(4)]
+    medians = a.median()
+    assert isinstance(medians, np.ndarray) and medians.shape == (5,)
+    assert list(medians[:4]) == [d.median() for d in expected]
+    assert math.isnan(medians[4])
+    assert list(a.quantile(0.9)[:4]) == [d.quantile(0.9) for d in expected]
+    assert list(a.percentile(10)[:4]) == [d.quantile(0.1) for d in expected]
+    assert list(a.cdf(1.0)[:4]) == [d.cdf(1.0) for d in expected]
+    assert list(a.mass()) == [d.mass() for d in expected] + [0.0]
+    with pytest.raises(ValueError):
+        a.quantile(1.5)
+
+
+def test_merge() -> None:
+    indices, values = sample_data(4000, 3)
+    a, b = TDigestArray(3), TDigestArray(3)
+    a.batch_update(indices[:2000], values[:2000])
+    b.batch_update(indices[2000:], values[2000:])
+    merged = a + b
+    assert merged.n_values == 4000
+    for i in range(3):
+        assert merged[i].n_values == a[i].n_values + b[i].n_values
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 8000
+    with pytest.raises(ValueError):
+        a.merge(TDigestArray(2))
+
+
+def stream(a: TDigestArray) -> TDigestArray:
+    f = io.BytesIO()
+    a.write_to(f)
+    f.seek(0)
+    return TDigestArray.read_from(f)
+
+
+def test_serialization() -> None:
+    indices, values = sample_data(3000, 4)
+    a = TDigestArray(4, max_centroids=100)
+    a.batch_update(indices, values)
+    a.update(3, 10.0)
+    for restore in (
+        lambda a: TDigestArray.from_bytes(a.to_bytes()),
+        stream,
+        lambda a: TDigestArray.from_dict(a.to_dict()),
+        lambda a: pickle.loads(pickle.dumps(a)),
+        copy.deepcopy,
+    ):
+        restored = restore(a)
+        assert restored == a
+        assert restored[3].max() == 10.0
+    d = a.to_dict()
+    assert d["max_centroids"] == 100
+    assert d["digests"][1] == a[1].to_dict()
+    d["digests"][1]["max_centroids"] = 50
+    with pytest.raises(ValueError):
+        TDigestArray.from_dict(d)
+    data = a.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            TDigestArray.from_bytes(bad)
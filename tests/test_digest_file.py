@@ -0,0 +1,76 @@
+import pytest
+from pathlib import Path
+from fastdigest import (
+    DigestFile, TDigest, TDigestMap, load_many, save_many
+)
+
+
+def test_save_and_load(tmp_path: Path) -> None:
+    path = tmp_path / "metrics.tdf"
+    digests = {
+        f"metric_{i}": TDigest.from_values(range(i, 10 * i + 10))
+        for i in range(100)
+    }
+    digests[("host", 7)] = TDigest.from_values([1.0, 2.0])
+    digests["empty"] = TDigest()
+    save_many(digests, path)
+    with load_many(path) as f:
+        assert isinstance(f, DigestFile)
+        assert len(f) == 102
+        assert f.keys() == list(digests)
+        assert list(f) == list(digests)
+        assert "metric_5" in f
+        assert "missing" not in f
+        assert f["metric_42"] == digests["metric_42"]
+        assert f[("host", 7)].median() == 1.5
+        assert f["empty"].is_empty()
+        assert f.get("missing") is None
+        assert f.get("missing", 0) == 0
+        with pytest.raises(KeyError):
+            f["missing"]
+        assert f.path == path
+    assert f.closed
+    with pytest.raises(ValueError, match="closed"):
+        f["metric_1"]
+
+
+def test_digest_map(tmp_path: Path) -> None:
+    path = tmp_path / "map.tdf"
+    m = TDigestMap(max_centroids=50)
+    m.batch_update(["a", "b", "a", 3], [1.0, 2.0, 3.0, 4.0])
+    m.update("b", 5.0)
+    save_many(m, path)
+    f = load_many(path)
+    assert f.keys() == ["a", "b", 3]
+    assert f["b"].max() == 5.0
+    assert f.to_map() == m
+    save_many({}, path)
+    assert len(load_many(path)) == 0
+    assert len(load_many(path).to_map()) == 0
+
+
+def test_errors(tmp_path: Path) -> None:
+    path = tmp_path / "bad.tdf"
+    with pytest.raises(TypeError):
+        save_many([TDigest()], path)
+    with pytest.raises(TypeError):
+        save_many({"a": 1.0}, path)
+
+    class Pairs:
+        def items(self):
+            return [(1, TDigest()), (1.0, TDigest())]
+
+    with pytest.raises(ValueError, match="duplicate"):
+        save_many(Pairs(), path)
+    with pytest.raises(OSError):
+        load_many(path)
+    save_many({"a": TDigest(50), "b": TDigest(60)}, path)
+    with pytest.raises(ValueError):
+        load_many(path).to_map()
+    data = path.read_bytes()
+    path.write_bytes(data[:-1])
+    with pytest.raises(ValueError):
+        load_many(path)
+    path.write_bytes(b"")
+    with pytest.raises(ValueError):
+        load_many(path)
@@ -0,0 +1,167 @@
+import copy
+import io
+import pickle
+import pytest
+import random
+import socket
+import threading
+from fastdigest import TDigest, TDigestMap
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    keys = [rng.choice(["/api", "/login", "/health"]) for _ in range(n)]
+    values = [rng.expovariate(1.0) for _ in range(n)]
+    return keys, values
+
+
+def test_init() -> None:
+    m = TDigestMap()
+    assert m.max_centroids == 1000
+    assert m.n_values == 0
+    assert len(m) == 0
+    assert m.keys() == []
+    assert repr(m) == "TDigestMap(max_centroids=1000)"
+    assert repr(TDigestMap(50)) == "TDigestMap(max_centroids=50)"
+    with pytest.raises(ValueError):
+        TDigestMap(-1)
+    with pytest.raises(KeyError):
+        m.quantile("/api", 0.5)
+
+
+def test_update() -> None:
+    keys, values = sample_data(2000)
+    m = TDigestMap()
+    for key, x in zip(keys, values):
+        m.update(key, x)
+    assert m.keys() == list(dict.fromkeys(keys))
+    assert m.n_values == 2000
+    for key in set(keys):
+        expected = TDigest.from_values(
+            [x for k, x in zip(keys, values) if k == key]
+        )
+        assert key in m
+        assert m[key].n_values == expected.n_values
+        assert m.median(key) == pytest.approx(expected.median(), rel=1e-3)
+    assert "/missing" not in m
+    with pytest.raises(ValueError):
+        m.update("/api", float("nan"))
+    with pytest.raises(ValueError):
+        m.update("/api", 1.0, w=0.0)
+    with pytest.raises(TypeError):
+        m.update([1], 1.0)
+
+
+def test_batch_update() -> None:
+    keys, values = sample_data(10_000)
+    m = TDigestMap()
+    m.batch_update(keys, values)
+    assert m.n_values == 10_000
+    for key in set(keys):
+        group = [x for k, x in zip(keys, values) if k == key]
+        expected = TDigest.from_values(group)
+        assert m[key] == expected
+        assert m.quantile(key, 0.99) == expected.quantile(0.99)
+        assert m.percentile(key, 50) == expected.quantile(0.5)
+        assert m.quantile_vec(key, [0.1, 0.9]) == expected.quantile_vec(
+            [0.1, 0.9]
+        )
+        assert m.cdf(key, 1.0) == expected.cdf(1.0)
+    m.batch_update([1, 2, 1], [1.0, 2.0, 3.0], w=[1.0, 1.0, 2.0])
+    assert m[1].mass() == 3.0
+    assert m[1.0].mean() == pytest.approx(7 / 3)
+    with pytest.raises(ValueError):
+        m.batch_update(["/api"], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        m.batch_update(["/api", "/x"], [1.0, float("inf")])
+    assert "/x" not in m
+    with pytest.raises(ValueError):
+        m.quantile("/api", 1.5)
+
+
+def test_numpy() -> None:
+    np = pytest.importorskip("numpy")
+    m = TDigestMap()
+    m.batch_update(np.array([1, 2, 1, 2]), np.array([1.0, 2.0, 3.0, 4.0]))
+    assert m.keys() == [1, 2]
+    assert m[2].max() == 4.0
+
+
+def test_merge() -> None:
+    keys, values = sample_data(4000)
+    a, b = TDigestMap(), TDigestMap()
+    a.batch_update(keys[:2000], values[:2000])
+    b.batch_update(keys[2000:], values[2000:])
+    b.update("/new", 5.0)
+    merged = a + b
+    assert merged.n_values == 4001
+    assert merged.keys()[-1] == "/new"
+    assert merged["/api"].n_values == (
+        a["/api"].n_values + b["/api"].n_values
+    )
+    a += b
+    assert a == merged
+    before = a.n_values
+    a.merge_inplace(a)
+    assert a.n_values == 2 * before
+
+
+def test_serialization() -> None:
+    keys, values = sample_data(3000)
+    m = TDigestMap(100)
+    m.batch_update(keys, values)
+    m.update(("tenant", 7), 1.5)
+    for restore in (
+        lambda m: TDigestMap.from_bytes(m.to_bytes()),
+        lambda m: TDigestMap.from_dict(m.to_dict()),
+        lambda m: pickle.loads(pickle.dumps(m)),
+        copy.deepcopy,
+    ):
+        restored = restore(m)
+        assert restored == m
+        assert restored.keys() == m.keys()
+        assert restored.quantile("/api", 0.9) == m.quantile("/api", 0.9)
+    d = m.to_dict()
+    assert d["max_centroids"] == 100
+    assert d["digests"]["/api"] == m["/api"].to_dict()
+    d["digests"]["/api"]["max_centroids"] = 50
+    with pytest.raises(ValueError):
+        TDigestMap.from_dict(d)
+    data = m.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            TDigestMap.from_bytes(bad)
+
+
+
+def test_streaming() -> None:
+    rng = random.Random(0)
+    m = TDigestMap(100)
+    # larger than one chunk, with pending single updates
+    for i in range(20_000):
+        m.update(i % 500, rng.random())
+    m.update(("tenant", 7), 1.5)
+    f = io.BytesIO()
+    m.write_to(f)
+    assert f.getvalue() == m.to_bytes()
+    TDigestMap(10).write_to(f)
+    f.seek(0)
+    restored = TDigestMap.read_from(f)
+    assert restored == m
+    assert restored.keys() == m.keys()
+    assert TDigestMap.read_from(f) == TDigestMap(10)
+    with pytest.raises(ValueError, match="empty"):
+        TDigestMap.read_from(f)
+    with pytest.raises(ValueError):
+        TDigestMap.read_from(io.BytesIO(m.to_bytes()[:-1]))
+    with pytest.raises(TypeError):
+        TDigestMap.read_from(io.StringIO("text"))
+    with pytest.raises(TypeError):
+        m.write_to(None)
+    # sockets are written with sendall and read with recv
+    sender, receiver = socket.socketpair()
+    with sender, receiver:
+        thread = threading.Thread(target=m.write_to, args=(sender,))
+        thread.start()
+        assert TDigestMap.read_from(receiver) == m
+        thread.join()
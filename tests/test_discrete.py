@@ -0,0 +1,165 @@
+import bisect
+import copy
+import math
+import pickle
+import pytest
+import random
+import struct
+from fastdigest import DiscreteTDigest, TDigest
+
+
+def sample_data(n: int, seed: int = 0) -> list:
+    rng = random.Random(seed)
+    # latencies mixed with two status codes
+    return [
+        rng.choice([200.0, 200.0, 404.0]) if rng.random() < 0.6
+        else rng.expovariate(0.01)
+        for _ in range(n)
+    ]
+
+
+def max_rank_error(quantile, values: list) -> float:
+    s = sorted(values)
+    error = 0.0
+    for i in range(1, 1000):
+        q = i / 1000
+        x = quantile(q)
+        lo = bisect.bisect_left(s, x) / len(s)
+        hi = bisect.bisect_right(s, x) / len(s)
+        if not lo <= q <= hi:
+            error = max(error, min(abs(q - lo), abs(q - hi)))
+    return error
+
+
+def test_init() -> None:
+    d = DiscreteTDigest()
+    assert d.min_fraction == 0.01
+    assert d.max_centroids == 1000
+    assert d.n_values == 0
+    assert d.is_empty()
+    assert d.mass() == 0.0
+    assert d.exact_values() == {}
+    assert d.snapshot().is_empty()
+    assert repr(d) == "DiscreteTDigest(min_fraction=0.01, max_centroids=1000)"
+    for min_fraction in (0.0, -0.1, 1.5, float("nan")):
+        with pytest.raises(ValueError):
+            DiscreteTDigest(min_fraction)
+    with pytest.raises(ValueError):
+        DiscreteTDigest(max_centroids=-1)
+    with pytest.raises(ValueError):
+        d.median()
+
+
+def test_update() -> None:
+    d = DiscreteTDigest(0.1)
+    for x in (1.0, 2.0, 2.0, 3.0):
+        d.update(x)
+    d.update(2.0, w=4.0)
+    assert d.n_values == 5
+    assert d.mass() == 8.0
+    assert d.exact_values() == {1.0: 1.0, 2.0: 6.0, 3.0: 1.0}
+    assert d.min() == 1.0 and d.max() == 3.0
+    assert d.mean() == 2.0
+    for q in (0.2, 0.5, 0.8):
+        assert d.quantile(q) == 2.0
+    assert d.quantile(0.0) == 1.0 and d.quantile(1.0) == 3.0
+    assert d.cdf(2.0) == 0.5
+    assert d.percentile(50) == d.median() == 2.0
+    with pytest.raises(ValueError):
+        d.update(math.inf)
+    with pytest.raises(ValueError):
+        d.update(1.0, w=0.0)
+    with pytest.raises(ValueError):
+        d.quantile(1.5)
+
+
+def test_batch_update() -> None:
+    values = sample_data(50_000)
+    d = DiscreteTDigest(max_centroids=50)
+    d.batch_update(values)
+    assert d.n_values == 50_000
+    assert d.mass() == pytest.approx(50_000)
+    assert set(d.exact_values()) == {200.0, 404.0}
+    assert d.exact_values()[200.0] == values.count(200.0)
+    assert d.min() == min(values) and d.max() == max(values)
+    assert d.mean() == pytest.approx(sum(values) / len(values))
+    # the ties bias the quantiles of a TDigest around them
+    digest = TDigest.from_values(values, max_centroids=50)
+    error = max_rank_error(d.quantile, values)
+    assert error < 0.002
+    assert error < max_rank_error(digest.quantile, values) / 5
+    qs = [i / 1000 for i in range(1001)]
+    xs = [d.quantile(q) for q in qs]
+    assert all(a <= b for a, b in zip(xs, xs[1:]))
+    # single updates give the same exact values
+    single = DiscreteTDigest(max_centroids=50)
+    for x in values:
+        single.update(x)
+    assert single.exact_values() == d.exact_values()
+    snapshot = d.snapshot()
+    assert snapshot.n_values == 50_000
+    assert snapshot.mass() == pytest.approx(d.mass())
+    weighted = DiscreteTDigest()
+    weighted.batch_update([1.0, 2.0, 2.0], w=[1.0, 0.5, 0.5])
+    assert weighted.exact_values() == {1.0: 1.0, 2.0: 1.0}
+    with pytest.raises(ValueError):
+        weighted.batch_update([1.0, math.nan])
+
+
+def test_merge() -> None:
+    a = DiscreteTDigest(max_centroids=50)
+    a.batch_update(sample_data(10_000, seed=1))
+    b = DiscreteTDigest(max_centroids=50)
+    b.batch_update(sample_data(10_000, seed=2))
+    merged = a + b
+    assert merged.n_values == 20_000
+    assert merged.mass() == pytest.approx(20_000)
+    for x in (200.0, 404.0):
+        assert merged.exact_values()[x] == (
+            a.exact_values()[x] + b.exact_values()[x]
+        )
+    a += a
+    assert a.n_values == 20_000
+    with pytest.raises(ValueError):
+        a.merge(DiscreteTDigest(0.05))
+
+
+def test_serialization() -> None:
+    d = DiscreteTDigest(0.05, max_centroids=50)
+    d.batch_update(sample_data(5000))
+    for restored in (
+        DiscreteTDigest.from_bytes(d.to_bytes()),
+        DiscreteTDigest.from_dict(d.to_dict()),
+        pickle.loads(pickle.dumps(d)),
+        copy.deepcopy(d),
+        d.copy(),
+    ):
+        assert restored == d
+        assert restored.median() == d.median()
+    assert DiscreteTDigest() != d
+    with pytest.raises(ValueError):
+        DiscreteTDigest.from_bytes(d.to_bytes()[:-1])
+    bad = d.to_dict()
+    bad["exact_weights"][0] = -1.0
+    with pytest.raises(ValueError):
+        DiscreteTDigest.from_dict(bad)
+    del bad["min_fraction"]
+    with pytest.raises(ValueError):
+        DiscreteTDigest.from_dict(bad)
+    # the embedded digest is checked strictly
+    bad = d.to_dict()
+    bad["digest"]["centroids"][0]["c"] = 0.0
+    with pytest.raises(ValueError):
+        DiscreteTDigest.from_dict(bad)
+    tracked = DiscreteTDigest(0.05, max_centroids=50)
+    tracked.batch_update([200.0] * 10)
+    inner = TDigest(max_centroids=50).to_bytes()
+    size = struct.pack("<Q", 50)
+    # mass 3.0 without centroids
+    massive = inner.replace(
+        size + struct.pack("<d", 0.0), size + struct.pack("<d", 3.0)
+    )
+    raw = tracked.to_bytes()
+    assert raw.count(inner) == 1
+    with pytest.raises(ValueError):
+        DiscreteTDigest.from_bytes(raw.replace(inner, massive))
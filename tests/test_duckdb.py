@@ -0,0 +1,42 @@
+import pytest
+import fastdigest.duckdb as fd_duckdb
+from fastdigest import TDigest, merge_all
+
+
+def test_duckdb_udfs() -> None:
+    blob = fd_duckdb.tdigest_from_list([1.0, None, 2.0, 3.0])
+    digest = TDigest.from_bytes(blob)
+    assert digest == TDigest.from_values([1.0, 2.0, 3.0])
+    assert fd_duckdb.tdigest_quantile(blob, 0.5) == 2.0
+    assert fd_duckdb.tdigest_cdf(blob, 3.0) == 1.0
+    other = TDigest.from_values([4.0, 5.0]).to_bytes()
+    merged_blob = fd_duckdb.tdigest_merge_list([blob, None, other])
+    merged = TDigest.from_bytes(merged_blob)
+    assert merged == merge_all([digest, TDigest.from_bytes(other)])
+    empty = fd_duckdb.tdigest_from_list([None])
+    assert fd_duckdb.tdigest_quantile(empty, 0.5) is None
+    assert fd_duckdb.tdigest_cdf(empty, 0.0) is None
+    with pytest.raises(ValueError):
+        fd_duckdb.tdigest_quantile(blob, 2.0)
+    with pytest.raises(ValueError):
+        fd_duckdb.tdigest_quantile(b"not a digest", 0.5)
+
+
+def test_duckdb_register() -> None:
+    duckdb = pytest.importorskip("duckdb")
+    conn = duckdb.connect()
+    fd_duckdb.register(conn)
+    conn.execute(
+        "CREATE TABLE t AS SELECT i % 2 AS g, i::DOUBLE AS v "
+        "FROM range(1, 101) r(i)"
+    )
+    rows = conn.execute(
+        "SELECT g, tdigest_quantile(tdigest_agg(v), 0.5) FROM t "
+        "GROUP BY g ORDER BY g"
+    ).fetchall()
+    assert rows == [(0, 51.0), (1, 50.0)]
+    (median,) = conn.execute(
+        "SELECT tdigest_quantile(tdigest_merge_agg(d), 0.5) FROM "
+        "(SELECT tdigest_agg(v) AS d FROM t GROUP BY g)"
+    ).fetchone()
+    assert median == 50.5
@@ -0,0 +1,128 @@
+import math
+import pickle
+import pytest
+import warnings
+import fastdigest
+from decimal import Decimal
+from fastdigest import DDSketch, KLLSketch, TDigest, TDigestMap
+from fastdigest.errors import (
+    EmptyDigestError,
+    FastDigestError,
+    InvalidQuantileError,
+    InvalidValueError,
+    PrecisionWarning,
+    SerializationError,
+)
+
+
+ERRORS = (
+    EmptyDigestError,
+    InvalidQuantileError,
+    InvalidValueError,
+    SerializationError,
+)
+
+
+def test_hierarchy() -> None:
+    assert issubclass(FastDigestError, ValueError)
+    for error in ERRORS:
+        assert issubclass(error, FastDigestError)
+        assert error.__module__ == "fastdigest.errors"
+        assert getattr(fastdigest, error.__name__) is error
+    assert fastdigest.FastDigestError is FastDigestError
+    e = pickle.loads(pickle.dumps(SerializationError("Data is corrupt.")))
+    assert type(e) is SerializationError
+    assert str(e) == "Data is corrupt."
+
+
+def test_empty_digest() -> None:
+    for sketch in (TDigest(), DDSketch(), KLLSketch()):
+        with pytest.raises(EmptyDigestError):
+            sketch.median()
+    with pytest.raises(EmptyDigestError):
+        TDigest().cdf(0.0)
+    # unknown keys are not empty digests
+    with pytest.raises(KeyError):
+        TDigestMap().median("a")
+
+
+def test_invalid_quantile() -> None:
+    digest = TDigest.from_values(range(100))
+    for q in (-0.1, 1.1, math.nan):
+        with pytest.raises(InvalidQuantileError):
+            digest.quantile(q)
+    with pytest.raises(InvalidQuantileError):
+        digest.quantile_vec([0.5, 2.0])
+    with pytest.raises(InvalidQuantileError):
+        digest.percentile(101)
+    with pytest.raises(InvalidQuantileError):
+        digest.trimmed_mean(0.9, 0.1)
+    # the range is checked before the digest
+    with pytest.raises(InvalidQuantileError):
+        TDigest().quantile(2.0)
+    with pytest.raises(InvalidQuantileError):
+        KLLSketch().quantile(2.0)
+
+
+def test_invalid_value() -> None:
+    digest = TDigest()
+    with pytest.raises(InvalidValueError):
+        digest.update(math.inf)
+    with pytest.raises(InvalidValueError):
+        digest.update(1.0, w=0.0)
+    with pytest.raises(InvalidValueError):
+        digest.batch_update([1.0, math.nan])
+    with pytest.raises(InvalidValueError):
+        TDigest.from_values([1.0, 2.0], w=[1.0, -1.0])
+    assert digest.is_empty()
+
+
+def test_serialization() -> None:
+    digest = TDigest.from_values(range(100))
+    for data in (b"", b"fake_bytes", digest.to_bytes()[:-1]):
+        with pytest.raises(SerializationError):
+            TDigest.from_bytes(data)
+    with pytest.raises(SerializationError):
+        TDigest.from_dict({})
+    with pytest.raises(SerializationError):
+        TDigest.from_dict({"centroids": [{"m": 1.0}]})
+    bad = digest.to_dict()
+    bad["centroids"][0]["c"] = -1.0
+    with pytest.raises(SerializationError):
+        TDigest.from_dict(bad, strict=True)
+    with pytest.raises(SerializationError):
+        KLLSketch.from_bytes(b"")
+    with pytest.raises(SerializationError):
+        DDSketch.from_dict({})
+
+
+def test_except_value_error() -> None:
+    # existing handlers for ValueError catch all of the errors
+    with pytest.raises(ValueError):
+        TDigest().median()
+    with pytest.raises(ValueError):
+        TDigest().quantile(2.0)
+    with pytest.raises(ValueError):
+        TDigest.from_bytes(b"")
+
+
+def test_precision_warning() -> None:
+    assert issubclass(PrecisionWarning, UserWarning)
+    assert fastdigest.PrecisionWarning is PrecisionWarning
+    values = [Decimal("0.5"), Decimal("0.1"), Decimal("0.2")]
+    # ignored by default
+    with warnings.catch_warnings(record=True) as record:
+        TDigest.from_values(values)
+    assert record == []
+    with warnings.catch_warnings():
+        warnings.simplefilter("error", PrecisionWarning)
+        TDigest.from_values(values[:1])
+        with pytest.raises(PrecisionWarning, match=r"Decimal\('0.1'\)"):
+            TDigest.from_values(values)
+        with pytest.raises(PrecisionWarning):
+            TDigest().update(Decimal("0.3"))
+        with pytest.raises(PrecisionWarning):
+            DDSketch().batch_update(values)
+    with pytest.warns(PrecisionWarning) as record:
+        TDigest().batch_update(values)
+    assert len(record) == 1
@@ -0,0 +1,151 @@
+import copy
+import pickle
+import pytest
+import random
+from fastdigest import EventTimeTDigest, TDigest
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    # event timestamps arrive out of order
+    timestamps = [rng.uniform(0.0, 300.0) for _ in range(n)]
+    values = [rng.gauss(0.0, 1.0) for _ in range(n)]
+    return timestamps, values
+
+
+def test_init() -> None:
+    e = EventTimeTDigest(60.0)
+    assert e.interval == 60.0
+    assert e.max_centroids == 1000
+    assert e.watermark is None
+    assert e.n_values == 0
+    assert e.n_late == 0
+    assert e.is_empty()
+    assert e.ranges() == []
+    assert e.snapshot().is_empty()
+    assert repr(e) == "EventTimeTDigest(interval=60.0, max_centroids=1000)"
+    for interval in (0.0, -1.0, float("inf"), float("nan")):
+        with pytest.raises(ValueError):
+            EventTimeTDigest(interval)
+    with pytest.raises(ValueError):
+        EventTimeTDigest(60.0, max_centroids=-1)
+    with pytest.raises(ValueError):
+        e.median()
+
+
+def test_update_expire() -> None:
+    e = EventTimeTDigest(10.0)
+    e.update(25.0, 3.0)
+    e.update(5.0, 1.0)
+    e.update(12.0, 2.0, w=2.0)
+    assert e.n_values == 3
+    assert e.ranges() == [(0.0, 10.0), (10.0, 20.0), (20.0, 30.0)]
+    assert e.snapshot().mass() == 4.0
+    assert e.snapshot(start=10.0, end=20.0).mass() == 2.0
+    assert e.snapshot(start=15.0).min() == 2.0
+    assert e.snapshot(end=10.0).max() == 1.0
+    # the interval [10, 20) still overlaps the watermark
+    e.expire(15.0)
+    assert e.watermark == 15.0
+    assert e.ranges() == [(10.0, 20.0), (20.0, 30.0)]
+    assert e.snapshot().min() == 2.0
+    # values older than the watermark are late
+    e.update(14.0, 4.0)
+    e.update(15.0, 5.0)
+    assert e.n_late == 1
+    assert e.n_values == 3
+    # the watermark does not move back
+    e.expire(0.0)
+    assert e.watermark == 15.0
+    e.expire(100.0)
+    assert e.is_empty()
+    with pytest.raises(ValueError):
+        e.expire(float("nan"))
+    with pytest.raises(ValueError):
+        e.update(float("inf"), 1.0)
+    with pytest.raises(ValueError):
+        e.update(200.0, float("nan"))
+    with pytest.raises(ValueError):
+        e.update(200.0, 1.0, w=0.0)
+
+
+def test_batch_update() -> None:
+    timestamps, values = sample_data(10_000)
+    e = EventTimeTDigest(60.0)
+    e.batch_update(timestamps, values)
+    assert e.n_values == 10_000
+    assert len(e.ranges()) == 5
+    expected = TDigest.from_values(values)
+    assert abs(e.median() - expected.median()) < 0.05
+    assert abs(e.quantile(0.9) - expected.quantile(0.9)) < 0.05
+    assert e.percentile(50) == e.median()
+    assert 0.4 < e.cdf(0.0) < 0.6
+    recent = [x for t, x in zip(timestamps, values) if t >= 240.0]
+    assert e.snapshot(start=240.0).mass() == len(recent)
+    single = EventTimeTDigest(60.0)
+    for t, x in zip(timestamps, values):
+        single.update(t, x)
+    assert single.n_values == e.n_values
+    e.expire(120.0)
+    e.batch_update([100.0, 130.0, 110.0], [1.0, 2.0, 3.0], w=2.0)
+    assert e.n_late == 2
+    assert e.n_values == sum(t >= 120.0 for t in timestamps) + 1
+    with pytest.raises(ValueError):
+        e.batch_update([301.0], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        e.batch_update([301.0, float("nan")], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        e.batch_update([301.0, 302.0], [1.0, float("inf")])
+
+
+def test_merge() -> None:
+    timestamps, values = sample_data(4000)
+    a, b = EventTimeTDigest(60.0), EventTimeTDigest(60.0)
+    a.batch_update(timestamps[::2], values[::2])
+    b.batch_update(timestamps[1::2], values[1::2])
+    b.expire(60.0)
+    merged = a + b
+    assert merged.watermark == 60.0
+    assert merged.n_values == sum(t >= 60.0 for t in timestamps)
+    assert (b + a).n_values == merged.n_values
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 2 * merged.n_values
+    b.expire(120.0)
+    assert (merged + b).watermark == 120.0
+    assert EventTimeTDigest(60.0) + b == b
+    with pytest.raises(ValueError):
+        a.merge(EventTimeTDigest(30.0))
+
+
+def test_serialization() -> None:
+    timestamps, values = sample_data(3000)
+    e = EventTimeTDigest(60.0, max_centroids=100)
+    e.batch_update(timestamps, values)
+    e.update(299.0, 10.0)
+    e.expire(100.0)
+    e.update(0.0, 1.0)
+    for restore in (
+        lambda e: EventTimeTDigest.from_bytes(e.to_bytes()),
+        lambda e: EventTimeTDigest.from_dict(e.to_dict()),
+        lambda e: pickle.loads(pickle.dumps(e)),
+        copy.deepcopy,
+    ):
+        restored = restore(e)
+        assert restored == e
+        assert restored.n_late == 1
+        assert restored.snapshot().max() == 10.0
+    empty = EventTimeTDigest(1.0)
+    assert EventTimeTDigest.from_bytes(empty.to_bytes()) == empty
+    d = e.to_dict()
+    assert d["watermark"] == 100.0
+    assert d["epochs"] == [1, 2, 3, 4]
+    assert d["digests"][0]["max_centroids"] == 100
+    d["epochs"][0] = 0
+    with pytest.raises(ValueError):
+        EventTimeTDigest.from_dict(d)
+    data = e.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            EventTimeTDigest.from_bytes(bad)
@@ -0,0 +1,102 @@
+import bisect
+import copy
+import pickle
+import pytest
+import random
+from fastdigest import ExponentialHistogram
+
+
+def test_init() -> None:
+    h = ExponentialHistogram(60.0)
+    assert h.window == 60.0
+    assert h.epsilon == 0.01
+    assert h.now is None
+    assert h.n_buckets == 0
+    assert h.is_empty()
+    assert h.query() == (0.0, 0.0)
+    assert repr(h) == "ExponentialHistogram(window=60.0, epsilon=0.01)"
+    for window in (0.0, -1.0, float("inf")):
+        with pytest.raises(ValueError):
+            ExponentialHistogram(window)
+    for epsilon in (0.0, 1.0):
+        with pytest.raises(ValueError):
+            ExponentialHistogram(60.0, epsilon)
+
+
+def test_exact() -> None:
+    h = ExponentialHistogram(10.0)
+    h.batch_add([1.0, 2.0, 3.0], [1.0, 2.0, 3.0])
+    assert h.now == 3.0
+    assert h.query() == (3.0, 6.0)
+    assert h.query(1.5) == (2.0, 5.0)
+    assert h.query(now=12.5) == (1.0, 3.0)
+    assert h.query(now=13.0) == (0.0, 0.0)
+
+
+def test_expire() -> None:
+    h = ExponentialHistogram(10.0)
+    h.batch_add([float(t) for t in range(1000)])
+    assert h.query() == (10.0, 10.0)
+    h.add(2000.0, 5.0)
+    assert h.n_buckets == 1
+    assert h.query() == (1.0, 5.0)
+
+
+def test_error_bound() -> None:
+    rng = random.Random(0)
+    for epsilon in (0.01, 0.1):
+        h = ExponentialHistogram(1000.0, epsilon)
+        timestamps = []
+        t = 0.0
+        for i in range(20_000):
+            t += rng.expovariate(10.0)
+            h.add(t)
+            timestamps.append(t)
+            if i % 499 == 0:
+                for window in (1.0, 10.0, 100.0, 1000.0):
+                    lo = bisect.bisect_right(timestamps, t - window)
+                    exact = len(timestamps) - lo
+                    count, total = h.query(window)
+                    assert count == total
+                    assert abs(count - exact) <= epsilon * exact
+        assert h.n_buckets < 40 / epsilon
+
+
+def test_order() -> None:
+    h = ExponentialHistogram(10.0)
+    h.add(5.0)
+    with pytest.raises(ValueError):
+        h.add(4.0)
+    with pytest.raises(ValueError):
+        h.batch_add([6.0, 7.0, 6.5])
+    with pytest.raises(ValueError):
+        h.add(float("nan"))
+    with pytest.raises(ValueError):
+        h.add(6.0, float("inf"))
+    with pytest.raises(ValueError):
+        h.batch_add([6.0, 7.0], [1.0])
+    assert h.query() == (1.0, 1.0)
+    with pytest.raises(ValueError):
+        h.query(now=4.0)
+    with pytest.raises(ValueError):
+        h.query(11.0)
+
+
+def test_serialization() -> None:
+    h = ExponentialHistogram(100.0, 0.1)
+    h.batch_add([t * 0.5 for t in range(1000)], [1.0] * 1000)
+    for restore in (
+        lambda h: ExponentialHistogram.from_bytes(h.to_bytes()),
+        lambda h: ExponentialHistogram.from_dict(h.to_dict()),
+        lambda h: pickle.loads(pickle.dumps(h)),
+        copy.deepcopy,
+    ):
+        restored = restore(h)
+        assert restored == h
+        assert restored.query(50.0) == h.query(50.0)
+    d = h.to_dict()
+    d["buckets"] = d["buckets"][::-1]
+    with pytest.raises(ValueError):
+        ExponentialHistogram.from_dict(d)
+    with pytest.raises(ValueError):
+        ExponentialHistogram.from_bytes(h.to_bytes()[:-1])
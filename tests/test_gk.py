@@ -0,0 +1,103 @@
+import bisect
+import copy
+import pickle
+import pytest
+from fastdigest import GKSketch
+from utils import make_data
+
+
+def max_rank_error(sketch: GKSketch, data: list) -> float:
+    data = sorted(data)
+    errors = []
+    for q in [i / 100 for i in range(101)]:
+        rank = bisect.bisect_right(data, sketch.quantile(q)) / len(data)
+        errors.append(abs(rank - q))
+    return max(errors)
+
+
+def test_init() -> None:
+    s = GKSketch(epsilon=0.05)
+    assert s.epsilon == 0.05
+    assert s.n_values == 0
+    assert s.n_samples == 0
+    assert s.is_empty()
+    assert repr(s) == "GKSketch(epsilon=0.05)"
+    assert GKSketch().epsilon == 0.01
+    with pytest.raises(ValueError):
+        s.quantile(0.5)
+    for bad_epsilon in [0.0, 1.0, -0.1]:
+        with pytest.raises(ValueError):
+            GKSketch(epsilon=bad_epsilon)
+
+
+def test_epsilon_guarantee() -> None:
+    data = make_data(100_000, "uniform")
+    s = GKSketch(epsilon=0.01)
+    s.batch_update(data)
+    assert s.n_values == len(data)
+    assert s.n_samples < 500
+    assert s.min() == s.quantile(0.0) == min(data)
+    assert s.max() == s.quantile(1.0) == max(data)
+    assert max_rank_error(s, data) <= 0.01
+    data.sort()
+    for x in [-90.0, -25.0, 0.0, 50.0, 99.0]:
+        rank = bisect.bisect_right(data, x) / len(data)
+        assert abs(s.rank(x) - rank) <= 0.01
+    assert s.rank(-101.0) == 0.0
+    assert s.rank(101.0) == 1.0
+    assert s.quantile_vec([0.5, 0.9]) == [s.median(), s.percentile(90)]
+    assert s.rank_vec([0.0, 1.0]) == [s.rank(0.0), s.rank(1.0)]
+    # deterministic: the same data gives the same sketch
+    other = GKSketch(epsilon=0.01)
+    other.batch_update(make_data(100_000, "uniform"))
+    assert other == s
+    # the guarantee also holds for sorted input
+    ascending = GKSketch(epsilon=0.01)
+    ascending.batch_update(data)
+    assert max_rank_error(ascending, data) <= 0.01 + 1e-9
+
+
+def test_updates() -> None:
+    s = GKSketch()
+    for x in range(1, 101):
+        s.update(x)
+    assert s.n_values == 100
+    assert s.median() == pytest.approx(50, abs=1)
+    with pytest.raises(ValueError):
+        s.update(float("nan"))
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, float("inf")])
+    assert s.n_values == 100
+
+
+def test_merge() -> None:
+    data = make_data(100_000, "uniform")
+    merged = GKSketch(epsilon=0.01)
+    for i in range(10):
+        part = GKSketch(epsilon=0.01)
+        part.batch_update(data[i * 10_000 : (i + 1) * 10_000])
+        merged += part
+    assert merged.n_values == len(data)
+    assert max_rank_error(merged, data) <= 0.01
+    coarse = GKSketch(epsilon=0.05)
+    coarse.batch_update(data[:1000])
+    combined = merged + coarse
+    assert combined.epsilon == 0.05
+    assert combined.n_values == len(data) + 1000
+    assert max_rank_error(combined, data + data[:1000]) <= 0.05
+
+
+def test_serialization() -> None:
+    s = GKSketch(epsilon=0.02)
+    s.batch_update(make_data(5000, "uniform"))
+    s.update(1.0)
+    assert GKSketch.from_bytes(s.to_bytes()) == s
+    assert GKSketch.from_dict(s.to_dict()) == s
+    assert pickle.loads(pickle.dumps(s)) == s
+    assert copy.deepcopy(s) == s
+    with pytest.raises(ValueError):
+        GKSketch.from_bytes(s.to_bytes()[:-1])
+    bad = s.to_dict()
+    bad["n_values"] += 1
+    with pytest.raises(ValueError):
+        GKSketch.from_dict(bad)
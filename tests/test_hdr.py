@@ -0,0 +1,126 @@
+import base64
+import copy
+import pickle
+import pytest
+import random
+import zlib
+from fastdigest import HdrHistogram
+
+
+def test_init() -> None:
+    h = HdrHistogram(1, 3_600_000_000, 3)
+    assert h.lowest == 1
+    assert h.highest == 3_600_000_000
+    assert h.sig_figs == 3
+    assert h.n_values == 0
+    assert h.is_empty()
+    assert repr(h) == "HdrHistogram(lowest=1, highest=3600000000, sig_figs=3)"
+    with pytest.raises(ValueError):
+        h.quantile(0.5)
+    for args in ((0, 10, 3), (5, 9, 3), (1, 10, 6), (1, 10, -1)):
+        with pytest.raises(ValueError):
+            HdrHistogram(*args)
+
+
+def test_percentiles() -> None:
+    # the reference implementation's standard test data
+    h = HdrHistogram(1, 3_600_000_000, 3)
+    h.batch_record([1000] * 10_000)
+    h.record(100_000_000)
+    assert h.n_values == 10_001
+    assert h.percentile(30.0) == 1000
+    assert h.percentile(99.99) == 1000
+    assert h.percentile(99.999) == 100_007_935
+    assert h.quantile(1.0) == h.max() == 100_007_935
+    assert h.median() == h.min() == 1000
+    assert h.quantile_vec([0.0, 0.5]) == [1000, 1000]
+    assert h.rank(999) == 0.0
+    assert h.rank(1000) == 10_000 / 10_001
+    assert h.mean() == pytest.approx(10_998.9, rel=1e-3)
+    with pytest.raises(ValueError):
+        h.percentile(101.0)
+
+
+def test_resolution() -> None:
+    rng = random.Random(0)
+    for sig_figs in (1, 2, 3):
+        h = HdrHistogram(1, 10**9, sig_figs)
+        values = sorted(rng.randrange(10**9) for _ in range(10_000))
+        h.batch_record(values)
+        for q in (0.01, 0.5, 0.99):
+            exact = values[int(q * len(values)) - 1]
+            assert h.quantile(q) == pytest.approx(exact, rel=10**-sig_figs)
+
+
+def test_record() -> None:
+    h = HdrHistogram(1, 1000, 3)
+    h.record(5, count=3)
+    assert h.n_values == 3
+    with pytest.raises(ValueError):
+        h.record(-1)
+    with pytest.raises(ValueError):
+        h.record(5, count=0)
+    with pytest.raises(ValueError):
+        h.record(10**6)
+    with pytest.raises(ValueError):
+        h.batch_record([1, 10**6])
+    assert h.n_values == 3
+
+
+def test_merge() -> None:
+    a, b = HdrHistogram(1, 10**6, 3), HdrHistogram(1, 10**6, 3)
+    a.batch_record(list(range(0, 1000, 3)))
+    b.batch_record(list(range(1, 1000, 3)))
+    merged = a + b
+    assert merged.n_values == a.n_values + b.n_values
+    assert merged.min() == 0 and merged.max() == 999
+    a += b
+    assert a == merged
+    coarse = HdrHistogram(1, 1000, 2)
+    coarse.record(999)
+    fine = HdrHistogram(1, 10**6, 3)
+    fine.merge_inplace(coarse)
+    assert fine.to_dict()["counts"] == [(996, 1)]
+    big = HdrHistogram(1, 10**6, 3)
+    big.record(10**6)
+    with pytest.raises(ValueError):
+        coarse.merge(big)
+
+
+def test_encoding() -> None:
+    h = HdrHistogram(1, 1000, 3)
+    encoded = h.encode()
+    assert encoded.startswith("HISTFAAA")
+    data = base64.b64decode(encoded)
+    assert data == h.to_bytes()
+    # V2 header: cookie, payload length, index offset, sig_figs, lowest,
+    # highest, conversion ratio, followed by a single zero count
+    raw = zlib.decompress(data[8:])
+    assert raw.hex() == (
+        "1c849313" "00000001" "00000000" "00000003"
+        "0000000000000001" "00000000000003e8" "3ff0000000000000" "00"
+    )
+    assert HdrHistogram.from_bytes(raw) == h
+
+
+def test_serialization() -> None:
+    h = HdrHistogram(1, 10**6, 3)
+    h.batch_record(list(range(0, 10**6, 7)))
+    for restore in (
+        lambda h: HdrHistogram.from_bytes(h.to_bytes()),
+        lambda h: HdrHistogram.decode(h.encode()),
+        lambda h: HdrHistogram.from_dict(h.to_dict()),
+        lambda h: pickle.loads(pickle.dumps(h)),
+        copy.deepcopy,
+    ):
+        assert restore(h) == h
+    data = h.to_bytes()
+    for bad in (b"", data[:-1], data[:12] + b"x" * (len(data) - 12)):
+        with pytest.raises(ValueError):
+            HdrHistogram.from_bytes(bad)
+    with pytest.raises(ValueError):
+        HdrHistogram.from_bytes(b"FASTDIGEST data")
+    d = h.to_dict()
+    d["counts"][0] = (1, 0)
+    with pytest.raises(ValueError):
+        HdrHistogram.from_dict(d)
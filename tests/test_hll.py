@@ -0,0 +1,94 @@
+import array
+import copy
+import pickle
+import pytest
+from fastdigest import HyperLogLog
+from fastdigest.errors import SerializationError
+
+
+def test_init() -> None:
+    h = HyperLogLog()
+    assert h.precision == 14
+    assert h.relative_error == pytest.approx(1.04 / 128)
+    assert h.is_empty()
+    assert h.estimate() == 0.0
+    assert len(h) == 0
+    assert repr(h) == "HyperLogLog(precision=14)"
+    assert HyperLogLog(precision=4).precision == 4
+    for bad_precision in [3, 19, -1]:
+        with pytest.raises(ValueError):
+            HyperLogLog(bad_precision)
+
+
+def test_estimate() -> None:
+    for n in [1, 10, 100, 1_000, 10_000, 200_000]:
+        h = HyperLogLog()
+        h.batch_update(range(n))
+        # duplicates do not change the estimate
+        h.batch_update(range(n))
+        assert not h.is_empty()
+        assert abs(h.estimate() - n) <= 4 * h.relative_error * n + 0.5
+    h = HyperLogLog(precision=8)
+    for i in range(50_000):
+        h.update(f"user-{i}")
+    assert abs(h.estimate() - 50_000) <= 4 * h.relative_error * 50_000
+
+
+def test_hashing() -> None:
+    h = HyperLogLog()
+    h.batch_update([1, 1.0, True, -0.0, 0, False])
+    assert len(h) == 2
+    values = [None, "a", b"a", bytearray(b"b"), 2.5, ("a", 1), 2**100]
+    h = HyperLogLog()
+    h.batch_update(values)
+    assert len(h) == len(values)
+    ints = HyperLogLog()
+    ints.batch_update(range(1_000))
+    assert ints == HyperLogLog.from_bytes(ints.to_bytes())
+    for typecode in "qd":
+        buffered = HyperLogLog()
+        buffered.batch_update(array.array(typecode, range(1_000)))
+        assert buffered == ints
+    large = HyperLogLog()
+    large.batch_update(array.array("q", [2**62, 2**62 + 1]))
+    assert len(large) == 2
+    with pytest.raises(TypeError):
+        h.update([1, 2])
+    with pytest.raises(TypeError):
+        h.batch_update([{1: 2}])
+
+
+def test_merge() -> None:
+    a = HyperLogLog()
+    a.batch_update(range(0, 60_000))
+    b = HyperLogLog()
+    b.batch_update(range(40_000, 100_000))
+    union = HyperLogLog()
+    union.batch_update(range(100_000))
+    assert a.merge(b) == union
+    assert a + b == union
+    a += b
+    assert a == union
+    a.merge_inplace(a)
+    assert a == union
+    with pytest.raises(ValueError):
+        a.merge(HyperLogLog(precision=10))
+
+
+def test_serialization() -> None:
+    h = HyperLogLog(precision=6)
+    h.batch_update(["a", "b", "c"])
+    assert HyperLogLog.from_bytes(h.to_bytes()) == h
+    assert HyperLogLog.from_dict(h.to_dict()) == h
+    assert pickle.loads(pickle.dumps(h)) == h
+    assert copy.deepcopy(h) == h
+    assert h.to_dict()["precision"] == 6
+    assert len(h.to_dict()["registers"]) == 64
+    with pytest.raises(ValueError):
+        HyperLogLog.from_bytes(h.to_bytes()[:-1])
+    bad = h.to_dict()
+    bad["registers"] = bad["registers"][:-1]
+    with pytest.raises(ValueError):
+        HyperLogLog.from_dict(bad)
+    with pytest.raises(SerializationError):
+        HyperLogLog.from_dict({"precision": 6})
@@ -0,0 +1,111 @@
+import bisect
+import copy
+import pickle
+import pytest
+from fastdigest import KLLSketch
+from utils import make_data
+
+
+def test_init() -> None:
+    s = KLLSketch(k=100, seed=1)
+    assert s.k == 100
+    assert s.n_values == 0
+    assert s.n_retained == 0
+    assert s.is_empty()
+    assert repr(s) == "KLLSketch(k=100)"
+    assert KLLSketch().k == 200
+    assert KLLSketch().rank_error == pytest.approx(0.0133, abs=1e-4)
+    with pytest.raises(ValueError):
+        s.quantile(0.5)
+    for bad_k in [7, 65536, -1]:
+        with pytest.raises(ValueError):
+            KLLSketch(k=bad_k)
+
+
+def test_rank_error() -> None:
+    data = make_data(100_000)
+    s = KLLSketch(seed=42)
+    s.batch_update(data)
+    assert s.n_values == len(data)
+    assert s.n_retained < 1000
+    assert s.min() == min(data)
+    assert s.max() == max(data)
+    assert s.quantile(0.0) == s.min()
+    assert s.quantile(1.0) == s.max()
+    data.sort()
+    for q in [i / 20 for i in range(1, 20)]:
+        rank = bisect.bisect_right(data, s.quantile(q)) / len(data)
+        assert abs(rank - q) <= s.rank_error
+        x = data[int(q * len(data))]
+        assert abs(s.rank(x) - q) <= s.rank_error
+    assert s.rank(data[0] - 1) == 0.0
+    assert s.rank(data[-1]) == 1.0
+    assert s.quantile_vec([0.25, 0.75]) == [s.quantile(0.25), s.quantile(0.75)]
+    assert s.rank_vec([0.0, 1.0]) == [s.rank(0.0), s.rank(1.0)]
+    assert s.percentile(50) == s.median()
+    with pytest.raises(ValueError):
+        s.quantile(-0.1)
+
+
+def test_updates() -> None:
+    s = KLLSketch()
+    for x in range(1, 101):
+        s.update(x)
+    assert s.n_values == s.n_retained == 100
+    assert s.median() == 50
+    with pytest.raises(ValueError):
+        s.update(float("nan"))
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, float("inf")])
+    assert s.n_values == 100
+
+
+def test_seed() -> None:
+    data = make_data(10_000)
+    a = KLLSketch(seed=7)
+    b = KLLSketch(seed=7)
+    a.batch_update(data)
+    b.batch_update(data)
+    assert a == b
+    c = KLLSketch(seed=8)
+    c.batch_update(data)
+    assert c != a
+
+
+def test_merge() -> None:
+    data = make_data(50_000)
+    a = KLLSketch(k=100, seed=1)
+    a.batch_update(data[:20_000])
+    b = KLLSketch(seed=2)
+    b.batch_update(data[20_000:])
+    merged = a + b
+    assert merged.k == 100
+    assert merged.n_values == 50_000
+    data.sort()
+    rank = bisect.bisect_right(data, merged.median()) / len(data)
+    assert abs(rank - 0.5) <= merged.rank_error
+    a += b
+    assert a == merged
+    a.merge_inplace(KLLSketch())
+    assert a == merged
+
+
+def test_serialization() -> None:
+    s = KLLSketch(k=50, seed=3)
+    s.batch_update(make_data(5000))
+    assert KLLSketch.from_bytes(s.to_bytes()) == s
+    assert KLLSketch.from_dict(s.to_dict()) == s
+    assert pickle.loads(pickle.dumps(s)) == s
+    assert copy.deepcopy(s) == s
+    restored = KLLSketch.from_bytes(s.to_bytes())
+    s.batch_update(range(100))
+    restored.batch_update(range(100))
+    assert restored == s
+    empty = KLLSketch(seed=0)
+    assert KLLSketch.from_bytes(empty.to_bytes()) == empty
+    with pytest.raises(ValueError):
+        KLLSketch.from_bytes(s.to_bytes()[:-8])
+    bad = s.to_dict()
+    bad["n_values"] += 1
+    with pytest.raises(ValueError):
+        KLLSketch.from_dict(bad)
@@ -0,0 +1,87 @@
+import pytest
+import subprocess
+import sys
+from pathlib import Path
+from fastdigest import MappedTDigest, TDigest
+
+
+def test_persistence(tmp_path: Path) -> None:
+    path = tmp_path / "latency.td"
+    values = [float(i % 97) for i in range(5000)]
+    with MappedTDigest(path, max_centroids=100) as d:
+        assert d.is_empty()
+        assert d.path == path
+        assert d.mode == "w"
+        assert d.sync == "update"
+        d.batch_update(values[:4000])
+        for x in values[4000:]:
+            d.update(x)
+        expected = TDigest.from_values(values, max_centroids=100)
+        assert d.n_values == 5000
+        assert d.median() == pytest.approx(expected.median(), abs=1.0)
+    assert d.closed
+    reopened = MappedTDigest(path)
+    assert reopened.max_centroids == 100
+    assert reopened.n_values == 5000
+    assert reopened.min() == 0.0
+    assert reopened.max() == 96.0
+    assert reopened.mean() == pytest.approx(sum(values) / len(values))
+    assert reopened.snapshot().n_values == 5000
+    reopened.update(1000.0, 2.0)
+    reopened.close()
+    reopened.close()
+    assert MappedTDigest(path, mode="r").max() == 1000.0
+
+
+def test_reader(tmp_path: Path) -> None:
+    path = tmp_path / "shared.td"
+    writer = MappedTDigest(path, sync="manual")
+    reader = MappedTDigest(path, mode="r")
+    writer.batch_update([1.0, 2.0, 3.0])
+    assert reader.is_empty()
+    writer.flush()
+    assert reader.n_values == 3
+    assert reader.median() == 2.0
+    # a reader in another process sees the same state
+    code = (
+        "import sys\n"
+        "from fastdigest import MappedTDigest\n"
+        "print(MappedTDigest(sys.argv[1], mode='r').n_values)\n"
+    )
+    out = subprocess.run(
+        [sys.executable, "-c", code, str(path)],
+        capture_output=True, text=True, check=True,
+    )
+    assert out.stdout.strip() == "3"
+    with pytest.raises(ValueError, match="read-only"):
+        reader.update(1.0)
+    writer.close()
+    reader.close()
+
+
+def test_errors(tmp_path: Path) -> None:
+    path = tmp_path / "digest.td"
+    with pytest.raises(ValueError):
+        MappedTDigest(path, mode="a")
+    with pytest.raises(ValueError):
+        MappedTDigest(path, sync="never")
+    with pytest.raises(OSError):
+        MappedTDigest(path, mode="r")
+    # the file has room for max_centroids centroids, so 0 is not allowed
+    with pytest.raises(ValueError, match="max_centroids"):
+        MappedTDigest(path, max_centroids=0)
+    assert not path.exists()
+    MappedTDigest(path, max_centroids=50).close()
+    with pytest.raises(ValueError, match="max_centroids"):
+        MappedTDigest(path, max_centroids=60)
+    d = MappedTDigest(path)
+    assert d.max_centroids == 50
+    d.close()
+    with pytest.raises(ValueError, match="closed"):
+        d.update(1.0)
+    with pytest.raises(ValueError, match="closed"):
+        d.median()
+    bad = tmp_path / "bad.td"
+    bad.write_bytes(b"not a digest" * 100)
+    with pytest.raises(ValueError):
+        MappedTDigest(bad)
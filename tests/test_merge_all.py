@@ -1,4 +1,8 @@
 import pytest
+import random
+import subprocess
+import sys
+from typing import Optional
 from fastdigest import TDigest, merge_all
 from utils import calculate_sample_quantiles, check_sample_quantiles
 
@@ -42,3 +46,119 @@ def test_merge_all() -> None:
         merge_all(empty_digests, max_centroids=10.0)
     with pytest.raises(ValueError):
         merge_all(empty_digests, max_centroids=-1)
+
+
+def test_merge_all_static() -> None:
+    digests = [TDigest.from_values(range(i, i + 10)) for i in range(1, 100, 10)]
+    merged = TDigest.merge_all(digests)
+    assert merged == merge_all(digests)
+    assert merged.n_values == 100
+    merged = TDigest.merge_all(iter(digests), max_centroids=10)
+    assert merged.max_centroids == 10
+    assert TDigest.merge_all([]) == TDigest()
+    with pytest.raises(TypeError):
+        TDigest.merge_all([1, 2])
+
+
+@pytest.mark.parametrize("n_threads", [2, 3, 8, None])
+def test_merge_all_parallel(n_threads: Optional[int]) -> None:
+    values = list(range(1, 1001))
+    digests = [TDigest.from_values(values[i::50]) for i in range(50)]
+    digests.append(TDigest())
+    merged = merge_all(digests, n_threads=n_threads)
+    expected = calculate_sample_quantiles(values)
+    check_sample_quantiles(merged, expected)
+    assert merged.n_values == 1000
+    assert merged.min() == 1 and merged.max() == 1000
+    serial = merge_all(digests, max_centroids=20)
+    parallel = merge_all(digests, max_centroids=20, n_threads=n_threads)
+    assert parallel.max_centroids == 20
+    assert parallel.n_centroids <= 21
+    assert parallel.mass() == serial.mass()
+    parallel = TDigest.merge_all(digests, n_threads=n_threads)
+    assert parallel.n_values == 1000
+
+
+@pytest.mark.parametrize("n_threads", [1, 3])
+def test_merge_all_deterministic(n_threads: int) -> None:
+    rng = random.Random(42)
+    digests = [
+        TDigest.from_values(
+            [rng.choice([1.0, 2.0, rng.random()]) for _ in range(200)],
+            max_centroids=20,
+        )
+        for _ in range(12)
+    ]
+    expected = merge_all(digests, max_centroids=30, deterministic=True)
+    for _ in range(5):
+        rng.shuffle(digests)
+        merged = merge_all(
+            digests, max_centroids=30, n_threads=n_threads, deterministic=True
+        )
+        assert merged.to_bytes() == expected.to_bytes()
+        merged = TDigest.merge_all(iter(digests), deterministic=True)
+        assert merged.n_values == expected.n_values
+    a, b = digests[:2]
+    assert (a + b).to_bytes() == (b + a).to_bytes()
+
+
+def test_merge_all_n_threads_validation() -> None:
+    digests = [TDigest.from_values([1, 2]), TDigest.from_values([3])]
+    assert merge_all(digests, n_threads=64).n_values == 3
+    with pytest.raises(ValueError):
+        merge_all(digests, n_threads=0)
+    with pytest.raises(ValueError):
+        merge_all(digests, n_threads=-2)
+
+
+def test_merge_all_streaming() -> None:
+    rng = random.Random(7)
+    digests = [
+        TDigest.from_values(
+            [rng.choice([1.0, 2.0, rng.random()]) for _ in range(100)],
+            max_centroids=20,
+        )
+        for _ in range(40)
+    ]
+    digests.append(TDigest())
+    for max_c in (None, 15):
+        expected = merge_all(digests, max_centroids=max_c)
+        merged = merge_all(digests, max_centroids=max_c, streaming=True)
+        assert merged.to_bytes() == expected.to_bytes()
+    expected = merge_all(digests, deterministic=True)
+    rng.shuffle(digests)
+    merged = TDigest.merge_all(
+        iter(digests), deterministic=True, streaming=True
+    )
+    assert merged.to_bytes() == expected.to_bytes()
+    a = digests[0]
+    assert merge_all([a, a], streaming=True) == merge_all([a, a])
+    assert merge_all([], streaming=True) == TDigest()
+    with pytest.raises(TypeError):
+        merge_all([a, 1], streaming=True)
+
+
+def test_merge_all_streaming_with_queries() -> None:
+    # another thread querying an input digest must not deadlock the merge;
+    # run in a subprocess, since a deadlock would hang the interpreter
+    code = (
+        "import threading\n"
+        "from fastdigest import TDigest, merge_all\n"
+        "ds = [TDigest.from_values(range(i, i + 1000)) for i in range(300)]\n"
+        "done = threading.Event()\n"
+        "def query():\n"
+        "    while not done.is_set():\n"
+        "        ds[-1].quantile(0.5)\n"
+        "thread = threading.Thread(target=query)\n"
+        "thread.start()\n"
+        "for _ in range(20):\n"
+        "    merged = merge_all(ds, streaming=True)\n"
+        "done.set()\n"
+        "thread.join()\n"
+        "print(merged.n_values)\n"
+    )
+    out = subprocess.run(
+        [sys.executable, "-c", code],
+        capture_output=True, text=True, check=True, timeout=120,
+    )
+    assert out.stdout.strip() == "300000"
@@ -0,0 +1,118 @@
+import copy
+import math
+import pickle
+import pytest
+import random
+from fastdigest import MomentsSketch
+
+
+def exact_moments(data: list) -> tuple:
+    n = len(data)
+    mean = sum(data) / n
+    m2, m3, m4 = (sum((x - mean) ** k for x in data) / n for k in (2, 3, 4))
+    return mean, m2, m3 / m2**1.5, m4 / m2**2 - 3
+
+
+def test_init() -> None:
+    m = MomentsSketch()
+    assert m.n_values == 0
+    assert m.is_empty()
+    assert m.mass() == 0.0
+    assert m.sum() == 0.0
+    assert repr(m) == "MomentsSketch(n_values=0)"
+    with pytest.raises(ValueError):
+        m.mean()
+    with pytest.raises(ValueError):
+        m.skew()
+
+
+def test_moments() -> None:
+    rng = random.Random(0)
+    # a large offset makes naive power sums lose all precision
+    data = [1e8 + rng.expovariate(1.0) for _ in range(20_000)]
+    m = MomentsSketch()
+    for x in data[:100]:
+        m.update(x)
+    m.batch_update(data[100:])
+    mean, var, skew, kurtosis = exact_moments(data)
+    assert m.n_values == len(data)
+    assert m.mean() == pytest.approx(mean, rel=1e-12)
+    assert m.var() == pytest.approx(var, rel=1e-6)
+    assert m.std() == pytest.approx(math.sqrt(var), rel=1e-6)
+    assert m.skew() == pytest.approx(skew, rel=1e-6)
+    assert m.kurtosis() == pytest.approx(kurtosis, rel=1e-6)
+    assert m.kurtosis(fisher=False) == pytest.approx(kurtosis + 3, rel=1e-6)
+    assert m.min() == min(data)
+    assert m.max() == max(data)
+    assert m.sum() == pytest.approx(sum(data), rel=1e-12)
+    assert repr(m) == "MomentsSketch(n_values=20000)"
+
+
+def test_weights() -> None:
+    weighted = MomentsSketch()
+    weighted.batch_update([1.0, 2.0, 3.0], w=[1.0, 2.0, 1.0])
+    repeated = MomentsSketch()
+    repeated.batch_update([1.0, 2.0, 2.0, 3.0])
+    assert weighted.mass() == 4.0
+    assert weighted.n_values == 3
+    assert weighted.var() == repeated.var() == 0.5
+    assert weighted.kurtosis() == pytest.approx(repeated.kurtosis())
+    single = MomentsSketch()
+    single.update(1.0)
+    single.update(2.0, w=2.0)
+    single.update(3.0)
+    assert single.var() == pytest.approx(0.5)
+    constant = MomentsSketch()
+    constant.batch_update([5.0, 5.0])
+    assert constant.var() == 0.0
+    assert math.isnan(constant.skew())
+    with pytest.raises(ValueError):
+        single.update(1.0, w=0.0)
+    with pytest.raises(ValueError):
+        single.batch_update([1.0, float("nan")])
+
+
+def test_merge() -> None:
+    rng = random.Random(1)
+    data = [rng.gauss(10.0, 3.0) for _ in range(10_000)]
+    parts = [MomentsSketch() for _ in range(7)]
+    for i, x in enumerate(data):
+        parts[i % 7].update(x)
+    merged = MomentsSketch()
+    for part in parts:
+        merged += part
+    mean, var, skew, kurtosis = exact_moments(data)
+    assert merged.n_values == len(data)
+    assert merged.mean() == pytest.approx(mean, rel=1e-12)
+    assert merged.var() == pytest.approx(var, rel=1e-9)
+    assert merged.skew() == pytest.approx(skew, rel=1e-6, abs=1e-9)
+    assert merged.kurtosis() == pytest.approx(kurtosis, rel=1e-6, abs=1e-9)
+    combined = parts[0] + parts[1]
+    assert combined.n_values == parts[0].n_values + parts[1].n_values
+    assert MomentsSketch() + parts[0] == parts[0]
+
+
+def test_serialization() -> None:
+    m = MomentsSketch()
+    m.batch_update([1.0, 4.0, 9.0, 16.0])
+    assert MomentsSketch.from_bytes(m.to_bytes()) == m
+    assert MomentsSketch.from_dict(m.to_dict()) == m
+    assert pickle.loads(pickle.dumps(m)) == m
+    assert copy.deepcopy(m) == m
+    empty = MomentsSketch()
+    assert MomentsSketch.from_bytes(empty.to_bytes()) == empty
+    with pytest.raises(ValueError):
+        MomentsSketch.from_bytes(m.to_bytes()[:-1])
+    bad = m.to_dict()
+    bad["m2"] = -1.0
+    with pytest.raises(ValueError):
+        MomentsSketch.from_dict(bad)
+    # a single value cannot span the range from min to max
+    bad = m.to_dict()
+    bad["n_values"] = 1
+    with pytest.raises(ValueError):
+        MomentsSketch.from_dict(bad)
+    huge = m.to_dict()
+    huge["n_values"] = 2**64 - 1
+    restored = MomentsSketch.from_dict(huge)
+    assert (restored + restored).n_values == 2**64 - 1
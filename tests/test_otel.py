@@ -0,0 +1,99 @@
+import math
+import pytest
+from fastdigest import TDigest
+from fastdigest.errors import SerializationError
+from utils import SAMPLE_QUANTILES, calculate_sample_quantiles, compare_values
+
+
+def test_to_otel_exponential_histogram() -> None:
+    values = [-4.0, -1.0, 0.0, 0.5, 1.0, 2.0, 3.0, 100.0]
+    d = TDigest.from_values(values)
+    hist = d.to_otel_exponential_histogram(scale=0)
+    assert hist["scale"] == 0
+    assert hist["count"] == len(values)
+    assert hist["sum"] == sum(values)
+    assert hist["min"] == -4.0
+    assert hist["max"] == 100.0
+    assert hist["zero_count"] == 1
+    # scale 0 -> base 2, bucket i covers (2^i, 2^(i+1)]
+    assert hist["positive"]["offset"] == -2
+    assert hist["positive"]["bucket_counts"] == [1, 1, 1, 1, 0, 0, 0, 0, 1]
+    assert hist["negative"]["offset"] == -1
+    assert hist["negative"]["bucket_counts"] == [1, 0, 1]
+    total = (
+        hist["zero_count"]
+        + sum(hist["positive"]["bucket_counts"])
+        + sum(hist["negative"]["bucket_counts"])
+    )
+    assert total == hist["count"]
+
+    hist = TDigest.from_values(range(1, 1001)).to_otel_exponential_histogram(
+        scale=20, max_size=16
+    )
+    assert hist["scale"] < 20
+    assert len(hist["positive"]["bucket_counts"]) <= 16
+    assert sum(hist["positive"]["bucket_counts"]) == 1000
+
+    empty = TDigest().to_otel_exponential_histogram(scale=5)
+    assert empty["count"] == 0
+    assert empty["positive"]["bucket_counts"] == []
+    with pytest.raises(ValueError):
+        d.to_otel_exponential_histogram(scale=21)
+    with pytest.raises(ValueError):
+        d.to_otel_exponential_histogram(scale=0, max_size=0)
+    with pytest.raises(ValueError):
+        d.to_otel_exponential_histogram(scale=0, zero_threshold=-1.0)
+
+
+def test_from_otel_exponential_histogram() -> None:
+    data = list(range(1, 1001))
+    d = TDigest.from_values(data)
+    hist = d.to_otel_exponential_histogram(scale=8)
+    restored = TDigest.from_otel_exponential_histogram(hist)
+    assert restored.n_values == d.n_values
+    assert restored.mass() == d.mass()
+    assert restored.sum() == d.sum()
+    assert restored.min() == d.min()
+    assert restored.max() == d.max()
+    expected = calculate_sample_quantiles(data)
+    estimated = [restored.quantile(q) for q in SAMPLE_QUANTILES]
+    compare_values("quantile", SAMPLE_QUANTILES, expected, estimated, rtol=0.02)
+
+    minimal = {
+        "scale": 0,
+        "zero_count": 2,
+        "positive": {"offset": 0, "bucket_counts": [1, 1]},
+    }
+    restored = TDigest.from_otel_exponential_histogram(minimal, max_centroids=5)
+    assert restored.max_centroids == 5
+    assert restored.n_values == 4
+    assert restored.centroids == [(0.0, 2.0), (1.5, 1.0), (3.0, 1.0)]
+    assert math.isclose(restored.sum(), 4.5)
+
+    assert TDigest.from_otel_exponential_histogram({"scale": 3}) == TDigest()
+    with pytest.raises(SerializationError):
+        TDigest.from_otel_exponential_histogram({"zero_count": 1})
+    with pytest.raises(ValueError):
+        TDigest.from_otel_exponential_histogram({"scale": -11})
+
+
+def test_from_otel_exponential_histogram_validation() -> None:
+    valid = {
+        "scale": 0,
+        "count": 2,
+        "min": 1.0,
+        "max": 4.0,
+        "positive": {"offset": 0, "bucket_counts": [1, 1]},
+    }
+    assert TDigest.from_otel_exponential_histogram(valid).n_values == 2
+    invalid = [
+        {"positive": {"offset": 10**6, "bucket_counts": [1]}, "scale": -10},
+        {"count": 3},
+        {"count": 1},
+        {"min": 5.0},
+        {"max": float("inf")},
+        {"zero_threshold": -1.0},
+    ]
+    for changes in invalid:
+        with pytest.raises(SerializationError):
+            TDigest.from_otel_exponential_histogram({**valid, **changes})
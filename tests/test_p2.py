@@ -0,0 +1,67 @@
+import copy
+import pickle
+import pytest
+import random
+from fastdigest import P2Quantile
+
+
+def test_init() -> None:
+    p = P2Quantile(0.95)
+    assert p.q == 0.95
+    assert p.n_values == 0
+    assert p.is_empty()
+    assert repr(p) == "P2Quantile(q=0.95)"
+    with pytest.raises(ValueError):
+        p.estimate()
+    for bad_q in [0.0, 1.0, 1.5]:
+        with pytest.raises(ValueError):
+            P2Quantile(bad_q)
+
+
+def test_few_values() -> None:
+    p = P2Quantile(0.5)
+    p.batch_update([3.0, 1.0, 2.0])
+    assert p.estimate() == 2.0
+    assert p.min() == 1.0
+    assert p.max() == 3.0
+    p.update(10.0)
+    p.update(-5.0)
+    assert p.n_values == 5
+    assert p.estimate() == 2.0
+    assert p.min() == -5.0
+    assert p.max() == 10.0
+
+
+@pytest.mark.parametrize("q", [0.1, 0.5, 0.95, 0.99])
+def test_estimate(q: float) -> None:
+    rng = random.Random(1)
+    data = [rng.expovariate(1.0) for _ in range(50_000)]
+    p = P2Quantile(q)
+    for x in data[:1000]:
+        p.update(x)
+    p.batch_update(data[1000:])
+    assert p.n_values == len(data)
+    assert p.min() == min(data)
+    assert p.max() == max(data)
+    expected = sorted(data)[int(q * len(data))]
+    assert p.estimate() == pytest.approx(expected, rel=0.01)
+    with pytest.raises(ValueError):
+        p.update(float("nan"))
+
+
+def test_serialization() -> None:
+    p = P2Quantile(0.9)
+    p.batch_update(range(1000))
+    assert P2Quantile.from_bytes(p.to_bytes()) == p
+    assert P2Quantile.from_dict(p.to_dict()) == p
+    assert pickle.loads(pickle.dumps(p)) == p
+    assert copy.deepcopy(p) == p
+    c = p.copy()
+    c.update(5000.0)
+    assert c != p
+    with pytest.raises(ValueError):
+        P2Quantile.from_bytes(p.to_bytes()[:-1])
+    bad = p.to_dict()
+    bad["positions"] = [5.0, 4.0, 3.0, 2.0, 1.0]
+    with pytest.raises(ValueError):
+        P2Quantile.from_dict(bad)
@@ -0,0 +1,82 @@
+import math
+import pytest
+import random
+import fastdigest.pandas as fd_pandas
+from fastdigest import TDigest
+
+
+def sample_frame(pd, n: int, seed: int = 0):
+    rng = random.Random(seed)
+    keys = [rng.choice(["a", "b", "c"]) for _ in range(n)]
+    values = [rng.gauss(0.0, 1.0) for _ in range(n)]
+    return pd.DataFrame({"key": keys, "x": values})
+
+
+def expected_digests(df) -> dict:
+    return {
+        key: TDigest.from_values(list(group))
+        for key, group in df.groupby("key")["x"]
+    }
+
+
+def test_tdigest_agg() -> None:
+    pd = pytest.importorskip("pandas")
+    df = sample_frame(pd, 3000)
+    digests = df.groupby("key")["x"].agg(fd_pandas.tdigest_agg)
+    assert dict(digests) == expected_digests(df)
+    digest = fd_pandas.tdigest_agg(pd.Series([1.0, None, 2.0]), 5)
+    assert digest == TDigest.from_values([1.0, 2.0], max_centroids=5)
+
+
+def test_tdigest_groupby() -> None:
+    pd = pytest.importorskip("pandas")
+    df = sample_frame(pd, 10_000)
+    grouped = df.groupby("key")["x"]
+    digests = fd_pandas.tdigest_groupby(grouped)
+    assert list(digests.index) == ["a", "b", "c"]
+    assert digests.name == "x"
+    assert dict(digests) == expected_digests(df)
+    threaded = fd_pandas.tdigest_groupby(grouped, n_threads=2)
+    assert dict(threaded) == dict(digests)
+    small = fd_pandas.tdigest_groupby(grouped, max_centroids=10)
+    assert small["a"].max_centroids == 10
+    with pytest.raises(TypeError):
+        fd_pandas.tdigest_groupby(df.groupby("key"))
+    with pytest.raises(ValueError):
+        fd_pandas.tdigest_groupby(grouped, max_centroids=-1)
+
+
+def test_tdigest_groupby_quantiles() -> None:
+    pd = pytest.importorskip("pandas")
+    df = sample_frame(pd, 5000)
+    result = fd_pandas.tdigest_groupby(
+        df.groupby("key")["x"], quantiles=[0.5, 0.99]
+    )
+    assert list(result.columns) == [0.5, 0.99]
+    for key, digest in expected_digests(df).items():
+        assert result.loc[key, 0.5] == digest.quantile(0.5)
+        assert result.loc[key, 0.99] == digest.quantile(0.99)
+    with pytest.raises(ValueError):
+        fd_pandas.tdigest_groupby(df.groupby("key")["x"], quantiles=[1.5])
+
+
+def test_tdigest_groupby_missing() -> None:
+    pd = pytest.importorskip("pandas")
+    df = pd.DataFrame(
+        {
+            "key": ["a", None, "a", "b", "c"],
+            "x": [1.0, 2.0, None, 3.0, None],
+        }
+    )
+    digests = fd_pandas.tdigest_groupby(df.groupby("key")["x"])
+    assert list(digests.index) == ["a", "b", "c"]
+    assert digests["a"] == TDigest.from_values([1.0])
+    assert digests["c"].is_empty()
+    quantiles = fd_pandas.tdigest_groupby(
+        df.groupby("key")["x"], quantiles=[0.5]
+    )
+    assert math.isnan(quantiles.loc["c", 0.5])
+    with pytest.raises(ValueError):
+        fd_pandas.tdigest_groupby(
+            pd.Series([1.0, math.inf]).groupby([0, 1])
+        )
@@ -0,0 +1,61 @@
+import pickle
+import pytest
+import fastdigest.polars as fd_polars
+from fastdigest import TDigest
+
+
+def test_polars_tdigest() -> None:
+    pl = pytest.importorskip("polars")
+    df = pl.DataFrame(
+        {
+            "key": ["a", "b", "a", "b", "a", "c"],
+            "x": [1.0, 10.0, 2.0, None, 3.0, None],
+        }
+    )
+    digest = df.select(fd_polars.tdigest("x")).item()
+    assert TDigest.from_bytes(digest) == TDigest.from_values(
+        [1.0, 10.0, 2.0, 3.0]
+    )
+    result = (
+        df.lazy()
+        .group_by("key")
+        .agg(fd_polars.tdigest("x", max_centroids=5).alias("digest"))
+        .sort("key")
+        .collect()
+    )
+    digests = [TDigest.from_bytes(blob) for blob in result["digest"]]
+    assert digests[0] == TDigest.from_values([1.0, 2.0, 3.0], max_centroids=5)
+    assert digests[1] == TDigest.from_values([10.0], max_centroids=5)
+    assert digests[2].is_empty()
+    with pytest.raises(ValueError):
+        fd_polars.tdigest("x", max_centroids=-1)
+
+
+def test_polars_merge_quantile() -> None:
+    pl = pytest.importorskip("polars")
+    df = pl.DataFrame(
+        {
+            "day": [1, 1, 2, 2, 2],
+            "key": ["a", "b", "a", "a", "b"],
+            "x": [1.0, 5.0, 2.0, 3.0, 7.0],
+        }
+    )
+    daily = df.group_by("day", "key").agg(
+        fd_polars.tdigest(pl.col("x")).alias("digest")
+    )
+    merged = (
+        daily.group_by("key")
+        .agg(fd_polars.tdigest_merge("digest").alias("digest"))
+        .sort("key")
+    )
+    assert TDigest.from_bytes(merged["digest"][0]) == TDigest.from_values(
+        [1.0, 2.0, 3.0]
+    )
+    medians = merged.select(fd_polars.tdigest_quantile("digest", 0.5))
+    assert medians.to_series().to_list() == [2.0, 6.0]
+    empty = pl.DataFrame({"digest": [None]}, schema={"digest": pl.Binary})
+    quantile = empty.select(fd_polars.tdigest_quantile("digest", 0.5))
+    assert quantile.item() is None
+    with pytest.raises(ValueError):
+        fd_polars.tdigest_quantile("digest", 1.5)
+    pickle.dumps(fd_polars.tdigest_quantile("digest", 0.5))
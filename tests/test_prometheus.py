@@ -0,0 +1,65 @@
+import pytest
+from fastdigest import TDigest
+
+
+def test_to_prometheus_summary() -> None:
+    d = TDigest.from_values(range(1, 101))
+    text = d.to_prometheus(
+        "latency_seconds",
+        quantiles=[0.5, 0.9],
+        labels={"path": '/a"b'},
+        help="Request latency",
+    )
+    assert text.splitlines() == [
+        "# HELP latency_seconds Request latency",
+        "# TYPE latency_seconds summary",
+        'latency_seconds{path="/a\\"b",quantile="0.5"} 50.5',
+        'latency_seconds{path="/a\\"b",quantile="0.9"} 90.1',
+        'latency_seconds_sum{path="/a\\"b"} 5050.0',
+        'latency_seconds_count{path="/a\\"b"} 100.0',
+    ]
+    default = d.to_prometheus("x")
+    assert 'x{quantile="0.99"}' in default
+    assert "# HELP" not in default
+    empty = TDigest().to_prometheus("x", quantiles=[0.5])
+    assert 'x{quantile="0.5"} NaN' in empty
+    assert "x_count 0.0" in empty
+
+
+def test_to_prometheus_histogram() -> None:
+    d = TDigest.from_values(range(1, 101))
+    text = d.to_prometheus("x", kind="histogram", buckets=[10, 50.5, 1000])
+    lines = text.splitlines()
+    assert lines[0] == "# TYPE x histogram"
+    assert lines[-3] == 'x_bucket{le="+Inf"} 100.0'
+    assert lines[-2] == "x_sum 5050.0"
+    assert lines[-1] == "x_count 100.0"
+    counts = [float(line.split()[-1]) for line in lines[1:-2]]
+    assert counts == sorted(counts)
+    assert counts[2] == 100.0
+    # an explicit +Inf bound is not repeated
+    text = d.to_prometheus("x", kind="histogram", buckets=[1, float("inf")])
+    assert text.count('le="+Inf"') == 1
+
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", kind="histogram")
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", kind="histogram", buckets=[2, 1])
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", buckets=[1, 2])
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", kind="gauge")
+    with pytest.raises(ValueError):
+        d.to_prometheus("1x")
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", labels={"bad-label": "v"})
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", quantiles=[1.5])
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", kind="histogram", buckets=[1], quantiles=[0.5])
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", labels={"quantile": "0.5"})
+    with pytest.raises(ValueError):
+        d.to_prometheus("x", kind="histogram", buckets=[1], labels={"le": "1"})
+    # the label of the other kind is free
+    assert 'le="1"' in d.to_prometheus("x", labels={"le": "1"})
@@ -0,0 +1,103 @@
+import fastdigest
+import pytest
+import threading
+from pathlib import Path
+from fastdigest import Registry, TDigest, load_many
+
+
+def test_module_registry() -> None:
+    assert isinstance(fastdigest.registry, Registry)
+    digest = fastdigest.registry.digest("test_module_registry")
+    assert fastdigest.registry["test_module_registry"] is digest
+    fastdigest.registry.unregister("test_module_registry")
+    assert "test_module_registry" not in fastdigest.registry
+
+
+def test_registration() -> None:
+    registry = Registry()
+    latency = registry.digest("latency_seconds", max_centroids=50)
+    assert registry.digest("latency_seconds") is latency
+    assert registry.digest("latency_seconds", max_centroids=50) is latency
+    with pytest.raises(ValueError, match="max_centroids=50"):
+        registry.digest("latency_seconds", max_centroids=100)
+    sizes = TDigest()
+    registry.register("size_bytes", sizes, help="Response size")
+    registry.register("size_bytes", sizes)
+    with pytest.raises(ValueError, match="already registered"):
+        registry.register("size_bytes", TDigest())
+    assert registry.names() == ["latency_seconds", "size_bytes"]
+    assert len(registry) == 2
+    assert registry.get("missing") is None
+    with pytest.raises(KeyError):
+        registry["missing"]
+    assert registry.unregister("latency_seconds") is latency
+    with pytest.raises(KeyError):
+        registry.unregister("latency_seconds")
+    assert registry.names() == ["size_bytes"]
+    assert registry["size_bytes"] is sizes
+    registry.clear()
+    assert len(registry) == 0
+
+
+def test_updates_and_snapshot() -> None:
+    registry = Registry()
+    registry.update("a", 1.0)
+    registry.batch_update("a", [2.0, 3.0])
+    registry.batch_update("b", [1.0, 5.0], w=2.0)
+    registry.digest("a").update(4.0)
+    snapshot = registry.snapshot()
+    assert list(snapshot) == ["a", "b"]
+    assert snapshot["a"].n_values == 4
+    assert snapshot["b"].mass() == 4.0
+    # the snapshot holds copies
+    registry.update("a", 100.0)
+    assert snapshot["a"].max() == 4.0
+    assert registry["a"].max() == 100.0
+
+
+def test_concurrent_updates() -> None:
+    registry = Registry()
+
+    def work() -> None:
+        for i in range(1000):
+            registry.update("shared", float(i))
+            if i % 100 == 0:
+                registry.snapshot()
+
+    threads = [threading.Thread(target=work) for _ in range(4)]
+    for t in threads:
+        t.start()
+    for t in threads:
+        t.join()
+    assert registry["shared"].n_values == 4000
+
+
+def test_export(tmp_path: Path) -> None:
+    registry = Registry()
+    registry.digest("latency_seconds", help="Request latency")
+    registry.batch_update("latency_seconds", range(1, 101))
+    registry.batch_update("size_bytes", [10.0, 20.0])
+    text = registry.to_prometheus(quantiles=[0.5], labels={"app": "x"})
+    assert text == (
+        registry["latency_seconds"].to_prometheus(
+            "latency_seconds",
+            quantiles=[0.5],
+            labels={"app": "x"},
+            help="Request latency",
+        )
+        + registry["size_bytes"].to_prometheus(
+            "size_bytes", quantiles=[0.5], labels={"app": "x"}
+        )
+    )
+    assert "# HELP latency_seconds Request latency" in text
+    assert "latency_seconds_count" in registry.to_prometheus(
+        kind="histogram", buckets=[50.0]
+    )
+    registry.update("not valid", 1.0)
+    with pytest.raises(ValueError):
+        registry.to_prometheus()
+    path = tmp_path / "metrics.tdf"
+    registry.save(path)
+    stored = load_many(path)
+    assert stored.keys() == registry.names()
+    assert stored["size_bytes"] == registry["size_bytes"]
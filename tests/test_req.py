@@ -0,0 +1,112 @@
+import bisect
+import copy
+import pickle
+import pytest
+from fastdigest import KLLSketch, REQSketch
+from utils import make_data
+
+
+def test_init() -> None:
+    s = REQSketch(k=10, seed=1)
+    assert s.k == 10
+    assert s.high_rank_accuracy
+    assert s.n_values == 0
+    assert s.is_empty()
+    assert repr(s) == "REQSketch(k=10, high_rank_accuracy=True)"
+    assert REQSketch().k == 12
+    r = repr(REQSketch(high_rank_accuracy=False))
+    assert r == "REQSketch(k=12, high_rank_accuracy=False)"
+    with pytest.raises(ValueError):
+        s.quantile(0.5)
+    for bad_k in [2, 11, 1026]:
+        with pytest.raises(ValueError):
+            REQSketch(k=bad_k)
+
+
+def test_high_ranks() -> None:
+    data = make_data(200_000, "expovariate")
+    s = REQSketch(seed=42)
+    s.batch_update(data)
+    kll = KLLSketch(seed=42)
+    kll.batch_update(data)
+    assert s.n_values == len(data)
+    assert s.quantile(1.0) == s.max() == max(data)
+    assert s.quantile(0.0) == s.min() == min(data)
+    data.sort()
+    n = len(data)
+    # the highest values are kept exactly
+    for i in range(1, 11):
+        assert s.quantile(1 - i / n) == data[-i - 1]
+    for q in [0.5, 0.9, 0.99, 0.999, 0.9999]:
+        rank = bisect.bisect_right(data, s.quantile(q)) / n
+        assert abs(rank - q) <= 4 * s.rank_error(q) + 2 / n
+        assert abs(s.rank(data[int(q * n)]) - q) <= 4 * s.rank_error(q) + 2 / n
+    kll_rank = bisect.bisect_right(data, kll.quantile(0.9999)) / n
+    rank = bisect.bisect_right(data, s.quantile(0.9999)) / n
+    assert abs(rank - 0.9999) < abs(kll_rank - 0.9999)
+    assert s.rank_error(0.99) < s.rank_error(0.5)
+    assert s.rank_error(1.0) == 0.0
+    assert s.quantile_vec([0.5, 0.99]) == [s.median(), s.percentile(99)]
+    assert s.rank_vec([1.0, 2.0]) == [s.rank(1.0), s.rank(2.0)]
+    with pytest.raises(ValueError):
+        s.rank_error(1.5)
+
+
+def test_low_ranks() -> None:
+    data = make_data(100_000, "expovariate")
+    s = REQSketch(high_rank_accuracy=False)
+    s.batch_update(data)
+    data.sort()
+    for i in range(10):
+        assert s.quantile((i + 1) / len(data)) == data[i]
+    assert s.rank_error(0.0) == 0.0
+    assert s.rank_error(0.01) < s.rank_error(0.5)
+
+
+def test_updates() -> None:
+    s = REQSketch()
+    for x in range(1, 11):
+        s.update(x)
+    assert s.n_values == s.n_retained == 10
+    assert s.median() == 5
+    with pytest.raises(ValueError):
+        s.update(float("nan"))
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, float("-inf")])
+
+
+def test_merge() -> None:
+    data = make_data(50_000, "expovariate")
+    a = REQSketch(seed=1)
+    a.batch_update(data[:10_000])
+    b = REQSketch(seed=2)
+    b.batch_update(data[10_000:])
+    merged = a + b
+    assert merged.n_values == 50_000
+    assert merged.max() == max(data)
+    data.sort()
+    rank = bisect.bisect_right(data, merged.quantile(0.999)) / len(data)
+    assert abs(rank - 0.999) <= 4 * merged.rank_error(0.999) + 1e-4
+    a += b
+    assert a == merged
+    with pytest.raises(ValueError):
+        a.merge(REQSketch(high_rank_accuracy=False))
+
+
+def test_serialization() -> None:
+    s = REQSketch(k=8, seed=3)
+    s.batch_update(make_data(20_000, "expovariate"))
+    assert REQSketch.from_bytes(s.to_bytes()) == s
+    assert REQSketch.from_dict(s.to_dict()) == s
+    assert pickle.loads(pickle.dumps(s)) == s
+    assert copy.deepcopy(s) == s
+    restored = REQSketch.from_bytes(s.to_bytes())
+    s.batch_update(range(1000))
+    restored.batch_update(range(1000))
+    assert restored == s
+    with pytest.raises(ValueError):
+        REQSketch.from_bytes(s.to_bytes()[:-1])
+    bad = s.to_dict()
+    bad["levels"][0]["items"].append(-1.0)
+    with pytest.raises(ValueError):
+        REQSketch.from_dict(bad)
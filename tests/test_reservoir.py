@@ -0,0 +1,104 @@
+import copy
+import pickle
+import pytest
+from collections import Counter
+from fastdigest import ReservoirSampler
+
+
+def test_init() -> None:
+    r = ReservoirSampler(10, seed=0)
+    assert r.k == 10
+    assert r.n_values == 0
+    assert len(r) == 0
+    assert r.is_empty()
+    assert r.to_list() == []
+    assert repr(r) == "ReservoirSampler(k=10)"
+    for k in (0, -1):
+        with pytest.raises(ValueError):
+            ReservoirSampler(k)
+
+
+def test_fill() -> None:
+    r = ReservoirSampler(10, seed=0)
+    r.batch_update([3.0, 1.0, 2.0])
+    assert r.to_list() == [3.0, 1.0, 2.0]
+    r.batch_update(range(100))
+    assert r.n_values == 103
+    assert len(r) == 10
+    with pytest.raises(ValueError):
+        r.update(float("nan"))
+
+
+def test_uniform() -> None:
+    counts = Counter()
+    for seed in range(2000):
+        r = ReservoirSampler(5, seed=seed)
+        r.batch_update(range(20))
+        counts.update(r.to_list())
+    assert set(counts) == set(range(20))
+    assert all(400 < c < 600 for c in counts.values())
+
+
+def test_seed() -> None:
+    a, b = ReservoirSampler(5, seed=1), ReservoirSampler(5, seed=1)
+    a.batch_update(range(100))
+    b.batch_update(range(100))
+    assert a == b
+    assert a.to_list() == b.to_list()
+
+
+def test_merge() -> None:
+    counts = Counter()
+    for seed in range(2000):
+        a = ReservoirSampler(5, seed=seed)
+        a.batch_update(range(30))
+        b = ReservoirSampler(8, seed=seed + 10_000)
+        b.batch_update(range(30, 40))
+        merged = a + b
+        assert merged.k == 5
+        assert merged.n_values == 40
+        assert len(merged) == 5
+        assert len(set(merged.to_list())) == 5
+        counts.update(merged.to_list())
+    assert all(150 < c < 350 for c in counts.values())
+
+
+def test_merge_small() -> None:
+    a, b = ReservoirSampler(5, seed=0), ReservoirSampler(5, seed=1)
+    a.batch_update([1.0, 2.0])
+    b.update(3.0)
+    a += b
+    assert sorted(a.to_list()) == [1.0, 2.0, 3.0]
+    a.merge_inplace(ReservoirSampler(5))
+    assert sorted(a.to_list()) == [1.0, 2.0, 3.0]
+
+
+def test_values() -> None:
+    np = pytest.importorskip("numpy")
+    r = ReservoirSampler(4, seed=0)
+    r.batch_update(range(10))
+    values = r.values()
+    assert values.dtype == np.float64
+    assert list(values) == r.to_list()
+
+
+def test_serialization() -> None:
+    r = ReservoirSampler(4, seed=0)
+    r.batch_update(range(10))
+    for restore in (
+        lambda r: ReservoirSampler.from_bytes(r.to_bytes()),
+        lambda r: ReservoirSampler.from_dict(r.to_dict()),
+        lambda r: pickle.loads(pickle.dumps(r)),
+        copy.deepcopy,
+    ):
+        restored = restore(r)
+        assert restored == r
+        restored.update(10.0)
+        r.update(10.0)
+        assert restored == r
+    d = r.to_dict()
+    d["sample"] = d["sample"][:-1]
+    with pytest.raises(ValueError):
+        ReservoirSampler.from_dict(d)
+    with pytest.raises(ValueError):
+        ReservoirSampler.from_bytes(r.to_bytes()[:-1])
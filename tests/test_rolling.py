@@ -0,0 +1,157 @@
+import copy
+import pickle
+import pytest
+import random
+import struct
+from fastdigest import RollingTDigest, TDigest
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    timestamps = sorted(rng.uniform(0.0, 300.0) for _ in range(n))
+    values = [rng.gauss(0.0, 1.0) for _ in range(n)]
+    return timestamps, values
+
+
+def test_init() -> None:
+    r = RollingTDigest(60.0)
+    assert r.window == 60.0
+    assert r.n_buckets == 10
+    assert r.max_centroids == 1000
+    assert r.now is None
+    assert r.n_values == 0
+    assert r.is_empty()
+    assert r.snapshot().is_empty()
+    assert repr(r) == (
+        "RollingTDigest(window=60.0, n_buckets=10, max_centroids=1000)"
+    )
+    for window in (0.0, -1.0, float("inf"), float("nan")):
+        with pytest.raises(ValueError):
+            RollingTDigest(window)
+    with pytest.raises(ValueError):
+        RollingTDigest(60.0, n_buckets=0)
+    with pytest.raises(ValueError):
+        RollingTDigest(60.0, max_centroids=-1)
+    with pytest.raises(ValueError):
+        r.median()
+
+
+def test_update() -> None:
+    r = RollingTDigest(60.0, n_buckets=6)
+    r.update(0.0, 1.0)
+    r.update(15.0, 2.0, w=2.0)
+    r.update(59.0, 3.0)
+    assert r.now == 59.0
+    assert r.n_values == 3
+    assert r.snapshot().mass() == 4.0
+    # the bucket [0, 10) leaves the window
+    r.update(60.0, 4.0)
+    assert r.n_values == 3
+    assert r.snapshot().mass() == 4.0
+    assert r.snapshot().min() == 2.0
+    r.advance(119.0)
+    assert r.n_values == 1
+    assert r.median() == 4.0
+    r.advance(500.0)
+    assert r.is_empty()
+    with pytest.raises(ValueError):
+        r.update(499.0, 1.0)
+    with pytest.raises(ValueError):
+        r.advance(float("nan"))
+    with pytest.raises(ValueError):
+        r.update(500.0, float("nan"))
+    with pytest.raises(ValueError):
+        r.update(500.0, 1.0, w=0.0)
+
+
+def test_batch_update() -> None:
+    timestamps, values = sample_data(10_000)
+    r = RollingTDigest(60.0)
+    r.batch_update(timestamps, values)
+    assert r.now == timestamps[-1]
+    assert r.n_values == sum(t >= 240.0 for t in timestamps)
+    recent = [x for t, x in zip(timestamps, values) if t >= 240.0]
+    expected = TDigest.from_values(recent)
+    assert abs(r.median() - expected.median()) < 0.05
+    assert abs(r.quantile(0.9) - expected.quantile(0.9)) < 0.05
+    assert r.percentile(50) == r.median()
+    assert 0.4 < r.cdf(0.0) < 0.6
+    single = RollingTDigest(60.0)
+    for t, x in zip(timestamps, values):
+        single.update(t, x)
+    assert single.n_values == r.n_values
+    n_values = r.n_values
+    latest = timestamps[-1]
+    r.batch_update([latest, latest], [1.0, 2.0], w=[1.0, 3.0])
+    assert r.n_values == n_values + 2
+    with pytest.raises(ValueError):
+        r.batch_update([301.0, 300.5], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        r.batch_update([301.0], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        r.batch_update([301.0, 302.0], [1.0, float("inf")])
+    assert r.now == latest
+
+
+def test_merge() -> None:
+    timestamps, values = sample_data(4000)
+    a, b = RollingTDigest(60.0), RollingTDigest(60.0)
+    a.batch_update(timestamps[::2], values[::2])
+    b.batch_update(timestamps[1::2], values[1::2])
+    merged = a + b
+    assert merged.now == timestamps[-1]
+    assert merged.n_values == a.n_values + b.n_values
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 2 * merged.n_values
+    late = RollingTDigest(60.0)
+    late.update(1000.0, 1.0)
+    assert (merged + late).n_values == 1
+    with pytest.raises(ValueError):
+        a.merge(RollingTDigest(30.0))
+    with pytest.raises(ValueError):
+        a.merge(RollingTDigest(60.0, n_buckets=5))
+
+
+def test_serialization() -> None:
+    timestamps, values = sample_data(3000)
+    r = RollingTDigest(60.0, n_buckets=4, max_centroids=100)
+    r.batch_update(timestamps, values)
+    r.update(300.0, 10.0)
+    for restore in (
+        lambda r: RollingTDigest.from_bytes(r.to_bytes()),
+        lambda r: RollingTDigest.from_dict(r.to_dict()),
+        lambda r: pickle.loads(pickle.dumps(r)),
+        copy.deepcopy,
+    ):
+        restored = restore(r)
+        assert restored == r
+        assert restored.snapshot().max() == 10.0
+    empty = RollingTDigest(1.0)
+    assert RollingTDigest.from_bytes(empty.to_bytes()) == empty
+    d = r.to_dict()
+    assert d["now"] == 300.0
+    assert d["epochs"] == [17, 18, 19, 20]
+    assert d["digests"][0]["max_centroids"] == 100
+    d["epochs"][0] = 10
+    with pytest.raises(ValueError):
+        RollingTDigest.from_dict(d)
+    data = r.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            RollingTDigest.from_bytes(bad)
+    # bucket digests are checked strictly
+    single = RollingTDigest(60.0)
+    single.update(0.0, 49.0)
+    d = single.to_dict()
+    d["digests"][0]["centroids"] = [{"m": 49.0, "c": 0}]
+    with pytest.raises(ValueError):
+        RollingTDigest.from_dict(d)
+    data = single.to_bytes()
+    centroid = struct.pack("<dd", 49.0, 1.0)
+    assert data.count(centroid) == 1
+    with pytest.raises(ValueError):
+        RollingTDigest.from_bytes(
+            data.replace(centroid, struct.pack("<dd", 49.0, 0.0))
+        )
@@ -0,0 +1,134 @@
+import math
+import pickle
+import pytest
+import random
+from fastdigest import TDigest
+from fastdigest.sklearn import QuantileBinner, QuantileTransformer
+
+
+def sample_matrix(n: int, seed: int = 0) -> list:
+    rng = random.Random(seed)
+    return [
+        [rng.gauss(0.0, 1.0), rng.expovariate(1.0), float(i % 7)]
+        for i in range(n)
+    ]
+
+
+def test_params() -> None:
+    t = QuantileTransformer()
+    assert t.get_params() == {
+        "output_distribution": "uniform",
+        "max_centroids": 1000,
+    }
+    assert repr(t) == (
+        "QuantileTransformer(output_distribution='uniform', "
+        "max_centroids=1000)"
+    )
+    assert t.set_params(output_distribution="normal") is t
+    assert t.get_params(deep=False)["output_distribution"] == "normal"
+    b = QuantileBinner(10, max_centroids=50)
+    assert b.get_params() == {"n_bins": 10, "max_centroids": 50}
+    assert repr(b) == "QuantileBinner(n_bins=10, max_centroids=50)"
+    assert not t.__sklearn_is_fitted__()
+    assert not hasattr(b, "n_features_in_")
+    for bad in (
+        {"output_distribution": "cauchy"},
+        {"max_centroids": -1},
+        {"n_bins": 5},
+    ):
+        with pytest.raises(ValueError):
+            t.set_params(**bad)
+    for n_bins in (0, 1, 2.5):
+        with pytest.raises(ValueError):
+            QuantileBinner(n_bins)
+
+
+def test_quantile_transformer() -> None:
+    pytest.importorskip("numpy")
+    X = sample_matrix(5000)
+    X[0][1] = float("nan")
+    t = QuantileTransformer().fit(X)
+    assert t.n_features_in_ == 3
+    assert [d.n_values for d in t.digests_] == [5000, 4999, 5000]
+    Y = t.transform(X)
+    assert Y.shape == (5000, 3)
+    expected = TDigest.from_values([row[0] for row in X])
+    assert Y[1, 0] == expected.cdf(X[1][0])
+    assert math.isnan(Y[0, 1])
+    assert Y.min() >= 0.0 and Y.max() <= 1.0
+    X_back = t.inverse_transform(Y)
+    assert X_back[1, 0] == pytest.approx(X[1][0], abs=1e-6)
+    normal = QuantileTransformer(output_distribution="normal")
+    Z = normal.fit_transform(X)
+    assert abs(Z[:, 0].mean()) < 0.05
+    assert abs(Z[:, 0].max()) < 5.3
+    assert normal.inverse_transform(Z)[1, 0] == pytest.approx(
+        X[1][0], abs=1e-3
+    )
+    with pytest.raises(ValueError):
+        t.transform([[1.0, 2.0]])
+    with pytest.raises(ValueError):
+        t.fit([1.0, 2.0])
+    with pytest.raises(ValueError):
+        QuantileTransformer().transform(X)
+
+
+def test_partial_fit() -> None:
+    pytest.importorskip("numpy")
+    X = sample_matrix(4000)
+    t = QuantileTransformer()
+    t.partial_fit(X[:2000]).partial_fit(X[2000:])
+    assert [d.n_values for d in t.digests_] == [4000] * 3
+    with pytest.raises(ValueError):
+        t.partial_fit([[1.0]])
+    with pytest.raises(ValueError):
+        t.partial_fit([[1.0, float("inf"), 1.0]])
+    assert t.digests_[0].n_values == 4000
+    t.fit(X[:100])
+    assert t.digests_[0].n_values == 100
+
+
+def test_quantile_binner() -> None:
+    pytest.importorskip("numpy")
+    X = sample_matrix(8000)
+    b = QuantileBinner(4).fit(X)
+    edges = b.bin_edges_
+    assert len(edges) == 3 and len(edges[0]) == 5
+    digest = b.digests_[0]
+    assert list(edges[0]) == [digest.quantile(i / 4) for i in range(5)]
+    bins = b.transform(X)
+    counts = [int((bins[:, 0] == i).sum()) for i in range(4)]
+    assert all(abs(count - 2000) < 40 for count in counts)
+    assert set(bins[:, 2].tolist()) <= {0.0, 1.0, 2.0, 3.0}
+    centers = b.inverse_transform([[0.0, 3.0, 1.0]])
+    assert centers[0, 0] == (edges[0][0] + edges[0][1]) / 2
+    assert centers[0, 1] == (edges[1][3] + edges[1][4]) / 2
+
+
+def test_pickle() -> None:
+    pytest.importorskip("numpy")
+    X = sample_matrix(1000)
+    for estimator in (
+        QuantileTransformer(output_distribution="normal", max_centroids=50),
+        QuantileBinner(3),
+    ):
+        restored = pickle.loads(pickle.dumps(estimator))
+        assert restored.get_params() == estimator.get_params()
+        assert not restored.__sklearn_is_fitted__()
+        estimator.fit(X)
+        restored = pickle.loads(pickle.dumps(estimator))
+        assert restored.get_params() == estimator.get_params()
+        assert (restored.transform(X) == estimator.transform(X)).all()
+
+
+def test_pipeline() -> None:
+    pytest.importorskip("sklearn")
+    from sklearn.base import clone
+    from sklearn.pipeline import make_pipeline
+
+    X = sample_matrix(1000)
+    t = QuantileTransformer(output_distribution="normal")
+    assert clone(t).get_params() == t.get_params()
+    pipeline = make_pipeline(QuantileBinner(3), QuantileTransformer())
+    Y = pipeline.fit_transform(X)
+    assert Y.shape == (1000, 3)
@@ -0,0 +1,95 @@
+import functools
+import pickle
+import struct
+import pytest
+import fastdigest.spark as fd_spark
+from fastdigest import TDigest
+from typing import List
+
+
+def test_spark_udf_bodies() -> None:
+    pd = pytest.importorskip("pandas")
+    values = pd.Series([1.0, None, 2.0, 3.0])
+    blob = fd_spark.build_series(values, pd.Series([5] * len(values)))
+    digest = TDigest.from_bytes(blob)
+    assert digest == TDigest.from_values([1.0, 2.0, 3.0], max_centroids=5)
+    merged = TDigest.from_bytes(fd_spark.merge_series(pd.Series([blob, blob])))
+    assert merged.n_values == 6
+    quantiles = fd_spark.quantile_series(
+        pd.Series([blob, None]), pd.Series([0.5, 0.5])
+    )
+    assert quantiles[0] == 2.0
+    assert quantiles.isna()[1]
+
+
+def test_spark_partition_bodies() -> None:
+    build = functools.partial(fd_spark.build_partition, max_centroids=10)
+    build = pickle.loads(pickle.dumps(build))
+    (blob1,) = build(iter([(1.0,), (None,), (2.0,)]))
+    (blob2,) = build(iter([]))
+    merged = TDigest.from_bytes(fd_spark.merge_pair(blob1, blob2))
+    assert merged == TDigest.from_values([1.0, 2.0], max_centroids=10)
+
+
+
+def quantile_summaries(
+    values: List[float], g: List[int], delta: List[int], wide: bool = True
+) -> bytes:
+    entry = ">dqq" if wide else ">dii"
+    data = struct.pack(">dqi", 0.01, sum(g), len(values))
+    for sample in zip(values, g, delta):
+        data += struct.pack(entry, *sample)
+    return data
+
+
+def test_from_spark_quantile_summaries() -> None:
+    values = [1.0, 2.0, 5.0, 7.0, 10.0]
+    g = [1, 2, 3, 0, 4]
+    for wide in (True, False):
+        data = quantile_summaries(values, g, [0, 1, 2, 0, 0], wide)
+        d = TDigest.from_spark_quantile_summaries(data)
+        assert d.n_values == 10
+        assert d.mass() == 10.0
+        assert d.min() == 1.0
+        assert d.max() == 10.0
+        assert d.sum() == 1.0 + 4.0 + 15.0 + 40.0
+        assert len(d) == 4
+    exact = quantile_summaries(
+        [float(i) for i in range(100)], [1] * 100, [0] * 100
+    )
+    d = TDigest.from_spark_quantile_summaries(exact, max_centroids=10)
+    assert d.max_centroids == 10
+    assert len(d) <= 10 + 1
+    assert d.n_values == 100
+    assert abs(d.median() - 49.5) < 2.0
+    d += TDigest.from_values(range(100, 200))
+    assert d.n_values == 200
+    empty = quantile_summaries([], [], [])
+    assert TDigest.from_spark_quantile_summaries(empty).n_values == 0
+    with pytest.raises(ValueError):
+        TDigest.from_spark_quantile_summaries(b"")
+    with pytest.raises(ValueError):
+        TDigest.from_spark_quantile_summaries(exact[:-1])
+    with pytest.raises(ValueError):
+        unsorted = quantile_summaries([2.0, 1.0], [1, 1], [0, 0])
+        TDigest.from_spark_quantile_summaries(unsorted)
+
+def test_spark_aggregations() -> None:
+    pytest.importorskip("pyspark")
+    from pyspark.sql import SparkSession
+
+    spark = SparkSession.builder.master("local[2]").getOrCreate()
+    df = spark.createDataFrame(
+        [(i % 2, float(i)) for i in range(1, 101)], ["g", "v"]
+    )
+    rows = (
+        df.groupBy("g")
+        .agg(fd_spark.tdigest_agg("v").alias("d"))
+        .select("g", fd_spark.tdigest_quantile("d", 0.5).alias("median"))
+        .orderBy("g")
+        .collect()
+    )
+    assert [(r.g, r.median) for r in rows] == [(0, 51.0), (1, 50.0)]
+    digest = fd_spark.tdigest(df.repartition(4), "v")
+    assert digest.n_values == 100
+    assert digest.median() == 50.5
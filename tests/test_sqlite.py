@@ -0,0 +1,49 @@
+import sqlite3
+import pytest
+import fastdigest.sqlite as fd_sqlite
+from fastdigest import TDigest
+
+
+@pytest.fixture
+def conn() -> sqlite3.Connection:
+    conn = sqlite3.connect(":memory:")
+    fd_sqlite.register(conn)
+    conn.execute("CREATE TABLE t (g INTEGER, v REAL, w REAL)")
+    rows = [(i % 2, float(i), 2.0) for i in range(1, 101)]
+    conn.executemany("INSERT INTO t VALUES (?, ?, ?)", rows)
+    conn.execute("INSERT INTO t VALUES (0, NULL, NULL)")
+    return conn
+
+
+def test_sqlite_aggregate(conn: sqlite3.Connection) -> None:
+    (blob,) = conn.execute("SELECT tdigest(v) FROM t").fetchone()
+    assert TDigest.from_bytes(blob) == TDigest.from_values(range(1, 101))
+    (blob,) = conn.execute("SELECT tdigest(v, w) FROM t").fetchone()
+    assert TDigest.from_bytes(blob).mass() == 200.0
+    rows = conn.execute(
+        "SELECT g, tdigest_quantile(tdigest(v), 0.5) FROM t "
+        "GROUP BY g ORDER BY g"
+    ).fetchall()
+    assert rows == [(0, 51.0), (1, 50.0)]
+    (rank,) = conn.execute(
+        "SELECT tdigest_cdf(tdigest(v), 100) FROM t"
+    ).fetchone()
+    assert rank == 1.0
+    (empty,) = conn.execute(
+        "SELECT tdigest_quantile(tdigest(v), 0.5) FROM t WHERE v IS NULL"
+    ).fetchone()
+    assert empty is None
+
+
+def test_sqlite_merge(conn: sqlite3.Connection) -> None:
+    conn.execute(
+        "CREATE TABLE digests AS SELECT g, tdigest(v) AS d FROM t GROUP BY g"
+    )
+    conn.execute("INSERT INTO digests VALUES (2, NULL)")
+    (median,) = conn.execute(
+        "SELECT tdigest_quantile(tdigest_merge(d), 0.5) FROM digests"
+    ).fetchone()
+    assert median == 50.5
+    conn.execute("INSERT INTO digests VALUES (3, x'00')")
+    with pytest.raises(sqlite3.OperationalError):
+        conn.execute("SELECT tdigest_merge(d) FROM digests").fetchone()
@@ -2,9 +2,21 @@ import pytest
 import math
 import random
 import pickle
+import array
+import io
+import bisect
+import asyncio
+import mmap
+import statistics
+import struct
+import sys
+import sysconfig
+import threading
 import collections.abc
+from decimal import Decimal
 from copy import copy, deepcopy
-from typing import Callable, Sequence, List
+from pathlib import Path
+from typing import AsyncIterator, Callable, Sequence, List, Optional
 from fastdigest import TDigest
 from utils import (
     EPS,
@@ -97,6 +109,172 @@ def test_max_centroids(sample_values: Sequence[int]) -> None:
         d.max_centroids = -1
 
 
+def test_set_max_centroids_recompresses() -> None:
+    values = [float(x) for x in range(10_000)]
+    random.shuffle(values)
+    for backend in ("merging", "tree"):
+        d = TDigest(max_centroids=500, backend=backend)
+        for x in values:
+            d.update(x)
+        n_before = len(d)
+        d.max_centroids = 50
+        assert d.max_centroids == 50 and len(d) <= 51 < n_before
+        assert d.n_values == 10_000 and d.min() == 0 and d.max() == 9999
+        assert math.isclose(d.quantile(0.5), 4999.5, rel_tol=0.01)
+        # later compressions use the new value as well
+        d.batch_update(values)
+        for x in values[:1000]:
+            d.update(x)
+        assert len(d) <= 51 and d.n_values == 21_000
+        d.max_centroids = 1000
+        assert d.max_centroids == 1000 and len(d) <= 51
+
+
+def test_f32_storage(sample_values: Sequence[int]) -> None:
+    assert TDigest().storage == "f64"
+    d = TDigest(storage="f32")
+    assert d.storage == "f32"
+    assert repr(d) == "TDigest(max_centroids=1000, storage='f32')"
+    d.batch_update(sample_values)
+    for x in sample_values:
+        d.update(x + 0.1)
+    expected = TDigest.from_values(sample_values)
+    expected.batch_update([x + 0.1 for x in sample_values])
+    assert d.n_values == expected.n_values
+    assert d.min() == expected.min() and d.max() == expected.max()
+    for q in SAMPLE_QUANTILES:
+        assert math.isclose(d.quantile(q), expected.quantile(q), rel_tol=1e-6)
+    assert copy(d).storage == "f32" and copy(d) == d
+    restored = pickle.loads(pickle.dumps(d))
+    assert restored.storage == "f32" and restored == d
+    assert TDigest.from_bytes(d.to_bytes()).storage == "f64"
+    d.storage = "f64"
+    assert d.storage == "f64"
+    assert repr(d) == "TDigest(max_centroids=1000)"
+    with pytest.raises(ValueError):
+        TDigest(storage="f16")
+    with pytest.raises(ValueError):
+        d.storage = "float32"
+
+
+def test_tail_singletons() -> None:
+    values = [float(x) for x in range(10_000)]
+    random.shuffle(values)
+    assert TDigest().tail_singletons == 0
+    d = TDigest(max_centroids=50, tail_singletons=5)
+    assert repr(d) == "TDigest(max_centroids=50, tail_singletons=5)"
+    for i in range(0, len(values), 1000):
+        d.batch_update(values[i : i + 1000])
+    centroids = d.centroids
+    assert centroids[:5] == [(float(x), 1.0) for x in range(5)]
+    assert centroids[-5:] == [(float(x), 1.0) for x in range(9995, 10_000)]
+    assert TDigest.from_values(values, max_centroids=50).centroids[0][1] > 1
+    merged = d.merge(TDigest.from_values(values, max_centroids=50))
+    assert merged.tail_singletons == 5
+    assert merged.centroids[0] == (0.0, 1.0)
+    restored = pickle.loads(pickle.dumps(d))
+    assert restored.tail_singletons == 5 and restored == d
+    assert copy(d).tail_singletons == 5
+    d.tail_singletons = 0
+    assert repr(d) == "TDigest(max_centroids=50)"
+    with pytest.raises(ValueError):
+        TDigest(tail_singletons=-1)
+
+
+def test_resolution() -> None:
+    values = [float(x) for x in range(100_000)]
+    random.shuffle(values)
+    assert TDigest().resolution == "both"
+    digests = {}
+    for resolution in ("both", "upper", "lower"):
+        d = TDigest(max_centroids=100, resolution=resolution)
+        for i in range(0, len(values), 10_000):
+            d.batch_update(values[i : i + 10_000])
+        assert d.resolution == resolution and len(d) <= 100
+        digests[resolution] = d
+    assert repr(digests["upper"]) == (
+        "TDigest(max_centroids=100, resolution='upper')"
+    )
+
+    def n_upper(d: TDigest) -> int:
+        return sum(1 for mean, _ in d.centroids if mean > 90_000)
+
+    assert n_upper(digests["upper"]) > n_upper(digests["both"])
+    assert n_upper(digests["lower"]) < n_upper(digests["both"])
+    restored = pickle.loads(pickle.dumps(digests["upper"]))
+    assert restored.resolution == "upper" and restored == digests["upper"]
+    assert (digests["upper"] + digests["upper"]).resolution == "upper"
+    assert (digests["upper"] + digests["lower"]).resolution == "both"
+    with pytest.raises(ValueError):
+        TDigest(resolution="middle")
+
+
+def test_tree_backend() -> None:
+    values = [float(x) for x in range(1, 20_001)]
+    random.shuffle(values)
+    assert TDigest().backend == "merging"
+    d = TDigest(max_centroids=100, backend="tree")
+    assert d.backend == "tree"
+    assert repr(d) == "TDigest(max_centroids=100, backend='tree')"
+    assert d.is_empty()
+    for x in values[:10_000]:
+        d.update(x)
+    d.update(0.5, w=3.0)
+    assert not d.is_empty()
+    assert d.n_values == 10_001 and d.mass() == 10_003.0
+    assert d.stats()["values_buffered"] == 10_001
+    assert d.min() == 0.5 and d.stats()["values_buffered"] == 0
+    d.batch_update(values[10_000:])
+    for x in values[:100]:
+        d.update(x)
+    assert d.n_values == 20_101 and len(d) <= 101
+    expected = TDigest.from_values(values + values[:100] + [0.5] * 3)
+    for q in SAMPLE_QUANTILES:
+        assert math.isclose(d.quantile(q), expected.quantile(q), rel_tol=0.01)
+    restored = pickle.loads(pickle.dumps(d))
+    assert restored.backend == "tree" and restored == d
+    assert copy(d).backend == "tree"
+    assert TDigest.from_bytes(d.to_bytes()).backend == "merging"
+    with pytest.raises(ValueError):
+        TDigest(backend="avl")
+    with pytest.raises(ValueError):
+        TDigest(exact_tails=5, backend="tree")
+    # max_centroids=0 keeps every value as its own centroid
+    tree = TDigest(max_centroids=0, backend="tree")
+    merging = TDigest(max_centroids=0)
+    for x in range(1000):
+        tree.update(x)
+        merging.update(x)
+    assert len(tree) == len(merging) == 1000
+    assert tree.quantile(0.1) == merging.quantile(0.1) == 99.9
+
+
+def test_exact_tails() -> None:
+    n = 100_000
+    values = [float(x) for x in range(n)]
+    random.shuffle(values)
+    assert TDigest().exact_tails == 0
+    d = TDigest(max_centroids=50, exact_tails=20)
+    plain = TDigest(max_centroids=50)
+    for i in range(0, n, 1000):
+        d.batch_update(values[i : i + 1000])
+        plain.batch_update(values[i : i + 1000])
+    assert repr(d) == "TDigest(max_centroids=50, exact_tails=20)"
+    for q in (0.0, 0.00005, 0.0001, 0.9999, 0.99995, 1.0):
+        assert math.isclose(d.quantile(q), q * (n - 1), abs_tol=1e-6)
+        assert d.quantile_vec([q]) == [d.quantile(q)]
+    assert abs(plain.quantile(0.99995) - 0.99995 * (n - 1)) > 1e-3
+    assert d.quantile(0.5) == plain.quantile(0.5)
+    restored = pickle.loads(pickle.dumps(d))
+    assert restored.exact_tails == 20
+    assert restored.quantile(0.9999) == d.quantile(0.9999)
+    assert (d + TDigest()).exact_tails == 20
+    assert (d + TDigest(exact_tails=5)).exact_tails == 5
+    assert (d + plain).exact_tails == 0
+    with pytest.raises(ValueError):
+        TDigest(exact_tails=-1)
+
+
 def test_properties() -> None:
     d = TDigest.from_values([1.0, 2.0, 3.0])
     assert isinstance(d.mass(), float) and d.mass() == 3.0
@@ -119,6 +297,49 @@ def test_properties() -> None:
     assert d.n_centroids == 5
 
 
+def test_memory_bytes() -> None:
+    small = TDigest(max_centroids=10)
+    d = TDigest.from_values(range(10_000))
+    assert 0 < small.memory_bytes() < d.memory_bytes()
+    assert sys.getsizeof(d) > d.memory_bytes()
+    assert sys.getsizeof(small) < sys.getsizeof(d)
+    f64_bytes = d.memory_bytes()
+    d.storage = "f32"
+    assert d.memory_bytes() < f64_bytes
+    assert d.memory_bytes() == d.copy().memory_bytes()
+
+
+
+def test_stats() -> None:
+    d = TDigest(max_centroids=100)
+    stats = d.stats()
+    assert stats == {
+        "compressions": 0,
+        "merges": 0,
+        "last_compression_ratio": None,
+        "values_buffered": 0,
+        "values_merged": 0,
+        "n_centroids": 0,
+        "max_centroids": 100,
+    }
+    for x in range(10):
+        d.update(x)
+    stats = d.stats()
+    assert stats["compressions"] == 0
+    assert stats["values_buffered"] == 10
+    d.batch_update(range(1000))
+    stats = d.stats()
+    assert stats["compressions"] == 2  # buffer flush + batch
+    assert stats["values_buffered"] == 0
+    assert stats["values_merged"] == 1010
+    assert stats["n_centroids"] == d.n_centroids
+    assert stats["last_compression_ratio"] > 1.0
+    d.merge_inplace(TDigest.from_values(range(10)))
+    assert d.stats()["merges"] == 1
+    assert d.stats()["compressions"] == 3
+    assert d.copy().stats() == d.stats()
+    assert (d + d).stats()["merges"] == 1
+
 def test_aggregates(sample_values: Sequence[int]) -> None:
     d = TDigest.from_values(sample_values)
     assert d.mass() == len(sample_values)
@@ -154,6 +375,43 @@ def test_centroids() -> None:
     assert isinstance(TDigest().centroids, list)
 
 
+def test_scale_weights() -> None:
+    d = TDigest.from_values([float(x) for x in range(1, 101)])
+    median = d.median()
+    d.scale_weights(0.5)
+    assert d.mass() == 50.0
+    assert d.sum() == 2525.0
+    assert d.median() == median
+    assert d.n_values == 100
+    # values added afterwards weigh twice as much
+    d.batch_update([1000.0] * 100)
+    assert d.median() == 1000.0
+    d = TDigest()
+    d.batch_update([1.0, 2.0, 3.0, 4.0], w=[1.0, 10.0, 10.0, 1.0])
+    d.scale_weights(0.5, min_weight=1.0)
+    assert d.centroids == [(2.0, 5.0), (3.0, 5.0)]
+    assert d.mass() == 10.0
+    assert (d.min(), d.max()) == (2.0, 3.0)
+    d.scale_weights(1.0, min_weight=100.0)
+    assert d.is_empty()
+    for factor in (0.0, -0.5, 1.5, float("nan")):
+        with pytest.raises(ValueError):
+            d.scale_weights(factor)
+    for min_weight in (-1.0, float("inf")):
+        with pytest.raises(ValueError):
+            d.scale_weights(0.5, min_weight=min_weight)
+
+def test_to_numpy() -> None:
+    np = pytest.importorskip("numpy")
+    d = TDigest.from_values([1.0, 2.0, 2.0, 3.0], w=[1, 2, 3, 4])
+    means, weights = d.to_numpy()
+    assert list(means) == [m for m, _ in d.centroids]
+    assert list(weights) == [w for _, w in d.centroids]
+    assert means.dtype == np.float64
+    means, weights = TDigest().to_numpy()
+    assert len(means) == len(weights) == 0
+
+
 # -------------------------------------------------------------------
 # Merge tests (merge, merge_inplace, __add__, __iadd__)
 # -------------------------------------------------------------------
@@ -209,6 +467,94 @@ def test_merge_inplace(sample_values: Sequence[int]) -> None:
     check_sample_quantiles(empty_digest, expected)
 
 
+
+@pytest.mark.parametrize("max_centroids", [0, 1, 3, 1000])
+def test_merge_inplace_matches_merge(max_centroids: int) -> None:
+    rng = random.Random(max_centroids)
+    batches = [[rng.gauss(0, 1) for _ in range(300)] for _ in range(5)]
+    d = TDigest(max_centroids)
+    for batch in batches:
+        other = TDigest.from_values(batch, max_centroids=max_centroids)
+        expected = d.merge(other)
+        d.merge_inplace(other)
+        assert d == expected
+        expected = d.merge(d)
+        d.merge_inplace(d)
+        assert d == expected
+    d = TDigest.from_values(batches[0], max_centroids=max_centroids)
+    for batch in batches[1:]:
+        d.batch_update(batch)
+    assert d.n_values == 1500
+    assert d.min() == min(map(min, batches))
+    assert d.max() == max(map(max, batches))
+
+
+def test_weighted_merge() -> None:
+    prior = TDigest.from_values([float(x) for x in range(1, 101)])
+    live = TDigest.from_values([1000.0] * 10)
+    # the prior counts as much as 10 live values
+    blended = live.merge(prior, weight=0.1)
+    assert blended.mass() == pytest.approx(20.0)
+    assert blended.n_values == 110
+    assert blended.max() == 1000.0
+    assert blended.quantile(0.25) < 100.0
+    assert blended.quantile(0.75) == 1000.0
+    assert prior.mass() == 100.0
+    assert prior.merge(live, weight=10).mass() == pytest.approx(200.0)
+    assert live.merge(prior, weight=1.0) == live.merge(prior)
+    d = live.copy()
+    d.merge_inplace(prior, weight=0.1)
+    assert d == blended
+    d.merge_inplace(d, weight=0.5)
+    assert d.mass() == pytest.approx(30.0)
+    for weight in (0.0, -1.0, float("inf"), float("nan")):
+        with pytest.raises(ValueError):
+            live.merge(prior, weight=weight)
+        with pytest.raises(ValueError):
+            live.merge_inplace(prior, weight=weight)
+
+
+def test_difference_remove() -> None:
+    rng = random.Random(0)
+    old = [rng.gauss(0.0, 1.0) for _ in range(5000)]
+    new = [rng.gauss(5.0, 1.0) for _ in range(5000)]
+    d_old = TDigest.from_values(old)
+    d_new = TDigest.from_values(new)
+    both = d_old + d_new
+    # approximately retires the contribution of the old values
+    retired = both.difference(d_old)
+    assert retired.mass() == pytest.approx(5000.0)
+    assert retired.n_values == 5000
+    assert abs(retired.median() - d_new.median()) < 0.1
+    assert abs(retired.quantile(0.9) - d_new.quantile(0.9)) < 0.2
+    assert both.mass() == 10_000.0
+    assert (both - d_old) == retired
+    assert both.difference(both).is_empty()
+    d = TDigest.from_values([1.0, 2.0, 3.0, 4.0])
+    d.remove(1.0)
+    assert d.centroids == [(2.0, 1.0), (3.0, 1.0), (4.0, 1.0)]
+    assert d.min() == 2.0
+    assert d.n_values == 3
+    d.remove(4.2, w=0.5)
+    assert d.centroids == [(2.0, 1.0), (3.0, 1.0), (4.0, 0.5)]
+    assert d.max() == 4.0
+    d.remove(3.9, w=1.0)
+    assert d.centroids == [(2.0, 1.0), (3.0, 0.5)]
+    assert d.max() == 3.0
+    assert d.sum() == 3.5
+    e = TDigest(exact_tails=2)
+    e.batch_update([1.0, 2.0, 3.0, 4.0, 5.0])
+    e.remove(1.0)
+    assert e.quantile(0.0) == 2.0
+    with pytest.raises(ValueError):
+        d.remove(1.0, w=2.0)
+    with pytest.raises(ValueError):
+        d.remove(float("nan"))
+    with pytest.raises(ValueError):
+        d.remove(1.0, w=0.0)
+    with pytest.raises(ValueError):
+        d_old.difference(both)
+
 @pytest.mark.parametrize(
     "iadd_op",
     [
@@ -230,6 +576,19 @@ def test_add_iadd(
     assert double_merged.n_values == 2 * n
 
 
+def test_sum(sample_values: Sequence[int]) -> None:
+    digests = [TDigest.from_values(sample_values[i::4]) for i in range(4)]
+    expected = TDigest.from_values(sample_values)
+    check_tdigest_equality(sum(digests), expected)
+    check_tdigest_equality(sum(digests, TDigest()), expected)
+    assert isinstance(0 + digests[0], TDigest)
+    assert (0 + digests[0]) is not digests[0]
+    with pytest.raises(TypeError):
+        1 + digests[0]
+    with pytest.raises(TypeError):
+        digests[0] + 0
+
+
 def test_add_with_empty_max_centroids(sample_values: Sequence[int]) -> None:
     digest = TDigest.from_values(sample_values)
     digest.max_centroids = 3
@@ -271,6 +630,384 @@ def test_updates(
     assert d.n_centroids <= max_centroids + 1
 
 
+def test_update_buffer_merges_like_batches() -> None:
+    rng = random.Random(3)
+    values = [rng.random() for _ in range(256 * 8)]
+    weights = [rng.choice([1.0, 2.5]) for _ in values]
+    for w in (None, weights):
+        single = TDigest(max_centroids=30)
+        batched = TDigest(max_centroids=30)
+        for i, x in enumerate(values):
+            single.update(x, w=None if w is None else w[i])
+        for start in range(0, len(values), 256):
+            chunk = values[start : start + 256]
+            batched.batch_update(
+                chunk, w=None if w is None else w[start : start + 256]
+            )
+        assert single.to_bytes() == batched.to_bytes()
+
+
+def test_buffer_updates(sample_values: Sequence[int]) -> None:
+    expected = TDigest.from_values(sample_values)
+    buf = array.array("d", sample_values)
+    check_tdigest_equality(TDigest.from_values(buf), expected)
+    check_tdigest_equality(TDigest.from_values(memoryview(buf)), expected)
+    d = TDigest()
+    d.batch_update(buf, w=1.0)
+    check_tdigest_equality(d, expected)
+    # non-contiguous views are read element by element
+    strided = memoryview(array.array("d", sorted(sample_values * 2)))[::2]
+    check_tdigest_equality(TDigest.from_values(strided), expected)
+    with pytest.raises(ValueError):
+        d.batch_update(array.array("d", [1.0, float("inf")]))
+
+
+def test_binary_buffer_updates(
+    sample_values: Sequence[int], tmp_path: Path
+) -> None:
+    expected = TDigest.from_values(sample_values)
+    raw = array.array("d", sample_values).tobytes()
+    check_tdigest_equality(
+        TDigest.from_values(memoryview(raw).cast("d")), expected
+    )
+    path = tmp_path / "values.bin"
+    path.write_bytes(raw)
+    with open(path, "rb") as f:
+        with mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ) as mm:
+            with memoryview(mm).cast("d") as view:
+                d = TDigest()
+                d.batch_update(view[:50])
+                d.batch_update(view[50:])
+    check_tdigest_equality(d, expected)
+
+
+
+def test_ingest_file(sample_values: Sequence[int], tmp_path: Path) -> None:
+    path = tmp_path / "values.f8"
+    path.write_bytes(array.array("d", sample_values).tobytes())
+    expected = TDigest.from_values(sample_values)
+    assert TDigest.ingest_file(path) == expected
+    assert TDigest.ingest_file(str(path), chunk=7).n_values == 100
+    digest = TDigest.ingest_file(path, chunk=7, max_centroids=3)
+    assert digest.max_centroids == 3
+    assert digest.n_values == 100
+    big_endian = tmp_path / "values.i2"
+    big_endian.write_bytes(struct.pack(">100h", *sample_values))
+    assert TDigest.ingest_file(big_endian, dtype=">i2") == expected
+    empty = tmp_path / "empty"
+    empty.write_bytes(b"")
+    assert TDigest.ingest_file(empty) == TDigest()
+    truncated = tmp_path / "truncated"
+    truncated.write_bytes(path.read_bytes()[:-1])
+    with pytest.raises(ValueError):
+        TDigest.ingest_file(truncated, chunk=7)
+    with pytest.raises(ValueError):
+        TDigest.ingest_file(path, dtype="c16")
+    with pytest.raises(ValueError):
+        TDigest.ingest_file(path, chunk=0)
+    with pytest.raises(FileNotFoundError):
+        TDigest.ingest_file(tmp_path / "missing")
+
+
+def test_from_csv(sample_values: Sequence[int], tmp_path: Path) -> None:
+    path = tmp_path / "values.csv"
+    rows = [f'{i},"label, {i}",{x}' for i, x in enumerate(sample_values)]
+    rows[3] = '3,"multi\nline ""label""",'
+    path.write_text("\n".join(["id,label,value", *rows, ""]))
+    expected = TDigest.from_values(
+        [x for i, x in enumerate(sample_values) if i != 3]
+    )
+    assert TDigest.from_csv(path, "value") == expected
+    assert TDigest.from_csv(str(path), 2, chunk=7) == expected
+    assert TDigest.from_csv(path, "id").n_values == 100
+    digest = TDigest.from_csv(path, "value", max_centroids=3)
+    assert digest.max_centroids == 3
+    semicolons = tmp_path / "values.txt"
+    semicolons.write_text("\r\n".join(["value", *map(str, sample_values)]))
+    assert TDigest.from_csv(semicolons, "value", delimiter=";") == (
+        TDigest.from_values(sample_values)
+    )
+    with pytest.raises(ValueError):
+        TDigest.from_csv(path, "missing")
+    with pytest.raises(ValueError):
+        TDigest.from_csv(path, "label")
+    with pytest.raises(ValueError):
+        TDigest.from_csv(path, 3)
+    with pytest.raises(ValueError):
+        TDigest.from_csv(path, "value", delimiter=";;")
+    with pytest.raises(TypeError):
+        TDigest.from_csv(path, -1)
+    with pytest.raises(FileNotFoundError):
+        TDigest.from_csv(tmp_path / "missing.csv", "value")
+
+
+
+def test_from_parquet(sample_values: Sequence[int], tmp_path: Path) -> None:
+    pa = pytest.importorskip("pyarrow")
+    pq = pytest.importorskip("pyarrow.parquet")
+    path = tmp_path / "values.parquet"
+    table = pa.table(
+        {
+            "id": list(range(102)),
+            "value": sample_values[:50] + [None, None] + sample_values[50:],
+        }
+    )
+    pq.write_table(table, path, row_group_size=16)
+    expected = TDigest.from_values(sample_values)
+    check_tdigest_equality(TDigest.from_parquet(path, "value"), expected)
+    digest = TDigest.from_parquet(str(path), "id", max_centroids=3)
+    assert digest.max_centroids == 3
+    assert digest.n_values == 102
+    with pytest.raises(Exception):
+        TDigest.from_parquet(path, "missing")
+
+def test_float32_buffer_updates(sample_values: Sequence[int]) -> None:
+    expected = TDigest.from_values(sample_values)
+    buf = array.array("f", sample_values)
+    check_tdigest_equality(TDigest.from_values(buf), expected)
+    d = TDigest()
+    d.batch_update(memoryview(buf), w=[1.0] * len(buf))
+    check_tdigest_equality(d, expected)
+    with pytest.raises(ValueError):
+        d.batch_update(array.array("f", [1.0, float("nan")]))
+
+
+@pytest.mark.parametrize("typecode", ["b", "B", "h", "H", "i", "I", "q", "Q"])
+def test_integer_buffer_updates(
+    typecode: str, sample_values: Sequence[int]
+) -> None:
+    expected = TDigest.from_values(sample_values)
+    buf = array.array(typecode, sample_values)
+    check_tdigest_equality(TDigest.from_values(buf), expected)
+    d = TDigest()
+    d.batch_update(buf, w=2)
+    assert d.mass() == 2 * len(buf)
+
+
+def test_dlpack_updates(sample_values: Sequence[int]) -> None:
+    np = pytest.importorskip("numpy")
+    if not hasattr(np.ndarray, "__dlpack__"):
+        pytest.skip("requires numpy with DLPack support")
+
+    class Tensor:
+        # exposes only the DLPack protocol, not the buffer protocol
+        def __init__(self, array) -> None:
+            self.array = array
+
+        def __dlpack__(self, **kwargs):
+            return self.array.__dlpack__()
+
+        def __dlpack_device__(self):
+            return self.array.__dlpack_device__()
+
+    expected = TDigest.from_values(sample_values)
+    for dtype in ["f8", "f4", "i8", "i2", "u1"]:
+        tensor = Tensor(np.array(sample_values, dtype=dtype))
+        check_tdigest_equality(TDigest.from_values(tensor), expected)
+    d = TDigest()
+    d.batch_update(Tensor(np.array(sample_values[:50], dtype="f2")))
+    d.batch_update(Tensor(np.array(sample_values[50:], dtype="f2")), w=1.0)
+    check_tdigest_equality(d, expected)
+    # strided tensors are copied in row-major order
+    matrix = np.array(sample_values, dtype="f8").reshape(10, 10)
+    check_tdigest_equality(TDigest.from_values(Tensor(matrix.T)), expected)
+    doubled = np.repeat(np.array(sample_values, dtype="f8"), 2)
+    check_tdigest_equality(TDigest.from_values(Tensor(doubled[::2])), expected)
+    with pytest.raises(ValueError):
+        d.batch_update(Tensor(np.array([1.0, np.nan])))
+    # tensors of other types are read item by item, if they are iterable
+    with pytest.raises(TypeError, match="iterable"):
+        TDigest.from_values(Tensor(np.array([1j, 2j])))
+
+
+def test_dlpack_fallback(sample_values: Sequence[int]) -> None:
+    class Values(list):
+        def __dlpack__(self, **kwargs):
+            raise BufferError("cannot export")
+
+        def __dlpack_device__(self):
+            return (1, 0)
+
+    expected = TDigest.from_values(sample_values)
+    check_tdigest_equality(
+        TDigest.from_values(Values(sample_values)), expected
+    )
+    np = pytest.importorskip("numpy")
+    # numpy cannot export these, or not with a supported type
+    flags = np.array([i % 3 == 0 for i in range(100)])
+    check_tdigest_equality(
+        TDigest.from_values(flags), TDigest.from_values(flags.tolist())
+    )
+    decimals = np.array([Decimal(x) / 4 for x in sample_values], dtype=object)
+    check_tdigest_equality(
+        TDigest.from_values(decimals),
+        TDigest.from_values([x / 4 for x in sample_values]),
+    )
+    doubled = np.repeat(np.array(sample_values, dtype="f8"), 2)
+    doubled.flags.writeable = False
+    check_tdigest_equality(TDigest.from_values(doubled[::2]), expected)
+
+
+def test_torch_updates(sample_values: Sequence[int]) -> None:
+    torch = pytest.importorskip("torch")
+    expected = TDigest.from_values(sample_values)
+    for dtype in [torch.float32, torch.bfloat16, torch.int64]:
+        tensor = torch.tensor(sample_values, dtype=dtype)
+        check_tdigest_equality(TDigest.from_values(tensor), expected)
+    d = TDigest()
+    d.batch_update(torch.tensor(sample_values).reshape(10, 10).T)
+    check_tdigest_equality(d, expected)
+    if torch.cuda.is_available():
+        tensor = torch.tensor(sample_values, dtype=torch.float64).cuda()
+        check_tdigest_equality(TDigest.from_values(tensor), expected)
+
+
+def test_iterable_updates(sample_values: Sequence[int]) -> None:
+    expected = TDigest.from_values(sample_values)
+    check_tdigest_equality(
+        TDigest.from_values(x for x in sample_values), expected
+    )
+    check_tdigest_equality(TDigest.from_values(set(sample_values)), expected)
+    decimals = [Decimal(x) / 4 for x in sample_values]
+    d = TDigest()
+    d.batch_update(decimals[:50])
+    for x in decimals[50:]:
+        d.update(x)
+    expected = TDigest.from_values([x / 4 for x in sample_values])
+    check_tdigest_equality(d, expected)
+    with pytest.raises(TypeError, match="got 'str' at index 1"):
+        TDigest.from_values([1.0, "2.0"])
+    with pytest.raises(TypeError, match="iterable"):
+        TDigest.from_values(1.0)
+
+
+def test_numpy_updates(sample_values: Sequence[int]) -> None:
+    np = pytest.importorskip("numpy")
+    expected = TDigest.from_values(sample_values)
+    arr = np.array(sample_values, dtype=np.float64)
+    check_tdigest_equality(TDigest.from_values(arr), expected)
+    check_tdigest_equality(TDigest.from_values(arr[::-1]), expected)
+    d = TDigest()
+    d.batch_update(arr, w=np.ones(len(arr)))
+    check_tdigest_equality(d, expected)
+    arr32 = arr.astype(np.float32)
+    check_tdigest_equality(TDigest.from_values(arr32), expected)
+    for dtype in (np.int8, np.int32, np.int64, np.uint16, np.uint64):
+        arr_int = arr.astype(dtype)
+        check_tdigest_equality(TDigest.from_values(arr_int), expected)
+
+
+def test_datetime_values(sample_values: Sequence[int]) -> None:
+    np = pytest.importorskip("numpy")
+    start = np.datetime64("2024-01-01T00:00:00", "ns")
+    times = start + np.array(sample_values, dtype="timedelta64[s]")
+    expected = TDigest.from_values(
+        [x + 1_704_067_200 for x in sample_values]
+    )
+    d = TDigest.from_values(np.append(times, np.datetime64("NaT")))
+    check_tdigest_equality(d, expected)
+    assert d.dtype == "datetime64[ns]"
+    assert repr(d) == "TDigest(max_centroids=1000, dtype='datetime64[ns]')"
+    median = d.median()
+    assert isinstance(median, np.datetime64)
+    assert median == np.datetime64(round(expected.median() * 1e9), "ns")
+    assert d.min() == times.min() and d.max() == times.max()
+    assert d.quantile_vec([0.0, 1.0]) == [times.min(), times.max()]
+    assert isinstance(d.iqr(), np.timedelta64)
+    assert d.cdf(d.median()) == pytest.approx(0.5, abs=0.01)
+    assert d.cdf_vec(times[:3]) == expected.cdf_vec(
+        [x + 1_704_067_200 for x in sample_values[:3]]
+    )
+    for restored in (
+        TDigest.from_dict(d.to_dict()),
+        pickle.loads(pickle.dumps(d)),
+        copy(d),
+        d + TDigest(),
+    ):
+        assert restored.dtype == "datetime64[ns]"
+    assert TDigest.from_bytes(d.to_bytes()).dtype is None
+    with pytest.raises(TypeError):
+        d.batch_update(np.array([1, 2], dtype="timedelta64[s]"))
+    d.dtype = None
+    assert d.median() == expected.median()
+
+
+def test_timedelta_values() -> None:
+    np = pytest.importorskip("numpy")
+    durations = np.array([120, 250, 80, 95, 300], dtype="timedelta64[ms]")
+    d = TDigest()
+    d.batch_update(durations)
+    assert d.dtype == "timedelta64[ms]"
+    assert d.median() == np.timedelta64(120, "ms")
+    assert d.mean() == np.timedelta64(169, "ms")
+    # finer units are kept
+    d.batch_update(np.array([1], dtype="timedelta64[us]"))
+    assert d.dtype == "timedelta64[us]"
+    assert d.min() == np.timedelta64(1, "us")
+    d.dtype = "datetime64[s]"
+    assert isinstance(d.median(), np.datetime64)
+    with pytest.raises(ValueError):
+        d.dtype = "datetime64[D]"
+    seconds = TDigest.from_values(durations / np.timedelta64(1, "s"))
+    assert seconds.dtype is None
+    assert seconds.median() == 0.12
+
+
+def test_pandas_updates(sample_values: Sequence[int]) -> None:
+    pd = pytest.importorskip("pandas")
+    expected = TDigest.from_values(sample_values)
+    s = pd.Series(sample_values, dtype="int64")
+    check_tdigest_equality(TDigest.from_series(s), expected)
+    # NaN/NA values are skipped, along with their weights
+    s = pd.Series(sample_values + [None, float("nan")], dtype="Float64")
+    check_tdigest_equality(TDigest.from_series(s), expected)
+    check_tdigest_equality(TDigest.from_values(s), expected)
+    d = TDigest()
+    d.batch_update(s, w=[1.0] * 100 + [5.0, 5.0])
+    check_tdigest_equality(d, expected)
+    assert d.mass() == 100
+    d.batch_update(pd.Series([None], dtype="Float64"))
+    assert d.n_values == 100
+    with pytest.raises(ValueError):
+        TDigest.from_series(pd.Series([1.0, float("inf")]))
+
+
+def test_polars_updates(sample_values: Sequence[int]) -> None:
+    pl = pytest.importorskip("polars")
+    expected = TDigest.from_values(sample_values)
+    for dtype in (pl.Float64, pl.Float32, pl.Int64, pl.UInt16):
+        s = pl.Series(sample_values, dtype=dtype)
+        check_tdigest_equality(TDigest.from_series(s), expected)
+    # null values are skipped, also in sliced and chunked Series
+    s = pl.Series([None] + sample_values[:50] + [None], dtype=pl.Float64)
+    s.append(pl.Series(sample_values[50:], dtype=pl.Float64))
+    check_tdigest_equality(TDigest.from_values(s), expected)
+    d = TDigest()
+    d.batch_update(s[1:], w=2.0)
+    assert d.mass() == 200
+    with pytest.raises(TypeError):
+        TDigest.from_series(pl.Series(["a", "b"]))
+
+
+def test_pyarrow_updates(sample_values: Sequence[int]) -> None:
+    pa = pytest.importorskip("pyarrow")
+    expected = TDigest.from_values(sample_values)
+    for dtype in (pa.float64(), pa.float32(), pa.int32(), pa.uint8()):
+        arr = pa.array(sample_values, type=dtype)
+        check_tdigest_equality(TDigest.from_values(arr), expected)
+    # null values are skipped in every chunk
+    chunked = pa.chunked_array(
+        [sample_values[:50] + [None], [None], sample_values[50:]]
+    )
+    check_tdigest_equality(TDigest.from_values(chunked), expected)
+    check_tdigest_equality(TDigest.from_series(chunked), expected)
+    d = TDigest()
+    d.batch_update(pa.array([None] + sample_values).slice(1), w=2.0)
+    assert d.mass() == 200
+    with pytest.raises(TypeError):
+        TDigest.from_values(pa.array(["a", "b"]))
+
+
 def test_weighted_updates() -> None:
     d = TDigest()
     d.batch_update([1, 2, 3], w=[1, 2, 3])
@@ -302,6 +1039,170 @@ def test_weighted_updates() -> None:
         d.batch_update([1, 2], w={1, 2})
 
 
+
+@pytest.mark.parametrize("n_threads", [2, 3, None])
+def test_parallel_sort(n_threads: Optional[int]) -> None:
+    rng = random.Random(42)
+    values = [rng.gauss(0, 1) for _ in range(300_000)]
+    weights = [rng.uniform(0.5, 2.0) for _ in values]
+    expected = TDigest.from_values(values)
+    assert TDigest.from_values(values, n_threads=n_threads) == expected
+    d = TDigest()
+    d.batch_update(values, n_threads=n_threads)
+    assert d == expected
+    assert TDigest.from_values(
+        values, w=weights, n_threads=n_threads
+    ) == TDigest.from_values(values, w=weights)
+    with pytest.raises(ValueError):
+        d.batch_update(values, n_threads=0)
+
+
+@pytest.mark.parametrize("n_threads", [2, 3, None])
+def test_chunked_build(n_threads: Optional[int]) -> None:
+    rng = random.Random(42)
+    values = [rng.gauss(0, 1) for _ in range(300_000)]
+    weights = [rng.uniform(0.5, 2.0) for _ in values]
+    expected = TDigest.from_values(values)
+    d = TDigest.from_values(values, n_threads=n_threads, chunked=True)
+    assert d.n_values == expected.n_values
+    assert (d.min(), d.max()) == (expected.min(), expected.max())
+    for q in (0.001, 0.25, 0.5, 0.75, 0.999):
+        assert d.quantile(q) == pytest.approx(expected.quantile(q), abs=0.01)
+    d = TDigest.from_values(
+        values, w=weights, n_threads=n_threads, chunked=True
+    )
+    assert d.mass() == pytest.approx(sum(weights))
+    # chunks of sorted values need no sort
+    d = TDigest.from_values(
+        sorted(values), n_threads=n_threads, presorted=True, chunked=True
+    )
+    assert d.median() == pytest.approx(expected.median(), abs=0.01)
+    # settings and exact tails carry over
+    d = TDigest(exact_tails=5, resolution="upper")
+    d.batch_update(values[:1000])
+    d.batch_update(values, n_threads=n_threads, chunked=True)
+    assert d.resolution == "upper"
+    assert d.quantile(0.0) == min(values)
+    assert d.n_values == 301_000
+    # inputs too small to be split give the single-threaded result
+    small = TDigest.from_values(values[:1000], n_threads=4, chunked=True)
+    assert small == TDigest.from_values(values[:1000])
+
+
+def test_presorted_updates(sample_values: Sequence[int]) -> None:
+    values = sorted(sample_values)
+    expected = TDigest.from_values(values)
+    assert TDigest.from_values(values, presorted=True) == expected
+    buffer = array.array("d", values)
+    assert TDigest.from_values(buffer, presorted=True) == expected
+    d = TDigest.from_values(values[:50])
+    d.batch_update(values[50:], presorted=True)
+    unsorted = TDigest.from_values(values[:50])
+    unsorted.batch_update(values[50:])
+    assert d == unsorted
+    weighted = TDigest.from_values(values, w=values, presorted=True)
+    assert weighted == TDigest.from_values(values, w=values)
+    with pytest.raises(ValueError):
+        TDigest.from_values([1, 3, 2], presorted=True)
+    with pytest.raises(ValueError):
+        d.batch_update([2, 1], w=[1, 1], presorted=True)
+    assert d.n_values == len(values)
+
+
+def test_batch_update_chunks(sample_values: Sequence[int]) -> None:
+    chunks = [sample_values[i : i + 30] for i in range(0, 100, 30)]
+    d = TDigest()
+    d.batch_update_chunks(iter(chunks))
+    assert d == TDigest.from_values(sample_values)
+    progress = []
+    d = TDigest()
+    d.batch_update_chunks(
+        chunks, callback=lambda *args: progress.append(args), every=3
+    )
+    assert progress == [(3, 90), (4, 100)]
+    assert d.n_values == 100
+
+    def cancel(n_chunks: int, n_values: int) -> bool:
+        return n_chunks < 2
+
+    d = TDigest()
+    d.batch_update_chunks(chunks, callback=cancel)
+    assert d.n_values == 60
+
+    def fail(n_chunks: int, n_values: int) -> None:
+        raise RuntimeError
+
+    d = TDigest()
+    with pytest.raises(RuntimeError):
+        d.batch_update_chunks(chunks, callback=fail)
+    assert d.n_values == 30
+    with pytest.raises(ValueError):
+        d.batch_update_chunks(chunks, every=0)
+
+
+
+def test_aupdate(sample_values: Sequence[int]) -> None:
+    async def chunks() -> AsyncIterator[object]:
+        for i in range(0, 90, 30):
+            await asyncio.sleep(0)
+            yield sample_values[i : i + 30]
+        for x in sample_values[90:]:
+            yield x
+
+    async def failing() -> AsyncIterator[object]:
+        yield sample_values[:10]
+        raise KeyError
+
+    async def main() -> None:
+        d = TDigest()
+        ticks = []
+
+        async def ticker() -> None:
+            for _ in range(5):
+                ticks.append(None)
+                await asyncio.sleep(0)
+
+        task = asyncio.create_task(ticker())
+        await d.aupdate(chunks())
+        await task
+        assert d == TDigest.from_values(sample_values)
+        assert len(ticks) == 5
+        d = TDigest()
+        with pytest.raises(KeyError):
+            await d.aupdate(failing())
+        assert d.n_values == 10
+        with pytest.raises(TypeError):
+            await d.aupdate(sample_values)  # type: ignore[arg-type]
+
+    asyncio.run(main())
+
+def test_threaded_updates(sample_values: Sequence[int]) -> None:
+    d = TDigest()
+    other = TDigest.from_values(sample_values)
+
+    def work() -> None:
+        for _ in range(10):
+            d.batch_update(sample_values)
+            d.merge_inplace(other)
+            d.quantile_vec([0.25, 0.5, 0.75])
+
+    threads = [threading.Thread(target=work) for _ in range(4)]
+    for t in threads:
+        t.start()
+    for t in threads:
+        t.join()
+    assert d.n_values == 80 * len(sample_values)
+
+
+@pytest.mark.skipif(
+    not sysconfig.get_config_var("Py_GIL_DISABLED"),
+    reason="requires a free-threaded build",
+)
+def test_free_threading() -> None:
+    # importing the module (and its submodules) must not re-enable the GIL
+    assert not sys._is_gil_enabled()  # type: ignore[attr-defined]
+
+
 # -------------------------------------------------------------------
 # Quantile tests (quantile, percentile, median, iqr)
 # -------------------------------------------------------------------
@@ -328,6 +1229,109 @@ def test_quantile_median_min_max(sample_values: Sequence[int]) -> None:
         TDigest().quantile(0.5)
 
 
+def test_quantile_monotonic() -> None:
+    rng = random.Random(7)
+    qs = [i / 1000 for i in range(1001)]
+    for max_centroids, kwargs in (
+        (3, {}),
+        (10, {"exact_tails": 5}),
+        (10, {"storage": "f32"}),
+        (50, {"tail_singletons": 2}),
+    ):
+        # many equal values with very different weights, where interpolating
+        # between the centroids used to round beyond their means
+        values = [rng.choice([5.0, -0.1, rng.gauss(0, 1)]) for _ in range(2000)]
+        weights = [rng.choice([0.01, 1.0, 3.7, 1e6]) for _ in values]
+        d = TDigest(max_centroids, **kwargs)
+        d.batch_update(values, weights)
+        d = d.merge(TDigest.from_values(values[:50], max_centroids=7))
+        xs = d.quantile_vec(qs)
+        assert xs == [d.quantile(q) for q in qs]
+        assert all(a <= b for a, b in zip(xs, xs[1:]))
+        assert d.min() <= xs[0] and xs[-1] <= d.max()
+    d = TDigest.from_values([1.0, 2.0, 3.0], w=[0.1, 0.1, 0.1])
+    assert d.quantile(1.0) == 3.0
+
+
+def test_rank_quantile_inverse() -> None:
+    rng = random.Random(11)
+    for max_centroids, kwargs in (
+        (3, {}),
+        (10, {"storage": "f32"}),
+        (50, {"tail_singletons": 2}),
+        (100, {}),
+    ):
+        values = [rng.gauss(0, 1) for _ in range(3000)]
+        weights = [rng.choice([0.5, 1.0, 3.7, 100.0]) for _ in values]
+        d = TDigest(max_centroids, **kwargs)
+        d.batch_update(values, weights)
+        means = [m for m, _ in d]
+        ranks = d.cdf_vec(means)
+        assert ranks == [d.cdf(m) for m in means]
+        assert all(0.0 <= r <= 1.0 for r in ranks)
+        # between the midpoints of the outermost centroids
+        for q in [rng.uniform(ranks[1], ranks[-1]) for _ in range(500)]:
+            assert math.isclose(d.cdf(d.quantile(q)), q, abs_tol=1e-12)
+        for x in [rng.uniform(d.min(), d.max()) for _ in range(500)]:
+            i = bisect.bisect_left(means, x)
+            lo = means[i - 1] if i > 0 else d.min()
+            hi = means[i] if i < len(means) else d.max()
+            assert lo <= d.quantile(d.cdf(x)) <= hi
+        assert d.quantile(d.cdf(d.min())) == means[0]
+        assert d.quantile(d.cdf(d.max())) == means[-1]
+    d = TDigest(max_centroids=10, exact_tails=5)
+    d.batch_update(values, weights)
+    lowest = sorted(values)[:5]
+    assert [d.quantile(d.cdf(x)) for x in lowest] == lowest
+    assert d.cdf_vec(lowest) == [d.cdf(x) for x in lowest]
+
+
+def test_compensated_sums() -> None:
+    # light values next to a heavy one, whose weights a naive sum drops
+    d = TDigest.from_values([0.0, *range(1, 1001)], w=[1e16] + [1.0] * 1000)
+    assert d.mass() == 1e16 + 1000
+    assert d.trimmed_mean(0.0, 1.0) == pytest.approx(d.mean(), rel=1e-12)
+    TDigest.from_bytes(d.to_bytes(), strict=True)
+    parts = [TDigest.from_values([1.0], w=[0.1]) for _ in range(1000)]
+    assert TDigest.merge_all(parts).mass() == math.fsum([0.1] * 1000)
+    # far from 0, E[X^2] - E[X]^2 cancels out
+    values = [1e9 + i / 10 for i in range(10_000)]
+    d = TDigest.from_values(values)
+    assert d.var() == pytest.approx(statistics.pvariance(values), rel=1e-4)
+
+
+def test_n_values_exact() -> None:
+    # the count does not depend on the weights, however compressed
+    d = TDigest(3)
+    d.batch_update([float(i) for i in range(10_007)], [0.1] * 10_007)
+    assert d.n_values == 10_007
+    assert d.mass() == pytest.approx(1000.7)
+    for restored in (
+        TDigest.from_bytes(d.to_bytes()),
+        TDigest.from_dict(d.to_dict()),
+        pickle.loads(pickle.dumps(d)),
+    ):
+        assert restored.n_values == 10_007
+    assert (d + d).n_values == 20_014
+    assert TDigest.merge_all([d, d, d]).n_values == 30_021
+
+
+def test_tail_quantile() -> None:
+    rng = random.Random(42)
+    d = TDigest.from_values([rng.expovariate(1.0) for _ in range(100_000)])
+    for q in (0.9999, 0.999999, 1 - 1e-8):
+        assert math.isclose(d.tail_quantile(q), -math.log(1 - q), rel_tol=0.05)
+    assert math.isclose(d.tail_quantile(1e-6), 0.0, abs_tol=1e-4)
+    assert d.tail_quantile(0.5) == d.quantile(0.5)
+    assert d.tail_quantile(0.97, threshold=0.99) == d.quantile(0.97)
+    with pytest.raises(ValueError):
+        d.tail_quantile(0.9999, method="gev")
+    with pytest.raises(ValueError):
+        d.tail_quantile(0.9999, threshold=0.3)
+    with pytest.raises(ValueError):
+        TDigest.from_values([1.0]).tail_quantile(0.9999)
+
+
 # -------------------------------------------------------------------
 # CDF tests (cdf, probability)
 # -------------------------------------------------------------------
@@ -350,6 +1354,61 @@ def test_cdf_methods(sample_values: Sequence[int]) -> None:
         TDigest().cdf(50)
 
 
+
+def test_vectorized_matches_scalar() -> None:
+    rng = random.Random(7)
+    values = [rng.expovariate(1.0) for _ in range(50_000)]
+    values += [1.0] * 1000  # duplicate centroid means
+    d = TDigest.from_values(values, max_centroids=200)
+    qs = [i / 500 for i in range(501)]
+    xs = [d.quantile(q) for q in qs] + [-1.0, 1.0, 100.0]
+    for q, result in zip(qs, d.quantile_vec(qs)):
+        assert math.isclose(result, d.quantile(q), rel_tol=1e-9, abs_tol=1e-12)
+    for x, result in zip(xs, d.cdf_vec(xs)):
+        assert math.isclose(result, d.cdf(x), rel_tol=1e-9, abs_tol=1e-12)
+
+
+def test_repeated_queries() -> None:
+    # the first query scans the centroids, later ones binary search cached
+    # prefix ranks, which every change resets
+    d = TDigest.from_values([float(i % 101) for i in range(10_000)])
+    first = [d.quantile(0.3), d.cdf(42.5)]
+    assert [d.quantile(0.3), d.cdf(42.5)] == first
+    assert [d.quantile(0.3), d.cdf(42.5)] == first
+    d.batch_update([1000.0] * 5000)
+    assert d.quantile(0.9) == 1000.0
+    assert d.quantile(0.9) == 1000.0
+    assert d.cdf(500.0) == pytest.approx(2 / 3, abs=0.01)
+    d.update(-1000.0)
+    assert d.quantile(0.0) == -1000.0
+    d.scale_weights(0.5)
+    assert d.quantile(0.5) == d.quantile_vec([0.5])[0]
+
+def test_cdf_vec_out() -> None:
+    d = TDigest.from_values([float(i % 101) for i in range(10_000)])
+    # enough values for several threads
+    x = array.array("d", (i % 1013 / 10 for i in range(300_000)))
+    expected = d.cdf_vec(x)
+    out = array.array("d", bytes(8 * len(x)))
+    assert d.cdf_vec(x, out=out, n_threads=4) is out
+    assert out.tolist() == expected
+    assert d.cdf_vec(x, n_threads=None) == expected
+    assert d.cdf_vec(x, n_threads=4) == expected
+    in_place = array.array("d", x[:100])
+    d.cdf_vec(in_place, out=in_place)
+    assert in_place.tolist() == expected[:100]
+    out = array.array("d", [0.0, 0.0])
+    d.cdf_vec([10, float("nan")], out=out)
+    assert out[0] == d.cdf(10) and math.isnan(out[1])
+    with pytest.raises(ValueError, match="same length"):
+        d.cdf_vec([1.0, 2.0, 3.0], out=out)
+    with pytest.raises(TypeError, match="float64"):
+        d.cdf_vec([1.0, 2.0], out=array.array("f", [0.0, 0.0]))
+    with pytest.raises(ValueError, match="writable"):
+        d.cdf_vec([1.0, 2.0], out=memoryview(bytes(16)).cast("d"))
+    with pytest.raises(ValueError, match="n_threads"):
+        d.cdf_vec([1.0], n_threads=0)
+
 # -------------------------------------------------------------------
 # Mean tests (mean, trimmed_mean)
 # -------------------------------------------------------------------
@@ -473,6 +1532,128 @@ def test_to_from_bytes(sample_values: Sequence[int]) -> None:
         TDigest.from_bytes(fake_bytes)
 
 
+def test_compact_bytes() -> None:
+    rng = random.Random(0)
+    d = TDigest.from_values([rng.gauss(100, 15) for _ in range(100_000)])
+    compact = d.to_bytes(mode="compact")
+    assert d.to_bytes(mode="exact") == d.to_bytes()
+    assert len(compact) < len(d.to_bytes()) / 3
+    restored = TDigest.from_bytes(compact, strict=True)
+    assert restored.to_bytes(mode="compact") == compact
+    assert restored.n_values == d.n_values
+    assert restored.mass() == d.mass() and restored.sum() == d.sum()
+    assert restored.min() == d.min() and restored.max() == d.max()
+    for q in (0.01, 0.5, 0.99):
+        assert restored.quantile(q) == pytest.approx(d.quantile(q), rel=1e-6)
+    # non-integer weights are kept exactly
+    w = TDigest()
+    w.batch_update([1.0, 2.0, 3.0], w=[0.5, 1.5, 2.0])
+    restored = TDigest.from_bytes(w.to_bytes(mode="compact"))
+    assert restored == w
+    assert TDigest.from_bytes(TDigest().to_bytes(mode="compact")).is_empty()
+    with pytest.raises(ValueError):
+        TDigest.from_bytes(compact[:-1])
+    with pytest.raises(ValueError):
+        d.to_bytes(mode="small")
+
+def test_streaming() -> None:
+    d = TDigest.from_values(range(1000), max_centroids=50)
+    f = io.BytesIO()
+    d.write_to(f)
+    TDigest().write_to(f)
+    assert f.getvalue() == d.to_bytes() + TDigest().to_bytes()
+    f.seek(0)
+    assert TDigest.read_from(f, strict=True) == d
+    assert TDigest.read_from(f).is_empty()
+    with pytest.raises(ValueError):
+        TDigest.read_from(f)
+    with pytest.raises(ValueError):
+        TDigest.read_from(io.BytesIO(d.to_bytes()[:-1]))
+    with pytest.raises(ValueError):
+        TDigest.read_from(io.BytesIO(d.to_bytes(mode="compact")))
+
+def test_delta() -> None:
+    rng = random.Random(0)
+    d = TDigest(max_centroids=100)
+    d.batch_update([rng.random() for _ in range(10_000)])
+    replica = TDigest.from_bytes(d.to_bytes())
+    snapshot = d.copy()
+    for n in (1, 10, 100, 0, 1000):
+        for _ in range(n):
+            d.update(rng.random())
+        delta = d.delta_since(snapshot)
+        assert len(delta) < len(d.to_bytes()) + 100
+        replica.apply_delta(delta)
+        assert replica.to_bytes() == d.to_bytes()
+        snapshot = d.copy()
+    assert len(d.delta_since(d)) < 200
+    # the replica keeps its other parameters
+    replica = TDigest(tail_singletons=2)
+    delta = d.delta_since(TDigest())
+    replica.apply_delta(delta)
+    assert replica == d and replica.tail_singletons == 2
+    with pytest.raises(ValueError):
+        replica.apply_delta(delta)
+    with pytest.raises(ValueError):
+        TDigest().apply_delta(delta[:-1])
+    with pytest.raises(ValueError):
+        TDigest().apply_delta(d.to_bytes())
+
+
+def test_strict_deserialization(sample_values: Sequence[int]) -> None:
+    for d in (
+        TDigest(),
+        TDigest.from_values([1.0]),
+        TDigest.from_values(sample_values, max_centroids=3),
+        TDigest.from_values(sample_values, [0.1] * len(sample_values)),
+    ):
+        assert TDigest.from_dict(d.to_dict(), strict=True) == d
+        assert TDigest.from_bytes(d.to_bytes(), strict=True) == d
+    legacy = {"centroids": [{"m": 1.0, "c": 2.0}, {"m": 3.0, "c": 1.0}]}
+    assert TDigest.from_dict(legacy, strict=True).mass() == 3.0
+    d_dict = TDigest.from_values(range(101), max_centroids=3).to_dict()
+    corruptions = [
+        ("centroids", 1, "m", math.nan),
+        ("centroids", 1, "m", math.inf),
+        ("centroids", 1, "c", 0.0),
+        ("centroids", 1, "c", -1.0),
+        ("centroids", 1, "c", math.nan),
+        ("centroids", 2, "m", 40.0),
+    ]
+    for key, i, field, value in corruptions:
+        bad = deepcopy(d_dict)
+        bad[key][i][field] = value
+        TDigest.from_dict(bad)
+        with pytest.raises(ValueError, match=f"Centroid {i}"):
+            TDigest.from_dict(bad, strict=True)
+    for key, value in (
+        ("mass", 100.0),
+        ("sum", 1.0),
+        ("sum", math.inf),
+        ("min", 20.0),
+        ("max", 50.0),
+        ("min", math.nan),
+        ("n_values", 0),
+        ("n_values", 2**128 - 1),
+    ):
+        bad = dict(d_dict, **{key: value})
+        TDigest.from_dict(bad)
+        with pytest.raises(ValueError, match=key):
+            TDigest.from_dict(bad, strict=True)
+    # merging does not overflow the count of a non-strictly loaded digest
+    huge = TDigest.from_dict(dict(d_dict, n_values=2**128 - 1))
+    assert (huge + huge).n_values == 2**128 - 1
+    assert TDigest.merge_all([huge, huge]).n_values == 2**128 - 1
+    light = {"centroids": [{"m": 1.0, "c": 0.1}]}
+    assert TDigest.from_dict(light, strict=True).n_values == 1
+    d_bytes = TDigest.from_values([1.5, 2.5, 3.5]).to_bytes()
+    bad_bytes = d_bytes.replace(struct.pack("d", 2.5), struct.pack("d", -1.0))
+    assert bad_bytes != d_bytes
+    TDigest.from_bytes(bad_bytes)
+    with pytest.raises(ValueError):
+        TDigest.from_bytes(bad_bytes, strict=True)
+
+
 def test_pickle_unpickle(sample_values: Sequence[int]) -> None:
     d = TDigest.from_values([1.0, 2.0, 3.0])
     dumped = pickle.dumps(d)
@@ -509,6 +1690,10 @@ def test_copy_methods(copy_func: Callable[[TDigest], TDigest]) -> None:
     check_tdigest_equality(d, d_copy)
     assert id(d_copy) != id(d)
     assert d.mean() == d_copy.mean() == 3.0
+    # the copy is a snapshot that is unaffected by later updates or merges
+    d.update(100.0)
+    d.merge_inplace(TDigest.from_values([50.0]))
+    assert d_copy.n_values == 4 and d_copy.max() == 6.0
     empty_digest = TDigest()
     empty_copy = copy_func(empty_digest)
     assert len(empty_copy) == 0
@@ -540,6 +1725,27 @@ def test_iter() -> None:
     iterator = iter(d)
     assert isinstance(iterator, collections.abc.Iterator)
     assert list(iterator) == d.centroids
+    d = TDigest.from_values([1.0, 2.0], w=[3.0, 4.0])
+    assert [(m, w) for m, w in d] == [(1.0, 3.0), (2.0, 4.0)]
+    assert list(TDigest()) == []
+
+
+def test_getitem() -> None:
+    d = TDigest.from_values([1.0, 2.0, 3.0, 4.0], w=[1, 2, 3, 4])
+    centroids = d.centroids
+    assert d[0] == (1.0, 1.0)
+    assert d[-1] == (4.0, 4.0)
+    assert d[1:3] == centroids[1:3]
+    assert d[::-2] == centroids[::-2]
+    assert d[10:] == []
+    with pytest.raises(IndexError):
+        d[4]
+    with pytest.raises(IndexError):
+        d[-5]
+    with pytest.raises(IndexError):
+        TDigest()[0]
+    with pytest.raises(TypeError):
+        d["a"]
 
 
 def test_equality() -> None:
@@ -558,3 +1764,8 @@ def test_equality() -> None:
     empty2 = TDigest()
     assert empty1 == empty2
     assert d1 != empty1
+    assert d1 != [1, 2, 3, 0]
+    assert not (d1 == "TDigest")
+    # digests are mutable, so they are compared by value but not hashable
+    with pytest.raises(TypeError):
+        hash(d1)
@@ -0,0 +1,92 @@
+import copy
+import pickle
+import pytest
+from fastdigest import ThetaSketch
+from fastdigest.errors import SerializationError
+
+
+def sketch_of(values: range, lg_k: int = 12) -> ThetaSketch:
+    s = ThetaSketch(lg_k)
+    s.batch_update(values)
+    return s
+
+
+def check_estimate(s: ThetaSketch, expected: int, rel: float) -> None:
+    assert abs(s.estimate() - expected) <= rel * expected
+    assert s.lower_bound(3) <= expected <= s.upper_bound(3)
+    assert s.lower_bound(1) <= s.estimate() <= s.upper_bound(1)
+
+
+def test_init() -> None:
+    s = ThetaSketch()
+    assert s.lg_k == 12
+    assert s.theta == 1.0
+    assert s.n_retained == 0
+    assert s.is_empty()
+    assert not s.is_estimation_mode()
+    assert s.estimate() == 0.0
+    assert repr(s) == "ThetaSketch(lg_k=12)"
+    for bad_lg_k in [3, 27]:
+        with pytest.raises(ValueError):
+            ThetaSketch(bad_lg_k)
+    with pytest.raises(ValueError):
+        s.lower_bound(4)
+
+
+def test_exact_mode() -> None:
+    s = sketch_of(range(1_000))
+    s.batch_update(range(500))
+    s.update("a")
+    assert not s.is_estimation_mode()
+    assert s.estimate() == 1_001
+    assert s.lower_bound() == s.upper_bound() == 1_001
+    assert (s & sketch_of(range(900, 2_000))).estimate() == 100
+    assert (s - sketch_of(range(900, 2_000))).estimate() == 901
+
+
+def test_estimation_mode() -> None:
+    s = sketch_of(range(200_000))
+    assert s.is_estimation_mode()
+    assert s.n_retained == 4096
+    assert 0.0 < s.theta < 1.0
+    check_estimate(s, 200_000, rel=0.06)
+    small = sketch_of(range(200_000), lg_k=6)
+    assert small.n_retained == 64
+    check_estimate(small, 200_000, rel=0.5)
+
+
+def test_set_operations() -> None:
+    a = sketch_of(range(0, 300_000))
+    b = sketch_of(range(200_000, 600_000))
+    check_estimate(a | b, 600_000, rel=0.06)
+    check_estimate(a.union(b), 600_000, rel=0.06)
+    check_estimate(a & b, 100_000, rel=0.15)
+    check_estimate(a - b, 200_000, rel=0.1)
+    check_estimate(b.difference(a), 300_000, rel=0.1)
+    disjoint = a & sketch_of(range(1_000_000, 1_300_000))
+    assert disjoint.estimate() == 0.0
+    assert disjoint.lower_bound() == 0.0
+    united = a.copy()
+    united |= b
+    assert united == a | b
+    united.union_inplace(united)
+    assert united == a | b
+    mixed = sketch_of(range(1_000), lg_k=6) | sketch_of(range(1_000))
+    assert mixed.lg_k == 6
+    assert mixed.n_retained == 64
+
+
+def test_serialization() -> None:
+    for s in [ThetaSketch(), sketch_of(range(10)), sketch_of(range(10_000))]:
+        assert ThetaSketch.from_bytes(s.to_bytes()) == s
+        assert ThetaSketch.from_dict(s.to_dict()) == s
+        assert pickle.loads(pickle.dumps(s)) == s
+        assert copy.deepcopy(s) == s
+    with pytest.raises(ValueError):
+        ThetaSketch.from_bytes(s.to_bytes()[:-1])
+    bad = s.to_dict()
+    bad["hashes"] = bad["hashes"][::-1]
+    with pytest.raises(ValueError):
+        ThetaSketch.from_dict(bad)
+    with pytest.raises(SerializationError):
+        ThetaSketch.from_dict({"lg_k": 12})
@@ -0,0 +1,190 @@
+import copy
+import pickle
+import pytest
+import random
+from fastdigest import TDigest, TimeSeriesDigest
+
+
+def sample_data(n: int, seed: int = 0) -> tuple:
+    rng = random.Random(seed)
+    # two hours of timestamps in seconds, in any order
+    timestamps = [rng.uniform(0.0, 7200.0) for _ in range(n)]
+    values = [rng.gauss(0.0, 1.0) for _ in range(n)]
+    return timestamps, values
+
+
+def test_init() -> None:
+    s = TimeSeriesDigest(60.0)
+    assert s.resolution == 60.0
+    assert s.max_centroids == 1000
+    assert s.n_buckets == 0
+    assert s.n_values == 0
+    assert s.is_empty()
+    assert s.ranges() == []
+    assert s.snapshot().is_empty()
+    assert repr(s) == "TimeSeriesDigest(resolution=60.0, max_centroids=1000)"
+    for resolution in (0.0, -1.0, float("inf"), float("nan")):
+        with pytest.raises(ValueError):
+            TimeSeriesDigest(resolution)
+    with pytest.raises(ValueError):
+        TimeSeriesDigest(60.0, max_centroids=-1)
+    with pytest.raises(ValueError):
+        s.median()
+
+
+def test_update_query() -> None:
+    s = TimeSeriesDigest(10.0)
+    s.update(25.0, 3.0)
+    s.update(5.0, 1.0)
+    s.update(12.0, 2.0, w=2.0)
+    s.update(-1.0, 0.0)
+    assert s.n_values == 4
+    assert s.n_buckets == 4
+    assert s.ranges() == [
+        (-10.0, 0.0),
+        (0.0, 10.0),
+        (10.0, 20.0),
+        (20.0, 30.0),
+    ]
+    assert s.snapshot().mass() == 5.0
+    assert s.snapshot(start=10.0, end=20.0).mass() == 2.0
+    assert s.snapshot(start=15.0).min() == 2.0
+    assert s.snapshot(end=10.0).max() == 1.0
+    assert s.snapshot(end=10.5).max() == 2.0
+    assert s.median(start=0.0, end=10.0) == 1.0
+    assert s.quantile(1.0, end=20.0) == 2.0
+    assert s.percentile(100, start=20.0) == 3.0
+    assert s.cdf(2.5, start=0.0) == pytest.approx(0.75)
+    assert s.snapshot(start=100.0).is_empty()
+    assert s.snapshot(start=10.0, end=10.0).is_empty()
+    with pytest.raises(ValueError):
+        s.quantile(0.5, start=10.0, end=2.0)
+    with pytest.raises(ValueError):
+        s.percentile(50, start=10.0, end=2.0)
+    with pytest.raises(ValueError):
+        s.snapshot(start=10.0, end=2.0)
+    with pytest.raises(ValueError):
+        s.update(float("inf"), 1.0)
+    with pytest.raises(ValueError):
+        s.update(1.0, float("nan"))
+    with pytest.raises(ValueError):
+        s.update(1.0, 1.0, w=0.0)
+
+
+def test_batch_update() -> None:
+    timestamps, values = sample_data(10_000)
+    s = TimeSeriesDigest(60.0)
+    s.batch_update(timestamps, values)
+    assert s.n_values == 10_000
+    assert s.n_buckets == 120
+    expected = TDigest.from_values(values)
+    assert abs(s.median() - expected.median()) < 0.05
+    assert abs(s.quantile(0.9) - expected.quantile(0.9)) < 0.05
+    second_hour = [t for t in timestamps if t >= 3600.0]
+    assert s.snapshot(start=3600.0).mass() == len(second_hour)
+    single = TimeSeriesDigest(60.0)
+    for t, x in zip(timestamps, values):
+        single.update(t, x)
+    assert single.ranges() == s.ranges()
+    assert single.n_values == s.n_values
+    with pytest.raises(ValueError):
+        s.batch_update([1.0], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, float("nan")], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        s.batch_update([1.0, 2.0], [1.0, float("inf")])
+
+
+def test_rollup() -> None:
+    timestamps, values = sample_data(10_000)
+    s = TimeSeriesDigest(60.0)
+    s.batch_update(timestamps, values)
+    # the first hour is rolled up, the second is still being written
+    s.rollup(3600.0, before=5000.0)
+    assert s.n_buckets == 61
+    assert s.ranges()[0] == (0.0, 3600.0)
+    assert s.n_values == 10_000
+    first_hour = [t for t in timestamps if t < 3600.0]
+    assert s.snapshot(end=3600.0).mass() == len(first_hour)
+    assert s.snapshot(start=1800.0, end=1860.0).mass() == len(first_hour)
+    # new values go to the bucket covering them
+    s.update(100.0, 1.0)
+    assert s.n_buckets == 61
+    assert s.snapshot(end=3600.0).mass() == len(first_hour) + 1
+    s.rollup(3600.0)
+    assert s.ranges() == [(0.0, 3600.0), (3600.0, 7200.0)]
+    rolled = s.copy()
+    s.rollup(3600.0)
+    assert s == rolled
+    # an interval that does not contain whole buckets is left as it is
+    s.rollup(5400.0)
+    assert s.ranges() == [(0.0, 3600.0), (3600.0, 7200.0)]
+    s.rollup(86400.0)
+    assert s.ranges() == [(0.0, 86400.0)]
+    assert s.n_values == 10_001
+    for resolution in (0.0, 30.0, 90.0, float("nan")):
+        with pytest.raises(ValueError):
+            s.rollup(resolution)
+    with pytest.raises(ValueError):
+        s.rollup(3600.0, before=float("nan"))
+
+
+def test_merge() -> None:
+    timestamps, values = sample_data(4000)
+    a, b = TimeSeriesDigest(60.0), TimeSeriesDigest(60.0)
+    a.batch_update(timestamps[::2], values[::2])
+    b.batch_update(timestamps[1::2], values[1::2])
+    merged = a + b
+    assert merged.n_values == 4000
+    assert merged.ranges() == (b + a).ranges()
+    a += b
+    assert a == merged
+    a.merge_inplace(a)
+    assert a.n_values == 8000
+    # buckets of different rollups are merged into one covering both
+    b.rollup(3600.0)
+    c = TimeSeriesDigest(60.0)
+    c.update(3000.0, 1.0)
+    c.update(4000.0, 1.0)
+    c.rollup(5400.0)
+    combined = b + c
+    assert combined.ranges() == [(0.0, 7200.0)]
+    assert combined.n_values == 2002
+    assert (c + b).ranges() == combined.ranges()
+    assert (merged + b).n_values == 6000
+    assert merged.n_buckets == 120
+    assert TimeSeriesDigest(60.0) + b == b
+    with pytest.raises(ValueError):
+        a.merge(TimeSeriesDigest(30.0))
+
+
+def test_serialization() -> None:
+    timestamps, values = sample_data(3000)
+    s = TimeSeriesDigest(60.0, max_centroids=100)
+    s.batch_update(timestamps, values)
+    s.update(7199.0, 10.0)
+    s.rollup(3600.0, before=3600.0)
+    for restore in (
+        lambda s: TimeSeriesDigest.from_bytes(s.to_bytes()),
+        lambda s: TimeSeriesDigest.from_dict(s.to_dict()),
+        lambda s: pickle.loads(pickle.dumps(s)),
+        copy.deepcopy,
+    ):
+        restored = restore(s)
+        assert restored == s
+        assert restored.ranges() == s.ranges()
+        assert restored.snapshot().max() == 10.0
+    empty = TimeSeriesDigest(1.0)
+    assert TimeSeriesDigest.from_bytes(empty.to_bytes()) == empty
+    d = s.to_dict()
+    assert d["resolution"] == 60.0
+    assert d["epochs"][:2] == [0, 60]
+    assert d["lengths"][:2] == [60, 1]
+    assert d["digests"][0]["max_centroids"] == 100
+    d["epochs"][1] = 59
+    with pytest.raises(ValueError):
+        TimeSeriesDigest.from_dict(d)
+    data = s.to_bytes()
+    for bad in (b"", data[:-1], data + b"\0"):
+        with pytest.raises(ValueError):
+            TimeSeriesDigest.from_bytes(bad)
@@ -0,0 +1,108 @@
+import copy
+import pickle
+import pytest
+import random
+from collections import Counter
+from fastdigest import TopK
+from fastdigest.errors import SerializationError
+
+
+def zipf_stream(n: int, seed: int) -> list:
+    rng = random.Random(seed)
+    return [int(rng.paretovariate(0.8)) for _ in range(n)]
+
+
+def check_bounds(t: TopK, counts: Counter) -> None:
+    assert t.total == sum(counts.values())
+    for item, count, error in t.top():
+        assert count - error <= counts[item] <= count
+        assert error <= t.max_error <= t.total / t.k
+    for item, count in counts.items():
+        assert t.estimate(item) >= count
+        if count > t.total / t.k:
+            assert item in t
+
+
+def test_init() -> None:
+    t = TopK(5)
+    assert t.k == 5
+    assert t.total == 0
+    assert t.max_error == 0
+    assert t.is_empty()
+    assert len(t) == 0
+    assert t.top() == []
+    assert t.estimate("a") == 0
+    assert repr(t) == "TopK(k=5)"
+    for bad_k in [0, -3]:
+        with pytest.raises(ValueError):
+            TopK(bad_k)
+
+
+def test_exact_below_k() -> None:
+    t = TopK(10)
+    t.batch_update(["a", "b", "a", "c", "a", "b"])
+    t.update("d", count=4)
+    assert t.top() == [("d", 4, 0), ("a", 3, 0), ("b", 2, 0), ("c", 1, 0)]
+    assert t.top(2) == [("d", 4, 0), ("a", 3, 0)]
+    assert "a" in t and "z" not in t
+    assert t.max_error == 0
+    with pytest.raises(ValueError):
+        t.update("a", count=0)
+    with pytest.raises(TypeError):
+        t.update({"a"})
+
+
+def test_heavy_hitters() -> None:
+    items = zipf_stream(100_000, seed=0)
+    counts = Counter(items)
+    t = TopK(50)
+    t.batch_update(items)
+    assert len(t) == 50
+    check_bounds(t, counts)
+    top5 = [item for item, _, _ in t.top(5)]
+    assert top5 == [item for item, _ in counts.most_common(5)]
+    threshold = 0.02 * t.total
+    hitters = {item for item, _, _ in t.heavy_hitters(0.02)}
+    guaranteed = {item for item, _, _ in t.heavy_hitters(0.02, True)}
+    exact = {item for item, count in counts.items() if count > threshold}
+    assert guaranteed <= exact <= hitters
+    for bad_phi in [0.0, 1.5]:
+        with pytest.raises(ValueError):
+            t.heavy_hitters(bad_phi)
+
+
+def test_merge() -> None:
+    items = zipf_stream(100_000, seed=1)
+    shards = [TopK(40) for _ in range(4)]
+    for i, item in enumerate(items):
+        shards[i % 4].update(item)
+    merged = TopK(40)
+    for shard in shards:
+        merged += shard
+    check_bounds(merged, Counter(items))
+    assert merged == shards[0] + shards[1] + shards[2] + shards[3]
+    small = TopK(10).merge(shards[0])
+    assert small.k == 10
+    assert len(small) == 10
+
+
+def test_serialization() -> None:
+    t = TopK(20)
+    items = [2**100, (1, "a", None), b"x", 1.5, True, 1.0, "é", -7]
+    t.batch_update(items)
+    assert t.estimate(1) == 2
+    assert TopK.from_bytes(t.to_bytes()) == t
+    assert TopK.from_dict(t.to_dict()) == t
+    assert pickle.loads(pickle.dumps(t)) == t
+    assert copy.deepcopy(t) == t
+    assert [item for item, _, _ in TopK.from_bytes(t.to_bytes()).top()] == [
+        item for item, _, _ in t.top()
+    ]
+    with pytest.raises(ValueError):
+        TopK.from_bytes(t.to_bytes()[:-1])
+    bad = t.to_dict()
+    bad["counters"] = bad["counters"] * 2
+    with pytest.raises(ValueError):
+        TopK.from_dict(bad)
+    with pytest.raises(SerializationError):
+        TopK.from_dict({"k": 20})
@@ -0,0 +1,142 @@
+import copy
+import math
+import pickle
+import pytest
+import random
+import struct
+from fastdigest import UDDSketch
+
+
+def sample_data(n: int, spread: float, seed: int = 0) -> list:
+    rng = random.Random(seed)
+    return [
+        math.exp(rng.uniform(-spread, spread)) * rng.choice([1, 1, 1, -1])
+        for _ in range(n)
+    ]
+
+
+def test_init() -> None:
+    s = UDDSketch()
+    assert s.relative_accuracy == 0.01
+    assert s.current_relative_accuracy == pytest.approx(0.01)
+    assert s.max_bins == 1024
+    assert s.n_collapses == 0
+    assert s.n_values == 0
+    assert s.n_bins == 0
+    assert s.is_empty()
+    assert repr(s) == "UDDSketch(relative_accuracy=0.01, max_bins=1024)"
+    with pytest.raises(ValueError):
+        s.quantile(0.5)
+    with pytest.raises(ValueError):
+        UDDSketch(relative_accuracy=0.0)
+    with pytest.raises(ValueError):
+        UDDSketch(max_bins=0)
+
+
+def check_accuracy(s: UDDSketch, data: list) -> None:
+    srt = sorted(data)
+    for q in [i / 100 for i in range(101)]:
+        exact = srt[int(q * (len(srt) - 1))]
+        estimate = s.quantile(q)
+        bound = s.current_relative_accuracy * abs(exact)
+        assert abs(estimate - exact) <= bound * (1 + 1e-9)
+
+
+def test_accuracy() -> None:
+    data = sample_data(20_000, 4.0) + [0.0] * 50
+    s = UDDSketch.from_values(data)
+    assert s.n_collapses == 0
+    assert s.n_values == len(data)
+    check_accuracy(s, data)
+    assert s.min() == min(data) and s.max() == max(data)
+    assert s.sum() == pytest.approx(sum(data))
+    assert s.mean() == pytest.approx(sum(data) / len(data))
+    assert s.median() == s.percentile(50) == s.quantile_vec([0.5])[0]
+
+
+def test_collapse() -> None:
+    data = sample_data(20_000, 40.0)
+    s = UDDSketch(relative_accuracy=0.001, max_bins=256)
+    s.batch_update(data)
+    assert s.n_bins <= 256
+    assert s.n_collapses > 0
+    alpha = 0.001
+    for _ in range(s.n_collapses):
+        alpha = 2 * alpha / (1 + alpha**2)
+    assert s.current_relative_accuracy == pytest.approx(alpha)
+    check_accuracy(s, data)
+
+
+def test_weights() -> None:
+    s = UDDSketch()
+    s.batch_update([1.0, 2.0], w=[3.0, 1.0])
+    assert s.mass() == 4.0
+    assert s.n_values == 2
+    assert s.quantile(0.5) == pytest.approx(1.0, rel=0.01)
+    with pytest.raises(ValueError):
+        s.update(1.0, w=-1.0)
+    with pytest.raises(ValueError):
+        s.update(float("inf"))
+
+
+def test_cdf() -> None:
+    data = sample_data(10_000, 5.0)
+    s = UDDSketch.from_values(data)
+    srt = sorted(data)
+    for q in (0.1, 0.5, 0.9):
+        assert s.cdf(srt[int(q * len(srt))]) == pytest.approx(q, abs=0.01)
+    assert s.cdf(min(data) - 1) == 0.0
+    assert s.cdf(max(data)) == 1.0
+    assert s.cdf_vec([min(data) - 1, max(data)]) == [0.0, 1.0]
+
+
+def test_merge() -> None:
+    wide = sample_data(10_000, 40.0, seed=1)
+    narrow = sample_data(10_000, 2.0, seed=2)
+    a = UDDSketch(relative_accuracy=0.001, max_bins=256)
+    b = UDDSketch(relative_accuracy=0.001, max_bins=256)
+    a.batch_update(wide)
+    b.batch_update(narrow)
+    assert a.n_collapses > b.n_collapses
+    merged = a + b
+    assert merged.n_values == 20_000
+    assert merged.n_collapses >= a.n_collapses
+    assert merged.n_bins <= 256
+    check_accuracy(merged, wide + narrow)
+    a += b
+    assert a == merged
+    with pytest.raises(ValueError):
+        a.merge(UDDSketch(relative_accuracy=0.02))
+
+
+def test_serialization() -> None:
+    s = UDDSketch(relative_accuracy=0.001, max_bins=256)
+    s.batch_update(sample_data(5_000, 40.0) + [0.0])
+    for restore in (
+        lambda s: UDDSketch.from_bytes(s.to_bytes()),
+        lambda s: UDDSketch.from_dict(s.to_dict()),
+        lambda s: pickle.loads(pickle.dumps(s)),
+        copy.deepcopy,
+    ):
+        restored = restore(s)
+        assert restored == s
+        assert restored.quantile(0.3) == s.quantile(0.3)
+    d = s.to_dict()
+    d["positive"] = d["positive"][::-1]
+    with pytest.raises(ValueError):
+        UDDSketch.from_dict(d)
+    with pytest.raises(ValueError):
+        UDDSketch.from_bytes(s.to_bytes()[:-1])
+    # totals that do not match the buckets
+    invalid = [("min", s.max() + 1.0), ("n_values", 1), ("sum", math.inf)]
+    for key, value in invalid:
+        d = s.to_dict()
+        d[key] = value
+        with pytest.raises(ValueError):
+            UDDSketch.from_dict(d)
+    raw = s.to_bytes()
+    min_bytes = struct.pack("<d", s.min())
+    assert raw.count(min_bytes) == 1
+    corrupt = raw.replace(min_bytes, struct.pack("<d", s.max() + 1.0))
+    with pytest.raises(ValueError):
+        UDDSketch.from_bytes(corrupt)
@@ -0,0 +1,84 @@
+import pytest
+import random
+from pathlib import Path
+from fastdigest import TDigest, WalTDigest
+
+
+def test_recovery(tmp_path: Path) -> None:
+    path = tmp_path / "agent.wal"
+    rng = random.Random(0)
+    values = [rng.expovariate(0.1) for _ in range(3000)]
+    d = WalTDigest(path, max_centroids=100, compact_every=1000)
+    assert d.is_empty()
+    assert d.path == path
+    assert d.compact_every == 1000
+    assert not d.fsync
+    d.batch_update(values[:1500])
+    assert d.n_logged == 0
+    for x in values[1500:]:
+        d.update(x)
+    d.update(100.0, 2.5)
+    assert d.n_logged == 501
+    expected = TDigest.from_values(values, max_centroids=100)
+    expected.update(100.0, 2.5)
+    # the process is "killed": the log is not closed
+    recovered = WalTDigest.recover(path)
+    assert recovered.n_values == 3001
+    assert recovered.mass() == expected.mass()
+    assert recovered.min() == expected.min()
+    assert recovered.max() == expected.max()
+    assert recovered.median() == pytest.approx(expected.median(), rel=0.02)
+    assert recovered == d.snapshot()
+    reopened = WalTDigest(path)
+    assert reopened.max_centroids == 100
+    assert reopened.n_values == 3001
+    assert reopened.n_logged == 501
+    reopened.compact()
+    assert reopened.n_logged == 0
+    assert WalTDigest.recover(path) == reopened.snapshot()
+
+
+def test_torn_record(tmp_path: Path) -> None:
+    path = tmp_path / "agent.wal"
+    with WalTDigest(path, fsync=True) as d:
+        d.batch_update([1.0, 2.0, 3.0])
+        d.update(4.0)
+    assert d.closed
+    size = path.stat().st_size
+    with open(path, "ab") as f:
+        f.write(b"\x01\x05\x00\x00")
+    assert WalTDigest.recover(path).n_values == 4
+    with WalTDigest(path) as d:
+        assert path.stat().st_size == size
+        d.update(5.0)
+        assert d.n_values == 5
+    assert WalTDigest.recover(path).max() == 5.0
+    # a corrupt record ends the log
+    data = bytearray(path.read_bytes())
+    data[-10] ^= 1
+    path.write_bytes(bytes(data))
+    assert WalTDigest.recover(path).n_values == 4
+
+
+def test_errors(tmp_path: Path) -> None:
+    path = tmp_path / "agent.wal"
+    with pytest.raises(ValueError):
+        WalTDigest(path, compact_every=0)
+    with pytest.raises(OSError):
+        WalTDigest.recover(path)
+    WalTDigest(path, max_centroids=50).close()
+    with pytest.raises(ValueError, match="max_centroids"):
+        WalTDigest(path, max_centroids=60)
+    d = WalTDigest(path)
+    d.close()
+    d.close()
+    with pytest.raises(ValueError, match="closed"):
+        d.update(1.0)
+    with pytest.raises(ValueError, match="closed"):
+        d.median()
+    bad = tmp_path / "bad.wal"
+    bad.write_bytes(b"not a log" * 100)
+    with pytest.raises(ValueError):
+        WalTDigest(bad)
+    with pytest.raises(ValueError):
+        WalTDigest.recover(bad)
@@ -1,5 +1,6 @@
 import sys
 import math
+import random
 from bisect import bisect_right
 from typing import Iterable, List, Sequence
 from fastdigest import TDigest
@@ -100,6 +101,24 @@ def generate_normal(n: int, mu: float = 0.0, sigma: float = 1.0) -> List[float]:
     return out
 
 
+def make_data(
+    n: int, distribution: str = "gauss", seed: int = 0
+) -> List[float]:
+    """
+    Generate `n` pseudo-random samples of a `distribution` of `random.Random`:
+    "gauss" (standard normal), "uniform" (between -100 and 100) or
+    "expovariate" (with rate 1).
+    """
+    rng = random.Random(seed)
+    draw = getattr(rng, distribution)
+    args = {
+        "gauss": (0.0, 1.0),
+        "uniform": (-100.0, 100.0),
+        "expovariate": (1.0,),
+    }
+    return [draw(*args[distribution]) for _ in range(n)]
+
+
 def calculate_sample_quantiles(
     data: Iterable[float], quantiles: Iterable[float] = SAMPLE_QUANTILES
 ) -> List[float]: